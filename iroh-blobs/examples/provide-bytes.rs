@@ -101,7 +101,14 @@ async fn main() -> Result<()> {
                         return;
                     }
                 };
-                iroh_blobs::provider::handle_connection(conn, db, MockEventSender, lp).await
+                iroh_blobs::provider::handle_connection(
+                    conn,
+                    db,
+                    MockEventSender,
+                    Default::default(),
+                    lp,
+                )
+                .await
             });
         }
     });
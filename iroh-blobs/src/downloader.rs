@@ -25,11 +25,17 @@
 //!   strictly needed since it's likely they will be useful soon again.
 //! - *Requests per node*: to avoid overwhelming nodes with requests, the number of concurrent
 //!   requests to a single node is also limited.
+//! - *Nodes per download*: raw blobs with more than one provider are striped across several
+//!   nodes, see [`Getter::get_striped`]. Each node is only asked for the ranges it announces
+//!   in response to a [`HaveRequest`](crate::protocol::HaveRequest). The number of nodes taking
+//!   part in a single download is limited.
 
 use std::{
-    collections::{hash_map::Entry, HashMap, HashSet},
+    cmp::Reverse,
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
     fmt,
     num::NonZeroUsize,
+    ops::Range,
     sync::{
         atomic::{AtomicU64, Ordering},
         Arc,
@@ -37,10 +43,11 @@ use std::{
     time::Duration,
 };
 
+use bao_tree::ChunkNum;
 use futures_lite::{future::BoxedLocal, Stream, StreamExt};
-use hashlink::LinkedHashSet;
 use iroh_base::hash::{BlobFormat, Hash, HashAndFormat};
 use iroh_net::{endpoint, Endpoint, NodeAddr, NodeId};
+use serde::{Deserialize, Serialize};
 use tokio::{
     sync::{mpsc, oneshot},
     task::JoinSet,
@@ -67,7 +74,19 @@ const IDLE_PEER_TIMEOUT: Duration = Duration::from_secs(10);
 const SERVICE_CHANNEL_CAPACITY: usize = 128;
 
 /// Identifier for a download intent.
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, derive_more::Display)]
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    Hash,
+    derive_more::Display,
+    Serialize,
+    Deserialize,
+)]
 pub struct IntentId(pub u64);
 
 /// Trait modeling a dialer. This allows for IO-less testing.
@@ -109,6 +128,18 @@ pub trait Getter {
         conn: Self::Connection,
         progress_sender: BroadcastProgressSender,
     ) -> GetFut;
+    /// Return a future that performs the download striped across several connections.
+    ///
+    /// The download starts with `conn`. Connections to further nodes are sent over
+    /// `more_conns` while the download is running, and should be used as they arrive. The
+    /// result of the download applies to all nodes that took part in it.
+    fn get_striped(
+        &mut self,
+        kind: DownloadKind,
+        conn: Self::Connection,
+        more_conns: mpsc::UnboundedReceiver<Self::Connection>,
+        progress_sender: BroadcastProgressSender,
+    ) -> GetFut;
 }
 
 /// Concurrency limits for the [`Downloader`].
@@ -122,6 +153,11 @@ pub struct ConcurrencyLimits {
     pub max_open_connections: usize,
     /// Maximum number of nodes to dial concurrently for a single request.
     pub max_concurrent_dials_per_hash: usize,
+    /// Maximum number of nodes a single raw blob is downloaded from concurrently.
+    ///
+    /// If this is larger than one, raw blobs with more than one provider are striped across
+    /// several nodes. Set this to one to download each blob from a single node.
+    pub max_nodes_per_download: usize,
 }
 
 impl Default for ConcurrencyLimits {
@@ -132,6 +168,7 @@ impl Default for ConcurrencyLimits {
             max_concurrent_requests_per_node: 4,
             max_open_connections: 25,
             max_concurrent_dials_per_hash: 5,
+            max_nodes_per_download: 4,
         }
     }
 }
@@ -161,6 +198,11 @@ impl ConcurrencyLimits {
     fn at_dials_per_hash_capacity(&self, concurrent_dials: usize) -> bool {
         concurrent_dials >= self.max_concurrent_dials_per_hash
     }
+
+    /// Checks if the maximum number of nodes taking part in a single download has been reached.
+    fn at_nodes_per_download_capacity(&self, nodes: usize) -> bool {
+        nodes >= self.max_nodes_per_download
+    }
 }
 
 /// Configuration for retry behavior of the [`Downloader`].
@@ -188,6 +230,7 @@ pub struct DownloadRequest {
     kind: DownloadKind,
     nodes: Vec<NodeAddr>,
     progress: Option<ProgressSubscriber>,
+    priority: i32,
 }
 
 impl DownloadRequest {
@@ -207,6 +250,7 @@ impl DownloadRequest {
             kind: resource.into(),
             nodes: nodes.into_iter().map(|n| n.into()).collect(),
             progress: None,
+            priority: 0,
         }
     }
 
@@ -215,32 +259,84 @@ impl DownloadRequest {
         self.progress = Some(sender);
         self
     }
+
+    /// Set the priority of the download.
+    ///
+    /// Queued downloads with a higher priority are started before downloads with a lower
+    /// priority, downloads with the same priority are started in the order they were queued.
+    /// The default priority is `0`. If several intents are registered for the same download,
+    /// the highest priority among them is used.
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
 }
 
 /// The kind of resource to download.
-#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy, derive_more::From, derive_more::Into)]
-pub struct DownloadKind(HashAndFormat);
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
+pub struct DownloadKind {
+    content: HashAndFormat,
+    /// The chunks to download, if only a part of a raw blob is requested.
+    chunks: Option<(ChunkNum, ChunkNum)>,
+}
 
 impl DownloadKind {
+    /// Download only the chunks in `chunks` of a raw blob.
+    ///
+    /// Chunks that are already present locally are skipped, and the last chunk is downloaded as
+    /// well as long as the size of the blob is not verified. Once all chunks of the blob are
+    /// present, it is complete. Requests for the same chunks of a blob are deduplicated, but
+    /// requests for different chunks, or for the whole blob, are separate downloads.
+    pub fn raw_chunks(hash: Hash, chunks: Range<ChunkNum>) -> Self {
+        Self {
+            content: HashAndFormat::raw(hash),
+            chunks: Some((chunks.start, chunks.end)),
+        }
+    }
+
     /// Get the hash of this download
     pub const fn hash(&self) -> Hash {
-        self.0.hash
+        self.content.hash
     }
 
     /// Get the format of this download
     pub const fn format(&self) -> BlobFormat {
-        self.0.format
+        self.content.format
     }
 
     /// Get the [`HashAndFormat`] pair of this download
     pub const fn hash_and_format(&self) -> HashAndFormat {
-        self.0
+        self.content
+    }
+
+    /// Get the chunks to download, if only a part of a raw blob is requested.
+    pub fn chunks(&self) -> Option<Range<ChunkNum>> {
+        self.chunks.map(|(start, end)| start..end)
+    }
+}
+
+impl From<HashAndFormat> for DownloadKind {
+    fn from(content: HashAndFormat) -> Self {
+        Self {
+            content,
+            chunks: None,
+        }
+    }
+}
+
+impl From<DownloadKind> for HashAndFormat {
+    fn from(kind: DownloadKind) -> Self {
+        kind.content
     }
 }
 
 impl fmt::Display for DownloadKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}:{:?}", self.0.hash.fmt_short(), self.0.format)
+        write!(f, "{}:{:?}", self.content.hash.fmt_short(), self.content.format)?;
+        if let Some((start, end)) = self.chunks {
+            write!(f, "[{}..{}]", start.0, end.0)?;
+        }
+        Ok(())
     }
 }
 
@@ -278,6 +374,13 @@ pub struct DownloadHandle {
     receiver: oneshot::Receiver<ExternalDownloadResult>,
 }
 
+impl DownloadHandle {
+    /// The id of the intent this handle belongs to.
+    pub fn id(&self) -> IntentId {
+        self.id
+    }
+}
+
 impl std::future::Future for DownloadHandle {
     type Output = ExternalDownloadResult;
 
@@ -384,6 +487,38 @@ impl Downloader {
         }
     }
 
+    /// Change the priority of a download intent.
+    ///
+    /// This reorders the queue if the download has not started yet. See
+    /// [`DownloadRequest::priority`] for details. Fails if the intent is unknown, e.g. because
+    /// the download already finished.
+    pub async fn set_priority(&self, id: IntentId, priority: i32) -> anyhow::Result<()> {
+        let (reply, reply_rx) = oneshot::channel();
+        let msg = Message::SetPriority {
+            id,
+            priority,
+            reply,
+        };
+        self.msg_tx
+            .send(msg)
+            .await
+            .map_err(|_| DownloadError::ActorClosed)?;
+        let found = reply_rx.await.map_err(|_| DownloadError::ActorClosed)?;
+        anyhow::ensure!(found, "unknown download intent {id}");
+        Ok(())
+    }
+
+    /// Get a snapshot of the active, queued and parked downloads.
+    pub async fn queue_snapshot(&self) -> anyhow::Result<QueueSnapshot> {
+        let (reply, reply_rx) = oneshot::channel();
+        self.msg_tx
+            .send(Message::Snapshot { reply })
+            .await
+            .map_err(|_| DownloadError::ActorClosed)?;
+        let snapshot = reply_rx.await.map_err(|_| DownloadError::ActorClosed)?;
+        Ok(snapshot)
+    }
+
     /// Declare that certains nodes can be used to download a hash.
     ///
     /// Note that this does not start a download, but only provides new nodes to already queued
@@ -412,6 +547,44 @@ enum Message {
     /// Cancel an intent. The associated request will be cancelled when the last intent is
     /// cancelled.
     CancelIntent { id: IntentId, kind: DownloadKind },
+    /// Change the priority of an intent. Replies whether the intent was found.
+    SetPriority {
+        id: IntentId,
+        priority: i32,
+        #[debug(skip)]
+        reply: oneshot::Sender<bool>,
+    },
+    /// Get a snapshot of the downloads.
+    Snapshot {
+        #[debug(skip)]
+        reply: oneshot::Sender<QueueSnapshot>,
+    },
+}
+
+/// A snapshot of the downloads of a [`Downloader`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QueueSnapshot {
+    /// Downloads that are currently running.
+    pub active: Vec<DownloadInfo>,
+    /// Downloads waiting to be started, in the order they will be started.
+    pub queued: Vec<DownloadInfo>,
+    /// Downloads on hold because all their candidate nodes are waiting for a retry.
+    pub parked: Vec<DownloadInfo>,
+}
+
+/// Information about a single download in a [`QueueSnapshot`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadInfo {
+    /// The hash and format of the download.
+    pub hash_and_format: HashAndFormat,
+    /// The effective priority of the download, the highest priority among its intents.
+    pub priority: i32,
+    /// The intents waiting for this download.
+    pub intents: Vec<IntentId>,
+    /// Nodes that are candidates to download from.
+    pub candidates: Vec<NodeId>,
+    /// Nodes the download is currently running on. Empty unless the download is active.
+    pub active_nodes: Vec<NodeId>,
 }
 
 #[derive(derive_more::Debug)]
@@ -419,6 +592,7 @@ struct IntentHandlers {
     #[debug("oneshot::Sender<DownloadResult>")]
     on_finish: oneshot::Sender<ExternalDownloadResult>,
     on_progress: Option<ProgressSubscriber>,
+    priority: i32,
 }
 
 /// Information about a request.
@@ -428,14 +602,38 @@ struct RequestInfo {
     intents: HashMap<IntentId, IntentHandlers>,
 }
 
+impl RequestInfo {
+    /// The priority of the request, which is the highest priority of its intents.
+    fn priority(&self) -> i32 {
+        self.intents
+            .values()
+            .map(|intent| intent.priority)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
 /// Information about a request in progress.
 #[derive(derive_more::Debug)]
-struct ActiveRequestInfo {
+struct ActiveRequestInfo<Conn> {
     /// Token used to cancel the future doing the request.
     #[debug(skip)]
     cancellation: CancellationToken,
     /// Peer doing this request attempt.
     node: NodeId,
+    /// Further peers taking part in this request, if it is striped.
+    swarm: Vec<NodeId>,
+    /// Channel to hand connections to further peers to the request, if it is striped and further
+    /// peers may still join. Dropped once no further peer is dialed for the request.
+    #[debug(skip)]
+    swarm_tx: Option<mpsc::UnboundedSender<Conn>>,
+}
+
+impl<Conn> ActiveRequestInfo<Conn> {
+    /// All peers taking part in this request.
+    fn nodes(&self) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::once(self.node).chain(self.swarm.iter().copied())
+    }
 }
 
 #[derive(Debug, Default)]
@@ -529,7 +727,7 @@ struct Service<G: Getter, D: Dialer> {
     /// Information about pending and active requests.
     requests: HashMap<DownloadKind, RequestInfo>,
     /// State of running downloads.
-    active_requests: HashMap<DownloadKind, ActiveRequestInfo>,
+    active_requests: HashMap<DownloadKind, ActiveRequestInfo<D::Connection>>,
     /// Tasks for currently running downloads.
     in_progress_downloads: JoinSet<(DownloadKind, InternalDownloadResult)>,
     /// Progress tracker
@@ -623,6 +821,17 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
                     .await
             }
             Message::CancelIntent { id, kind } => self.handle_cancel_download(id, kind).await,
+            Message::SetPriority {
+                id,
+                priority,
+                reply,
+            } => {
+                let found = self.handle_set_priority(id, priority);
+                reply.send(found).ok();
+            }
+            Message::Snapshot { reply } => {
+                reply.send(self.snapshot()).ok();
+            }
             Message::NodesHave { hash, nodes } => {
                 let updated = self
                     .providers
@@ -648,6 +857,7 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
             kind,
             nodes,
             progress,
+            priority,
         } = request;
         debug!(%kind, nodes=?nodes.iter().map(|n| n.node_id.fmt_short()).collect::<Vec<_>>(), "queue intent");
 
@@ -655,6 +865,7 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
         let intent_handlers = IntentHandlers {
             on_finish,
             on_progress: progress,
+            priority,
         };
 
         // early exit if no providers.
@@ -672,6 +883,12 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
             .providers
             .add_hash_with_nodes(kind.hash(), nodes.iter().map(|n| n.node_id));
 
+        // the priority of the request, taking the new intent into account
+        let priority = self
+            .requests
+            .get(&kind)
+            .map_or(priority, |info| info.priority().max(priority));
+
         // queue the transfer (if not running) or attach to transfer progress (if already running)
         if self.active_requests.contains_key(&kind) {
             // the transfer is already running, so attach the progress sender
@@ -692,8 +909,10 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
                 self.queue.unpark(&kind);
             } else if !self.queue.contains(&kind) {
                 // the transfer is not yet queued: add to queue.
-                self.queue.insert(kind);
+                self.queue.insert(kind, priority);
             }
+            // the new intent might have raised the priority of an already queued transfer
+            self.queue.set_priority(&kind, priority);
         }
 
         // store the request info
@@ -701,6 +920,54 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
         request_info.intents.insert(intent_id, intent_handlers);
     }
 
+    /// Handle a [`Message::SetPriority`].
+    ///
+    /// Returns `false` if the intent is unknown.
+    fn handle_set_priority(&mut self, intent_id: IntentId, priority: i32) -> bool {
+        let Some((kind, request_info)) = self
+            .requests
+            .iter_mut()
+            .find(|(_, info)| info.intents.contains_key(&intent_id))
+        else {
+            debug!(%intent_id, "set priority called for unknown intent");
+            return false;
+        };
+        if let Some(intent) = request_info.intents.get_mut(&intent_id) {
+            intent.priority = priority;
+        }
+        debug!(%kind, %intent_id, priority, "set priority");
+        self.queue.set_priority(kind, request_info.priority());
+        true
+    }
+
+    /// Create a [`QueueSnapshot`] of the current downloads.
+    fn snapshot(&self) -> QueueSnapshot {
+        let info = |kind: &DownloadKind| DownloadInfo {
+            hash_and_format: kind.hash_and_format(),
+            priority: self
+                .requests
+                .get(kind)
+                .map(RequestInfo::priority)
+                .unwrap_or_default(),
+            intents: self
+                .requests
+                .get(kind)
+                .map(|info| info.intents.keys().copied().collect())
+                .unwrap_or_default(),
+            candidates: self.providers.get_candidates(&kind.hash()).collect(),
+            active_nodes: self
+                .active_requests
+                .get(kind)
+                .map(|info| info.nodes().collect())
+                .unwrap_or_default(),
+        };
+        QueueSnapshot {
+            active: self.active_requests.keys().map(info).collect(),
+            queued: self.queue.iter_main().map(info).collect(),
+            parked: self.queue.iter_parked().map(info).collect(),
+        }
+    }
+
     /// Cancels a download intent.
     ///
     /// This removes the intent from the list of intents for the `kind`. If the removed intent was
@@ -738,6 +1005,10 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
                 self.queue.remove(&kind);
             }
             self.remove_hash_if_not_queued(&kind.hash());
+        } else {
+            // the removed intent might have had the highest priority
+            let priority = request_info.priority();
+            self.queue.set_priority(&kind, priority);
         }
     }
 
@@ -753,14 +1024,39 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
                 let drop_key = self.goodbye_nodes_queue.insert(node, IDLE_PEER_TIMEOUT);
                 self.connected_nodes
                     .insert(node, ConnectionInfo::new_idle(connection, drop_key));
+                // let the node join striped downloads it can help with
+                for kind in self.striped_downloads_of(node) {
+                    self.extend_swarm(kind);
+                }
             }
             Err(err) => {
                 debug!(%node, %err, "connection to node failed");
+                let striped = self.striped_downloads_of(node);
                 self.disconnect_and_retry(node);
+                // striped downloads waiting for the node might have no nodes left to wait for
+                for kind in striped {
+                    self.extend_swarm(kind);
+                }
             }
         }
     }
 
+    /// The striped downloads that further nodes can join, of the hashes `node` provides.
+    fn striped_downloads_of(&self, node: NodeId) -> Vec<DownloadKind> {
+        self.providers
+            .node_hash
+            .get(&node)
+            .into_iter()
+            .flatten()
+            .map(|hash| DownloadKind::from(HashAndFormat::raw(*hash)))
+            .filter(|kind| {
+                self.active_requests
+                    .get(kind)
+                    .is_some_and(|info| info.swarm_tx.is_some())
+            })
+            .collect()
+    }
+
     fn on_download_completed(&mut self, kind: DownloadKind, result: InternalDownloadResult) {
         // first remove the request
         let active_request_info = self
@@ -771,8 +1067,36 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
         // get general request info
         let request_info = self.requests.remove(&kind).expect("request was active");
 
-        let ActiveRequestInfo { node, .. } = active_request_info;
+        for node in active_request_info.nodes() {
+            self.on_node_request_completed(kind, node, &result);
+        }
 
+        // we finalize the download if either the download was successful,
+        // or if it should never proceed because all intents were dropped,
+        // or if we don't have any candidates to proceed with anymore.
+        let finalize = match &result {
+            Ok(_) | Err(FailureAction::AllIntentsDropped) => true,
+            _ => !self.providers.has_candidates(&kind.hash()),
+        };
+
+        if finalize {
+            let result = result.map_err(|_| DownloadError::DownloadFailed);
+            self.finalize_download(kind, request_info.intents, result);
+        } else {
+            // reinsert the download at the front of the queue to try from the next node
+            let priority = request_info.priority();
+            self.requests.insert(kind, request_info);
+            self.queue.insert_front(kind, priority);
+        }
+    }
+
+    /// Update the state of a node that took part in a completed download.
+    fn on_node_request_completed(
+        &mut self,
+        kind: DownloadKind,
+        node: NodeId,
+        result: &InternalDownloadResult,
+    ) {
         // get node info
         let node_info = self
             .connected_nodes
@@ -789,7 +1113,7 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
             Some(active_requests) => ConnectedState::Busy { active_requests },
         };
 
-        match &result {
+        match result {
             Ok(_) => {
                 debug!(%kind, node=%node.fmt_short(), "download successful");
                 // clear retry state if operation was successful
@@ -820,23 +1144,6 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
                 }
             }
         };
-
-        // we finalize the download if either the download was successful,
-        // or if it should never proceed because all intents were dropped,
-        // or if we don't have any candidates to proceed with anymore.
-        let finalize = match &result {
-            Ok(_) | Err(FailureAction::AllIntentsDropped) => true,
-            _ => !self.providers.has_candidates(&kind.hash()),
-        };
-
-        if finalize {
-            let result = result.map_err(|_| DownloadError::DownloadFailed);
-            self.finalize_download(kind, request_info.intents, result);
-        } else {
-            // reinsert the download at the front of the queue to try from the next node
-            self.requests.insert(kind, request_info);
-            self.queue.insert_front(kind);
-        }
     }
 
     /// Finalize a download.
@@ -1070,9 +1377,12 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
 
     /// Start downloading from the given node.
     ///
+    /// Raw blobs with more than one provider are striped, and further providers join the
+    /// download as they become available, see [`Self::extend_swarm`].
+    ///
     /// Panics if hash is not in self.requests or node is not in self.nodes.
     fn start_download(&mut self, kind: DownloadKind, node: NodeId) {
-        let node_info = self.connected_nodes.get_mut(&node).expect("node exists");
+        let node_info = self.connected_nodes.get(&node).expect("node exists");
         let request_info = self.requests.get(&kind).expect("hash exists");
 
         // create a progress sender and subscribe all intents to the progress sender
@@ -1082,14 +1392,30 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
             .flat_map(|state| state.on_progress.clone());
         let progress_sender = self.progress_tracker.track(kind, subscribers);
 
+        // stripe raw blobs if there is more than one provider
+        let striped = kind.format() == BlobFormat::Raw
+            && kind.chunks().is_none()
+            && !self.concurrency_limits.at_nodes_per_download_capacity(1)
+            && self.providers.get_candidates(&kind.hash()).nth(1).is_some();
+
         // create the active request state
         let cancellation = CancellationToken::new();
+        let conn = node_info.conn.clone();
+        let (get_fut, swarm_tx) = if striped {
+            let (swarm_tx, swarm_rx) = mpsc::unbounded_channel();
+            let get_fut = self
+                .getter
+                .get_striped(kind, conn, swarm_rx, progress_sender);
+            (get_fut, Some(swarm_tx))
+        } else {
+            (self.getter.get(kind, conn, progress_sender), None)
+        };
         let state = ActiveRequestInfo {
             cancellation: cancellation.clone(),
             node,
+            swarm: Vec::new(),
+            swarm_tx,
         };
-        let conn = node_info.conn.clone();
-        let get_fut = self.getter.get(kind, conn, progress_sender);
         let fut = async move {
             // NOTE: it's an open question if we should do timeouts at this point. Considerations from @Frando:
             // > at this stage we do not know the size of the download, so the timeout would have
@@ -1106,6 +1432,89 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
             (kind, res)
         }
         .instrument(error_span!("transfer", %kind, node=%node.fmt_short()));
+        self.mark_node_busy(node);
+        self.active_requests.insert(kind, state);
+        self.in_progress_downloads.spawn_local(fut);
+        if striped {
+            self.extend_swarm(kind);
+        }
+    }
+
+    /// Add further providers to the striped download for `kind`.
+    ///
+    /// Connected providers with free capacity join the download right away. Disconnected
+    /// providers are dialed if limits permit, and join once connected. If no provider is being
+    /// dialed, no further node will join, so the channel to the download is closed to let it fail
+    /// once its nodes are exhausted.
+    fn extend_swarm(&mut self, kind: DownloadKind) {
+        let Some(info) = self.active_requests.get(&kind) else {
+            return;
+        };
+        let mut nodes = 1 + info.swarm.len();
+        let mut dialing = 0;
+        let mut to_join = Vec::new();
+        let mut to_dial = Vec::new();
+        for node in self.providers.get_candidates(&kind.hash()) {
+            if info.nodes().any(|n| n == node) {
+                continue;
+            }
+            match self.node_state(node) {
+                NodeState::Connected(conn_info) => {
+                    if !self
+                        .concurrency_limits
+                        .node_at_request_capacity(conn_info.active_requests())
+                        && !self
+                            .concurrency_limits
+                            .at_nodes_per_download_capacity(nodes)
+                    {
+                        to_join.push(node);
+                        nodes += 1;
+                    }
+                }
+                NodeState::Dialing => dialing += 1,
+                NodeState::Disconnected => to_dial.push(node),
+                NodeState::WaitForRetry => {}
+            }
+        }
+
+        for node in to_join {
+            let info = self.active_requests.get_mut(&kind).expect("just checked");
+            let swarm_tx = info.swarm_tx.as_ref().expect("download is striped");
+            let conn = self.connected_nodes[&node].conn.clone();
+            if swarm_tx.send(conn).is_err() {
+                // the download is about to complete
+                return;
+            }
+            debug!(%kind, node=%node.fmt_short(), "node joins striped download");
+            info.swarm.push(node);
+            self.mark_node_busy(node);
+        }
+
+        for node in to_dial {
+            if self
+                .concurrency_limits
+                .at_nodes_per_download_capacity(nodes + dialing)
+                || self.concurrency_limits.at_dials_per_hash_capacity(dialing)
+                || self.at_connections_capacity()
+            {
+                break;
+            }
+            debug!(%kind, node=%node.fmt_short(), "dial node for striped download");
+            self.dialer.queue_dial(node);
+            dialing += 1;
+        }
+
+        if dialing == 0 {
+            if let Some(info) = self.active_requests.get_mut(&kind) {
+                trace!(%kind, "no more nodes join striped download");
+                info.swarm_tx = None;
+            }
+        }
+    }
+
+    /// Count a new active request for a connected node.
+    fn mark_node_busy(&mut self, node: NodeId) {
+        let node_info = self.connected_nodes.get_mut(&node).expect("node exists");
         node_info.state = match &node_info.state {
             ConnectedState::Busy { active_requests } => ConnectedState::Busy {
                 active_requests: active_requests.saturating_add(1),
@@ -1117,8 +1526,6 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
                 }
             }
         };
-        self.active_requests.insert(kind, state);
-        self.in_progress_downloads.spawn_local(fut);
     }
 
     fn disconnect_idle_node(&mut self, node: NodeId, reason: &'static str) -> bool {
@@ -1317,96 +1724,160 @@ impl ProviderMap {
 /// The queue of requested downloads.
 ///
 /// This manages two datastructures:
-/// * The main queue, a FIFO queue where each item can only appear once.
-///   New downloads are pushed to the back of the queue, and the next download to process is popped
-///   from the front.
-/// * The parked set, a hash set. Items can be moved from the main queue into the parked set.
+/// * The main queue, a priority queue where each item can only appear once. Downloads with a
+///   higher priority come first, downloads with the same priority are ordered FIFO.
+///   New downloads are pushed to the back of their priority, and the next download to process is
+///   popped from the front.
+/// * The parked set, a hash map. Items can be moved from the main queue into the parked set.
 ///   Parked items will not be popped unless they are moved back into the main queue.
 #[derive(Debug, Default)]
 struct Queue {
-    main: LinkedHashSet<DownloadKind>,
-    parked: HashSet<DownloadKind>,
+    main: BTreeMap<QueuePosition, DownloadKind>,
+    /// Position of each download in the main queue.
+    positions: HashMap<DownloadKind, QueuePosition>,
+    /// Parked downloads with their priority.
+    parked: HashMap<DownloadKind, i32>,
+    /// Sequence number for the next download inserted at the back of its priority.
+    next_back: i64,
+    /// Sequence number of the last download inserted at the front of its priority.
+    next_front: i64,
+}
+
+/// The position of a download in the main queue of the [`Queue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct QueuePosition {
+    priority: Reverse<i32>,
+    seq: i64,
 }
 
 impl Queue {
     /// Peek at the front element of the main queue.
     pub fn front(&self) -> Option<&DownloadKind> {
-        self.main.front()
+        self.main.values().next()
     }
 
-    #[cfg(any(test, debug_assertions))]
+    /// Iterate over the parked set.
     pub fn iter_parked(&self) -> impl Iterator<Item = &DownloadKind> {
-        self.parked.iter()
+        self.parked.keys()
+    }
+
+    /// Iterate over the main queue, in order.
+    pub fn iter_main(&self) -> impl Iterator<Item = &DownloadKind> {
+        self.main.values()
     }
 
     #[cfg(any(test, debug_assertions))]
     pub fn iter(&self) -> impl Iterator<Item = &DownloadKind> {
-        self.main.iter().chain(self.parked.iter())
+        self.main.values().chain(self.parked.keys())
     }
 
     /// Returns `true` if either the main queue or the parked set contain a download.
     pub fn contains(&self, kind: &DownloadKind) -> bool {
-        self.main.contains(kind) || self.parked.contains(kind)
+        self.positions.contains_key(kind) || self.parked.contains_key(kind)
     }
 
     /// Returns `true` if either the main queue or the parked set contain a download for a hash.
     pub fn contains_hash(&self, hash: Hash) -> bool {
-        let as_raw = HashAndFormat::raw(hash).into();
-        let as_hash_seq = HashAndFormat::hash_seq(hash).into();
-        self.contains(&as_raw) || self.contains(&as_hash_seq)
+        self.positions
+            .keys()
+            .chain(self.parked.keys())
+            .any(|kind| kind.hash() == hash)
     }
 
     /// Returns `true` if a download is in the parked set.
     pub fn is_parked(&self, kind: &DownloadKind) -> bool {
-        self.parked.contains(kind)
+        self.parked.contains_key(kind)
     }
 
-    /// Insert an element at the back of the main queue.
-    pub fn insert(&mut self, kind: DownloadKind) {
-        if !self.main.contains(&kind) {
-            self.main.insert(kind);
+    /// Insert an element at the back of its priority in the main queue.
+    pub fn insert(&mut self, kind: DownloadKind, priority: i32) {
+        if !self.positions.contains_key(&kind) {
+            let seq = self.next_back;
+            self.next_back += 1;
+            self.insert_at(kind, QueuePosition::new(priority, seq));
         }
     }
 
-    /// Insert an element at the front of the main queue.
-    pub fn insert_front(&mut self, kind: DownloadKind) {
-        if !self.main.contains(&kind) {
-            self.main.insert(kind);
-        }
-        self.main.to_front(&kind);
+    /// Insert an element at the front of its priority in the main queue.
+    pub fn insert_front(&mut self, kind: DownloadKind, priority: i32) {
+        self.remove_main(&kind);
+        self.next_front -= 1;
+        let seq = self.next_front;
+        self.insert_at(kind, QueuePosition::new(priority, seq));
     }
 
     /// Dequeue the first download of the main queue.
     pub fn pop_front(&mut self) -> Option<DownloadKind> {
-        self.main.pop_front()
+        self.pop_front_with_position().map(|(_, kind)| kind)
     }
 
     /// Move the front item of the main queue into the parked set.
     pub fn park_front(&mut self) {
-        if let Some(item) = self.pop_front() {
-            self.parked.insert(item);
+        if let Some((position, item)) = self.pop_front_with_position() {
+            self.parked.insert(item, position.priority.0);
         }
     }
 
-    /// Move a download from the parked set to the front of the main queue.
+    /// Move a download from the parked set to the front of its priority in the main queue.
     pub fn unpark(&mut self, kind: &DownloadKind) {
-        if self.parked.remove(kind) {
-            self.main.insert(*kind);
-            self.main.to_front(kind);
+        if let Some(priority) = self.parked.remove(kind) {
+            self.insert_front(*kind, priority);
         }
     }
 
     /// Move any download for a hash from the parked set to the main queue.
     pub fn unpark_hash(&mut self, hash: Hash) {
-        let as_raw = HashAndFormat::raw(hash).into();
-        let as_hash_seq = HashAndFormat::hash_seq(hash).into();
-        self.unpark(&as_raw);
-        self.unpark(&as_hash_seq);
+        let parked = self
+            .parked
+            .keys()
+            .filter(|kind| kind.hash() == hash)
+            .copied()
+            .collect::<Vec<_>>();
+        for kind in parked {
+            self.unpark(&kind);
+        }
+    }
+
+    /// Change the priority of a queued or parked download.
+    ///
+    /// A download in the main queue keeps its position relative to downloads of the same priority.
+    pub fn set_priority(&mut self, kind: &DownloadKind, priority: i32) {
+        if let Some(parked_priority) = self.parked.get_mut(kind) {
+            *parked_priority = priority;
+        } else if let Some(position) = self.remove_main(kind) {
+            self.insert_at(*kind, QueuePosition::new(priority, position.seq));
+        }
     }
 
     /// Remove a download from both the main queue and the parked set.
     pub fn remove(&mut self, kind: &DownloadKind) -> bool {
-        self.main.remove(kind) || self.parked.remove(kind)
+        self.remove_main(kind).is_some() || self.parked.remove(kind).is_some()
+    }
+
+    fn insert_at(&mut self, kind: DownloadKind, position: QueuePosition) {
+        self.main.insert(position, kind);
+        self.positions.insert(kind, position);
+    }
+
+    fn remove_main(&mut self, kind: &DownloadKind) -> Option<QueuePosition> {
+        let position = self.positions.remove(kind)?;
+        self.main.remove(&position);
+        Some(position)
+    }
+
+    fn pop_front_with_position(&mut self) -> Option<(QueuePosition, DownloadKind)> {
+        let (position, kind) = self.main.pop_first()?;
+        self.positions.remove(&kind);
+        Some((position, kind))
+    }
+}
+
+impl QueuePosition {
+    fn new(priority: i32, seq: i64) -> Self {
+        Self {
+            priority: Reverse(priority),
+            seq,
+        }
     }
 }
 
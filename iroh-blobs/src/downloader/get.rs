@@ -2,30 +2,49 @@
 //!
 //! [`Connection`]: iroh_net::endpoint::Connection
 
+use std::num::NonZeroU64;
+
 use crate::{
-    get::{db::get_to_db, error::GetError},
+    get::{
+        db::{get_blob_striped, get_chunks_to_db, get_to_db},
+        error::GetError,
+        Stats,
+    },
     store::Store,
 };
 use futures_lite::FutureExt;
 #[cfg(feature = "metrics")]
 use iroh_metrics::{inc, inc_by};
 use iroh_net::endpoint;
+use tokio::sync::mpsc;
 
 #[cfg(feature = "metrics")]
 use crate::metrics::Metrics;
 
-use super::{progress::BroadcastProgressSender, DownloadKind, FailureAction, GetFut, Getter};
+use super::{
+    progress::BroadcastProgressSender, DownloadKind, FailureAction, GetFut, Getter,
+    InternalDownloadResult,
+};
+
+/// Size of the pieces striped downloads are split into, in chunks (4 MiB).
+const STRIPE_SIZE: NonZeroU64 = match NonZeroU64::new(4096) {
+    Some(size) => size,
+    None => panic!("stripe size must not be zero"),
+};
 
 impl From<GetError> for FailureAction {
     fn from(e: GetError) -> Self {
         match e {
             e @ GetError::NotFound(_) => FailureAction::AbortRequest(e.into()),
             e @ GetError::RemoteReset(_) => FailureAction::RetryLater(e.into()),
+            e @ GetError::AccessDenied(_) => FailureAction::AbortRequest(e.into()),
             e @ GetError::NoncompliantNode(_) => FailureAction::DropPeer(e.into()),
             e @ GetError::Io(_) => FailureAction::RetryLater(e.into()),
             e @ GetError::BadRequest(_) => FailureAction::AbortRequest(e.into()),
             // TODO: what do we want to do on local failures?
             e @ GetError::LocalFailure(_) => FailureAction::AbortRequest(e.into()),
+            // the nodes failed for different reasons, so just try again later
+            e @ GetError::AllNodesFailed(_) => FailureAction::RetryLater(e.into()),
         }
     }
 }
@@ -48,37 +67,71 @@ impl<S: Store> Getter for IoGetter<S> {
     ) -> GetFut {
         let store = self.store.clone();
         let fut = async move {
-            let get_conn = || async move { Ok(conn) };
-            let res = get_to_db(&store, get_conn, &kind.hash_and_format(), progress_sender).await;
-            match res {
-                Ok(stats) => {
-                    #[cfg(feature = "metrics")]
-                    {
-                        let crate::get::Stats {
-                            bytes_written,
-                            bytes_read: _,
-                            elapsed,
-                        } = stats;
-
-                        inc!(Metrics, downloads_success);
-                        inc_by!(Metrics, download_bytes_total, bytes_written);
-                        inc_by!(Metrics, download_time_total, elapsed.as_millis() as u64);
-                    }
-                    Ok(stats)
-                }
-                Err(e) => {
-                    // record metrics according to the error
-                    #[cfg(feature = "metrics")]
-                    {
-                        match &e {
-                            GetError::NotFound(_) => inc!(Metrics, downloads_notfound),
-                            _ => inc!(Metrics, downloads_error),
-                        }
-                    }
-                    Err(e.into())
+            let res = match kind.chunks() {
+                Some(chunks) => get_chunks_to_db(&store, conn, &kind.hash(), chunks).await,
+                None => {
+                    let get_conn = || async move { Ok(conn) };
+                    get_to_db(&store, get_conn, &kind.hash_and_format(), progress_sender).await
                 }
-            }
+            };
+            into_download_result(res)
         };
         fut.boxed_local()
     }
+
+    fn get_striped(
+        &mut self,
+        kind: DownloadKind,
+        conn: Self::Connection,
+        mut more_conns: mpsc::UnboundedReceiver<Self::Connection>,
+        progress_sender: BroadcastProgressSender,
+    ) -> GetFut {
+        let store = self.store.clone();
+        let fut = async move {
+            let more_conns = futures_lite::stream::poll_fn(move |cx| more_conns.poll_recv(cx));
+            let res = get_blob_striped(
+                &store,
+                conn,
+                more_conns,
+                &kind.hash(),
+                STRIPE_SIZE,
+                progress_sender,
+            )
+            .await;
+            into_download_result(res)
+        };
+        fut.boxed_local()
+    }
+}
+
+/// Record metrics for a finished download, and convert its result.
+fn into_download_result(res: Result<Stats, GetError>) -> InternalDownloadResult {
+    match res {
+        Ok(stats) => {
+            #[cfg(feature = "metrics")]
+            {
+                let Stats {
+                    bytes_written,
+                    bytes_read: _,
+                    elapsed,
+                } = stats;
+
+                inc!(Metrics, downloads_success);
+                inc_by!(Metrics, download_bytes_total, bytes_written);
+                inc_by!(Metrics, download_time_total, elapsed.as_millis() as u64);
+            }
+            Ok(stats)
+        }
+        Err(e) => {
+            // record metrics according to the error
+            #[cfg(feature = "metrics")]
+            {
+                match &e {
+                    GetError::NotFound(_) => inc!(Metrics, downloads_notfound),
+                    _ => inc!(Metrics, downloads_error),
+                }
+            }
+            Err(e.into())
+        }
+    }
 }
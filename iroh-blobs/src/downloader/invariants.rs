@@ -24,6 +24,7 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
             max_concurrent_requests_per_node,
             max_open_connections,
             max_concurrent_dials_per_hash,
+            max_nodes_per_download,
         } = &self.concurrency_limits;
 
         // check the total number of active requests to ensure it stays within the limit
@@ -53,6 +54,14 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
             )
         }
 
+        // check the nodes taking part in a single download don't exceed the limit
+        for (kind, info) in self.active_requests.iter() {
+            assert!(
+                info.nodes().count() <= (*max_nodes_per_download).max(1),
+                "max_nodes_per_download exceeded for {kind}"
+            )
+        }
+
         // check that we do not dial more nodes than allowed for the next pending hashes
         if let Some(kind) = self.queue.front() {
             let hash = kind.hash();
@@ -87,7 +96,9 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
             HashMap::with_capacity(self.connected_nodes.len());
         for req_info in self.active_requests.values() {
             // nothing like some classic word count
-            *real_count.entry(req_info.node).or_default() += 1;
+            for node in req_info.nodes() {
+                *real_count.entry(node).or_default() += 1;
+            }
         }
         for (peer, info) in self.connected_nodes.iter() {
             assert_eq!(
@@ -150,12 +161,9 @@ impl<G: Getter<Connection = D::Connection>, D: Dialer> Service<G, D> {
     #[track_caller]
     fn check_provider_map_prunning(&self) {
         for hash in self.providers.hash_node.keys() {
-            let as_raw = DownloadKind(HashAndFormat::raw(*hash));
-            let as_hash_seq = DownloadKind(HashAndFormat::hash_seq(*hash));
             assert!(
                 self.queue.contains_hash(*hash)
-                    || self.active_requests.contains_key(&as_raw)
-                    || self.active_requests.contains_key(&as_hash_seq),
+                    || self.active_requests.keys().any(|kind| kind.hash() == *hash),
                 "all hashes in the provider map are in the queue or active"
             )
         }
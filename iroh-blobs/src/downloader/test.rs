@@ -500,3 +500,144 @@ async fn retry_nodes_jump_queue() {
     // assert history
     dialer.assert_history(&[bad_node, good_node, bad_node]);
 }
+
+/// Tests that raw blobs with several providers are striped across them, up to the limit of
+/// nodes per download.
+#[tokio::test]
+async fn striped_download() {
+    let _guard = iroh_test::logging::setup();
+    let dialer = dialer::TestingDialer::default();
+    let getter = getter::TestingGetter::default();
+    // make sure the second node connects while the request is running
+    getter.set_request_duration(Duration::from_millis(200));
+    let concurrency_limits = ConcurrencyLimits {
+        max_nodes_per_download: 2,
+        ..Default::default()
+    };
+
+    let downloader = Downloader::spawn_for_test(dialer.clone(), getter.clone(), concurrency_limits);
+
+    let nodes = (0..3)
+        .map(|_| SecretKey::generate().public())
+        .collect::<Vec<_>>();
+    let kind: DownloadKind = HashAndFormat::raw(Hash::new([0u8; 32])).into();
+    let req = DownloadRequest::new(kind, nodes.clone());
+    let handle = downloader.queue(req).await;
+    assert!(handle.await.is_ok());
+
+    // one node performed the request, and only one other node joined it
+    let history = getter.request_history();
+    let swarm_history = getter.swarm_history();
+    assert_eq!(history.len(), 1);
+    assert_eq!(swarm_history.len(), 1);
+    assert_ne!(history[0].1, swarm_history[0].1);
+    assert!(nodes.contains(&history[0].1));
+    assert!(nodes.contains(&swarm_history[0].1));
+}
+
+/// Tests that a striped download learns that no further nodes will join once the dial to the
+/// other provider failed, so that it can fail instead of waiting for them.
+#[tokio::test]
+async fn striped_download_swarm_closed() {
+    let _guard = iroh_test::logging::setup();
+    let dialer = dialer::TestingDialer::default();
+    let getter = getter::TestingGetter::default();
+    getter.set_request_duration(Duration::from_millis(200));
+    let concurrency_limits = ConcurrencyLimits {
+        max_nodes_per_download: 2,
+        ..Default::default()
+    };
+
+    let downloader = Downloader::spawn_for_test(dialer.clone(), getter.clone(), concurrency_limits);
+
+    let good_node = SecretKey::generate().public();
+    let bad_node = SecretKey::generate().public();
+    dialer.set_dial_outcome(move |node| node == good_node);
+    let kind: DownloadKind = HashAndFormat::raw(Hash::new([0u8; 32])).into();
+    let req = DownloadRequest::new(kind, vec![good_node, bad_node]);
+    let handle = downloader.queue(req).await;
+    assert!(handle.await.is_ok());
+
+    assert_eq!(getter.request_history(), vec![(kind, good_node)]);
+    assert!(getter.swarm_history().is_empty());
+    assert_eq!(getter.swarm_closed(), vec![kind]);
+}
+
+/// Tests that queued downloads are started in the order of their priority, and that the
+/// priority of a queued download can be changed.
+#[tokio::test]
+async fn download_priorities() {
+    let _guard = iroh_test::logging::setup();
+    let dialer = dialer::TestingDialer::default();
+    let getter = getter::TestingGetter::default();
+    getter.set_request_duration(Duration::from_millis(100));
+    // only run a single download at a time, so that all others are queued
+    let concurrency_limits = ConcurrencyLimits {
+        max_concurrent_requests: 1,
+        ..Default::default()
+    };
+
+    let downloader = Downloader::spawn_for_test(dialer.clone(), getter.clone(), concurrency_limits);
+
+    let peer = SecretKey::generate().public();
+    let kinds = (0..5)
+        .map(|i| DownloadKind::from(HashAndFormat::raw(Hash::new([i; 32]))))
+        .collect::<Vec<_>>();
+
+    // start the first download and wait until it is active
+    let mut handles = vec![
+        downloader
+            .queue(DownloadRequest::new(kinds[0], [peer]))
+            .await,
+    ];
+    loop {
+        let snapshot = downloader.queue_snapshot().await.unwrap();
+        if !snapshot.active.is_empty() {
+            assert_eq!(snapshot.active[0].hash_and_format, kinds[0].into());
+            assert_eq!(snapshot.active[0].active_nodes, vec![peer]);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    for (kind, priority) in kinds[1..].iter().zip([0, 5, 1, 0]) {
+        let req = DownloadRequest::new(*kind, [peer]).priority(priority);
+        handles.push(downloader.queue(req).await);
+    }
+    let queued = |snapshot: QueueSnapshot| {
+        snapshot
+            .queued
+            .into_iter()
+            .map(|info| DownloadKind::from(info.hash_and_format))
+            .collect::<Vec<_>>()
+    };
+    let snapshot = downloader.queue_snapshot().await.unwrap();
+    assert_eq!(snapshot.queued[0].intents, vec![handles[2].id()]);
+    assert_eq!(snapshot.queued[0].candidates, vec![peer]);
+    assert_eq!(
+        queued(snapshot),
+        vec![kinds[2], kinds[3], kinds[1], kinds[4]]
+    );
+
+    // move the last download to the front
+    downloader.set_priority(handles[4].id(), 10).await.unwrap();
+    let snapshot = downloader.queue_snapshot().await.unwrap();
+    assert_eq!(
+        queued(snapshot),
+        vec![kinds[4], kinds[2], kinds[3], kinds[1]]
+    );
+    assert!(downloader
+        .set_priority(IntentId(u64::MAX), 1)
+        .await
+        .is_err());
+
+    assert!(
+        futures_buffered::join_all(handles)
+            .await
+            .into_iter()
+            .all(|r| r.is_ok()),
+        "all downloads should succeed"
+    );
+    let order = [0, 4, 2, 3, 1].map(|i| (kinds[i], peer));
+    getter.assert_history(&order);
+}
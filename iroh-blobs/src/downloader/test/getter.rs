@@ -28,6 +28,10 @@ struct TestingGetterInner {
     request_history: Vec<(DownloadKind, NodeId)>,
     /// Set a handler function which actually handles the requests.
     request_handler: Option<RequestHandlerFn>,
+    /// History of nodes that joined striped requests.
+    swarm_history: Vec<(DownloadKind, NodeId)>,
+    /// Striped requests that no further nodes will join.
+    swarm_closed: Vec<DownloadKind>,
 }
 
 impl Getter for TestingGetter {
@@ -55,6 +59,25 @@ impl Getter for TestingGetter {
         }
         .boxed_local()
     }
+
+    fn get_striped(
+        &mut self,
+        kind: DownloadKind,
+        peer: NodeId,
+        mut more_conns: mpsc::UnboundedReceiver<NodeId>,
+        progress_sender: BroadcastProgressSender,
+    ) -> GetFut {
+        let request = self.get(kind, peer, progress_sender);
+        let inner = self.0.clone();
+        let record_swarm = async move {
+            while let Some(peer) = more_conns.recv().await {
+                inner.write().swarm_history.push((kind, peer));
+            }
+            inner.write().swarm_closed.push(kind);
+            std::future::pending().await
+        };
+        request.or(record_swarm).boxed_local()
+    }
 }
 
 impl TestingGetter {
@@ -69,4 +92,16 @@ impl TestingGetter {
     pub(super) fn assert_history(&self, history: &[(DownloadKind, NodeId)]) {
         assert_eq!(self.0.read().request_history, history);
     }
+    /// Get the history of requests performed by the [`Getter`].
+    pub(super) fn request_history(&self) -> Vec<(DownloadKind, NodeId)> {
+        self.0.read().request_history.clone()
+    }
+    /// Get the history of nodes that joined striped requests.
+    pub(super) fn swarm_history(&self) -> Vec<(DownloadKind, NodeId)> {
+        self.0.read().swarm_history.clone()
+    }
+    /// Get the striped requests that no further nodes will join.
+    pub(super) fn swarm_closed(&self) -> Vec<DownloadKind> {
+        self.0.read().swarm_closed.clone()
+    }
 }
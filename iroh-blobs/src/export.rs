@@ -1,15 +1,24 @@
 //! Functions to export data from a store
 
-use std::path::PathBuf;
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+};
 
 use anyhow::Context;
 use bytes::Bytes;
 use iroh_base::rpc::RpcError;
+use iroh_io::AsyncSliceReaderExt;
 use serde::{Deserialize, Serialize};
-use tracing::trace;
+use tokio::io::AsyncWriteExt;
+use tracing::{trace, warn};
 
 use crate::{
-    format::collection::Collection,
+    format::{
+        chunked::ChunkedBlob,
+        collection::{Collection, EntryKind, EntryMeta},
+    },
     store::{BaoBlobSize, ExportFormat, ExportMode, MapEntry, Store as BaoStore},
     util::progress::{IdGenerator, ProgressSender},
     Hash,
@@ -34,10 +43,16 @@ pub async fn export<D: BaoStore>(
     match format {
         ExportFormat::Blob => export_blob(db, hash, outpath, mode, progress).await,
         ExportFormat::Collection => export_collection(db, hash, outpath, mode, progress).await,
+        ExportFormat::Chunked => export_chunked(db, hash, outpath, progress).await,
     }
 }
 
 /// Export all entries of a collection, recursively, to files on the local filesystem.
+///
+/// If the collection has file system metadata, empty directories and symlinks
+/// are created, and permission bits and modification times are restored where
+/// the platform supports it. Metadata that would reach outside of `outpath` is
+/// skipped, see [`restore_meta`].
 pub async fn export_collection<D: BaoStore>(
     db: &D,
     hash: Hash,
@@ -47,14 +62,164 @@ pub async fn export_collection<D: BaoStore>(
 ) -> anyhow::Result<()> {
     tokio::fs::create_dir_all(&outpath).await?;
     let collection = Collection::load_db(db, &hash).await?;
-    for (name, hash) in collection.into_iter() {
-        #[allow(clippy::needless_borrow)]
-        let path = outpath.join(pathbuf_from_name(&name));
-        export_blob(db, hash, path, mode, progress.clone()).await?;
+    for (name, hash) in collection.iter() {
+        let path = outpath.join(pathbuf_from_name(name));
+        export_blob(db, *hash, path, mode, progress.clone()).await?;
+    }
+    let entries = collection
+        .iter_meta()
+        .map(|(name, meta)| (name.clone(), meta.clone()))
+        .collect::<Vec<_>>();
+    if !entries.is_empty() {
+        let blobs = collection
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<HashSet<_>>();
+        tokio::task::spawn_blocking(move || restore_meta(&outpath, entries, &blobs)).await??;
+    }
+    Ok(())
+}
+
+/// Restore file system metadata of exported collection entries below `root`.
+///
+/// All directories and symlinks are created before any metadata is applied,
+/// so restored permissions never prevent creating an entry, and creating an
+/// entry never changes a restored modification time. Symlinks are created
+/// after the files have been written, so no file is ever written through a
+/// symlink from the collection. Directories are updated last and deepest
+/// first, so their modification times are not changed by restoring their
+/// children.
+///
+/// The metadata comes from the collection and is not trusted. Entries with
+/// invalid names, symlinks that point outside of `root`, and file metadata for
+/// names that were not exported as one of the `blobs` are skipped. Nothing is
+/// ever created or changed through a symlink.
+fn restore_meta(
+    root: &Path,
+    mut entries: Vec<(String, EntryMeta)>,
+    blobs: &HashSet<String>,
+) -> io::Result<()> {
+    entries.retain(|(name, meta)| {
+        let valid = is_valid_name(name)
+            && match &meta.kind {
+                EntryKind::Symlink { target } => is_contained_target(name, target),
+                EntryKind::File => blobs.contains(name),
+                EntryKind::Dir => true,
+            };
+        if !valid {
+            warn!("skipping metadata of collection entry {:?}", name);
+        }
+        valid
+    });
+    entries.sort_by_key(|(name, meta)| {
+        let order = match meta.kind {
+            EntryKind::Symlink { .. } => 0,
+            EntryKind::File => 1,
+            EntryKind::Dir => 2,
+        };
+        (order, std::cmp::Reverse(name.split('/').count()))
+    });
+    for (name, meta) in &entries {
+        let path = root.join(pathbuf_from_name(name));
+        match &meta.kind {
+            EntryKind::Dir => {
+                if has_symlink(root, name, true)? {
+                    warn!("skipping directory {:?} below a symlink", name);
+                    continue;
+                }
+                std::fs::create_dir_all(path)?
+            }
+            EntryKind::Symlink { target } => {
+                if has_symlink(root, name, false)? {
+                    warn!("skipping symlink {:?} below a symlink", name);
+                    continue;
+                }
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(target, &path)?;
+                #[cfg(not(unix))]
+                tracing::warn!(
+                    "not creating symlink {} -> {}, symlinks are only supported on unix",
+                    path.display(),
+                    target
+                );
+            }
+            EntryKind::File => {}
+        }
+    }
+    for (name, meta) in entries {
+        if matches!(meta.kind, EntryKind::Symlink { .. }) {
+            continue;
+        }
+        if has_symlink(root, &name, true)? {
+            warn!("not restoring metadata of {:?} through a symlink", name);
+            continue;
+        }
+        let path = root.join(pathbuf_from_name(&name));
+        if let Some(mtime) = meta.mtime {
+            std::fs::File::open(&path)?.set_modified(mtime)?;
+        }
+        #[cfg(unix)]
+        if let Some(mode) = meta.mode {
+            use std::os::unix::fs::PermissionsExt;
+            // never restore setuid and setgid bits
+            let permissions = std::fs::Permissions::from_mode(mode & 0o1777);
+            std::fs::set_permissions(&path, permissions)?;
+        }
     }
     Ok(())
 }
 
+/// Whether `name` is a relative path without empty, `.` or `..` components.
+fn is_valid_name(name: &str) -> bool {
+    name.split('/').all(|part| !matches!(part, "" | "." | ".."))
+}
+
+/// Whether the `target` of a symlink at `name` stays inside the export root.
+///
+/// Only relative targets that first climb up with `..` and then descend are
+/// allowed, so that climbing up never starts from the target of another
+/// symlink.
+fn is_contained_target(name: &str, target: &str) -> bool {
+    let mut depth = name.split('/').count() - 1;
+    let mut descending = false;
+    for part in target.split('/') {
+        match part {
+            "" => return false,
+            "." => {}
+            ".." if descending || depth == 0 => return false,
+            ".." => depth -= 1,
+            _ => descending = true,
+        }
+    }
+    true
+}
+
+/// Whether an existing component of `name` below `root` is a symlink.
+///
+/// The last component is only checked if `include_last` is true.
+fn has_symlink(root: &Path, name: &str, include_last: bool) -> io::Result<bool> {
+    let parts = name.split('/').collect::<Vec<_>>();
+    let len = if include_last {
+        parts.len()
+    } else {
+        parts.len() - 1
+    };
+    let mut path = root.to_path_buf();
+    for part in &parts[..len] {
+        path.push(part);
+        match std::fs::symlink_metadata(&path) {
+            Ok(meta) if meta.file_type().is_symlink() => return Ok(true),
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(false)
+}
+
 /// Export a single blob to a file on the local filesystem.
 pub async fn export_blob<D: BaoStore>(
     db: &D,
@@ -90,6 +255,52 @@ pub async fn export_blob<D: BaoStore>(
     Ok(())
 }
 
+/// Reassemble a [`ChunkedBlob`] into a single file on the local filesystem.
+///
+/// The chunks are always copied, since they are not stored contiguously.
+pub async fn export_chunked<D: BaoStore>(
+    db: &D,
+    hash: Hash,
+    outpath: PathBuf,
+    progress: impl ProgressSender<Msg = ExportProgress> + IdGenerator,
+) -> anyhow::Result<()> {
+    if let Some(parent) = outpath.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    trace!("exporting chunked blob {} to {}", hash, outpath.display());
+    let blob = ChunkedBlob::load_db(db, &hash).await?;
+    let id = progress.new_id();
+    progress
+        .send(ExportProgress::Found {
+            id,
+            hash,
+            outpath: outpath.clone(),
+            size: BaoBlobSize::Unverified(blob.size()),
+            meta: None,
+        })
+        .await?;
+    let mut file = tokio::fs::File::create(&outpath).await?;
+    let mut offset = 0;
+    for (chunk, size) in blob.chunks() {
+        let entry = db.get(chunk).await?.context("chunk not there")?;
+        anyhow::ensure!(entry.is_complete(), "chunk {} not complete", chunk);
+        let data = entry.data_reader().await?.read_to_end().await?;
+        anyhow::ensure!(
+            data.len() as u64 == *size,
+            "chunk {} has size {}, expected {}",
+            chunk,
+            data.len(),
+            size
+        );
+        file.write_all(&data).await?;
+        offset += size;
+        progress.try_send(ExportProgress::Progress { id, offset })?;
+    }
+    file.sync_all().await?;
+    progress.send(ExportProgress::Done { id }).await?;
+    Ok(())
+}
+
 /// Progress events for an export operation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ExportProgress {
@@ -134,3 +345,75 @@ fn pathbuf_from_name(name: &str) -> PathBuf {
     }
     path
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+    use crate::{format::collection::Collection, store::mem, util::progress::IgnoreProgressSender};
+
+    #[tokio::test]
+    async fn export_collection_hostile_meta() -> testresult::TestResult {
+        let dir = tempfile::tempdir()?;
+        let victim = dir.path().join("victim");
+        std::fs::create_dir(&victim)?;
+        std::fs::write(victim.join(".bashrc"), b"safe")?;
+        std::fs::set_permissions(
+            victim.join(".bashrc"),
+            std::fs::Permissions::from_mode(0o600),
+        )?;
+
+        let db = mem::Store::new();
+        let tag = db
+            .import_bytes("hello".into(), crate::BlobFormat::Raw)
+            .await?;
+        let mut collection = [("a".to_string(), *tag.hash())]
+            .into_iter()
+            .collect::<Collection>();
+        let symlink = |target: &str| {
+            EntryMeta::new(EntryKind::Symlink {
+                target: target.into(),
+            })
+        };
+        let file = EntryMeta {
+            kind: EntryKind::File,
+            mode: Some(0o777),
+            mtime: None,
+            size: None,
+        };
+        collection.set_meta("l".into(), symlink(victim.to_str().unwrap()));
+        collection.set_meta("l/.bashrc".into(), file.clone());
+        collection.set_meta("up".into(), symlink("../victim"));
+        collection.set_meta("../victim/.bashrc".into(), file.clone());
+        collection.set_meta("d/esc".into(), symlink("x/../../.."));
+        collection.set_meta("ok".into(), symlink("d/../a"));
+        collection.set_meta("in".into(), symlink("a"));
+        collection.set_meta("in/x".into(), EntryMeta::new(EntryKind::Dir));
+        collection.set_meta("a".into(), file);
+        let root = collection.store(&db).await?;
+
+        let out = dir.path().join("out");
+        export_collection(
+            &db,
+            *root.hash(),
+            out.clone(),
+            ExportMode::Copy,
+            IgnoreProgressSender::default(),
+        )
+        .await?;
+
+        let mode = std::fs::metadata(victim.join(".bashrc"))?
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(std::fs::read(victim.join(".bashrc"))?, b"safe");
+        for name in ["l", "up", "d/esc", "ok"] {
+            assert!(std::fs::symlink_metadata(out.join(name)).is_err(), "{name}");
+        }
+        assert_eq!(std::fs::read_link(out.join("in"))?, Path::new("a"));
+        let mode = std::fs::metadata(out.join("a"))?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o777);
+        Ok(())
+    }
+}
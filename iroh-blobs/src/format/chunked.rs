@@ -0,0 +1,494 @@
+//! Content defined chunking for large blobs.
+//!
+//! A chunked blob is stored as a [`HashSeq`] whose first child is a metadata
+//! blob, followed by the chunks in order. The metadata blob starts with
+//! [`ChunkedBlob::HEADER`], which marks the root as a chunked blob and holds
+//! the format version, followed by the size of each chunk. Hash sequences
+//! without this header, like collections, are rejected when loading them as
+//! a chunked blob.
+//!
+//! Chunk boundaries depend only on the content, so versions of a file that
+//! differ by a few bytes share most of their chunks, both in the store and
+//! when transferring them. The root is a regular [`BlobFormat::HashSeq`], so
+//! it is provided and downloaded like any other hash sequence, and chunks
+//! that are already present locally are not transferred again.
+use std::io;
+
+use anyhow::Context;
+use bao_tree::blake3;
+use bytes::{Bytes, BytesMut};
+use futures_lite::{Stream, StreamExt};
+use iroh_io::AsyncSliceReaderExt;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    hashseq::HashSeq,
+    store::{temp_name, ImportProgress, MapEntry},
+    util::{
+        progress::{IdGenerator, ProgressSender},
+        TempTag,
+    },
+    BlobFormat, Hash,
+};
+
+/// Configuration for the content defined [`Chunker`].
+///
+/// All sizes are in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkerConfig {
+    /// No chunk except the last one is smaller than this.
+    pub min_size: usize,
+    /// The size chunks are normalized to. Must be a power of two.
+    pub avg_size: usize,
+    /// No chunk is larger than this.
+    pub max_size: usize,
+}
+
+impl ChunkerConfig {
+    /// Check that the average size is a power of two, and the sizes are
+    /// ordered `0 < min_size <= avg_size <= max_size`.
+    pub fn validate(&self) -> io::Result<()> {
+        let invalid = |msg| Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        if !self.avg_size.is_power_of_two() {
+            return invalid("avg_size must be a power of two");
+        }
+        if self.min_size == 0 || self.min_size > self.avg_size {
+            return invalid("min_size must be between 0 and avg_size");
+        }
+        if self.avg_size > self.max_size {
+            return invalid("max_size must be at least avg_size");
+        }
+        Ok(())
+    }
+}
+
+impl Default for ChunkerConfig {
+    fn default() -> Self {
+        Self {
+            min_size: 64 * 1024,
+            avg_size: 256 * 1024,
+            max_size: 1024 * 1024,
+        }
+    }
+}
+
+/// A FastCDC style content defined chunker.
+///
+/// Boundaries are found with a gear rolling hash. Before the average size a
+/// boundary requires more matching bits than after it, which keeps the chunk
+/// sizes close to the average.
+#[derive(Debug, Clone)]
+pub struct Chunker {
+    config: ChunkerConfig,
+    mask_small: u64,
+    mask_large: u64,
+}
+
+impl Chunker {
+    /// Create a new chunker.
+    ///
+    /// Fails if the config is invalid, see [`ChunkerConfig::validate`].
+    pub fn new(config: ChunkerConfig) -> io::Result<Self> {
+        config.validate()?;
+        let bits = config.avg_size.ilog2();
+        Ok(Self {
+            config,
+            mask_small: high_bits(bits + 1),
+            mask_large: high_bits(bits.saturating_sub(1)),
+        })
+    }
+
+    /// The configuration of this chunker.
+    pub fn config(&self) -> &ChunkerConfig {
+        &self.config
+    }
+
+    /// Find the end of the first chunk in `data`.
+    ///
+    /// Only the first `max_size` bytes are considered, so the result is stable
+    /// as long as `data` contains at least that many bytes or is the end of
+    /// the input.
+    pub fn cut(&self, data: &[u8]) -> usize {
+        let ChunkerConfig {
+            min_size,
+            avg_size,
+            max_size,
+        } = self.config;
+        if data.len() <= min_size {
+            return data.len();
+        }
+        let end = data.len().min(max_size);
+        let normal = end.min(avg_size);
+        let mut hash = 0u64;
+        for (i, byte) in data.iter().enumerate().take(normal).skip(min_size) {
+            hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+            if hash & self.mask_small == 0 {
+                return i + 1;
+            }
+        }
+        for (i, byte) in data.iter().enumerate().take(end).skip(normal) {
+            hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+            if hash & self.mask_large == 0 {
+                return i + 1;
+            }
+        }
+        end
+    }
+
+    /// Split `data` into chunks.
+    pub fn chunks<'a>(&'a self, mut data: &'a [u8]) -> impl Iterator<Item = &'a [u8]> + 'a {
+        std::iter::from_fn(move || {
+            if data.is_empty() {
+                return None;
+            }
+            let (chunk, rest) = data.split_at(self.cut(data));
+            data = rest;
+            Some(chunk)
+        })
+    }
+}
+
+/// A mask with the `n` most significant bits set.
+///
+/// The gear hash is shifted left, so the high bits depend on a window of
+/// the last 64 bytes.
+fn high_bits(n: u32) -> u64 {
+    match n {
+        0 => 0,
+        n => u64::MAX << (64 - n.min(64)),
+    }
+}
+
+/// Random values for the gear hash, one for each byte value.
+static GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    // splitmix64, so the table is fixed without spelling out 256 constants
+    let mut table = [0u64; 256];
+    let mut state = 0x6a09_e667_f3bc_c908u64;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// A blob that was split into content defined chunks.
+///
+/// Note that the format is subject to change.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct ChunkedBlob {
+    /// The hashes and sizes of the chunks, in order.
+    chunks: Vec<(Hash, u64)>,
+}
+
+/// Metadata for a chunked blob
+///
+/// This is the wire format for the metadata blob.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+struct ChunkedBlobMeta {
+    header: [u8; 13], // Must contain "ChunkedBlob0."
+    sizes: Vec<u64>,
+}
+
+impl FromIterator<(Hash, u64)> for ChunkedBlob {
+    fn from_iter<T: IntoIterator<Item = (Hash, u64)>>(iter: T) -> Self {
+        Self {
+            chunks: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl ChunkedBlob {
+    /// The header for the chunked blob format.
+    ///
+    /// This is the start of the metadata blob. The last digit is the format
+    /// version.
+    pub const HEADER: &'static [u8; 13] = b"ChunkedBlob0.";
+
+    /// The hashes and sizes of the chunks, in order.
+    pub fn chunks(&self) -> &[(Hash, u64)] {
+        &self.chunks
+    }
+
+    /// The total size of the data in bytes.
+    pub fn size(&self) -> u64 {
+        self.chunks.iter().map(|(_, size)| size).sum()
+    }
+
+    /// Import data from a stream, splitting it into content defined chunks.
+    ///
+    /// Returns a temp tag for the [`BlobFormat::HashSeq`] root and the total size.
+    pub async fn import<D>(
+        db: &D,
+        mut data: impl Stream<Item = io::Result<Bytes>> + Unpin,
+        config: ChunkerConfig,
+        progress: impl ProgressSender<Msg = ImportProgress> + IdGenerator,
+    ) -> io::Result<(TempTag, u64)>
+    where
+        D: crate::store::Store,
+    {
+        let chunker = Chunker::new(config)?;
+        let id = progress.new_id();
+        let name = temp_name();
+        progress.send(ImportProgress::Found { id, name }).await?;
+        let mut chunks = Vec::new();
+        // keep the chunks alive until the root protects them
+        let mut tags = Vec::new();
+        let mut buffer = BytesMut::new();
+        let mut offset = 0u64;
+        let mut done = false;
+        while !done {
+            match data.next().await {
+                Some(bytes) => buffer.extend_from_slice(&bytes?),
+                None => done = true,
+            }
+            // only cut once enough data is buffered for the boundary to be stable
+            while !buffer.is_empty() && (done || buffer.len() >= config.max_size) {
+                let chunk = buffer.split_to(chunker.cut(&buffer)).freeze();
+                let size = chunk.len() as u64;
+                let tag = db.import_bytes(chunk, BlobFormat::Raw).await?;
+                chunks.push((*tag.hash(), size));
+                tags.push(tag);
+                offset += size;
+                progress
+                    .try_send(ImportProgress::CopyProgress { id, offset })
+                    .ok();
+            }
+        }
+        progress
+            .send(ImportProgress::Size { id, size: offset })
+            .await?;
+        let blob = Self { chunks };
+        let tag = blob.store(db).await.map_err(io::Error::other)?;
+        progress
+            .send(ImportProgress::OutboardDone {
+                id,
+                hash: *tag.hash(),
+            })
+            .await?;
+        Ok((tag, offset))
+    }
+
+    /// Load a chunked blob from a store given a root hash
+    ///
+    /// This assumes that both the links and the metadata of the chunked blob are stored in the store.
+    /// It does not require that all chunks are stored in the store.
+    pub async fn load_db<D>(db: &D, root: &Hash) -> anyhow::Result<Self>
+    where
+        D: crate::store::Map,
+    {
+        let links_entry = db.get(root).await?.context("links not found")?;
+        anyhow::ensure!(links_entry.is_complete(), "links not complete");
+        let links_bytes = links_entry.data_reader().await?.read_to_end().await?;
+        let mut links = HashSeq::try_from(links_bytes)?;
+        let meta_hash = links.pop_front().context("meta hash not found")?;
+        let meta_entry = db.get(&meta_hash).await?.context("meta not found")?;
+        anyhow::ensure!(meta_entry.is_complete(), "meta not complete");
+        let meta_bytes = meta_entry.data_reader().await?.read_to_end().await?;
+        let header = meta_bytes.get(..13).context("metadata too short")?;
+        anyhow::ensure!(
+            header == Self::HEADER,
+            "not a chunked blob: expected header {:?}, got {:?}",
+            Self::HEADER,
+            header
+        );
+        let meta: ChunkedBlobMeta = postcard::from_bytes(&meta_bytes)?;
+        anyhow::ensure!(
+            meta.sizes.len() == links.len(),
+            "sizes and links length mismatch"
+        );
+        Ok(links.into_iter().zip(meta.sizes).collect())
+    }
+
+    /// Store the links and metadata of a chunked blob in a store. returns the
+    /// root hash as a TempTag.
+    pub async fn store<D>(&self, db: &D) -> anyhow::Result<TempTag>
+    where
+        D: crate::store::Store,
+    {
+        let meta_bytes = postcard::to_stdvec(&self.meta())?;
+        let meta_tag = db.import_bytes(meta_bytes.into(), BlobFormat::Raw).await?;
+        let links_bytes = std::iter::once(*meta_tag.hash())
+            .chain(self.chunks.iter().map(|(hash, _)| *hash))
+            .collect::<HashSeq>();
+        let links_tag = db
+            .import_bytes(links_bytes.into(), BlobFormat::HashSeq)
+            .await?;
+        Ok(links_tag)
+    }
+
+    /// Convert the chunked blob to an iterator of blobs, with the last being
+    /// the root blob. The chunks themselves are not included.
+    pub fn to_blobs(&self) -> anyhow::Result<impl DoubleEndedIterator<Item = Bytes>> {
+        let meta_bytes = postcard::to_stdvec(&self.meta())?;
+        let meta_bytes_hash = blake3::hash(&meta_bytes).into();
+        let links = std::iter::once(meta_bytes_hash)
+            .chain(self.chunks.iter().map(|(hash, _)| *hash))
+            .collect::<HashSeq>();
+        Ok([meta_bytes.into(), links.into_inner()].into_iter())
+    }
+
+    fn meta(&self) -> ChunkedBlobMeta {
+        ChunkedBlobMeta {
+            header: *Self::HEADER,
+            sizes: self.chunks.iter().map(|(_, size)| *size).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        format::collection::Collection,
+        store::{mem, ExportFormat, ExportMode, Store},
+        util::progress::IgnoreProgressSender,
+    };
+
+    fn test_data(size: usize, seed: u64) -> Vec<u8> {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        (0..size).map(|_| rng.gen()).collect()
+    }
+
+    fn small_config() -> ChunkerConfig {
+        ChunkerConfig {
+            min_size: 1024,
+            avg_size: 4096,
+            max_size: 16384,
+        }
+    }
+
+    #[test]
+    fn invalid_config() {
+        let invalid = [
+            (1024, 3000, 16384),
+            (0, 4096, 16384),
+            (8192, 4096, 16384),
+            (1024, 4096, 2048),
+        ];
+        for (min_size, avg_size, max_size) in invalid {
+            let config = ChunkerConfig {
+                min_size,
+                avg_size,
+                max_size,
+            };
+            let err = Chunker::new(config).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn chunk_sizes() {
+        let chunker = Chunker::new(small_config()).unwrap();
+        let data = test_data(1024 * 1024, 0);
+        let chunks = chunker.chunks(&data).collect::<Vec<_>>();
+        assert_eq!(chunks.concat(), data);
+        let (last, rest) = chunks.split_last().unwrap();
+        assert!(last.len() <= 16384);
+        for chunk in rest {
+            assert!(chunk.len() >= 1024 && chunk.len() <= 16384);
+        }
+        // the average is close to the configured one
+        let avg = data.len() / chunks.len();
+        assert!((2048..8192).contains(&avg), "avg {avg}");
+    }
+
+    #[test]
+    fn chunks_survive_insertion() {
+        let chunker = Chunker::new(small_config()).unwrap();
+        let data = test_data(1024 * 1024, 1);
+        let mut changed = data.clone();
+        changed.splice(1000..1000, b"a few more bytes".iter().copied());
+        let a = chunker.chunks(&data).collect::<BTreeSet<_>>();
+        let b = chunker.chunks(&changed).collect::<BTreeSet<_>>();
+        let shared = a.intersection(&b).count();
+        assert!(shared + 2 >= a.len(), "{shared} of {} shared", a.len());
+    }
+
+    #[tokio::test]
+    async fn import_export() -> testresult::TestResult {
+        let db = mem::Store::new();
+        let data = test_data(200 * 1024, 2);
+        let stream = futures_lite::stream::iter(
+            data.chunks(1000)
+                .map(|x| io::Result::Ok(Bytes::copy_from_slice(x)))
+                .collect::<Vec<_>>(),
+        );
+        let (tag, size) =
+            ChunkedBlob::import(&db, stream, small_config(), IgnoreProgressSender::default())
+                .await?;
+        assert_eq!(size, data.len() as u64);
+        assert_eq!(tag.format(), BlobFormat::HashSeq);
+
+        // chunking the stream gives the same result as chunking all data at once
+        let blob = ChunkedBlob::load_db(&db, tag.hash()).await?;
+        let expected = Chunker::new(small_config())?
+            .chunks(&data)
+            .map(|chunk| (blake3::hash(chunk).into(), chunk.len() as u64))
+            .collect::<ChunkedBlob>();
+        assert_eq!(blob, expected);
+        assert_eq!(blob.size(), data.len() as u64);
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("out");
+        crate::export::export(
+            &db,
+            *tag.hash(),
+            path.clone(),
+            ExportFormat::Chunked,
+            ExportMode::Copy,
+            IgnoreProgressSender::default(),
+        )
+        .await?;
+        assert_eq!(std::fs::read(path)?, data);
+        db.shutdown().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn load_rejects_other_hash_seqs() -> testresult::TestResult {
+        let db = mem::Store::new();
+        let data = db
+            .import_bytes(test_data(1024, 3).into(), BlobFormat::Raw)
+            .await?;
+
+        // a collection has a metadata blob as first child, but with a different header
+        let mut collection = Collection::default();
+        collection.push("data".to_string(), *data.hash());
+        let root = collection.store(&db).await?;
+        let err = ChunkedBlob::load_db(&db, root.hash()).await.unwrap_err();
+        assert!(err.to_string().contains("not a chunked blob"), "{err}");
+
+        // a plain hash seq whose first child is not metadata at all
+        let links = [*data.hash(), *data.hash()]
+            .into_iter()
+            .collect::<HashSeq>();
+        let root = db.import_bytes(links.into(), BlobFormat::HashSeq).await?;
+        let err = ChunkedBlob::load_db(&db, root.hash()).await.unwrap_err();
+        assert!(err.to_string().contains("not a chunked blob"), "{err}");
+
+        let dir = tempfile::tempdir()?;
+        let res = crate::export::export(
+            &db,
+            *root.hash(),
+            dir.path().join("out"),
+            ExportFormat::Chunked,
+            ExportMode::Copy,
+            IgnoreProgressSender::default(),
+        )
+        .await;
+        assert!(res.is_err());
+        db.shutdown().await;
+        Ok(())
+    }
+}
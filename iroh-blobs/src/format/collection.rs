@@ -1,5 +1,13 @@
 //! The collection type used by iroh
-use std::{collections::BTreeMap, future::Future};
+//!
+//! A collection is a sequence of named blobs. Optionally, it can carry file
+//! system metadata for its entries, such as permission bits, modification times
+//! and entry types, which allows to represent symlinks and empty directories
+//! that have no blob of their own. See [EntryMeta].
+//!
+//! Collections without metadata use the original `CollectionV0.` format, so
+//! they have the same hash as before and can be read by older versions.
+use std::{collections::BTreeMap, future::Future, io, path::Path, time::SystemTime};
 
 use anyhow::Context;
 use bao_tree::blake3;
@@ -22,6 +30,97 @@ use crate::{
 pub struct Collection {
     /// Links to the blobs in this collection
     blobs: Vec<(String, Hash)>,
+    /// File system metadata, by name
+    ///
+    /// This can contain entries that are not in `blobs`, e.g. directories and
+    /// symlinks.
+    meta: BTreeMap<String, EntryMeta>,
+}
+
+/// The type of an entry in a collection.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum EntryKind {
+    /// A regular file, the content is the blob with the same name.
+    File,
+    /// A directory.
+    ///
+    /// Directories only need to be listed explicitly if they are empty, or to
+    /// preserve their metadata.
+    Dir,
+    /// A symbolic link to the given target.
+    Symlink {
+        /// The target of the link, as stored in the file system.
+        target: String,
+    },
+}
+
+/// File system metadata of an entry in a collection.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EntryMeta {
+    /// The type of the entry.
+    pub kind: EntryKind,
+    /// POSIX permission bits, e.g. `0o755`.
+    pub mode: Option<u32>,
+    /// Modification time.
+    ///
+    /// Times before the unix epoch can not be represented, so
+    /// [`Collection::set_meta`] drops them.
+    pub mtime: Option<SystemTime>,
+    /// Size hint, for files this is the size of the blob.
+    pub size: Option<u64>,
+}
+
+impl EntryMeta {
+    /// Create metadata for an entry of the given kind, without any attributes.
+    pub fn new(kind: EntryKind) -> Self {
+        Self {
+            kind,
+            mode: None,
+            mtime: None,
+            size: None,
+        }
+    }
+
+    /// Read the metadata of a path in the local file system.
+    ///
+    /// Symlinks are not followed.
+    pub fn from_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let metadata = std::fs::symlink_metadata(path)?;
+        let file_type = metadata.file_type();
+        let kind = if file_type.is_symlink() {
+            let target = std::fs::read_link(path)?;
+            let target = target.into_os_string().into_string().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "symlink target is not unicode")
+            })?;
+            EntryKind::Symlink { target }
+        } else if file_type.is_dir() {
+            EntryKind::Dir
+        } else {
+            EntryKind::File
+        };
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            Some(metadata.permissions().mode() & 0o7777)
+        };
+        #[cfg(not(unix))]
+        let mode = None;
+        let mtime = metadata
+            .modified()
+            .ok()
+            .filter(|time| *time >= SystemTime::UNIX_EPOCH);
+        let size = match kind {
+            EntryKind::File => Some(metadata.len()),
+            _ => None,
+        };
+        Ok(Self {
+            kind,
+            mode,
+            mtime,
+            size,
+        })
+    }
 }
 
 impl std::ops::Index<usize> for Collection {
@@ -79,23 +178,56 @@ struct CollectionMeta {
     names: Vec<String>,
 }
 
+/// Metadata for a collection with file system metadata
+///
+/// This is the wire format for the metadata blob of collections that have
+/// file system metadata.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+struct CollectionMetaV1 {
+    header: [u8; 13], // Must contain "CollectionV1."
+    names: Vec<String>,
+    entries: Vec<(String, EntryMeta)>,
+}
+
+/// Parse the metadata blob of a collection, in any supported version.
+fn parse_meta(bytes: &[u8]) -> anyhow::Result<(Vec<String>, BTreeMap<String, EntryMeta>)> {
+    let header = bytes.get(..13).context("metadata too short")?;
+    if header == Collection::HEADER {
+        let meta: CollectionMeta = postcard::from_bytes(bytes)?;
+        Ok((meta.names, BTreeMap::new()))
+    } else if header == Collection::HEADER_V1 {
+        let meta: CollectionMetaV1 = postcard::from_bytes(bytes)?;
+        Ok((meta.names, meta.entries.into_iter().collect()))
+    } else {
+        anyhow::bail!(
+            "expected header {:?} or {:?}, got {:?}",
+            Collection::HEADER,
+            Collection::HEADER_V1,
+            header
+        );
+    }
+}
+
 impl Collection {
     /// The header for the collection format.
     ///
     /// This is the start of the metadata blob.
     pub const HEADER: &'static [u8; 13] = b"CollectionV0.";
 
+    /// The header for the collection format with file system metadata.
+    ///
+    /// This is the start of the metadata blob if the collection has metadata.
+    pub const HEADER_V1: &'static [u8; 13] = b"CollectionV1.";
+
     /// Convert the collection to an iterator of blobs, with the last being the
     /// root blob.
     ///
     /// To persist the collection, write all the blobs to storage, and use the
     /// hash of the last blob as the collection hash.
     pub fn to_blobs(&self) -> impl DoubleEndedIterator<Item = Bytes> {
-        let meta = CollectionMeta {
-            header: *Self::HEADER,
-            names: self.names(),
-        };
-        let meta_bytes = postcard::to_stdvec(&meta).unwrap();
+        let meta_bytes = self
+            .meta_bytes()
+            .expect("set_meta drops times that can not be serialized");
         let meta_bytes_hash = blake3::hash(&meta_bytes).into();
         let links = std::iter::once(meta_bytes_hash)
             .chain(self.links())
@@ -124,15 +256,8 @@ impl Collection {
             let mut children = links.clone();
             let meta_link = children.pop_front().context("meta link not found")?;
             let curr = at_meta.next(meta_link);
-            let (curr, names) = curr.concatenate_into_vec().await?;
-            let names = postcard::from_bytes::<CollectionMeta>(&names)?;
-            anyhow::ensure!(
-                names.header == *Self::HEADER,
-                "expected header {:?}, got {:?}",
-                Self::HEADER,
-                names.header
-            );
-            let collection = Collection::from_parts(children, names);
+            let (curr, meta) = curr.concatenate_into_vec().await?;
+            let collection = Collection::from_parts(children, &meta)?;
             (curr.next(), collection)
         };
         Ok((next, links, collection))
@@ -172,12 +297,7 @@ impl Collection {
         let hs = HashSeq::try_from(hs)?;
         let meta_hash = hs.iter().next().context("empty hash seq")?;
         let meta = store.load(meta_hash).await?;
-        let meta: CollectionMeta = postcard::from_bytes(&meta)?;
-        anyhow::ensure!(
-            meta.names.len() + 1 == hs.len(),
-            "names and links length mismatch"
-        );
-        Ok(Self::from_parts(hs.into_iter().skip(1), meta))
+        Self::from_parts(hs.into_iter().skip(1), &meta)
     }
 
     /// Load a collection from a store given a root hash
@@ -196,12 +316,7 @@ impl Collection {
         let meta_entry = db.get(&meta_hash).await?.context("meta not found")?;
         anyhow::ensure!(links_entry.is_complete(), "links not complete");
         let meta_bytes = meta_entry.data_reader().await?.read_to_end().await?;
-        let meta: CollectionMeta = postcard::from_bytes(&meta_bytes)?;
-        anyhow::ensure!(
-            meta.names.len() == links.len(),
-            "names and links length mismatch"
-        );
-        Ok(Self::from_parts(links, meta))
+        Self::from_parts(links, &meta_bytes)
     }
 
     /// Store a collection in a store. returns the root hash of the collection
@@ -210,8 +325,8 @@ impl Collection {
     where
         D: crate::store::Store,
     {
-        let (links, meta) = self.into_parts();
-        let meta_bytes = postcard::to_stdvec(&meta)?;
+        let meta_bytes = self.meta_bytes()?;
+        let links = self.links().collect::<Vec<_>>();
         let meta_tag = db.import_bytes(meta_bytes.into(), BlobFormat::Raw).await?;
         let links_bytes = std::iter::once(*meta_tag.hash())
             .chain(links)
@@ -222,24 +337,39 @@ impl Collection {
         Ok(links_tag)
     }
 
-    /// Split a collection into a sequence of links and metadata
-    fn into_parts(self) -> (Vec<Hash>, CollectionMeta) {
-        let mut names = Vec::with_capacity(self.blobs.len());
-        let mut links = Vec::with_capacity(self.blobs.len());
-        for (name, hash) in self.blobs {
-            names.push(name);
-            links.push(hash);
+    /// Serialize the metadata blob, in the oldest format that can represent
+    /// this collection.
+    fn meta_bytes(&self) -> postcard::Result<Vec<u8>> {
+        let names = self.names();
+        if self.meta.is_empty() {
+            postcard::to_stdvec(&CollectionMeta {
+                header: *Self::HEADER,
+                names,
+            })
+        } else {
+            postcard::to_stdvec(&CollectionMetaV1 {
+                header: *Self::HEADER_V1,
+                names,
+                entries: self
+                    .meta
+                    .iter()
+                    .map(|(name, meta)| (name.clone(), meta.clone()))
+                    .collect(),
+            })
         }
-        let meta = CollectionMeta {
-            header: *Self::HEADER,
-            names,
-        };
-        (links, meta)
     }
 
-    /// Create a new collection from a list of hashes and metadata
-    fn from_parts(links: impl IntoIterator<Item = Hash>, meta: CollectionMeta) -> Self {
-        meta.names.into_iter().zip(links).collect()
+    /// Create a new collection from a list of hashes and the metadata blob
+    fn from_parts(links: impl IntoIterator<Item = Hash>, meta: &[u8]) -> anyhow::Result<Self> {
+        let (names, meta) = parse_meta(meta)?;
+        let links = links.into_iter().collect::<Vec<_>>();
+        anyhow::ensure!(
+            names.len() == links.len(),
+            "names and links length mismatch"
+        );
+        let mut res: Self = names.into_iter().zip(links).collect();
+        res.meta = meta;
+        Ok(res)
     }
 
     /// Get the links to the blobs in this collection
@@ -271,6 +401,26 @@ impl Collection {
     pub fn push(&mut self, name: String, hash: Hash) {
         self.blobs.push((name, hash));
     }
+
+    /// Set the file system metadata for an entry.
+    ///
+    /// The name does not have to refer to a blob in this collection, e.g. for
+    /// empty directories or symlinks. A modification time before the unix
+    /// epoch can not be represented and is dropped.
+    pub fn set_meta(&mut self, name: String, mut meta: EntryMeta) {
+        meta.mtime = meta.mtime.filter(|time| *time >= SystemTime::UNIX_EPOCH);
+        self.meta.insert(name, meta);
+    }
+
+    /// Get the file system metadata for an entry, if any.
+    pub fn meta(&self, name: &str) -> Option<&EntryMeta> {
+        self.meta.get(name)
+    }
+
+    /// Iterate over all entries with file system metadata, sorted by name.
+    pub fn iter_meta(&self) -> impl Iterator<Item = (&String, &EntryMeta)> {
+        self.meta.iter()
+    }
 }
 
 #[cfg(test)]
@@ -330,6 +480,84 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn collection_with_meta_store_load() -> testresult::TestResult {
+        let plain = (0..2)
+            .map(|i| (format!("dir/blob{}", i), Hash::new([i as u8])))
+            .collect::<Collection>();
+        let mut collection = plain.clone();
+        collection.set_meta(
+            "dir/blob0".into(),
+            EntryMeta {
+                kind: EntryKind::File,
+                mode: Some(0o755),
+                mtime: Some(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1234)),
+                size: Some(1),
+            },
+        );
+        collection.set_meta("empty".into(), EntryMeta::new(EntryKind::Dir));
+        collection.set_meta(
+            "link".into(),
+            EntryMeta::new(EntryKind::Symlink {
+                target: "dir/blob1".into(),
+            }),
+        );
+        // times before the unix epoch are dropped instead of failing to serialize
+        let mut before_epoch = EntryMeta::new(EntryKind::File);
+        before_epoch.mtime = Some(SystemTime::UNIX_EPOCH - std::time::Duration::from_secs(1));
+        let mut collection2 = collection.clone();
+        collection2.set_meta("dir/blob1".into(), before_epoch);
+        assert_eq!(collection2.meta("dir/blob1").unwrap().mtime, None);
+        assert_eq!(collection2.to_blobs().count(), 2);
+        // collections without metadata keep the old format
+        let plain_meta = plain.to_blobs().next().unwrap();
+        assert_eq!(&plain_meta[..13], Collection::HEADER);
+        let meta = collection.to_blobs().next().unwrap();
+        assert_eq!(&meta[..13], Collection::HEADER_V1);
+
+        for collection in [plain, collection] {
+            let mut root = None;
+            let store = collection
+                .to_blobs()
+                .map(|data| {
+                    let hash = Hash::new(&data);
+                    root = Some(hash);
+                    (hash, data)
+                })
+                .collect::<TestStore>();
+            let collection2 = Collection::load(root.unwrap(), &store).await?;
+            assert_eq!(collection, collection2);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn entry_meta_from_path() -> testresult::TestResult {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"hello")?;
+        let meta = EntryMeta::from_path(&file)?;
+        assert_eq!(meta.kind, EntryKind::File);
+        assert_eq!(meta.size, Some(5));
+        assert!(meta.mtime.is_some());
+        assert_eq!(EntryMeta::from_path(dir.path())?.kind, EntryKind::Dir);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o751))?;
+            assert_eq!(EntryMeta::from_path(&file)?.mode, Some(0o751));
+            let link = dir.path().join("link");
+            std::os::unix::fs::symlink("file", &link)?;
+            assert_eq!(
+                EntryMeta::from_path(&link)?.kind,
+                EntryKind::Symlink {
+                    target: "file".into()
+                }
+            );
+        }
+        Ok(())
+    }
+
     /// An implementation of a [SimpleStore] for testing
     struct TestStore(BTreeMap<Hash, Bytes>);
 
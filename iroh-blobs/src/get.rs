@@ -60,8 +60,11 @@ pub mod fsm {
     use std::{io, result};
 
     use crate::{
-        protocol::{GetRequest, NonEmptyRequestRangeSpecIter, Request, MAX_MESSAGE_SIZE},
+        protocol::{
+            GetManyRequest, GetRequest, NonEmptyRequestRangeSpecIter, Request, MAX_MESSAGE_SIZE,
+        },
         store::BaoBatchWriter,
+        util::reblock::reblock_batch,
     };
 
     use super::*;
@@ -90,6 +93,34 @@ pub mod fsm {
         AtInitial::new(connection, request)
     }
 
+    /// The entry point of the get response machine for a request for multiple blobs
+    ///
+    /// The response to such a request never contains a root, so the machine will
+    /// only ever go into the [`AtStartChild`] state, where the child offset is
+    /// the index of the hash in [`GetManyRequest::hashes`].
+    pub fn start_get_many(connection: Connection, request: GetManyRequest) -> AtInitial {
+        AtInitial {
+            connection,
+            request: request.into(),
+        }
+    }
+
+    /// A request that is answered with data, i.e. one the get response machine can handle
+    #[derive(Debug, Clone, From)]
+    enum AnyGetRequest {
+        Get(GetRequest),
+        GetMany(GetManyRequest),
+    }
+
+    impl From<AnyGetRequest> for Request {
+        fn from(request: AnyGetRequest) -> Self {
+            match request {
+                AnyGetRequest::Get(request) => request.into(),
+                AnyGetRequest::GetMany(request) => request.into(),
+            }
+        }
+    }
+
     /// Owned iterator for the ranges in a request
     ///
     /// We need an owned iterator for a fsm style API, otherwise we would have
@@ -127,7 +158,7 @@ pub mod fsm {
     #[derive(Debug)]
     pub struct AtInitial {
         connection: Connection,
-        request: GetRequest,
+        request: AnyGetRequest,
     }
 
     impl AtInitial {
@@ -138,7 +169,7 @@ pub mod fsm {
         pub fn new(connection: Connection, request: GetRequest) -> Self {
             Self {
                 connection,
-                request,
+                request: request.into(),
             }
         }
 
@@ -163,7 +194,7 @@ pub mod fsm {
         start: Instant,
         reader: WrappedRecvStream,
         writer: TrackingWriter<SendStream>,
-        request: GetRequest,
+        request: AnyGetRequest,
     }
 
     /// Possible next states after the handshake has been sent
@@ -233,16 +264,13 @@ pub mod fsm {
                 start,
                 reader,
                 mut writer,
-                mut request,
+                request,
             } = self;
             // 1. Send Request
             {
                 debug!("sending request");
-                let wrapped = Request::Get(request);
-                let request_bytes =
-                    postcard::to_stdvec(&wrapped).map_err(ConnectedNextError::PostcardSer)?;
-                let Request::Get(x) = wrapped;
-                request = x;
+                let request_bytes = postcard::to_stdvec(&Request::from(request.clone()))
+                    .map_err(ConnectedNextError::PostcardSer)?;
 
                 if request_bytes.len() > MAX_MESSAGE_SIZE {
                     return Err(ConnectedNextError::RequestTooBig);
@@ -259,8 +287,13 @@ pub mod fsm {
             let (mut writer, bytes_written) = writer.into_parts();
             writer.finish().await?;
 
-            let hash = request.hash;
-            let ranges_iter = RangesIter::new(request.ranges);
+            // a request for many blobs is handled like a request for the children
+            // of a hash sequence, but there is no root
+            let (root, ranges) = match request {
+                AnyGetRequest::Get(request) => (Some(request.hash), request.ranges),
+                AnyGetRequest::GetMany(request) => (None, request.hash_seq_ranges()),
+            };
+            let ranges_iter = RangesIter::new(ranges);
             // this is in a box so we don't have to memcpy it on every state transition
             let mut misc = Box::new(Misc {
                 start,
@@ -268,25 +301,22 @@ pub mod fsm {
                 ranges_iter,
             });
             Ok(match misc.ranges_iter.next() {
-                Some((offset, ranges)) => {
-                    if offset == 0 {
-                        AtStartRoot {
-                            reader,
-                            ranges,
-                            misc,
-                            hash,
-                        }
-                        .into()
-                    } else {
-                        AtStartChild {
-                            reader,
-                            ranges,
-                            misc,
-                            child_offset: offset - 1,
-                        }
-                        .into()
+                Some((offset, ranges)) => match root {
+                    Some(hash) if offset == 0 => AtStartRoot {
+                        reader,
+                        ranges,
+                        misc,
+                        hash,
                     }
-                }
+                    .into(),
+                    _ => AtStartChild {
+                        reader,
+                        ranges,
+                        misc,
+                        child_offset: offset - 1,
+                    }
+                    .into(),
+                },
                 None => AtClosing::new(misc, reader, true).into(),
             })
         }
@@ -316,6 +346,8 @@ pub mod fsm {
         /// This must be used to determine the hash needed to call next.
         /// If this is larger than the number of children in the collection,
         /// you can call finish to stop reading the response.
+        ///
+        /// For a [`GetManyRequest`], this is the index of the hash in the request.
         pub fn child_offset(&self) -> u64 {
             self.child_offset
         }
@@ -702,32 +734,11 @@ pub mod fsm {
         where
             B: BaoBatchWriter,
         {
-            let mut writer = writer;
-            let mut buf = Vec::new();
-            let mut content = self;
-            let size = content.tree().size();
-            loop {
-                match content.next().await {
-                    BlobContentNext::More((next, item)) => {
-                        let item = item?;
-                        match &item {
-                            BaoContentItem::Parent(_) => {
-                                buf.push(item);
-                            }
-                            BaoContentItem::Leaf(_) => {
-                                buf.push(item);
-                                let batch = std::mem::take(&mut buf);
-                                writer.write_batch(size, batch).await?;
-                            }
-                        }
-                        content = next;
-                    }
-                    BlobContentNext::Done(end) => {
-                        assert!(buf.is_empty());
-                        return Ok(end);
-                    }
-                }
-            }
+            let stream = decode_all_batch(self.stream, writer).await?;
+            Ok(AtEndBlob {
+                stream,
+                misc: self.misc,
+            })
         }
 
         /// Write the entire blob to a slice writer and to an optional outboard.
@@ -796,6 +807,54 @@ pub mod fsm {
         }
     }
 
+    /// Decode a bao response for a single blob and write it to a batch writer.
+    ///
+    /// This is what [`AtBlobContent::write_all_batch`] does, but for any reader,
+    /// so that data that did not arrive over a connection can be ingested the
+    /// same way. Returns the reader, positioned after the blob.
+    ///
+    /// If the writer uses a different chunk group size than the stream, the
+    /// batches are converted on the fly.
+    pub(crate) async fn decode_all_batch<R, B>(
+        stream: ResponseDecoder<R>,
+        mut writer: B,
+    ) -> result::Result<R, DecodeError>
+    where
+        R: AsyncStreamReader,
+        B: BaoBatchWriter,
+    {
+        let mut buf = Vec::new();
+        let mut stream = stream;
+        let size = stream.tree().size();
+        let from = stream.tree().block_size();
+        let to = writer.block_size();
+        loop {
+            match stream.next().await {
+                ResponseDecoderNext::More((next, item)) => {
+                    let item = item.map_err(DecodeError::from)?;
+                    match &item {
+                        BaoContentItem::Parent(_) => {
+                            buf.push(item);
+                        }
+                        BaoContentItem::Leaf(_) => {
+                            buf.push(item);
+                            let mut batch = std::mem::take(&mut buf);
+                            if from != to {
+                                batch = reblock_batch(size, batch, from, to);
+                            }
+                            writer.write_batch(size, batch).await?;
+                        }
+                    }
+                    stream = next;
+                }
+                ResponseDecoderNext::Done(reader) => {
+                    assert!(buf.is_empty());
+                    return Ok(reader);
+                }
+            }
+        }
+    }
+
     /// State after we have read all the content for a blob
     #[derive(Debug)]
     pub struct AtEndBlob {
@@ -1,13 +1,18 @@
 //! Functions that use the iroh-blobs protocol in conjunction with a bao store.
 
+use std::collections::VecDeque;
 use std::future::Future;
 use std::io;
 use std::num::NonZeroU64;
+use std::ops::Range;
+use std::time::Instant;
 
-use futures_lite::StreamExt;
+use futures_buffered::FuturesUnordered;
+use futures_lite::{Stream, StreamExt};
 use iroh_base::hash::Hash;
 use iroh_base::rpc::RpcError;
-use iroh_net::endpoint::Connection;
+use iroh_net::endpoint::{get_remote_node_id, Connection};
+use iroh_net::NodeId;
 use serde::{Deserialize, Serialize};
 
 use crate::hashseq::parse_hash_seq;
@@ -22,17 +27,19 @@ use crate::{
         error::GetError,
         fsm::{AtBlobHeader, AtEndBlob, ConnectedNext, EndBlobNext},
         progress::TransferState,
+        request::get_available_ranges,
         Stats,
     },
-    protocol::{GetRequest, RangeSpecSeq},
+    protocol::{GetManyRequest, GetRequest, HaveRequest, RangeSpecSeq},
     store::{MapEntry, MapEntryMut, MapMut, Store as BaoStore},
     util::progress::{IdGenerator, ProgressSender},
     BlobFormat, HashAndFormat,
 };
 use anyhow::anyhow;
 use bao_tree::{ChunkNum, ChunkRanges};
-use iroh_io::AsyncSliceReader;
-use tracing::trace;
+use range_collections::range_set::RangeSetRange;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, trace};
 
 /// Get a blob or collection into a store.
 ///
@@ -59,6 +66,90 @@ pub async fn get_to_db<
     }
 }
 
+/// Get multiple unrelated blobs into a store, using a single request.
+///
+/// This considers data that is already in the store, and will only request
+/// the remaining data. Blobs are reported with [`BlobId::Child`] ids, using
+/// their index in `hashes` plus one as the offset.
+///
+/// Progress is reported as [`DownloadProgress`] through a [`ProgressSender`]. Note that the
+/// [`DownloadProgress::AllDone`] event is not emitted from here, but left to an upper layer to send,
+/// if desired.
+pub async fn get_many_to_db<
+    D: BaoStore,
+    C: FnOnce() -> F,
+    F: Future<Output = anyhow::Result<Connection>>,
+>(
+    db: &D,
+    get_conn: C,
+    hashes: &[Hash],
+    sender: impl ProgressSender<Msg = DownloadProgress> + IdGenerator,
+) -> Result<Stats, GetError> {
+    use tracing::info as log;
+    let missing_info = blob_infos(db, hashes).await?;
+    // send info about what we have
+    for (i, info) in missing_info.iter().enumerate() {
+        if let Some(size) = info.size() {
+            sender
+                .send(DownloadProgress::FoundLocal {
+                    child: BlobId::from_offset((i as u64) + 1),
+                    hash: hashes[i],
+                    size,
+                    valid_ranges: RangeSpec::new(info.valid_ranges()),
+                })
+                .await?;
+        }
+    }
+    if missing_info
+        .iter()
+        .all(|x| matches!(x, BlobInfo::Complete { .. }))
+    {
+        log!("nothing to do");
+        return Ok(Stats::default());
+    }
+    let ranges = RangeSpecSeq::from_ranges(missing_info.iter().map(|x| x.missing_ranges()));
+    log!("requesting chunks {:?}", ranges);
+    let request = GetManyRequest::new(hashes.to_vec(), ranges);
+    let conn = get_conn().await.map_err(GetError::Io)?;
+    let request = get::fsm::start_get_many(conn, request);
+    // create a new bidi stream
+    let connected = request.next().await?;
+    // a get many request has no root, so this must be StartChild
+    let ConnectedNext::StartChild(start) = connected.next().await? else {
+        return Err(GetError::NoncompliantNode(anyhow!("expected StartChild")));
+    };
+    let mut next = EndBlobNext::MoreChildren(start);
+    // read all the blobs
+    let finishing = loop {
+        let start = match next {
+            EndBlobNext::MoreChildren(start) => start,
+            EndBlobNext::Closing(finish) => break finish,
+        };
+        let child_offset = usize::try_from(start.child_offset())
+            .map_err(|_| GetError::NoncompliantNode(anyhow!("child offset too large")))?;
+        let (hash, info) = match (hashes.get(child_offset), missing_info.get(child_offset)) {
+            (Some(hash), Some(info)) => (*hash, info),
+            _ => break start.finish(),
+        };
+        let header = start.next(hash);
+        let end_blob = match info {
+            BlobInfo::Missing => get_blob_inner(db, header, sender.clone()).await?,
+            BlobInfo::Partial { entry, .. } => {
+                get_blob_inner_partial(db, header, entry.clone(), sender.clone()).await?
+            }
+            BlobInfo::Complete { .. } => {
+                return Err(GetError::NoncompliantNode(anyhow!(
+                    "got data we have not requested"
+                )));
+            }
+        };
+        next = end_blob.next();
+    };
+    // this closes the bidi stream. Do something with the stats?
+    let stats = finishing.next().await?;
+    Ok(stats)
+}
+
 /// Get a blob that was requested completely.
 ///
 /// We need to create our own files and handle the case where an outboard
@@ -141,23 +232,11 @@ async fn get_blob<D: BaoStore, C: FnOnce() -> F, F: Future<Output = anyhow::Resu
 }
 
 /// Given a partial entry, get the valid ranges.
+///
+/// See [`MapEntry::available_ranges`].
 pub async fn valid_ranges<D: MapMut>(entry: &D::EntryMut) -> anyhow::Result<ChunkRanges> {
-    use tracing::trace as log;
-    // compute the valid range from just looking at the data file
-    let mut data_reader = entry.data_reader().await?;
-    let data_size = data_reader.size().await?;
-    let valid_from_data = ChunkRanges::from(..ChunkNum::full_chunks(data_size));
-    // compute the valid range from just looking at the outboard file
-    let mut outboard = entry.outboard().await?;
-    let all = ChunkRanges::all();
-    let mut stream = bao_tree::io::fsm::valid_outboard_ranges(&mut outboard, &all);
-    let mut valid_from_outboard = ChunkRanges::empty();
-    while let Some(range) = stream.next().await {
-        valid_from_outboard |= ChunkRanges::from(range?);
-    }
-    let valid: ChunkRanges = valid_from_data.intersection(&valid_from_outboard);
-    log!("valid_from_data: {:?}", valid_from_data);
-    log!("valid_from_outboard: {:?}", valid_from_data);
+    let valid = entry.available_ranges().await?;
+    trace!("valid ranges: {:?}", valid);
     Ok(valid)
 }
 
@@ -268,6 +347,450 @@ async fn get_blob_inner_partial<D: BaoStore>(
     Ok(at_end)
 }
 
+/// Number of pieces that are requested concurrently from a single node in a striped download.
+const STRIPE_PIPELINE_DEPTH: usize = 2;
+
+/// Get a single blob into a store, striping the missing ranges across several connections.
+///
+/// The missing ranges of the blob are split into pieces of at most `stripe_size` chunks. Each
+/// connection requests the next unassigned piece whenever it has finished one, so faster nodes
+/// get more pieces. Once no unassigned pieces are left, connections with free capacity also
+/// request the pieces that are still in flight on other connections, and whichever finishes
+/// first wins. This way a single slow node can not hold up the end of the download.
+///
+/// The download starts with `conn`. Connections yielded by `more_conns` join the download
+/// as they arrive. Each node is first asked which of the missing ranges it has, using a
+/// [`HaveRequest`], and is only assigned pieces that it has completely. Nodes that do not
+/// understand the request are assumed to have the entire blob.
+///
+/// All pieces are verified and written into the same partial entry, which is marked as
+/// complete once all pieces are done. A connection that fails is no longer used for this
+/// download, and its piece is requested from the remaining connections. Once no connection
+/// is left, the download fails with the error of the node if only a single node failed, or
+/// with [`GetError::AllNodesFailed`] containing the error of each node.
+///
+/// Progress is reported as [`DownloadProgress`] through a [`ProgressSender`]. Since pieces are
+/// written out of order, progress is reported once per finished piece, and the offset of
+/// [`DownloadProgress::Progress`] is the total number of bytes available locally. Note that
+/// the [`DownloadProgress::AllDone`] event is not emitted from here, but left to an upper
+/// layer to send, if desired.
+pub async fn get_blob_striped<D: BaoStore>(
+    db: &D,
+    conn: Connection,
+    mut more_conns: impl Stream<Item = Connection> + Unpin,
+    hash: &Hash,
+    stripe_size: NonZeroU64,
+    progress: impl ProgressSender<Msg = DownloadProgress> + IdGenerator,
+) -> Result<Stats, GetError> {
+    let start = Instant::now();
+    let node_id = get_remote_node_id(&conn).map_err(GetError::NoncompliantNode)?;
+    let (entry, mut stats) = match db.get_mut(hash).await? {
+        Some(entry) if entry.is_complete() => {
+            tracing::info!("already got entire blob");
+            progress
+                .send(DownloadProgress::FoundLocal {
+                    child: BlobId::Root,
+                    hash: *hash,
+                    size: entry.size(),
+                    valid_ranges: RangeSpec::all(),
+                })
+                .await?;
+            return Ok(Stats::default());
+        }
+        Some(entry) => {
+            trace!("got partial data for {}", hash);
+            let valid_ranges = valid_ranges::<D>(&entry)
+                .await
+                .unwrap_or_else(|_| ChunkRanges::empty());
+            progress
+                .send(DownloadProgress::FoundLocal {
+                    child: BlobId::Root,
+                    hash: *hash,
+                    size: entry.size(),
+                    valid_ranges: RangeSpec::new(&valid_ranges),
+                })
+                .await?;
+            match entry.size() {
+                BaoBlobSize::Verified(_) => (entry, Stats::default()),
+                BaoBlobSize::Unverified(_) => get_last_chunk(db, conn.clone(), hash).await?,
+            }
+        }
+        None => get_last_chunk(db, conn.clone(), hash).await?,
+    };
+    // we need to know the size to split the blob into pieces
+    let size = entry.size().value();
+    let valid_ranges = valid_ranges::<D>(&entry)
+        .await
+        .unwrap_or_else(|_| ChunkRanges::empty());
+    let missing = ChunkRanges::from(..ChunkNum::chunks(size)).difference(&valid_ranges);
+    let mut pieces = split_into_pieces(&missing, stripe_size.get());
+    let missing_bytes = bytes_in_ranges(&missing, size);
+    let id = progress.new_id();
+    progress
+        .send(DownloadProgress::Found {
+            id,
+            hash: *hash,
+            size,
+            child: BlobId::Root,
+        })
+        .await?;
+    // a piece can be downloaded from more than one node at the end, so progress is only
+    // counted once the piece is done
+    let mut available = size - missing_bytes;
+
+    let probe = |node_idx: usize, conn: Connection| {
+        let request = HaveRequest::new(*hash, RangeSpecSeq::from_ranges([missing.clone()]));
+        async move { (node_idx, get_available_ranges(&conn, request).await) }
+    };
+    let mut nodes = vec![StripeNode::new(node_id, conn.clone())];
+    let mut probes = FuturesUnordered::new();
+    probes.push(probe(0, conn));
+    let mut queue = (0..pieces.len()).collect::<VecDeque<_>>();
+    let mut in_flight = FuturesUnordered::new();
+    let mut more_conns_done = false;
+    let mut errors = Vec::new();
+    loop {
+        // hand out pieces to all nodes that have free capacity
+        for (node_idx, node) in nodes.iter_mut().enumerate() {
+            let Some(available) = &node.available else {
+                // still waiting for the node to tell us what it has
+                continue;
+            };
+            while !node.failed && node.in_flight < STRIPE_PIPELINE_DEPTH {
+                let has_piece = |piece: &Piece| piece.chunks.is_subset(available);
+                let piece_idx = match queue.iter().position(|idx| has_piece(&pieces[*idx])) {
+                    Some(pos) => queue.remove(pos).expect("position is valid"),
+                    // no unassigned pieces left, so help with a piece in flight on another node
+                    None => match pieces.iter().position(|piece| {
+                        !piece.done
+                            && piece.attempts.len() == 1
+                            && piece.attempts[0].0 != node_idx
+                            && has_piece(piece)
+                    }) {
+                        Some(piece_idx) => piece_idx,
+                        None => break,
+                    },
+                };
+                let piece = &mut pieces[piece_idx];
+                let cancel = CancellationToken::new();
+                piece.attempts.push((node_idx, cancel.clone()));
+                node.in_flight += 1;
+                trace!(node_idx, piece_idx, "requesting piece");
+                let fut = get_piece(
+                    db,
+                    entry.clone(),
+                    node.conn.clone(),
+                    hash,
+                    size,
+                    piece.chunks.clone(),
+                );
+                in_flight.push(async move {
+                    let res = tokio::select! {
+                        _ = cancel.cancelled() => None,
+                        res = fut => Some(res),
+                    };
+                    (node_idx, piece_idx, res)
+                });
+            }
+        }
+        if pieces.iter().all(|piece| piece.done) {
+            break;
+        }
+        if in_flight.is_empty() && probes.is_empty() && more_conns_done {
+            // all nodes have failed, or none of them has the remaining pieces, and no more
+            // nodes will join
+            return Err(match errors.len() {
+                0 => GetError::Io(anyhow!("no connection left to download from")),
+                1 => errors.pop().map(|(_, err)| err).expect("one error"),
+                _ => GetError::AllNodesFailed(errors),
+            });
+        }
+        tokio::select! {
+            Some((node_idx, res)) = probes.next() => {
+                let available = match res {
+                    Ok(ranges) => ranges
+                        .iter()
+                        .next()
+                        .map(RangeSpec::to_chunk_ranges)
+                        .unwrap_or_else(ChunkRanges::empty),
+                    Err(err) => {
+                        // the node might not support have requests, so just try everything
+                        debug!(node_idx, ?err, "have request failed, assuming complete");
+                        ChunkRanges::all()
+                    }
+                };
+                trace!(node_idx, ?available, "node has ranges");
+                nodes[node_idx].available = Some(available);
+            }
+            Some((node_idx, piece_idx, res)) = in_flight.next() => {
+                let node: &mut StripeNode = &mut nodes[node_idx];
+                let piece: &mut Piece = &mut pieces[piece_idx];
+                node.in_flight -= 1;
+                piece.attempts.retain(|(idx, _)| *idx != node_idx);
+                match res {
+                    // another node was faster
+                    None => {}
+                    Some(Ok(piece_stats)) => {
+                        trace!(node_idx, piece_idx, "piece done");
+                        piece.done = true;
+                        for (_, cancel) in piece.attempts.drain(..) {
+                            cancel.cancel();
+                        }
+                        stats.bytes_written += piece_stats.bytes_written;
+                        stats.bytes_read += piece_stats.bytes_read;
+                        available += bytes_in_ranges(&piece.chunks, size);
+                        progress
+                            .send(DownloadProgress::Progress { id, offset: available })
+                            .await?;
+                    }
+                    Some(Err(err)) => {
+                        debug!(node_idx, piece_idx, ?err, "piece failed, dropping node");
+                        node.failed = true;
+                        if !piece.done && piece.attempts.is_empty() {
+                            queue.push_front(piece_idx);
+                        }
+                        errors.push((node.node_id, err));
+                    }
+                }
+            }
+            conn = more_conns.next(), if !more_conns_done => match conn {
+                Some(conn) => match get_remote_node_id(&conn) {
+                    Ok(node_id) => {
+                        let node_idx = nodes.len();
+                        trace!(node_idx, "node joined");
+                        nodes.push(StripeNode::new(node_id, conn.clone()));
+                        probes.push(probe(node_idx, conn));
+                    }
+                    Err(err) => debug!(?err, "ignoring connection without node id"),
+                },
+                None => more_conns_done = true,
+            },
+        }
+    }
+    drop(in_flight);
+    drop(probes);
+    // all pieces were verified and written, so the entry is complete
+    db.insert_complete(entry).await?;
+    progress.send(DownloadProgress::Done { id }).await?;
+    stats.elapsed = start.elapsed();
+    Ok(stats)
+}
+
+/// Request the last chunk of a blob, to learn and verify its size.
+///
+/// This creates a partial entry for the blob if there is none yet, and writes the last chunk
+/// into it.
+async fn get_last_chunk<D: BaoStore>(
+    db: &D,
+    conn: Connection,
+    hash: &Hash,
+) -> Result<(D::EntryMut, Stats), GetError> {
+    let last_chunk = ChunkRanges::from(ChunkNum(u64::MAX)..);
+    let (entry, _size, stats) = get_ranges(db, conn, hash, None, last_chunk).await?;
+    Ok((entry, stats))
+}
+
+/// Request exactly `ranges` of a blob and write them into a store.
+///
+/// The data is written to `entry`, or to a newly created partial entry if `entry` is `None`.
+/// Ranges that are already present are requested again, so callers should only pass the
+/// ranges they are missing. The entry is not marked as complete, even if all of the blob is
+/// present afterwards.
+///
+/// Returns the entry, together with the size of the blob claimed by the remote. The size is
+/// only verified if `ranges` include the last chunk of the blob.
+pub async fn get_ranges<D: BaoStore>(
+    db: &D,
+    conn: Connection,
+    hash: &Hash,
+    entry: Option<D::EntryMut>,
+    ranges: ChunkRanges,
+) -> Result<(D::EntryMut, u64, Stats), GetError> {
+    let request = GetRequest::new(*hash, RangeSpecSeq::from_ranges([ranges]));
+    let connected = get::fsm::start(conn, request).next().await?;
+    let ConnectedNext::StartRoot(start) = connected.next().await? else {
+        return Err(GetError::NoncompliantNode(anyhow!("expected StartRoot")));
+    };
+    let (at_content, size) = start.next().next().await?;
+    let entry = match entry {
+        Some(entry) => entry,
+        None => db.get_or_create(*hash, size).await?,
+    };
+    let mut bw = entry.batch_writer().await?;
+    let end = at_content.write_all_batch(&mut bw).await?;
+    bw.sync().await?;
+    drop(bw);
+    let EndBlobNext::Closing(closing) = end.next() else {
+        return Err(GetError::NoncompliantNode(anyhow!("expected Closing")));
+    };
+    let stats = closing.next().await?;
+    Ok((entry, size, stats))
+}
+
+/// Get the chunks in `chunks` of a raw blob that are not present locally into a store.
+///
+/// Chunks are only considered present if their data matches the outboard, see
+/// [`verified_ranges`]. If the size of the blob is not verified yet, the last chunk is requested
+/// as well. Once all chunks of the blob are present, the entry is marked complete.
+pub async fn get_chunks_to_db<D: BaoStore>(
+    db: &D,
+    conn: Connection,
+    hash: &Hash,
+    chunks: Range<ChunkNum>,
+) -> Result<Stats, GetError> {
+    let entry = db.get_mut(hash).await?;
+    let wanted = ChunkRanges::from(chunks);
+    let (size, present) = match &entry {
+        Some(entry) if entry.is_complete() => return Ok(Stats::default()),
+        Some(entry) => (
+            verified_size::<D>(entry).await?,
+            verified_ranges::<D>(entry, &wanted).await?,
+        ),
+        None => (None, ChunkRanges::empty()),
+    };
+    let ranges: ChunkRanges = match size {
+        Some(size) => wanted.intersection(&ChunkRanges::from(..ChunkNum::chunks(size))),
+        // request the last chunk to verify the size
+        None => wanted.union(&ChunkRanges::from(ChunkNum(u64::MAX)..)),
+    };
+    let ranges: ChunkRanges = ranges.difference(&present);
+    if ranges.is_empty() {
+        return Ok(Stats::default());
+    }
+    let (entry, size, stats) = get_ranges(db, conn, hash, entry, ranges).await?;
+    // the outboard alone can vouch for chunks next to the fetched ones, so check the data
+    let all = ChunkRanges::from(..ChunkNum::chunks(size));
+    if verified_ranges::<D>(&entry, &all).await?.is_superset(&all) {
+        db.insert_complete(entry).await?;
+    }
+    Ok(stats)
+}
+
+/// Get the size of a partial entry, if it is verified.
+///
+/// Stores do not always track whether the size of a partial entry is verified, so the size
+/// also counts as verified if the last chunk for this size matches the outboard.
+pub async fn verified_size<D: MapMut>(entry: &D::EntryMut) -> io::Result<Option<u64>> {
+    let size = match entry.size() {
+        BaoBlobSize::Verified(size) => return Ok(Some(size)),
+        BaoBlobSize::Unverified(size) => size,
+    };
+    let last = ChunkNum(ChunkNum::chunks(size).0.saturating_sub(1));
+    let last = ChunkRanges::from(last..ChunkNum::chunks(size));
+    let verified = verified_ranges::<D>(entry, &last).await?;
+    Ok((size > 0 && verified.is_superset(&last)).then_some(size))
+}
+
+/// Get the chunks in `ranges` of a partial entry whose data matches the outboard.
+///
+/// Unlike [`valid_ranges`], this hashes the data, so chunks whose hashes are in the outboard
+/// but whose data is missing are not included.
+pub async fn verified_ranges<D: MapMut>(
+    entry: &D::EntryMut,
+    ranges: &ChunkRanges,
+) -> io::Result<ChunkRanges> {
+    let outboard = entry.outboard().await?;
+    let data = entry.data_reader().await?;
+    let mut stream = bao_tree::io::fsm::valid_ranges(outboard, data, ranges);
+    let mut verified = ChunkRanges::empty();
+    while let Some(range) = stream.next().await {
+        verified |= ChunkRanges::from(range?);
+    }
+    Ok(verified)
+}
+
+/// Request a single piece of a striped download and write it into `entry`.
+///
+/// Fails if the node claims a different size than `size`.
+async fn get_piece<D: BaoStore>(
+    db: &D,
+    entry: D::EntryMut,
+    conn: Connection,
+    hash: &Hash,
+    size: u64,
+    chunks: ChunkRanges,
+) -> Result<Stats, GetError> {
+    let (_entry, claimed_size, stats) = get_ranges(db, conn, hash, Some(entry), chunks).await?;
+    if claimed_size != size {
+        return Err(GetError::NoncompliantNode(anyhow!(
+            "size mismatch: expected {size}, got {claimed_size}"
+        )));
+    }
+    Ok(stats)
+}
+
+/// The number of bytes of a blob of `size` bytes in `ranges`. All ranges must be bounded.
+fn bytes_in_ranges(ranges: &ChunkRanges, size: u64) -> u64 {
+    ranges
+        .iter()
+        .map(|range| match range {
+            RangeSetRange::Range(range) => {
+                range.end.to_bytes().min(size) - range.start.to_bytes().min(size)
+            }
+            RangeSetRange::RangeFrom(_) => unreachable!("ranges are bounded"),
+        })
+        .sum()
+}
+
+/// Split `ranges` into pieces of at most `stripe_size` chunks.
+///
+/// Pieces are aligned to multiples of `stripe_size`. All ranges must be bounded.
+fn split_into_pieces(ranges: &ChunkRanges, stripe_size: u64) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    for range in ranges.iter() {
+        let RangeSetRange::Range(range) = range else {
+            unreachable!("ranges are bounded");
+        };
+        let (mut start, end) = (range.start.0, range.end.0);
+        while start < end {
+            let piece_end = ((start / stripe_size + 1) * stripe_size).min(end);
+            pieces.push(Piece {
+                chunks: ChunkRanges::from(ChunkNum(start)..ChunkNum(piece_end)),
+                done: false,
+                attempts: Vec::new(),
+            });
+            start = piece_end;
+        }
+    }
+    pieces
+}
+
+/// A piece of a striped download.
+#[derive(Debug)]
+struct Piece {
+    /// The chunks of this piece.
+    chunks: ChunkRanges,
+    /// Whether the piece has been downloaded.
+    done: bool,
+    /// Nodes currently requesting this piece, with the token to cancel the request.
+    attempts: Vec<(usize, CancellationToken)>,
+}
+
+/// A node taking part in a striped download.
+#[derive(Debug)]
+struct StripeNode {
+    node_id: NodeId,
+    conn: Connection,
+    /// Number of pieces currently requested from this node.
+    in_flight: usize,
+    /// Whether a request to this node has failed.
+    failed: bool,
+    /// The missing ranges the node claims to have, once it has answered the have request.
+    available: Option<ChunkRanges>,
+}
+
+impl StripeNode {
+    fn new(node_id: NodeId, conn: Connection) -> Self {
+        Self {
+            node_id,
+            conn,
+            in_flight: 0,
+            failed: false,
+            available: None,
+        }
+    }
+}
+
 /// Get information about a blob in a store.
 ///
 /// This will compute the valid ranges for partial blobs, so it is somewhat expensive for those.
@@ -1,7 +1,8 @@
 //! Error returned from get operations
 
-use iroh_net::endpoint;
+use iroh_net::{endpoint, NodeId};
 
+use crate::protocol::Closed;
 use crate::util::progress::ProgressSendError;
 
 /// Failures for a get operation
@@ -13,6 +14,9 @@ pub enum GetError {
     /// Remote has reset the connection.
     #[error("Remote has reset the connection")]
     RemoteReset(#[source] anyhow::Error),
+    /// Remote has denied access to the requested data.
+    #[error("Remote has denied access")]
+    AccessDenied(#[source] anyhow::Error),
     /// Remote behaved in a non-compliant way.
     #[error("Remote behaved in a non-compliant way")]
     NoncompliantNode(#[source] anyhow::Error),
@@ -27,6 +31,11 @@ pub enum GetError {
     /// Operation failed on the local node.
     #[error("Operation failed on the local node")]
     LocalFailure(#[source] anyhow::Error),
+    /// A download from several nodes failed on each of them.
+    ///
+    /// Contains the error of each node, in the order the nodes failed.
+    #[error("Download failed on all {} nodes", .0.len())]
+    AllNodesFailed(Vec<(NodeId, GetError)>),
 }
 
 impl From<ProgressSendError> for GetError {
@@ -81,6 +90,9 @@ impl From<endpoint::ReadError> for GetError {
     fn from(value: endpoint::ReadError) -> Self {
         use endpoint::ReadError;
         match value {
+            ReadError::Reset(code) if code == Closed::AccessDenied.into() => {
+                GetError::AccessDenied(value.into())
+            }
             e @ ReadError::Reset(_) => GetError::RemoteReset(e.into()),
             ReadError::ConnectionLost(conn_error) => conn_error.into(),
             ReadError::UnknownStream
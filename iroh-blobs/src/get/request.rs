@@ -3,7 +3,7 @@ use std::sync::Arc;
 
 use crate::{
     hashseq::HashSeq,
-    protocol::{GetRequest, RangeSpecSeq},
+    protocol::{GetRequest, HaveRequest, RangeSpecSeq, Request, MAX_MESSAGE_SIZE},
     Hash, HashAndFormat,
 };
 use bao_tree::{ChunkNum, ChunkRanges};
@@ -136,6 +136,24 @@ pub async fn get_hash_seq_and_sizes(
     Ok((hash_seq, sizes.into()))
 }
 
+/// Ask a peer which of the ranges of `request` it has available.
+///
+/// The result has the same shape as the ranges of the request. It is only a hint: the
+/// data is not verified, and the peer might have more or less data by the time it is
+/// requested.
+pub async fn get_available_ranges(
+    connection: &Connection,
+    request: HaveRequest,
+) -> anyhow::Result<RangeSpecSeq> {
+    let (mut send, mut recv) = connection.open_bi().await?;
+    let request_bytes = postcard::to_stdvec(&Request::Have(request))?;
+    send.write_all(&request_bytes).await?;
+    send.finish().await?;
+    let response = recv.read_to_end(MAX_MESSAGE_SIZE).await?;
+    let ranges = postcard::from_bytes(&response)?;
+    Ok(ranges)
+}
+
 /// Probe for a single chunk of a blob.
 ///
 /// This is used to check if a peer has a specific chunk.
@@ -57,12 +57,10 @@
 //!
 //! - Do not support discovery.
 //!
-//!   The protocol does not yet have a discovery mechanism for asking the provider
-//! what ranges are available for a given blob. Currently you have to have some
-//! out-of-band knowledge about what node has data for a given hash, or you can
-//! just try to retrieve the data and see if it is available.
-//!
-//! A discovery protocol is planned in the future though.
+//!   The protocol does not have a discovery mechanism for finding nodes that
+//! have data for a given hash. You have to have some out-of-band knowledge about
+//! what node has data for a given hash. Once you know a node, you can ask it
+//! which ranges it has with a [`HaveRequest`].
 //!
 //! # Requests
 //!
@@ -87,6 +85,25 @@
 //! the same format as the getter defined requests, followed by the bao encoded
 //! data. From then on the protocol is the same as for getter defined requests.
 //!
+//! ## Push requests
+//!
+//! In this case the roles are reversed: the node sending the request has the
+//! data, and the provider is asked to store it. A [`PushRequest`] contains a
+//! hash and a [`RangeSpecSeq`], just like a [`GetRequest`].
+//!
+//! The request is immediately followed by the bao encoded data for the requested
+//! ranges on the same stream, in exactly the same format that a provider would
+//! use to respond to a [`GetRequest`] with the same hash and ranges. Since the
+//! request is postcard encoded, which is self-delimiting, no additional framing
+//! is needed.
+//!
+//! The provider validates the data as it arrives and stores it. Once all data
+//! has been received and stored, the provider finishes its side of the stream.
+//!
+//! A provider is free to reject push requests. In this case, or if the pushed
+//! data can not be validated or stored, it will stop or reset the stream with
+//! [`Closed::PushRejected`].
+//!
 //! ## Specifying the required data
 //!
 //! A [`GetRequest`] contains a hash and a specification of what data related to
@@ -322,22 +339,62 @@
 //! In this case the provider will close just the stream used to send the response.
 //! The exact location of the missing data can be retrieved from the error.
 //!
+//! A provider may also restrict which nodes can access which data. If a request
+//! is denied, the provider will reset the stream with [`Closed::AccessDenied`]
+//! without sending any data. If the provider is already serving too many
+//! requests, it will reset the stream with [`Closed::TooManyTransfers`], and
+//! the request can be retried later.
+//!
 //! # Requesting multiple unrelated blobs
 //!
-//! Currently, the protocol does not support requesting multiple unrelated blobs
-//! in a single request. As an alternative, you can create a collection
-//! on the provider side and use that to efficiently retrieve the blobs.
+//! Multiple unrelated blobs can be requested in a single request using a
+//! [`GetManyRequest`]. It contains a list of hashes, and a [`RangeSpecSeq`]
+//! where the *i*-th element specifies the ranges for the *i*-th hash. Unlike
+//! for a [`GetRequest`], there is no root blob, so the first element of the
+//! [`RangeSpecSeq`] already refers to the first hash.
 //!
-//! If that is not possible, you can create a custom request handler that
-//! accepts a custom request struct that contains the hashes of the blobs.
+//! ```rust
+//! # use bao_tree::{ChunkNum, ChunkRanges};
+//! # use iroh_blobs::protocol::{GetManyRequest, RangeSpecSeq};
+//! # let hash_a: iroh_blobs::Hash = [0; 32].into();
+//! # let hash_b: iroh_blobs::Hash = [1; 32].into();
+//! let request = GetManyRequest::new(
+//!     vec![hash_a, hash_b],
+//!     RangeSpecSeq::from_ranges([
+//!         ChunkRanges::all(), // all of the first blob
+//!         ChunkRanges::from(..ChunkNum(10)), // the first 10 chunks of the second blob
+//!     ]),
+//! );
+//! ```
+//!
+//! The response is identical to the response for the children of a collection,
+//! so the data for each hash is sent in the order in which the hashes appear in
+//! the request. Hashes for which an empty range is requested are skipped.
 //!
-//! If neither of these options are possible, you have no choice but to do
-//! multiple requests. However, note that multiple requests will be multiplexed
-//! over a single connection, and the overhead of a new QUIC stream on an existing
-//! connection is very low.
+//! Just like for collections, the provider will stop sending data and close the
+//! stream once it encounters a hash it does not have.
+//!
+//! Alternatively, you can create a collection on the provider side and use
+//! that to efficiently retrieve the blobs, or do multiple requests. Note that
+//! multiple requests will be multiplexed over a single connection, and the
+//! overhead of a new QUIC stream on an existing connection is very low.
 //!
 //! In case nodes are permanently exchanging data, it is probably valuable to
 //! keep a connection open and reuse it for multiple requests.
+//!
+//! # Asking for available ranges
+//!
+//! A provider that only has parts of a blob will stop sending data once it
+//! reaches a range it does not have. To find out what a provider has without
+//! requesting the data, send a [`HaveRequest`]. It has the same shape as a
+//! [`GetRequest`], and the provider responds with a single postcard encoded
+//! [`RangeSpecSeq`] of the same shape, containing the subset of the requested
+//! ranges that it has, and then finishes the stream.
+//!
+//! A complete blob is reported as [`RangeSpec::all`], regardless of its size.
+//! The children of a hash seq are only reported if the provider has the
+//! complete hash seq. The response is not verified, it is just a hint for
+//! which ranges to request from which provider.
 use bao_tree::{ChunkNum, ChunkRanges};
 use derive_more::From;
 use iroh_net::endpoint::VarInt;
@@ -358,6 +415,12 @@ pub const ALPN: &[u8] = b"/iroh-bytes/4";
 pub enum Request {
     /// A get request for a blob or collection
     Get(GetRequest),
+    /// A get request for multiple unrelated blobs
+    GetMany(GetManyRequest),
+    /// A request to store a blob or collection, followed by the data
+    Push(PushRequest),
+    /// A request for the ranges of a blob or collection the provider has
+    Have(HaveRequest),
 }
 
 /// A request
@@ -417,6 +480,119 @@ impl GetRequest {
     }
 }
 
+/// A request for multiple unrelated blobs
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+pub struct GetManyRequest {
+    /// blake3 hashes of the requested blobs
+    pub hashes: Vec<Hash>,
+    /// The ranges of data to request
+    ///
+    /// The *i*-th element is the range spec for the *i*-th hash. Elements
+    /// beyond the number of hashes are ignored.
+    pub ranges: RangeSpecSeq,
+}
+
+impl GetManyRequest {
+    /// Request multiple blobs with specified ranges
+    pub fn new(hashes: Vec<Hash>, ranges: RangeSpecSeq) -> Self {
+        Self { hashes, ranges }
+    }
+
+    /// Request multiple blobs completely
+    pub fn all(hashes: Vec<Hash>) -> Self {
+        Self {
+            hashes,
+            ranges: RangeSpecSeq::all(),
+        }
+    }
+
+    /// The ranges of the request, normalized to the shape of a [`GetRequest`]
+    /// for a hash sequence containing exactly the requested hashes.
+    ///
+    /// The first element, which would refer to the hash sequence itself, is
+    /// always empty, and there are no elements beyond the number of hashes.
+    pub(crate) fn hash_seq_ranges(&self) -> RangeSpecSeq {
+        RangeSpecSeq::new(
+            std::iter::once(RangeSpec::EMPTY)
+                .chain(self.ranges.iter().take(self.hashes.len()).cloned())
+                .chain(std::iter::once(RangeSpec::EMPTY)),
+        )
+    }
+}
+
+/// A request to push data to the provider
+///
+/// The request is followed by the bao encoded data, in the same format as a
+/// response to a [`GetRequest`] with the same hash and ranges.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+pub struct PushRequest {
+    /// blake3 hash
+    pub hash: Hash,
+    /// The range of data that is pushed
+    ///
+    /// The first element is the parent, all subsequent elements are children.
+    pub ranges: RangeSpecSeq,
+}
+
+impl PushRequest {
+    /// Push a blob or collection with specified ranges
+    pub fn new(hash: Hash, ranges: RangeSpecSeq) -> Self {
+        Self { hash, ranges }
+    }
+
+    /// Push a collection and all its children
+    pub fn all(hash: Hash) -> Self {
+        Self {
+            hash,
+            ranges: RangeSpecSeq::all(),
+        }
+    }
+
+    /// Push just a single blob
+    pub fn single(hash: Hash) -> Self {
+        Self {
+            hash,
+            ranges: RangeSpecSeq::from_ranges([ChunkRanges::all()]),
+        }
+    }
+}
+
+/// A request for the ranges of a blob or collection the provider has
+///
+/// See the [module docs](self#asking-for-available-ranges) for the response.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone)]
+pub struct HaveRequest {
+    /// blake3 hash
+    pub hash: Hash,
+    /// The ranges to ask about
+    ///
+    /// The first element is the parent, all subsequent elements are children.
+    pub ranges: RangeSpecSeq,
+}
+
+impl HaveRequest {
+    /// Ask about specified ranges of a blob or collection
+    pub fn new(hash: Hash, ranges: RangeSpecSeq) -> Self {
+        Self { hash, ranges }
+    }
+
+    /// Ask about a collection and all its children
+    pub fn all(hash: Hash) -> Self {
+        Self {
+            hash,
+            ranges: RangeSpecSeq::all(),
+        }
+    }
+
+    /// Ask about just a single blob
+    pub fn single(hash: Hash) -> Self {
+        Self {
+            hash,
+            ranges: RangeSpecSeq::from_ranges([ChunkRanges::all()]),
+        }
+    }
+}
+
 /// Reasons to close connections or stop streams.
 ///
 /// A QUIC **connection** can be *closed* and a **stream** can request the other side to
@@ -445,6 +621,20 @@ pub enum Closed {
     /// Only a single request is allowed on a stream, if more data is received after this a
     /// provider may send this error code in a STOP_STREAM frame.
     RequestReceived = 2,
+    /// The provider rejected a push request.
+    ///
+    /// This is used both if the push request was not accepted, and if the pushed data
+    /// could not be validated or stored.
+    PushRejected = 3,
+    /// The provider denied access to the requested data.
+    ///
+    /// The provider resets the stream with this error code instead of sending any data.
+    AccessDenied = 4,
+    /// The provider is serving too many requests.
+    ///
+    /// The provider resets the stream with this error code instead of sending any data.
+    /// The request can be retried later.
+    TooManyTransfers = 5,
 }
 
 impl Closed {
@@ -454,6 +644,9 @@ impl Closed {
             Closed::StreamDropped => b"stream dropped",
             Closed::ProviderTerminating => b"provider terminating",
             Closed::RequestReceived => b"request received",
+            Closed::PushRejected => b"push rejected",
+            Closed::AccessDenied => b"access denied",
+            Closed::TooManyTransfers => b"too many transfers",
         }
     }
 }
@@ -477,6 +670,9 @@ impl TryFrom<VarInt> for Closed {
             0 => Ok(Self::StreamDropped),
             1 => Ok(Self::ProviderTerminating),
             2 => Ok(Self::RequestReceived),
+            3 => Ok(Self::PushRejected),
+            4 => Ok(Self::AccessDenied),
+            5 => Ok(Self::TooManyTransfers),
             val => Err(UnknownErrorCode(val)),
         }
     }
@@ -486,7 +682,9 @@ impl TryFrom<VarInt> for Closed {
 mod tests {
     use iroh_test::{assert_eq_hex, hexdump::parse_hexdump};
 
-    use super::{GetRequest, Request};
+    use bao_tree::{ChunkNum, ChunkRanges};
+
+    use super::{GetManyRequest, GetRequest, HaveRequest, PushRequest, RangeSpecSeq, Request};
 
     #[test]
     fn request_wire_format() {
@@ -508,6 +706,32 @@ mod tests {
                     01000100 # the RangeSpecSeq
             ",
             ),
+            (
+                Request::from(GetManyRequest::all(vec![hash, hash])),
+                r"
+                    01 # enum variant for GetManyRequest
+                    02 # number of hashes
+                    dadadadadadadadadadadadadadadadadadadadadadadadadadadadadadadada # the first hash
+                    dadadadadadadadadadadadadadadadadadadadadadadadadadadadadadadada # the second hash
+                    01000100 # the RangeSpecSeq
+            ",
+            ),
+            (
+                Request::from(PushRequest::single(hash)),
+                r"
+                    02 # enum variant for PushRequest
+                    dadadadadadadadadadadadadadadadadadadadadadadadadadadadadadadada # the hash
+                    020001000100 # the RangeSpecSeq
+            ",
+            ),
+            (
+                Request::from(HaveRequest::all(hash)),
+                r"
+                    03 # enum variant for HaveRequest
+                    dadadadadadadadadadadadadadadadadadadadadadadadadadadadadadadada # the hash
+                    01000100 # the RangeSpecSeq
+            ",
+            ),
         ];
         for (case, expected_hex) in cases {
             let expected = parse_hexdump(expected_hex).unwrap();
@@ -515,4 +739,25 @@ mod tests {
             assert_eq_hex!(bytes, expected);
         }
     }
+    #[test]
+    fn get_many_hash_seq_ranges() {
+        let hash = [0xda; 32].into();
+        let request = GetManyRequest::all(vec![hash, hash]);
+        assert_eq!(
+            request.hash_seq_ranges(),
+            RangeSpecSeq::from_ranges([
+                ChunkRanges::empty(),
+                ChunkRanges::all(),
+                ChunkRanges::all()
+            ])
+        );
+        let request = GetManyRequest::new(
+            vec![hash],
+            RangeSpecSeq::from_ranges_infinite([ChunkRanges::from(..ChunkNum(1))]),
+        );
+        assert_eq!(
+            request.hash_seq_ranges(),
+            RangeSpecSeq::from_ranges([ChunkRanges::empty(), ChunkRanges::from(..ChunkNum(1))])
+        );
+    }
 }
@@ -2,26 +2,47 @@
 use std::fmt::Debug;
 use std::time::Duration;
 
+use std::io::Cursor;
+use std::sync::Arc;
+
 use anyhow::{Context, Result};
 use bao_tree::io::fsm::{encode_ranges_validated, Outboard};
+use bao_tree::io::fsm::{ResponseDecoder, ResponseDecoderNext};
 use bao_tree::io::EncodeError;
+use bao_tree::{BaoTree, ChunkRanges};
+use bytes::Bytes;
 use futures_lite::future::Boxed as BoxFuture;
 use iroh_base::rpc::RpcError;
 use iroh_io::stats::{
     SliceReaderStats, StreamWriterStats, TrackingSliceReader, TrackingStreamWriter,
 };
-use iroh_io::{AsyncSliceReader, AsyncStreamWriter, TokioStreamWriter};
-use iroh_net::endpoint::{self, RecvStream, SendStream};
+use iroh_io::{
+    AsyncSliceReader, AsyncStreamReader, AsyncStreamWriter, TokioStreamReader, TokioStreamWriter,
+};
+use iroh_net::endpoint::{self, get_remote_node_id, RecvStream, SendStream};
+use iroh_net::NodeId;
 use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+use tokio::sync::OnceCell;
 use tokio_util::task::LocalPoolHandle;
 use tracing::{debug, debug_span, info, trace, warn};
 use tracing_futures::Instrument;
 
+use crate::get::db::valid_ranges;
+use crate::get::fsm::decode_all_batch;
 use crate::hashseq::parse_hash_seq;
-use crate::protocol::{GetRequest, RangeSpec, Request};
+use crate::protocol::{
+    Closed, GetManyRequest, GetRequest, HaveRequest, PushRequest, RangeSpec, RangeSpecSeq, Request,
+    MAX_MESSAGE_SIZE,
+};
 use crate::store::*;
-use crate::util::Tag;
-use crate::{BlobFormat, Hash};
+use crate::util::{reblock::ReblockOutboard, SetTagOption, Tag};
+use crate::{BlobFormat, Hash, HashAndFormat, IROH_BLOCK_SIZE};
+
+mod limits;
+
+use limits::{ConnectionLimits, LimitedWriter};
+pub use limits::{TransferLimiter, TransferLimits};
 
 /// Events emitted by the provider informing about the current status.
 #[derive(Debug, Clone)]
@@ -49,6 +70,75 @@ pub enum Event {
         /// The hash for which the client wants to receive data.
         hash: Hash,
     },
+    /// A get or get many request was denied by the [`AuthorizeGet`] hook.
+    GetRequestDenied {
+        /// An unique connection id.
+        connection_id: u64,
+        /// An identifier uniquely identifying this transfer request.
+        request_id: u64,
+    },
+    /// A get, get many or have request was rejected because the maximum number of
+    /// concurrent transfers was reached, see [`TransferLimits`].
+    TransferRejected {
+        /// An unique connection id.
+        connection_id: u64,
+        /// An identifier uniquely identifying this transfer request.
+        request_id: u64,
+    },
+    /// A transfer is being slowed down by an upload rate limit, see [`TransferLimits`].
+    ///
+    /// This is sent at most once per request, when the transfer is delayed for the first time.
+    TransferThrottled {
+        /// An unique connection id.
+        connection_id: u64,
+        /// An identifier uniquely identifying this transfer request.
+        request_id: u64,
+    },
+    /// A request for multiple blobs was received from a client.
+    GetManyRequestReceived {
+        /// An unique connection id.
+        connection_id: u64,
+        /// An identifier uniquely identifying this transfer request.
+        request_id: u64,
+        /// The hashes for which the client wants to receive data.
+        hashes: Vec<Hash>,
+    },
+    /// A push request was received from a client.
+    PushRequestReceived {
+        /// An unique connection id.
+        connection_id: u64,
+        /// An identifier uniquely identifying this transfer request.
+        request_id: u64,
+        /// The hash of the data the client wants to push.
+        hash: Hash,
+    },
+    /// A push request was rejected.
+    PushRequestRejected {
+        /// An unique connection id.
+        connection_id: u64,
+        /// An identifier uniquely identifying this transfer request.
+        request_id: u64,
+    },
+    /// The data of a push request was received and stored.
+    PushCompleted {
+        /// An unique connection id.
+        connection_id: u64,
+        /// An identifier uniquely identifying this transfer request.
+        request_id: u64,
+        /// The hash of the pushed data.
+        hash: Hash,
+        /// The tag that protects the pushed data from garbage collection.
+        tag: Tag,
+    },
+    /// A request for the available ranges of a blob or collection was received from a client.
+    HaveRequestReceived {
+        /// An unique connection id.
+        connection_id: u64,
+        /// An identifier uniquely identifying this request.
+        request_id: u64,
+        /// The hash for which the client wants to know the available ranges.
+        hash: Hash,
+    },
     /// A request was received from a client.
     CustomGetRequestReceived {
         /// An unique connection id.
@@ -138,6 +228,16 @@ pub enum AddProgress {
         /// The hash of the entry.
         hash: Hash,
     },
+    /// An item with name `name` is unchanged since it was last added, and was not
+    /// read again. It is not preceded by [`AddProgress::Found`].
+    Skipped {
+        /// The name of the entry.
+        name: String,
+        /// The size of the entry in bytes.
+        size: u64,
+        /// The hash of the entry.
+        hash: Hash,
+    },
     /// We are done with the whole operation.
     AllDone {
         /// The hash of the created data.
@@ -155,16 +255,28 @@ pub enum AddProgress {
 
 /// Read the request from the getter.
 ///
-/// Will fail if there is an error while reading, if the reader
-/// contains more data than the Request, or if no valid request is sent.
+/// Will fail if there is an error while reading, if the request is larger than
+/// [`MAX_MESSAGE_SIZE`], or if no valid request is sent.
 ///
-/// When successful, the buffer is empty after this function call.
-pub async fn read_request(mut reader: RecvStream) -> Result<Request> {
-    let payload = reader
-        .read_to_end(crate::protocol::MAX_MESSAGE_SIZE)
-        .await?;
-    let request: Request = postcard::from_bytes(&payload)?;
-    Ok(request)
+/// Since the request is self-delimiting, this does not wait for the end of the
+/// stream. Any data that was received after the request is returned as well.
+/// For a push request, this is the beginning of the pushed data. For all other
+/// requests it should be empty.
+pub async fn read_request(reader: &mut RecvStream) -> Result<(Request, Bytes)> {
+    let mut buf = Vec::new();
+    loop {
+        match postcard::take_from_bytes::<Request>(&buf) {
+            Ok((request, rest)) => return Ok((request, Bytes::copy_from_slice(rest))),
+            Err(postcard::Error::DeserializeUnexpectedEnd) => {}
+            Err(cause) => return Err(cause.into()),
+        }
+        anyhow::ensure!(buf.len() <= MAX_MESSAGE_SIZE, "request too large");
+        let chunk = reader
+            .read_chunk(MAX_MESSAGE_SIZE, true)
+            .await?
+            .context("stream finished before the request was complete")?;
+        buf.extend_from_slice(&chunk.bytes);
+    }
 }
 
 /// Transfers the collection & blob data.
@@ -279,15 +391,86 @@ pub trait EventSender: Clone + Sync + Send + 'static {
     fn send(&self, event: Event) -> BoxFuture<()>;
 }
 
+/// Hook to decide whether to accept push requests.
+pub trait AcceptPush: Debug + Send + Sync + 'static {
+    /// Decide whether to accept the push `request` from the node `remote`.
+    ///
+    /// This is called before any of the pushed data is read. Returns `None` to
+    /// reject the request, or the tag to create for the pushed data once the
+    /// push is complete. Until then, the data is protected by a temp tag.
+    fn accept(&self, remote: NodeId, request: &PushRequest) -> BoxFuture<Option<SetTagOption>>;
+}
+
+/// Hook to decide whether a node may retrieve data.
+///
+/// This is called with the remote node id and the request before any data is
+/// sent. Note that a request for a hash sequence also requests (parts of) its
+/// children, so access to the children is granted by granting access to the
+/// request for the hash sequence.
+pub trait AuthorizeGet: Debug + Send + Sync + 'static {
+    /// Decide whether the node `remote` may retrieve the data described by `request`.
+    fn authorize(&self, remote: NodeId, request: &GetRequest) -> BoxFuture<bool>;
+
+    /// Decide whether the node `remote` may retrieve the data described by `request`.
+    ///
+    /// The default implementation authorizes each requested hash as a separate
+    /// [`GetRequest`] for a single blob, and only grants access if all of them are
+    /// authorized.
+    fn authorize_many(&self, remote: NodeId, request: &GetManyRequest) -> BoxFuture<bool> {
+        let checks = request
+            .hashes
+            .iter()
+            .zip(request.ranges.iter())
+            .filter(|(_, ranges)| !ranges.is_empty())
+            .map(|(hash, ranges)| {
+                let request = GetRequest::new(*hash, RangeSpecSeq::new([ranges.clone()]));
+                self.authorize(remote, &request)
+            })
+            .collect::<Vec<_>>();
+        Box::pin(async move {
+            for check in checks {
+                if !check.await {
+                    return false;
+                }
+            }
+            true
+        })
+    }
+}
+
+/// Options for serving connections with [`handle_connection`].
+///
+/// The default serves all get requests without limits and rejects all push
+/// requests.
+#[derive(Debug, Clone, Default)]
+pub struct ProviderOptions {
+    /// Push requests are rejected unless this is set and accepts them.
+    pub accept_push: Option<Arc<dyn AcceptPush>>,
+    /// If set, get requests are only served if it authorizes them.
+    pub authorize_get: Option<Arc<dyn AuthorizeGet>>,
+    /// If set, serving data is subject to its [`TransferLimits`].
+    pub limiter: Option<TransferLimiter>,
+}
+
 /// Handle a single connection.
-pub async fn handle_connection<D: Map, E: EventSender>(
+pub async fn handle_connection<D: Store, E: EventSender>(
     connection: endpoint::Connection,
     db: D,
     events: E,
+    options: ProviderOptions,
     rt: LocalPoolHandle,
 ) {
+    let ProviderOptions {
+        accept_push,
+        authorize_get,
+        limiter,
+    } = options;
     let remote_addr = connection.remote_address();
     let connection_id = connection.stable_id() as u64;
+    let remote_node_id = get_remote_node_id(&connection).ok();
+    let limits = limiter
+        .map(|limiter| limiter.connection(remote_node_id))
+        .unwrap_or_default();
     let span = debug_span!("connection", connection_id, %remote_addr);
     async move {
         while let Ok((writer, reader)) = connection.accept_bi().await {
@@ -299,12 +482,25 @@ pub async fn handle_connection<D: Map, E: EventSender>(
                 connection_id,
                 events: events.clone(),
                 inner: writer,
+                limits: limits.clone(),
+                throttled: false,
             };
             events.send(Event::ClientConnected { connection_id }).await;
             let db = db.clone();
-            rt.spawn_pinned(|| {
+            let accept_push = accept_push.clone();
+            let authorize_get = authorize_get.clone();
+            rt.spawn_pinned(move || {
                 async move {
-                    if let Err(err) = handle_stream(db, reader, writer).await {
+                    if let Err(err) = handle_stream(
+                        db,
+                        reader,
+                        writer,
+                        remote_node_id,
+                        accept_push,
+                        authorize_get,
+                    )
+                    .await
+                    {
                         warn!("error: {err:#?}",);
                     }
                 }
@@ -316,14 +512,17 @@ pub async fn handle_connection<D: Map, E: EventSender>(
     .await
 }
 
-async fn handle_stream<D: Map, E: EventSender>(
+async fn handle_stream<D: Store, E: EventSender>(
     db: D,
-    reader: RecvStream,
+    mut reader: RecvStream,
     writer: ResponseWriter<E>,
+    remote_node_id: Option<NodeId>,
+    accept_push: Option<Arc<dyn AcceptPush>>,
+    authorize_get: Option<Arc<dyn AuthorizeGet>>,
 ) -> Result<()> {
     // 1. Decode the request.
     debug!("reading request");
-    let request = match read_request(reader).await {
+    let (request, rest) = match read_request(&mut reader).await {
         Ok(r) => r,
         Err(e) => {
             writer.notify_transfer_aborted(None).await;
@@ -332,10 +531,102 @@ async fn handle_stream<D: Map, E: EventSender>(
     };
 
     match request {
-        Request::Get(request) => handle_get(db, request, writer).await,
+        Request::Get(request) => {
+            let allowed = authorized(&authorize_get, remote_node_id, |authorize_get, remote| {
+                authorize_get.authorize(remote, &request)
+            })
+            .await;
+            if !allowed {
+                return deny_request(writer).await;
+            }
+            let Some(_permit) = writer.limits.try_start_transfer() else {
+                return reject_transfer(writer).await;
+            };
+            handle_get(db, request, writer).await
+        }
+        Request::GetMany(request) => {
+            let allowed = authorized(&authorize_get, remote_node_id, |authorize_get, remote| {
+                authorize_get.authorize_many(remote, &request)
+            })
+            .await;
+            if !allowed {
+                return deny_request(writer).await;
+            }
+            let Some(_permit) = writer.limits.try_start_transfer() else {
+                return reject_transfer(writer).await;
+            };
+            handle_get_many(db, request, writer).await
+        }
+        Request::Push(request) => {
+            let accepted = match (remote_node_id, accept_push) {
+                (Some(remote), Some(accept_push)) => accept_push.accept(remote, &request).await,
+                _ => None,
+            };
+            handle_push(db, request, accepted, rest, reader, writer).await
+        }
+        Request::Have(request) => {
+            // knowing what is available is treated like getting it
+            let allowed = authorized(&authorize_get, remote_node_id, |authorize_get, remote| {
+                let get = GetRequest::new(request.hash, request.ranges.clone());
+                authorize_get.authorize(remote, &get)
+            })
+            .await;
+            if !allowed {
+                return deny_request(writer).await;
+            }
+            // answering reads from the store for every requested blob, like a transfer
+            let Some(_permit) = writer.limits.try_start_transfer() else {
+                return reject_transfer(writer).await;
+            };
+            handle_have(db, request, writer).await
+        }
+    }
+}
+
+/// Check whether the remote node may retrieve the data of a request with `authorize`.
+///
+/// All requests are authorized if there is no [`AuthorizeGet`], and requests from
+/// unknown nodes are denied if there is one.
+async fn authorized(
+    authorize_get: &Option<Arc<dyn AuthorizeGet>>,
+    remote_node_id: Option<NodeId>,
+    authorize: impl FnOnce(&dyn AuthorizeGet, NodeId) -> BoxFuture<bool>,
+) -> bool {
+    match (authorize_get, remote_node_id) {
+        (None, _) => true,
+        (Some(authorize_get), Some(remote)) => authorize(authorize_get.as_ref(), remote).await,
+        (Some(_), None) => false,
     }
 }
 
+/// Deny a get request by resetting the stream with [`Closed::AccessDenied`].
+async fn deny_request<E: EventSender>(mut writer: ResponseWriter<E>) -> Result<()> {
+    debug!("access denied");
+    writer.inner.reset(Closed::AccessDenied.into()).ok();
+    writer
+        .events
+        .send(Event::GetRequestDenied {
+            connection_id: writer.connection_id(),
+            request_id: writer.request_id(),
+        })
+        .await;
+    Ok(())
+}
+
+/// Reject a get request by resetting the stream with [`Closed::TooManyTransfers`].
+async fn reject_transfer<E: EventSender>(mut writer: ResponseWriter<E>) -> Result<()> {
+    debug!("too many transfers");
+    writer.inner.reset(Closed::TooManyTransfers.into()).ok();
+    writer
+        .events
+        .send(Event::TransferRejected {
+            connection_id: writer.connection_id(),
+            request_id: writer.request_id(),
+        })
+        .await;
+    Ok(())
+}
+
 /// Handle a single standard get request.
 pub async fn handle_get<D: Map, E: EventSender>(
     db: D,
@@ -360,11 +651,16 @@ pub async fn handle_get<D: Map, E: EventSender>(
             let mut stats = Box::<TransferStats>::default();
             let t0 = std::time::Instant::now();
             // 5. Transfer data!
+            let outboard = ReblockOutboard::new(
+                entry.outboard().await?,
+                entry.data_reader().await?,
+                IROH_BLOCK_SIZE,
+            );
             let res = transfer_collection(
                 request,
                 &db,
                 &mut writer,
-                entry.outboard().await?,
+                outboard,
                 entry.data_reader().await?,
                 &mut stats,
             )
@@ -372,7 +668,8 @@ pub async fn handle_get<D: Map, E: EventSender>(
             stats.duration = t0.elapsed();
             match res {
                 Ok(SentStatus::Sent) => {
-                    writer.notify_transfer_completed(&hash, stats).await;
+                    info!("transfer completed for {}", hash);
+                    writer.notify_transfer_completed(stats).await;
                 }
                 Ok(SentStatus::NotFound) => {
                     writer.notify_transfer_aborted(Some(stats)).await;
@@ -395,17 +692,358 @@ pub async fn handle_get<D: Map, E: EventSender>(
     Ok(())
 }
 
+/// Handle a single get many request.
+///
+/// Sends the requested ranges of each blob in the order of the hashes in the
+/// request. Stops and finishes the stream once a blob is not found.
+pub async fn handle_get_many<D: Map, E: EventSender>(
+    db: D,
+    request: GetManyRequest,
+    mut writer: ResponseWriter<E>,
+) -> Result<()> {
+    debug!(
+        "received get many request for {} hashes",
+        request.hashes.len()
+    );
+    writer
+        .events
+        .send(Event::GetManyRequestReceived {
+            hashes: request.hashes.clone(),
+            connection_id: writer.connection_id(),
+            request_id: writer.request_id(),
+        })
+        .await;
+    let mut stats = Box::<TransferStats>::default();
+    let t0 = std::time::Instant::now();
+    let res = transfer_many(request, &db, &mut writer, &mut stats).await;
+    stats.duration = t0.elapsed();
+    match res {
+        Ok(SentStatus::Sent) => {
+            info!("get many transfer completed");
+            writer.notify_transfer_completed(stats).await;
+        }
+        Ok(SentStatus::NotFound) => {
+            writer.notify_transfer_aborted(Some(stats)).await;
+        }
+        Err(e) => {
+            writer.notify_transfer_aborted(Some(stats)).await;
+            return Err(e);
+        }
+    }
+    debug!("finished response");
+    Ok(())
+}
+
+/// Handle a single have request.
+///
+/// Responds with the subset of the requested ranges that is available, see
+/// the [protocol docs](crate::protocol#asking-for-available-ranges).
+pub async fn handle_have<D: Map, E: EventSender>(
+    db: D,
+    request: HaveRequest,
+    mut writer: ResponseWriter<E>,
+) -> Result<()> {
+    let hash = request.hash;
+    debug!(%hash, "received have request");
+    writer
+        .events
+        .send(Event::HaveRequestReceived {
+            hash,
+            connection_id: writer.connection_id(),
+            request_id: writer.request_id(),
+        })
+        .await;
+    let ranges = have_ranges(&db, &request).await?;
+    let response = postcard::to_stdvec(&ranges)?;
+    writer.inner.write_all(&response).await?;
+    writer.inner.finish().await?;
+    debug!("finished response");
+    Ok(())
+}
+
+/// The subset of the ranges of `request` that is available in `db`.
+///
+/// Children of a hash seq are only considered if the hash seq is complete.
+async fn have_ranges<D: Map>(db: &D, request: &HaveRequest) -> Result<RangeSpecSeq> {
+    let mut res = Vec::new();
+    let mut children = None;
+    for (offset, ranges) in request.ranges.iter_non_empty() {
+        let (hash, ranges) = if offset == 0 {
+            (request.hash, ranges)
+        } else {
+            if children.is_none() {
+                let Some(entry) = db.get(&request.hash).await? else {
+                    break;
+                };
+                if !entry.is_complete() {
+                    break;
+                }
+                let Ok((stream, _)) = parse_hash_seq(entry.data_reader().await?).await else {
+                    break;
+                };
+                children = Some((stream, 0));
+            }
+            let (stream, prev) = children.as_mut().expect("just set");
+            // skip to the next blob if there is a gap
+            if *prev < offset - 1 {
+                stream.skip(offset - *prev - 1).await?;
+            }
+            *prev = offset;
+            match stream.next().await? {
+                Some(hash) => (hash, ranges),
+                None => break,
+            }
+        };
+        let available = match db.get(&hash).await? {
+            Some(entry) => entry.available_ranges().await?,
+            None => ChunkRanges::empty(),
+        };
+        let offset = usize::try_from(offset)?;
+        res.resize(offset, RangeSpec::EMPTY);
+        res.push(RangeSpec::new(ranges.to_chunk_ranges() & available));
+    }
+    Ok(RangeSpecSeq::new(
+        res.into_iter().chain(std::iter::once(RangeSpec::EMPTY)),
+    ))
+}
+
+/// Transfers the requested ranges of multiple unrelated blobs.
+///
+/// If a blob cannot be found in the database, the transfer will gracefully
+/// close the writer, and return with `Ok(SentStatus::NotFound)`.
+async fn transfer_many<D: Map, E: EventSender>(
+    request: GetManyRequest,
+    db: &D,
+    writer: &mut ResponseWriter<E>,
+    stats: &mut TransferStats,
+) -> Result<SentStatus> {
+    for (offset, ranges) in request.ranges.iter_non_empty() {
+        let Some(hash) = usize::try_from(offset)
+            .ok()
+            .and_then(|offset| request.hashes.get(offset))
+            .copied()
+        else {
+            // ranges beyond the number of hashes are ignored
+            break;
+        };
+        debug!("writing ranges '{:?}' of blob {}", ranges, hash);
+        let mut tw = writer.tracking_writer();
+        tokio::task::yield_now().await;
+        let (status, size, blob_read_stats) = send_blob(db, hash, ranges, &mut tw).await?;
+        stats.send += tw.stats();
+        stats.read += blob_read_stats;
+        if SentStatus::NotFound == status {
+            writer.inner.finish().await?;
+            return Ok(status);
+        }
+        writer
+            .events
+            .send(Event::TransferBlobCompleted {
+                connection_id: writer.connection_id(),
+                request_id: writer.request_id(),
+                hash,
+                index: offset,
+                size,
+            })
+            .await;
+    }
+    debug!("done writing");
+    Ok(SentStatus::Sent)
+}
+
+/// Handle a single push request.
+///
+/// If `accepted` is `None`, the request is rejected by stopping the stream
+/// with [`Closed::PushRejected`]. Otherwise the pushed data is validated and
+/// stored, starting with `rest`, the data that was read together with the
+/// request, and continuing with `reader`. The data is protected by a temp tag
+/// while it is received, and by the tag given in `accepted` once it is stored.
+pub async fn handle_push<D: Store, E: EventSender>(
+    db: D,
+    request: PushRequest,
+    accepted: Option<SetTagOption>,
+    rest: Bytes,
+    mut reader: RecvStream,
+    mut writer: ResponseWriter<E>,
+) -> Result<()> {
+    let hash = request.hash;
+    debug!(%hash, "received push request");
+    writer
+        .events
+        .send(Event::PushRequestReceived {
+            hash,
+            connection_id: writer.connection_id(),
+            request_id: writer.request_id(),
+        })
+        .await;
+    let Some(tag) = accepted else {
+        debug!(%hash, "push request rejected");
+        reader.stop(Closed::PushRejected.into()).ok();
+        writer.inner.reset(Closed::PushRejected.into()).ok();
+        writer
+            .events
+            .send(Event::PushRequestRejected {
+                connection_id: writer.connection_id(),
+                request_id: writer.request_id(),
+            })
+            .await;
+        return Ok(());
+    };
+    // a push of children is a push of a hash seq, which protects the children
+    // from gc as well
+    let format = if request
+        .ranges
+        .iter_non_empty()
+        .any(|(offset, _)| offset > 0)
+    {
+        BlobFormat::HashSeq
+    } else {
+        BlobFormat::Raw
+    };
+    let hash_and_format = HashAndFormat { hash, format };
+    let temp_tag = db.temp_tag(hash_and_format);
+    let reader = TokioStreamReader::new(Cursor::new(rest).chain(reader));
+    let res = async {
+        receive_ranges(&db, request.hash, &request.ranges, reader).await?;
+        let tag = match tag {
+            SetTagOption::Named(tag) => {
+                db.set_tag(tag.clone(), Some(hash_and_format), None).await?;
+                tag
+            }
+            SetTagOption::Auto => db.create_tag(hash_and_format, None).await?,
+        };
+        anyhow::Ok(tag)
+    }
+    .await;
+    drop(temp_tag);
+    match res {
+        Ok(tag) => {
+            writer.inner.finish().await?;
+            info!("push completed for {}", hash);
+            writer
+                .events
+                .send(Event::PushCompleted {
+                    connection_id: writer.connection_id(),
+                    request_id: writer.request_id(),
+                    hash,
+                    tag,
+                })
+                .await;
+            Ok(())
+        }
+        Err(e) => {
+            writer.inner.reset(Closed::PushRejected.into()).ok();
+            writer.notify_transfer_aborted(None).await;
+            Err(e)
+        }
+    }
+}
+
+/// Receive and store `ranges` of `root` and, for a hash seq, its children.
+///
+/// The data is in the same format as a response to a get request with the
+/// same hash and ranges. This is used for push requests, and for importing
+/// data that was exported with [`send_ranges`].
+///
+/// Returns the reader, positioned after the data.
+pub(crate) async fn receive_ranges<D: MapMut, R: AsyncStreamReader>(
+    db: &D,
+    root: Hash,
+    ranges: &RangeSpecSeq,
+    mut reader: R,
+) -> Result<R> {
+    let children = OnceCell::new();
+    for (offset, ranges) in ranges.iter_non_empty() {
+        let hash = if offset == 0 {
+            root
+        } else {
+            // the hash seq must be available locally, either because it was
+            // just pushed or because we had it before.
+            let children = children
+                .get_or_try_init(|| load_children(db, root))
+                .await?
+                .as_deref()
+                .context("hash seq not available")?;
+            match child_at(children, offset) {
+                Some(hash) => hash,
+                None => break,
+            }
+        };
+        reader = receive_blob(db, hash, ranges.to_chunk_ranges(), reader).await?;
+    }
+    Ok(reader)
+}
+
+/// Receive the pushed ranges of a single blob, validating and storing them
+/// using the entry's [`BaoBatchWriter`].
+async fn receive_blob<D: MapMut, R: AsyncStreamReader>(
+    db: &D,
+    hash: Hash,
+    ranges: ChunkRanges,
+    mut reader: R,
+) -> Result<R> {
+    // read the size. The size we get here is not verified, but since we use
+    // it for the tree traversal we are guaranteed not to get more than size.
+    let size = u64::from_le_bytes(reader.read::<8>().await?);
+    let entry = match db.get_mut(&hash).await? {
+        Some(entry) if entry.is_complete() => None,
+        Some(entry) => Some(entry),
+        None => Some(db.get_or_create(hash, size).await?),
+    };
+    let tree = BaoTree::new(size, IROH_BLOCK_SIZE);
+    let mut decoder = ResponseDecoder::new(hash.into(), ranges.clone(), tree, reader);
+    let Some(entry) = entry else {
+        // we already have the complete blob, but still need to consume the data
+        debug!(%hash, "already got entire blob, draining pushed data");
+        loop {
+            match decoder.next().await {
+                ResponseDecoderNext::More((next, item)) => {
+                    item?;
+                    decoder = next;
+                }
+                ResponseDecoderNext::Done(reader) => return Ok(reader),
+            }
+        }
+    };
+    let mut bw = entry.batch_writer().await?;
+    // same decoding and writing as for a get request
+    let reader = decode_all_batch(decoder, &mut bw).await?;
+    bw.sync().await?;
+    drop(bw);
+    // only mark the entry as complete if we now have all the data
+    let complete = ranges.is_superset(&ChunkRanges::from(..tree.chunks()))
+        || valid_ranges::<D>(&entry)
+            .await?
+            .is_superset(&ChunkRanges::from(..tree.chunks()));
+    if complete {
+        db.insert_complete(entry).await?;
+    }
+    Ok(reader)
+}
+
 /// A helper struct that combines a quinn::SendStream with auxiliary information
 #[derive(Debug)]
 pub struct ResponseWriter<E> {
     inner: SendStream,
     events: E,
     connection_id: u64,
+    limits: ConnectionLimits,
+    throttled: bool,
 }
 
 impl<E: EventSender> ResponseWriter<E> {
-    fn tracking_writer(&mut self) -> TrackingStreamWriter<TokioStreamWriter<&mut SendStream>> {
-        TrackingStreamWriter::new(TokioStreamWriter(&mut self.inner))
+    fn tracking_writer(
+        &mut self,
+    ) -> TrackingStreamWriter<LimitedWriter<'_, TokioStreamWriter<&mut SendStream>, E>> {
+        let request_id = self.request_id();
+        TrackingStreamWriter::new(LimitedWriter::new(
+            TokioStreamWriter(&mut self.inner),
+            &self.limits,
+            &mut self.throttled,
+            &self.events,
+            self.connection_id,
+            request_id,
+        ))
     }
 
     fn connection_id(&self) -> u64 {
@@ -445,8 +1083,7 @@ impl<E: EventSender> ResponseWriter<E> {
         )
     }
 
-    async fn notify_transfer_completed(&self, hash: &Hash, stats: Box<TransferStats>) {
-        info!("transfer completed for {}", hash);
+    async fn notify_transfer_completed(&self, stats: Box<TransferStats>) {
         Self::print_stats(&stats);
         self.events
             .send(Event::TransferCompleted {
@@ -489,7 +1126,12 @@ pub async fn send_blob<D: Map, W: AsyncStreamWriter>(
 ) -> Result<(SentStatus, u64, SliceReaderStats)> {
     match db.get(&hash).await? {
         Some(entry) => {
-            let outboard = entry.outboard().await?;
+            // the store may use a different chunk group size than the protocol
+            let outboard = ReblockOutboard::new(
+                entry.outboard().await?,
+                entry.data_reader().await?,
+                IROH_BLOCK_SIZE,
+            );
             let size = outboard.tree().size();
             let mut file_reader = TrackingSliceReader::new(entry.data_reader().await?);
             writer.write(size.to_le_bytes().as_slice()).await?;
@@ -511,6 +1153,65 @@ pub async fn send_blob<D: Map, W: AsyncStreamWriter>(
     }
 }
 
+/// Send `ranges` of `root` and, for a hash seq, its children.
+///
+/// The data is written in the same order a provider sends it in response to
+/// a get request with the same hash and ranges. Unlike a provider, this does
+/// not skip over missing blobs: the hash of the first blob that is not in
+/// `db` is returned, and nothing is written for it or any later blob.
+pub(crate) async fn send_ranges<D: Map, W: AsyncStreamWriter>(
+    db: &D,
+    root: Hash,
+    ranges: &RangeSpecSeq,
+    mut writer: W,
+) -> Result<Option<Hash>> {
+    let children = OnceCell::new();
+    for (offset, ranges) in ranges.iter_non_empty() {
+        let hash = if offset == 0 {
+            root
+        } else {
+            let Some(children) = children.get_or_try_init(|| load_children(db, root)).await? else {
+                return Ok(Some(root));
+            };
+            match child_at(children, offset) {
+                Some(hash) => hash,
+                None => break,
+            }
+        };
+        debug!("sending ranges '{:?}' of {}", ranges, hash);
+        let (status, _, _) = send_blob(db, hash, ranges, &mut writer).await?;
+        if status == SentStatus::NotFound {
+            return Ok(Some(hash));
+        }
+    }
+    Ok(None)
+}
+
+/// Load the children of the hash seq `root`, or `None` if it is not complete locally.
+async fn load_children<D: Map>(db: &D, root: Hash) -> Result<Option<Vec<Hash>>> {
+    let Some(entry) = db.get(&root).await? else {
+        return Ok(None);
+    };
+    if !entry.is_complete() {
+        return Ok(None);
+    }
+    let (mut stream, _) = parse_hash_seq(entry.data_reader().await?)
+        .await
+        .context("failed to parse hash seq")?;
+    let mut hashes = Vec::new();
+    while let Some(hash) = stream.next().await? {
+        hashes.push(hash);
+    }
+    Ok(Some(hashes))
+}
+
+/// The hash at `offset` in a request for a hash seq with `children`, for an `offset` of at
+/// least 1, since offset 0 is the hash seq itself.
+fn child_at(children: &[Hash], offset: u64) -> Option<Hash> {
+    let index = usize::try_from(offset.checked_sub(1)?).ok()?;
+    children.get(index).copied()
+}
+
 fn encode_error_to_anyhow(err: EncodeError, hash: &Hash) -> anyhow::Error {
     match err {
         EncodeError::LeafHashMismatch(x) => anyhow::Error::from(EncodeError::LeafHashMismatch(x))
@@ -0,0 +1,288 @@
+//! Bandwidth and concurrency limits for serving data.
+use std::collections::HashMap;
+use std::num::NonZeroU64;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use bytes::Bytes;
+use iroh_io::AsyncStreamWriter;
+use iroh_net::NodeId;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+use tracing::debug;
+
+use super::{Event, EventSender};
+
+/// The amount of time worth of data a rate limiter lets through without delay.
+const MAX_BURST: Duration = Duration::from_millis(100);
+
+/// Limits for serving data to other nodes.
+///
+/// The default imposes no limits.
+#[derive(Debug, Clone, Default)]
+pub struct TransferLimits {
+    /// Maximum upload rate in bytes per second, shared by all transfers.
+    pub max_upload_rate: Option<NonZeroU64>,
+    /// Maximum upload rate in bytes per second for all transfers to a single node.
+    pub max_upload_rate_per_peer: Option<NonZeroU64>,
+    /// Maximum number of get and have requests that are served concurrently.
+    ///
+    /// Requests beyond this limit are rejected by resetting the stream with
+    /// [`Closed::TooManyTransfers`](crate::protocol::Closed::TooManyTransfers).
+    pub max_concurrent_transfers: Option<usize>,
+}
+
+/// Enforces [`TransferLimits`] across all connections it is used for.
+///
+/// Clones share the same state, so the same limiter should be passed to
+/// [`handle_connection`](super::handle_connection) for all connections.
+#[derive(Debug, Clone)]
+pub struct TransferLimiter {
+    inner: Arc<TransferLimiterInner>,
+}
+
+#[derive(Debug)]
+struct TransferLimiterInner {
+    limits: TransferLimits,
+    upload_rate: Option<Arc<RateLimiter>>,
+    peer_upload_rates: Mutex<HashMap<NodeId, Weak<RateLimiter>>>,
+    transfers: Option<Arc<Semaphore>>,
+}
+
+impl TransferLimiter {
+    /// Create a new limiter enforcing `limits`.
+    pub fn new(limits: TransferLimits) -> Self {
+        let upload_rate = limits
+            .max_upload_rate
+            .map(|rate| Arc::new(RateLimiter::new(rate)));
+        let transfers = limits
+            .max_concurrent_transfers
+            .map(|n| Arc::new(Semaphore::new(n)));
+        Self {
+            inner: Arc::new(TransferLimiterInner {
+                limits,
+                upload_rate,
+                peer_upload_rates: Default::default(),
+                transfers,
+            }),
+        }
+    }
+
+    /// The limits enforced by this limiter.
+    pub fn limits(&self) -> &TransferLimits {
+        &self.inner.limits
+    }
+
+    /// Get the limits for a connection to `remote`.
+    ///
+    /// The per peer rate is shared by all connections to the same node. If the
+    /// remote node is unknown, it only applies to this connection.
+    pub(super) fn connection(&self, remote: Option<NodeId>) -> ConnectionLimits {
+        let mut rates = Vec::new();
+        if let Some(rate) = &self.inner.upload_rate {
+            rates.push(rate.clone());
+        }
+        if let Some(rate) = self.inner.limits.max_upload_rate_per_peer {
+            let peer_rate = match remote {
+                Some(remote) => {
+                    let mut peers = self.inner.peer_upload_rates.lock().unwrap();
+                    match peers.get(&remote).and_then(Weak::upgrade) {
+                        Some(peer_rate) => peer_rate,
+                        None => {
+                            // drop the entries of nodes we are no longer connected to
+                            peers.retain(|_, rate| rate.strong_count() > 0);
+                            let peer_rate = Arc::new(RateLimiter::new(rate));
+                            peers.insert(remote, Arc::downgrade(&peer_rate));
+                            peer_rate
+                        }
+                    }
+                }
+                None => Arc::new(RateLimiter::new(rate)),
+            };
+            rates.push(peer_rate);
+        }
+        ConnectionLimits {
+            rates,
+            transfers: self.inner.transfers.clone(),
+        }
+    }
+}
+
+/// The limits that apply to a single connection.
+#[derive(Debug, Clone, Default)]
+pub(super) struct ConnectionLimits {
+    rates: Vec<Arc<RateLimiter>>,
+    transfers: Option<Arc<Semaphore>>,
+}
+
+impl ConnectionLimits {
+    /// Try to start a new transfer.
+    ///
+    /// Returns `None` if the maximum number of concurrent transfers is reached.
+    /// The transfer counts against the limit until the permit is dropped.
+    pub(super) fn try_start_transfer(&self) -> Option<TransferPermit> {
+        match &self.transfers {
+            Some(transfers) => {
+                let permit = transfers.clone().try_acquire_owned().ok()?;
+                Some(TransferPermit {
+                    _permit: Some(permit),
+                })
+            }
+            None => Some(TransferPermit { _permit: None }),
+        }
+    }
+
+    /// Reserve sending `len` bytes, returning how long to wait before sending them.
+    fn reserve(&self, len: usize) -> Duration {
+        self.rates
+            .iter()
+            .map(|rate| rate.reserve(len as u64))
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// A running transfer, see [`ConnectionLimits::try_start_transfer`].
+#[derive(Debug)]
+pub(super) struct TransferPermit {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+/// A rate limiter using the generic cell rate algorithm.
+///
+/// Every reservation moves the theoretical arrival time forward by the time it
+/// takes to send the reserved bytes at the configured rate. The caller has to
+/// wait until the theoretical arrival time is no more than [`MAX_BURST`] ahead.
+#[derive(Debug)]
+struct RateLimiter {
+    /// Bytes per second.
+    rate: NonZeroU64,
+    tat: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(rate: NonZeroU64) -> Self {
+        Self {
+            rate,
+            tat: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn reserve(&self, len: u64) -> Duration {
+        let now = Instant::now();
+        let cost = Duration::from_secs_f64(len as f64 / self.rate.get() as f64);
+        let mut tat = self.tat.lock().unwrap();
+        *tat = (*tat).max(now) + cost;
+        tat.duration_since(now).saturating_sub(MAX_BURST)
+    }
+}
+
+/// A writer that enforces the upload rates of a connection.
+///
+/// Sends [`Event::TransferThrottled`] the first time a write of the request is delayed.
+#[derive(Debug)]
+pub(super) struct LimitedWriter<'a, W, E> {
+    inner: W,
+    limits: &'a ConnectionLimits,
+    throttled: &'a mut bool,
+    events: &'a E,
+    connection_id: u64,
+    request_id: u64,
+}
+
+impl<'a, W: AsyncStreamWriter, E: EventSender> LimitedWriter<'a, W, E> {
+    pub(super) fn new(
+        inner: W,
+        limits: &'a ConnectionLimits,
+        throttled: &'a mut bool,
+        events: &'a E,
+        connection_id: u64,
+        request_id: u64,
+    ) -> Self {
+        Self {
+            inner,
+            limits,
+            throttled,
+            events,
+            connection_id,
+            request_id,
+        }
+    }
+
+    async fn wait(&mut self, len: usize) {
+        let delay = self.limits.reserve(len);
+        if delay.is_zero() {
+            return;
+        }
+        if !*self.throttled {
+            *self.throttled = true;
+            debug!("transfer throttled");
+            self.events
+                .send(Event::TransferThrottled {
+                    connection_id: self.connection_id,
+                    request_id: self.request_id,
+                })
+                .await;
+        }
+        tokio::time::sleep(delay).await;
+    }
+}
+
+impl<W: AsyncStreamWriter, E: EventSender> AsyncStreamWriter for LimitedWriter<'_, W, E> {
+    async fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.wait(data.len()).await;
+        self.inner.write(data).await
+    }
+
+    async fn write_bytes(&mut self, data: Bytes) -> std::io::Result<()> {
+        self.wait(data.len()).await;
+        self.inner.write_bytes(data).await
+    }
+
+    async fn sync(&mut self) -> std::io::Result<()> {
+        self.inner.sync().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_limiter() {
+        let rate = RateLimiter::new(NonZeroU64::new(1000).unwrap());
+        // 100 bytes at 1000 bytes per second fit into the burst
+        assert_eq!(rate.reserve(100), Duration::ZERO);
+        // everything beyond has to wait
+        assert_eq!(rate.reserve(1000), Duration::from_secs(1));
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(rate.reserve(100), Duration::from_millis(100));
+        // after being idle, the burst is available again
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(rate.reserve(100), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn transfer_limiter() {
+        let limiter = TransferLimiter::new(TransferLimits {
+            max_upload_rate: None,
+            max_upload_rate_per_peer: NonZeroU64::new(1000),
+            max_concurrent_transfers: Some(1),
+        });
+        let a = iroh_net::key::SecretKey::generate().public();
+        let b = iroh_net::key::SecretKey::generate().public();
+        let a1 = limiter.connection(Some(a));
+        let a2 = limiter.connection(Some(a));
+        let b1 = limiter.connection(Some(b));
+        // connections to the same peer share the rate
+        assert_eq!(a1.reserve(1100), Duration::from_secs(1));
+        assert_eq!(a2.reserve(100), Duration::from_millis(1100));
+        assert_eq!(b1.reserve(100), Duration::ZERO);
+
+        // the concurrency limit is shared by all connections
+        let permit = a1.try_start_transfer().unwrap();
+        assert!(b1.try_start_transfer().is_none());
+        drop(permit);
+        assert!(b1.try_start_transfer().is_some());
+    }
+}
@@ -0,0 +1,106 @@
+//! The sending side of push requests
+//!
+//! A push request is the reverse of a get request: the node that opens the
+//! stream has the data and sends it to the provider, which validates and stores
+//! it. See the [protocol docs](crate::protocol) for details.
+use std::time::Instant;
+
+use anyhow::Result;
+use iroh_io::stats::TrackingStreamWriter;
+use iroh_io::{AsyncStreamWriter, TokioStreamWriter};
+use iroh_net::endpoint::{Connection, ReadError, WriteError};
+
+use crate::get::Stats;
+use crate::protocol::{Closed, PushRequest, Request, MAX_MESSAGE_SIZE};
+use crate::provider::send_ranges;
+use crate::store::Map;
+use crate::Hash;
+
+/// Error when pushing data to a provider.
+#[derive(Debug, thiserror::Error)]
+pub enum PushError {
+    /// The provider rejected the push, or could not validate or store the data.
+    #[error("push rejected by the provider")]
+    Rejected,
+    /// The data to push is not available in the local store.
+    #[error("blob not found: {0}")]
+    NotFound(Hash),
+    /// A generic error
+    #[error("generic: {0}")]
+    Generic(anyhow::Error),
+}
+
+impl From<anyhow::Error> for PushError {
+    fn from(cause: anyhow::Error) -> Self {
+        // writing might fail because the provider stopped the stream
+        match cause.downcast::<std::io::Error>() {
+            Ok(cause) => cause.into(),
+            Err(cause) => Self::Generic(cause),
+        }
+    }
+}
+
+impl From<WriteError> for PushError {
+    fn from(cause: WriteError) -> Self {
+        match cause {
+            WriteError::Stopped(code) if code == Closed::PushRejected.into() => Self::Rejected,
+            cause => Self::Generic(cause.into()),
+        }
+    }
+}
+
+impl From<std::io::Error> for PushError {
+    fn from(cause: std::io::Error) -> Self {
+        match cause
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<WriteError>())
+        {
+            Some(cause) => cause.clone().into(),
+            None => Self::Generic(cause.into()),
+        }
+    }
+}
+
+/// Push the data described by `request` from `db` to the provider at the other
+/// end of `connection`.
+///
+/// Returns once the provider has confirmed that it has received and stored
+/// all data.
+pub async fn push<D: Map>(
+    connection: Connection,
+    db: &D,
+    request: PushRequest,
+) -> Result<Stats, PushError> {
+    let start = Instant::now();
+    let (mut send, mut recv) = connection.open_bi().await.map_err(anyhow::Error::from)?;
+    let request_bytes =
+        postcard::to_stdvec(&Request::Push(request.clone())).map_err(anyhow::Error::from)?;
+    if request_bytes.len() > MAX_MESSAGE_SIZE {
+        return Err(anyhow::anyhow!("request too big").into());
+    }
+    let mut writer = TrackingStreamWriter::new(TokioStreamWriter(&mut send));
+    writer.write(&request_bytes).await?;
+    // send the data in the same order a provider would send it in response to
+    // a get request with the same hash and ranges
+    if let Some(hash) = send_ranges(db, request.hash, &request.ranges, &mut writer).await? {
+        return Err(PushError::NotFound(hash));
+    }
+    let bytes_written = writer.stats().total().size;
+    send.finish().await?;
+    // the provider finishes its side of the stream once the data is stored
+    match recv.read_chunk(8, true).await {
+        Ok(None) => {}
+        Ok(Some(_)) => {
+            return Err(anyhow::anyhow!("received unexpected data from the provider").into());
+        }
+        Err(ReadError::Reset(code)) if code == Closed::PushRejected.into() => {
+            return Err(PushError::Rejected);
+        }
+        Err(cause) => return Err(anyhow::Error::from(cause).into()),
+    }
+    Ok(Stats {
+        bytes_written,
+        bytes_read: 0,
+        elapsed: start.elapsed(),
+    })
+}
@@ -1,14 +1,19 @@
 //! Implementations of blob stores
 use crate::{BlobFormat, Hash, HashAndFormat};
 
+pub mod archive;
 #[cfg(feature = "fs-store")]
 mod bao_file;
+#[cfg(feature = "fs-store")]
+mod encryption;
 pub mod mem;
 mod mutable_mem_storage;
 pub mod readonly_mem;
 
 #[cfg(feature = "fs-store")]
 pub mod fs;
+#[cfg(feature = "s3-store")]
+pub mod s3;
 
 mod traits;
 use tracing::warn;
@@ -21,7 +26,7 @@ fn new_uuid() -> [u8; 16] {
 }
 
 /// Create temp file name based on a 16 byte UUID.
-fn temp_name() -> String {
+pub(crate) fn temp_name() -> String {
     format!("{}.temp", hex::encode(new_uuid()))
 }
 
@@ -0,0 +1,200 @@
+//! Self-describing files containing verified bao encoded data
+//!
+//! An archive contains the bao encoding of selected ranges of a blob, or of a
+//! hash seq and its children, so verified data can be moved without a
+//! connection between nodes, e.g. on a usb stick.
+//!
+//! The layout of an archive is
+//!
+//! - the 8 byte [`MAGIC`],
+//! - the length of the header as a little endian `u32`,
+//! - the postcard encoded [`ArchiveHeader`],
+//! - the data, exactly as a provider would send it in response to a get
+//!   request for the hash and ranges in the header.
+//!
+//! Importing an archive validates and stores the data the same way as data
+//! that is received over the network.
+use anyhow::{ensure, Context};
+use bao_tree::ChunkRanges;
+use iroh_io::{AsyncStreamReader, AsyncStreamWriter};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    protocol::{RangeSpecSeq, MAX_MESSAGE_SIZE},
+    provider::{receive_ranges, send_ranges},
+    store::{Map, Store},
+    util::TempTag,
+    BlobFormat, Hash, HashAndFormat,
+};
+
+/// The magic bytes at the start of every archive, including the format version.
+pub const MAGIC: &[u8; 8] = b"irohbao1";
+
+/// The header of an archive, describing the contained data.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveHeader {
+    /// The root hash.
+    pub hash: Hash,
+    /// The format of the root.
+    pub format: BlobFormat,
+    /// The ranges of the root and, for a hash seq, its children.
+    pub ranges: RangeSpecSeq,
+}
+
+impl ArchiveHeader {
+    /// A header for all the data of a blob, or a hash seq and all its children.
+    pub fn new(content: HashAndFormat) -> Self {
+        let ranges = match content.format {
+            BlobFormat::Raw => RangeSpecSeq::from_ranges([ChunkRanges::all()]),
+            BlobFormat::HashSeq => RangeSpecSeq::all(),
+        };
+        Self {
+            hash: content.hash,
+            format: content.format,
+            ranges,
+        }
+    }
+
+    /// The root hash and format.
+    pub fn hash_and_format(&self) -> HashAndFormat {
+        HashAndFormat {
+            hash: self.hash,
+            format: self.format,
+        }
+    }
+}
+
+/// Write an archive for the data described by `header` from `db` to `writer`.
+///
+/// Fails if any of the described blobs is not in `db`. Incomplete blobs can
+/// be exported as long as the requested ranges are available.
+pub async fn export_archive<D: Map, W: AsyncStreamWriter>(
+    db: &D,
+    header: &ArchiveHeader,
+    mut writer: W,
+) -> anyhow::Result<()> {
+    let header_bytes = postcard::to_stdvec(header)?;
+    writer.write(MAGIC).await?;
+    writer
+        .write(&u32::try_from(header_bytes.len())?.to_le_bytes())
+        .await?;
+    writer.write(&header_bytes).await?;
+    if let Some(hash) = send_ranges(db, header.hash, &header.ranges, &mut writer).await? {
+        anyhow::bail!("blob not found: {hash}");
+    }
+    writer.sync().await?;
+    Ok(())
+}
+
+/// Validate and store the data of an archive read from `reader` in `db`.
+///
+/// Blobs are marked as complete once all their data is available. Returns the
+/// header of the archive, and a temp tag that protects the imported data from
+/// garbage collection from the moment the header is read. The caller should
+/// keep the temp tag until it has tagged the data.
+pub async fn import_archive<D: Store, R: AsyncStreamReader>(
+    db: &D,
+    mut reader: R,
+) -> anyhow::Result<(ArchiveHeader, TempTag)> {
+    let magic = reader.read::<8>().await.context("not an archive")?;
+    ensure!(&magic == MAGIC, "not an archive");
+    let len = u32::from_le_bytes(reader.read::<4>().await?) as usize;
+    ensure!(len <= MAX_MESSAGE_SIZE, "archive header too large");
+    let header_bytes = reader.read_bytes(len).await?;
+    ensure!(header_bytes.len() == len, "archive header truncated");
+    let header: ArchiveHeader = postcard::from_bytes(&header_bytes)?;
+    let temp_tag = db.temp_tag(header.hash_and_format());
+    let mut reader = receive_ranges(db, header.hash, &header.ranges, reader).await?;
+    ensure!(
+        reader.read_bytes(1).await?.is_empty(),
+        "unexpected data at the end of the archive"
+    );
+    Ok((header, temp_tag))
+}
+
+#[cfg(test)]
+mod tests {
+    use bao_tree::ChunkNum;
+    use bytes::Bytes;
+    use iroh_io::AsyncSliceReaderExt;
+
+    use super::*;
+    use crate::{
+        format::collection::Collection,
+        store::{mem, EntryStatus, MapEntry, MapMut, Store},
+    };
+
+    async fn read(db: &mem::Store, hash: &Hash) -> anyhow::Result<Option<Bytes>> {
+        let Some(entry) = db.get(hash).await? else {
+            return Ok(None);
+        };
+        anyhow::ensure!(entry.is_complete(), "incomplete");
+        let data = entry.data_reader().await?.read_to_end().await?;
+        Ok(Some(data))
+    }
+
+    #[tokio::test]
+    async fn export_import_collection() -> testresult::TestResult {
+        let src = mem::Store::new();
+        let a = src
+            .import_bytes(vec![1u8; 100_000].into(), BlobFormat::Raw)
+            .await?;
+        let b = src
+            .import_bytes(Bytes::from_static(b"hello"), BlobFormat::Raw)
+            .await?;
+        let collection: Collection = [("a", *a.hash()), ("b", *b.hash())].into_iter().collect();
+        let root = collection.store(&src).await?;
+
+        let mut archive = Vec::new();
+        let header = ArchiveHeader::new(*root.inner());
+        export_archive(&src, &header, &mut archive).await?;
+        assert_eq!(&archive[..8], MAGIC);
+
+        let dst = mem::Store::new();
+        let (imported, temp_tag) = import_archive(&dst, Bytes::from(archive.clone())).await?;
+        assert_eq!(imported, header);
+        assert_eq!(temp_tag.inner(), root.inner());
+        for hash in [root.hash(), a.hash(), b.hash()] {
+            assert_eq!(read(&dst, hash).await?, read(&src, hash).await?);
+        }
+
+        // corrupted data is rejected
+        let last = archive.len() - 1;
+        archive[last] ^= 1;
+        let dst = mem::Store::new();
+        assert!(import_archive(&dst, Bytes::from(archive)).await.is_err());
+        assert_ne!(dst.entry_status(b.hash()).await?, EntryStatus::Complete);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn export_ranges() -> testresult::TestResult {
+        let src = mem::Store::new();
+        let data = (0..100_000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let tag = src.import_bytes(data.into(), BlobFormat::Raw).await?;
+        let ranges = ChunkRanges::from(ChunkNum(16)..ChunkNum(32));
+        let header = ArchiveHeader {
+            hash: *tag.hash(),
+            format: BlobFormat::Raw,
+            ranges: RangeSpecSeq::from_ranges([ranges]),
+        };
+        let mut archive = Vec::new();
+        export_archive(&src, &header, &mut archive).await?;
+        let mut full = Vec::new();
+        export_archive(&src, &ArchiveHeader::new(*tag.inner()), &mut full).await?;
+        assert!(archive.len() < full.len() / 4);
+
+        // the partial data is validated, but the blob is not complete
+        let dst = mem::Store::new();
+        import_archive(&dst, Bytes::from(archive)).await?;
+        let entry = dst.get(tag.hash()).await?;
+        assert!(entry.map_or(true, |entry| !entry.is_complete()));
+
+        // missing data can not be exported
+        let header = ArchiveHeader::new(HashAndFormat::raw(Hash::new(b"missing")));
+        assert!(export_archive(&src, &header, Vec::new()).await.is_err());
+        // garbage is not an archive
+        assert!(import_archive(&dst, &b"garbage"[..]).await.is_err());
+        Ok(())
+    }
+}
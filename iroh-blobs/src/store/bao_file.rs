@@ -23,7 +23,7 @@ use bao_tree::{
         outboard::PreOrderOutboard,
         sync::{ReadAt, WriteAt},
     },
-    BaoTree,
+    BaoTree, BlockSize,
 };
 use bytes::{Bytes, BytesMut};
 use derive_more::Debug;
@@ -36,7 +36,10 @@ use crate::{
 };
 use iroh_base::hash::Hash;
 
-use super::mutable_mem_storage::{MutableMemStorage, SizeInfo};
+use super::{
+    encryption::{file_stem, BlobFile, EncryptionKey},
+    mutable_mem_storage::{MutableMemStorage, SizeInfo},
+};
 
 /// Data files are stored in 3 files. The data file, the outboard file,
 /// and a sizes file. The sizes file contains the size that the remote side told us
@@ -87,10 +90,10 @@ struct DataPaths {
 pub struct CompleteStorage {
     /// data part, which can be in memory or on disk.
     #[debug("{:?}", data.as_ref().map_mem(|x| x.len()))]
-    pub data: MemOrFile<Bytes, (File, u64)>,
+    pub data: MemOrFile<Bytes, (BlobFile, u64)>,
     /// outboard part, which can be in memory or on disk.
     #[debug("{:?}", outboard.as_ref().map_mem(|x| x.len()))]
-    pub outboard: MemOrFile<Bytes, (File, u64)>,
+    pub outboard: MemOrFile<Bytes, (BlobFile, u64)>,
 }
 
 impl CompleteStorage {
@@ -179,14 +182,14 @@ fn max_offset(batch: &[BaoContentItem]) -> u64 {
 /// A file storage for an incomplete bao file.
 #[derive(Debug)]
 pub struct FileStorage {
-    data: std::fs::File,
-    outboard: std::fs::File,
+    data: BlobFile,
+    outboard: BlobFile,
     sizes: std::fs::File,
 }
 
 impl FileStorage {
     /// Split into data, outboard and sizes files.
-    pub fn into_parts(self) -> (File, File, File) {
+    pub fn into_parts(self) -> (BlobFile, BlobFile, File) {
         (self.data, self.outboard, self.sizes)
     }
 
@@ -203,8 +206,13 @@ impl FileStorage {
         }
     }
 
-    fn write_batch(&mut self, size: u64, batch: &[BaoContentItem]) -> io::Result<()> {
-        let tree = BaoTree::new(size, IROH_BLOCK_SIZE);
+    fn write_batch(
+        &mut self,
+        size: u64,
+        batch: &[BaoContentItem],
+        block_size: BlockSize,
+    ) -> io::Result<()> {
+        let tree = BaoTree::new(size, block_size);
         for item in batch {
             match item {
                 BaoContentItem::Parent(parent) => {
@@ -332,6 +340,7 @@ pub struct BaoFileHandleInner {
     pub(crate) storage: RwLock<BaoFileStorage>,
     config: Arc<BaoFileConfig>,
     hash: Hash,
+    block_size: BlockSize,
 }
 
 /// A cheaply cloneable handle to a bao file, including the hash and the configuration.
@@ -353,6 +362,10 @@ pub struct BaoFileConfig {
     /// Todo: make this async.
     #[debug("{:?}", on_file_create.as_ref().map(|_| ()))]
     on_file_create: Option<CreateCb>,
+    /// Key to encrypt data and outboard files with, if any.
+    encryption: Option<EncryptionKey>,
+    /// Chunk group size for new entries.
+    block_size: BlockSize,
 }
 
 impl BaoFileConfig {
@@ -362,17 +375,53 @@ impl BaoFileConfig {
             dir,
             max_mem,
             on_file_create,
+            encryption: None,
+            block_size: IROH_BLOCK_SIZE,
         }
     }
 
+    /// Use the given chunk group size for new entries.
+    pub fn with_block_size(mut self, block_size: BlockSize) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    /// Encrypt data and outboard files with the given key.
+    pub fn with_encryption(mut self, key: Option<EncryptionKey>) -> Self {
+        self.encryption = key;
+        self
+    }
+
     /// Get the paths for a hash.
-    fn paths(&self, hash: &Hash) -> DataPaths {
+    fn paths(&self, hash: &Hash, block_size: BlockSize) -> DataPaths {
+        let hash = file_stem(self.encryption.as_ref(), hash);
+        let log = block_size.chunk_log();
         DataPaths {
-            data: self.dir.join(format!("{}.data", hash.to_hex())),
-            outboard: self.dir.join(format!("{}.obao4", hash.to_hex())),
-            sizes: self.dir.join(format!("{}.sizes4", hash.to_hex())),
+            data: self.dir.join(format!("{hash}.data")),
+            outboard: self.dir.join(format!("{hash}.obao{log}")),
+            sizes: self.dir.join(format!("{hash}.sizes{log}")),
         }
     }
+
+    /// Open the files for a hash for reading and writing, creating them if
+    /// needed.
+    ///
+    /// The sizes file is never encrypted, since it only contains sizes.
+    fn open_files(&self, hash: &Hash, block_size: BlockSize) -> io::Result<FileStorage> {
+        let paths = self.paths(hash, block_size);
+        let key = self.encryption.as_ref();
+        Ok(FileStorage {
+            data: BlobFile::new(
+                create_read_write(&paths.data)?,
+                key.map(|key| key.data_cipher(hash)),
+            ),
+            outboard: BlobFile::new(
+                create_read_write(&paths.outboard)?,
+                key.map(|key| key.outboard_cipher(hash, block_size)),
+            ),
+            sizes: create_read_write(&paths.sizes)?,
+        })
+    }
 }
 
 /// A reader for a bao file, reading just the data.
@@ -483,27 +532,31 @@ impl BaoFileHandle {
     ///
     /// This will create a new file handle with an empty memory storage.
     /// Since there are very likely to be many of these, we use an arc rwlock
+    ///
+    /// The chunk group size is taken from the config.
     pub fn incomplete_mem(config: Arc<BaoFileConfig>, hash: Hash) -> Self {
         let storage = BaoFileStorage::incomplete_mem();
+        let block_size = config.block_size;
         Self(Arc::new(BaoFileHandleInner {
             storage: RwLock::new(storage),
             config,
             hash,
+            block_size,
         }))
     }
 
     /// Create a new bao file handle with a partial file.
-    pub fn incomplete_file(config: Arc<BaoFileConfig>, hash: Hash) -> io::Result<Self> {
-        let paths = config.paths(&hash);
-        let storage = BaoFileStorage::IncompleteFile(FileStorage {
-            data: create_read_write(&paths.data)?,
-            outboard: create_read_write(&paths.outboard)?,
-            sizes: create_read_write(&paths.sizes)?,
-        });
+    pub fn incomplete_file(
+        config: Arc<BaoFileConfig>,
+        hash: Hash,
+        block_size: BlockSize,
+    ) -> io::Result<Self> {
+        let storage = BaoFileStorage::IncompleteFile(config.open_files(&hash, block_size)?);
         Ok(Self(Arc::new(BaoFileHandleInner {
             storage: RwLock::new(storage),
             config,
             hash,
+            block_size,
         })))
     }
 
@@ -511,14 +564,16 @@ impl BaoFileHandle {
     pub fn new_complete(
         config: Arc<BaoFileConfig>,
         hash: Hash,
-        data: MemOrFile<Bytes, (File, u64)>,
-        outboard: MemOrFile<Bytes, (File, u64)>,
+        data: MemOrFile<Bytes, (BlobFile, u64)>,
+        outboard: MemOrFile<Bytes, (BlobFile, u64)>,
+        block_size: BlockSize,
     ) -> Self {
         let storage = BaoFileStorage::Complete(CompleteStorage { data, outboard });
         Self(Arc::new(BaoFileHandleInner {
             storage: RwLock::new(storage),
             config,
             hash,
+            block_size,
         }))
     }
 
@@ -571,7 +626,7 @@ impl BaoFileHandle {
     /// The outboard for the file.
     pub fn outboard(&self) -> io::Result<PreOrderOutboard<OutboardReader>> {
         let root = self.hash.into();
-        let tree = BaoTree::new(self.current_size()?, IROH_BLOCK_SIZE);
+        let tree = BaoTree::new(self.current_size()?, self.block_size);
         let outboard = self.outboard_reader();
         Ok(PreOrderOutboard {
             root,
@@ -585,6 +640,11 @@ impl BaoFileHandle {
         self.hash
     }
 
+    /// The chunk group size of the outboard.
+    pub fn block_size(&self) -> BlockSize {
+        self.block_size
+    }
+
     /// Create a new writer from the handle.
     pub fn writer(&self) -> BaoFileWriter {
         BaoFileWriter(Some(self.clone()))
@@ -597,25 +657,25 @@ impl BaoFileHandle {
             BaoFileStorage::IncompleteMem(mem) => {
                 // check if we need to switch to file mode, otherwise write to memory
                 if max_offset(batch) <= self.config.max_mem as u64 {
-                    mem.write_batch(size, batch)?;
+                    mem.write_batch(size, batch, self.block_size)?;
                     Ok(HandleChange::None)
                 } else {
-                    // create the paths. This allocates 3 pathbufs, so we do it
+                    // open the files. This allocates 3 pathbufs, so we do it
                     // only when we need to.
-                    let paths = self.config.paths(&self.hash);
+                    let files = self.config.open_files(&self.hash, self.block_size)?;
                     // *first* switch to file mode, *then* write the batch.
                     //
                     // otherwise we might allocate a lot of memory if we get
                     // a write at the end of a very large file.
-                    let mut file_batch = mem.persist(paths)?;
-                    file_batch.write_batch(size, batch)?;
+                    let mut file_batch = mem.persist(files, self.block_size)?;
+                    file_batch.write_batch(size, batch, self.block_size)?;
                     *storage = BaoFileStorage::IncompleteFile(file_batch);
                     Ok(HandleChange::MemToFile)
                 }
             }
             BaoFileStorage::IncompleteFile(file) => {
                 // already in file mode, just write the batch
-                file.write_batch(size, batch)?;
+                file.write_batch(size, batch, self.block_size)?;
                 Ok(HandleChange::None)
             }
             BaoFileStorage::Complete(_) => {
@@ -634,29 +694,32 @@ impl BaoFileHandle {
 
 impl SizeInfo {
     /// Persist into a file where each chunk has its own slot.
-    pub fn persist(&self, mut target: impl WriteAt) -> io::Result<()> {
-        let size_offset = (self.offset >> IROH_BLOCK_SIZE.chunk_log()) << 3;
+    pub fn persist(&self, mut target: impl WriteAt, block_size: BlockSize) -> io::Result<()> {
+        let size_offset = (self.offset >> block_size.chunk_log()) << 3;
         target.write_all_at(size_offset, self.size.to_le_bytes().as_slice())?;
         Ok(())
     }
 
     /// Convert to a vec in slot format.
-    pub fn to_vec(&self) -> Vec<u8> {
+    pub fn to_vec(&self, block_size: BlockSize) -> Vec<u8> {
         let mut res = Vec::new();
-        self.persist(&mut res).expect("io error writing to vec");
+        self.persist(&mut res, block_size)
+            .expect("io error writing to vec");
         res
     }
 }
 
 impl MutableMemStorage {
-    /// Persist the batch to disk, creating a FileBatch.
-    fn persist(&self, paths: DataPaths) -> io::Result<FileStorage> {
-        let mut data = create_read_write(&paths.data)?;
-        let mut outboard = create_read_write(&paths.outboard)?;
-        let mut sizes = create_read_write(&paths.sizes)?;
+    /// Persist the batch to disk, into the given freshly opened files.
+    fn persist(&self, files: FileStorage, block_size: BlockSize) -> io::Result<FileStorage> {
+        let FileStorage {
+            mut data,
+            mut outboard,
+            mut sizes,
+        } = files;
         self.data.persist(&mut data)?;
         self.outboard.persist(&mut outboard)?;
-        self.sizes.persist(&mut sizes)?;
+        self.sizes.persist(&mut sizes, block_size)?;
         data.sync_all()?;
         outboard.sync_all()?;
         sizes.sync_all()?;
@@ -716,6 +779,12 @@ impl BaoBatchWriter for BaoFileWriter {
         self.0 = Some(handle);
         res
     }
+
+    fn block_size(&self) -> BlockSize {
+        self.0
+            .as_ref()
+            .map_or(IROH_BLOCK_SIZE, |handle| handle.block_size())
+    }
 }
 
 #[cfg(test)]
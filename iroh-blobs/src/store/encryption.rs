@@ -0,0 +1,276 @@
+//! Encryption at rest for the file system store.
+//!
+//! The data and outboard of each blob are encrypted with a keystream that is
+//! derived from the store key, the hash of the blob, and the part (data, or
+//! outboard and its block size). The keystream is the extendable output of a keyed blake3 hash,
+//! so it can be positioned at any offset. This means that any range of a file
+//! can be read or written without touching the rest of the file, and that the
+//! encrypted file has exactly the same size as the plaintext.
+//!
+//! Reusing the keystream for the same hash is fine, since the plaintext at a
+//! given offset of a given blob is always the same. For the same reason, the
+//! inlined data and outboard in the database are byte for byte identical to
+//! what would be stored in the corresponding file.
+//!
+//! Hashes always refer to the plaintext, so nothing changes on the wire.
+//!
+//! File names are derived from a keyed hash of the blob hash, so a listing of
+//! the data directory does not reveal which blobs the store contains. The
+//! database does: its tables are keyed by the plaintext hash, and the tags,
+//! including their names and the hashes they point to, are stored in the clear.
+//! Only the content of blobs is protected. The size of each blob is visible
+//! from the file sizes and the database, and so is the number of blobs. The
+//! keystream does not authenticate the content either:
+//! integrity relies on the bao verification of the data against the outboard
+//! and the hash whenever data is sent or validated.
+use std::{borrow::Cow, fmt, fs::File, io};
+
+use bao_tree::{
+    blake3,
+    io::sync::{ReadAt, Size, WriteAt},
+    BlockSize,
+};
+use iroh_base::hash::Hash;
+
+/// Context string for deriving the key id that is stored in the database.
+const KEY_ID_CONTEXT: &str = "iroh-blobs 2024-07-01 encryption key id";
+
+/// Context string for deriving the key for file names.
+const FILE_NAME_CONTEXT: &str = "iroh-blobs 2024-07-01 encryption file names";
+
+/// A secret key to encrypt the content of a file system store at rest.
+///
+/// The key must be the same every time the store is opened. There is no way
+/// to recover the content of a store if the key is lost.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; 32]);
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+impl From<[u8; 32]> for EncryptionKey {
+    fn from(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl EncryptionKey {
+    /// Create a key from 32 secret bytes.
+    pub fn new(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Generate a new random key.
+    pub fn generate() -> Self {
+        Self(rand::random())
+    }
+
+    /// The secret bytes of the key.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// A public identifier for the key, used to detect opening a store with
+    /// the wrong key.
+    pub(crate) fn id(&self) -> [u8; 32] {
+        blake3::derive_key(KEY_ID_CONTEXT, &self.0)
+    }
+
+    /// The stem of the file names of a blob, the hex encoded keyed hash of its hash.
+    fn file_stem(&self, hash: &Hash) -> String {
+        let key = blake3::derive_key(FILE_NAME_CONTEXT, &self.0);
+        blake3::keyed_hash(&key, hash.as_bytes())
+            .to_hex()
+            .to_string()
+    }
+
+    /// The cipher for the data part of a blob.
+    pub(crate) fn data_cipher(&self, hash: &Hash) -> BlobCipher {
+        self.cipher(hash, b"d")
+    }
+
+    /// The cipher for the outboard part of a blob with the given block size.
+    ///
+    /// Outboards of the same blob with different block sizes can exist at the
+    /// same time, so they must not share a keystream.
+    pub(crate) fn outboard_cipher(&self, hash: &Hash, block_size: BlockSize) -> BlobCipher {
+        self.cipher(hash, &[b'o', block_size.chunk_log()])
+    }
+
+    fn cipher(&self, hash: &Hash, part: &[u8]) -> BlobCipher {
+        let mut hasher = blake3::Hasher::new_keyed(&self.0);
+        hasher.update(hash.as_bytes());
+        hasher.update(part);
+        BlobCipher(hasher)
+    }
+}
+
+/// The cipher for one part of a blob.
+///
+/// Since this is a stream cipher, encryption and decryption are the same
+/// operation.
+#[derive(Clone)]
+pub(crate) struct BlobCipher(blake3::Hasher);
+
+impl fmt::Debug for BlobCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("BlobCipher(..)")
+    }
+}
+
+impl BlobCipher {
+    /// Apply the keystream at the given offset to a buffer.
+    pub fn apply(&self, offset: u64, buf: &mut [u8]) {
+        let mut reader = self.0.finalize_xof();
+        reader.set_position(offset);
+        let mut keystream = [0u8; 4096];
+        for chunk in buf.chunks_mut(keystream.len()) {
+            let keystream = &mut keystream[..chunk.len()];
+            reader.fill(keystream);
+            for (b, k) in chunk.iter_mut().zip(keystream.iter()) {
+                *b ^= k;
+            }
+        }
+    }
+}
+
+/// The stem of the file names of a blob.
+///
+/// Without encryption, this is the hex encoded hash.
+pub(crate) fn file_stem(key: Option<&EncryptionKey>, hash: &Hash) -> String {
+    match key {
+        Some(key) => key.file_stem(hash),
+        None => hash.to_hex(),
+    }
+}
+
+/// Apply an optional cipher to an entire in memory blob part.
+///
+/// Without a cipher, the data is returned as is.
+pub(crate) fn apply_cipher(cipher: Option<BlobCipher>, data: &[u8]) -> Cow<'_, [u8]> {
+    match cipher {
+        Some(cipher) => {
+            let mut data = data.to_vec();
+            cipher.apply(0, &mut data);
+            Cow::Owned(data)
+        }
+        None => Cow::Borrowed(data),
+    }
+}
+
+/// The data or outboard file of a blob, encrypted if a cipher is given.
+///
+/// All reads and writes go through the cipher, so users of this see the
+/// plaintext.
+#[derive(Debug)]
+pub struct BlobFile {
+    file: File,
+    cipher: Option<BlobCipher>,
+}
+
+impl BlobFile {
+    /// Wrap a file, encrypting and decrypting with the given cipher.
+    pub(crate) fn new(file: File, cipher: Option<BlobCipher>) -> Self {
+        Self { file, cipher }
+    }
+
+    /// Wrap a file that is not encrypted.
+    pub(crate) fn plain(file: File) -> Self {
+        Self::new(file, None)
+    }
+
+    /// Metadata of the underlying file.
+    pub fn metadata(&self) -> io::Result<std::fs::Metadata> {
+        self.file.metadata()
+    }
+
+    /// Sync the underlying file.
+    pub fn sync_all(&self) -> io::Result<()> {
+        self.file.sync_all()
+    }
+}
+
+impl ReadAt for BlobFile {
+    fn read_at(&self, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.file.read_at(pos, buf)?;
+        if let Some(cipher) = &self.cipher {
+            cipher.apply(pos, &mut buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+impl WriteAt for BlobFile {
+    fn write_at(&mut self, pos: u64, buf: &[u8]) -> io::Result<usize> {
+        match &self.cipher {
+            Some(cipher) => {
+                let mut buf = buf.to_vec();
+                cipher.apply(pos, &mut buf);
+                self.file.write_at(pos, &buf)
+            }
+            None => self.file.write_at(pos, buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        WriteAt::flush(&mut self.file)
+    }
+}
+
+impl Size for BlobFile {
+    fn size(&self) -> io::Result<Option<u64>> {
+        self.file.size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keystream_is_seekable() {
+        let key = EncryptionKey::generate();
+        let cipher = key.data_cipher(&Hash::new(b"test"));
+        let data = (0..10000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let encrypted = apply_cipher(Some(cipher.clone()), &data);
+        assert_ne!(&encrypted[..], &data[..]);
+        // decrypting any range at its offset gives the plaintext
+        for (start, end) in [(0, 10000), (1, 2), (4095, 4097), (5000, 9999)] {
+            let mut buf = encrypted[start..end].to_vec();
+            cipher.apply(start as u64, &mut buf);
+            assert_eq!(buf, &data[start..end]);
+        }
+        // data and outboard use different keystreams
+        let outboard = key.outboard_cipher(&Hash::new(b"test"), BlockSize::from_chunk_log(4));
+        let outboard = apply_cipher(Some(outboard), &data);
+        assert_ne!(outboard, encrypted);
+        // outboards with different block sizes use different keystreams
+        let other = key.outboard_cipher(&Hash::new(b"test"), BlockSize::from_chunk_log(0));
+        assert_ne!(apply_cipher(Some(other), &data), outboard);
+    }
+
+    #[test]
+    fn blob_file_roundtrip() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("test.data");
+        let key = EncryptionKey::generate();
+        let cipher = key.data_cipher(&Hash::new(b"test"));
+        let data = vec![0xaau8; 20000];
+        let mut file = BlobFile::new(File::create(&path)?, Some(cipher.clone()));
+        // write out of order, like a partial download would
+        file.write_all_at(16384, &data[16384..])?;
+        file.write_all_at(0, &data[..16384])?;
+        drop(file);
+        let raw = std::fs::read(&path)?;
+        assert_eq!(raw.len(), data.len());
+        assert_ne!(raw, data);
+        let file = BlobFile::new(File::open(&path)?, Some(cipher));
+        let mut buf = vec![0u8; 1000];
+        file.read_exact_at(10000, &mut buf)?;
+        assert_eq!(buf, &data[10000..11000]);
+        Ok(())
+    }
+}
@@ -50,6 +50,19 @@
 //! The inline_outboard table contains the actual outboard for complete entries.
 //! The tags table contains a mapping from tag to hash.
 //!
+//! Encryption:
+//!
+//! If the store is opened with an [`EncryptionKey`], owned data and outboard
+//! files as well as the inline_data and inline_outboard tables are encrypted.
+//! For a given hash, the inlined bytes are identical to the content of the
+//! corresponding file, so moving data between the database and the file system
+//! does not require decrypting it. External files are not owned by the store
+//! and are therefore never used when encryption is enabled. The owned files
+//! are named after a keyed hash of the blob hash instead of the hash itself.
+//! The database is not encrypted apart from the inlined bytes: its tables are
+//! keyed by the plaintext hash, and tags are stored in the clear, so anyone who
+//! can read the database can list all hashes and tags in the store.
+//!
 //! Design:
 //!
 //! The redb store is accessed in a single threaded way by an actor that runs
@@ -72,17 +85,20 @@ use std::{
     time::{Duration, SystemTime},
 };
 
-use bao_tree::io::{
-    fsm::Outboard,
-    outboard::PreOrderOutboard,
-    sync::{ReadAt, Size},
+use bao_tree::{
+    io::{
+        fsm::Outboard,
+        outboard::PreOrderOutboard,
+        sync::{ReadAt, Size},
+    },
+    BaoTree, BlockSize,
 };
 use bytes::Bytes;
 use futures_lite::{Stream, StreamExt};
 
 use iroh_base::hash::{BlobFormat, Hash, HashAndFormat};
 use iroh_io::AsyncSliceReader;
-use redb::{AccessGuard, DatabaseError, ReadableTable, StorageError};
+use redb::{AccessGuard, DatabaseError, ReadableTable, ReadableTableMetadata, StorageError};
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
 use tokio::{io::AsyncWriteExt, sync::oneshot};
@@ -101,9 +117,10 @@ mod validate;
 use crate::{
     store::{
         bao_file::{BaoFileStorage, CompleteStorage},
+        encryption::{apply_cipher, file_stem, BlobCipher, BlobFile},
         fs::{
             tables::BaoFilePart,
-            util::{overwrite_and_sync, read_and_remove, ProgressReader},
+            util::{copy_with_cipher, overwrite_and_sync, read_and_remove, ProgressReader},
         },
     },
     util::{
@@ -111,7 +128,7 @@ use crate::{
             BoxedProgressSender, IdGenerator, IgnoreProgressSender, ProgressSendError,
             ProgressSender,
         },
-        raw_outboard_size, MemOrFile, TagCounter, TagDrop,
+        MemOrFile, TagCounter, TagDrop, TagUpdate,
     },
     Tag, TempTag, IROH_BLOCK_SIZE,
 };
@@ -121,10 +138,12 @@ use self::{tables::DeleteSet, util::PeekableFlumeReceiver};
 
 use self::test_support::EntryData;
 
+pub use super::encryption::EncryptionKey;
+
 use super::{
     bao_file::{BaoFileConfig, BaoFileHandle, BaoFileHandleWeak, CreateCb},
-    temp_name, BaoBatchWriter, BaoBlobSize, ConsistencyCheckProgress, EntryStatus, ExportMode,
-    ExportProgressCb, ImportMode, ImportProgress, Map, TempCounterMap,
+    temp_name, BaoBatchWriter, BaoBlobSize, BlobUsage, ConsistencyCheckProgress, EntryStats,
+    EntryStatus, ExportMode, ExportProgressCb, ImportMode, ImportProgress, Map, TempCounterMap,
 };
 
 /// Location of the data.
@@ -201,7 +220,7 @@ impl<I, E> DataLocation<I, E> {
 /// Outboards are implementation specific to the store and as such are always owned.
 ///
 /// Only complete outboards can be inlined.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub(crate) enum OutboardLocation<I = ()> {
     /// Outboard is in the inline_outboard table.
     Inline(I),
@@ -387,18 +406,6 @@ impl PathOptions {
         }
     }
 
-    fn owned_data_path(&self, hash: &Hash) -> PathBuf {
-        self.data_path.join(format!("{}.data", hash.to_hex()))
-    }
-
-    fn owned_outboard_path(&self, hash: &Hash) -> PathBuf {
-        self.data_path.join(format!("{}.obao4", hash.to_hex()))
-    }
-
-    fn owned_sizes_path(&self, hash: &Hash) -> PathBuf {
-        self.data_path.join(format!("{}.sizes4", hash.to_hex()))
-    }
-
     fn temp_file_name(&self) -> PathBuf {
         self.temp_path.join(temp_name())
     }
@@ -437,6 +444,52 @@ pub struct Options {
     pub inline: InlineOptions,
     /// Transaction batching options.
     pub batch: BatchOptions,
+    /// Key to encrypt data and outboards at rest, if any.
+    ///
+    /// The key must be given every time the store is opened, and can not be
+    /// added to or removed from an existing store. Temp files used while
+    /// importing data are not encrypted, and are removed once the import is
+    /// complete.
+    pub encryption: Option<EncryptionKey>,
+    /// Chunk group size for the outboards of new entries.
+    ///
+    /// Larger chunk groups make outboards smaller, but data can only be
+    /// validated and stored in units of a chunk group. The chunk group size is
+    /// recorded per entry, so existing entries keep theirs when this changes.
+    /// Data is always sent and received with [`IROH_BLOCK_SIZE`] and
+    /// converted as needed.
+    pub block_size: BlockSize,
+}
+
+impl Options {
+    fn owned_data_path(&self, hash: &Hash) -> PathBuf {
+        let stem = file_stem(self.encryption.as_ref(), hash);
+        self.path.data_path.join(format!("{stem}.data"))
+    }
+
+    fn owned_outboard_path(&self, hash: &Hash, block_size: BlockSize) -> PathBuf {
+        let stem = file_stem(self.encryption.as_ref(), hash);
+        let log = block_size.chunk_log();
+        self.path.data_path.join(format!("{stem}.obao{log}"))
+    }
+
+    fn owned_sizes_path(&self, hash: &Hash, block_size: BlockSize) -> PathBuf {
+        let stem = file_stem(self.encryption.as_ref(), hash);
+        let log = block_size.chunk_log();
+        self.path.data_path.join(format!("{stem}.sizes{log}"))
+    }
+
+    /// The cipher for the data of a blob, if encryption is enabled.
+    fn data_cipher(&self, hash: &Hash) -> Option<BlobCipher> {
+        self.encryption.as_ref().map(|key| key.data_cipher(hash))
+    }
+
+    /// The cipher for the outboard of a blob, if encryption is enabled.
+    fn outboard_cipher(&self, hash: &Hash, block_size: BlockSize) -> Option<BlobCipher> {
+        self.encryption
+            .as_ref()
+            .map(|key| key.outboard_cipher(hash, block_size))
+    }
 }
 
 #[derive(derive_more::Debug)]
@@ -508,6 +561,8 @@ pub(crate) struct Import {
     /// Outboard without length prefix
     #[debug("{:?}", outboard.as_ref().map(|x| x.len()))]
     outboard: Option<Vec<u8>>,
+    /// Chunk group size of the outboard
+    block_size: BlockSize,
 }
 
 #[derive(derive_more::Debug)]
@@ -611,6 +666,7 @@ pub(crate) enum ActorMessage {
     },
     /// Bulk query method: get the entire tags table
     Tags {
+        prefix: Tag,
         #[debug(skip)]
         filter: FilterPredicate<Tag, HashAndFormat>,
         #[allow(clippy::type_complexity)]
@@ -618,17 +674,42 @@ pub(crate) enum ActorMessage {
             ActorResult<Vec<std::result::Result<(Tag, HashAndFormat), StorageError>>>,
         >,
     },
+    /// Bulk query method: get the size and last access time of all blobs
+    BlobUsage {
+        #[allow(clippy::type_complexity)]
+        tx: oneshot::Sender<ActorResult<Vec<std::result::Result<BlobUsage, StorageError>>>>,
+    },
+    /// Bulk query method: get the counts and sizes of all entries
+    EntryStats {
+        tx: oneshot::Sender<ActorResult<EntryStats>>,
+    },
     /// Modification method: set a tag to a value, or remove it.
     SetTag {
         tag: Tag,
         value: Option<HashAndFormat>,
+        ttl: Option<Duration>,
         tx: oneshot::Sender<ActorResult<()>>,
     },
+    /// Modification method: atomically apply a batch of tag changes.
+    UpdateTags {
+        updates: Vec<TagUpdate>,
+        tx: oneshot::Sender<ActorResult<bool>>,
+    },
     /// Modification method: create a new unique tag and set it to a value.
     CreateTag {
         hash: HashAndFormat,
+        ttl: Option<Duration>,
         tx: oneshot::Sender<ActorResult<Tag>>,
     },
+    /// Modification method: remove all expired tags.
+    RemoveExpiredTags {
+        tx: oneshot::Sender<ActorResult<Vec<(Tag, HashAndFormat)>>>,
+    },
+    /// Modification method: delete all tags starting with a prefix.
+    DeleteTagsWithPrefix {
+        prefix: Tag,
+        tx: oneshot::Sender<ActorResult<Vec<(Tag, HashAndFormat)>>>,
+    },
     /// Modification method: unconditional delete the data for a number of hashes
     Delete {
         hashes: Vec<Hash>,
@@ -667,6 +748,8 @@ impl ActorMessage {
             | Self::EntryStatus { .. }
             | Self::Blobs { .. }
             | Self::Tags { .. }
+            | Self::BlobUsage { .. }
+            | Self::EntryStats { .. }
             | Self::GcStart { .. }
             | Self::GetFullEntryState { .. }
             | Self::Dump => MessageCategory::ReadOnly,
@@ -675,7 +758,10 @@ impl ActorMessage {
             | Self::OnMemSizeExceeded { .. }
             | Self::OnComplete { .. }
             | Self::SetTag { .. }
+            | Self::UpdateTags { .. }
             | Self::CreateTag { .. }
+            | Self::RemoveExpiredTags { .. }
+            | Self::DeleteTagsWithPrefix { .. }
             | Self::SetFullEntryState { .. }
             | Self::Delete { .. } => MessageCategory::ReadWrite,
             Self::UpdateInlineOptions { .. }
@@ -724,6 +810,22 @@ impl Store {
             path: PathOptions::new(path),
             inline: Default::default(),
             batch: Default::default(),
+            encryption: None,
+            block_size: IROH_BLOCK_SIZE,
+        };
+        Self::new(db_path, options).await
+    }
+
+    /// Load or create a new store that is encrypted at rest with the given key.
+    pub async fn load_encrypted(root: impl AsRef<Path>, key: EncryptionKey) -> io::Result<Self> {
+        let path = root.as_ref();
+        let db_path = path.join("blobs.db");
+        let options = Options {
+            path: PathOptions::new(path),
+            inline: Default::default(),
+            batch: Default::default(),
+            encryption: Some(key),
+            block_size: IROH_BLOCK_SIZE,
         };
         Self::new(db_path, options).await
     }
@@ -776,7 +878,8 @@ struct StoreInner {
     tx: flume::Sender<ActorMessage>,
     temp: Arc<RwLock<TempCounterMap>>,
     handle: Option<std::thread::JoinHandle<()>>,
-    path_options: Arc<PathOptions>,
+    options: Arc<Options>,
+    block_size: BlockSize,
 }
 
 impl TagDrop for RwLock<TempCounterMap> {
@@ -822,7 +925,8 @@ impl StoreInner {
             tx,
             temp,
             handle: Some(handle),
-            path_options: Arc::new(options.path),
+            block_size: options.block_size,
+            options: Arc::new(options),
         })
     }
 
@@ -888,12 +992,12 @@ impl StoreInner {
         Ok(res)
     }
 
-    async fn tags(&self) -> OuterResult<Vec<io::Result<(Tag, HashAndFormat)>>> {
+    async fn tags(&self, prefix: Tag) -> OuterResult<Vec<io::Result<(Tag, HashAndFormat)>>> {
         let (tx, rx) = oneshot::channel();
         let filter: FilterPredicate<Tag, HashAndFormat> =
             Box::new(|_i, k, v| Some((k.value(), v.value())));
         self.tx
-            .send_async(ActorMessage::Tags { filter, tx })
+            .send_async(ActorMessage::Tags { prefix, filter, tx })
             .await?;
         let tags = rx.await?;
         // transform the internal error type into io::Error
@@ -904,18 +1008,69 @@ impl StoreInner {
         Ok(tags)
     }
 
-    async fn set_tag(&self, tag: Tag, value: Option<HashAndFormat>) -> OuterResult<()> {
+    async fn blob_usage(&self) -> OuterResult<Vec<io::Result<BlobUsage>>> {
+        let (tx, rx) = oneshot::channel();
+        self.tx.send_async(ActorMessage::BlobUsage { tx }).await?;
+        let usage = rx.await?;
+        let usage = usage?
+            .into_iter()
+            .map(|r| r.map_err(|e| ActorError::from(e).into()))
+            .collect();
+        Ok(usage)
+    }
+
+    async fn entry_stats(&self) -> OuterResult<EntryStats> {
+        let (tx, rx) = oneshot::channel();
+        self.tx.send_async(ActorMessage::EntryStats { tx }).await?;
+        Ok(rx.await??)
+    }
+
+    async fn set_tag(
+        &self,
+        tag: Tag,
+        value: Option<HashAndFormat>,
+        ttl: Option<Duration>,
+    ) -> OuterResult<()> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send_async(ActorMessage::SetTag {
+                tag,
+                value,
+                ttl,
+                tx,
+            })
+            .await?;
+        Ok(rx.await??)
+    }
+
+    async fn update_tags(&self, updates: Vec<TagUpdate>) -> OuterResult<bool> {
         let (tx, rx) = oneshot::channel();
         self.tx
-            .send_async(ActorMessage::SetTag { tag, value, tx })
+            .send_async(ActorMessage::UpdateTags { updates, tx })
             .await?;
         Ok(rx.await??)
     }
 
-    async fn create_tag(&self, hash: HashAndFormat) -> OuterResult<Tag> {
+    async fn create_tag(&self, hash: HashAndFormat, ttl: Option<Duration>) -> OuterResult<Tag> {
         let (tx, rx) = oneshot::channel();
         self.tx
-            .send_async(ActorMessage::CreateTag { hash, tx })
+            .send_async(ActorMessage::CreateTag { hash, ttl, tx })
+            .await?;
+        Ok(rx.await??)
+    }
+
+    async fn remove_expired_tags(&self) -> OuterResult<Vec<(Tag, HashAndFormat)>> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send_async(ActorMessage::RemoveExpiredTags { tx })
+            .await?;
+        Ok(rx.await??)
+    }
+
+    async fn delete_tags_with_prefix(&self, prefix: Tag) -> OuterResult<Vec<(Tag, HashAndFormat)>> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .send_async(ActorMessage::DeleteTagsWithPrefix { prefix, tx })
             .await?;
         Ok(rx.await??)
     }
@@ -1128,13 +1283,13 @@ impl StoreInner {
                 let span = trace_span!("outboard.compute", path = %path.display());
                 let _guard = span.enter();
                 let file = std::fs::File::open(path)?;
-                compute_outboard(file, data_size, move |offset| {
+                compute_outboard(file, data_size, self.block_size, move |offset| {
                     Ok(progress2.try_send(ImportProgress::OutboardProgress { id, offset })?)
                 })?
             }
             MemOrFile::Mem(bytes) => {
                 // todo: progress? usually this is will be small enough that progress might not be needed.
-                compute_outboard(bytes, data_size, |_| Ok(()))?
+                compute_outboard(bytes, data_size, self.block_size, |_| Ok(()))?
             }
         };
         progress.blocking_send(ImportProgress::OutboardDone { id, hash })?;
@@ -1149,6 +1304,7 @@ impl StoreInner {
                 source: file,
                 outboard,
                 data_size,
+                block_size: self.block_size,
             },
             tx,
         })?;
@@ -1156,7 +1312,7 @@ impl StoreInner {
     }
 
     fn temp_file_name(&self) -> PathBuf {
-        self.path_options.temp_file_name()
+        self.options.path.temp_file_name()
     }
 
     async fn shutdown(&self) {
@@ -1181,6 +1337,8 @@ impl Drop for StoreInner {
 struct ActorState {
     handles: BTreeMap<Hash, BaoFileHandleWeak>,
     protected: BTreeSet<Hash>,
+    /// Last access times, only tracked in memory.
+    last_access: BTreeMap<Hash, SystemTime>,
     temp: Arc<RwLock<TempCounterMap>>,
     msgs: flume::Receiver<ActorMessage>,
     create_options: Arc<BaoFileConfig>,
@@ -1316,8 +1474,23 @@ impl super::ReadableStore for Store {
         Ok(Box::new(self.0.partial_blobs().await?.into_iter()))
     }
 
+    async fn blob_usage(&self) -> io::Result<super::DbIter<BlobUsage>> {
+        Ok(Box::new(self.0.blob_usage().await?.into_iter()))
+    }
+
+    async fn entry_stats(&self) -> io::Result<EntryStats> {
+        Ok(self.0.entry_stats().await?)
+    }
+
     async fn tags(&self) -> io::Result<super::DbIter<(Tag, HashAndFormat)>> {
-        Ok(Box::new(self.0.tags().await?.into_iter()))
+        Ok(Box::new(self.0.tags(Tag(Bytes::new())).await?.into_iter()))
+    }
+
+    async fn tags_with_prefix(
+        &self,
+        prefix: Tag,
+    ) -> io::Result<super::DbIter<(Tag, HashAndFormat)>> {
+        Ok(Box::new(self.0.tags(prefix).await?.into_iter()))
     }
 
     fn temp_tags(&self) -> Box<dyn Iterator<Item = HashAndFormat> + Send + Sync + 'static> {
@@ -1403,12 +1576,29 @@ impl super::Store for Store {
         .await??)
     }
 
-    async fn set_tag(&self, name: Tag, hash: Option<HashAndFormat>) -> io::Result<()> {
-        Ok(self.0.set_tag(name, hash).await?)
+    async fn set_tag(
+        &self,
+        name: Tag,
+        hash: Option<HashAndFormat>,
+        ttl: Option<Duration>,
+    ) -> io::Result<()> {
+        Ok(self.0.set_tag(name, hash, ttl).await?)
+    }
+
+    async fn update_tags(&self, updates: Vec<TagUpdate>) -> io::Result<bool> {
+        Ok(self.0.update_tags(updates).await?)
+    }
+
+    async fn create_tag(&self, hash: HashAndFormat, ttl: Option<Duration>) -> io::Result<Tag> {
+        Ok(self.0.create_tag(hash, ttl).await?)
     }
 
-    async fn create_tag(&self, hash: HashAndFormat) -> io::Result<Tag> {
-        Ok(self.0.create_tag(hash).await?)
+    async fn remove_expired_tags(&self) -> io::Result<Vec<(Tag, HashAndFormat)>> {
+        Ok(self.0.remove_expired_tags().await?)
+    }
+
+    async fn delete_tags_with_prefix(&self, prefix: Tag) -> io::Result<Vec<(Tag, HashAndFormat)>> {
+        Ok(self.0.delete_tags_with_prefix(prefix).await?)
     }
 
     async fn delete(&self, hashes: Vec<Hash>) -> io::Result<()> {
@@ -1448,7 +1638,9 @@ impl Actor {
         // create tables and drop them just to create them.
         let mut t = Default::default();
         let tables = Tables::new(&txn, &mut t)?;
+        let is_empty = tables.blobs.is_empty()?;
         drop(tables);
+        check_encryption_key(&txn, options.encryption.as_ref(), is_empty)?;
         txn.commit()?;
         // make the channel relatively large. there are some messages that don't
         // require a response, it's fine if they pile up a bit.
@@ -1464,7 +1656,9 @@ impl Actor {
             Arc::new(options.path.data_path.clone()),
             16 * 1024,
             Some(on_file_create),
-        );
+        )
+        .with_encryption(options.encryption.clone())
+        .with_block_size(options.block_size);
         Ok((
             Self {
                 db,
@@ -1472,6 +1666,7 @@ impl Actor {
                     temp,
                     handles: BTreeMap::new(),
                     protected: BTreeSet::new(),
+                    last_access: BTreeMap::new(),
                     msgs: rx,
                     options,
                     create_options: Arc::new(create_options),
@@ -1528,7 +1723,7 @@ impl Actor {
                     }
                     drop(tables);
                     txn.commit()?;
-                    delete_after_commit.apply_and_clear(&self.state.options.path);
+                    delete_after_commit.apply_and_clear(&self.state.options);
                     tracing::debug!("write transaction committed");
                 }
             }
@@ -1569,22 +1764,24 @@ impl ActorState {
         // and return a complete entry.
         let entry = entry.value();
         let config = self.create_options.clone();
+        let block_size = entry_block_size(tables, &hash)?;
         let handle = match entry {
             EntryState::Complete {
                 data_location,
                 outboard_location,
             } => {
-                let data = load_data(tables, &self.options.path, data_location, &hash)?;
+                let data = load_data(tables, &self.options, data_location, &hash)?;
                 let outboard = load_outboard(
                     tables,
-                    &self.options.path,
+                    &self.options,
                     outboard_location,
                     data.size(),
                     &hash,
+                    block_size,
                 )?;
-                BaoFileHandle::new_complete(config, hash, data, outboard)
+                BaoFileHandle::new_complete(config, hash, data, outboard, block_size)
             }
-            EntryState::Partial { .. } => BaoFileHandle::incomplete_file(config, hash)?,
+            EntryState::Partial { .. } => BaoFileHandle::incomplete_file(config, hash, block_size)?,
         };
         self.handles.insert(hash, handle.downgrade());
         Ok(Some(handle))
@@ -1618,17 +1815,28 @@ impl ActorState {
                         ActorError::Inconsistent("inline data not found".to_owned())
                     })?;
                     tracing::trace!("exporting inline data to {}", target.display());
-                    tx.send(std::fs::write(&target, data.value()).map_err(|e| e.into()))
+                    let data =
+                        apply_cipher(self.options.data_cipher(temp_tag.hash()), data.value());
+                    tx.send(std::fs::write(&target, data).map_err(|e| e.into()))
                         .ok();
                 }
                 DataLocation::Owned(size) => {
-                    let path = self.options.path.owned_data_path(temp_tag.hash());
+                    let path = self.options.owned_data_path(temp_tag.hash());
+                    let cipher = self.options.data_cipher(temp_tag.hash());
+                    // an encrypted file can not be handed out as is, so always copy
+                    let mode = if cipher.is_some() {
+                        ExportMode::Copy
+                    } else {
+                        mode
+                    };
                     match mode {
                         ExportMode::Copy => {
                             // copy in an external thread
                             self.rt.spawn_blocking(move || {
-                                tx.send(export_file_copy(temp_tag, path, size, target, progress))
-                                    .ok();
+                                tx.send(export_file_copy(
+                                    temp_tag, path, size, target, cipher, progress,
+                                ))
+                                .ok();
                             });
                         }
                         ExportMode::TryReference => match std::fs::rename(&path, &target) {
@@ -1692,8 +1900,10 @@ impl ActorState {
                     } else {
                         // copy in an external thread
                         self.rt.spawn_blocking(move || {
-                            tx.send(export_file_copy(temp_tag, path, size, target, progress))
-                                .ok();
+                            tx.send(export_file_copy(
+                                temp_tag, path, size, target, None, progress,
+                            ))
+                            .ok();
                         });
                     }
                 }
@@ -1711,6 +1921,7 @@ impl ActorState {
             source: file,
             outboard,
             data_size,
+            block_size,
         } = cmd;
         let outboard_size = outboard.as_ref().map(|x| x.len() as u64).unwrap_or(0);
         let inline_data = data_size <= self.options.inline.max_data_inlined;
@@ -1731,6 +1942,12 @@ impl ActorState {
                     );
                     let data = Bytes::from(std::fs::read(&external_path)?);
                     DataLocation::Inline(data)
+                } else if let Some(cipher) = self.options.data_cipher(&hash) {
+                    // an external file can not be encrypted, so copy it instead
+                    let data_path = self.options.owned_data_path(&hash);
+                    copy_with_cipher(&external_path, &data_path, &cipher)?;
+                    tracing::debug!("created encrypted file {}", data_path.display());
+                    DataLocation::Owned(data_size)
                 } else {
                     DataLocation::External(vec![external_path], data_size)
                 }
@@ -1744,8 +1961,13 @@ impl ActorState {
                     let data = Bytes::from(read_and_remove(&temp_data_path)?);
                     DataLocation::Inline(data)
                 } else {
-                    let data_path = self.options.path.owned_data_path(&hash);
-                    std::fs::rename(&temp_data_path, &data_path)?;
+                    let data_path = self.options.owned_data_path(&hash);
+                    if let Some(cipher) = self.options.data_cipher(&hash) {
+                        copy_with_cipher(&temp_data_path, &data_path, &cipher)?;
+                        std::fs::remove_file(&temp_data_path)?;
+                    } else {
+                        std::fs::rename(&temp_data_path, &data_path)?;
+                    }
                     tracing::debug!("created file {}", data_path.display());
                     DataLocation::Owned(data_size)
                 }
@@ -1754,7 +1976,8 @@ impl ActorState {
                 if inline_data {
                     DataLocation::Inline(data)
                 } else {
-                    let data_path = self.options.path.owned_data_path(&hash);
+                    let data_path = self.options.owned_data_path(&hash);
+                    let data = apply_cipher(self.options.data_cipher(&hash), &data);
                     overwrite_and_sync(&data_path, &data)?;
                     tracing::debug!("created file {}", data_path.display());
                     DataLocation::Owned(data_size)
@@ -1765,7 +1988,9 @@ impl ActorState {
             if inline_outboard {
                 OutboardLocation::Inline(Bytes::from(outboard))
             } else {
-                let outboard_path = self.options.path.owned_outboard_path(&hash);
+                let outboard_path = self.options.owned_outboard_path(&hash, block_size);
+                let outboard =
+                    apply_cipher(self.options.outboard_cipher(&hash, block_size), &outboard);
                 // todo: this blocks the actor when writing a large outboard
                 overwrite_and_sync(&outboard_path, &outboard)?;
                 OutboardLocation::Owned
@@ -1774,9 +1999,11 @@ impl ActorState {
             OutboardLocation::NotNeeded
         };
         if let DataLocation::Inline(data) = &data_location {
+            let data = apply_cipher(self.options.data_cipher(&hash), data);
             tables.inline_data.insert(hash, data.as_ref())?;
         }
         if let OutboardLocation::Inline(outboard) = &outboard_location {
+            let outboard = apply_cipher(self.options.outboard_cipher(&hash, block_size), outboard);
             tables.inline_outboard.insert(hash, outboard.as_ref())?;
         }
         if let DataLocation::Owned(_) = &data_location {
@@ -1785,16 +2012,41 @@ impl ActorState {
         if let OutboardLocation::Owned = &outboard_location {
             tables
                 .delete_after_commit
-                .remove(hash, [BaoFilePart::Outboard]);
+                .remove(hash, [BaoFilePart::Outboard(block_size)]);
         }
         let entry = tables.blobs.get(hash)?;
-        let entry = entry.map(|x| x.value()).unwrap_or_default();
+        let entry = entry.map(|x| x.value());
         let data_location = data_location.discard_inline_data();
         let outboard_location = outboard_location.discard_extra_data();
-        let entry = entry.union(EntryState::Complete {
+        let old_block_size = entry_block_size(tables, &hash)?;
+        let replace_outboard = entry.is_some() && old_block_size != block_size;
+        let mut entry = entry.unwrap_or_default().union(EntryState::Complete {
             data_location,
-            outboard_location,
+            outboard_location: outboard_location.clone(),
         })?;
+        if replace_outboard {
+            // the existing outboard has a different chunk group size, so it
+            // can not be kept next to the new one
+            if !matches!(outboard_location, OutboardLocation::Inline(())) {
+                tables.inline_outboard.remove(hash)?;
+            }
+            if let EntryState::Complete {
+                outboard_location: location,
+                ..
+            } = &mut entry
+            {
+                *location = outboard_location;
+            }
+            tables.delete_after_commit.insert(
+                hash,
+                [
+                    BaoFilePart::Outboard(old_block_size),
+                    BaoFilePart::Sizes(old_block_size),
+                ],
+            );
+            self.handles.remove(&hash);
+        }
+        set_entry_block_size(tables, hash, block_size)?;
         tables.blobs.insert(hash, entry)?;
         Ok((tag, data_size))
     }
@@ -1811,26 +2063,34 @@ impl ActorState {
         let entry = tables.blobs().get(hash)?;
         let handle = if let Some(entry) = entry {
             let entry = entry.value();
+            let block_size = entry_block_size(tables, &hash)?;
             match entry {
                 EntryState::Complete {
                     data_location,
                     outboard_location,
                     ..
                 } => {
-                    let data = load_data(tables, &self.options.path, data_location, &hash)?;
+                    let data = load_data(tables, &self.options, data_location, &hash)?;
                     let outboard = load_outboard(
                         tables,
-                        &self.options.path,
+                        &self.options,
                         outboard_location,
                         data.size(),
                         &hash,
+                        block_size,
                     )?;
                     println!("creating complete entry for {}", hash.to_hex());
-                    BaoFileHandle::new_complete(self.create_options.clone(), hash, data, outboard)
+                    BaoFileHandle::new_complete(
+                        self.create_options.clone(),
+                        hash,
+                        data,
+                        outboard,
+                        block_size,
+                    )
                 }
                 EntryState::Partial { .. } => {
                     println!("creating partial entry for {}", hash.to_hex());
-                    BaoFileHandle::incomplete_file(self.create_options.clone(), hash)?
+                    BaoFileHandle::incomplete_file(self.create_options.clone(), hash, block_size)?
                 }
             }
         } else {
@@ -1866,17 +2126,28 @@ impl ActorState {
     }
 
     /// Read the entire tags table. Callers can then sift through the results to find what they need
+    ///
+    /// Expired tags are skipped, even if they were not removed yet.
     fn tags(
         &mut self,
         tables: &impl ReadableTables,
+        prefix: Tag,
         filter: FilterPredicate<Tag, HashAndFormat>,
     ) -> ActorResult<Vec<std::result::Result<(Tag, HashAndFormat), StorageError>>> {
+        let now = unix_millis(SystemTime::now());
         let mut res = Vec::new();
         let mut index = 0u64;
         #[allow(clippy::explicit_counter_loop)]
-        for item in tables.tags().iter()? {
+        for item in tables.tags().range(prefix.clone()..)? {
             match item {
                 Ok((k, v)) => {
+                    // tags are sorted, so all tags with the prefix are contiguous
+                    if !k.value().starts_with(&prefix.0) {
+                        break;
+                    }
+                    if is_tag_expired(tables, &k.value(), now)? {
+                        continue;
+                    }
                     if let Some(item) = filter(index, k, v) {
                         res.push(Ok(item));
                     }
@@ -1890,12 +2161,117 @@ impl ActorState {
         Ok(res)
     }
 
-    fn create_tag(&mut self, tables: &mut Tables, content: HashAndFormat) -> ActorResult<Tag> {
+    /// Get the size and last access time of all blobs.
+    fn blob_usage(
+        &mut self,
+        tables: &impl ReadableTables,
+    ) -> ActorResult<Vec<std::result::Result<BlobUsage, StorageError>>> {
+        let mut res = Vec::new();
+        for item in tables.blobs().iter()? {
+            let (hash, entry) = match item {
+                Ok((k, v)) => (k.value(), v.value()),
+                Err(e) => {
+                    res.push(Err(e));
+                    continue;
+                }
+            };
+            let (size, complete_size) = match entry {
+                EntryState::Complete { data_location, .. } => match data_location {
+                    DataLocation::Inline(()) => {
+                        let size = tables
+                            .inline_data()
+                            .get(hash)?
+                            .map(|data| data.value().len() as u64)
+                            .unwrap_or_default();
+                        (size, Some(size))
+                    }
+                    DataLocation::Owned(size) => (size, Some(size)),
+                    // external data is not owned by the store
+                    DataLocation::External(_, size) => (0, Some(size)),
+                },
+                EntryState::Partial { .. } => {
+                    let size = std::fs::metadata(self.options.owned_data_path(&hash))
+                        .map(|meta| meta.len())
+                        .unwrap_or_default();
+                    (size, None)
+                }
+            };
+            res.push(Ok(BlobUsage {
+                hash,
+                size,
+                complete_size,
+                last_access: self.last_access.get(&hash).copied(),
+            }));
+        }
+        Ok(res)
+    }
+
+    /// Get the counts and sizes of all entries, by data location.
+    fn entry_stats(&mut self, tables: &impl ReadableTables) -> ActorResult<EntryStats> {
+        let mut stats = EntryStats::default();
+        for item in tables.blobs().iter()? {
+            let (hash, entry) = item?;
+            let hash = hash.value();
+            match entry.value() {
+                EntryState::Complete {
+                    data_location,
+                    outboard_location,
+                } => {
+                    let (group, data_size) = match data_location {
+                        DataLocation::Inline(()) => {
+                            let size = tables
+                                .inline_data()
+                                .get(hash)?
+                                .map(|data| data.value().len() as u64)
+                                .unwrap_or_default();
+                            (&mut stats.inline, size)
+                        }
+                        DataLocation::Owned(size) => (&mut stats.owned, size),
+                        DataLocation::External(_, size) => (&mut stats.external, size),
+                    };
+                    let outboard_size = match outboard_location {
+                        OutboardLocation::Inline(()) => tables
+                            .inline_outboard()
+                            .get(hash)?
+                            .map(|outboard| outboard.value().len() as u64)
+                            .unwrap_or_default(),
+                        OutboardLocation::Owned => {
+                            let block_size = entry_block_size(tables, &hash)?;
+                            BaoTree::new(data_size, block_size).outboard_size()
+                        }
+                        OutboardLocation::NotNeeded => 0,
+                    };
+                    group.add(data_size, outboard_size);
+                }
+                EntryState::Partial { .. } => {
+                    let file_size = |path: PathBuf| {
+                        std::fs::metadata(path)
+                            .map(|meta| meta.len())
+                            .unwrap_or_default()
+                    };
+                    let block_size = entry_block_size(tables, &hash)?;
+                    stats.partial.add(
+                        file_size(self.options.owned_data_path(&hash)),
+                        file_size(self.options.owned_outboard_path(&hash, block_size)),
+                    );
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    fn create_tag(
+        &mut self,
+        tables: &mut Tables,
+        content: HashAndFormat,
+        ttl: Option<Duration>,
+    ) -> ActorResult<Tag> {
         let tag = {
             let tag = Tag::auto(SystemTime::now(), |x| {
                 matches!(tables.tags.get(Tag(Bytes::copy_from_slice(x))), Ok(Some(_)))
             });
             tables.tags.insert(tag.clone(), content)?;
+            set_tag_expiry(tables, tag.clone(), ttl)?;
             tag
         };
         Ok(tag)
@@ -1906,18 +2282,86 @@ impl ActorState {
         tables: &mut Tables,
         tag: Tag,
         value: Option<HashAndFormat>,
+        ttl: Option<Duration>,
     ) -> ActorResult<()> {
         match value {
             Some(value) => {
-                tables.tags.insert(tag, value)?;
+                tables.tags.insert(tag.clone(), value)?;
+                set_tag_expiry(tables, tag, ttl)?;
             }
             None => {
-                tables.tags.remove(tag)?;
+                tables.tags.remove(tag.clone())?;
+                tables.tag_expiry.remove(tag)?;
             }
         }
         Ok(())
     }
 
+    fn update_tags(&self, tables: &mut Tables, updates: Vec<TagUpdate>) -> ActorResult<bool> {
+        let now = unix_millis(SystemTime::now());
+        for update in &updates {
+            // an expired tag is treated as absent, even if it was not removed yet
+            let current = if is_tag_expired(tables, &update.name, now)? {
+                None
+            } else {
+                tables.tags.get(&update.name)?.map(|x| x.value())
+            };
+            if !update.matches(current.as_ref()) {
+                return Ok(false);
+            }
+        }
+        for TagUpdate {
+            name, value, ttl, ..
+        } in updates
+        {
+            self.set_tag(tables, name, value, ttl)?;
+        }
+        Ok(true)
+    }
+
+    fn remove_expired_tags(&self, tables: &mut Tables) -> ActorResult<Vec<(Tag, HashAndFormat)>> {
+        let now = unix_millis(SystemTime::now());
+        let mut expired = Vec::new();
+        for item in tables.tag_expiry.iter()? {
+            let (tag, expiry) = item?;
+            if expiry.value() <= now {
+                expired.push(tag.value());
+            }
+        }
+        let mut removed = Vec::new();
+        for tag in expired {
+            tables.tag_expiry.remove(tag.clone())?;
+            if let Some(value) = tables.tags.remove(tag.clone())? {
+                removed.push((tag, value.value()));
+            }
+        }
+        Ok(removed)
+    }
+
+    fn delete_tags_with_prefix(
+        &self,
+        tables: &mut Tables,
+        prefix: Tag,
+    ) -> ActorResult<Vec<(Tag, HashAndFormat)>> {
+        let mut matching = Vec::new();
+        for item in tables.tags.range(prefix.clone()..)? {
+            let (tag, _) = item?;
+            let tag = tag.value();
+            if !tag.starts_with(&prefix.0) {
+                break;
+            }
+            matching.push(tag);
+        }
+        let mut removed = Vec::new();
+        for tag in matching {
+            tables.tag_expiry.remove(tag.clone())?;
+            if let Some(value) = tables.tags.remove(tag.clone())? {
+                removed.push((tag, value.value()));
+            }
+        }
+        Ok(removed)
+    }
+
     fn on_mem_size_exceeded(&mut self, tables: &mut Tables, hash: Hash) -> ActorResult<()> {
         let entry = tables
             .blobs
@@ -1926,10 +2370,17 @@ impl ActorState {
             .unwrap_or_default();
         let entry = entry.union(EntryState::Partial { size: None })?;
         tables.blobs.insert(hash, entry)?;
+        // in memory entries always use the chunk group size of the store
+        let block_size = self.options.block_size;
+        set_entry_block_size(tables, hash, block_size)?;
         // protect all three parts of the entry
         tables.delete_after_commit.remove(
             hash,
-            [BaoFilePart::Data, BaoFilePart::Outboard, BaoFilePart::Sizes],
+            [
+                BaoFilePart::Data,
+                BaoFilePart::Outboard(block_size),
+                BaoFilePart::Sizes(block_size),
+            ],
         );
         Ok(())
     }
@@ -1967,7 +2418,7 @@ impl ActorState {
                             DataLocation::Owned(size) => {
                                 // inline
                                 if size <= self.options.inline.max_data_inlined {
-                                    let path = self.options.path.owned_data_path(&hash);
+                                    let path = self.options.owned_data_path(&hash);
                                     let data = std::fs::read(&path)?;
                                     tables.delete_after_commit.insert(hash, [BaoFilePart::Data]);
                                     tables.inline_data.insert(hash, data.as_slice())?;
@@ -1983,7 +2434,7 @@ impl ActorState {
                                 let data = guard.value();
                                 let size = data.len() as u64;
                                 if size > self.options.inline.max_data_inlined {
-                                    let path = self.options.path.owned_data_path(&hash);
+                                    let path = self.options.owned_data_path(&hash);
                                     std::fs::write(&path, data)?;
                                     drop(guard);
                                     tables.inline_data.remove(hash)?;
@@ -1996,17 +2447,18 @@ impl ActorState {
                                 (DataLocation::External(paths, size), size, false)
                             }
                         };
-                        let outboard_size = raw_outboard_size(data_size);
+                        let block_size = entry_block_size(&tables, &hash)?;
+                        let outboard_size = BaoTree::new(data_size, block_size).outboard_size();
                         let (outboard_location, outboard_location_changed) = match outboard_location
                         {
                             OutboardLocation::Owned
                                 if outboard_size <= self.options.inline.max_outboard_inlined =>
                             {
-                                let path = self.options.path.owned_outboard_path(&hash);
+                                let path = self.options.owned_outboard_path(&hash, block_size);
                                 let outboard = std::fs::read(&path)?;
                                 tables
                                     .delete_after_commit
-                                    .insert(hash, [BaoFilePart::Outboard]);
+                                    .insert(hash, [BaoFilePart::Outboard(block_size)]);
                                 tables.inline_outboard.insert(hash, outboard.as_slice())?;
                                 (OutboardLocation::Inline(()), true)
                             }
@@ -2017,7 +2469,7 @@ impl ActorState {
                                     ActorError::Inconsistent("inline outboard missing".to_owned())
                                 })?;
                                 let outboard = guard.value();
-                                let path = self.options.path.owned_outboard_path(&hash);
+                                let path = self.options.owned_outboard_path(&hash, block_size);
                                 std::fs::write(&path, outboard)?;
                                 drop(guard);
                                 tables.inline_outboard.remove(hash)?;
@@ -2039,7 +2491,7 @@ impl ActorState {
                 }
             }
             tx.commit()?;
-            delete_after_commit.apply_and_clear(&self.options.path);
+            delete_after_commit.apply_and_clear(&self.options);
         }
         Ok(())
     }
@@ -2055,6 +2507,9 @@ impl ActorState {
             }
             tracing::debug!("deleting {}", &hash.to_hex()[..8]);
             self.handles.remove(&hash);
+            self.last_access.remove(&hash);
+            let block_size = entry_block_size(tables, &hash)?;
+            tables.block_size.remove(hash)?;
             if let Some(entry) = tables.blobs.remove(hash)? {
                 match entry.value() {
                     EntryState::Complete {
@@ -2079,7 +2534,7 @@ impl ActorState {
                                 // mark the outboard for deletion
                                 tables
                                     .delete_after_commit
-                                    .insert(hash, [BaoFilePart::Outboard]);
+                                    .insert(hash, [BaoFilePart::Outboard(block_size)]);
                             }
                             OutboardLocation::NotNeeded => {}
                         }
@@ -2088,7 +2543,11 @@ impl ActorState {
                         // mark all parts for deletion
                         tables.delete_after_commit.insert(
                             hash,
-                            [BaoFilePart::Outboard, BaoFilePart::Data, BaoFilePart::Sizes],
+                            [
+                                BaoFilePart::Outboard(block_size),
+                                BaoFilePart::Data,
+                                BaoFilePart::Sizes(block_size),
+                            ],
                         );
                     }
                 }
@@ -2099,6 +2558,7 @@ impl ActorState {
 
     fn on_complete(&mut self, tables: &mut Tables, entry: BaoFileHandle) -> ActorResult<()> {
         let hash = entry.hash();
+        let block_size = entry.block_size();
         let mut info = None;
         tracing::trace!("on_complete({})", hash.to_hex());
         entry.transform(|state| {
@@ -2106,8 +2566,8 @@ impl ActorState {
             let entry = match complete_storage(
                 state,
                 &hash,
-                &self.options.path,
-                &self.options.inline,
+                &self.options,
+                block_size,
                 tables.delete_after_commit,
             )? {
                 Ok(entry) => {
@@ -2151,15 +2611,22 @@ impl ActorState {
                     .get(hash)?
                     .map(|x| x.value())
                     .unwrap_or_default();
+                // an entry that is already complete keeps its outboard
+                if !matches!(entry, EntryState::Complete { .. }) {
+                    set_entry_block_size(tables, hash, block_size)?;
+                }
                 let entry = entry.union(EntryState::Complete {
                     data_location,
                     outboard_location,
                 })?;
                 tables.blobs.insert(hash, entry)?;
                 if let Some(data) = data {
+                    let data = apply_cipher(self.options.data_cipher(&hash), &data);
                     tables.inline_data.insert(hash, data.as_ref())?;
                 }
                 if let Some(outboard) = outboard {
+                    let outboard =
+                        apply_cipher(self.options.outboard_cipher(&hash, block_size), &outboard);
                     tables.inline_outboard.insert(hash, outboard.as_ref())?;
                 }
             }
@@ -2210,6 +2677,9 @@ impl ActorState {
         match msg {
             ActorMessage::Get { hash, tx } => {
                 let res = self.get(tables, hash);
+                if let Ok(Some(_)) = &res {
+                    self.last_access.insert(hash, SystemTime::now());
+                }
                 tx.send(res).ok();
             }
             ActorMessage::GetOrCreate { hash, tx } => {
@@ -2224,8 +2694,16 @@ impl ActorState {
                 let res = self.blobs(tables, filter);
                 tx.send(res).ok();
             }
-            ActorMessage::Tags { filter, tx } => {
-                let res = self.tags(tables, filter);
+            ActorMessage::Tags { prefix, filter, tx } => {
+                let res = self.tags(tables, prefix, filter);
+                tx.send(res).ok();
+            }
+            ActorMessage::BlobUsage { tx } => {
+                let res = self.blob_usage(tables);
+                tx.send(res).ok();
+            }
+            ActorMessage::EntryStats { tx } => {
+                let res = self.entry_stats(tables);
                 tx.send(res).ok();
             }
             ActorMessage::GcStart { tx } => {
@@ -2259,12 +2737,29 @@ impl ActorState {
                 let res = self.import(tables, cmd);
                 tx.send(res).ok();
             }
-            ActorMessage::SetTag { tag, value, tx } => {
-                let res = self.set_tag(tables, tag, value);
+            ActorMessage::SetTag {
+                tag,
+                value,
+                ttl,
+                tx,
+            } => {
+                let res = self.set_tag(tables, tag, value, ttl);
+                tx.send(res).ok();
+            }
+            ActorMessage::UpdateTags { updates, tx } => {
+                let res = self.update_tags(tables, updates);
                 tx.send(res).ok();
             }
-            ActorMessage::CreateTag { hash, tx } => {
-                let res = self.create_tag(tables, hash);
+            ActorMessage::CreateTag { hash, ttl, tx } => {
+                let res = self.create_tag(tables, hash, ttl);
+                tx.send(res).ok();
+            }
+            ActorMessage::RemoveExpiredTags { tx } => {
+                let res = self.remove_expired_tags(tables);
+                tx.send(res).ok();
+            }
+            ActorMessage::DeleteTagsWithPrefix { prefix, tx } => {
+                let res = self.delete_tags_with_prefix(tables, prefix);
                 tx.send(res).ok();
             }
             ActorMessage::Delete { hashes, tx } => {
@@ -2301,17 +2796,24 @@ impl ActorState {
     }
 }
 
-/// Export a file by copying out its content to a new location
+/// Export a file by copying out its content to a new location, decrypting it
+/// if needed.
 fn export_file_copy(
     temp_tag: TempTag,
     path: PathBuf,
     size: u64,
     target: PathBuf,
+    cipher: Option<BlobCipher>,
     progress: ExportProgressCb,
 ) -> ActorResult<()> {
     progress(0)?;
     // todo: fine grained copy progress
-    reflink_copy::reflink_or_copy(path, target)?;
+    match cipher {
+        Some(cipher) => copy_with_cipher(&path, &target, &cipher)?,
+        None => {
+            reflink_copy::reflink_or_copy(path, target)?;
+        }
+    }
     progress(size)?;
     drop(temp_tag);
     Ok(())
@@ -2331,6 +2833,7 @@ fn export_file_copy(
 fn compute_outboard(
     read: impl Read,
     size: u64,
+    block_size: BlockSize,
     progress: impl Fn(u64) -> io::Result<()> + Send + Sync + 'static,
 ) -> io::Result<(Hash, Option<Vec<u8>>)> {
     use bao_tree::io::sync::CreateOutboard;
@@ -2342,7 +2845,7 @@ fn compute_outboard(
     let buf_size = usize::try_from(size).unwrap_or(usize::MAX).min(1024 * 1024);
     let reader = BufReader::with_capacity(buf_size, reader);
 
-    let ob = PreOrderOutboard::<Vec<u8>>::create_sized(reader, size, IROH_BLOCK_SIZE)?;
+    let ob = PreOrderOutboard::<Vec<u8>>::create_sized(reader, size, block_size)?;
     let root = ob.root.into();
     let data = ob.data;
     tracing::trace!(%root, "done");
@@ -2350,6 +2853,56 @@ fn compute_outboard(
     Ok((root, data))
 }
 
+/// The chunk group size of the outboard of an entry.
+fn entry_block_size(tables: &impl ReadableTables, hash: &Hash) -> ActorResult<BlockSize> {
+    Ok(match tables.block_size().get(hash)? {
+        Some(log) => BlockSize::from_chunk_log(log.value()),
+        None => IROH_BLOCK_SIZE,
+    })
+}
+
+/// Record the chunk group size of the outboard of an entry.
+fn set_entry_block_size(tables: &mut Tables, hash: Hash, block_size: BlockSize) -> ActorResult<()> {
+    if block_size == IROH_BLOCK_SIZE {
+        tables.block_size.remove(hash)?;
+    } else {
+        tables.block_size.insert(hash, block_size.chunk_log())?;
+    }
+    Ok(())
+}
+
+/// Set or clear the expiry of a tag.
+fn set_tag_expiry(tables: &mut Tables, tag: Tag, ttl: Option<Duration>) -> ActorResult<()> {
+    match ttl.and_then(|ttl| SystemTime::now().checked_add(ttl)) {
+        Some(expiry) => {
+            tables.tag_expiry.insert(tag, unix_millis(expiry))?;
+        }
+        None => {
+            tables.tag_expiry.remove(tag)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether a tag has an expiry at or before `now`, in milliseconds since the unix epoch.
+fn is_tag_expired(
+    tables: &impl ReadableTables,
+    tag: &Tag,
+    now: u64,
+) -> std::result::Result<bool, StorageError> {
+    Ok(tables
+        .tag_expiry()
+        .get(tag)?
+        .is_some_and(|expiry| expiry.value() <= now))
+}
+
+/// Milliseconds since the unix epoch, saturating at the bounds of a u64.
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis().try_into().unwrap_or(u64::MAX))
+        .unwrap_or_default()
+}
+
 fn dump(tables: &impl ReadableTables) -> ActorResult<()> {
     for e in tables.blobs().iter()? {
         let (k, v) = e?;
@@ -2363,6 +2916,12 @@ fn dump(tables: &impl ReadableTables) -> ActorResult<()> {
         let v = v.value();
         println!("tags: {} -> {:?}", k, v);
     }
+    for e in tables.tag_expiry().iter()? {
+        let (k, v) = e?;
+        let k = k.value();
+        let v = v.value();
+        println!("tag_expiry: {} -> {}", k, v);
+    }
     for e in tables.inline_data().iter()? {
         let (k, v) = e?;
         let k = k.value();
@@ -2378,12 +2937,50 @@ fn dump(tables: &impl ReadableTables) -> ActorResult<()> {
     Ok(())
 }
 
+/// Check that the store is opened with the same key it was created with.
+///
+/// Encryption can only be enabled for a store that does not contain any blobs
+/// yet.
+fn check_encryption_key(
+    txn: &redb::WriteTransaction,
+    key: Option<&EncryptionKey>,
+    is_empty: bool,
+) -> ActorResult<()> {
+    const KEY_ID: &str = "key-id";
+    let mut table = txn.open_table(tables::ENCRYPTION_TABLE)?;
+    let stored = table.get(KEY_ID)?.map(|x| x.value());
+    match (stored, key) {
+        (Some(stored), Some(key)) if stored != key.id() => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "store is encrypted with a different key",
+        )
+        .into()),
+        (Some(_), None) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "store is encrypted, but no key was given",
+        )
+        .into()),
+        (None, Some(key)) => {
+            if !is_empty {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "can not enable encryption for a store that already contains data",
+                )
+                .into());
+            }
+            table.insert(KEY_ID, key.id())?;
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
 fn load_data(
     tables: &impl ReadableTables,
-    options: &PathOptions,
+    options: &Options,
     location: DataLocation<(), u64>,
     hash: &Hash,
-) -> ActorResult<MemOrFile<Bytes, (std::fs::File, u64)>> {
+) -> ActorResult<MemOrFile<Bytes, (BlobFile, u64)>> {
     Ok(match location {
         DataLocation::Inline(()) => {
             let Some(data) = tables.inline_data().get(hash)? else {
@@ -2392,7 +2989,8 @@ fn load_data(
                     hash.to_hex()
                 )));
             };
-            MemOrFile::Mem(Bytes::copy_from_slice(data.value()))
+            let data = apply_cipher(options.data_cipher(hash), data.value());
+            MemOrFile::Mem(Bytes::from(data.into_owned()))
         }
         DataLocation::Owned(data_size) => {
             let path = options.owned_data_path(hash);
@@ -2403,6 +3001,7 @@ fn load_data(
                 )
                 .into());
             };
+            let file = BlobFile::new(file, options.data_cipher(hash));
             MemOrFile::File((file, data_size))
         }
         DataLocation::External(paths, data_size) => {
@@ -2419,18 +3018,20 @@ fn load_data(
                 )
                 .into());
             };
-            MemOrFile::File((file, data_size))
+            // external files are not owned by the store, so never encrypted
+            MemOrFile::File((BlobFile::plain(file), data_size))
         }
     })
 }
 
 fn load_outboard(
     tables: &impl ReadableTables,
-    options: &PathOptions,
+    options: &Options,
     location: OutboardLocation,
     size: u64,
     hash: &Hash,
-) -> ActorResult<MemOrFile<Bytes, (std::fs::File, u64)>> {
+    block_size: BlockSize,
+) -> ActorResult<MemOrFile<Bytes, (BlobFile, u64)>> {
     Ok(match location {
         OutboardLocation::NotNeeded => MemOrFile::Mem(Bytes::new()),
         OutboardLocation::Inline(_) => {
@@ -2440,11 +3041,13 @@ fn load_outboard(
                     hash.to_hex()
                 )));
             };
-            MemOrFile::Mem(Bytes::copy_from_slice(outboard.value()))
+            let outboard =
+                apply_cipher(options.outboard_cipher(hash, block_size), outboard.value());
+            MemOrFile::Mem(Bytes::from(outboard.into_owned()))
         }
         OutboardLocation::Owned => {
-            let outboard_size = raw_outboard_size(size);
-            let path = options.owned_outboard_path(hash);
+            let outboard_size = BaoTree::new(size, block_size).outboard_size();
+            let path = options.owned_outboard_path(hash, block_size);
             let Ok(file) = std::fs::File::open(&path) else {
                 return Err(io::Error::new(
                     io::ErrorKind::NotFound,
@@ -2452,6 +3055,7 @@ fn load_outboard(
                 )
                 .into());
             };
+            let file = BlobFile::new(file, options.outboard_cipher(hash, block_size));
             MemOrFile::File((file, outboard_size))
         }
     })
@@ -2461,10 +3065,11 @@ fn load_outboard(
 fn complete_storage(
     storage: BaoFileStorage,
     hash: &Hash,
-    path_options: &PathOptions,
-    inline_options: &InlineOptions,
+    options: &Options,
+    block_size: BlockSize,
     delete_after_commit: &mut DeleteSet,
 ) -> ActorResult<std::result::Result<CompleteStorage, CompleteStorage>> {
+    let inline_options = &options.inline;
     let (data, outboard, _sizes) = match storage {
         BaoFileStorage::Complete(c) => return Ok(Err(c)),
         BaoFileStorage::IncompleteMem(storage) => {
@@ -2472,7 +3077,7 @@ fn complete_storage(
             (
                 MemOrFile::Mem(Bytes::from(data.into_parts().0)),
                 MemOrFile::Mem(Bytes::from(outboard.into_parts().0)),
-                MemOrFile::Mem(Bytes::from(sizes.to_vec())),
+                MemOrFile::Mem(Bytes::from(sizes.to_vec(block_size))),
             )
         }
         BaoFileStorage::IncompleteFile(storage) => {
@@ -2487,7 +3092,7 @@ fn complete_storage(
     let data_size = data.size()?.unwrap();
     let outboard_size = outboard.size()?.unwrap();
     // todo: perform more sanity checks if in debug mode
-    debug_assert!(raw_outboard_size(data_size) == outboard_size);
+    debug_assert!(BaoTree::new(data_size, block_size).outboard_size() == outboard_size);
     // inline data if needed, or write to file if needed
     let data = if data_size <= inline_options.max_data_inlined {
         match data {
@@ -2505,9 +3110,10 @@ fn complete_storage(
         delete_after_commit.remove(*hash, [BaoFilePart::Data]);
         match data {
             MemOrFile::Mem(data) => {
-                let path = path_options.owned_data_path(hash);
-                let file = overwrite_and_sync(&path, &data)?;
-                MemOrFile::File((file, data_size))
+                let path = options.owned_data_path(hash);
+                let cipher = options.data_cipher(hash);
+                let file = overwrite_and_sync(&path, &apply_cipher(cipher.clone(), &data))?;
+                MemOrFile::File((BlobFile::new(file, cipher), data_size))
             }
             MemOrFile::File(data) => MemOrFile::File((data, data_size)),
         }
@@ -2522,25 +3128,26 @@ fn complete_storage(
                 outboard.read_at(0, &mut buf)?;
                 drop(outboard);
                 // mark outboard for deletion after commit
-                delete_after_commit.insert(*hash, [BaoFilePart::Outboard]);
+                delete_after_commit.insert(*hash, [BaoFilePart::Outboard(block_size)]);
                 MemOrFile::Mem(Bytes::from(buf))
             }
             MemOrFile::Mem(outboard) => MemOrFile::Mem(outboard),
         }
     } else {
         // protect the outboard from previous deletions
-        delete_after_commit.remove(*hash, [BaoFilePart::Outboard]);
+        delete_after_commit.remove(*hash, [BaoFilePart::Outboard(block_size)]);
         match outboard {
             MemOrFile::Mem(outboard) => {
-                let path = path_options.owned_outboard_path(hash);
-                let file = overwrite_and_sync(&path, &outboard)?;
-                MemOrFile::File((file, outboard_size))
+                let path = options.owned_outboard_path(hash, block_size);
+                let cipher = options.outboard_cipher(hash, block_size);
+                let file = overwrite_and_sync(&path, &apply_cipher(cipher.clone(), &outboard))?;
+                MemOrFile::File((BlobFile::new(file, cipher), outboard_size))
             }
             MemOrFile::File(outboard) => MemOrFile::File((outboard, outboard_size)),
         }
     };
     // mark sizes for deletion after commit in any case - a complete entry
     // does not need sizes.
-    delete_after_commit.insert(*hash, [BaoFilePart::Sizes]);
+    delete_after_commit.insert(*hash, [BaoFilePart::Sizes(block_size)]);
     Ok(Ok(CompleteStorage { data, outboard }))
 }
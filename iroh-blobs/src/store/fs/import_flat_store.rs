@@ -98,6 +98,14 @@ impl ActorState {
         db: &redb::Database,
         paths: FlatStorePaths,
     ) -> ActorResult<bool> {
+        if self.options.encryption.is_some() {
+            // flat store files are moved into place, so they would stay unencrypted
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "importing a flat store into an encrypted store is not supported",
+            )
+            .into());
+        }
         #[derive(Debug, Default)]
         struct EntryPaths {
             data: Option<(PathBuf, u64)>,
@@ -229,8 +237,7 @@ impl ActorState {
                 } else {
                     None
                 };
-                if let Err(cause) =
-                    std::fs::rename(data_path, self.options.path.owned_data_path(&hash))
+                if let Err(cause) = std::fs::rename(data_path, self.options.owned_data_path(&hash))
                 {
                     tracing::error!("failed to move data file: {}", cause);
                     continue;
@@ -238,7 +245,7 @@ impl ActorState {
                 if let Some(outboard_path) = outboard_path {
                     if let Err(cause) = copy_outboard(
                         &outboard_path,
-                        &self.options.path.owned_outboard_path(&hash),
+                        &self.options.owned_outboard_path(&hash, IROH_BLOCK_SIZE),
                     ) {
                         tracing::error!("failed to move outboard file: {}", cause);
                         continue;
@@ -279,7 +286,7 @@ impl ActorState {
                 if let Some(outboard_path) = outboard_path {
                     if let Err(cause) = copy_outboard(
                         &outboard_path,
-                        &self.options.path.owned_outboard_path(&hash),
+                        &self.options.owned_outboard_path(&hash, IROH_BLOCK_SIZE),
                     ) {
                         tracing::error!("failed to move outboard file: {}", cause);
                         continue;
@@ -318,8 +325,7 @@ impl ActorState {
                 } else {
                     None
                 };
-                if let Err(cause) =
-                    std::fs::rename(data_path, self.options.path.owned_data_path(&hash))
+                if let Err(cause) = std::fs::rename(data_path, self.options.owned_data_path(&hash))
                 {
                     tracing::error!("failed to move data file: {}", cause);
                     continue;
@@ -327,7 +333,7 @@ impl ActorState {
                 if let Some(outboard_path) = outboard_path {
                     if let Err(cause) = copy_outboard(
                         &outboard_path,
-                        &self.options.path.owned_outboard_path(&hash),
+                        &self.options.owned_outboard_path(&hash, IROH_BLOCK_SIZE),
                     ) {
                         tracing::error!("failed to move outboard file: {}", cause);
                         continue;
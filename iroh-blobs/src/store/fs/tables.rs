@@ -1,31 +1,47 @@
 //! Table definitions and accessors for the redb database.
 use std::collections::BTreeSet;
 
+use bao_tree::BlockSize;
 use redb::{ReadableTable, TableDefinition, TableError};
 
 use iroh_base::hash::{Hash, HashAndFormat};
 
-use super::{EntryState, PathOptions};
+use super::{EntryState, Options};
 use crate::util::Tag;
 
 pub(super) const BLOBS_TABLE: TableDefinition<Hash, EntryState> = TableDefinition::new("blobs-0");
 
 pub(super) const TAGS_TABLE: TableDefinition<Tag, HashAndFormat> = TableDefinition::new("tags-0");
 
+/// Expiry of tags with a ttl, in milliseconds since the unix epoch.
+pub(super) const TAG_EXPIRY_TABLE: TableDefinition<Tag, u64> = TableDefinition::new("tag-expiry-0");
+
 pub(super) const INLINE_DATA_TABLE: TableDefinition<Hash, &[u8]> =
     TableDefinition::new("inline-data-0");
 
 pub(super) const INLINE_OUTBOARD_TABLE: TableDefinition<Hash, &[u8]> =
     TableDefinition::new("inline-outboard-0");
 
+/// Log2 of the chunk group size of the outboard of an entry, in chunks.
+///
+/// Only entries with a chunk group size other than
+/// [`IROH_BLOCK_SIZE`](crate::IROH_BLOCK_SIZE) are in this table.
+pub(super) const BLOCK_SIZE_TABLE: TableDefinition<Hash, u8> = TableDefinition::new("block-size-0");
+
+/// Id of the encryption key of the store, if the store is encrypted.
+pub(super) const ENCRYPTION_TABLE: TableDefinition<&str, [u8; 32]> =
+    TableDefinition::new("encryption-0");
+
 /// A trait similar to [`redb::ReadableTable`] but for all tables that make up
 /// the blob store. This can be used in places where either a readonly or
 /// mutable table is needed.
 pub(super) trait ReadableTables {
     fn blobs(&self) -> &impl ReadableTable<Hash, EntryState>;
     fn tags(&self) -> &impl ReadableTable<Tag, HashAndFormat>;
+    fn tag_expiry(&self) -> &impl ReadableTable<Tag, u64>;
     fn inline_data(&self) -> &impl ReadableTable<Hash, &'static [u8]>;
     fn inline_outboard(&self) -> &impl ReadableTable<Hash, &'static [u8]>;
+    fn block_size(&self) -> &impl ReadableTable<Hash, u8>;
 }
 
 /// A struct similar to [`redb::Table`] but for all tables that make up the
@@ -33,16 +49,21 @@ pub(super) trait ReadableTables {
 pub(super) struct Tables<'a> {
     pub blobs: redb::Table<'a, Hash, EntryState>,
     pub tags: redb::Table<'a, Tag, HashAndFormat>,
+    pub tag_expiry: redb::Table<'a, Tag, u64>,
     pub inline_data: redb::Table<'a, Hash, &'static [u8]>,
     pub inline_outboard: redb::Table<'a, Hash, &'static [u8]>,
+    pub block_size: redb::Table<'a, Hash, u8>,
     pub delete_after_commit: &'a mut DeleteSet,
 }
 
+/// A file that belongs to an entry.
+///
+/// The names of the outboard and sizes files depend on the chunk group size.
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub(super) enum BaoFilePart {
-    Outboard,
+    Outboard(BlockSize),
     Data,
-    Sizes,
+    Sizes(BlockSize),
 }
 
 impl<'txn> Tables<'txn> {
@@ -53,8 +74,10 @@ impl<'txn> Tables<'txn> {
         Ok(Self {
             blobs: tx.open_table(BLOBS_TABLE)?,
             tags: tx.open_table(TAGS_TABLE)?,
+            tag_expiry: tx.open_table(TAG_EXPIRY_TABLE)?,
             inline_data: tx.open_table(INLINE_DATA_TABLE)?,
             inline_outboard: tx.open_table(INLINE_OUTBOARD_TABLE)?,
+            block_size: tx.open_table(BLOCK_SIZE_TABLE)?,
             delete_after_commit,
         })
     }
@@ -67,12 +90,18 @@ impl ReadableTables for Tables<'_> {
     fn tags(&self) -> &impl ReadableTable<Tag, HashAndFormat> {
         &self.tags
     }
+    fn tag_expiry(&self) -> &impl ReadableTable<Tag, u64> {
+        &self.tag_expiry
+    }
     fn inline_data(&self) -> &impl ReadableTable<Hash, &'static [u8]> {
         &self.inline_data
     }
     fn inline_outboard(&self) -> &impl ReadableTable<Hash, &'static [u8]> {
         &self.inline_outboard
     }
+    fn block_size(&self) -> &impl ReadableTable<Hash, u8> {
+        &self.block_size
+    }
 }
 
 /// A struct similar to [`redb::ReadOnlyTable`] but for all tables that make up
@@ -80,8 +109,10 @@ impl ReadableTables for Tables<'_> {
 pub(super) struct ReadOnlyTables {
     pub blobs: redb::ReadOnlyTable<Hash, EntryState>,
     pub tags: redb::ReadOnlyTable<Tag, HashAndFormat>,
+    pub tag_expiry: redb::ReadOnlyTable<Tag, u64>,
     pub inline_data: redb::ReadOnlyTable<Hash, &'static [u8]>,
     pub inline_outboard: redb::ReadOnlyTable<Hash, &'static [u8]>,
+    pub block_size: redb::ReadOnlyTable<Hash, u8>,
 }
 
 impl<'txn> ReadOnlyTables {
@@ -89,8 +120,10 @@ impl<'txn> ReadOnlyTables {
         Ok(Self {
             blobs: tx.open_table(BLOBS_TABLE)?,
             tags: tx.open_table(TAGS_TABLE)?,
+            tag_expiry: tx.open_table(TAG_EXPIRY_TABLE)?,
             inline_data: tx.open_table(INLINE_DATA_TABLE)?,
             inline_outboard: tx.open_table(INLINE_OUTBOARD_TABLE)?,
+            block_size: tx.open_table(BLOCK_SIZE_TABLE)?,
         })
     }
 }
@@ -102,12 +135,18 @@ impl ReadableTables for ReadOnlyTables {
     fn tags(&self) -> &impl ReadableTable<Tag, HashAndFormat> {
         &self.tags
     }
+    fn tag_expiry(&self) -> &impl ReadableTable<Tag, u64> {
+        &self.tag_expiry
+    }
     fn inline_data(&self) -> &impl ReadableTable<Hash, &'static [u8]> {
         &self.inline_data
     }
     fn inline_outboard(&self) -> &impl ReadableTable<Hash, &'static [u8]> {
         &self.inline_outboard
     }
+    fn block_size(&self) -> &impl ReadableTable<Hash, u8> {
+        &self.block_size
+    }
 }
 
 /// Helper to keep track of files to delete after a transaction is committed.
@@ -140,13 +179,13 @@ impl DeleteSet {
     ///
     /// This will delete all files marked for deletion and then clear the set.
     /// Errors will just be logged.
-    pub fn apply_and_clear(&mut self, options: &PathOptions) {
+    pub fn apply_and_clear(&mut self, options: &Options) {
         for (hash, to_delete) in &self.0 {
             tracing::debug!("deleting {:?} for {hash}", to_delete);
             let path = match to_delete {
                 BaoFilePart::Data => options.owned_data_path(hash),
-                BaoFilePart::Outboard => options.owned_outboard_path(hash),
-                BaoFilePart::Sizes => options.owned_sizes_path(hash),
+                BaoFilePart::Outboard(block_size) => options.owned_outboard_path(hash, *block_size),
+                BaoFilePart::Sizes(block_size) => options.owned_sizes_path(hash, *block_size),
             };
             if let Err(cause) = std::fs::remove_file(&path) {
                 // Ignore NotFound errors, if the file is already gone that's fine.
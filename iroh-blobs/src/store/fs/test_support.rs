@@ -9,15 +9,17 @@ use std::{
 
 use tokio::sync::oneshot;
 
+use bao_tree::BaoTree;
+
 use super::{
+    entry_block_size,
     tables::{ReadableTables, Tables},
     ActorError, ActorMessage, ActorResult, ActorState, DataLocation, EntryState, FilterPredicate,
     OutboardLocation, OuterResult, Store, StoreInner,
 };
 use crate::{
     store::{mutable_mem_storage::SizeInfo, DbIter},
-    util::raw_outboard_size,
-    Hash,
+    Hash, IROH_BLOCK_SIZE,
 };
 use redb::ReadableTable;
 
@@ -94,12 +96,12 @@ impl Store {
 
     /// Owned data path
     pub fn owned_data_path(&self, hash: &Hash) -> PathBuf {
-        self.0.path_options.owned_data_path(hash)
+        self.0.options.owned_data_path(hash)
     }
 
-    /// Owned outboard path
+    /// Owned outboard path, for the default chunk group size
     pub fn owned_outboard_path(&self, hash: &Hash) -> PathBuf {
-        self.0.path_options.owned_outboard_path(hash)
+        self.0.options.owned_outboard_path(hash, IROH_BLOCK_SIZE)
     }
 }
 
@@ -161,9 +163,10 @@ impl ActorState {
         tables: &impl ReadableTables,
         hash: Hash,
     ) -> ActorResult<Option<EntryData>> {
-        let data_path = self.options.path.owned_data_path(&hash);
-        let outboard_path = self.options.path.owned_outboard_path(&hash);
-        let sizes_path = self.options.path.owned_sizes_path(&hash);
+        let block_size = entry_block_size(tables, &hash)?;
+        let data_path = self.options.owned_data_path(&hash);
+        let outboard_path = self.options.owned_outboard_path(&hash, block_size);
+        let sizes_path = self.options.owned_sizes_path(&hash, block_size);
         let entry = match tables.blobs().get(hash)? {
             Some(guard) => match guard.value() {
                 EntryState::Complete {
@@ -199,7 +202,8 @@ impl ActorState {
                             data.value().to_vec()
                         }
                     };
-                    let expected_outboard_size = raw_outboard_size(data.len() as u64);
+                    let expected_outboard_size =
+                        BaoTree::new(data.len() as u64, block_size).outboard_size();
                     let outboard = match outboard_location {
                         OutboardLocation::Owned => std::fs::read(outboard_path)?,
                         OutboardLocation::Inline(_) => tables
@@ -241,15 +245,18 @@ impl ActorState {
         hash: Hash,
         entry: Option<EntryData>,
     ) -> ActorResult<()> {
-        let data_path = self.options.path.owned_data_path(&hash);
-        let outboard_path = self.options.path.owned_outboard_path(&hash);
-        let sizes_path = self.options.path.owned_sizes_path(&hash);
+        let old_block_size = entry_block_size(tables, &hash)?;
+        let data_path = self.options.owned_data_path(&hash);
         // tabula rasa
-        std::fs::remove_file(&outboard_path).ok();
+        std::fs::remove_file(self.options.owned_outboard_path(&hash, old_block_size)).ok();
         std::fs::remove_file(&data_path).ok();
-        std::fs::remove_file(&sizes_path).ok();
+        std::fs::remove_file(self.options.owned_sizes_path(&hash, old_block_size)).ok();
         tables.inline_data.remove(&hash)?;
         tables.inline_outboard.remove(&hash)?;
+        tables.block_size.remove(&hash)?;
+        // the new entry data always uses the default chunk group size
+        let outboard_path = self.options.owned_outboard_path(&hash, IROH_BLOCK_SIZE);
+        let sizes_path = self.options.owned_sizes_path(&hash, IROH_BLOCK_SIZE);
         let Some(entry) = entry else {
             tables.blobs.remove(&hash)?;
             return Ok(());
@@ -378,7 +385,8 @@ pub fn make_partial(
                             let current_size = data.len() as u64;
                             if size < current_size {
                                 let size = size as usize;
-                                let sizes = SizeInfo::complete(current_size).to_vec();
+                                let sizes =
+                                    SizeInfo::complete(current_size).to_vec(IROH_BLOCK_SIZE);
                                 Some(EntryData::Partial {
                                     data: data[..size].to_vec(),
                                     outboard: outboard.to_vec(),
@@ -1,12 +1,20 @@
-use bao_tree::ChunkRanges;
+use bao_tree::{
+    io::{fsm::ResponseDecoder, outboard::PreOrderMemOutboard},
+    ChunkRanges,
+};
 use iroh_io::AsyncSliceReaderExt;
 use std::io::Cursor;
 
+use crate::get::fsm::decode_all_batch;
 use crate::store::bao_file::test_support::{
     decode_response_into_batch, make_wire_data, random_test_data, simulate_remote, validate,
 };
-use crate::store::{Map as _, MapEntryMut, MapMut, ReadableStore, Store as _};
-use crate::util::raw_outboard;
+use crate::store::{
+    ConsistencyCheckProgress, Map as _, MapEntryMut, MapMut, ReadableStore, ReportLevel, Store as _,
+};
+use crate::util::progress::FlumeProgressSender;
+use crate::util::reblock::ReblockOutboard;
+use crate::util::{raw_outboard, raw_outboard_size, TagUpdate};
 
 macro_rules! assert_matches {
         ($expression:expr, $pattern:pat) => {
@@ -52,6 +60,8 @@ async fn create_test_db() -> (tempfile::TempDir, Store) {
         path: PathOptions::new(testdir.path()),
         batch: Default::default(),
         inline: Default::default(),
+        encryption: None,
+        block_size: IROH_BLOCK_SIZE,
     };
     let db = Store::new(db_path, options).await.unwrap();
     (testdir, db)
@@ -472,6 +482,92 @@ async fn import_file_reference_cases() {
     }
 }
 
+#[tokio::test]
+async fn stats_cases() {
+    let np = IgnoreProgressSender::<ImportProgress>::default;
+    let (tempdir, db) = create_test_db().await;
+    let small = db
+        .import_bytes(
+            random_test_data(SMALL_SIZE as usize).into(),
+            BlobFormat::Raw,
+        )
+        .await
+        .unwrap();
+    let mid = db
+        .import_bytes(random_test_data(MID_SIZE as usize).into(), BlobFormat::Raw)
+        .await
+        .unwrap();
+    let path = tempdir.path().join("external.data");
+    std::fs::write(&path, random_test_data(MID_SIZE as usize + 1)).unwrap();
+    let (external, _) = db
+        .import_file(path, ImportMode::TryReference, BlobFormat::Raw, np())
+        .await
+        .unwrap();
+    let seq = [*small.hash(), *mid.hash()]
+        .into_iter()
+        .collect::<crate::hashseq::HashSeq>()
+        .into_inner();
+    let seq_size = seq.len() as u64;
+    let root = db.import_bytes(seq, BlobFormat::HashSeq).await.unwrap();
+    // a partial entry
+    let data = random_test_data(LARGE_SIZE as usize);
+    #[allow(clippy::single_range_in_vec_init)]
+    let ranges = [0..1024 * 64];
+    let (hash, chunk_ranges, wire_data) = make_wire_data(&data, &ranges);
+    let handle = db.get_or_create(hash, 0).await.unwrap();
+    decode_response_into_batch(
+        hash,
+        IROH_BLOCK_SIZE,
+        chunk_ranges,
+        Cursor::new(wire_data.as_slice()),
+        handle.batch_writer().await.unwrap(),
+    )
+    .await
+    .unwrap();
+    drop(handle);
+    db.sync().await.unwrap();
+
+    let stats = db.entry_stats().await.unwrap();
+    // the small blob and the hash seq are inlined
+    assert_eq!(stats.inline.count, 2);
+    assert_eq!(stats.inline.data_size, SMALL_SIZE + seq_size);
+    assert_eq!(stats.inline.outboard_size, 0);
+    assert_eq!(stats.owned.count, 1);
+    assert_eq!(stats.owned.data_size, MID_SIZE);
+    assert_eq!(stats.owned.outboard_size, raw_outboard_size(MID_SIZE));
+    assert_eq!(stats.external.count, 1);
+    assert_eq!(stats.external.data_size, MID_SIZE + 1);
+    assert_eq!(stats.partial.count, 1);
+    assert!(stats.partial.data_size >= 1024 * 64);
+    assert_eq!(stats.complete().count, 4);
+
+    db.set_tag("seq".into(), Some(*root.inner()), None)
+        .await
+        .unwrap();
+    db.set_tag("external".into(), Some(*external.inner()), None)
+        .await
+        .unwrap();
+    db.set_tag("partial".into(), Some(HashAndFormat::raw(hash)), None)
+        .await
+        .unwrap();
+    let stats = db.stats().await.unwrap();
+    assert_eq!(stats.entries.complete().count, 4);
+    let tag = |name: &str| {
+        let stats = stats.tags.iter().find(|stats| stats.tag == Tag::from(name));
+        stats.map(|stats| (stats.blobs, stats.size, stats.missing))
+    };
+    assert_eq!(tag("seq"), Some((3, seq_size + SMALL_SIZE + MID_SIZE, 0)));
+    assert_eq!(tag("external"), Some((1, MID_SIZE + 1, 0)));
+    assert_eq!(tag("partial"), Some((0, 0, 1)));
+    // computing the stats does not count as an access of the reachable blobs
+    for usage in db.blob_usage().await.unwrap() {
+        let usage = usage.unwrap();
+        if usage.hash != *root.hash() {
+            assert_eq!(usage.last_access, None);
+        }
+    }
+}
+
 #[tokio::test]
 async fn import_file_error_cases() {
     let np = IgnoreProgressSender::<ImportProgress>::default;
@@ -536,7 +632,7 @@ async fn import_file_datadir_is_file() {
     let (tempdir, db) = create_test_db().await;
     // temp dir is readonly, this is a bit mean since we mess with the internals of the store
     {
-        let data_dir = db.0.path_options.data_path.to_owned();
+        let data_dir = db.0.options.path.data_path.to_owned();
         std::fs::remove_dir_all(&data_dir).unwrap();
         std::fs::write(data_dir, []).unwrap();
         let path = tempdir.path().join("mid.data");
@@ -781,6 +877,8 @@ async fn actor_store_smoke() {
         path: PathOptions::new(testdir.path()),
         batch: Default::default(),
         inline: Default::default(),
+        encryption: None,
+        block_size: IROH_BLOCK_SIZE,
     };
     let db = Store::new(db_path, options).await.unwrap();
     db.dump().await.unwrap();
@@ -803,3 +901,353 @@ async fn actor_store_smoke() {
     db.sync().await.unwrap();
     db.dump().await.unwrap();
 }
+
+async fn create_encrypted_test_db(path: &Path, key: Option<EncryptionKey>) -> io::Result<Store> {
+    let db_path = path.join("db.redb");
+    let options = Options {
+        path: PathOptions::new(path),
+        batch: Default::default(),
+        inline: Default::default(),
+        encryption: key,
+        block_size: IROH_BLOCK_SIZE,
+    };
+    Store::new(db_path, options).await
+}
+
+#[tokio::test]
+async fn encrypted_store_cases() {
+    let np = IgnoreProgressSender::<ImportProgress>::default;
+    let testdir = tempfile::tempdir().unwrap();
+    let key = EncryptionKey::generate();
+    let db = create_encrypted_test_db(testdir.path(), Some(key.clone()))
+        .await
+        .unwrap();
+    let mut hashes = Vec::new();
+    for size in [SMALL_SIZE, MID_SIZE, LARGE_SIZE] {
+        let data = Bytes::from(random_test_data(size as usize));
+        let tt = db
+            .import_bytes(data.clone(), BlobFormat::Raw)
+            .await
+            .unwrap();
+        let hash = *tt.hash();
+        // the hash refers to the plaintext
+        assert_eq!(hash, Hash::new(&data));
+        // reads return the plaintext, also for ranges
+        let entry = db.get(&hash).await.unwrap().expect("entry not found");
+        let actual = entry.data_reader().read_to_end().await.unwrap();
+        assert_eq!(actual, data);
+        let actual = entry.data_reader().read_at(size / 2, 100).await.unwrap();
+        assert_eq!(&actual[..], &data[(size / 2) as usize..][..100]);
+        // the stored data and outboard are not the plaintext
+        let (outboard, _) = raw_outboard(&data);
+        let Some(EntryData::Complete {
+            data: raw_data,
+            outboard: raw_outboard,
+        }) = db.get_full_entry_state(hash).await.unwrap()
+        else {
+            panic!("entry not complete");
+        };
+        assert_eq!(raw_data.len(), data.len());
+        assert_ne!(raw_data, data);
+        assert_eq!(raw_outboard.len(), outboard.len());
+        if !outboard.is_empty() {
+            assert_ne!(raw_outboard, outboard);
+        }
+        // export decrypts, and never hands out the encrypted file
+        let target = testdir.path().join(format!("export-{size}"));
+        for mode in [ExportMode::Copy, ExportMode::TryReference] {
+            db.export(hash, target.clone(), mode, Box::new(|_| Ok(())))
+                .await
+                .unwrap();
+            assert_eq!(std::fs::read(&target).unwrap(), data);
+        }
+        db.validate(false, IgnoreProgressSender::default().boxed())
+            .await
+            .unwrap();
+        hashes.push((hash, data, tt));
+    }
+    // referencing an external file copies and encrypts it
+    let mid = random_test_data(MID_SIZE as usize);
+    let path = testdir.path().join("mid.data");
+    std::fs::write(&path, &mid).unwrap();
+    let (tt, _) = db
+        .import_file(path, ImportMode::TryReference, BlobFormat::Raw, np())
+        .await
+        .unwrap();
+    let hash = *tt.hash();
+    assert_matches!(
+        db.entry_state(hash).await.unwrap().db,
+        Some(EntryState::Complete {
+            data_location: DataLocation::Owned(MID_SIZE),
+            outboard_location: OutboardLocation::Inline(_),
+        })
+    );
+    let raw_data = std::fs::read(db.owned_data_path(&hash)).unwrap();
+    assert_ne!(raw_data, mid);
+    // file names do not reveal the hashes of the blobs
+    let names = std::fs::read_dir(testdir.path().join("data"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    assert!(!names.is_empty());
+    for hash in hashes.iter().map(|(hash, _, _)| hash).chain([&hash]) {
+        assert!(names.iter().all(|name| !name.contains(&hash.to_hex())));
+    }
+    drop(tt);
+    db.sync().await.unwrap();
+    db.shutdown().await;
+    drop(db);
+    // opening without the key or with a different key fails
+    assert!(create_encrypted_test_db(testdir.path(), None)
+        .await
+        .is_err());
+    assert!(
+        create_encrypted_test_db(testdir.path(), Some(EncryptionKey::generate()))
+            .await
+            .is_err()
+    );
+    // opening with the same key works
+    let db = create_encrypted_test_db(testdir.path(), Some(key))
+        .await
+        .unwrap();
+    for (hash, data, _tt) in hashes {
+        let entry = db.get(&hash).await.unwrap().expect("entry not found");
+        let actual = entry.data_reader().read_to_end().await.unwrap();
+        assert_eq!(actual, data);
+    }
+}
+
+#[tokio::test]
+async fn encrypted_store_partial() {
+    let testdir = tempfile::tempdir().unwrap();
+    let key = EncryptionKey::generate();
+    let db = create_encrypted_test_db(testdir.path(), Some(key))
+        .await
+        .unwrap();
+    let data = random_test_data(1024 * 1024);
+    // write the second half first, so the entry is partial on disk for a while
+    let ranges = [512 * 1024..data.len() as u64, 0..512 * 1024];
+    let handle = db.get_or_create(Hash::new(&data), 0).await.unwrap();
+    for range in ranges.iter() {
+        let (hash, chunk_ranges, wire_data) = make_wire_data(&data, std::slice::from_ref(range));
+        decode_response_into_batch(
+            hash,
+            IROH_BLOCK_SIZE,
+            chunk_ranges,
+            Cursor::new(wire_data.as_slice()),
+            handle.batch_writer().await.unwrap(),
+        )
+        .await
+        .unwrap();
+        validate(&handle, &data, std::slice::from_ref(range)).await;
+    }
+    let raw_data = std::fs::read(db.owned_data_path(&handle.hash())).unwrap();
+    assert_eq!(raw_data.len(), data.len());
+    assert_ne!(raw_data, data);
+    db.insert_complete(handle.clone()).await.unwrap();
+    db.sync().await.unwrap();
+    #[allow(clippy::single_range_in_vec_init)]
+    validate(&handle, &data, &[0..data.len() as u64]).await;
+}
+
+#[tokio::test]
+async fn encrypted_store_requires_empty_store() {
+    let testdir = tempfile::tempdir().unwrap();
+    let db = create_encrypted_test_db(testdir.path(), None)
+        .await
+        .unwrap();
+    let tt = db
+        .import_bytes(Bytes::from_static(b"hello"), BlobFormat::Raw)
+        .await
+        .unwrap();
+    db.set_tag("hello".into(), Some(*tt.inner()), None)
+        .await
+        .unwrap();
+    db.sync().await.unwrap();
+    db.shutdown().await;
+    drop(db);
+    let res = create_encrypted_test_db(testdir.path(), Some(EncryptionKey::generate())).await;
+    assert!(res.is_err());
+}
+
+async fn create_block_size_test_db(path: &Path, block_size: BlockSize) -> Store {
+    let options = Options {
+        path: PathOptions::new(path),
+        batch: Default::default(),
+        // keep all outboards in files, so they can be inspected
+        inline: InlineOptions::NO_INLINE,
+        encryption: None,
+        block_size,
+    };
+    Store::new(path.join("db.redb"), options).await.unwrap()
+}
+
+/// Encode the entire blob with the protocol chunk group size, like the provider does.
+async fn encode_for_wire(entry: &BaoFileHandle) -> Vec<u8> {
+    let outboard = ReblockOutboard::new(
+        entry.outboard().unwrap(),
+        entry.data_reader(),
+        IROH_BLOCK_SIZE,
+    );
+    let mut encoded = Vec::new();
+    bao_tree::io::fsm::encode_ranges_validated(
+        entry.data_reader(),
+        outboard,
+        &ChunkRanges::all(),
+        &mut encoded,
+    )
+    .await
+    .unwrap();
+    encoded
+}
+
+#[tokio::test]
+async fn block_size_cases() {
+    let testdir = tempfile::tempdir().unwrap();
+    let large_blocks = BlockSize::from_chunk_log(6);
+    let small_blocks = BlockSize::from_chunk_log(2);
+    let data = random_test_data(1024 * 1024 + 17);
+    let size = data.len() as u64;
+    let (hash, _, wire_data) = make_wire_data(&data, std::slice::from_ref(&(0..size)));
+
+    // an imported blob gets an outboard with the configured chunk group size
+    let src = create_block_size_test_db(&testdir.path().join("src"), large_blocks).await;
+    let tt = src
+        .import_bytes(data.clone().into(), BlobFormat::Raw)
+        .await
+        .unwrap();
+    assert_eq!(tt.hash(), &hash);
+    let outboard_path = src.0.options.owned_outboard_path(&hash, large_blocks);
+    let outboard = std::fs::read(outboard_path).unwrap();
+    assert_eq!(
+        outboard.len() as u64,
+        BaoTree::new(size, large_blocks).outboard_size()
+    );
+    assert_eq!(
+        outboard,
+        PreOrderMemOutboard::create(&data, large_blocks).data
+    );
+    assert!(!src.owned_outboard_path(&hash).exists());
+    // it is sent with the protocol chunk group size
+    let entry = src.get(&hash).await.unwrap().unwrap();
+    assert_eq!(encode_for_wire(&entry).await, wire_data[8..]);
+
+    // a received blob is stored with the chunk group size of the receiving store
+    let dst_path = testdir.path().join("dst");
+    let dst = create_block_size_test_db(&dst_path, small_blocks).await;
+    let handle = dst.get_or_create(hash, 0).await.unwrap();
+    // write the second half first, so the entry is partial on disk for a while
+    for range in [size / 2..size, 0..size / 2] {
+        let (_, chunk_ranges, wire_data) = make_wire_data(&data, std::slice::from_ref(&range));
+        let decoder = ResponseDecoder::new(
+            hash.into(),
+            chunk_ranges,
+            BaoTree::new(size, IROH_BLOCK_SIZE),
+            Cursor::new(&wire_data[8..]),
+        );
+        decode_all_batch(decoder, handle.batch_writer().await.unwrap())
+            .await
+            .unwrap();
+    }
+    dst.insert_complete(handle).await.unwrap();
+    dst.sync().await.unwrap();
+    let outboard_path = dst.0.options.owned_outboard_path(&hash, small_blocks);
+    assert_eq!(
+        std::fs::read(outboard_path).unwrap(),
+        PreOrderMemOutboard::create(&data, small_blocks).data
+    );
+    dst.shutdown().await;
+    drop(dst);
+
+    // the chunk group size of existing entries survives reopening with other options
+    let dst = create_block_size_test_db(&dst_path, IROH_BLOCK_SIZE).await;
+    let entry = dst.get(&hash).await.unwrap().unwrap();
+    assert_eq!(entry.block_size(), small_blocks);
+    #[allow(clippy::single_range_in_vec_init)]
+    validate(&entry, &data, &[0..size]).await;
+    assert_eq!(encode_for_wire(&entry).await, wire_data[8..]);
+    // the outboard with the non-default chunk group size is not an orphan
+    let (tx, rx) = flume::unbounded();
+    dst.consistency_check(false, FlumeProgressSender::new(tx).boxed())
+        .await
+        .unwrap();
+    assert!(!rx.drain().any(|x| matches!(
+        x,
+        ConsistencyCheckProgress::Update {
+            level: ReportLevel::Warn | ReportLevel::Error,
+            ..
+        }
+    )));
+}
+
+#[tokio::test]
+async fn tag_update_cases() {
+    let (_tempdir, db) = create_test_db().await;
+    let a = HashAndFormat::raw(Hash::new(b"a"));
+    let b = HashAndFormat::raw(Hash::new(b"b"));
+    let tags = |prefix: &str| {
+        let db = db.clone();
+        let prefix = Tag::from(prefix);
+        async move {
+            db.tags_with_prefix(prefix)
+                .await
+                .unwrap()
+                .collect::<io::Result<Vec<_>>>()
+                .unwrap()
+        }
+    };
+    db.set_tag("x/1".into(), Some(a), None).await.unwrap();
+    db.set_tag("y/1".into(), Some(a), None).await.unwrap();
+
+    // a batch with one failing expectation changes nothing
+    let updates = vec![
+        TagUpdate::set("x/1".into(), b).if_equals(Some(a)),
+        TagUpdate::set("x/2".into(), b).if_equals(Some(a)),
+    ];
+    assert!(!db.update_tags(updates).await.unwrap());
+    assert_eq!(tags("x/").await, vec![("x/1".into(), a)]);
+
+    // a batch where all expectations hold is applied entirely
+    let updates = vec![
+        TagUpdate::set("x/1".into(), b).if_equals(Some(a)),
+        TagUpdate::set("x/2".into(), a).if_equals(None),
+        TagUpdate::delete("y/1".into()),
+    ];
+    assert!(db.update_tags(updates).await.unwrap());
+    assert_eq!(tags("x/").await, vec![("x/1".into(), b), ("x/2".into(), a)]);
+    assert_eq!(tags("y/").await, vec![]);
+
+    // updated tags can expire like any other tag
+    let updates = vec![TagUpdate::set("y/2".into(), a).with_ttl(Duration::ZERO)];
+    assert!(db.update_tags(updates).await.unwrap());
+    let expired = db.remove_expired_tags().await.unwrap();
+    assert_eq!(expired, vec![("y/2".into(), a)]);
+
+    // expired tags that were not removed yet are treated as absent
+    let updates = vec![TagUpdate::set("y/3".into(), a).with_ttl(Duration::ZERO)];
+    assert!(db.update_tags(updates).await.unwrap());
+    assert_eq!(tags("y/").await, vec![]);
+    assert!(db
+        .tags()
+        .await
+        .unwrap()
+        .all(|item| item.unwrap().0 != "y/3".into()));
+    let updates = vec![TagUpdate::set("y/3".into(), b).if_equals(Some(a))];
+    assert!(!db.update_tags(updates).await.unwrap());
+    let updates = vec![TagUpdate::set("y/3".into(), b).if_equals(None)];
+    assert!(db.update_tags(updates).await.unwrap());
+    assert_eq!(tags("y/").await, vec![("y/3".into(), b)]);
+    assert!(db.remove_expired_tags().await.unwrap().is_empty());
+    assert!(db
+        .update_tags(vec![TagUpdate::delete("y/3".into())])
+        .await
+        .unwrap());
+
+    // tags sorting right before and after the prefix are neither listed nor deleted
+    db.set_tag("w/1".into(), Some(a), None).await.unwrap();
+    db.set_tag("x0".into(), Some(a), None).await.unwrap();
+    assert_eq!(tags("x/").await, vec![("x/1".into(), b), ("x/2".into(), a)]);
+    let deleted = db.delete_tags_with_prefix("x/".into()).await.unwrap();
+    assert_eq!(deleted, vec![("x/1".into(), b), ("x/2".into(), a)]);
+    assert_eq!(tags("").await, vec![("w/1".into(), a), ("x0".into(), a)]);
+}
@@ -1,10 +1,12 @@
 use std::{
     fs::OpenOptions,
-    io::{self, Write},
+    io::{self, Read, Write},
     path::Path,
     time::{Duration, Instant},
 };
 
+use crate::store::encryption::BlobCipher;
+
 /// A reader that calls a callback with the number of bytes read after each read.
 pub(crate) struct ProgressReader<R, F: Fn(u64) -> io::Result<()>> {
     inner: R,
@@ -60,6 +62,34 @@ pub fn overwrite_and_sync(path: &Path, data: &[u8]) -> io::Result<std::fs::File>
     Ok(file)
 }
 
+/// Copy a file to a new location, applying a cipher to the content.
+///
+/// This is used instead of renaming or reflinking files when the store is
+/// encrypted. Since the cipher is a stream cipher, the same function is used
+/// for encrypting and decrypting.
+pub fn copy_with_cipher(source: &Path, target: &Path, cipher: &BlobCipher) -> io::Result<()> {
+    tracing::trace!(
+        "copying {} to {} with cipher",
+        source.display(),
+        target.display()
+    );
+    let mut source = std::fs::File::open(source)?;
+    let mut target = std::fs::File::create(target)?;
+    let mut buf = vec![0u8; 1024 * 1024];
+    let mut offset = 0;
+    loop {
+        let n = source.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        cipher.apply(offset, &mut buf[..n]);
+        target.write_all(&buf[..n])?;
+        offset += n as u64;
+    }
+    target.sync_all()?;
+    Ok(())
+}
+
 /// Read a file into memory and then delete it.
 pub fn read_and_remove(path: &Path) -> io::Result<Vec<u8>> {
     let data = std::fs::read(path)?;
@@ -1,16 +1,19 @@
 //! Validation of the store's contents.
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
+use bao_tree::{BaoTree, BlockSize};
 use redb::ReadableTable;
 
 use crate::{
-    store::{fs::tables::BaoFilePart, ConsistencyCheckProgress, ReportLevel},
+    store::{
+        encryption::file_stem, fs::tables::BaoFilePart, ConsistencyCheckProgress, ReportLevel,
+    },
     util::progress::BoxedProgressSender,
+    IROH_BLOCK_SIZE,
 };
 
 use super::{
-    raw_outboard_size, tables::Tables, ActorResult, ActorState, DataLocation, EntryState, Hash,
-    OutboardLocation,
+    tables::Tables, ActorResult, ActorState, DataLocation, EntryState, Hash, OutboardLocation,
 };
 
 impl ActorState {
@@ -110,6 +113,13 @@ impl ActorState {
             let inline_data = &mut tables.inline_data;
             let inline_outboard = &mut tables.inline_outboard;
             let tags = &mut tables.tags;
+            let block_sizes = &tables.block_size;
+            let entry_block_size = |hash: &Hash| -> Result<BlockSize, redb::StorageError> {
+                Ok(match block_sizes.get(hash)? {
+                    Some(log) => BlockSize::from_chunk_log(log.value()),
+                    None => IROH_BLOCK_SIZE,
+                })
+            };
             let mut orphaned_inline_data = BTreeSet::new();
             let mut orphaned_inline_outboard = BTreeSet::new();
             let mut orphaned_data = BTreeSet::new();
@@ -211,6 +221,10 @@ impl ActorState {
                         let hash = hash.value();
                         entries.insert(hash);
                         entry_info!(hash, "validating blob");
+                        let Ok(block_size) = entry_block_size(&hash) else {
+                            entry_error!(hash, "block size can not be accessed");
+                            continue;
+                        };
                         let entry = entry.value();
                         match entry {
                             EntryState::Complete {
@@ -230,7 +244,7 @@ impl ActorState {
                                         inline_data.value().len() as u64
                                     }
                                     DataLocation::Owned(size) => {
-                                        let path = self.options.path.owned_data_path(&hash);
+                                        let path = self.options.owned_data_path(&hash);
                                         let Ok(metadata) = path.metadata() else {
                                             entry_error!(hash, "owned data file does not exist");
                                             continue;
@@ -269,6 +283,8 @@ impl ActorState {
                                         size
                                     }
                                 };
+                                let expected_outboard_size =
+                                    BaoTree::new(data_size, block_size).outboard_size();
                                 match outboard_location {
                                     OutboardLocation::Inline(_) => {
                                         let Ok(inline_outboard) = inline_outboard.get(hash) else {
@@ -283,13 +299,15 @@ impl ActorState {
                                             continue;
                                         };
                                         let outboard_size = inline_outboard.value().len() as u64;
-                                        if outboard_size != raw_outboard_size(data_size) {
+                                        if outboard_size != expected_outboard_size {
                                             entry_error!(hash, "inline outboard size mismatch");
                                         }
                                     }
                                     OutboardLocation::Owned => {
-                                        let Ok(metadata) =
-                                            self.options.path.owned_outboard_path(&hash).metadata()
+                                        let Ok(metadata) = self
+                                            .options
+                                            .owned_outboard_path(&hash, block_size)
+                                            .metadata()
                                         else {
                                             entry_error!(
                                                 hash,
@@ -298,12 +316,12 @@ impl ActorState {
                                             continue;
                                         };
                                         let outboard_size = metadata.len();
-                                        if outboard_size != raw_outboard_size(data_size) {
+                                        if outboard_size != expected_outboard_size {
                                             entry_error!(hash, "owned outboard size mismatch");
                                         }
                                     }
                                     OutboardLocation::NotNeeded => {
-                                        if raw_outboard_size(data_size) != 0 {
+                                        if expected_outboard_size != 0 {
                                             entry_error!(
                                                 hash,
                                                 "outboard not needed but data size is not zero"
@@ -313,10 +331,10 @@ impl ActorState {
                                 }
                             }
                             EntryState::Partial { .. } => {
-                                if !self.options.path.owned_data_path(&hash).exists() {
+                                if !self.options.owned_data_path(&hash).exists() {
                                     entry_error!(hash, "persistent partial entry has no data");
                                 }
-                                if !self.options.path.owned_outboard_path(&hash).exists() {
+                                if !self.options.owned_outboard_path(&hash, block_size).exists() {
                                     entry_error!(hash, "persistent partial entry has no outboard");
                                 }
                             }
@@ -372,6 +390,21 @@ impl ActorState {
                 }
             };
             info!("checking for unexpected or orphaned files");
+            // with encryption, file names can only be mapped back to the hashes of known entries
+            let stems = self.options.encryption.as_ref().map(|key| {
+                entries
+                    .iter()
+                    .map(|hash| (file_stem(Some(key), hash), *hash))
+                    .collect::<BTreeMap<_, _>>()
+            });
+            let hash_from_stem = |stem: &str| match &stems {
+                Some(stems) => stems.get(stem).copied(),
+                None => {
+                    let mut hash = [0u8; 32];
+                    hex::decode_to_slice(stem, &mut hash).ok()?;
+                    Some(Hash::from(hash))
+                }
+            };
             for entry in self.options.path.data_path.read_dir()? {
                 let entry = entry?;
                 let path = entry.path();
@@ -382,12 +415,10 @@ impl ActorState {
                 match path.extension().and_then(|x| x.to_str()) {
                     Some("data") => match path.file_stem().and_then(|x| x.to_str()) {
                         Some(stem) => {
-                            let mut hash = [0u8; 32];
-                            let Ok(_) = hex::decode_to_slice(stem, &mut hash) else {
+                            let Some(hash) = hash_from_stem(stem) else {
                                 warn!("unexpected data file in data directory: {}", path.display());
                                 continue;
                             };
-                            let hash = Hash::from(hash);
                             if !entries.contains(&hash) {
                                 orphaned_data.insert(hash);
                                 entry_warn!(hash, "orphaned data file");
@@ -397,52 +428,72 @@ impl ActorState {
                             warn!("unexpected data file in data directory: {}", path.display());
                         }
                     },
-                    Some("obao4") => match path.file_stem().and_then(|x| x.to_str()) {
-                        Some(stem) => {
-                            let mut hash = [0u8; 32];
-                            let Ok(_) = hex::decode_to_slice(stem, &mut hash) else {
+                    Some(ext) if ext.starts_with("obao") => {
+                        match path.file_stem().and_then(|x| x.to_str()) {
+                            Some(stem) => {
+                                let Ok(chunk_log) = ext["obao".len()..].parse::<u8>() else {
+                                    warn!(
+                                        "unexpected outboard file in data directory: {}",
+                                        path.display()
+                                    );
+                                    continue;
+                                };
+                                let block_size = BlockSize::from_chunk_log(chunk_log);
+                                let Some(hash) = hash_from_stem(stem) else {
+                                    warn!(
+                                        "unexpected outboard file in data directory: {}",
+                                        path.display()
+                                    );
+                                    continue;
+                                };
+                                if !entries.contains(&hash)
+                                    || entry_block_size(&hash).ok() != Some(block_size)
+                                {
+                                    orphaned_outboardard.insert((hash, block_size));
+                                    entry_warn!(hash, "orphaned outboard file");
+                                }
+                            }
+                            None => {
                                 warn!(
                                     "unexpected outboard file in data directory: {}",
                                     path.display()
                                 );
-                                continue;
-                            };
-                            let hash = Hash::from(hash);
-                            if !entries.contains(&hash) {
-                                orphaned_outboardard.insert(hash);
-                                entry_warn!(hash, "orphaned outboard file");
                             }
                         }
-                        None => {
-                            warn!(
-                                "unexpected outboard file in data directory: {}",
-                                path.display()
-                            );
-                        }
-                    },
-                    Some("sizes4") => match path.file_stem().and_then(|x| x.to_str()) {
-                        Some(stem) => {
-                            let mut hash = [0u8; 32];
-                            let Ok(_) = hex::decode_to_slice(stem, &mut hash) else {
+                    }
+                    Some(ext) if ext.starts_with("sizes") => {
+                        match path.file_stem().and_then(|x| x.to_str()) {
+                            Some(stem) => {
+                                let Ok(chunk_log) = ext["sizes".len()..].parse::<u8>() else {
+                                    warn!(
+                                        "unexpected outboard file in data directory: {}",
+                                        path.display()
+                                    );
+                                    continue;
+                                };
+                                let block_size = BlockSize::from_chunk_log(chunk_log);
+                                let Some(hash) = hash_from_stem(stem) else {
+                                    warn!(
+                                        "unexpected outboard file in data directory: {}",
+                                        path.display()
+                                    );
+                                    continue;
+                                };
+                                if !entries.contains(&hash)
+                                    || entry_block_size(&hash).ok() != Some(block_size)
+                                {
+                                    orphaned_sizes.insert((hash, block_size));
+                                    entry_warn!(hash, "orphaned outboard file");
+                                }
+                            }
+                            None => {
                                 warn!(
                                     "unexpected outboard file in data directory: {}",
                                     path.display()
                                 );
-                                continue;
-                            };
-                            let hash = Hash::from(hash);
-                            if !entries.contains(&hash) {
-                                orphaned_sizes.insert(hash);
-                                entry_warn!(hash, "orphaned outboard file");
                             }
                         }
-                        None => {
-                            warn!(
-                                "unexpected outboard file in data directory: {}",
-                                path.display()
-                            );
-                        }
-                    },
+                    }
                     _ => {
                         warn!("unexpected file in data directory: {}", path.display());
                     }
@@ -461,15 +512,15 @@ impl ActorState {
                 for hash in orphaned_data {
                     tables.delete_after_commit.insert(hash, [BaoFilePart::Data]);
                 }
-                for hash in orphaned_outboardard {
+                for (hash, block_size) in orphaned_outboardard {
                     tables
                         .delete_after_commit
-                        .insert(hash, [BaoFilePart::Outboard]);
+                        .insert(hash, [BaoFilePart::Outboard(block_size)]);
                 }
-                for hash in orphaned_sizes {
+                for (hash, block_size) in orphaned_sizes {
                     tables
                         .delete_after_commit
-                        .insert(hash, [BaoFilePart::Sizes]);
+                        .insert(hash, [BaoFilePart::Sizes(block_size)]);
                 }
             }
         }
@@ -478,9 +529,13 @@ impl ActorState {
             info!("repairing - deleting orphaned files");
             for (hash, part) in delete_after_commit.into_inner() {
                 let path = match part {
-                    BaoFilePart::Data => self.options.path.owned_data_path(&hash),
-                    BaoFilePart::Outboard => self.options.path.owned_outboard_path(&hash),
-                    BaoFilePart::Sizes => self.options.path.owned_sizes_path(&hash),
+                    BaoFilePart::Data => self.options.owned_data_path(&hash),
+                    BaoFilePart::Outboard(block_size) => {
+                        self.options.owned_outboard_path(&hash, block_size)
+                    }
+                    BaoFilePart::Sizes(block_size) => {
+                        self.options.owned_sizes_path(&hash, block_size)
+                    }
                 };
                 entry_info!(hash, "deleting orphaned file: {}", path.display());
                 if let Err(cause) = std::fs::remove_file(&path) {
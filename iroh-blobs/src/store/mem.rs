@@ -13,17 +13,18 @@ use std::{
     collections::BTreeMap,
     io,
     path::PathBuf,
-    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
-    time::SystemTime,
+    sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::{Duration, SystemTime},
 };
 
 use crate::{
     store::{
-        mutable_mem_storage::MutableMemStorage, BaoBlobSize, MapEntry, MapEntryMut, ReadableStore,
+        mutable_mem_storage::MutableMemStorage, BaoBlobSize, BlobUsage, EntryStats, MapEntry,
+        MapEntryMut, ReadableStore,
     },
     util::{
         progress::{BoxedProgressSender, IdGenerator, IgnoreProgressSender, ProgressSender},
-        TagCounter, TagDrop,
+        TagCounter, TagDrop, TagUpdate,
     },
     Tag, TempTag, IROH_BLOCK_SIZE,
 };
@@ -38,6 +39,9 @@ use super::{
 #[derive(Debug, Clone, Default)]
 pub struct Store {
     inner: Arc<StoreInner>,
+    /// Last access times, behind a separate lock so reads do not need to take
+    /// the write lock of the store.
+    last_access: Arc<Mutex<BTreeMap<Hash, SystemTime>>>,
 }
 
 #[derive(Debug, Default)]
@@ -201,23 +205,94 @@ impl super::Store for Store {
         .await?
     }
 
-    async fn set_tag(&self, name: Tag, value: Option<HashAndFormat>) -> io::Result<()> {
+    async fn set_tag(
+        &self,
+        name: Tag,
+        value: Option<HashAndFormat>,
+        ttl: Option<Duration>,
+    ) -> io::Result<()> {
         let mut state = self.write_lock();
         if let Some(value) = value {
+            state.set_expiry(name.clone(), ttl);
             state.tags.insert(name, value);
         } else {
+            state.tag_expiry.remove(&name);
             state.tags.remove(&name);
         }
         Ok(())
     }
 
-    async fn create_tag(&self, hash: HashAndFormat) -> io::Result<Tag> {
+    async fn update_tags(&self, updates: Vec<TagUpdate>) -> io::Result<bool> {
+        let mut state = self.write_lock();
+        let now = SystemTime::now();
+        if !updates
+            .iter()
+            .all(|u| u.matches(state.live_tag(&u.name, now)))
+        {
+            return Ok(false);
+        }
+        for TagUpdate {
+            name, value, ttl, ..
+        } in updates
+        {
+            if let Some(value) = value {
+                state.set_expiry(name.clone(), ttl);
+                state.tags.insert(name, value);
+            } else {
+                state.tag_expiry.remove(&name);
+                state.tags.remove(&name);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn create_tag(&self, hash: HashAndFormat, ttl: Option<Duration>) -> io::Result<Tag> {
         let mut state = self.write_lock();
         let tag = Tag::auto(SystemTime::now(), |x| state.tags.contains_key(x));
+        state.set_expiry(tag.clone(), ttl);
         state.tags.insert(tag.clone(), hash);
         Ok(tag)
     }
 
+    async fn remove_expired_tags(&self) -> io::Result<Vec<(Tag, HashAndFormat)>> {
+        let mut state = self.write_lock();
+        let now = SystemTime::now();
+        #[allow(clippy::mutable_key_type)]
+        let expired = state
+            .tag_expiry
+            .iter()
+            .filter(|(_, expiry)| **expiry <= now)
+            .map(|(tag, _)| tag.clone())
+            .collect::<Vec<_>>();
+        let mut removed = Vec::new();
+        for tag in expired {
+            state.tag_expiry.remove(&tag);
+            if let Some(value) = state.tags.remove(&tag) {
+                removed.push((tag, value));
+            }
+        }
+        Ok(removed)
+    }
+
+    async fn delete_tags_with_prefix(&self, prefix: Tag) -> io::Result<Vec<(Tag, HashAndFormat)>> {
+        let mut state = self.write_lock();
+        #[allow(clippy::mutable_key_type)]
+        let matching = state
+            .tags
+            .range(prefix.clone()..)
+            .take_while(|(tag, _)| tag.starts_with(&prefix.0))
+            .map(|(tag, _)| tag.clone())
+            .collect::<Vec<_>>();
+        let mut removed = Vec::new();
+        for tag in matching {
+            state.tag_expiry.remove(&tag);
+            if let Some(value) = state.tags.remove(&tag) {
+                removed.push((tag, value));
+            }
+        }
+        Ok(removed)
+    }
+
     fn temp_tag(&self, tag: HashAndFormat) -> TempTag {
         self.inner.temp_tag(tag)
     }
@@ -231,6 +306,7 @@ impl super::Store for Store {
         for hash in hashes {
             if !state.temp.contains(&hash) {
                 state.entries.remove(&hash);
+                self.last_access.lock().unwrap().remove(&hash);
             }
         }
         Ok(())
@@ -243,9 +319,36 @@ impl super::Store for Store {
 struct StateInner {
     entries: BTreeMap<Hash, Entry>,
     tags: BTreeMap<Tag, HashAndFormat>,
+    tag_expiry: BTreeMap<Tag, SystemTime>,
     temp: TempCounterMap,
 }
 
+impl StateInner {
+    /// The value of a tag, or `None` if it does not exist or is expired.
+    fn live_tag(&self, tag: &Tag, now: SystemTime) -> Option<&HashAndFormat> {
+        if self
+            .tag_expiry
+            .get(tag)
+            .is_some_and(|expiry| *expiry <= now)
+        {
+            return None;
+        }
+        self.tags.get(tag)
+    }
+
+    /// Set or clear the expiry of a tag.
+    fn set_expiry(&mut self, tag: Tag, ttl: Option<Duration>) {
+        match ttl.and_then(|ttl| SystemTime::now().checked_add(ttl)) {
+            Some(expiry) => {
+                self.tag_expiry.insert(tag, expiry);
+            }
+            None => {
+                self.tag_expiry.remove(&tag);
+            }
+        }
+    }
+}
+
 /// An in memory entry
 #[derive(Debug, Clone)]
 pub struct Entry {
@@ -325,7 +428,11 @@ impl super::BaoBatchWriter for BatchWriter {
         size: u64,
         batch: Vec<bao_tree::io::fsm::BaoContentItem>,
     ) -> io::Result<()> {
-        self.0.data.write().unwrap().write_batch(size, &batch)
+        self.0
+            .data
+            .write()
+            .unwrap()
+            .write_batch(size, &batch, IROH_BLOCK_SIZE)
     }
 
     async fn sync(&mut self) -> io::Result<()> {
@@ -337,7 +444,14 @@ impl super::Map for Store {
     type Entry = Entry;
 
     async fn get(&self, hash: &Hash) -> std::io::Result<Option<Self::Entry>> {
-        Ok(self.inner.0.read().unwrap().entries.get(hash).cloned())
+        let entry = self.read_lock().entries.get(hash).cloned();
+        if entry.is_some() {
+            self.last_access
+                .lock()
+                .unwrap()
+                .insert(*hash, SystemTime::now());
+        }
+        Ok(entry)
     }
 }
 
@@ -349,13 +463,19 @@ impl super::MapMut for Store {
     }
 
     async fn get_or_create(&self, hash: Hash, _size: u64) -> std::io::Result<Entry> {
-        let entry = Entry {
-            inner: Arc::new(EntryInner {
-                hash,
-                data: RwLock::new(MutableMemStorage::default()),
-            }),
-            complete: false,
-        };
+        // partial entries are kept, so data that is downloaded in parts adds up
+        let entry = self
+            .write_lock()
+            .entries
+            .entry(hash)
+            .or_insert_with(|| Entry {
+                inner: Arc::new(EntryInner {
+                    hash,
+                    data: RwLock::new(MutableMemStorage::default()),
+                }),
+                complete: false,
+            })
+            .clone();
         Ok(entry)
     }
 
@@ -413,11 +533,49 @@ impl ReadableStore for Store {
         ))
     }
 
+    async fn blob_usage(&self) -> io::Result<crate::store::DbIter<BlobUsage>> {
+        let state = self.read_lock();
+        let last_access = self.last_access.lock().unwrap();
+        let usage = state
+            .entries
+            .iter()
+            .map(|(hash, entry)| {
+                let size = entry.inner.data.read().unwrap().current_size();
+                Ok(BlobUsage {
+                    hash: *hash,
+                    size,
+                    complete_size: entry.complete.then_some(size),
+                    last_access: last_access.get(hash).copied(),
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(Box::new(usage.into_iter()))
+    }
+
+    async fn entry_stats(&self) -> io::Result<EntryStats> {
+        let mut stats = EntryStats::default();
+        for entry in self.read_lock().entries.values() {
+            let storage = entry.inner.data.read().unwrap();
+            let group = match entry.complete {
+                true => &mut stats.owned,
+                false => &mut stats.partial,
+            };
+            group.add(storage.data.len() as u64, storage.outboard.len() as u64);
+        }
+        Ok(stats)
+    }
+
     async fn tags(
         &self,
     ) -> io::Result<crate::store::DbIter<(crate::Tag, iroh_base::hash::HashAndFormat)>> {
-        #[allow(clippy::mutable_key_type)]
-        let tags = self.read_lock().tags.clone();
+        let state = self.read_lock();
+        let now = SystemTime::now();
+        // expired tags are skipped, even if they were not removed yet
+        let tags = state
+            .tags
+            .keys()
+            .filter_map(|tag| Some((tag.clone(), *state.live_tag(tag, now)?)))
+            .collect::<Vec<_>>();
         Ok(Box::new(tags.into_iter().map(Ok)))
     }
 
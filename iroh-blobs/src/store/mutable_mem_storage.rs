@@ -1,6 +1,6 @@
 use bao_tree::{
     io::{fsm::BaoContentItem, sync::WriteAt},
-    BaoTree,
+    BaoTree, BlockSize,
 };
 use bytes::Bytes;
 
@@ -47,7 +47,7 @@ impl SizeInfo {
     }
 
     /// Write a size at the given offset. The size at the highest offset is going to be kept.
-    fn write(&mut self, offset: u64, size: u64) {
+    pub(super) fn write(&mut self, offset: u64, size: u64) {
         // >= instead of > because we want to be able to update size 0, the initial value.
         if offset >= self.offset {
             self.offset = offset;
@@ -97,8 +97,9 @@ impl MutableMemStorage {
         &mut self,
         size: u64,
         batch: &[BaoContentItem],
+        block_size: BlockSize,
     ) -> std::io::Result<()> {
-        let tree = BaoTree::new(size, IROH_BLOCK_SIZE);
+        let tree = BaoTree::new(size, block_size);
         for item in batch {
             match item {
                 BaoContentItem::Parent(parent) => {
@@ -6,6 +6,7 @@ use std::{
     io,
     path::PathBuf,
     sync::Arc,
+    time::Duration,
 };
 
 use crate::{
@@ -15,7 +16,7 @@ use crate::{
     },
     util::{
         progress::{BoxedProgressSender, IdGenerator, ProgressSender},
-        Tag,
+        Tag, TagUpdate,
     },
     BlobFormat, Hash, HashAndFormat, TempTag, IROH_BLOCK_SIZE,
 };
@@ -28,7 +29,10 @@ use futures_lite::Stream;
 use iroh_io::AsyncSliceReader;
 use tokio::io::AsyncWriteExt;
 
-use super::{BaoBatchWriter, BaoBlobSize, ConsistencyCheckProgress, DbIter, ExportProgressCb};
+use super::{
+    BaoBatchWriter, BaoBlobSize, BlobUsage, ConsistencyCheckProgress, DbIter, EntryStats,
+    ExportProgressCb,
+};
 
 /// A readonly in memory database for iroh-blobs.
 ///
@@ -260,6 +264,33 @@ impl ReadableStore for Store {
     async fn partial_blobs(&self) -> io::Result<DbIter<Hash>> {
         Ok(Box::new(std::iter::empty()))
     }
+
+    async fn blob_usage(&self) -> io::Result<DbIter<BlobUsage>> {
+        Ok(Box::new(
+            self.0
+                .iter()
+                .map(|(hash, (_, data))| {
+                    Ok(BlobUsage {
+                        hash: *hash,
+                        size: data.len() as u64,
+                        complete_size: Some(data.len() as u64),
+                        last_access: None,
+                    })
+                })
+                .collect::<Vec<_>>()
+                .into_iter(),
+        ))
+    }
+
+    async fn entry_stats(&self) -> io::Result<EntryStats> {
+        let mut stats = EntryStats::default();
+        for (outboard, data) in self.0.values() {
+            stats
+                .owned
+                .add(data.len() as u64, outboard.data.len() as u64);
+        }
+        Ok(stats)
+    }
 }
 
 impl MapEntryMut for Entry {
@@ -312,14 +343,27 @@ impl super::Store for Store {
         Err(io::Error::new(io::ErrorKind::Other, "not implemented"))
     }
 
-    async fn set_tag(&self, _name: Tag, _hash: Option<HashAndFormat>) -> io::Result<()> {
+    async fn set_tag(
+        &self,
+        _name: Tag,
+        _hash: Option<HashAndFormat>,
+        _ttl: Option<Duration>,
+    ) -> io::Result<()> {
         Err(io::Error::new(io::ErrorKind::Other, "not implemented"))
     }
 
-    async fn create_tag(&self, _hash: HashAndFormat) -> io::Result<Tag> {
+    async fn update_tags(&self, _updates: Vec<TagUpdate>) -> io::Result<bool> {
         Err(io::Error::new(io::ErrorKind::Other, "not implemented"))
     }
 
+    async fn create_tag(&self, _hash: HashAndFormat, _ttl: Option<Duration>) -> io::Result<Tag> {
+        Err(io::Error::new(io::ErrorKind::Other, "not implemented"))
+    }
+
+    async fn remove_expired_tags(&self) -> io::Result<Vec<(Tag, HashAndFormat)>> {
+        Ok(Vec::new())
+    }
+
     fn temp_tag(&self, inner: HashAndFormat) -> TempTag {
         TempTag::new(inner, None)
     }
@@ -0,0 +1,1316 @@
+//! A blob store that keeps data and outboards in an S3 compatible bucket.
+//!
+//! Main entry point is [Store].
+//!
+//! Complete blobs are stored as two objects, `{prefix}{hash}.data` and
+//! `{prefix}{hash}.obao4`, where the outboard object is omitted for blobs
+//! that fit in a single chunk group. Reads are done with ranged GETs, and
+//! large objects are written with multipart uploads.
+//!
+//! Metadata, i.e. the set of complete blobs and their sizes and the tags, is
+//! kept in a local redb database. Partial blobs are kept in local files until
+//! they are complete, and are discarded when the store is dropped.
+//!
+//! Since objects are immutable and addressed by hash, the same bucket and
+//! prefix can in principle be shared by multiple stores. But deleting a blob
+//! from one store will delete it from the bucket, so this is only safe if gc
+//! is disabled.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::{Duration, SystemTime},
+};
+
+use bao_tree::{
+    io::{
+        fsm::{BaoContentItem, Outboard},
+        outboard::PreOrderOutboard,
+        sync::{CreateOutboard, ReadAt, WriteAt},
+    },
+    BaoTree,
+};
+use bytes::{Bytes, BytesMut};
+use futures_lite::{Stream, StreamExt};
+use iroh_base::hash::{BlobFormat, Hash, HashAndFormat};
+use iroh_io::AsyncSliceReader;
+use redb::{ReadableTable, TableDefinition};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+
+use crate::{
+    store::{
+        mutable_mem_storage::SizeInfo, BaoBlobSize, BlobUsage, ConsistencyCheckProgress,
+        EntryStats, EntryStatus, ExportMode, ExportProgressCb, ImportMode, ImportProgress,
+        MapEntry, MapEntryMut, ReadableStore, ReportLevel,
+    },
+    util::{
+        progress::{BoxedProgressSender, IdGenerator, ProgressSender},
+        raw_outboard, raw_outboard_size, TagCounter, TagDrop, TagUpdate,
+    },
+    Tag, TempTag, IROH_BLOCK_SIZE,
+};
+
+use super::{temp_name, BaoBatchWriter, DbIter, Map, TempCounterMap};
+
+mod client;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+#[cfg(test)]
+mod tests;
+
+use client::Client;
+
+const BLOBS_TABLE: TableDefinition<Hash, u64> = TableDefinition::new("blobs-0");
+
+/// Local copies of the outboards of complete blobs, so walking the tree does
+/// not cost one request per node.
+const OUTBOARDS_TABLE: TableDefinition<Hash, &[u8]> = TableDefinition::new("outboards-0");
+
+const TAGS_TABLE: TableDefinition<Tag, HashAndFormat> = TableDefinition::new("tags-0");
+
+/// Expiry of tags with a ttl, in milliseconds since the unix epoch.
+const TAG_EXPIRY_TABLE: TableDefinition<Tag, u64> = TableDefinition::new("tag-expiry-0");
+
+/// Configuration for the bucket of an S3 store.
+#[derive(Clone)]
+pub struct S3Config {
+    /// Url of the S3 endpoint, e.g. `https://s3.eu-central-1.amazonaws.com`
+    /// or `http://localhost:9000`.
+    ///
+    /// Buckets are always addressed in path style, i.e. as `{endpoint}/{bucket}`.
+    pub endpoint: String,
+    /// Name of the bucket.
+    pub bucket: String,
+    /// Region of the bucket, used for signing requests.
+    pub region: String,
+    /// Access key id.
+    pub access_key_id: String,
+    /// Secret access key.
+    pub secret_access_key: String,
+    /// Prefix for all object keys, e.g. `blobs/`.
+    pub prefix: String,
+    /// Part size for multipart uploads, in bytes.
+    ///
+    /// Objects up to this size are uploaded in a single request. S3 requires
+    /// parts to be at least 5 MiB.
+    pub part_size: usize,
+}
+
+impl fmt::Debug for S3Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3Config")
+            .field("endpoint", &self.endpoint)
+            .field("bucket", &self.bucket)
+            .field("region", &self.region)
+            .field("access_key_id", &self.access_key_id)
+            .field("prefix", &self.prefix)
+            .field("part_size", &self.part_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl S3Config {
+    /// Default part size for multipart uploads.
+    pub const DEFAULT_PART_SIZE: usize = 8 * 1024 * 1024;
+
+    /// Create a new config with an empty prefix and the default part size.
+    pub fn new(
+        endpoint: impl Into<String>,
+        bucket: impl Into<String>,
+        region: impl Into<String>,
+        access_key_id: impl Into<String>,
+        secret_access_key: impl Into<String>,
+    ) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            bucket: bucket.into(),
+            region: region.into(),
+            access_key_id: access_key_id.into(),
+            secret_access_key: secret_access_key.into(),
+            prefix: String::new(),
+            part_size: Self::DEFAULT_PART_SIZE,
+        }
+    }
+
+    /// Set the prefix for all object keys.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+}
+
+/// Key of the data object of a blob.
+fn data_key(hash: &Hash) -> String {
+    format!("{}.data", hash.to_hex())
+}
+
+/// Key of the outboard object of a blob.
+fn outboard_key(hash: &Hash) -> String {
+    format!("{}.obao4", hash.to_hex())
+}
+
+/// A blob store that keeps data and outboards in an S3 compatible bucket, and
+/// metadata in a local database.
+#[derive(Debug, Clone)]
+pub struct Store(Arc<StoreInner>);
+
+#[derive(derive_more::Debug)]
+struct StoreInner {
+    client: Client,
+    #[debug(skip)]
+    db: redb::Database,
+    /// Directory for temp files when importing streams.
+    temp_path: PathBuf,
+    /// Directory for the data and outboard files of partial entries.
+    partial_path: PathBuf,
+    state: RwLock<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Partial entries, backed by files in the partial directory.
+    partial: BTreeMap<Hash, Arc<RwLock<PartialStorage>>>,
+    /// Last access times, only tracked in memory.
+    last_access: BTreeMap<Hash, SystemTime>,
+    temp: TempCounterMap,
+}
+
+impl TagDrop for StoreInner {
+    fn on_drop(&self, inner: &HashAndFormat) {
+        tracing::trace!("temp tag drop: {:?}", inner);
+        self.state.write().unwrap().temp.dec(inner);
+    }
+}
+
+impl TagCounter for StoreInner {
+    fn on_create(&self, inner: &HashAndFormat) {
+        tracing::trace!("temp tagging: {:?}", inner);
+        self.state.write().unwrap().temp.inc(inner);
+    }
+}
+
+impl Store {
+    /// Load or create a store, with metadata in the given local directory and
+    /// data in the configured bucket.
+    pub async fn load(root: impl AsRef<Path>, config: S3Config) -> io::Result<Self> {
+        let root = root.as_ref().to_owned();
+        let client = Client::new(config)?;
+        tokio::task::spawn_blocking(move || {
+            let temp_path = root.join("temp");
+            std::fs::create_dir_all(&temp_path)?;
+            // partial entries do not survive a restart, so remove leftovers
+            let partial_path = root.join("partial");
+            if partial_path.exists() {
+                std::fs::remove_dir_all(&partial_path)?;
+            }
+            std::fs::create_dir_all(&partial_path)?;
+            let db = redb::Database::create(root.join("blobs.db")).map_err(io::Error::other)?;
+            // create the tables, so readers don't have to deal with missing tables
+            let tx = db.begin_write().map_err(io::Error::other)?;
+            tx.open_table(BLOBS_TABLE).map_err(io::Error::other)?;
+            tx.open_table(OUTBOARDS_TABLE).map_err(io::Error::other)?;
+            tx.open_table(TAGS_TABLE).map_err(io::Error::other)?;
+            tx.open_table(TAG_EXPIRY_TABLE).map_err(io::Error::other)?;
+            tx.commit().map_err(io::Error::other)?;
+            Ok(Self(Arc::new(StoreInner {
+                client,
+                db,
+                temp_path,
+                partial_path,
+                state: Default::default(),
+            })))
+        })
+        .await?
+    }
+
+    fn read_lock(&self) -> RwLockReadGuard<'_, State> {
+        self.0.state.read().unwrap()
+    }
+
+    fn write_lock(&self) -> RwLockWriteGuard<'_, State> {
+        self.0.state.write().unwrap()
+    }
+
+    fn part_size(&self) -> usize {
+        self.0.client.config().part_size
+    }
+
+    /// Run a blocking operation on the metadata database.
+    async fn with_db<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&redb::Database) -> anyhow::Result<T> + Send + 'static,
+    ) -> io::Result<T> {
+        let this = self.clone();
+        tokio::task::spawn_blocking(move || f(&this.0.db).map_err(io::Error::other)).await?
+    }
+
+    /// Size of a complete blob, if it is in the store.
+    async fn complete_size(&self, hash: Hash) -> io::Result<Option<u64>> {
+        self.with_db(move |db| {
+            let tx = db.begin_read()?;
+            let blobs = tx.open_table(BLOBS_TABLE)?;
+            let size = blobs.get(hash)?.map(|x| x.value());
+            Ok(size)
+        })
+        .await
+    }
+
+    /// The outboard of a complete blob.
+    ///
+    /// Served from the local copy if there is one, otherwise fetched from the
+    /// bucket in a single request and cached.
+    async fn complete_outboard(&self, hash: Hash, size: u64) -> io::Result<Bytes> {
+        let outboard_size = raw_outboard_size(size);
+        if outboard_size == 0 {
+            return Ok(Bytes::new());
+        }
+        let cached = self
+            .with_db(move |db| {
+                let tx = db.begin_read()?;
+                let outboards = tx.open_table(OUTBOARDS_TABLE)?;
+                let outboard = outboards.get(hash)?.map(|x| x.value().to_vec());
+                Ok(outboard)
+            })
+            .await?;
+        if let Some(outboard) = cached {
+            return Ok(outboard.into());
+        }
+        let outboard = self
+            .0
+            .client
+            .get_range(&outboard_key(&hash), 0, outboard_size)
+            .await?;
+        let data = outboard.clone();
+        self.with_db(move |db| {
+            let tx = db.begin_write()?;
+            // only cache outboards of blobs that were not deleted in the meantime
+            if tx.open_table(BLOBS_TABLE)?.get(hash)?.is_some() {
+                tx.open_table(OUTBOARDS_TABLE)?.insert(hash, &data[..])?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await?;
+        Ok(outboard)
+    }
+
+    /// Upload an object from a reader of known size.
+    ///
+    /// Objects up to the part size are uploaded in a single request, larger
+    /// ones with a multipart upload.
+    async fn upload(
+        &self,
+        key: &str,
+        mut source: impl AsyncRead + Unpin,
+        size: u64,
+        progress: impl Fn(u64) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let client = &self.0.client;
+        let part_size = self.part_size();
+        if size <= part_size as u64 {
+            let mut buf = Vec::with_capacity(size as usize);
+            source.read_to_end(&mut buf).await?;
+            client.put(key, buf.into()).await?;
+            return progress(size);
+        }
+        let upload_id = client.create_multipart_upload(key).await?;
+        let res = async {
+            let mut etags = Vec::new();
+            let mut offset = 0;
+            while offset < size {
+                let len = (size - offset).min(part_size as u64) as usize;
+                let mut buf = BytesMut::zeroed(len);
+                source.read_exact(&mut buf).await?;
+                let part_number = etags.len() as u32 + 1;
+                etags.push(
+                    client
+                        .upload_part(key, &upload_id, part_number, buf.freeze())
+                        .await?,
+                );
+                offset += len as u64;
+                progress(offset)?;
+            }
+            client
+                .complete_multipart_upload(key, &upload_id, &etags)
+                .await
+        }
+        .await;
+        if res.is_err() {
+            if let Err(cause) = client.abort_multipart_upload(key, &upload_id).await {
+                tracing::warn!("failed to abort multipart upload for {}: {}", key, cause);
+            }
+        }
+        res
+    }
+
+    /// Upload the outboard of a blob, if needed, and record it as complete.
+    async fn finish_upload(&self, hash: Hash, size: u64, outboard: Vec<u8>) -> io::Result<()> {
+        if !outboard.is_empty() {
+            let len = outboard.len() as u64;
+            self.upload(&outboard_key(&hash), outboard.as_slice(), len, |_| Ok(()))
+                .await?;
+        }
+        self.with_db(move |db| {
+            let tx = db.begin_write()?;
+            tx.open_table(BLOBS_TABLE)?.insert(hash, size)?;
+            if !outboard.is_empty() {
+                tx.open_table(OUTBOARDS_TABLE)?
+                    .insert(hash, outboard.as_slice())?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await?;
+        tracing::debug!("stored {} ({} bytes)", hash.to_hex(), size);
+        Ok(())
+    }
+
+    /// Import a local file, computing the outboard and uploading data and
+    /// outboard.
+    async fn import_path(
+        &self,
+        path: PathBuf,
+        format: BlobFormat,
+        id: u64,
+        progress: impl ProgressSender<Msg = ImportProgress> + IdGenerator,
+    ) -> io::Result<(TempTag, u64)> {
+        let size = tokio::fs::metadata(&path).await?.len();
+        progress.send(ImportProgress::Size { id, size }).await?;
+        let path2 = path.clone();
+        let progress2 = progress.clone();
+        let (hash, outboard) = tokio::task::spawn_blocking(move || {
+            let file = std::fs::File::open(path2)?;
+            let buf_size = usize::try_from(size).unwrap_or(usize::MAX).min(1024 * 1024);
+            let reader = io::BufReader::with_capacity(buf_size, file);
+            progress2.try_send(ImportProgress::OutboardProgress { id, offset: 0 })?;
+            let ob = PreOrderOutboard::<Vec<u8>>::create_sized(reader, size, IROH_BLOCK_SIZE)?;
+            progress2.try_send(ImportProgress::OutboardProgress { id, offset: size })?;
+            io::Result::Ok((Hash::from(ob.root), ob.data))
+        })
+        .await??;
+        progress
+            .send(ImportProgress::OutboardDone { id, hash })
+            .await?;
+        // from here on, the hash is protected by the temp tag
+        let tag = self.0.temp_tag(HashAndFormat { hash, format });
+        if self.complete_size(hash).await?.is_none() {
+            let file = tokio::fs::File::open(&path).await?;
+            self.upload(&data_key(&hash), file, size, |offset| {
+                progress.try_send(ImportProgress::CopyProgress { id, offset })?;
+                Ok(())
+            })
+            .await?;
+            self.finish_upload(hash, size, outboard).await?;
+        }
+        Ok((tag, size))
+    }
+
+    async fn export_impl(
+        &self,
+        hash: Hash,
+        target: PathBuf,
+        progress: ExportProgressCb,
+    ) -> io::Result<()> {
+        tracing::trace!("exporting {} to {}", hash, target.display());
+        if !target.is_absolute() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "target path must be absolute",
+            ));
+        }
+        let parent = target.parent().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "target path has no parent directory",
+            )
+        })?;
+        tokio::fs::create_dir_all(parent).await?;
+        let size = self
+            .complete_size(hash)
+            .await?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "hash not found"))?;
+        let key = data_key(&hash);
+        let mut file = tokio::fs::File::create(target).await?;
+        let part_size = self.part_size() as u64;
+        let mut offset = 0;
+        while offset < size {
+            let len = (size - offset).min(part_size);
+            let bytes = self.0.client.get_range(&key, offset, len).await?;
+            if bytes.len() as u64 != len {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "object is shorter than expected",
+                ));
+            }
+            file.write_all(&bytes).await?;
+            offset += len;
+            progress(offset)?;
+        }
+        file.flush().await?;
+        Ok(())
+    }
+
+    async fn consistency_check_impl(
+        &self,
+        repair: bool,
+        progress: &BoxedProgressSender<ConsistencyCheckProgress>,
+    ) -> io::Result<()> {
+        let blobs = self
+            .with_db(|db| {
+                let tx = db.begin_read()?;
+                let blobs = tx.open_table(BLOBS_TABLE)?;
+                let mut res = Vec::new();
+                for item in blobs.iter()? {
+                    let (hash, size) = item?;
+                    res.push((hash.value(), size.value()));
+                }
+                Ok(res)
+            })
+            .await?;
+        let mut broken = Vec::new();
+        for (hash, size) in blobs {
+            let mut expected = vec![(data_key(&hash), size)];
+            let outboard_size = raw_outboard_size(size);
+            if outboard_size > 0 {
+                expected.push((outboard_key(&hash), outboard_size));
+            }
+            for (key, expected_size) in expected {
+                let message = match self.0.client.head(&key).await? {
+                    Some(actual) if actual == expected_size => continue,
+                    Some(actual) => format!(
+                        "object {} has size {}, expected {}",
+                        key, actual, expected_size
+                    ),
+                    None => format!("object {} is missing", key),
+                };
+                progress
+                    .send(ConsistencyCheckProgress::Update {
+                        message,
+                        entry: Some(hash),
+                        level: ReportLevel::Error,
+                    })
+                    .await?;
+                broken.push(hash);
+            }
+        }
+        if repair && !broken.is_empty() {
+            let count = broken.len();
+            self.with_db(move |db| {
+                let tx = db.begin_write()?;
+                {
+                    let mut blobs = tx.open_table(BLOBS_TABLE)?;
+                    let mut outboards = tx.open_table(OUTBOARDS_TABLE)?;
+                    for hash in broken {
+                        blobs.remove(hash)?;
+                        outboards.remove(hash)?;
+                    }
+                }
+                tx.commit()?;
+                Ok(())
+            })
+            .await?;
+            progress
+                .send(ConsistencyCheckProgress::Update {
+                    message: format!("removed {} broken entries", count),
+                    entry: None,
+                    level: ReportLevel::Info,
+                })
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Milliseconds since the unix epoch, saturating at the bounds of a u64.
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis().try_into().unwrap_or(u64::MAX))
+        .unwrap_or_default()
+}
+
+/// Whether a tag has an expiry at or before `now`, in milliseconds since the unix epoch.
+fn is_tag_expired(
+    expiry: &impl ReadableTable<Tag, u64>,
+    tag: &Tag,
+    now: u64,
+) -> anyhow::Result<bool> {
+    Ok(expiry.get(tag)?.is_some_and(|expiry| expiry.value() <= now))
+}
+
+/// Set a tag and its expiry in a write transaction.
+fn set_tag_tx(
+    tx: &redb::WriteTransaction,
+    tag: Tag,
+    value: Option<HashAndFormat>,
+    ttl: Option<Duration>,
+) -> anyhow::Result<()> {
+    let mut tags = tx.open_table(TAGS_TABLE)?;
+    let mut expiry = tx.open_table(TAG_EXPIRY_TABLE)?;
+    match value {
+        Some(value) => {
+            tags.insert(tag.clone(), value)?;
+            match ttl.and_then(|ttl| SystemTime::now().checked_add(ttl)) {
+                Some(time) => {
+                    expiry.insert(tag, unix_millis(time))?;
+                }
+                None => {
+                    expiry.remove(tag)?;
+                }
+            }
+        }
+        None => {
+            tags.remove(tag.clone())?;
+            expiry.remove(tag)?;
+        }
+    }
+    Ok(())
+}
+
+/// An entry in the S3 store.
+///
+/// Complete entries are read from the bucket, partial entries from local files.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    hash: Hash,
+    state: EntryState,
+}
+
+#[derive(Debug, Clone)]
+enum EntryState {
+    Complete { size: u64, store: Store },
+    Partial(Arc<RwLock<PartialStorage>>),
+}
+
+/// Data and outboard of a partial entry, in local files.
+///
+/// The files are removed when the storage is dropped.
+#[derive(Debug)]
+struct PartialStorage {
+    data: std::fs::File,
+    outboard: std::fs::File,
+    data_path: PathBuf,
+    outboard_path: PathBuf,
+    sizes: SizeInfo,
+}
+
+impl PartialStorage {
+    fn create(dir: &Path, hash: &Hash) -> io::Result<Self> {
+        let data_path = dir.join(format!("{}.data", hash.to_hex()));
+        let outboard_path = dir.join(format!("{}.obao4", hash.to_hex()));
+        let create = |path: &Path| {
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path)
+        };
+        Ok(Self {
+            data: create(&data_path)?,
+            outboard: create(&outboard_path)?,
+            data_path,
+            outboard_path,
+            sizes: SizeInfo::default(),
+        })
+    }
+
+    fn current_size(&self) -> u64 {
+        self.sizes.current_size()
+    }
+
+    fn data_len(&self) -> io::Result<u64> {
+        Ok(self.data.metadata()?.len())
+    }
+
+    fn outboard_len(&self) -> io::Result<u64> {
+        Ok(self.outboard.metadata()?.len())
+    }
+
+    fn write_batch(&mut self, size: u64, batch: &[BaoContentItem]) -> io::Result<()> {
+        let tree = BaoTree::new(size, IROH_BLOCK_SIZE);
+        for item in batch {
+            match item {
+                BaoContentItem::Parent(parent) => {
+                    if let Some(offset) = tree.pre_order_offset(parent.node) {
+                        let o0 = offset * 64;
+                        self.outboard
+                            .write_all_at(o0, parent.pair.0.as_bytes().as_slice())?;
+                        self.outboard
+                            .write_all_at(o0 + 32, parent.pair.1.as_bytes().as_slice())?;
+                    }
+                }
+                BaoContentItem::Leaf(leaf) => {
+                    self.sizes.write(leaf.offset, size);
+                    self.data.write_all_at(leaf.offset, leaf.data.as_ref())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PartialStorage {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.data_path).ok();
+        std::fs::remove_file(&self.outboard_path).ok();
+    }
+}
+
+/// Read from a file at the given offset, until end of file or max bytes.
+fn read_file_at(file: &std::fs::File, offset: u64, max: usize) -> io::Result<Bytes> {
+    let mut res = BytesMut::new();
+    let mut buf = [0u8; 4096];
+    let mut remaining = max;
+    let mut offset = offset;
+    while remaining > 0 {
+        let end = buf.len().min(remaining);
+        let read = file.read_at(offset, &mut buf[..end])?;
+        if read == 0 {
+            // eof
+            break;
+        }
+        res.extend_from_slice(&buf[..read]);
+        offset += read as u64;
+        remaining -= read;
+    }
+    Ok(res.freeze())
+}
+
+impl MapEntry for Entry {
+    fn hash(&self) -> Hash {
+        self.hash
+    }
+
+    fn size(&self) -> BaoBlobSize {
+        match &self.state {
+            EntryState::Complete { size, .. } => BaoBlobSize::Verified(*size),
+            EntryState::Partial(storage) => {
+                BaoBlobSize::Unverified(storage.read().unwrap().current_size())
+            }
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        matches!(self.state, EntryState::Complete { .. })
+    }
+
+    async fn outboard(&self) -> io::Result<impl Outboard> {
+        let (size, reader) = match &self.state {
+            EntryState::Complete { size, store } => (
+                *size,
+                EntryReader::Bytes(store.complete_outboard(self.hash, *size).await?),
+            ),
+            EntryState::Partial(storage) => (
+                storage.read().unwrap().current_size(),
+                EntryReader::Outboard(storage.clone()),
+            ),
+        };
+        Ok(PreOrderOutboard {
+            root: self.hash.into(),
+            tree: BaoTree::new(size, IROH_BLOCK_SIZE),
+            data: reader,
+        })
+    }
+
+    async fn data_reader(&self) -> io::Result<impl AsyncSliceReader> {
+        Ok(match &self.state {
+            EntryState::Complete { size, store } => EntryReader::Object {
+                client: store.0.client.clone(),
+                key: data_key(&self.hash),
+                size: *size,
+            },
+            EntryState::Partial(storage) => EntryReader::Data(storage.clone()),
+        })
+    }
+}
+
+impl MapEntryMut for Entry {
+    async fn batch_writer(&self) -> io::Result<impl BaoBatchWriter> {
+        Ok(BatchWriter(match &self.state {
+            EntryState::Complete { .. } => None,
+            EntryState::Partial(storage) => Some(storage.clone()),
+        }))
+    }
+}
+
+/// Reader for the data or outboard of an entry.
+#[derive(Debug)]
+enum EntryReader {
+    /// An object in the bucket, read with ranged GETs.
+    Object {
+        client: Client,
+        key: String,
+        size: u64,
+    },
+    /// An object kept in memory.
+    Bytes(Bytes),
+    /// The data of a partial entry.
+    Data(Arc<RwLock<PartialStorage>>),
+    /// The outboard of a partial entry.
+    Outboard(Arc<RwLock<PartialStorage>>),
+}
+
+impl AsyncSliceReader for EntryReader {
+    async fn read_at(&mut self, offset: u64, len: usize) -> io::Result<Bytes> {
+        match self {
+            Self::Object { client, key, size } => {
+                let len = size.saturating_sub(offset).min(len as u64);
+                client.get_range(key, offset, len).await
+            }
+            Self::Bytes(bytes) => bytes.read_at(offset, len).await,
+            Self::Data(storage) => {
+                let storage = storage.clone();
+                tokio::task::spawn_blocking(move || {
+                    read_file_at(&storage.read().unwrap().data, offset, len)
+                })
+                .await?
+            }
+            Self::Outboard(storage) => {
+                let storage = storage.clone();
+                tokio::task::spawn_blocking(move || {
+                    read_file_at(&storage.read().unwrap().outboard, offset, len)
+                })
+                .await?
+            }
+        }
+    }
+
+    async fn size(&mut self) -> io::Result<u64> {
+        match self {
+            Self::Object { size, .. } => Ok(*size),
+            Self::Bytes(bytes) => Ok(bytes.len() as u64),
+            Self::Data(storage) => storage.read().unwrap().data_len(),
+            Self::Outboard(storage) => storage.read().unwrap().outboard_len(),
+        }
+    }
+}
+
+/// Writer for partial entries. Writes to complete entries are ignored.
+struct BatchWriter(Option<Arc<RwLock<PartialStorage>>>);
+
+impl BaoBatchWriter for BatchWriter {
+    async fn write_batch(&mut self, size: u64, batch: Vec<BaoContentItem>) -> io::Result<()> {
+        if let Some(storage) = self.0.clone() {
+            tokio::task::spawn_blocking(move || storage.write().unwrap().write_batch(size, &batch))
+                .await??;
+        }
+        Ok(())
+    }
+
+    async fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Map for Store {
+    type Entry = Entry;
+
+    async fn get(&self, hash: &Hash) -> io::Result<Option<Self::Entry>> {
+        let partial = self.read_lock().partial.get(hash).cloned();
+        let state = match partial {
+            Some(storage) => EntryState::Partial(storage),
+            None => match self.complete_size(*hash).await? {
+                Some(size) => EntryState::Complete {
+                    size,
+                    store: self.clone(),
+                },
+                None => return Ok(None),
+            },
+        };
+        self.write_lock()
+            .last_access
+            .insert(*hash, SystemTime::now());
+        Ok(Some(Entry { hash: *hash, state }))
+    }
+}
+
+impl super::MapMut for Store {
+    type EntryMut = Entry;
+
+    async fn get_mut(&self, hash: &Hash) -> io::Result<Option<Self::EntryMut>> {
+        self.get(hash).await
+    }
+
+    async fn get_or_create(&self, hash: Hash, _size: u64) -> io::Result<Entry> {
+        if let Some(entry) = self.get(&hash).await? {
+            return Ok(entry);
+        }
+        let storage = {
+            let mut state = self.write_lock();
+            match state.partial.entry(hash) {
+                std::collections::btree_map::Entry::Occupied(e) => e.get().clone(),
+                std::collections::btree_map::Entry::Vacant(e) => {
+                    let storage = PartialStorage::create(&self.0.partial_path, &hash)?;
+                    e.insert(Arc::new(RwLock::new(storage))).clone()
+                }
+            }
+        };
+        Ok(Entry {
+            hash,
+            state: EntryState::Partial(storage),
+        })
+    }
+
+    async fn entry_status(&self, hash: &Hash) -> io::Result<EntryStatus> {
+        if self.complete_size(*hash).await?.is_some() {
+            Ok(EntryStatus::Complete)
+        } else if self.read_lock().partial.contains_key(hash) {
+            Ok(EntryStatus::Partial)
+        } else {
+            Ok(EntryStatus::NotFound)
+        }
+    }
+
+    fn entry_status_sync(&self, hash: &Hash) -> io::Result<EntryStatus> {
+        let tx = self.0.db.begin_read().map_err(io::Error::other)?;
+        let blobs = tx.open_table(BLOBS_TABLE).map_err(io::Error::other)?;
+        if blobs.get(hash).map_err(io::Error::other)?.is_some() {
+            Ok(EntryStatus::Complete)
+        } else if self.read_lock().partial.contains_key(hash) {
+            Ok(EntryStatus::Partial)
+        } else {
+            Ok(EntryStatus::NotFound)
+        }
+    }
+
+    async fn insert_complete(&self, entry: Entry) -> io::Result<()> {
+        let EntryState::Partial(storage) = entry.state else {
+            // already complete, nothing to do
+            return Ok(());
+        };
+        let hash = entry.hash;
+        if self.complete_size(hash).await?.is_none() {
+            let (size, data_len, data_path, outboard_path) = {
+                let storage = storage.read().unwrap();
+                (
+                    storage.current_size(),
+                    storage.data_len()?,
+                    storage.data_path.clone(),
+                    storage.outboard_path.clone(),
+                )
+            };
+            if data_len != size {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "entry is not complete",
+                ));
+            }
+            // the outboard can contain trailing garbage from writes with a
+            // larger announced size, so truncate it to the real size.
+            let mut outboard = tokio::fs::read(&outboard_path).await?;
+            outboard.truncate(raw_outboard_size(size) as usize);
+            let data = tokio::fs::File::open(&data_path).await?;
+            self.upload(&data_key(&hash), data, size, |_| Ok(()))
+                .await?;
+            self.finish_upload(hash, size, outboard).await?;
+        }
+        self.write_lock().partial.remove(&hash);
+        Ok(())
+    }
+}
+
+impl ReadableStore for Store {
+    async fn blobs(&self) -> io::Result<DbIter<Hash>> {
+        let blobs = self
+            .with_db(|db| {
+                let tx = db.begin_read()?;
+                let blobs = tx.open_table(BLOBS_TABLE)?;
+                let mut res = Vec::new();
+                for item in blobs.iter()? {
+                    res.push(Ok(item?.0.value()));
+                }
+                Ok(res)
+            })
+            .await?;
+        Ok(Box::new(blobs.into_iter()))
+    }
+
+    async fn partial_blobs(&self) -> io::Result<DbIter<Hash>> {
+        let partial = self
+            .read_lock()
+            .partial
+            .keys()
+            .copied()
+            .map(Ok)
+            .collect::<Vec<_>>();
+        Ok(Box::new(partial.into_iter()))
+    }
+
+    async fn blob_usage(&self) -> io::Result<DbIter<BlobUsage>> {
+        let complete = self
+            .with_db(|db| {
+                let tx = db.begin_read()?;
+                let blobs = tx.open_table(BLOBS_TABLE)?;
+                let mut res = Vec::new();
+                for item in blobs.iter()? {
+                    let (hash, size) = item?;
+                    res.push((hash.value(), size.value()));
+                }
+                Ok(res)
+            })
+            .await?;
+        let state = self.read_lock();
+        let partial = state
+            .partial
+            .iter()
+            .map(|(hash, storage)| (*hash, storage.read().unwrap().current_size(), None));
+        let usage = complete
+            .into_iter()
+            .map(|(hash, size)| (hash, size, Some(size)))
+            .chain(partial)
+            .map(|(hash, size, complete_size)| {
+                Ok(BlobUsage {
+                    hash,
+                    size,
+                    complete_size,
+                    last_access: state.last_access.get(&hash).copied(),
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(Box::new(usage.into_iter()))
+    }
+
+    async fn entry_stats(&self) -> io::Result<EntryStats> {
+        let sizes = self
+            .with_db(|db| {
+                let tx = db.begin_read()?;
+                let blobs = tx.open_table(BLOBS_TABLE)?;
+                let mut res = Vec::new();
+                for item in blobs.iter()? {
+                    res.push(item?.1.value());
+                }
+                Ok(res)
+            })
+            .await?;
+        let mut stats = EntryStats::default();
+        for size in sizes {
+            // the objects in the bucket are owned by the store
+            stats.owned.add(size, raw_outboard_size(size));
+        }
+        for storage in self.read_lock().partial.values() {
+            let storage = storage.read().unwrap();
+            stats
+                .partial
+                .add(storage.data_len()?, storage.outboard_len()?);
+        }
+        Ok(stats)
+    }
+
+    async fn tags(&self) -> io::Result<DbIter<(Tag, HashAndFormat)>> {
+        let tags = self
+            .with_db(|db| {
+                let now = unix_millis(SystemTime::now());
+                let tx = db.begin_read()?;
+                let tags = tx.open_table(TAGS_TABLE)?;
+                let expiry = tx.open_table(TAG_EXPIRY_TABLE)?;
+                let mut res = Vec::new();
+                for item in tags.iter()? {
+                    let (tag, value) = item?;
+                    let tag = tag.value();
+                    // expired tags are skipped, even if they were not removed yet
+                    if !is_tag_expired(&expiry, &tag, now)? {
+                        res.push(Ok((tag, value.value())));
+                    }
+                }
+                Ok(res)
+            })
+            .await?;
+        Ok(Box::new(tags.into_iter()))
+    }
+
+    async fn tags_with_prefix(&self, prefix: Tag) -> io::Result<DbIter<(Tag, HashAndFormat)>> {
+        let tags = self
+            .with_db(move |db| {
+                let now = unix_millis(SystemTime::now());
+                let tx = db.begin_read()?;
+                let tags = tx.open_table(TAGS_TABLE)?;
+                let expiry = tx.open_table(TAG_EXPIRY_TABLE)?;
+                let mut res = Vec::new();
+                for item in tags.range(prefix.clone()..)? {
+                    let (tag, value) = item?;
+                    let tag = tag.value();
+                    if !tag.starts_with(&prefix.0) {
+                        break;
+                    }
+                    if !is_tag_expired(&expiry, &tag, now)? {
+                        res.push(Ok((tag, value.value())));
+                    }
+                }
+                Ok(res)
+            })
+            .await?;
+        Ok(Box::new(tags.into_iter()))
+    }
+
+    fn temp_tags(&self) -> Box<dyn Iterator<Item = HashAndFormat> + Send + Sync + 'static> {
+        Box::new(self.read_lock().temp.keys())
+    }
+
+    async fn consistency_check(
+        &self,
+        repair: bool,
+        progress: BoxedProgressSender<ConsistencyCheckProgress>,
+    ) -> io::Result<()> {
+        progress.send(ConsistencyCheckProgress::Start).await?;
+        if let Err(cause) = self.consistency_check_impl(repair, &progress).await {
+            progress
+                .send(ConsistencyCheckProgress::Abort(cause.into()))
+                .await?;
+            return Ok(());
+        }
+        progress.send(ConsistencyCheckProgress::Done).await?;
+        Ok(())
+    }
+
+    async fn export(
+        &self,
+        hash: Hash,
+        target: PathBuf,
+        _mode: ExportMode,
+        progress: ExportProgressCb,
+    ) -> io::Result<()> {
+        // objects can not be referenced, so always copy
+        self.export_impl(hash, target, progress).await
+    }
+}
+
+impl super::Store for Store {
+    async fn import_file(
+        &self,
+        path: PathBuf,
+        _mode: ImportMode,
+        format: BlobFormat,
+        progress: impl ProgressSender<Msg = ImportProgress> + IdGenerator,
+    ) -> io::Result<(TempTag, u64)> {
+        if !path.is_absolute() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "path must be absolute",
+            ));
+        }
+        let id = progress.new_id();
+        progress
+            .send(ImportProgress::Found {
+                id,
+                name: path.to_string_lossy().to_string(),
+            })
+            .await?;
+        // the data is always copied into the bucket, so there is no need to
+        // copy the file first
+        self.import_path(path, format, id, progress).await
+    }
+
+    async fn import_bytes(&self, data: Bytes, format: BlobFormat) -> io::Result<TempTag> {
+        let data2 = data.clone();
+        let (outboard, hash) = tokio::task::spawn_blocking(move || raw_outboard(&data2)).await?;
+        let tag = self.0.temp_tag(HashAndFormat { hash, format });
+        if self.complete_size(hash).await?.is_none() {
+            let size = data.len() as u64;
+            self.upload(&data_key(&hash), data.as_ref(), size, |_| Ok(()))
+                .await?;
+            self.finish_upload(hash, size, outboard).await?;
+        }
+        Ok(tag)
+    }
+
+    async fn import_stream(
+        &self,
+        mut data: impl Stream<Item = io::Result<Bytes>> + Unpin + Send + 'static,
+        format: BlobFormat,
+        progress: impl ProgressSender<Msg = ImportProgress> + IdGenerator,
+    ) -> io::Result<(TempTag, u64)> {
+        let id = progress.new_id();
+        // write to a temp file, since we need the hash before uploading
+        let name = temp_name();
+        let temp_path = self.0.temp_path.join(&name);
+        progress.send(ImportProgress::Found { id, name }).await?;
+        let mut writer = tokio::fs::File::create(&temp_path).await?;
+        let mut offset = 0;
+        while let Some(chunk) = data.next().await {
+            let chunk = chunk?;
+            writer.write_all(&chunk).await?;
+            offset += chunk.len() as u64;
+            progress.try_send(ImportProgress::CopyProgress { id, offset })?;
+        }
+        writer.flush().await?;
+        drop(writer);
+        let res = self
+            .import_path(temp_path.clone(), format, id, progress)
+            .await;
+        tokio::fs::remove_file(&temp_path).await.ok();
+        res
+    }
+
+    async fn set_tag(
+        &self,
+        name: Tag,
+        hash: Option<HashAndFormat>,
+        ttl: Option<Duration>,
+    ) -> io::Result<()> {
+        self.with_db(move |db| {
+            let tx = db.begin_write()?;
+            set_tag_tx(&tx, name, hash, ttl)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_tags(&self, updates: Vec<TagUpdate>) -> io::Result<bool> {
+        self.with_db(move |db| {
+            let tx = db.begin_write()?;
+            {
+                let now = unix_millis(SystemTime::now());
+                let tags = tx.open_table(TAGS_TABLE)?;
+                let expiry = tx.open_table(TAG_EXPIRY_TABLE)?;
+                for update in &updates {
+                    // an expired tag is treated as absent, even if it was not removed yet
+                    let current = if is_tag_expired(&expiry, &update.name, now)? {
+                        None
+                    } else {
+                        tags.get(&update.name)?.map(|x| x.value())
+                    };
+                    if !update.matches(current.as_ref()) {
+                        return Ok(false);
+                    }
+                }
+            }
+            for TagUpdate {
+                name, value, ttl, ..
+            } in updates
+            {
+                set_tag_tx(&tx, name, value, ttl)?;
+            }
+            tx.commit()?;
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn create_tag(&self, hash: HashAndFormat, ttl: Option<Duration>) -> io::Result<Tag> {
+        self.with_db(move |db| {
+            let tx = db.begin_write()?;
+            let tag = {
+                let tags = tx.open_table(TAGS_TABLE)?;
+                Tag::auto(SystemTime::now(), |x| {
+                    matches!(tags.get(Tag(Bytes::copy_from_slice(x))), Ok(Some(_)))
+                })
+            };
+            set_tag_tx(&tx, tag.clone(), Some(hash), ttl)?;
+            tx.commit()?;
+            Ok(tag)
+        })
+        .await
+    }
+
+    async fn remove_expired_tags(&self) -> io::Result<Vec<(Tag, HashAndFormat)>> {
+        self.with_db(|db| {
+            let now = unix_millis(SystemTime::now());
+            let tx = db.begin_write()?;
+            let mut removed = Vec::new();
+            {
+                let mut tags = tx.open_table(TAGS_TABLE)?;
+                let mut expiry = tx.open_table(TAG_EXPIRY_TABLE)?;
+                let mut expired = Vec::new();
+                for item in expiry.iter()? {
+                    let (tag, time) = item?;
+                    if time.value() <= now {
+                        expired.push(tag.value());
+                    }
+                }
+                for tag in expired {
+                    expiry.remove(tag.clone())?;
+                    if let Some(value) = tags.remove(tag.clone())? {
+                        removed.push((tag, value.value()));
+                    }
+                }
+            }
+            tx.commit()?;
+            Ok(removed)
+        })
+        .await
+    }
+
+    async fn delete_tags_with_prefix(&self, prefix: Tag) -> io::Result<Vec<(Tag, HashAndFormat)>> {
+        self.with_db(move |db| {
+            let tx = db.begin_write()?;
+            let mut removed = Vec::new();
+            {
+                let mut tags = tx.open_table(TAGS_TABLE)?;
+                let mut expiry = tx.open_table(TAG_EXPIRY_TABLE)?;
+                let mut matching = Vec::new();
+                for item in tags.range(prefix.clone()..)? {
+                    let (tag, _) = item?;
+                    let tag = tag.value();
+                    if !tag.starts_with(&prefix.0) {
+                        break;
+                    }
+                    matching.push(tag);
+                }
+                for tag in matching {
+                    expiry.remove(tag.clone())?;
+                    if let Some(value) = tags.remove(tag.clone())? {
+                        removed.push((tag, value.value()));
+                    }
+                }
+            }
+            tx.commit()?;
+            Ok(removed)
+        })
+        .await
+    }
+
+    fn temp_tag(&self, value: HashAndFormat) -> TempTag {
+        self.0.temp_tag(value)
+    }
+
+    async fn gc_start(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    async fn delete(&self, hashes: Vec<Hash>) -> io::Result<()> {
+        let hashes = {
+            let mut state = self.write_lock();
+            let hashes = hashes
+                .into_iter()
+                .filter(|hash| !state.temp.contains(hash))
+                .collect::<BTreeSet<_>>();
+            for hash in &hashes {
+                state.partial.remove(hash);
+                state.last_access.remove(hash);
+            }
+            hashes
+        };
+        let deleted = self
+            .with_db(move |db| {
+                let tx = db.begin_write()?;
+                let mut deleted = Vec::new();
+                {
+                    let mut blobs = tx.open_table(BLOBS_TABLE)?;
+                    let mut outboards = tx.open_table(OUTBOARDS_TABLE)?;
+                    for hash in hashes {
+                        outboards.remove(hash)?;
+                        if let Some(size) = blobs.remove(hash)? {
+                            deleted.push((hash, size.value()));
+                        }
+                    }
+                }
+                tx.commit()?;
+                Ok(deleted)
+            })
+            .await?;
+        // remove the objects only after the metadata is gone, so we never
+        // have metadata for missing objects
+        for (hash, size) in deleted {
+            tracing::debug!("deleting {}", hash.to_hex());
+            self.0.client.delete(&data_key(&hash)).await?;
+            if raw_outboard_size(size) > 0 {
+                self.0.client.delete(&outboard_key(&hash)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn shutdown(&self) {}
+}
@@ -0,0 +1,388 @@
+//! A minimal client for S3 compatible object storage.
+//!
+//! This only implements the handful of operations the store needs, and signs
+//! requests with AWS signature version 4. Payloads are not signed, which is
+//! fine since the content of every object is verified against its blake3 hash
+//! anyway.
+use std::{fmt::Write as _, io, sync::Arc};
+
+use bytes::Bytes;
+use hmac::{Hmac, Mac};
+use reqwest::{header, Method, StatusCode, Url};
+use sha2::{Digest, Sha256};
+
+use super::S3Config;
+
+/// Payload hash for requests where the payload is not signed.
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// A client for a single bucket.
+#[derive(Debug, Clone)]
+pub(super) struct Client {
+    http: reqwest::Client,
+    config: Arc<S3Config>,
+}
+
+impl Client {
+    pub fn new(config: S3Config) -> io::Result<Self> {
+        // check the endpoint early, so we don't fail on every request
+        Url::parse(&config.endpoint).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok(Self {
+            http: reqwest::Client::new(),
+            config: Arc::new(config),
+        })
+    }
+
+    /// The configuration of the bucket.
+    pub fn config(&self) -> &S3Config {
+        &self.config
+    }
+
+    /// Read `len` bytes of an object, starting at `offset`.
+    ///
+    /// The caller is responsible for not reading beyond the end of the object.
+    pub async fn get_range(&self, key: &str, offset: u64, len: u64) -> io::Result<Bytes> {
+        if len == 0 {
+            return Ok(Bytes::new());
+        }
+        let range = format!("bytes={}-{}", offset, offset + len - 1);
+        let res = self
+            .request(Method::GET, key, &[])
+            .header(header::RANGE, range)
+            .send()
+            .await
+            .map_err(io::Error::other)?;
+        let res = check_status(res).await?;
+        res.bytes().await.map_err(io::Error::other)
+    }
+
+    /// Get the size of an object, or `None` if it does not exist.
+    pub async fn head(&self, key: &str) -> io::Result<Option<u64>> {
+        let res = self
+            .request(Method::HEAD, key, &[])
+            .send()
+            .await
+            .map_err(io::Error::other)?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let res = check_status(res).await?;
+        Ok(res.content_length())
+    }
+
+    /// Upload an object in a single request.
+    pub async fn put(&self, key: &str, body: Bytes) -> io::Result<()> {
+        let res = self
+            .request(Method::PUT, key, &[])
+            .body(body)
+            .send()
+            .await
+            .map_err(io::Error::other)?;
+        check_status(res).await?;
+        Ok(())
+    }
+
+    /// Delete an object. Deleting an object that does not exist is not an error.
+    pub async fn delete(&self, key: &str) -> io::Result<()> {
+        let res = self
+            .request(Method::DELETE, key, &[])
+            .send()
+            .await
+            .map_err(io::Error::other)?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        check_status(res).await?;
+        Ok(())
+    }
+
+    /// Start a multipart upload, returning the upload id.
+    pub async fn create_multipart_upload(&self, key: &str) -> io::Result<String> {
+        let res = self
+            .request(Method::POST, key, &[("uploads", "")])
+            .send()
+            .await
+            .map_err(io::Error::other)?;
+        let res = check_status(res).await?;
+        let text = res.text().await.map_err(io::Error::other)?;
+        xml_element(&text, "UploadId")
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing upload id"))
+    }
+
+    /// Upload a part of a multipart upload, returning the etag of the part.
+    pub async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        body: Bytes,
+    ) -> io::Result<String> {
+        let part_number = part_number.to_string();
+        let res = self
+            .request(
+                Method::PUT,
+                key,
+                &[("partNumber", &part_number), ("uploadId", upload_id)],
+            )
+            .body(body)
+            .send()
+            .await
+            .map_err(io::Error::other)?;
+        let res = check_status(res).await?;
+        res.headers()
+            .get(header::ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing part etag"))
+    }
+
+    /// Complete a multipart upload from the etags of all parts, in order.
+    pub async fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        etags: &[String],
+    ) -> io::Result<()> {
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for (i, etag) in etags.iter().enumerate() {
+            write!(
+                body,
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                i + 1,
+                etag
+            )
+            .ok();
+        }
+        body.push_str("</CompleteMultipartUpload>");
+        let res = self
+            .request(Method::POST, key, &[("uploadId", upload_id)])
+            .body(body)
+            .send()
+            .await
+            .map_err(io::Error::other)?;
+        let res = check_status(res).await?;
+        // a complete request can fail even with a 200 status code
+        let text = res.text().await.map_err(io::Error::other)?;
+        if let Some(message) = xml_element(&text, "Message").filter(|_| text.contains("<Error>")) {
+            return Err(io::Error::other(format!(
+                "completing multipart upload failed: {message}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Abort a multipart upload, so the uploaded parts are not kept around.
+    pub async fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> io::Result<()> {
+        let res = self
+            .request(Method::DELETE, key, &[("uploadId", upload_id)])
+            .send()
+            .await
+            .map_err(io::Error::other)?;
+        check_status(res).await?;
+        Ok(())
+    }
+
+    /// Create a signed request for an object.
+    fn request(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, &str)],
+    ) -> reqwest::RequestBuilder {
+        let mut query = query
+            .iter()
+            .map(|(k, v)| (uri_encode(k, true), uri_encode(v, true)))
+            .collect::<Vec<_>>();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect::<Vec<_>>()
+            .join("&");
+        let key = format!("{}{}", self.config.prefix, key);
+        let mut url = Url::parse(&format!(
+            "{}/{}/{}",
+            self.config.endpoint.trim_end_matches('/'),
+            uri_encode(&self.config.bucket, true),
+            uri_encode(&key, false),
+        ))
+        .expect("endpoint was checked on creation");
+        if !query.is_empty() {
+            url.set_query(Some(&query));
+        }
+        let now = chrono::Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let authorization = self.authorization(&method, &url, &query, &amz_date);
+        self.http
+            .request(method, url)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", UNSIGNED_PAYLOAD)
+            .header(header::AUTHORIZATION, authorization)
+    }
+
+    /// Compute the authorization header for a request.
+    fn authorization(&self, method: &Method, url: &Url, query: &str, amz_date: &str) -> String {
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_owned(),
+        };
+        let headers = [
+            ("host", host.as_str()),
+            ("x-amz-content-sha256", UNSIGNED_PAYLOAD),
+            ("x-amz-date", amz_date),
+        ];
+        let canonical_request = canonical_request(
+            method.as_str(),
+            url.path(),
+            query,
+            &headers,
+            UNSIGNED_PAYLOAD,
+        );
+        let (scope, signature) = signature(
+            &self.config.secret_access_key,
+            &self.config.region,
+            amz_date,
+            &canonical_request,
+        );
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.config.access_key_id,
+            scope,
+            signed_headers(&headers),
+            signature
+        )
+    }
+}
+
+/// Build the canonical request for signature version 4.
+///
+/// `headers` are the signed headers, with lower case names and sorted by name.
+pub(super) fn canonical_request(
+    method: &str,
+    path: &str,
+    query: &str,
+    headers: &[(&str, &str)],
+    payload_hash: &str,
+) -> String {
+    let mut res = format!("{method}\n{path}\n{query}\n");
+    for (name, value) in headers {
+        writeln!(res, "{name}:{}", value.trim()).ok();
+    }
+    write!(res, "\n{}\n{payload_hash}", signed_headers(headers)).ok();
+    res
+}
+
+fn signed_headers(headers: &[(&str, &str)]) -> String {
+    headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Sign a canonical request for the s3 service, returning the credential
+/// scope and the hex encoded signature.
+pub(super) fn signature(
+    secret_access_key: &str,
+    region: &str,
+    amz_date: &str,
+    canonical_request: &str,
+) -> (String, String) {
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let secret = format!("AWS4{}", secret_access_key);
+    let key = hmac_sha256(secret.as_bytes(), date.as_bytes());
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, b"s3");
+    let key = hmac_sha256(&key, b"aws4_request");
+    let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+    (scope, signature)
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts any key size");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// Percent encode a string as required for signing, optionally keeping slashes.
+fn uri_encode(s: &str, encode_slash: bool) -> String {
+    let mut res = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                res.push(b as char)
+            }
+            b'/' if !encode_slash => res.push('/'),
+            b => {
+                write!(res, "%{b:02X}").ok();
+            }
+        }
+    }
+    res
+}
+
+/// Get the text of the first element with the given name from an xml document.
+///
+/// The responses we care about are simple enough to not need a full parser.
+fn xml_element<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    let start = text.find(&format!("<{name}>"))? + name.len() + 2;
+    let end = start + text[start..].find(&format!("</{name}>"))?;
+    Some(&text[start..end])
+}
+
+/// Turn a non-success response into an error, including the error message.
+async fn check_status(res: reqwest::Response) -> io::Result<reqwest::Response> {
+    let status = res.status();
+    if status.is_success() {
+        return Ok(res);
+    }
+    let text = res.text().await.unwrap_or_default();
+    let message = xml_element(&text, "Message").unwrap_or(&text);
+    let kind = match status {
+        StatusCode::NOT_FOUND => io::ErrorKind::NotFound,
+        StatusCode::FORBIDDEN | StatusCode::UNAUTHORIZED => io::ErrorKind::PermissionDenied,
+        _ => io::ErrorKind::Other,
+    };
+    Err(io::Error::new(
+        kind,
+        format!("s3 request failed with {status}: {message}"),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Example from the AWS documentation for signature version 4.
+    #[test]
+    fn signing_key() {
+        let secret = "AWS4wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+        let key = hmac_sha256(secret.as_bytes(), b"20120215");
+        let key = hmac_sha256(&key, b"us-east-1");
+        let key = hmac_sha256(&key, b"iam");
+        let key = hmac_sha256(&key, b"aws4_request");
+        assert_eq!(
+            hex::encode(key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn encoding() {
+        assert_eq!(uri_encode("a b/c~", true), "a%20b%2Fc~");
+        assert_eq!(uri_encode("a b/c~", false), "a%20b/c~");
+        assert_eq!(
+            xml_element("<A><UploadId>xyz</UploadId></A>", "UploadId"),
+            Some("xyz")
+        );
+        assert_eq!(xml_element("<A></A>", "UploadId"), None);
+    }
+}
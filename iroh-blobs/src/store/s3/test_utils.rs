@@ -0,0 +1,179 @@
+//! A fake S3 server to test the store against.
+use std::{
+    collections::BTreeMap,
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+/// State of the fake S3 server.
+#[derive(Debug, Default)]
+pub struct Bucket {
+    /// The stored objects by key.
+    pub objects: BTreeMap<String, Vec<u8>>,
+    /// The parts of multipart uploads in progress, by upload id.
+    pub uploads: BTreeMap<String, BTreeMap<u32, Vec<u8>>>,
+    /// Number of multipart uploads started.
+    pub multipart_uploads: usize,
+    /// Number of GET requests per key.
+    pub gets: BTreeMap<String, usize>,
+}
+
+/// The state of a fake S3 server, shared with the server task.
+pub type SharedBucket = Arc<Mutex<Bucket>>;
+
+/// A tiny in process stand-in for an S3 compatible server.
+///
+/// It supports just enough of the protocol for the store: put, ranged get,
+/// head, delete and multipart uploads, for a single bucket. Signatures are
+/// not checked, only that requests are signed at all. The signing itself is
+/// checked against a known vector in the tests of the store.
+///
+/// The bucket is named `bucket` and the server runs until the runtime shuts
+/// down.
+pub async fn fake_s3() -> (SocketAddr, SharedBucket) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let bucket = SharedBucket::default();
+    let bucket2 = bucket.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(serve_connection(stream, bucket2.clone()));
+        }
+    });
+    (addr, bucket)
+}
+
+async fn serve_connection(stream: TcpStream, bucket: SharedBucket) -> io::Result<()> {
+    let mut stream = BufReader::new(stream);
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await? == 0 {
+            return Ok(());
+        }
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_owned();
+        let target = parts.next().unwrap_or_default().to_owned();
+        let mut headers = BTreeMap::new();
+        loop {
+            let mut line = String::new();
+            stream.read_line(&mut line).await?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.insert(name.trim().to_lowercase(), value.trim().to_owned());
+            }
+        }
+        let len = headers
+            .get("content-length")
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(0);
+        let mut body = vec![0u8; len];
+        stream.read_exact(&mut body).await?;
+        let (status, extra_headers, body) = if headers.contains_key("authorization") {
+            handle(&bucket, &method, &target, &headers, body)
+        } else {
+            (
+                403,
+                String::new(),
+                b"<Error><Message>unsigned</Message></Error>".to_vec(),
+            )
+        };
+        let body = if method == "HEAD" { Vec::new() } else { body };
+        let response = format!(
+            "HTTP/1.1 {} X\r\ncontent-length: {}\r\n{}\r\n",
+            status,
+            body.len(),
+            extra_headers
+        );
+        stream.get_mut().write_all(response.as_bytes()).await?;
+        stream.get_mut().write_all(&body).await?;
+    }
+}
+
+fn handle(
+    bucket: &SharedBucket,
+    method: &str,
+    target: &str,
+    headers: &BTreeMap<String, String>,
+    body: Vec<u8>,
+) -> (u16, String, Vec<u8>) {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter_map(|x| x.split_once('=').or(Some((x, ""))))
+        .filter(|(k, _)| !k.is_empty())
+        .collect::<BTreeMap<_, _>>();
+    let Some(key) = path.strip_prefix("/bucket/") else {
+        return (404, String::new(), Vec::new());
+    };
+    let key = key.to_owned();
+    let mut bucket = bucket.lock().unwrap();
+    match (method, query.get("uploadId")) {
+        ("POST", None) => {
+            bucket.multipart_uploads += 1;
+            let upload_id = format!("upload{}", bucket.multipart_uploads);
+            bucket.uploads.insert(upload_id.clone(), Default::default());
+            let body = format!("<Result><UploadId>{upload_id}</UploadId></Result>");
+            (200, String::new(), body.into_bytes())
+        }
+        ("PUT", Some(upload_id)) => {
+            let part_number = query["partNumber"].parse().unwrap();
+            let Some(parts) = bucket.uploads.get_mut(*upload_id) else {
+                return (404, String::new(), Vec::new());
+            };
+            parts.insert(part_number, body);
+            let etag = format!("etag: \"{part_number}\"\r\n");
+            (200, etag, Vec::new())
+        }
+        ("POST", Some(upload_id)) => {
+            let Some(parts) = bucket.uploads.remove(*upload_id) else {
+                return (404, String::new(), Vec::new());
+            };
+            let data = parts.into_values().flatten().collect();
+            bucket.objects.insert(key, data);
+            (200, String::new(), b"<Result></Result>".to_vec())
+        }
+        ("DELETE", Some(upload_id)) => {
+            bucket.uploads.remove(*upload_id);
+            (204, String::new(), Vec::new())
+        }
+        ("PUT", None) => {
+            bucket.objects.insert(key, body);
+            (200, String::new(), Vec::new())
+        }
+        ("DELETE", None) => match bucket.objects.remove(&key) {
+            Some(_) => (204, String::new(), Vec::new()),
+            None => (404, String::new(), Vec::new()),
+        },
+        ("GET" | "HEAD", None) => {
+            if method == "GET" {
+                *bucket.gets.entry(key.clone()).or_default() += 1;
+            }
+            let Some(data) = bucket.objects.get(&key) else {
+                return (404, String::new(), Vec::new());
+            };
+            let range = headers
+                .get("range")
+                .and_then(|x| x.strip_prefix("bytes="))
+                .and_then(|x| x.split_once('-'))
+                .map(|(start, end)| {
+                    let start = start.parse::<usize>().unwrap();
+                    let end = end.parse::<usize>().unwrap() + 1;
+                    start.min(data.len())..end.min(data.len())
+                });
+            match range {
+                Some(range) => (206, String::new(), data[range].to_vec()),
+                None => (200, String::new(), data.clone()),
+            }
+        }
+        _ => (400, String::new(), Vec::new()),
+    }
+}
@@ -0,0 +1,323 @@
+use std::net::SocketAddr;
+
+use bao_tree::{io::fsm::encode_ranges_validated, ChunkRanges};
+use iroh_io::AsyncSliceReaderExt;
+
+use super::{test_utils::fake_s3, *};
+use crate::{
+    store::{MapMut as _, Store as _},
+    util::progress::{FlumeProgressSender, IgnoreProgressSender},
+};
+
+async fn create_test_store(path: &Path, addr: SocketAddr, part_size: usize) -> Store {
+    let _ = tracing_subscriber::fmt::try_init();
+    let mut config = S3Config::new(
+        format!("http://{addr}"),
+        "bucket",
+        "us-east-1",
+        "access",
+        "secret",
+    )
+    .with_prefix("blobs/");
+    config.part_size = part_size;
+    Store::load(path, config).await.unwrap()
+}
+
+fn test_data(size: usize) -> Bytes {
+    (0..size)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// Check that the entry can be served, which validates data and outboard.
+async fn validate(store: &Store, hash: Hash, data: &[u8]) {
+    let entry = store.get(&hash).await.unwrap().expect("entry not found");
+    assert!(entry.is_complete());
+    let actual = entry
+        .data_reader()
+        .await
+        .unwrap()
+        .read_to_end()
+        .await
+        .unwrap();
+    assert_eq!(actual, data);
+    let outboard = entry.outboard().await.unwrap();
+    let mut reader = entry.data_reader().await.unwrap();
+    let mut encoded = Vec::new();
+    encode_ranges_validated(&mut reader, outboard, &ChunkRanges::all(), &mut encoded)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn s3_store_import_cases() {
+    let testdir = tempfile::tempdir().unwrap();
+    let (addr, bucket) = fake_s3().await;
+    let store = create_test_store(testdir.path(), addr, 64 * 1024).await;
+    // small blobs without outboard, single put, and multipart uploads
+    let sizes = [0, 1, 1024, 16 * 1024, 64 * 1024, 100 * 1024, 1024 * 1024];
+    let mut tags = Vec::new();
+    for size in sizes {
+        let data = test_data(size);
+        let tag = store
+            .import_bytes(data.clone(), BlobFormat::Raw)
+            .await
+            .unwrap();
+        let hash = *tag.hash();
+        validate(&store, hash, &data).await;
+        let bucket = bucket.lock().unwrap();
+        assert_eq!(
+            bucket.objects.get(&format!("blobs/{}", data_key(&hash))),
+            Some(&data.to_vec())
+        );
+        assert_eq!(
+            bucket
+                .objects
+                .contains_key(&format!("blobs/{}", outboard_key(&hash))),
+            raw_outboard_size(size as u64) > 0
+        );
+        tags.push(tag);
+    }
+    {
+        let bucket = bucket.lock().unwrap();
+        // two blobs are larger than the part size
+        assert_eq!(bucket.multipart_uploads, 2);
+        assert!(bucket.uploads.is_empty());
+    }
+    // ranged reads
+    let data = test_data(1024 * 1024);
+    let entry = store
+        .get(tags.last().unwrap().hash())
+        .await
+        .unwrap()
+        .unwrap();
+    let mut reader = entry.data_reader().await.unwrap();
+    let bytes = reader.read_at(100_000, 1000).await.unwrap();
+    assert_eq!(bytes, &data[100_000..101_000]);
+    let bytes = reader.read_at(data.len() as u64 - 10, 1000).await.unwrap();
+    assert_eq!(bytes, &data[data.len() - 10..]);
+    assert!(reader
+        .read_at(data.len() as u64, 10)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn s3_store_import_file_and_export() {
+    let testdir = tempfile::tempdir().unwrap();
+    let (addr, _bucket) = fake_s3().await;
+    let store = create_test_store(&testdir.path().join("db"), addr, 64 * 1024).await;
+    let data = test_data(200 * 1024);
+    let path = testdir.path().join("in");
+    std::fs::write(&path, &data).unwrap();
+    let (tag, size) = store
+        .import_file(
+            path,
+            ImportMode::TryReference,
+            BlobFormat::Raw,
+            IgnoreProgressSender::default(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(size, data.len() as u64);
+    assert_eq!(*tag.hash(), Hash::new(&data));
+    validate(&store, *tag.hash(), &data).await;
+    let chunks = (0..data.len())
+        .step_by(1000)
+        .map(|i| Ok(data.slice(i..(i + 1000).min(data.len()))))
+        .collect::<Vec<_>>();
+    let stream = futures_lite::stream::iter(chunks);
+    let (tag2, _) = store
+        .import_stream(stream, BlobFormat::Raw, IgnoreProgressSender::default())
+        .await
+        .unwrap();
+    assert_eq!(tag2.hash(), tag.hash());
+    let target = testdir.path().join("out");
+    store
+        .export(
+            *tag.hash(),
+            target.clone(),
+            ExportMode::TryReference,
+            Box::new(|_| Ok(())),
+        )
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read(target).unwrap(), data);
+}
+
+#[cfg(feature = "fs-store")]
+#[tokio::test]
+async fn s3_store_partial() {
+    use crate::store::bao_file::test_support::{decode_response_into_batch, make_wire_data};
+
+    let testdir = tempfile::tempdir().unwrap();
+    let (addr, bucket) = fake_s3().await;
+    let store = create_test_store(testdir.path(), addr, 64 * 1024).await;
+    let data = test_data(100 * 1024);
+    let hash = Hash::new(&data);
+    let entry = store.get_or_create(hash, 0).await.unwrap();
+    assert_eq!(
+        store.entry_status(&hash).await.unwrap(),
+        EntryStatus::Partial
+    );
+    // write the second half first, like an out of order download would
+    for range in [64 * 1024..data.len() as u64, 0..64 * 1024] {
+        let (hash, chunk_ranges, wire_data) = make_wire_data(&data, [range]);
+        decode_response_into_batch(
+            hash,
+            IROH_BLOCK_SIZE,
+            chunk_ranges,
+            std::io::Cursor::new(wire_data.as_slice()),
+            entry.batch_writer().await.unwrap(),
+        )
+        .await
+        .unwrap();
+    }
+    // nothing is uploaded until the entry is complete, the data is on disk
+    assert!(bucket.lock().unwrap().objects.is_empty());
+    let partial_path = testdir.path().join("partial");
+    let data_path = partial_path.join(format!("{}.data", hash.to_hex()));
+    assert_eq!(std::fs::read(&data_path).unwrap(), data.as_ref());
+    store.insert_complete(entry).await.unwrap();
+    // the partial files are removed once the entry is complete
+    assert_eq!(std::fs::read_dir(&partial_path).unwrap().count(), 0);
+    assert_eq!(
+        store.entry_status(&hash).await.unwrap(),
+        EntryStatus::Complete
+    );
+    assert!(store.partial_blobs().await.unwrap().next().is_none());
+    assert_eq!(bucket.lock().unwrap().objects.len(), 2);
+    validate(&store, hash, &data).await;
+}
+
+#[tokio::test]
+async fn s3_store_persistence_and_delete() {
+    let testdir = tempfile::tempdir().unwrap();
+    let (addr, bucket) = fake_s3().await;
+    let data = test_data(100 * 1024);
+    let hash = {
+        let store = create_test_store(testdir.path(), addr, 64 * 1024).await;
+        let tag = store
+            .import_bytes(data.clone(), BlobFormat::Raw)
+            .await
+            .unwrap();
+        store
+            .set_tag(Tag::from("a"), Some(*tag.inner()), None)
+            .await
+            .unwrap();
+        *tag.hash()
+    };
+    // metadata is kept locally, data in the bucket
+    let store = create_test_store(testdir.path(), addr, 64 * 1024).await;
+    validate(&store, hash, &data).await;
+    let tags = store
+        .tags()
+        .await
+        .unwrap()
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(tags, vec![(Tag::from("a"), HashAndFormat::raw(hash))]);
+    let blobs = store
+        .blobs()
+        .await
+        .unwrap()
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(blobs, vec![hash]);
+    // a consistency check finds missing objects
+    bucket
+        .lock()
+        .unwrap()
+        .objects
+        .remove(&format!("blobs/{}", outboard_key(&hash)));
+    let (tx, rx) = flume::unbounded();
+    store
+        .consistency_check(false, FlumeProgressSender::new(tx).boxed())
+        .await
+        .unwrap();
+    assert!(rx.drain().any(|x| matches!(
+        x,
+        ConsistencyCheckProgress::Update {
+            level: ReportLevel::Error,
+            ..
+        }
+    )));
+    // deleting removes metadata and objects
+    store.delete(vec![hash]).await.unwrap();
+    assert!(store.get(&hash).await.unwrap().is_none());
+    assert!(bucket.lock().unwrap().objects.is_empty());
+}
+
+#[tokio::test]
+async fn s3_store_outboard_cache() {
+    let testdir = tempfile::tempdir().unwrap();
+    let (addr, bucket) = fake_s3().await;
+    let store = create_test_store(testdir.path(), addr, 64 * 1024).await;
+    let data = test_data(1024 * 1024);
+    let tag = store
+        .import_bytes(data.clone(), BlobFormat::Raw)
+        .await
+        .unwrap();
+    let hash = *tag.hash();
+    let key = format!("blobs/{}", outboard_key(&hash));
+    // the outboard is kept locally when uploading
+    validate(&store, hash, &data).await;
+    assert_eq!(bucket.lock().unwrap().gets.get(&key), None);
+    // without a local copy, it is fetched once in a single request
+    let tx = store.0.db.begin_write().unwrap();
+    tx.open_table(OUTBOARDS_TABLE)
+        .unwrap()
+        .remove(hash)
+        .unwrap();
+    tx.commit().unwrap();
+    validate(&store, hash, &data).await;
+    validate(&store, hash, &data).await;
+    assert_eq!(bucket.lock().unwrap().gets.get(&key), Some(&1));
+    // deleting removes the local copy as well
+    drop(tag);
+    store.delete(vec![hash]).await.unwrap();
+    let tx = store.0.db.begin_read().unwrap();
+    let outboards = tx.open_table(OUTBOARDS_TABLE).unwrap();
+    assert!(outboards.get(hash).unwrap().is_none());
+}
+
+/// The GET object example from the AWS signature version 4 documentation.
+#[test]
+fn sigv4_example() {
+    let empty_sha256 = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    let canonical_request = client::canonical_request(
+        "GET",
+        "/test.txt",
+        "",
+        &[
+            ("host", "examplebucket.s3.amazonaws.com"),
+            ("range", "bytes=0-9"),
+            ("x-amz-content-sha256", empty_sha256),
+            ("x-amz-date", "20130524T000000Z"),
+        ],
+        empty_sha256,
+    );
+    assert_eq!(
+        canonical_request,
+        "GET\n/test.txt\n\n\
+         host:examplebucket.s3.amazonaws.com\n\
+         range:bytes=0-9\n\
+         x-amz-content-sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855\n\
+         x-amz-date:20130524T000000Z\n\n\
+         host;range;x-amz-content-sha256;x-amz-date\n\
+         e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    let (scope, signature) = client::signature(
+        "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY",
+        "us-east-1",
+        "20130524T000000Z",
+        &canonical_request,
+    );
+    assert_eq!(scope, "20130524/us-east-1/s3/aws4_request");
+    assert_eq!(
+        signature,
+        "f0e8bdb87c964420e857bd35b5d6ed310bd44f0170aba48dd91039c6036bdb41"
+    );
+}
@@ -1,9 +1,15 @@
 //! Traits for in-memory or persistent maps of blob with bao encoded outboards.
-use std::{collections::BTreeSet, future::Future, io, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    future::Future,
+    io,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
 
 use bao_tree::{
     io::fsm::{BaoContentItem, Outboard},
-    BaoTree, ChunkRanges,
+    BaoTree, BlockSize, ChunkNum, ChunkRanges,
 };
 use bytes::Bytes;
 use futures_lite::{Stream, StreamExt};
@@ -15,11 +21,12 @@ use tokio::io::AsyncRead;
 use tokio_util::task::LocalPoolHandle;
 
 use crate::{
+    format::chunked::{ChunkedBlob, ChunkerConfig},
     hashseq::parse_hash_seq,
     protocol::RangeSpec,
     util::{
         progress::{BoxedProgressSender, IdGenerator, ProgressSender},
-        Tag,
+        Tag, TagUpdate,
     },
     BlobFormat, Hash, HashAndFormat, TempTag, IROH_BLOCK_SIZE,
 };
@@ -92,6 +99,31 @@ pub trait MapEntry: std::fmt::Debug + Clone + Send + Sync + 'static {
     fn outboard(&self) -> impl Future<Output = io::Result<impl Outboard>> + Send;
     /// A future that resolves to a reader that can be used to read the data
     fn data_reader(&self) -> impl Future<Output = io::Result<impl AsyncSliceReader>> + Send;
+
+    /// The chunks of the entry that are available.
+    ///
+    /// For a complete entry this is [`ChunkRanges::all`]. For a partial entry
+    /// these are the chunks for which both the data and the outboard are present.
+    fn available_ranges(&self) -> impl Future<Output = io::Result<ChunkRanges>> {
+        async move {
+            if self.is_complete() {
+                return Ok(ChunkRanges::all());
+            }
+            // compute the valid range from just looking at the data file
+            let mut data_reader = self.data_reader().await?;
+            let data_size = data_reader.size().await?;
+            let valid_from_data = ChunkRanges::from(..ChunkNum::full_chunks(data_size));
+            // compute the valid range from just looking at the outboard file
+            let mut outboard = self.outboard().await?;
+            let all = ChunkRanges::all();
+            let mut stream = bao_tree::io::fsm::valid_outboard_ranges(&mut outboard, &all);
+            let mut valid_from_outboard = ChunkRanges::empty();
+            while let Some(range) = stream.next().await {
+                valid_from_outboard |= ChunkRanges::from(range?);
+            }
+            Ok(valid_from_data.intersection(&valid_from_outboard))
+        }
+    }
 }
 
 /// A generic map from hashes to bao blobs (blobs with bao outboards).
@@ -153,6 +185,14 @@ pub trait BaoBatchWriter {
         batch: Vec<BaoContentItem>,
     ) -> impl Future<Output = io::Result<()>>;
 
+    /// The chunk group size of the outboard that is written.
+    ///
+    /// Data is always received with [`IROH_BLOCK_SIZE`]. If this is different,
+    /// batches are converted before they are passed to [`Self::write_batch`].
+    fn block_size(&self) -> BlockSize {
+        IROH_BLOCK_SIZE
+    }
+
     /// Sync the written data to permanent storage, if applicable.
     /// E.g. for a file based implementation, this would call sync_data
     /// on all files.
@@ -165,6 +205,10 @@ impl<W: BaoBatchWriter> BaoBatchWriter for &mut W {
         (**self).write_batch(size, batch).await
     }
 
+    fn block_size(&self) -> BlockSize {
+        (**self).block_size()
+    }
+
     async fn sync(&mut self) -> io::Result<()> {
         (**self).sync().await
     }
@@ -214,6 +258,10 @@ impl<W: BaoBatchWriter, F: Fn(u64, usize) -> io::Result<()> + 'static> BaoBatchW
     async fn sync(&mut self) -> io::Result<()> {
         self.0.sync().await
     }
+
+    fn block_size(&self) -> BlockSize {
+        self.0.block_size()
+    }
 }
 
 /// A mutable bao map.
@@ -266,6 +314,21 @@ pub trait ReadableStore: Map {
     /// list all tags (collections or other explicitly added things) in the database
     fn tags(&self) -> impl Future<Output = io::Result<DbIter<(Tag, HashAndFormat)>>> + Send;
 
+    /// list all tags that start with `prefix`
+    fn tags_with_prefix(
+        &self,
+        prefix: Tag,
+    ) -> impl Future<Output = io::Result<DbIter<(Tag, HashAndFormat)>>> + Send {
+        async move {
+            let tags = self.tags().await?;
+            let tags = tags.filter(move |item| match item {
+                Ok((tag, _)) => tag.starts_with(&prefix.0),
+                Err(_) => true,
+            });
+            Ok(Box::new(tags) as DbIter<_>)
+        }
+    }
+
     /// Temp tags
     fn temp_tags(&self) -> Box<dyn Iterator<Item = HashAndFormat> + Send + Sync + 'static>;
 
@@ -279,6 +342,24 @@ pub trait ReadableStore: Map {
     /// list partial blobs in the database
     fn partial_blobs(&self) -> impl Future<Output = io::Result<DbIter<Hash>>> + Send;
 
+    /// Size and last access time of all complete and partial blobs in the database.
+    ///
+    /// This is used to enforce a storage quota during gc. Access times are only
+    /// tracked in memory, so they are lost when the store is closed.
+    fn blob_usage(&self) -> impl Future<Output = io::Result<DbIter<BlobUsage>>> + Send;
+
+    /// Counts and sizes of all entries in the store, by status and data location.
+    fn entry_stats(&self) -> impl Future<Output = io::Result<EntryStats>> + Send;
+
+    /// Statistics for the whole store.
+    ///
+    /// In addition to the [`ReadableStore::entry_stats`], this computes the
+    /// size of the data that is reachable from each tag. The future is not
+    /// `Send`, since it has to read hash sequences from the store.
+    fn stats(&self) -> impl Future<Output = io::Result<StoreStats>> {
+        stats_impl(self)
+    }
+
     /// This trait method extracts a file to a local path.
     ///
     /// `hash` is the hash of the file
@@ -343,15 +424,91 @@ pub trait Store: ReadableStore + MapMut + std::fmt::Debug {
         self.import_stream(stream, format, progress)
     }
 
+    /// Import data from a stream of bytes, split into content defined chunks.
+    ///
+    /// Each chunk is stored as a blob of its own, so data that is shared with
+    /// other imports is only stored once. Returns a temp tag for the
+    /// [`BlobFormat::HashSeq`] root of the [`ChunkedBlob`] and the total size.
+    ///
+    /// [`ChunkedBlob`]: crate::format::chunked::ChunkedBlob
+    fn import_stream_chunked(
+        &self,
+        data: impl Stream<Item = io::Result<Bytes>> + Send + Unpin + 'static,
+        config: ChunkerConfig,
+        progress: impl ProgressSender<Msg = ImportProgress> + IdGenerator,
+    ) -> impl Future<Output = io::Result<(TempTag, u64)>> + Send {
+        ChunkedBlob::import(self, data, config, progress)
+    }
+
     /// Set a tag
+    ///
+    /// If `ttl` is set, the tag expires after this duration and is removed by
+    /// the next gc mark phase. Setting a tag without a `ttl` makes it permanent.
     fn set_tag(
         &self,
         name: Tag,
         hash: Option<HashAndFormat>,
+        ttl: Option<Duration>,
     ) -> impl Future<Output = io::Result<()>> + Send;
 
     /// Create a new tag
-    fn create_tag(&self, hash: HashAndFormat) -> impl Future<Output = io::Result<Tag>> + Send;
+    ///
+    /// If `ttl` is set, the tag expires after this duration, see [`Store::set_tag`].
+    fn create_tag(
+        &self,
+        hash: HashAndFormat,
+        ttl: Option<Duration>,
+    ) -> impl Future<Output = io::Result<Tag>> + Send;
+
+    /// Remove all tags that have expired, returning the removed tags.
+    ///
+    /// This is called at the start of the gc mark phase.
+    fn remove_expired_tags(
+        &self,
+    ) -> impl Future<Output = io::Result<Vec<(Tag, HashAndFormat)>>> + Send;
+
+    /// Atomically apply a batch of tag changes.
+    ///
+    /// If the expectation of any of the updates does not hold, nothing is
+    /// changed and `false` is returned. This can be used to e.g. swap a
+    /// "current" and a "previous" tag without a window in which either is
+    /// missing or stale. If a tag appears more than once, the expectations
+    /// refer to the value before the batch and the last update wins.
+    fn update_tags(&self, updates: Vec<TagUpdate>)
+        -> impl Future<Output = io::Result<bool>> + Send;
+
+    /// Delete all tags that start with `prefix`, returning the deleted tags.
+    ///
+    /// The default implementation lists the tags and deletes them with
+    /// [`Store::update_tags`], retrying a bounded number of times if
+    /// the tags change concurrently. Stores that can delete within a single
+    /// transaction should override this.
+    fn delete_tags_with_prefix(
+        &self,
+        prefix: Tag,
+    ) -> impl Future<Output = io::Result<Vec<(Tag, HashAndFormat)>>> + Send {
+        async move {
+            const MAX_ATTEMPTS: usize = 8;
+            for _ in 0..MAX_ATTEMPTS {
+                let tags = self
+                    .tags_with_prefix(prefix.clone())
+                    .await?
+                    .collect::<io::Result<Vec<_>>>()?;
+                let updates = tags
+                    .iter()
+                    .map(|(tag, value)| TagUpdate::delete(tag.clone()).if_equals(Some(*value)))
+                    .collect();
+                // if a tag was changed in the meantime, nothing is deleted and
+                // the next attempt lists the tags again
+                if self.update_tags(updates).await? {
+                    return Ok(tags);
+                }
+            }
+            Err(io::Error::other(format!(
+                "tags with prefix {prefix} changed concurrently"
+            )))
+        }
+    }
 
     /// Create a temporary pin for this store
     fn temp_tag(&self, value: HashAndFormat) -> TempTag;
@@ -382,13 +539,22 @@ pub trait Store: ReadableStore + MapMut + std::fmt::Debug {
 
     /// Remove all blobs that are not marked as live.
     ///
+    /// If a `quota` in bytes is given, blobs that are not live are only removed
+    /// while the total size of all blobs exceeds the quota, least recently used
+    /// first. Access times are not persisted, so after a restart blobs that have
+    /// not been accessed since are removed first, in arbitrary order.
+    ///
     /// Poll this stream to completion to perform a full gc sweep. Not polling this stream
     /// to completion just means that some garbage will remain in the database.
     ///
     /// Sweeping might take long, but it can safely be done in the background.
-    fn gc_sweep(&self, live: &BTreeSet<Hash>) -> impl Stream<Item = GcSweepEvent> + Unpin {
-        Gen::new(|co| async move {
-            if let Err(e) = gc_sweep_task(self, live, &co).await {
+    fn gc_sweep(
+        &self,
+        live: &BTreeSet<Hash>,
+        quota: Option<u64>,
+    ) -> impl Stream<Item = GcSweepEvent> + Unpin {
+        Gen::new(move |co| async move {
+            if let Err(e) = gc_sweep_task(self, live, quota, &co).await {
                 co.yield_(GcSweepEvent::Error(e)).await;
             }
         })
@@ -559,6 +725,9 @@ async fn gc_mark_task<'a>(
             co.yield_(GcMarkEvent::CustomWarning(format!($($arg)*), None)).await;
         };
     }
+    for (tag, value) in store.remove_expired_tags().await? {
+        co.yield_(GcMarkEvent::TagExpired { tag, value }).await;
+    }
     let mut roots = BTreeSet::new();
     debug!("traversing tags");
     for item in store.tags().await? {
@@ -612,8 +781,12 @@ async fn gc_mark_task<'a>(
 async fn gc_sweep_task<'a>(
     store: &'a impl Store,
     live: &BTreeSet<Hash>,
+    quota: Option<u64>,
     co: &Co<GcSweepEvent>,
 ) -> anyhow::Result<()> {
+    if let Some(quota) = quota {
+        return gc_evict_task(store, live, quota, co).await;
+    }
     let blobs = store.blobs().await?.chain(store.partial_blobs().await?);
     let mut count = 0;
     let mut batch = Vec::new();
@@ -639,9 +812,230 @@ async fn gc_sweep_task<'a>(
     Ok(())
 }
 
+/// Sweep implementation for a store with a storage quota.
+///
+/// Blobs that are not live are kept as long as the store is within the quota. Dead entries that
+/// do not count towards the quota, such as entries with external data or empty partial entries,
+/// would never be evicted, so they are always deleted.
+async fn gc_evict_task(
+    store: &impl Store,
+    live: &BTreeSet<Hash>,
+    quota: u64,
+    co: &Co<GcSweepEvent>,
+) -> anyhow::Result<()> {
+    let mut used = 0u64;
+    let mut candidates = Vec::new();
+    let mut batch = Vec::new();
+    let mut count = 0;
+    for usage in store.blob_usage().await? {
+        let usage = usage?;
+        used += usage.size;
+        if live.contains(&usage.hash) {
+            continue;
+        }
+        if usage.size > 0 {
+            candidates.push(usage);
+        } else {
+            batch.push(usage.hash);
+            count += 1;
+        }
+        if batch.len() >= 100 {
+            store.delete(std::mem::take(&mut batch)).await?;
+        }
+    }
+    if !batch.is_empty() {
+        store.delete(std::mem::take(&mut batch)).await?;
+    }
+    co.yield_(GcSweepEvent::CustomDebug(format!(
+        "deleted {} blobs without stored data",
+        count
+    )))
+    .await;
+    if used <= quota {
+        co.yield_(GcSweepEvent::CustomDebug(format!(
+            "{} of {} bytes used, nothing to evict",
+            used, quota
+        )))
+        .await;
+        return Ok(());
+    }
+    // blobs that have not been accessed since the store was opened go first
+    candidates.sort_by_key(|usage| usage.last_access);
+    for BlobUsage { hash, size, .. } in candidates {
+        if used <= quota {
+            break;
+        }
+        used -= size;
+        batch.push(hash);
+        co.yield_(GcSweepEvent::Evicted { hash, size }).await;
+        if batch.len() >= 100 {
+            store.delete(std::mem::take(&mut batch)).await?;
+        }
+    }
+    if !batch.is_empty() {
+        store.delete(batch).await?;
+    }
+    if used > quota {
+        co.yield_(GcSweepEvent::QuotaExceeded { used, quota }).await;
+    }
+    Ok(())
+}
+
+/// Size and last access time of a blob, see [`ReadableStore::blob_usage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobUsage {
+    /// The hash of the blob.
+    pub hash: Hash,
+    /// The number of bytes of data stored for the blob.
+    pub size: u64,
+    /// The size of the blob, if it is complete.
+    ///
+    /// This differs from `size` for blobs whose data is not owned by the store.
+    pub complete_size: Option<u64>,
+    /// The last time the blob was accessed, if it was accessed since the store was opened.
+    pub last_access: Option<SystemTime>,
+}
+
+/// Count and size of a group of entries, see [`EntryStats`].
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SizeStats {
+    /// The number of entries.
+    pub count: u64,
+    /// The number of bytes of data stored for the entries.
+    pub data_size: u64,
+    /// The number of bytes of outboard stored for the entries.
+    pub outboard_size: u64,
+}
+
+impl SizeStats {
+    /// Add an entry.
+    pub fn add(&mut self, data_size: u64, outboard_size: u64) {
+        self.count += 1;
+        self.data_size += data_size;
+        self.outboard_size += outboard_size;
+    }
+
+    /// The total number of bytes stored for the entries.
+    pub fn total_size(&self) -> u64 {
+        self.data_size + self.outboard_size
+    }
+}
+
+/// Counts and sizes of the entries in a store, see [`ReadableStore::entry_stats`].
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EntryStats {
+    /// Complete entries with data stored inline in the database.
+    pub inline: SizeStats,
+    /// Complete entries with data stored in files or memory owned by the store.
+    pub owned: SizeStats,
+    /// Complete entries with data in external files, which are referenced but
+    /// not owned by the store.
+    ///
+    /// The data size is the size of the external data, which does not take
+    /// space in the store.
+    pub external: SizeStats,
+    /// Partial entries.
+    ///
+    /// The sizes are the space currently taken, not the size of the complete blobs.
+    pub partial: SizeStats,
+}
+
+impl EntryStats {
+    /// Counts and sizes of all complete entries.
+    pub fn complete(&self) -> SizeStats {
+        let mut res = self.inline;
+        for stats in [self.owned, self.external] {
+            res.count += stats.count;
+            res.data_size += stats.data_size;
+            res.outboard_size += stats.outboard_size;
+        }
+        res
+    }
+}
+
+/// The data reachable from a tag, see [`StoreStats`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TagStats {
+    /// The name of the tag.
+    pub tag: Tag,
+    /// The content the tag points to.
+    pub content: HashAndFormat,
+    /// The number of complete blobs reachable from the tag.
+    pub blobs: u64,
+    /// The total size of the complete blobs reachable from the tag.
+    pub size: u64,
+    /// The number of reachable blobs that are missing or incomplete.
+    pub missing: u64,
+}
+
+/// Statistics for a whole store, see [`ReadableStore::stats`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StoreStats {
+    /// Counts and sizes of the entries in the store.
+    pub entries: EntryStats,
+    /// The data reachable from each tag.
+    pub tags: Vec<TagStats>,
+}
+
+async fn stats_impl(store: &impl ReadableStore) -> io::Result<StoreStats> {
+    let entries = store.entry_stats().await?;
+    // sizes are taken from a single pass over the store instead of getting each
+    // entry, since getting an entry counts as an access for eviction
+    let mut sizes = BTreeMap::new();
+    for usage in store.blob_usage().await? {
+        let usage = usage?;
+        if let Some(size) = usage.complete_size {
+            sizes.insert(usage.hash, size);
+        }
+    }
+    let mut tags = Vec::new();
+    for item in store.tags().await? {
+        let (tag, content) = item?;
+        let mut reachable = BTreeSet::from([content.hash]);
+        // reading a hash seq root counts as an access, like during gc marking
+        if !content.format.is_raw() && sizes.contains_key(&content.hash) {
+            if let Some(entry) = store.get(&content.hash).await? {
+                if entry.is_complete() {
+                    // a root that is not a valid hash seq keeps nothing else alive
+                    if let Ok((mut stream, _)) = parse_hash_seq(entry.data_reader().await?).await {
+                        while let Ok(Some(hash)) = stream.next().await {
+                            reachable.insert(hash);
+                        }
+                    }
+                }
+            }
+        }
+        let mut stats = TagStats {
+            tag,
+            content,
+            blobs: 0,
+            size: 0,
+            missing: 0,
+        };
+        for hash in reachable {
+            match sizes.get(&hash) {
+                Some(size) => {
+                    stats.blobs += 1;
+                    stats.size += size;
+                }
+                None => stats.missing += 1,
+            }
+        }
+        tags.push(stats);
+    }
+    Ok(StoreStats { entries, tags })
+}
+
 /// An event related to GC
 #[derive(Debug)]
 pub enum GcMarkEvent {
+    /// A tag has expired and was removed.
+    TagExpired {
+        /// The name of the tag.
+        tag: Tag,
+        /// The content the tag was pointing to.
+        value: HashAndFormat,
+    },
     /// A custom event (info)
     CustomDebug(String),
     /// A custom non critical error
@@ -653,6 +1047,20 @@ pub enum GcMarkEvent {
 /// An event related to GC
 #[derive(Debug)]
 pub enum GcSweepEvent {
+    /// A blob that is not live was deleted to get below the storage quota.
+    Evicted {
+        /// The hash of the blob.
+        hash: Hash,
+        /// The number of bytes freed.
+        size: u64,
+    },
+    /// The store is above the storage quota even after evicting all blobs that are not live.
+    QuotaExceeded {
+        /// The number of bytes in use.
+        used: u64,
+        /// The quota in bytes.
+        quota: u64,
+    },
     /// A custom event (debug)
     CustomDebug(String),
     /// A custom non critical error
@@ -758,6 +1166,11 @@ pub enum ExportFormat {
     ///
     /// If the blob cannot be parsed as a collection, the operation will fail.
     Collection,
+    /// The hash refers to a [`crate::format::chunked::ChunkedBlob`] root and
+    /// the chunks shall be reassembled into a single file.
+    ///
+    /// If the blob cannot be parsed as a chunked blob, the operation will fail.
+    Chunked,
 }
 
 #[allow(missing_docs)]
@@ -8,7 +8,7 @@ use std::{
     borrow::Borrow,
     fmt,
     sync::{Arc, Weak},
-    time::SystemTime,
+    time::{Duration, SystemTime},
 };
 
 use crate::{BlobFormat, Hash, HashAndFormat, IROH_BLOCK_SIZE};
@@ -17,6 +17,7 @@ pub mod io;
 mod mem_or_file;
 pub mod progress;
 pub use mem_or_file::MemOrFile;
+pub(crate) mod reblock;
 mod sparse_mem_file;
 pub use sparse_mem_file::SparseMemFile;
 
@@ -124,6 +125,14 @@ impl Tag {
             i += 1;
         }
     }
+
+    /// Check if the tag starts with `prefix`.
+    ///
+    /// Prefixes can be used to give applications that share a store their own
+    /// namespace of tags, e.g. `myapp/current` and `myapp/previous`.
+    pub fn starts_with(&self, prefix: &[u8]) -> bool {
+        self.0.starts_with(prefix)
+    }
 }
 
 /// Option for commands that allow setting a tag
@@ -135,6 +144,68 @@ pub enum SetTagOption {
     Named(Tag),
 }
 
+/// A change of a single tag, as part of an atomic batch of tag changes.
+///
+/// See [`Store::update_tags`](crate::store::Store::update_tags).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagUpdate {
+    /// The tag to change
+    pub name: Tag,
+    /// The current value the tag must have for the batch to be applied.
+    ///
+    /// `Some(None)` means that the tag must not exist, `None` means that the
+    /// current value does not matter.
+    pub expected: Option<Option<HashAndFormat>>,
+    /// The new value, or `None` to delete the tag
+    pub value: Option<HashAndFormat>,
+    /// Time after which the new value expires, see
+    /// [`Store::set_tag`](crate::store::Store::set_tag)
+    pub ttl: Option<Duration>,
+}
+
+impl TagUpdate {
+    /// Set `name` to `value`, regardless of its current value.
+    pub fn set(name: Tag, value: HashAndFormat) -> Self {
+        Self {
+            name,
+            expected: None,
+            value: Some(value),
+            ttl: None,
+        }
+    }
+
+    /// Delete `name`, regardless of its current value.
+    pub fn delete(name: Tag) -> Self {
+        Self {
+            name,
+            expected: None,
+            value: None,
+            ttl: None,
+        }
+    }
+
+    /// Only apply the batch if the tag currently has the value `expected`,
+    /// or does not exist if `expected` is `None`.
+    pub fn if_equals(mut self, expected: Option<HashAndFormat>) -> Self {
+        self.expected = Some(expected);
+        self
+    }
+
+    /// Let the new value expire after `ttl`.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Check if the expectation of this update holds for the current value.
+    pub fn matches(&self, current: Option<&HashAndFormat>) -> bool {
+        match &self.expected {
+            Some(expected) => expected.as_ref() == current,
+            None => true,
+        }
+    }
+}
+
 /// Trait used from temp tags to notify an abstract store that a temp tag is
 /// being dropped.
 pub trait TagDrop: std::fmt::Debug + Send + Sync + 'static {
@@ -1,4 +1,4 @@
-use std::{fs::File, io};
+use std::io;
 
 use bao_tree::io::sync::{ReadAt, Size};
 use bytes::Bytes;
@@ -29,7 +29,7 @@ where
     }
 }
 
-impl ReadAt for MemOrFile<Bytes, File> {
+impl<F: ReadAt> ReadAt for MemOrFile<Bytes, F> {
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
         match self {
             MemOrFile::Mem(mem) => mem.as_ref().read_at(offset, buf),
@@ -38,7 +38,7 @@ impl ReadAt for MemOrFile<Bytes, File> {
     }
 }
 
-impl Size for MemOrFile<Bytes, File> {
+impl<F: Size> Size for MemOrFile<Bytes, F> {
     fn size(&self) -> io::Result<Option<u64>> {
         match self {
             MemOrFile::Mem(mem) => Ok(Some(mem.len() as u64)),
@@ -0,0 +1,300 @@
+//! Conversion of bao encodings between chunk group sizes.
+//!
+//! The protocol always uses [`IROH_BLOCK_SIZE`](crate::IROH_BLOCK_SIZE), but a
+//! store can keep its outboards with a different chunk group size. Hashes of
+//! groups that are larger than the chunk group size of an outboard are stored
+//! in the outboard, hashes of smaller groups have to be computed from the data.
+//!
+//! [`ReblockOutboard`] presents an outboard as if it had a different chunk
+//! group size, for sending, and [`reblock_batch`] converts received items for
+//! a writer with a different chunk group size.
+use std::{io, ops::Range};
+
+use bao_tree::{
+    blake3,
+    io::{fsm::Outboard, BaoContentItem, Leaf, Parent},
+    BaoTree, BlockSize, ChunkNum, TreeNode,
+};
+use iroh_io::AsyncSliceReader;
+
+/// An outboard that presents an inner outboard with a different chunk group size.
+///
+/// Hash pairs that the inner outboard has are loaded from it, all others are
+/// computed from the data. The data is not validated here, but the encoder
+/// that uses this outboard validates everything it sends.
+#[derive(Debug)]
+pub struct ReblockOutboard<O, D> {
+    inner: O,
+    data: D,
+    tree: BaoTree,
+}
+
+impl<O: Outboard, D: AsyncSliceReader> ReblockOutboard<O, D> {
+    /// Wrap `inner`, which validates `data`, to look like an outboard with `block_size`.
+    pub fn new(inner: O, data: D, block_size: BlockSize) -> Self {
+        let tree = BaoTree::new(inner.tree().size(), block_size);
+        Self { inner, data, tree }
+    }
+}
+
+impl<O: Outboard, D: AsyncSliceReader> Outboard for ReblockOutboard<O, D> {
+    fn root(&self) -> blake3::Hash {
+        self.inner.root()
+    }
+
+    fn tree(&self) -> BaoTree {
+        self.tree
+    }
+
+    async fn load(&mut self, node: TreeNode) -> io::Result<Option<(blake3::Hash, blake3::Hash)>> {
+        let inner_tree = self.inner.tree();
+        if node.level() >= inner_tree.block_size().chunk_log() as u32 {
+            return self.inner.load(node).await;
+        }
+        let size = inner_tree.size();
+        let start = node.chunk_range().start;
+        let mid = node.mid();
+        if mid.to_bytes() >= size {
+            return Ok(None);
+        }
+        let end = node.chunk_range().end.to_bytes().min(size);
+        let len = usize::try_from(end - start.to_bytes()).expect("node too large");
+        let data = self.data.read_at(start.to_bytes(), len).await?;
+        if data.len() != len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("data missing for node {node:?}"),
+            ));
+        }
+        Ok(Some(hash_pair(node, start, &data)))
+    }
+}
+
+/// Convert a batch of items encoded with chunk group size `from` to items for
+/// an outboard with chunk group size `to`, for a blob of `size` bytes.
+///
+/// Parents that are too small for `to` are dropped, and parents that are
+/// missing because `to` is smaller than `from` are computed from the leaves.
+/// The batch must already be validated.
+pub fn reblock_batch(
+    size: u64,
+    batch: Vec<BaoContentItem>,
+    from: BlockSize,
+    to: BlockSize,
+) -> Vec<BaoContentItem> {
+    let min_level = to.chunk_log() as u32;
+    let max_level = from.chunk_log() as u32;
+    let root = BaoTree::new(size, from).root();
+    let mut res = Vec::with_capacity(batch.len());
+    for item in batch {
+        match item {
+            BaoContentItem::Parent(parent) => {
+                if parent.node.level() >= min_level {
+                    res.push(BaoContentItem::Parent(parent));
+                }
+            }
+            BaoContentItem::Leaf(leaf) => {
+                if min_level < max_level {
+                    add_parents(root, &leaf, size, min_level..max_level, &mut res);
+                }
+                res.push(BaoContentItem::Leaf(leaf));
+            }
+        }
+    }
+    res
+}
+
+/// Add the parents at `node` and below with a level in `levels` whose data is
+/// entirely contained in `leaf`, in pre order.
+///
+/// A leaf is usually a whole chunk group, but can be smaller when only some
+/// chunks of a chunk group were requested.
+fn add_parents(
+    node: TreeNode,
+    leaf: &Leaf,
+    size: u64,
+    levels: Range<u32>,
+    res: &mut Vec<BaoContentItem>,
+) {
+    let chunks = node.chunk_range();
+    let start = chunks.start.to_bytes();
+    let end = chunks.end.to_bytes().min(size);
+    let leaf_end = leaf.offset + leaf.data.len() as u64;
+    if node.level() < levels.start || end <= leaf.offset || start >= leaf_end {
+        return;
+    }
+    // nodes without a right half are not part of the tree
+    if levels.contains(&node.level())
+        && start >= leaf.offset
+        && end <= leaf_end
+        && node.mid().to_bytes() < size
+    {
+        let data = &leaf.data[(start - leaf.offset) as usize..(end - leaf.offset) as usize];
+        let pair = hash_pair(node, chunks.start, data);
+        res.push(BaoContentItem::Parent(Parent { node, pair }));
+    }
+    if let Some(left) = node.left_child() {
+        add_parents(left, leaf, size, levels.clone(), res);
+    }
+    if let Some(right) = node.right_child() {
+        add_parents(right, leaf, size, levels, res);
+    }
+}
+
+/// Compute the hashes of the two children of `node`, given the data of `node`
+/// that starts at chunk `start`.
+fn hash_pair(node: TreeNode, start: ChunkNum, data: &[u8]) -> (blake3::Hash, blake3::Hash) {
+    let mid = usize::try_from(ChunkNum(node.mid().0 - start.0).to_bytes()).unwrap();
+    let left = hash_subtree(start.0, &data[..mid]);
+    let right = hash_subtree(node.mid().0, &data[mid..]);
+    (left, right)
+}
+
+/// Hash a subtree that is not the root of its tree.
+fn hash_subtree(start_chunk: u64, data: &[u8]) -> blake3::Hash {
+    use blake3::guts::{parent_cv, ChunkState, CHUNK_LEN};
+    if data.len() <= CHUNK_LEN {
+        let mut hasher = ChunkState::new(start_chunk);
+        hasher.update(data);
+        hasher.finalize(false)
+    } else if data.len().is_power_of_two() {
+        blake3::guts::hash_subtree(start_chunk, data, false)
+    } else {
+        let chunks = data.len().div_ceil(CHUNK_LEN).next_power_of_two();
+        let mid = chunks / 2;
+        let left = hash_subtree(start_chunk, &data[..mid * CHUNK_LEN]);
+        let right = hash_subtree(start_chunk + mid as u64, &data[mid * CHUNK_LEN..]);
+        parent_cv(&left, &right, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bao_tree::{
+        io::{
+            fsm::{encode_ranges_validated, ResponseDecoder, ResponseDecoderNext},
+            outboard::PreOrderMemOutboard,
+        },
+        ChunkRanges,
+    };
+    use bytes::Bytes;
+
+    use super::*;
+
+    fn bs(chunk_log: u8) -> BlockSize {
+        BlockSize::from_chunk_log(chunk_log)
+    }
+
+    /// Encode `ranges` of `data` from an outboard with chunk group size `local`,
+    /// as an encoding with chunk group size `wire`.
+    async fn encode(
+        data: &Bytes,
+        local: BlockSize,
+        wire: BlockSize,
+        ranges: &ChunkRanges,
+    ) -> Vec<u8> {
+        let outboard = PreOrderMemOutboard::create(data, local);
+        let outboard = ReblockOutboard::new(outboard, data.clone(), wire);
+        let mut res = Vec::new();
+        encode_ranges_validated(data.clone(), outboard, ranges, &mut res)
+            .await
+            .unwrap();
+        res
+    }
+
+    /// Decode an encoding with chunk group size `wire` into an outboard with
+    /// chunk group size `local`.
+    async fn decode(
+        data: &Bytes,
+        encoded: Vec<u8>,
+        wire: BlockSize,
+        local: BlockSize,
+        ranges: &ChunkRanges,
+    ) -> PreOrderMemOutboard {
+        let size = data.len() as u64;
+        let hash = blake3::hash(data);
+        let mut decoder = ResponseDecoder::new(
+            hash,
+            ranges.clone(),
+            BaoTree::new(size, wire),
+            Bytes::from(encoded),
+        );
+        let tree = BaoTree::new(size, local);
+        let mut outboard = PreOrderMemOutboard {
+            root: hash,
+            tree,
+            data: vec![0u8; tree.outboard_size() as usize],
+        };
+        let mut batch = Vec::new();
+        while let ResponseDecoderNext::More((next, item)) = decoder.next().await {
+            let item = item.unwrap();
+            let is_leaf = matches!(item, BaoContentItem::Leaf(_));
+            batch.push(item);
+            if is_leaf {
+                for item in reblock_batch(size, std::mem::take(&mut batch), wire, local) {
+                    if let BaoContentItem::Parent(parent) = item {
+                        let offset = tree.pre_order_offset(parent.node).unwrap() as usize;
+                        let target = &mut outboard.data[offset * 64..offset * 64 + 64];
+                        target[..32].copy_from_slice(parent.pair.0.as_bytes());
+                        target[32..].copy_from_slice(parent.pair.1.as_bytes());
+                    }
+                }
+            }
+            decoder = next;
+        }
+        outboard
+    }
+
+    #[tokio::test]
+    async fn reblock_roundtrip() {
+        let sizes = [
+            0,
+            1,
+            1024,
+            1025,
+            16 * 1024,
+            16 * 1024 + 1,
+            100_000,
+            1024 * 1024 + 17,
+        ];
+        for size in sizes {
+            let data = Bytes::from((0..size).map(|i| (i / 7) as u8).collect::<Vec<_>>());
+            let ranges = ChunkRanges::all();
+            for local in [bs(0), bs(2), bs(4), bs(6)] {
+                let wire = bs(4);
+                let expected = encode(&data, wire, wire, &ranges).await;
+                let encoded = encode(&data, local, wire, &ranges).await;
+                assert_eq!(encoded, expected, "size {size} local {local}");
+                let outboard = decode(&data, encoded, wire, local, &ranges).await;
+                assert_eq!(
+                    outboard.data,
+                    PreOrderMemOutboard::create(&data, local).data,
+                    "size {size} local {local}"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn reblock_ranges() {
+        let data = Bytes::from((0..300_000u32).map(|i| i as u8).collect::<Vec<_>>());
+        let ranges = ChunkRanges::from(ChunkNum(40)..ChunkNum(70));
+        for local in [bs(0), bs(2), bs(3), bs(5), bs(8)] {
+            let wire = bs(4);
+            let expected = encode(&data, wire, wire, &ranges).await;
+            let encoded = encode(&data, local, wire, &ranges).await;
+            assert_eq!(encoded, expected, "local {local}");
+            // the ranges split chunk groups, so the leaves at the edges are partial
+            let outboard = decode(&data, encoded, wire, local, &ranges).await;
+            let full = PreOrderMemOutboard::create(&data, local);
+            let mut written = 0;
+            for (actual, expected) in outboard.data.chunks(64).zip(full.data.chunks(64)) {
+                if actual.iter().any(|x| *x != 0) {
+                    assert_eq!(actual, expected, "local {local}");
+                    written += 1;
+                }
+            }
+            assert!(written > 0, "local {local}");
+        }
+    }
+}
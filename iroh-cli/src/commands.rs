@@ -89,6 +89,12 @@ pub(crate) enum Commands {
         /// Options when adding data.
         #[clap(flatten)]
         add_options: BlobAddOptions,
+
+        /// Address to serve blobs and collections over HTTP on.
+        ///
+        /// Overrides `gateway_addr` from the config file. Disabled by default.
+        #[clap(long)]
+        gateway_addr: Option<SocketAddr>,
     },
 
     /// Open the iroh console
@@ -172,7 +178,11 @@ impl Cli {
                     command.run(&iroh, &env).await
                 }
             }
-            Commands::Start { add, add_options } => {
+            Commands::Start {
+                add,
+                add_options,
+                gateway_addr,
+            } => {
                 // if adding data on start, exit early if the path doesn't exist
                 if let Some(BlobSource::Path(ref path)) = add {
                     ensure!(
@@ -181,7 +191,10 @@ impl Cli {
                         path.display()
                     );
                 }
-                let config = Self::load_config(self.config, self.metrics_port).await?;
+                let mut config = Self::load_config(self.config, self.metrics_port).await?;
+                if gateway_addr.is_some() {
+                    config.gateway_addr = gateway_addr;
+                }
 
                 let add_command = add.map(|source| blob::BlobCommands::Add {
                     source,
@@ -17,17 +17,20 @@ use iroh::{
     base::node_addr::AddrInfoOptions,
     base::ticket::BlobTicket,
     blobs::{
+        downloader::{DownloadInfo, IntentId},
+        format::chunked::ChunkerConfig,
         get::{db::DownloadProgress, progress::BlobProgress, Stats},
         provider::AddProgress,
         store::{
-            ConsistencyCheckProgress, ExportFormat, ExportMode, ReportLevel, ValidateProgress,
+            ConsistencyCheckProgress, ExportFormat, ExportMode, ReportLevel, SizeStats, StoreStats,
+            ValidateProgress,
         },
         util::SetTagOption,
         BlobFormat, Hash, HashAndFormat, Tag,
     },
     client::{
         blobs::{
-            BlobInfo, BlobStatus, CollectionInfo, DownloadMode, DownloadOptions,
+            AddPathOptions, BlobInfo, BlobStatus, CollectionInfo, DownloadMode, DownloadOptions,
             IncompleteBlobInfo, WrapOption,
         },
         Iroh,
@@ -92,6 +95,16 @@ pub enum BlobCommands {
         /// downloads running concurrently.
         #[clap(long)]
         queued: bool,
+        /// Priority of the download in the download queue.
+        ///
+        /// Queued downloads with a higher priority are started first.
+        #[clap(
+            long,
+            requires = "queued",
+            allow_negative_numbers = true,
+            default_value_t = 0
+        )]
+        priority: i32,
     },
     /// Export a blob from the internal blob store to the local filesystem.
     Export {
@@ -105,11 +118,35 @@ pub enum BlobCommands {
         /// the collection.
         #[clap(long, default_value_t = false)]
         recursive: bool,
+        /// Set to true if the hash refers to a file added with `add --chunked`, to reassemble
+        /// the chunks into a single file.
+        #[clap(long, default_value_t = false, conflicts_with = "recursive")]
+        chunked: bool,
         /// If set, the data will be moved to the output directory, and iroh will assume that it
         /// will not change.
         #[clap(long, default_value_t = false)]
         stable: bool,
     },
+    /// Export a blob or collection to an archive file, for moving verified data
+    /// to a node without a connection to this one.
+    ExportArchive {
+        /// The hash to export.
+        hash: Hash,
+        /// The archive file to create.
+        out: PathBuf,
+        /// Set to true if the hash refers to a collection and you want to export all children of
+        /// the collection.
+        #[clap(long, default_value_t = false)]
+        recursive: bool,
+    },
+    /// Validate and import an archive file created with `export-archive`.
+    ImportArchive {
+        /// The archive file to import.
+        path: PathBuf,
+        /// Tag to tag the data with.
+        #[clap(long)]
+        tag: Option<String>,
+    },
     /// List available content on the node.
     #[clap(subcommand)]
     List(ListCommands),
@@ -139,9 +176,14 @@ pub enum BlobCommands {
         #[clap(long, default_value_t = false)]
         repair: bool,
     },
+    /// Show how much space the content on the node takes, and how much data each tag keeps alive.
+    Stats,
     /// Delete content on the node.
     #[clap(subcommand)]
     Delete(DeleteCommands),
+    /// Inspect and manage the download queue of the node.
+    #[clap(subcommand)]
+    Queue(QueueCommands),
     /// Get a ticket to share this blob.
     Share {
         /// Hash of the blob to share.
@@ -194,6 +236,7 @@ impl BlobCommands {
                 stable,
                 tag,
                 queued,
+                priority,
             } => {
                 let (node_addr, hash, format) = match ticket {
                     TicketOrHash::Ticket(ticket) => {
@@ -266,6 +309,7 @@ impl BlobCommands {
                             nodes: vec![node_addr],
                             tag,
                             mode,
+                            priority,
                         },
                     )
                     .await?;
@@ -308,6 +352,7 @@ impl BlobCommands {
                 hash,
                 out,
                 recursive,
+                chunked,
                 stable,
             } => {
                 match out {
@@ -316,6 +361,10 @@ impl BlobCommands {
                             !recursive,
                             "Recursive option is not supported when exporting to STDOUT"
                         );
+                        ensure!(
+                            !chunked,
+                            "Chunked option is not supported when exporting to STDOUT"
+                        );
                         let mut blob_read = iroh.blobs().read(hash).await?;
                         tokio::io::copy(&mut blob_read, &mut tokio::io::stdout()).await?;
                     }
@@ -328,9 +377,10 @@ impl BlobCommands {
                             true => ExportMode::TryReference,
                             false => ExportMode::Copy,
                         };
-                        let format = match recursive {
-                            true => ExportFormat::Collection,
-                            false => ExportFormat::Blob,
+                        let format = match (recursive, chunked) {
+                            (true, _) => ExportFormat::Collection,
+                            (false, true) => ExportFormat::Chunked,
+                            (false, false) => ExportFormat::Blob,
                         };
                         tracing::info!(
                             "exporting {hash} to {} -> {}",
@@ -344,8 +394,41 @@ impl BlobCommands {
                 };
                 Ok(())
             }
+            Self::ExportArchive {
+                hash,
+                out,
+                recursive,
+            } => {
+                let absolute = std::env::current_dir()?.join(&out);
+                ensure!(!absolute.is_dir(), "output must not be a directory");
+                let format = match recursive {
+                    true => BlobFormat::HashSeq,
+                    false => BlobFormat::Raw,
+                };
+                iroh.blobs()
+                    .export_archive(hash, format, absolute.clone())
+                    .await?;
+                println!("Exported {hash} to {}", absolute.display());
+                Ok(())
+            }
+            Self::ImportArchive { path, tag } => {
+                let absolute = std::env::current_dir()?.join(&path);
+                let tag = match tag {
+                    Some(tag) => SetTagOption::Named(Tag::from(tag)),
+                    None => SetTagOption::Auto,
+                };
+                let (content, tag) = iroh.blobs().import_archive(absolute, tag).await?;
+                println!("Imported {content} with tag {tag}");
+                Ok(())
+            }
             Self::List(cmd) => cmd.run(iroh).await,
             Self::Delete(cmd) => cmd.run(iroh).await,
+            Self::Stats => {
+                let stats = iroh.blobs().stats().await?;
+                print_store_stats(&stats);
+                Ok(())
+            }
+            Self::Queue(cmd) => cmd.run(iroh).await,
             Self::Validate { verbose, repair } => validate(iroh, verbose, repair).await,
             Self::ConsistencyCheck { verbose, repair } => {
                 consistency_check(iroh, verbose, repair).await
@@ -430,6 +513,21 @@ pub struct BlobAddOptions {
     /// Do not print the all-in-one ticket to get the added data from this node.
     #[clap(long)]
     pub no_ticket: bool,
+
+    /// Split a single file into content defined chunks.
+    ///
+    /// Chunks that are shared with other chunked files are only stored and transferred
+    /// once, so this saves space and bandwidth for versions of the same large file.
+    /// Use `blob export --chunked` to reassemble the file.
+    #[clap(long, conflicts_with_all = ["wrap", "in_place"])]
+    pub chunked: bool,
+
+    /// Keep file modes, modification times, symlinks and empty directories when
+    /// adding a directory.
+    ///
+    /// The hash of the collection then depends on this metadata.
+    #[clap(long, conflicts_with = "chunked")]
+    pub meta: bool,
 }
 
 #[derive(Subcommand, Debug, Clone)]
@@ -513,6 +611,72 @@ impl DeleteCommands {
     }
 }
 
+#[derive(Subcommand, Debug, Clone)]
+pub enum QueueCommands {
+    /// List the active, queued and parked downloads.
+    List,
+    /// Change the priority of a download intent.
+    SetPriority {
+        /// The id of the download intent, as shown by `queue list`.
+        intent: u64,
+        /// The new priority. Queued downloads with a higher priority are started first.
+        #[clap(allow_negative_numbers = true)]
+        priority: i32,
+    },
+}
+
+impl QueueCommands {
+    pub async fn run(self, iroh: &Iroh) -> Result<()> {
+        match self {
+            Self::List => {
+                let snapshot = iroh.blobs().download_queue().await?;
+                for (state, downloads) in [
+                    ("active", snapshot.active),
+                    ("queued", snapshot.queued),
+                    ("parked", snapshot.parked),
+                ] {
+                    for download in downloads {
+                        print_download_info(state, download);
+                    }
+                }
+            }
+            Self::SetPriority { intent, priority } => {
+                iroh.blobs()
+                    .set_download_priority(IntentId(intent), priority)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn print_download_info(state: &str, download: DownloadInfo) {
+    let DownloadInfo {
+        hash_and_format,
+        priority,
+        intents,
+        candidates,
+        active_nodes,
+    } = download;
+    let intents = intents
+        .iter()
+        .map(|intent| intent.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    println!(
+        "{state} {} {:?} priority {priority} intents [{intents}]",
+        hash_and_format.hash, hash_and_format.format
+    );
+    for node in candidates {
+        let marker = if active_nodes.contains(&node) {
+            " (active)"
+        } else {
+            ""
+        };
+        println!("  {node}{marker}");
+    }
+}
+
 fn get_report_level(verbose: u8) -> ReportLevel {
     match verbose {
         0 => ReportLevel::Warn,
@@ -571,6 +735,44 @@ pub async fn consistency_check(iroh: &Iroh, verbose: u8, repair: bool) -> Result
     Ok(())
 }
 
+fn print_store_stats(stats: &StoreStats) {
+    let row = |name: &str, stats: &SizeStats| {
+        println!(
+            "{:<10} {:>8} {:>12} {:>12}",
+            name,
+            stats.count,
+            HumanBytes(stats.data_size).to_string(),
+            HumanBytes(stats.outboard_size).to_string(),
+        );
+    };
+    println!(
+        "{:<10} {:>8} {:>12} {:>12}",
+        "entries", "count", "data", "outboard"
+    );
+    row("inline", &stats.entries.inline);
+    row("owned", &stats.entries.owned);
+    row("external", &stats.entries.external);
+    row("partial", &stats.entries.partial);
+    row("complete", &stats.entries.complete());
+    if stats.tags.is_empty() {
+        return;
+    }
+    println!();
+    println!(
+        "{:<32} {:>8} {:>12} {:>8}",
+        "tag", "blobs", "size", "missing"
+    );
+    for tag in &stats.tags {
+        println!(
+            "{:<32} {:>8} {:>12} {:>8}",
+            tag.tag.to_string(),
+            tag.blobs,
+            HumanBytes(tag.size).to_string(),
+            tag.missing,
+        );
+    }
+}
+
 pub async fn validate(iroh: &Iroh, verbose: u8, repair: bool) -> Result<()> {
     let mut state = ValidateProgressState::new();
     let mut response = iroh.blobs().validate(repair).await?;
@@ -819,8 +1021,9 @@ pub async fn add_with_opts(
         (false, None) => WrapOption::NoWrap,
         (false, Some(_)) => bail!("`--filename` may not be used without `--wrap`"),
     };
+    let chunking = opts.chunked.then(ChunkerConfig::default);
 
-    add(client, source, tag, ticket, wrap).await
+    add(client, source, tag, ticket, wrap, chunking, opts.meta).await
 }
 
 /// Add data to iroh, either from a path or, if path is `None`, from STDIN.
@@ -830,6 +1033,8 @@ pub async fn add(
     tag: SetTagOption,
     ticket: TicketOption,
     wrap: WrapOption,
+    chunking: Option<ChunkerConfig>,
+    meta: bool,
 ) -> Result<()> {
     let (hash, format, entries) = match source {
         BlobSourceIroh::LocalFs { path, in_place } => {
@@ -837,9 +1042,16 @@ pub async fn add(
             println!("Adding {} as {}...", path.display(), absolute.display());
 
             // tell the node to add the data
+            let opts = AddPathOptions {
+                in_place,
+                tag,
+                wrap,
+                chunking,
+                meta,
+            };
             let stream = client
                 .blobs()
-                .add_from_path(absolute, in_place, tag, wrap)
+                .add_from_path_with_opts(absolute, opts)
                 .await?;
             aggregate_add_response(stream).await?
         }
@@ -855,9 +1067,16 @@ pub async fn add(
             drop(file);
 
             // tell the node to add the data
+            let opts = AddPathOptions {
+                in_place: false,
+                tag,
+                wrap,
+                chunking,
+                meta,
+            };
             let stream = client
                 .blobs()
-                .add_from_path(path_buf, false, tag, wrap)
+                .add_from_path_with_opts(path_buf, opts)
                 .await?;
             aggregate_add_response(stream).await?
         }
@@ -884,6 +1103,7 @@ pub async fn aggregate_add_response(
 ) -> Result<(Hash, BlobFormat, Vec<ProvideResponseEntry>)> {
     let mut hash_and_format = None;
     let mut collections = BTreeMap::<u64, (String, u64, Option<Hash>)>::new();
+    let mut skipped = Vec::new();
     let mut mp = Some(ProvideProgressState::new());
     while let Some(item) = stream.next().await {
         match item? {
@@ -914,6 +1134,10 @@ pub async fn aggregate_add_response(
                     }
                 }
             }
+            AddProgress::Skipped { name, size, hash } => {
+                tracing::trace!("Skipped({name},{size},{hash:?})");
+                skipped.push(ProvideResponseEntry { name, size, hash });
+            }
             AddProgress::AllDone { hash, format, .. } => {
                 tracing::trace!("AllDone({hash:?})");
                 if let Some(mp) = mp.take() {
@@ -932,13 +1156,14 @@ pub async fn aggregate_add_response(
     }
     let HashAndFormat { hash, format } =
         hash_and_format.context("Missing hash for collection or blob")?;
-    let entries = collections
+    let mut entries = collections
         .into_iter()
         .map(|(_, (name, size, hash))| {
             let hash = hash.context(format!("Missing hash for {name}"))?;
             Ok(ProvideResponseEntry { name, size, hash })
         })
         .collect::<Result<Vec<_>>>()?;
+    entries.extend(skipped);
     Ok((hash, format, entries))
 }
 
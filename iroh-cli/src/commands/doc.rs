@@ -3,7 +3,7 @@ use std::{
     collections::BTreeMap,
     path::{Path, PathBuf},
     rc::Rc,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::{anyhow, bail, Context, Result};
@@ -120,6 +120,41 @@ pub enum DocCommands {
         #[clap(long, default_value_t = AddrInfoOptions::Id)]
         addr_options: AddrInfoOptions,
     },
+    /// Allow an author to write to a document, and share the grant over a ticket.
+    ///
+    /// The ticket gives read access to the document, and write access for the author only.
+    Grant {
+        /// Document to operate on.
+        ///
+        /// Required unless the document is set through the IROH_DOC environment variable.
+        /// Within the Iroh console, the active document can also set with `doc switch`.
+        #[clap(short, long)]
+        doc: Option<NamespaceId>,
+        /// The author that may write to the document.
+        author: AuthorId,
+        /// Only allow keys that start with this prefix.
+        #[clap(long, default_value = "")]
+        prefix: String,
+        /// Only allow entries written within this many seconds from now.
+        #[clap(long)]
+        expires_in: Option<u64>,
+        /// Options to configure the address information in the generated ticket.
+        ///
+        /// Use `relay-and-addresses` in networks with no internet connectivity.
+        #[clap(long, default_value_t = AddrInfoOptions::Id)]
+        addr_options: AddrInfoOptions,
+    },
+    /// Revoke all write grants of an author for a document.
+    Revoke {
+        /// Document to operate on.
+        ///
+        /// Required unless the document is set through the IROH_DOC environment variable.
+        /// Within the Iroh console, the active document can also set with `doc switch`.
+        #[clap(short, long)]
+        doc: Option<NamespaceId>,
+        /// The author whose grants are revoked.
+        author: AuthorId,
+    },
     /// Set an entry in a document.
     Set {
         /// Document to operate on.
@@ -291,12 +326,15 @@ pub enum Sorting {
     Author,
     /// Sort by key, then author
     Key,
+    /// Sort by timestamp, then author, then key
+    Timestamp,
 }
 impl From<Sorting> for iroh::docs::store::SortBy {
     fn from(value: Sorting) -> Self {
         match value {
             Sorting::Author => Self::AuthorKey,
             Sorting::Key => Self::KeyAuthor,
+            Sorting::Timestamp => Self::Timestamp,
         }
     }
 }
@@ -349,6 +387,29 @@ impl DocCommands {
                 let ticket = doc.share(mode, addr_options).await?;
                 println!("{}", ticket);
             }
+            Self::Grant {
+                doc,
+                author,
+                prefix,
+                expires_in,
+                addr_options,
+            } => {
+                let doc = get_doc(iroh, env, doc).await?;
+                let expires = expires_in.map(|secs| {
+                    let expires = SystemTime::now() + Duration::from_secs(secs);
+                    let expires = expires.duration_since(SystemTime::UNIX_EPOCH);
+                    expires.expect("time drift").as_micros() as u64
+                });
+                let ticket = doc
+                    .share_delegated(author, prefix.into_bytes(), expires, addr_options)
+                    .await?;
+                println!("{}", ticket);
+            }
+            Self::Revoke { doc, author } => {
+                let doc = get_doc(iroh, env, doc).await?;
+                let removed = doc.revoke_write(author).await?;
+                println!("Revoked {removed} grants of author {}.", fmt_short(author));
+            }
             Self::Set {
                 doc,
                 author,
@@ -841,6 +902,25 @@ async fn import_coordinator(
                         }
                     }
                 }
+                AddProgress::Skipped { name, size, hash } => {
+                    tracing::info!("Skipped({name},{size},{hash:?})");
+                    imp.add_found(name.clone(), size);
+                    imp.add_progress(size);
+                    imp.import_found(name.clone());
+                    let key = match path_to_key(
+                        PathBuf::from(&name),
+                        Some(prefix.clone()),
+                        Some(root.clone()),
+                    ) {
+                        Ok(k) => k.to_vec(),
+                        Err(e) => {
+                            return Some(Err(anyhow::anyhow!(
+                                "Issue creating a key for entry {hash:?}: {e}"
+                            )));
+                        }
+                    };
+                    Some(Ok((key, hash, size)))
+                }
                 AddProgress::AllDone { hash, .. } => {
                     imp.add_done();
                     tracing::info!("AddProgress::AllDone({hash:?})");
@@ -949,7 +1029,8 @@ mod tests {
 
         let data_dir = tempfile::tempdir()?;
 
-        let node = crate::commands::start::start_node(data_dir.path(), None, None).await?;
+        let node =
+            crate::commands::start::start_node(data_dir.path(), None, None, None, false).await?;
         let client = node.client();
         let doc = client.docs().create().await.context("doc create")?;
         let author = client.authors().create().await.context("author create")?;
@@ -1049,6 +1049,17 @@ fn inspect_ticket(ticket: &str, zbase32: bool) -> anyhow::Result<()> {
             Capability::Write(secret) => {
                 println!("  write: {}", bold(secret));
             }
+            Capability::Delegated(grant) => {
+                println!("  read: {}", bold(grant.namespace()));
+                println!("  write as author: {}", bold(grant.author()));
+                println!(
+                    "  key prefix: {}",
+                    bold(String::from_utf8_lossy(grant.prefix()))
+                );
+                if let Some(expires) = grant.expires() {
+                    println!("  expires: {}", bold(expires));
+                }
+            }
         }
         for node in &ticket.nodes {
             print_node_addr("    ", node, zbase32);
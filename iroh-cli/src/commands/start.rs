@@ -76,7 +76,14 @@ where
     let relay_map = config.relay_map()?;
 
     let spinner = create_spinner("Iroh booting...");
-    let node = start_node(iroh_data_root, rpc_addr, relay_map).await?;
+    let node = start_node(
+        iroh_data_root,
+        rpc_addr,
+        relay_map,
+        config.gateway_addr,
+        config.gateway_fetch_tickets,
+    )
+    .await?;
     drop(spinner);
 
     eprintln!("{}", welcome_message(&node)?);
@@ -119,6 +126,8 @@ pub(crate) async fn start_node(
     iroh_data_root: &Path,
     rpc_addr: Option<SocketAddr>,
     relay_map: Option<RelayMap>,
+    gateway_addr: Option<SocketAddr>,
+    gateway_fetch_tickets: bool,
 ) -> Result<Node<iroh::blobs::store::fs::Store>> {
     let rpc_status = RpcStatus::load(iroh_data_root).await?;
     match rpc_status {
@@ -136,21 +145,29 @@ pub(crate) async fn start_node(
     };
 
     let rpc_addr = rpc_addr.unwrap_or(DEFAULT_RPC_ADDR);
-    Node::persistent(iroh_data_root)
+    let builder = Node::persistent(iroh_data_root)
         .await?
         .relay_mode(relay_mode)
         .enable_rpc_with_addr(rpc_addr)
-        .await?
-        .spawn()
-        .await
+        .await?;
+    let builder = match gateway_addr {
+        Some(addr) => builder
+            .http_gateway(addr)
+            .http_gateway_fetch_tickets(gateway_fetch_tickets),
+        None => builder,
+    };
+    builder.spawn().await
 }
 
 fn welcome_message<B: iroh::blobs::store::Store>(node: &Node<B>) -> Result<String> {
-    let msg = format!(
+    let mut msg = format!(
         "{}\nNode ID: {}\n",
         "Iroh is running".green(),
         node.node_id()
     );
+    if let Some(addr) = node.gateway_addr() {
+        msg.push_str(&format!("HTTP gateway: http://{addr}\n"));
+    }
 
     Ok(msg)
 }
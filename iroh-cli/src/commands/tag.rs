@@ -1,42 +1,157 @@
-use anyhow::Result;
+use anyhow::{ensure, Result};
 use bytes::Bytes;
 use clap::Subcommand;
 use futures_lite::StreamExt;
-use iroh::blobs::Tag;
+use iroh::blobs::{util::TagUpdate, BlobFormat, Hash, HashAndFormat, Tag};
 use iroh::client::Iroh;
 
 #[derive(Subcommand, Debug, Clone)]
 #[allow(clippy::large_enum_variant)]
 pub enum TagCommands {
     /// List all tags
-    List,
+    List {
+        /// Only list tags that start with this prefix
+        #[clap(long)]
+        prefix: Option<String>,
+        #[clap(long, default_value_t = false)]
+        hex: bool,
+    },
     /// Delete a tag
     Delete {
         tag: String,
         #[clap(long, default_value_t = false)]
         hex: bool,
+        /// Delete all tags that start with the given tag
+        #[clap(long, default_value_t = false)]
+        prefix: bool,
+    },
+    /// Set a tag to a hash
+    Set {
+        tag: String,
+        hash: Hash,
+        /// Set to true if the hash refers to a collection or other hash sequence.
+        #[clap(long, default_value_t = false)]
+        recursive: bool,
+        #[clap(long, default_value_t = false)]
+        hex: bool,
+        /// Only set the tag if it currently refers to this hash
+        #[clap(long, conflicts_with = "expect_missing")]
+        expect: Option<Hash>,
+        /// Only set the tag if it does not exist yet
+        #[clap(long, default_value_t = false)]
+        expect_missing: bool,
+    },
+    /// Atomically swap the values of two tags
+    Swap {
+        a: String,
+        b: String,
+        #[clap(long, default_value_t = false)]
+        hex: bool,
     },
 }
 
 impl TagCommands {
     pub async fn run(self, iroh: &Iroh) -> Result<()> {
         match self {
-            Self::List => {
-                let mut response = iroh.tags().list().await?;
+            Self::List { prefix, hex } => {
+                let mut response = match prefix {
+                    Some(prefix) => iroh
+                        .tags()
+                        .list_prefix(parse_tag(prefix, hex)?)
+                        .await?
+                        .boxed(),
+                    None => iroh.tags().list().await?.boxed(),
+                };
                 while let Some(res) = response.next().await {
                     let res = res?;
                     println!("{}: {} ({:?})", res.name, res.hash, res.format);
                 }
             }
-            Self::Delete { tag, hex } => {
-                let tag = if hex {
-                    Tag::from(Bytes::from(hex::decode(tag)?))
+            Self::Delete { tag, hex, prefix } => {
+                let tag = parse_tag(tag, hex)?;
+                if prefix {
+                    let count = iroh.tags().delete_prefix(tag).await?;
+                    println!("Deleted {count} tags");
                 } else {
-                    Tag::from(tag)
+                    iroh.tags().delete(tag).await?;
+                }
+            }
+            Self::Set {
+                tag,
+                hash,
+                recursive,
+                hex,
+                expect,
+                expect_missing,
+            } => {
+                let tag = parse_tag(tag, hex)?;
+                let format = match recursive {
+                    true => BlobFormat::HashSeq,
+                    false => BlobFormat::Raw,
                 };
-                iroh.tags().delete(tag).await?;
+                let mut update = TagUpdate::set(tag.clone(), HashAndFormat { hash, format });
+                if expect_missing {
+                    update = update.if_equals(None);
+                } else if let Some(expected) = expect {
+                    let current = get_tag(iroh, &tag).await?;
+                    ensure!(
+                        current.map(|x| x.hash) == Some(expected),
+                        "tag {tag} does not refer to {expected}"
+                    );
+                    update = update.if_equals(current);
+                }
+                let applied = iroh.tags().update(vec![update]).await?;
+                ensure!(applied, "tag {tag} was changed concurrently");
+            }
+            Self::Swap { a, b, hex } => {
+                let a = parse_tag(a, hex)?;
+                let b = parse_tag(b, hex)?;
+                let value_a = get_tag(iroh, &a).await?;
+                let value_b = get_tag(iroh, &b).await?;
+                let updates = vec![
+                    swap_update(a.clone(), value_a, value_b),
+                    swap_update(b.clone(), value_b, value_a),
+                ];
+                let applied = iroh.tags().update(updates).await?;
+                ensure!(applied, "tags {a} and {b} were changed concurrently");
             }
         }
         Ok(())
     }
 }
+
+fn parse_tag(tag: String, hex: bool) -> Result<Tag> {
+    Ok(if hex {
+        Tag::from(Bytes::from(hex::decode(tag)?))
+    } else {
+        Tag::from(tag)
+    })
+}
+
+/// Get the current value of a tag.
+async fn get_tag(iroh: &Iroh, tag: &Tag) -> Result<Option<HashAndFormat>> {
+    let mut tags = iroh.tags().list_prefix(tag.clone()).await?;
+    while let Some(info) = tags.next().await {
+        let info = info?;
+        if &info.name == tag {
+            return Ok(Some(HashAndFormat {
+                hash: info.hash,
+                format: info.format,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// Change `tag` from `current` to `value`, failing if it was changed in the meantime.
+fn swap_update(
+    tag: Tag,
+    current: Option<HashAndFormat>,
+    value: Option<HashAndFormat>,
+) -> TagUpdate {
+    let update = match value {
+        Some(value) => TagUpdate::set(tag, value),
+        None => TagUpdate::delete(tag),
+    };
+    update.if_equals(current)
+}
@@ -57,6 +57,10 @@ pub(crate) struct NodeConfig {
     pub(crate) gc_policy: GcPolicy,
     /// Bind address on which to serve Prometheus metrics
     pub(crate) metrics_addr: Option<SocketAddr>,
+    /// Bind address on which to serve blobs and collections over HTTP
+    pub(crate) gateway_addr: Option<SocketAddr>,
+    /// Whether the HTTP gateway may fetch content with blob tickets
+    pub(crate) gateway_fetch_tickets: bool,
     pub(crate) file_logs: super::logging::FileLogging,
 }
 
@@ -82,6 +86,8 @@ impl Default for NodeConfig {
             relay_nodes: relay_nodes.into(),
             gc_policy: GcPolicy::Disabled,
             metrics_addr: Some(([127, 0, 0, 1], 9090).into()),
+            gateway_addr: None,
+            gateway_fetch_tickets: false,
             file_logs: Default::default(),
         }
     }
@@ -24,11 +24,15 @@ use crate::{
     },
     Author, AuthorHeads, AuthorId, Capability, CapabilityKind, ContentStatus,
     ContentStatusCallback, Event, NamespaceId, NamespaceSecret, PeerIdBytes, Replica, ReplicaInfo,
-    SignedEntry, SyncOutcome,
+    SignedEntry, SyncOutcome, WriteGrant,
 };
 
 const ACTION_CAP: usize = 1024;
 pub(crate) const MAX_COMMIT_DELAY: Duration = Duration::from_millis(500);
+/// Interval in which expired entries are removed from the store.
+const EXPIRY_GC_INTERVAL: Duration = Duration::from_secs(60);
+/// Interval in which all documents are compacted, if compaction is enabled.
+const COMPACTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
 #[derive(derive_more::Debug, derive_more::Display)]
 enum Action {
@@ -120,6 +124,7 @@ enum ReplicaAction {
         key: Bytes,
         hash: Hash,
         len: u64,
+        expires: Option<u64>,
         #[debug("reply")]
         reply: oneshot::Sender<Result<()>>,
     },
@@ -129,6 +134,18 @@ enum ReplicaAction {
         #[debug("reply")]
         reply: oneshot::Sender<Result<usize>>,
     },
+    GrantWrite {
+        author: AuthorId,
+        prefix: Bytes,
+        expires: Option<u64>,
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<WriteGrant>>,
+    },
+    RevokeWrite {
+        author: AuthorId,
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<usize>>,
+    },
     InsertRemote {
         entry: SignedEntry,
         from: PeerIdBytes,
@@ -162,6 +179,10 @@ enum ReplicaAction {
         include_empty: bool,
         reply: oneshot::Sender<Result<Option<SignedEntry>>>,
     },
+    GetHeads {
+        key: Bytes,
+        reply: oneshot::Sender<Result<Vec<SignedEntry>>>,
+    },
     GetMany {
         query: Query,
         reply: flume::Sender<Result<SignedEntry>>,
@@ -248,9 +269,13 @@ impl OpenOpts {
 #[allow(missing_docs)]
 impl SyncHandle {
     /// Spawn a sync actor and return a handle.
+    ///
+    /// If `compaction` is set, all documents are periodically compacted with it as maximum age,
+    /// see [`Store::compact`].
     pub fn spawn(
         store: Store,
         content_status_callback: Option<ContentStatusCallback>,
+        compaction: Option<Duration>,
         me: String,
     ) -> SyncHandle {
         let (action_tx, action_rx) = flume::bounded(ACTION_CAP);
@@ -259,6 +284,7 @@ impl SyncHandle {
             states: Default::default(),
             action_rx,
             content_status_callback,
+            compaction,
             tasks: Default::default(),
         };
         let join_handle = std::thread::Builder::new()
@@ -329,6 +355,7 @@ impl SyncHandle {
         key: Bytes,
         hash: Hash,
         len: u64,
+        expires: Option<u64>,
     ) -> Result<()> {
         let (reply, rx) = oneshot::channel();
         let action = ReplicaAction::InsertLocal {
@@ -336,6 +363,7 @@ impl SyncHandle {
             key,
             hash,
             len,
+            expires,
             reply,
         };
         self.send_replica(namespace, action).await?;
@@ -354,6 +382,31 @@ impl SyncHandle {
         rx.await?
     }
 
+    pub async fn grant_write(
+        &self,
+        namespace: NamespaceId,
+        author: AuthorId,
+        prefix: Bytes,
+        expires: Option<u64>,
+    ) -> Result<WriteGrant> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::GrantWrite {
+            author,
+            prefix,
+            expires,
+            reply,
+        };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
+    pub async fn revoke_write(&self, namespace: NamespaceId, author: AuthorId) -> Result<usize> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::RevokeWrite { author, reply };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
     pub async fn insert_remote(
         &self,
         namespace: NamespaceId,
@@ -458,6 +511,13 @@ impl SyncHandle {
         rx.await?
     }
 
+    pub async fn get_heads(&self, namespace: NamespaceId, key: Bytes) -> Result<Vec<SignedEntry>> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::GetHeads { key, reply };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
     pub async fn drop_replica(&self, namespace: NamespaceId) -> Result<()> {
         let (reply, rx) = oneshot::channel();
         let action = ReplicaAction::DropReplica { reply };
@@ -593,6 +653,7 @@ struct Actor {
     states: OpenReplicas,
     action_rx: flume::Receiver<Action>,
     content_status_callback: Option<ContentStatusCallback>,
+    compaction: Option<Duration>,
     tasks: JoinSet<()>,
 }
 
@@ -607,6 +668,10 @@ impl Actor {
     }
 
     async fn run_async(mut self) {
+        let mut expiry_gc = tokio::time::interval(EXPIRY_GC_INTERVAL);
+        expiry_gc.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut compaction = tokio::time::interval(COMPACTION_INTERVAL);
+        compaction.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
         let reply = loop {
             let timeout = tokio::time::sleep(MAX_COMMIT_DELAY);
             tokio::pin!(timeout);
@@ -617,6 +682,19 @@ impl Actor {
                     }
                     continue;
                 }
+                _ = expiry_gc.tick() => {
+                    match self.store.remove_expired() {
+                        Ok(removed) => trace!(removed, "removed expired entries"),
+                        Err(cause) => error!(?cause, "failed to remove expired entries"),
+                    }
+                    continue;
+                }
+                _ = compaction.tick(), if self.compaction.is_some() => {
+                    if let Some(max_age) = self.compaction {
+                        self.compact_all(max_age);
+                    }
+                    continue;
+                }
                 action = self.action_rx.recv_async() => {
                     match action {
                         Ok(action) => action,
@@ -652,6 +730,29 @@ impl Actor {
         }
     }
 
+    /// Compact all documents in the store, see [`Store::compact`].
+    fn compact_all(&mut self, max_age: Duration) {
+        let namespaces = match self.store.list_namespaces() {
+            Ok(namespaces) => namespaces.collect::<Result<Vec<_>>>(),
+            Err(cause) => Err(cause),
+        };
+        let namespaces = match namespaces {
+            Ok(namespaces) => namespaces,
+            Err(cause) => {
+                error!(?cause, "failed to list documents for compaction");
+                return;
+            }
+        };
+        for (namespace, _kind) in namespaces {
+            match self.store.compact(&namespace, max_age) {
+                Ok(outcome) => trace!(namespace = %namespace.fmt_short(), ?outcome, "compacted"),
+                Err(cause) => {
+                    error!(namespace = %namespace.fmt_short(), ?cause, "failed to compact")
+                }
+            }
+        }
+    }
+
     fn on_action(&mut self, action: Action) -> Result<(), SendReplyError> {
         match action {
             Action::Shutdown { .. } => {
@@ -739,11 +840,12 @@ impl Actor {
                 key,
                 hash,
                 len,
+                expires,
                 reply,
             } => send_reply_with(reply, self, move |this| {
                 let author = get_author(&mut this.store, &author)?;
                 let mut replica = this.states.replica(namespace, &mut this.store)?;
-                replica.insert(&key, &author, hash, len)?;
+                replica.insert_with_expiry(&key, &author, hash, len, expires)?;
                 Ok(())
             }),
             ReplicaAction::DeletePrefix { author, key, reply } => {
@@ -754,6 +856,23 @@ impl Actor {
                     Ok(res)
                 })
             }
+            ReplicaAction::GrantWrite {
+                author,
+                prefix,
+                expires,
+                reply,
+            } => send_reply_with(reply, self, move |this| {
+                let mut replica = this.states.replica(namespace, &mut this.store)?;
+                let grant = replica.grant_write(author, &prefix, expires)?;
+                Ok(grant)
+            }),
+            ReplicaAction::RevokeWrite { author, reply } => {
+                send_reply_with(reply, self, move |this| {
+                    let mut replica = this.states.replica(namespace, &mut this.store)?;
+                    let res = replica.revoke_write(author)?;
+                    Ok(res)
+                })
+            }
             ReplicaAction::InsertRemote {
                 entry,
                 from,
@@ -806,6 +925,10 @@ impl Actor {
                 this.states.ensure_open(&namespace)?;
                 this.store.get_exact(namespace, author, key, include_empty)
             }),
+            ReplicaAction::GetHeads { key, reply } => send_reply_with(reply, self, move |this| {
+                this.states.ensure_open(&namespace)?;
+                this.store.get_heads(namespace, key)
+            }),
             ReplicaAction::GetMany { query, reply } => {
                 let iter = self
                     .states
@@ -1024,7 +1147,7 @@ mod tests {
     #[tokio::test]
     async fn open_close() -> anyhow::Result<()> {
         let store = store::Store::memory();
-        let sync = SyncHandle::spawn(store, None, "foo".into());
+        let sync = SyncHandle::spawn(store, None, None, "foo".into());
         let namespace = NamespaceSecret::new(&mut rand::rngs::OsRng {});
         let id = namespace.id();
         sync.import_namespace(namespace.into()).await?;
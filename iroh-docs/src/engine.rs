@@ -7,6 +7,7 @@ use std::{
     io,
     str::FromStr,
     sync::{Arc, RwLock},
+    time::Duration,
 };
 
 use anyhow::{bail, Context, Result};
@@ -18,6 +19,7 @@ use iroh_net::util::SharedAbortingJoinHandle;
 use iroh_net::{key::PublicKey, Endpoint, NodeAddr};
 use serde::{Deserialize, Serialize};
 use tokio::sync::{mpsc, oneshot};
+use tokio_util::task::LocalPoolHandle;
 use tracing::{error, error_span, Instrument};
 
 use crate::{actor::SyncHandle, ContentStatus, ContentStatusCallback, Entry, NamespaceId};
@@ -27,10 +29,12 @@ use self::gossip::GossipActor;
 use self::live::{LiveActor, ToLiveActor};
 
 pub use self::live::SyncEvent;
+pub use self::merge::{MergeHandler, MergeHead};
 pub use self::state::{Origin, SyncReason};
 
 mod gossip;
 mod live;
+mod merge;
 mod state;
 
 /// Capacity of the channel for the [`ToLiveActor`] messages.
@@ -60,13 +64,21 @@ impl Engine {
     ///
     /// This will spawn two tokio tasks for the live sync coordination and gossip actors, and a
     /// thread for the [`crate::actor::SyncHandle`].
+    ///
+    /// If `compaction` is set, all documents are periodically compacted with it as maximum age,
+    /// see [`crate::store::fs::Store::compact`].
+    ///
+    /// The `local_pool` is used to read the content of entries for a [`MergeHandler`].
+    #[allow(clippy::too_many_arguments)]
     pub async fn spawn<B: iroh_blobs::store::Store>(
         endpoint: Endpoint,
         gossip: Gossip,
         replica_store: crate::store::Store,
         bao_store: B,
         downloader: Downloader,
+        local_pool: LocalPoolHandle,
         default_author_storage: DefaultAuthorStorage,
+        compaction: Option<Duration>,
     ) -> anyhow::Result<Self> {
         let (live_actor_tx, to_live_actor_recv) = mpsc::channel(ACTOR_CHANNEL_CAP);
         let (to_gossip_actor, to_gossip_actor_recv) = mpsc::channel(ACTOR_CHANNEL_CAP);
@@ -76,7 +88,12 @@ impl Engine {
             let bao_store = bao_store.clone();
             Arc::new(move |hash| entry_to_content_status(bao_store.entry_status_sync(&hash)))
         };
-        let sync = SyncHandle::spawn(replica_store, Some(content_status_cb.clone()), me.clone());
+        let sync = SyncHandle::spawn(
+            replica_store,
+            Some(content_status_cb.clone()),
+            compaction,
+            me.clone(),
+        );
 
         let actor = LiveActor::new(
             sync.clone(),
@@ -84,6 +101,7 @@ impl Engine {
             gossip.clone(),
             bao_store,
             downloader,
+            local_pool,
             to_live_actor_recv,
             live_actor_tx.clone(),
             to_gossip_actor,
@@ -157,6 +175,37 @@ impl Engine {
         Ok(())
     }
 
+    /// Set the [`MergeHandler`] for a document.
+    ///
+    /// The handler is called when an entry from a remote peer was inserted and the heads of its
+    /// key have more than one distinct value. The merged value is written by `author`, which must
+    /// be present in the store. Replaces the previous handler, if any.
+    pub async fn set_merge_handler(
+        &self,
+        namespace: NamespaceId,
+        author: AuthorId,
+        handler: Arc<dyn MergeHandler>,
+    ) -> Result<()> {
+        self.to_live_actor
+            .send(ToLiveActor::SetMergeHandler {
+                namespace,
+                handler: Some((author, handler)),
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Remove the [`MergeHandler`] of a document.
+    pub async fn remove_merge_handler(&self, namespace: NamespaceId) -> Result<()> {
+        self.to_live_actor
+            .send(ToLiveActor::SetMergeHandler {
+                namespace,
+                handler: None,
+            })
+            .await?;
+        Ok(())
+    }
+
     /// Subscribe to replica and sync progress events.
     pub async fn subscribe(
         &self,
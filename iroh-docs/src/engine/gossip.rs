@@ -159,7 +159,7 @@ impl GossipActor {
     async fn on_gossip_event_inner(&mut self, namespace: NamespaceId, event: Event) -> Result<()> {
         match event {
             Event::Received(msg) => {
-                let op: Op = postcard::from_bytes(&msg.content)?;
+                let op = Op::decode(&msg.content)?;
                 match op {
                     Op::Put(entry) => {
                         debug!(peer = %msg.delivered_from.fmt_short(), namespace = %namespace.fmt_short(), "received entry via gossip");
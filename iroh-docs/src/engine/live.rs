@@ -1,15 +1,19 @@
 #![allow(missing_docs)]
 
 use std::collections::HashSet;
+use std::sync::Arc;
 use std::{collections::HashMap, time::SystemTime};
 
-use anyhow::{Context, Result};
+use anyhow::{ensure, Context, Result};
+use bytes::Bytes;
 use futures_lite::FutureExt;
 use iroh_blobs::downloader::{DownloadError, DownloadRequest, Downloader};
 use iroh_blobs::get::Stats;
-use iroh_blobs::HashAndFormat;
+use iroh_blobs::store::MapEntry;
 use iroh_blobs::{store::EntryStatus, Hash};
+use iroh_blobs::{BlobFormat, HashAndFormat};
 use iroh_gossip::{net::Gossip, proto::TopicId};
+use iroh_io::AsyncSliceReader;
 use iroh_net::NodeId;
 use iroh_net::{key::PublicKey, Endpoint, NodeAddr};
 use serde::{Deserialize, Serialize};
@@ -17,6 +21,7 @@ use tokio::{
     sync::{self, mpsc, oneshot},
     task::JoinSet,
 };
+use tokio_util::task::LocalPoolHandle;
 use tracing::{debug, error, error_span, info, instrument, trace, warn, Instrument, Span};
 
 use crate::{
@@ -25,15 +30,22 @@ use crate::{
         connect_and_sync, handle_connection, AbortReason, AcceptError, AcceptOutcome, ConnectError,
         SyncFinished,
     },
-    AuthorHeads, ContentStatus, NamespaceId, SignedEntry,
+    sync::{ExpiryEnvelope, WireEntry},
+    AuthorHeads, AuthorId, ContentStatus, NamespaceId, SignedEntry,
 };
 
 use super::gossip::{GossipActor, ToGossipActor};
+use super::merge::{MergeHandler, MergeHead};
 use super::state::{NamespaceStates, Origin, SyncReason};
 
 /// Name used for logging when new node addresses are added from the docs engine.
 const SOURCE_NAME: &str = "docs_engine";
 
+/// Maximum size of the content of an entry that is read into memory for a [`MergeHandler`].
+///
+/// Keys with larger values are not merged.
+const MAX_MERGE_CONTENT_SIZE: u64 = 16 * 1024 * 1024;
+
 /// An iroh-docs operation
 ///
 /// This is the message that is broadcast over iroh-gossip.
@@ -47,6 +59,55 @@ pub enum Op {
     SyncReport(SyncReport),
 }
 
+impl Op {
+    /// Serialize this operation for broadcasting, including the expiry of a put entry.
+    pub(super) fn encode(&self) -> postcard::Result<Vec<u8>> {
+        let op = WireOp::from(self.clone());
+        let entry = match &op {
+            WireOp::Put(entry) => Some(&entry.0),
+            _ => None,
+        };
+        ExpiryEnvelope::encode(&op, entry, Vec::new())
+    }
+
+    /// Deserialize an operation serialized with [`Self::encode`].
+    pub(super) fn decode(bytes: &[u8]) -> postcard::Result<Self> {
+        let (mut op, envelope) = ExpiryEnvelope::decode::<WireOp>(bytes)?;
+        if let (Some(envelope), WireOp::Put(entry)) = (envelope, &mut op) {
+            envelope.apply(Some(&mut entry.0));
+        }
+        Ok(op.into())
+    }
+}
+
+/// An [`Op`] as broadcast over iroh-gossip, with the entry of a put as [`WireEntry`].
+#[derive(Debug, Serialize, Deserialize)]
+enum WireOp {
+    Put(WireEntry),
+    ContentReady(Hash),
+    SyncReport(SyncReport),
+}
+
+impl From<Op> for WireOp {
+    fn from(op: Op) -> Self {
+        match op {
+            Op::Put(entry) => WireOp::Put(WireEntry(entry)),
+            Op::ContentReady(hash) => WireOp::ContentReady(hash),
+            Op::SyncReport(report) => WireOp::SyncReport(report),
+        }
+    }
+}
+
+impl From<WireOp> for Op {
+    fn from(op: WireOp) -> Self {
+        match op {
+            WireOp::Put(entry) => Op::Put(entry.0),
+            WireOp::ContentReady(hash) => Op::ContentReady(hash),
+            WireOp::SyncReport(report) => Op::SyncReport(report),
+        }
+    }
+}
+
 /// Report of a successful sync with the new heads.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SyncReport {
@@ -107,6 +168,11 @@ pub enum ToLiveActor {
         namespace: NamespaceId,
         peer: PublicKey,
     },
+    SetMergeHandler {
+        namespace: NamespaceId,
+        #[debug("MergeHandler")]
+        handler: Option<(AuthorId, Arc<dyn MergeHandler>)>,
+    },
 }
 
 /// Events informing about actions of the live sync progress.
@@ -141,6 +207,7 @@ type SyncConnectRes = (
 );
 type SyncAcceptRes = Result<SyncFinished, AcceptError>;
 type DownloadRes = (NamespaceId, Hash, Result<Stats, DownloadError>);
+type MergeRes = (NamespaceId, Bytes, Result<Option<Hash>>);
 
 // Currently peers might double-sync in both directions.
 pub struct LiveActor<B: iroh_blobs::store::Store> {
@@ -151,6 +218,7 @@ pub struct LiveActor<B: iroh_blobs::store::Store> {
     gossip: Gossip,
     bao_store: B,
     downloader: Downloader,
+    local_pool: LocalPoolHandle,
     replica_events_tx: flume::Sender<crate::Event>,
     replica_events_rx: flume::Receiver<crate::Event>,
 
@@ -166,10 +234,16 @@ pub struct LiveActor<B: iroh_blobs::store::Store> {
     running_sync_accept: JoinSet<SyncAcceptRes>,
     /// Running download futures.
     download_tasks: JoinSet<DownloadRes>,
+    /// Running merge futures.
+    merge_tasks: JoinSet<MergeRes>,
     /// Content hashes which are wanted but not yet queued because no provider was found.
     missing_hashes: HashSet<Hash>,
     /// Content hashes queued in downloader.
     queued_hashes: QueuedHashes,
+    /// Merge handlers and the authors of merged entries per document.
+    merge_handlers: HashMap<NamespaceId, (AuthorId, Arc<dyn MergeHandler>)>,
+    /// Keys whose merge waits for the download of a content hash.
+    pending_merges: HashMap<Hash, HashSet<(NamespaceId, Bytes)>>,
 
     /// Subscribers to actor events
     subscribers: SubscribersMap,
@@ -186,6 +260,7 @@ impl<B: iroh_blobs::store::Store> LiveActor<B> {
         gossip: Gossip,
         bao_store: B,
         downloader: Downloader,
+        local_pool: LocalPoolHandle,
         inbox: mpsc::Receiver<ToLiveActor>,
         sync_actor_tx: mpsc::Sender<ToLiveActor>,
         gossip_actor_tx: mpsc::Sender<ToGossipActor>,
@@ -200,15 +275,19 @@ impl<B: iroh_blobs::store::Store> LiveActor<B> {
             gossip,
             bao_store,
             downloader,
+            local_pool,
             sync_actor_tx,
             gossip_actor_tx,
             running_sync_connect: Default::default(),
             running_sync_accept: Default::default(),
             subscribers: Default::default(),
             download_tasks: Default::default(),
+            merge_tasks: Default::default(),
             state: Default::default(),
             missing_hashes: Default::default(),
             queued_hashes: Default::default(),
+            merge_handlers: Default::default(),
+            pending_merges: Default::default(),
         }
     }
 
@@ -282,6 +361,11 @@ impl<B: iroh_blobs::store::Store> LiveActor<B> {
                     self.on_download_ready(namespace, hash, res).await;
 
                 }
+                Some(res) = self.merge_tasks.join_next(), if !self.merge_tasks.is_empty() => {
+                    trace!(?i, "tick: merge_tasks");
+                    let (namespace, key, res) = res.context("merge_tasks closed")?;
+                    self.on_merge_finished(namespace, key, res).await;
+                }
             }
         }
     }
@@ -349,6 +433,14 @@ impl<B: iroh_blobs::store::Store> LiveActor<B> {
             } => {
                 self.on_neighbor_content_ready(namespace, node, hash).await;
             }
+            ToLiveActor::SetMergeHandler { namespace, handler } => match handler {
+                Some(handler) => {
+                    self.merge_handlers.insert(namespace, handler);
+                }
+                None => {
+                    self.merge_handlers.remove(&namespace);
+                }
+            },
         };
         Ok(true)
     }
@@ -615,7 +707,7 @@ impl<B: iroh_blobs::store::Store> LiveActor<B> {
             return;
         }
 
-        let msg = match postcard::to_stdvec(op) {
+        let msg = match op.encode() {
             Ok(msg) => msg,
             Err(err) => {
                 error!(?err, ?op, "Failed to serialize message:");
@@ -652,6 +744,10 @@ impl<B: iroh_blobs::store::Store> LiveActor<B> {
             // Inform our neighbors that we have new content ready.
             self.broadcast_neighbors(namespace, &Op::ContentReady(hash))
                 .await;
+            // Run the merges that waited for this content.
+            for (namespace, key) in self.pending_merges.remove(&hash).unwrap_or_default() {
+                self.start_merge(namespace, key);
+            }
         } else {
             self.missing_hashes.insert(hash);
         }
@@ -708,7 +804,7 @@ impl<B: iroh_blobs::store::Store> LiveActor<B> {
                 // A new entry was inserted locally. Broadcast a gossip message.
                 if self.state.is_syncing(&namespace) {
                     let op = Op::Put(entry.clone());
-                    let message = postcard::to_stdvec(&op)?.into();
+                    let message = op.encode()?.into();
                     self.gossip.broadcast(topic, message).await?;
                 }
             }
@@ -731,12 +827,68 @@ impl<B: iroh_blobs::store::Store> LiveActor<B> {
                         self.missing_hashes.insert(hash);
                     }
                 }
+                let key = Bytes::copy_from_slice(entry.key());
+                self.start_merge(namespace, key);
             }
         }
 
         Ok(())
     }
 
+    /// Merge the heads of `key` with the [`MergeHandler`] of the document, if any.
+    ///
+    /// The merge runs in a task, so that reading the content and running the handler does not
+    /// block the actor.
+    fn start_merge(&mut self, namespace: NamespaceId, key: Bytes) {
+        let Some((author, handler)) = self.merge_handlers.get(&namespace).cloned() else {
+            return;
+        };
+        let sync = self.sync.clone();
+        let bao_store = self.bao_store.clone();
+        let local_pool = self.local_pool.clone();
+        let fut = async move {
+            let res = merge_key(
+                &sync,
+                &bao_store,
+                &local_pool,
+                namespace,
+                key.clone(),
+                author,
+                handler,
+            )
+            .await;
+            (namespace, key, res)
+        };
+        self.merge_tasks.spawn(fut.instrument(Span::current()));
+    }
+
+    async fn on_merge_finished(
+        &mut self,
+        namespace: NamespaceId,
+        key: Bytes,
+        res: Result<Option<Hash>>,
+    ) {
+        match res {
+            Ok(None) => {}
+            Ok(Some(hash)) => {
+                // The download may have finished while the merge was running.
+                let entry_status = self.bao_store.entry_status(&hash).await;
+                if matches!(entry_status, Ok(EntryStatus::Complete)) {
+                    self.start_merge(namespace, key);
+                } else {
+                    debug!(namespace=%namespace.fmt_short(), hash=%hash.fmt_short(), "postpone merge until content is ready");
+                    self.pending_merges
+                        .entry(hash)
+                        .or_default()
+                        .insert((namespace, key));
+                }
+            }
+            Err(err) => {
+                warn!(namespace=%namespace.fmt_short(), ?err, "failed to merge key");
+            }
+        }
+    }
+
     async fn start_download(
         &mut self,
         namespace: NamespaceId,
@@ -869,6 +1021,95 @@ impl SubscribersMap {
     }
 }
 
+/// Merge the heads of `key` with `handler` and write the merged value as `author`.
+///
+/// Returns the hash of a content that is not available locally yet, in which case the merge has
+/// to be retried once it was downloaded.
+async fn merge_key<B: iroh_blobs::store::Store>(
+    sync: &SyncHandle,
+    bao_store: &B,
+    local_pool: &LocalPoolHandle,
+    namespace: NamespaceId,
+    key: Bytes,
+    author: AuthorId,
+    handler: Arc<dyn MergeHandler>,
+) -> Result<Option<Hash>> {
+    let heads = sync.get_heads(namespace, key.clone()).await?;
+    let values = heads
+        .iter()
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| entry.content_hash())
+        .collect::<HashSet<_>>();
+    if values.len() < 2 {
+        return Ok(None);
+    }
+    if heads
+        .iter()
+        .any(|entry| entry.content_len() > MAX_MERGE_CONTENT_SIZE)
+    {
+        debug!(namespace=%namespace.fmt_short(), "skip merge of key with large values");
+        return Ok(None);
+    }
+
+    let mut merge_heads = Vec::with_capacity(heads.len());
+    for entry in heads {
+        let content = if entry.is_empty() {
+            None
+        } else {
+            let hash = entry.content_hash();
+            match read_complete_blob(bao_store, local_pool, hash).await? {
+                Some(content) => Some(content),
+                None => return Ok(Some(hash)),
+            }
+        };
+        merge_heads.push(MergeHead { entry, content });
+    }
+
+    let Some(merged) = handler.merge(namespace, &key, &merge_heads) else {
+        return Ok(None);
+    };
+    let hash = Hash::new(&merged);
+    if merge_heads[0].entry.content_hash() == hash {
+        return Ok(None);
+    }
+    debug!(namespace=%namespace.fmt_short(), hash=%hash.fmt_short(), "write merged value");
+    let len = merged.len() as u64;
+    let _tag = bao_store.import_bytes(merged, BlobFormat::Raw).await?;
+    sync.insert_local(namespace, author, key, hash, len, None)
+        .await?;
+    Ok(None)
+}
+
+/// Read the content of a blob, if it is complete in the store.
+///
+/// Blob readers are not `Send`, so the read runs on the local pool. Fails if the blob is larger
+/// than [`MAX_MERGE_CONTENT_SIZE`].
+async fn read_complete_blob<B: iroh_blobs::store::Store>(
+    store: &B,
+    local_pool: &LocalPoolHandle,
+    hash: Hash,
+) -> Result<Option<Bytes>> {
+    let store = store.clone();
+    local_pool
+        .spawn_pinned(move || async move {
+            match store.get(&hash).await? {
+                Some(blob) if blob.is_complete() => {
+                    let size = blob.size().value();
+                    ensure!(
+                        size <= MAX_MERGE_CONTENT_SIZE,
+                        "content {} is too large to merge: {size} bytes",
+                        hash.fmt_short()
+                    );
+                    Ok(Some(
+                        blob.data_reader().await?.read_at(0, size as usize).await?,
+                    ))
+                }
+                _ => Ok(None),
+            }
+        })
+        .await?
+}
+
 #[derive(Debug, Default)]
 struct QueuedHashes {
     by_hash: HashMap<Hash, HashSet<NamespaceId>>,
@@ -0,0 +1,41 @@
+//! Hooks to merge the concurrent values of a key.
+
+use std::fmt::Debug;
+
+use bytes::Bytes;
+
+use crate::{NamespaceId, SignedEntry};
+
+/// A head of a key, as passed to a [`MergeHandler`].
+#[derive(Debug, Clone)]
+pub struct MergeHead {
+    /// The entry of one author for the key.
+    pub entry: SignedEntry,
+    /// The content of the entry, or `None` if the entry is empty (a deletion marker).
+    pub content: Option<Bytes>,
+}
+
+/// Merges the concurrent values of a key into a new value.
+///
+/// A merge handler is set per document with [`super::Engine::set_merge_handler`]. Whenever an
+/// entry from a remote peer is inserted into the document, the engine collects the heads of its
+/// key, i.e. the latest entry of every author, see [`crate::store::Store::get_heads`]. If the
+/// heads have more than one distinct value, the handler is called once the content of all heads
+/// is available locally. The value it returns is written to the key by the merge author, unless
+/// it is the value of the latest head already.
+///
+/// The heads are the latest value of every author, not only the concurrent ones: entries carry no
+/// causal history, so a head may be dominated by a newer head that already includes it, e.g. a
+/// value that was merged before. The handler must therefore be idempotent, merging a value into
+/// a result that already includes it must not change the result. Values larger than 16 MiB are
+/// not merged.
+///
+/// Other peers with a merge handler merge the new value in turn, so the handler should be
+/// deterministic and return the same value for the same set of heads, regardless of their order
+/// and authors. Otherwise peers keep writing new merged values to each other.
+pub trait MergeHandler: Debug + Send + Sync + 'static {
+    /// Merge the `heads` of `key`, which are sorted newest first.
+    ///
+    /// Returns the merged value, or `None` to leave the key as it is.
+    fn merge(&self, namespace: NamespaceId, key: &[u8], heads: &[MergeHead]) -> Option<Bytes>;
+}
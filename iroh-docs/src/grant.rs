@@ -0,0 +1,366 @@
+//! Delegated write access to a namespace.
+//!
+//! Without further setup, only holders of the [`NamespaceSecret`] can write to a namespace. A
+//! [`WriteGrant`] allows a single [`AuthorId`] to write entries without knowing the namespace
+//! secret, optionally limited to keys starting with a prefix and to timestamps before an expiry
+//! time.
+//!
+//! Grants are stored as entries in the namespace itself, so they are synced to all peers along
+//! with the other entries. A grant entry is authored by the namespace key (the [`AuthorId`] has
+//! the same bytes as the [`NamespaceId`]) and its key starts with [`GRANT_KEY_PREFIX`], followed
+//! by the grantee, the expiry and the key prefix.
+//!
+//! A revocation is an entry authored by the namespace key whose key is [`REVOCATION_KEY_PREFIX`],
+//! followed by the grantee and a zero byte. It does not delete any grants: grants issued before
+//! the revocation stay in the namespace, but only cover the entries of the grantee that the
+//! namespace owner accepted until then. Each of these entries is listed in an entry of its own,
+//! authored by the namespace key, whose key is [`REVOCATION_KEY_PREFIX`], followed by the grantee,
+//! a one byte and a digest of the accepted entry. The content hash of the revocation commits to
+//! the set of these digests, so all keys have a fixed length no matter how many entries the
+//! grantee wrote. This way, entries written while a grant was valid can still be synced to peers
+//! that did not receive them yet, but a revoked author cannot write any other entry, whatever its
+//! timestamp. Entries of the grantee that the namespace owner did not see before the revocation
+//! are rejected as well.
+//!
+//! Entries written by a delegated author are signed twice by the author, see
+//! [`EntrySignature::from_delegated_entry`](crate::EntrySignature::from_delegated_entry). They
+//! are only valid while a grant in the namespace covers them.
+
+use std::collections::HashSet;
+
+use ed25519_dalek::SignatureError;
+use iroh_base::hash::Hash;
+use serde::{Deserialize, Serialize};
+
+use crate::{Author, AuthorId, Entry, NamespaceId, NamespaceSecret, Record, SignedEntry};
+
+/// Key prefix of the entries that hold the [`WriteGrant`]s of a namespace.
+pub const GRANT_KEY_PREFIX: &[u8] = b"\0iroh-docs/grant/";
+
+/// Key prefix of the entries that revoke [`WriteGrant`]s and that list the entries still covered
+/// by revoked grants.
+///
+/// Neither this nor [`GRANT_KEY_PREFIX`] is a prefix of the other, so inserting a revocation
+/// never deletes a grant or the other way around.
+pub const REVOCATION_KEY_PREFIX: &[u8] = b"\0iroh-docs/revocation/";
+
+/// Value of the expiry field of grants that do not expire.
+const NO_EXPIRY: u64 = u64::MAX;
+
+/// Byte after the grantee in the key of a revocation.
+const REVOKED: u8 = 0;
+
+/// Byte after the grantee in the key of an entry that lists an accepted entry.
+const ACCEPTED: u8 = 1;
+
+/// Permission for an author to write to a namespace without the namespace secret.
+///
+/// See the [module docs](self) for how grants are stored and revoked.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "SignedEntry", into = "SignedEntry")]
+pub struct WriteGrant {
+    entry: SignedEntry,
+}
+
+impl WriteGrant {
+    /// Allow `author` to write entries with keys starting with `prefix` to `namespace`.
+    ///
+    /// If `expires` is set, only entries with a timestamp (in microseconds since the Unix
+    /// epoch) before `expires` are covered. Note that the timestamp of an entry is chosen by its
+    /// author, so an expiry does not stop the author from writing entries with an earlier
+    /// timestamp. Use [`WriteGrant::revocation`] to end write access.
+    pub fn new(
+        namespace: &NamespaceSecret,
+        author: AuthorId,
+        prefix: impl AsRef<[u8]>,
+        expires: Option<u64>,
+    ) -> Self {
+        let mut key = grant_key(author);
+        key.extend_from_slice(&expires.unwrap_or(NO_EXPIRY).to_be_bytes());
+        key.extend_from_slice(prefix.as_ref());
+        // a grant has no content of its own, so the record refers to the grant key
+        let record = Record::new_current(Hash::new(&key), key.len() as u64);
+        let entry = SignedEntry::from_parts(namespace, &namespace_author(namespace), key, record);
+        Self { entry }
+    }
+
+    /// Create the entries that revoke all grants issued to `author` so far.
+    ///
+    /// The grants keep covering the `accepted` entries of `author`, which should be all entries
+    /// of `author` known to the namespace owner. All other entries of `author` are rejected.
+    ///
+    /// Returns an entry for each accepted entry, followed by the revocation itself. They must be
+    /// inserted in this order, so that no peer sees the revocation without the accepted entries.
+    pub fn revocation<'a>(
+        namespace: &NamespaceSecret,
+        author: AuthorId,
+        accepted: impl IntoIterator<Item = &'a Entry>,
+    ) -> Vec<SignedEntry> {
+        let mut digests = accepted.into_iter().map(entry_digest).collect::<Vec<_>>();
+        digests.sort();
+        digests.dedup();
+        let mut entries = digests
+            .iter()
+            .map(|digest| {
+                let mut key = revocation_key(author, ACCEPTED);
+                key.extend_from_slice(digest.as_bytes());
+                // like a grant, the entry has no content and refers to its key
+                let record = Record::new_current(Hash::new(&key), key.len() as u64);
+                SignedEntry::from_parts(namespace, &namespace_author(namespace), key, record)
+            })
+            .collect::<Vec<_>>();
+        let key = revocation_key(author, REVOKED);
+        // the content of a revocation is its key followed by the sorted digests
+        let mut content = key.clone();
+        for digest in &digests {
+            content.extend_from_slice(digest.as_bytes());
+        }
+        let record = Record::new_current(Hash::new(&content), content.len() as u64);
+        entries.push(SignedEntry::from_parts(
+            namespace,
+            &namespace_author(namespace),
+            key,
+            record,
+        ));
+        entries
+    }
+
+    /// Get the [`NamespaceId`] this grant is for.
+    pub fn namespace(&self) -> NamespaceId {
+        self.entry.entry().namespace()
+    }
+
+    /// Get the [`AuthorId`] that may write to the namespace.
+    pub fn author(&self) -> AuthorId {
+        let bytes = &self.entry.key()[GRANT_KEY_PREFIX.len()..][..32];
+        AuthorId::from(<&[u8; 32]>::try_from(bytes).expect("checked on creation"))
+    }
+
+    /// Get the prefix that the keys of covered entries must start with.
+    pub fn prefix(&self) -> &[u8] {
+        &self.entry.key()[GRANT_KEY_PREFIX.len() + 40..]
+    }
+
+    /// Get the time (in microseconds since the Unix epoch) from which on entries are no longer
+    /// covered, if any.
+    pub fn expires(&self) -> Option<u64> {
+        let bytes = &self.entry.key()[GRANT_KEY_PREFIX.len() + 32..][..8];
+        let expires = u64::from_be_bytes(bytes.try_into().expect("checked on creation"));
+        (expires != NO_EXPIRY).then_some(expires)
+    }
+
+    /// Get the time at which this grant was issued.
+    pub fn timestamp(&self) -> u64 {
+        self.entry.timestamp()
+    }
+
+    /// Get the signed entry that holds this grant in the namespace.
+    pub fn entry(&self) -> &SignedEntry {
+        &self.entry
+    }
+
+    /// Whether this grant allows the author of `entry` to write it.
+    pub fn authorizes(&self, entry: &Entry) -> bool {
+        entry.namespace() == self.namespace()
+            && entry.author() == self.author()
+            && entry.key().starts_with(self.prefix())
+            && self
+                .expires()
+                .map_or(true, |expires| entry.timestamp() < expires)
+    }
+}
+
+impl From<WriteGrant> for SignedEntry {
+    fn from(value: WriteGrant) -> Self {
+        value.entry
+    }
+}
+
+impl TryFrom<SignedEntry> for WriteGrant {
+    type Error = InvalidGrant;
+
+    fn try_from(entry: SignedEntry) -> Result<Self, Self::Error> {
+        if !entry.key().starts_with(GRANT_KEY_PREFIX) || entry.entry().record().is_empty() {
+            return Err(InvalidGrant::NotAGrant);
+        }
+        if entry.key().len() < GRANT_KEY_PREFIX.len() + 40 {
+            return Err(InvalidGrant::NotAGrant);
+        }
+        let namespace = entry.entry().namespace().into_public_key()?;
+        let author = entry.entry().author().into_public_key()?;
+        entry
+            .signature()
+            .verify(entry.entry(), &namespace, &author)?;
+        Ok(Self { entry })
+    }
+}
+
+/// Error when reading a [`WriteGrant`] from a [`SignedEntry`].
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidGrant {
+    /// The entry does not hold a grant.
+    #[error("entry is not a write grant")]
+    NotAGrant,
+    /// The entry is not signed by the namespace key.
+    #[error("invalid grant signature: {0}")]
+    BadSignature(#[from] SignatureError),
+}
+
+/// The [`WriteGrant`]s and revocations known for a namespace.
+#[derive(Debug, Clone, Default)]
+pub struct WriteGrants {
+    grants: Vec<WriteGrant>,
+    /// The author and time of each revocation.
+    revocations: Vec<(AuthorId, u64)>,
+    /// The author and digest of each entry that revoked grants still cover.
+    accepted: HashSet<(AuthorId, Hash)>,
+}
+
+impl WriteGrants {
+    /// Add a grant or revocation to the set.
+    ///
+    /// Returns `false` if `entry` is neither a valid grant nor a valid revocation.
+    pub fn insert(&mut self, entry: &SignedEntry) -> bool {
+        self.insert_inner(entry, true)
+    }
+
+    /// Add a grant or revocation from the replica store to the set.
+    ///
+    /// Entries in the store were verified when they were inserted, so this skips checking the
+    /// signatures of revocations, of which there is one for each accepted entry.
+    pub(crate) fn insert_stored(&mut self, entry: &SignedEntry) -> bool {
+        self.insert_inner(entry, false)
+    }
+
+    fn insert_inner(&mut self, entry: &SignedEntry, verify: bool) -> bool {
+        if let Some(revocation) = Revocation::from_entry(entry, verify) {
+            match revocation {
+                Revocation::Revoked(author) => {
+                    self.revocations.push((author, entry.timestamp()));
+                }
+                Revocation::Accepted(author, digest) => {
+                    self.accepted.insert((author, digest));
+                }
+            }
+            true
+        } else if let Ok(grant) = WriteGrant::try_from(entry.clone()) {
+            self.grants.push(grant);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Get a grant that allows the author of `entry` to write it, if any.
+    ///
+    /// Once revoked, a grant only covers the entries that the namespace owner accepted before
+    /// the revocation.
+    pub fn find(&self, entry: &Entry) -> Option<&WriteGrant> {
+        self.grants.iter().find(|grant| {
+            grant.authorizes(entry)
+                && (!self.is_revoked(grant)
+                    || self
+                        .accepted
+                        .contains(&(entry.author(), entry_digest(entry))))
+        })
+    }
+
+    /// Iterate over the grants for `author` that are not revoked.
+    pub fn for_author(&self, author: AuthorId) -> impl Iterator<Item = &WriteGrant> {
+        self.grants
+            .iter()
+            .filter(move |grant| grant.author() == author && !self.is_revoked(grant))
+    }
+
+    /// Whether a revocation issued after `grant` ends it.
+    fn is_revoked(&self, grant: &WriteGrant) -> bool {
+        self.revocations
+            .iter()
+            .any(|(author, timestamp)| *author == grant.author() && *timestamp > grant.timestamp())
+    }
+}
+
+/// Whether `entry` is stored under the grant or revocation keys of its namespace.
+pub(crate) fn is_grant_entry(entry: &Entry) -> bool {
+    entry.author().as_bytes() == entry.namespace().as_bytes()
+        && (entry.key().starts_with(GRANT_KEY_PREFIX)
+            || entry.key().starts_with(REVOCATION_KEY_PREFIX))
+}
+
+/// The key of the grants for `author`, without expiry and prefix.
+fn grant_key(author: AuthorId) -> Vec<u8> {
+    let mut key = GRANT_KEY_PREFIX.to_vec();
+    key.extend_from_slice(author.as_bytes());
+    key
+}
+
+/// The namespace key used as author, to sign grants and revocations.
+fn namespace_author(namespace: &NamespaceSecret) -> Author {
+    Author::from_bytes(&namespace.to_bytes())
+}
+
+/// The key of a revocation entry for `author`, up to and including `kind`.
+fn revocation_key(author: AuthorId, kind: u8) -> Vec<u8> {
+    let mut key = REVOCATION_KEY_PREFIX.to_vec();
+    key.extend_from_slice(author.as_bytes());
+    key.push(kind);
+    key
+}
+
+/// An entry stored under [`REVOCATION_KEY_PREFIX`].
+#[derive(Debug)]
+enum Revocation {
+    /// The grants of the author are revoked.
+    Revoked(AuthorId),
+    /// The entry of the author with this digest is still covered by its revoked grants.
+    Accepted(AuthorId, Hash),
+}
+
+impl Revocation {
+    /// Read a revocation from `entry`, if it is a valid revocation.
+    ///
+    /// The signature of `entry` is only checked if `verify` is set.
+    fn from_entry(entry: &SignedEntry, verify: bool) -> Option<Self> {
+        let key = entry.key().strip_prefix(REVOCATION_KEY_PREFIX)?;
+        if key.len() < 33
+            || !is_grant_entry(entry.entry())
+            || (verify && !is_namespace_signed(entry))
+        {
+            return None;
+        }
+        let author = AuthorId::from(<&[u8; 32]>::try_from(&key[..32]).expect("checked length"));
+        match (key[32], &key[33..]) {
+            (REVOKED, []) => Some(Self::Revoked(author)),
+            (ACCEPTED, digest) => {
+                let digest = <[u8; 32]>::try_from(digest).ok()?;
+                Some(Self::Accepted(author, Hash::from_bytes(digest)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The digest of an accepted entry, covering its author, key, timestamp and content.
+fn entry_digest(entry: &Entry) -> Hash {
+    let mut bytes = Vec::with_capacity(entry.key().len() + 88);
+    bytes.extend_from_slice(entry.author().as_bytes());
+    bytes.extend_from_slice(&(entry.key().len() as u64).to_be_bytes());
+    bytes.extend_from_slice(entry.key());
+    bytes.extend_from_slice(&entry.timestamp().to_be_bytes());
+    bytes.extend_from_slice(entry.content_hash().as_bytes());
+    bytes.extend_from_slice(&entry.content_len().to_be_bytes());
+    Hash::new(bytes)
+}
+
+fn is_namespace_signed(entry: &SignedEntry) -> bool {
+    let (Ok(namespace), Ok(author)) = (
+        entry.entry().namespace().into_public_key(),
+        entry.entry().author().into_public_key(),
+    ) else {
+        return false;
+    };
+    entry
+        .signature()
+        .verify(entry.entry(), &namespace, &author)
+        .is_ok()
+}
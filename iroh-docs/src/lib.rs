@@ -13,6 +13,9 @@
 //! * The [Author] key, as a proof of authorship. Any number of authors may be created, and
 //!   their semantic meaning is application-specific. The public key of an author is the [AuthorId].
 //!
+//! Authors can also be allowed to write without the namespace key through a [`WriteGrant`]. Their
+//! entries carry a second author signature instead of the namespace signature.
+//!
 //! Replicas can be synchronized between peers by exchanging messages. The synchronization algorithm
 //! is based on a technique called *range-based set reconciliation*, based on [this paper][paper] by
 //! Aljoscha Meyer:
@@ -47,10 +50,12 @@ pub mod actor;
 pub mod store;
 pub mod sync;
 
+mod grant;
 mod heads;
 mod keys;
 mod ranger;
 
+pub use self::grant::*;
 pub use self::heads::*;
 pub use self::keys::*;
 pub use self::sync::*;
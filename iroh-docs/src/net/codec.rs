@@ -13,7 +13,9 @@ use tracing::{debug, trace, Span};
 use crate::{
     actor::SyncHandle,
     net::{AbortReason, AcceptError, AcceptOutcome, ConnectError},
-    NamespaceId, SyncOutcome,
+    ranger::{self, RangeEntry},
+    sync::{ExpiryEnvelope, WireEntry},
+    NamespaceId, SignedEntry, SyncOutcome,
 };
 
 #[derive(Debug, Default)]
@@ -39,8 +41,15 @@ impl Decoder for SyncCodec {
             return Ok(None);
         }
 
-        let message: Message = postcard::from_bytes(&src[4..4 + frame_len])?;
+        let (mut message, envelope) =
+            ExpiryEnvelope::decode::<Message<WireEntry>>(&src[4..4 + frame_len])?;
+        let pruned = match envelope {
+            Some(envelope) => envelope.apply(message.entries_mut().map(|entry| &mut entry.0)),
+            None => Vec::new(),
+        };
         src.advance(4 + frame_len);
+        let mut message = message.map_entries(|entry| entry.0);
+        message.set_pruned(pruned);
         Ok(Some(message))
     }
 }
@@ -48,9 +57,11 @@ impl Decoder for SyncCodec {
 impl Encoder<Message> for SyncCodec {
     type Error = anyhow::Error;
 
-    fn encode(&mut self, item: Message, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        let len =
-            postcard::serialize_with_flavor(&item, postcard::ser_flavors::Size::default()).unwrap();
+    fn encode(&mut self, mut item: Message, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let pruned = item.take_pruned();
+        let item = item.map_entries(WireEntry);
+        let bytes = ExpiryEnvelope::encode(&item, item.entries().map(|entry| &entry.0), pruned)?;
+        let len = bytes.len();
         ensure!(
             len <= MAX_MESSAGE_SIZE,
             "attempting to send message that is too large {}",
@@ -58,10 +69,7 @@ impl Encoder<Message> for SyncCodec {
         );
 
         dst.put_u32(u32::try_from(len).expect("already checked"));
-        if dst.len() < 4 + len {
-            dst.resize(4 + len, 0u8);
-        }
-        postcard::to_slice(&item, &mut dst[4..])?;
+        dst.extend_from_slice(&bytes);
 
         Ok(())
     }
@@ -73,21 +81,78 @@ impl Encoder<Message> for SyncCodec {
 /// - N Sync messages
 ///
 /// On any error and on success the substream is closed.
+///
+/// On the wire, the entries are sent as [`WireEntry`].
 #[derive(Debug, Clone, Serialize, Deserialize)]
-enum Message {
+#[serde(bound(
+    serialize = "ranger::Message<E>: Serialize",
+    deserialize = "ranger::Message<E>: Deserialize<'de>"
+))]
+enum Message<E: RangeEntry = SignedEntry> {
     /// Init message (sent by the dialing peer)
     Init {
         /// Namespace to sync
         namespace: NamespaceId,
         /// Initial message
-        message: crate::sync::ProtocolMessage,
+        message: ranger::Message<E>,
     },
     /// Sync messages (sent by both peers)
-    Sync(crate::sync::ProtocolMessage),
+    Sync(ranger::Message<E>),
     /// Abort message (sent by the accepting peer to decline a request)
     Abort { reason: AbortReason },
 }
 
+impl<E: RangeEntry> Message<E> {
+    /// Convert the entries contained in this message.
+    fn map_entries<F: RangeEntry<Key = E::Key>>(self, f: impl FnMut(E) -> F) -> Message<F> {
+        match self {
+            Message::Init { namespace, message } => Message::Init {
+                namespace,
+                message: message.map_values(f),
+            },
+            Message::Sync(message) => Message::Sync(message.map_values(f)),
+            Message::Abort { reason } => Message::Abort { reason },
+        }
+    }
+
+    /// Take the pruned entries out of this message, see [`ranger::Message::pruned`].
+    fn take_pruned(&mut self) -> Vec<E> {
+        match self {
+            Message::Init { message, .. } | Message::Sync(message) => message.take_pruned(),
+            Message::Abort { .. } => Vec::new(),
+        }
+    }
+
+    /// Set the pruned entries of this message, see [`ranger::Message::pruned`].
+    fn set_pruned(&mut self, pruned: Vec<E>) {
+        if let Message::Init { message, .. } | Message::Sync(message) = self {
+            message.set_pruned(pruned);
+        }
+    }
+
+    /// The entries contained in this message, in the order they are serialized.
+    fn entries(&self) -> impl Iterator<Item = &E> {
+        let message = match self {
+            Message::Init { message, .. } | Message::Sync(message) => Some(message),
+            Message::Abort { .. } => None,
+        };
+        message
+            .into_iter()
+            .flat_map(|message| message.values().map(|(entry, _)| entry))
+    }
+
+    /// Mutable access to the entries contained in this message, see [`Self::entries`].
+    fn entries_mut(&mut self) -> impl Iterator<Item = &mut E> {
+        let message = match self {
+            Message::Init { message, .. } | Message::Sync(message) => Some(message),
+            Message::Abort { .. } => None,
+        };
+        message
+            .into_iter()
+            .flat_map(|message| message.values_mut().map(|(entry, _)| entry))
+    }
+}
+
 /// Runs the initiator side of the sync protocol.
 pub(super) async fn run_alice<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
     writer: &mut W,
@@ -356,7 +421,7 @@ mod tests {
         let (alice, bob) = tokio::io::duplex(64);
 
         let (mut alice_reader, mut alice_writer) = tokio::io::split(alice);
-        let alice_handle = SyncHandle::spawn(alice_store, None, "alice".to_string());
+        let alice_handle = SyncHandle::spawn(alice_store, None, None, "alice".to_string());
         alice_handle
             .open(namespace.id(), OpenOpts::default().sync())
             .await?;
@@ -374,7 +439,7 @@ mod tests {
         });
 
         let (mut bob_reader, mut bob_writer) = tokio::io::split(bob);
-        let bob_handle = SyncHandle::spawn(bob_store, None, "bob".to_string());
+        let bob_handle = SyncHandle::spawn(bob_store, None, None, "bob".to_string());
         bob_handle
             .open(namespace.id(), OpenOpts::default().sync())
             .await?;
@@ -537,10 +602,10 @@ mod tests {
                 // replicas can be opened only once so close the replicas before spawning the
                 // actors
                 alice_store.close_replica(namespace.id());
-                let alice_handle = SyncHandle::spawn(alice_store, None, "alice".to_string());
+                let alice_handle = SyncHandle::spawn(alice_store, None, None, "alice".to_string());
 
                 bob_store.close_replica(namespace.id());
-                let bob_handle = SyncHandle::spawn(bob_store, None, "bob".to_string());
+                let bob_handle = SyncHandle::spawn(bob_store, None, None, "bob".to_string());
 
                 run_sync(
                     alice_handle.clone(),
@@ -664,8 +729,8 @@ mod tests {
         alice_store.close_replica(namespace.id());
         bob_store.close_replica(namespace.id());
 
-        let alice_handle = SyncHandle::spawn(alice_store, None, "alice".to_string());
-        let bob_handle = SyncHandle::spawn(bob_store, None, "bob".to_string());
+        let alice_handle = SyncHandle::spawn(alice_store, None, None, "alice".to_string());
+        let bob_handle = SyncHandle::spawn(bob_store, None, None, "bob".to_string());
 
         run_sync(
             alice_handle.clone(),
@@ -690,4 +755,91 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_codec_expiry_envelope() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let mut alice_store = store::Store::memory();
+        let mut bob_store = store::Store::memory();
+        let author = alice_store.new_author(&mut rng)?;
+        let namespace = NamespaceSecret::new(&mut rng);
+        let expires = crate::sync::system_time_now() + 60_000_000;
+        let mut alice = alice_store.new_replica(namespace.clone())?;
+        alice.hash_and_insert("keep", &author, "keep")?;
+        alice.insert_with_expiry("presence", &author, Hash::new("here"), 4, Some(expires))?;
+        let mut bob = bob_store.new_replica(namespace.clone())?;
+        let init = bob.sync_initial_message()?;
+        let reply = alice
+            .sync_process_message(init, [2u8; 32], &mut Default::default())?
+            .expect("alice has entries to send");
+        let message = super::Message::Sync(reply);
+        assert_eq!(message.entries().count(), 2);
+
+        let mut frame = BytesMut::new();
+        SyncCodec.encode(message.clone(), &mut frame)?;
+
+        // a peer that does not know about expiry decodes the message, but the expiring entry
+        // fails to verify
+        let v1_message: super::Message<WireEntry> = postcard::from_bytes(&frame[4..])?;
+        let mut verified = v1_message
+            .entries()
+            .filter(|entry| entry.0.verify(&(), &Default::default()).is_ok())
+            .map(|entry| entry.0.key().to_vec());
+        assert_eq!(verified.next(), Some(b"keep".to_vec()));
+        assert_eq!(verified.next(), None);
+
+        // with the envelope, the expiry is restored
+        let decoded = SyncCodec.decode(&mut frame)?.expect("complete frame");
+        let entries = decoded.entries().collect::<Vec<_>>();
+        assert_eq!(entries, message.entries().collect::<Vec<_>>());
+        assert!(entries
+            .iter()
+            .all(|entry| entry.verify(&(), &Default::default()).is_ok()));
+        assert_eq!(entries[1].expires(), Some(expires));
+
+        // messages without expiring entries are encoded as in the first protocol version
+        let message = super::Message::Sync(bob.sync_initial_message()?);
+        let mut frame = BytesMut::new();
+        SyncCodec.encode(message.clone(), &mut frame)?;
+        assert_eq!(
+            &frame[4..],
+            &postcard::to_stdvec(&message.map_entries(WireEntry))?[..]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_codec_pruned_envelope() -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let mut store = store::Store::memory();
+        let author = store.new_author(&mut rng)?;
+        let namespace = NamespaceSecret::new(&mut rng);
+        let mut replica = store.new_replica(namespace.clone())?;
+        replica.delete_prefix("del/", &author)?;
+        let mut message = replica.sync_initial_message()?;
+        drop(replica);
+        let tombstone = store
+            .get_exact(namespace.id(), author.id(), "del/", true)?
+            .expect("deletion marker exists");
+        message.set_pruned(vec![tombstone.clone()]);
+        let message = super::Message::Sync(message);
+
+        let mut frame = BytesMut::new();
+        SyncCodec.encode(message.clone(), &mut frame)?;
+
+        // a peer that does not know about pruned entries decodes the message without them
+        let v1_message: super::Message<WireEntry> = postcard::from_bytes(&frame[4..])?;
+        let super::Message::Sync(v1_message) = v1_message else {
+            panic!("expected a sync message");
+        };
+        assert!(v1_message.pruned().is_empty());
+
+        // with the envelope, the pruned entries are restored
+        let decoded = SyncCodec.decode(&mut frame)?.expect("complete frame");
+        let super::Message::Sync(decoded) = decoded else {
+            panic!("expected a sync message");
+        };
+        assert_eq!(decoded.pruned(), &[tombstone]);
+        Ok(())
+    }
 }
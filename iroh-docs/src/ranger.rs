@@ -189,6 +189,13 @@ impl<E: RangeEntry> MessagePart<E> {
             MessagePart::RangeItem(RangeItem { values, .. }) => Some(values),
         }
     }
+
+    pub fn values_mut(&mut self) -> Option<&mut [(E, ContentStatus)]> {
+        match self {
+            MessagePart::RangeFingerprint(_) => None,
+            MessagePart::RangeItem(RangeItem { values, .. }) => Some(values),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -198,6 +205,12 @@ pub struct Message<E: RangeEntry> {
         deserialize = "MessagePart<E>: Deserialize<'de>"
     ))]
     parts: Vec<MessagePart<E>>,
+    /// Entries that the sender pruned when compacting its store, and that delete or supersede
+    /// entries the receiver sent, see [`Store::put_compacted`].
+    ///
+    /// These are not part of the serialized message, transports have to send them separately.
+    #[serde(skip, default = "Vec::new")]
+    pruned: Vec<E>,
 }
 
 impl<E: RangeEntry> Message<E> {
@@ -207,7 +220,10 @@ impl<E: RangeEntry> Message<E> {
         let range = Range::new(x.clone(), x);
         let fingerprint = store.get_fingerprint(&range)?;
         let part = MessagePart::RangeFingerprint(RangeFingerprint { range, fingerprint });
-        Ok(Message { parts: vec![part] })
+        Ok(Message {
+            parts: vec![part],
+            pruned: Vec::new(),
+        })
     }
 
     pub fn parts(&self) -> &[MessagePart<E>] {
@@ -218,8 +234,58 @@ impl<E: RangeEntry> Message<E> {
         self.parts().iter().filter_map(|p| p.values()).flatten()
     }
 
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut (E, ContentStatus)> {
+        self.parts
+            .iter_mut()
+            .filter_map(|p| p.values_mut())
+            .flatten()
+    }
+
+    /// The number of entries in this message, including pruned entries.
     pub fn value_count(&self) -> usize {
-        self.values().count()
+        self.values().count() + self.pruned.len()
+    }
+
+    /// Entries that the sender pruned, see [`Store::put_compacted`].
+    pub fn pruned(&self) -> &[E] {
+        &self.pruned
+    }
+
+    /// Take the pruned entries out of this message.
+    pub fn take_pruned(&mut self) -> Vec<E> {
+        std::mem::take(&mut self.pruned)
+    }
+
+    /// Set the pruned entries of this message.
+    pub fn set_pruned(&mut self, pruned: Vec<E>) {
+        self.pruned = pruned;
+    }
+
+    /// Convert the entries of this message, e.g. to change how they are serialized.
+    pub fn map_values<F: RangeEntry<Key = E::Key>>(self, mut f: impl FnMut(E) -> F) -> Message<F> {
+        let parts = self
+            .parts
+            .into_iter()
+            .map(|part| match part {
+                MessagePart::RangeFingerprint(fingerprint) => {
+                    MessagePart::RangeFingerprint(fingerprint)
+                }
+                MessagePart::RangeItem(RangeItem {
+                    range,
+                    values,
+                    have_local,
+                }) => MessagePart::RangeItem(RangeItem {
+                    range,
+                    values: values
+                        .into_iter()
+                        .map(|(entry, status)| (f(entry), status))
+                        .collect(),
+                    have_local,
+                }),
+            })
+            .collect();
+        let pruned = self.pruned.into_iter().map(f).collect();
+        Message { parts, pruned }
     }
 }
 
@@ -298,6 +364,43 @@ pub trait Store<E: RangeEntry>: Sized {
         predicate: impl Fn(&E::Value) -> bool,
     ) -> Result<usize, Self::Error>;
 
+    /// Returns the entry that was pruned when compacting the store and that supersedes or
+    /// deletes `entry`, if any.
+    ///
+    /// Such entries are never inserted, so that compaction does not resurrect them.
+    fn get_compacted(&mut self, _entry: &E) -> Result<Option<E>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Remember `entry` as pruned, without storing it as an entry.
+    ///
+    /// Stores that are never compacted do not need to remember anything.
+    fn entry_put_compacted(&mut self, _entry: E) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Apply an entry that a peer pruned when compacting its store.
+    ///
+    /// The entries that `entry` supersedes or deletes are removed, like in [`Self::put`], but
+    /// instead of being inserted, `entry` is remembered as pruned with
+    /// [`Self::entry_put_compacted`]. An existing equal entry is removed as well, so that both
+    /// peers end up with the same set of entries.
+    ///
+    /// Returns `true` if `entry` was applied.
+    fn put_compacted(&mut self, entry: E) -> Result<bool, Self::Error> {
+        if self.get_compacted(&entry)?.is_some() {
+            return Ok(false);
+        }
+        for prefix_entry in self.prefixes_of(entry.key())? {
+            if entry.value() < prefix_entry?.value() {
+                return Ok(false);
+            }
+        }
+        self.remove_prefix_filtered(entry.key(), |value| entry.value() >= value)?;
+        self.entry_put_compacted(entry)?;
+        Ok(true)
+    }
+
     /// Generates the initial message.
     fn initial_message(&mut self) -> Result<Message<E>, Self::Error> {
         Message::init(self)
@@ -316,6 +419,10 @@ pub trait Store<E: RangeEntry>: Sized {
     ///
     /// `content_status_cb` is called for each outgoing entry about to be sent to the remote.
     /// It must return a [`ContentStatus`], which will be sent to the remote with the entry.
+    ///
+    /// Incoming entries that are superseded or deleted by an entry pruned from our store are
+    /// answered with the pruned entry, so that the remote can drop them as well, see
+    /// [`Self::put_compacted`].
     fn process_message<F, F2, F3>(
         &mut self,
         config: &SyncConfig,
@@ -330,6 +437,14 @@ pub trait Store<E: RangeEntry>: Sized {
         F3: Fn(&Self, &E) -> ContentStatus,
     {
         let mut out = Vec::new();
+        let mut pruned_out: Vec<E> = Vec::new();
+
+        // Apply the entries the remote pruned
+        for entry in message.pruned {
+            if validate_cb(self, &entry, ContentStatus::Complete) {
+                self.put_compacted(entry)?;
+            }
+        }
 
         // TODO: can these allocs be avoided?
         let mut items = Vec::new();
@@ -388,6 +503,12 @@ pub trait Store<E: RangeEntry>: Sized {
 
             // Store incoming values
             for (entry, content_status) in values {
+                if let Some(compacted) = self.get_compacted(&entry)? {
+                    if !pruned_out.iter().any(|e| e.key() == compacted.key()) {
+                        pruned_out.push(compacted);
+                    }
+                    continue;
+                }
                 if validate_cb(self, &entry, content_status) {
                     // TODO: Get rid of the clone?
                     let outcome = self.put(entry.clone())?;
@@ -540,8 +661,11 @@ pub trait Store<E: RangeEntry>: Sized {
         }
 
         // If we have any parts, return a message
-        if !out.is_empty() {
-            Ok(Some(Message { parts: out }))
+        if !out.is_empty() || !pruned_out.is_empty() {
+            Ok(Some(Message {
+                parts: out,
+                pruned: pruned_out,
+            }))
         } else {
             Ok(None)
         }
@@ -558,9 +682,14 @@ pub trait Store<E: RangeEntry>: Sized {
     /// Note: The deleted entries are simply dropped right now. We might want to make this return
     /// an iterator, to potentially log or expose the deleted entries.
     ///
+    /// Entries for which [`Store::get_compacted`] returns an entry are not inserted.
+    ///
     /// Returns `true` if the entry was inserted.
     /// Returns `false` if it was not inserted.
     fn put(&mut self, entry: E) -> Result<InsertOutcome, Self::Error> {
+        if self.get_compacted(&entry)?.is_some() {
+            return Ok(InsertOutcome::NotInserted);
+        }
         let prefix_entry = self.prefixes_of(entry.key())?;
         // First we check if our entry is strictly greater than all parent elements.
         // From the willow spec:
@@ -653,6 +782,14 @@ impl<E: RangeEntry, S: Store<E>> Store<E> for &mut S {
     ) -> Result<usize, Self::Error> {
         (**self).remove_prefix_filtered(prefix, predicate)
     }
+
+    fn get_compacted(&mut self, entry: &E) -> Result<Option<E>, Self::Error> {
+        (**self).get_compacted(entry)
+    }
+
+    fn entry_put_compacted(&mut self, entry: E) -> Result<(), Self::Error> {
+        (**self).entry_put_compacted(entry)
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -676,7 +813,7 @@ impl Default for SyncConfig {
 #[derive(Debug)]
 pub enum InsertOutcome {
     /// The entry was not inserted because a newer entry for its key or a
-    /// prefix of its key exists.
+    /// prefix of its key exists, or existed before the store was compacted.
     NotInserted,
     /// The entry was inserted.
     Inserted {
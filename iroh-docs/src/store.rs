@@ -3,6 +3,7 @@ use std::num::NonZeroUsize;
 
 use anyhow::Result;
 use bytes::Bytes;
+use iroh_base::hash::Hash;
 use serde::{Deserialize, Serialize};
 
 use crate::{AuthorId, Entry, NamespaceId};
@@ -60,6 +61,15 @@ pub enum ImportNamespaceOutcome {
     NoChange,
 }
 
+/// Outcome of [`Store::compact`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompactOutcome {
+    /// The timestamp before which records were pruned, in microseconds since the Unix epoch.
+    pub horizon: u64,
+    /// Number of empty entries (deletion markers) that were removed.
+    pub tombstones: usize,
+}
+
 /// Download policy to decide which content blobs shall be downloaded.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum DownloadPolicy {
@@ -168,6 +178,8 @@ pub struct QueryBuilder<K> {
     kind: K,
     filter_author: AuthorFilter,
     filter_key: KeyFilter,
+    filter_timestamp: TimestampFilter,
+    filter_hash: Option<Hash>,
     limit: Option<u64>,
     offset: u64,
     include_empty: bool,
@@ -190,6 +202,31 @@ impl<K> QueryBuilder<K> {
         self.filter_key = KeyFilter::Prefix(key.as_ref().to_vec().into());
         self
     }
+    /// Filter by key range, from `start` (inclusive) to `end` (exclusive).
+    pub fn key_range(mut self, start: impl AsRef<[u8]>, end: impl AsRef<[u8]>) -> Self {
+        self.filter_key =
+            KeyFilter::Range(start.as_ref().to_vec().into(), end.as_ref().to_vec().into());
+        self
+    }
+    /// Only include entries with a timestamp equal to or after `timestamp`.
+    ///
+    /// Timestamps are in microseconds since the Unix epoch.
+    pub fn updated_since(mut self, timestamp: u64) -> Self {
+        self.filter_timestamp.since = Some(timestamp);
+        self
+    }
+    /// Only include entries with a timestamp before `timestamp`.
+    ///
+    /// Timestamps are in microseconds since the Unix epoch.
+    pub fn updated_before(mut self, timestamp: u64) -> Self {
+        self.filter_timestamp.before = Some(timestamp);
+        self
+    }
+    /// Only include entries that point to the content with `hash`.
+    pub fn content_hash(mut self, hash: Hash) -> Self {
+        self.filter_hash = Some(hash);
+        self
+    }
     /// Filter by author.
     pub fn author(mut self, author: AuthorId) -> Self {
         self.filter_author = AuthorFilter::Exact(author);
@@ -255,6 +292,8 @@ impl From<QueryBuilder<SingleLatestPerKeyQuery>> for Query {
             kind: QueryKind::SingleLatestPerKey(builder.kind),
             filter_author: builder.filter_author,
             filter_key: builder.filter_key,
+            filter_timestamp: builder.filter_timestamp,
+            filter_hash: builder.filter_hash,
             limit: builder.limit,
             offset: builder.offset,
             include_empty: builder.include_empty,
@@ -269,6 +308,8 @@ impl From<QueryBuilder<FlatQuery>> for Query {
             kind: QueryKind::Flat(builder.kind),
             filter_author: builder.filter_author,
             filter_key: builder.filter_key,
+            filter_timestamp: builder.filter_timestamp,
+            filter_hash: builder.filter_hash,
             limit: builder.limit,
             offset: builder.offset,
             include_empty: builder.include_empty,
@@ -278,12 +319,14 @@ impl From<QueryBuilder<FlatQuery>> for Query {
 }
 
 /// Note: When using the `SingleLatestPerKey` query kind, the key filter is applied *before* the
-/// grouping, the author filter is applied *after* the grouping.
+/// grouping, the author, timestamp and content hash filters are applied *after* the grouping.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Query {
     kind: QueryKind,
     filter_author: AuthorFilter,
     filter_key: KeyFilter,
+    filter_timestamp: TimestampFilter,
+    filter_hash: Option<Hash>,
     limit: Option<u64>,
     offset: u64,
     include_empty: bool,
@@ -316,6 +359,23 @@ impl Query {
         Self::all().key_prefix(prefix)
     }
 
+    /// Create a [`Query::all`] query filtered by a key range.
+    pub fn key_range(start: impl AsRef<[u8]>, end: impl AsRef<[u8]>) -> QueryBuilder<FlatQuery> {
+        Self::all().key_range(start, end)
+    }
+
+    /// Create a [`Query::all`] query for entries updated since `timestamp`, sorted by timestamp.
+    pub fn updated_since(timestamp: u64) -> QueryBuilder<FlatQuery> {
+        Self::all()
+            .updated_since(timestamp)
+            .sort_by(SortBy::Timestamp, SortDirection::Asc)
+    }
+
+    /// Create a [`Query::all`] query for entries that point to the content with `hash`.
+    pub fn content_hash(hash: Hash) -> QueryBuilder<FlatQuery> {
+        Self::all().content_hash(hash)
+    }
+
     /// Get the limit for this query (max. number of entries to emit).
     pub fn limit(&self) -> Option<u64> {
         self.limit
@@ -325,6 +385,14 @@ impl Query {
     pub fn offset(&self) -> u64 {
         self.offset
     }
+
+    /// Test if a record is matched by the timestamp and content hash filters of this query.
+    fn matches_record(&self, timestamp: u64, hash: &[u8; 32]) -> bool {
+        self.filter_timestamp.matches(timestamp)
+            && self
+                .filter_hash
+                .map_or(true, |expected| expected.as_bytes() == hash)
+    }
 }
 
 /// Sort direction
@@ -353,6 +421,8 @@ pub enum SortBy {
     /// Sort by author, then key.
     #[default]
     AuthorKey,
+    /// Sort by timestamp, then author, then key.
+    Timestamp,
 }
 
 /// Key matching.
@@ -365,6 +435,8 @@ pub enum KeyFilter {
     Exact(Bytes),
     /// All keys that start with the provided value.
     Prefix(Bytes),
+    /// All keys from the first value (inclusive) to the second value (exclusive).
+    Range(Bytes, Bytes),
 }
 
 impl<T: AsRef<[u8]>> From<T> for KeyFilter {
@@ -380,10 +452,28 @@ impl KeyFilter {
             Self::Any => true,
             Self::Exact(k) => &k[..] == key,
             Self::Prefix(p) => key.starts_with(p),
+            Self::Range(start, end) => &start[..] <= key && key < &end[..],
         }
     }
 }
 
+/// Timestamp matching.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, Eq, PartialEq)]
+pub struct TimestampFilter {
+    /// Only timestamps equal to or after this value.
+    pub since: Option<u64>,
+    /// Only timestamps before this value.
+    pub before: Option<u64>,
+}
+
+impl TimestampFilter {
+    /// Test if a timestamp is matched by this [`TimestampFilter`].
+    pub fn matches(&self, timestamp: u64) -> bool {
+        self.since.map_or(true, |since| timestamp >= since)
+            && self.before.map_or(true, |before| timestamp < before)
+    }
+}
+
 /// Author matching.
 #[derive(Debug, Serialize, Deserialize, Clone, Default, Eq, PartialEq)]
 pub enum AuthorFilter {
@@ -7,6 +7,7 @@ use std::{
     num::NonZeroU64,
     ops::Bound,
     path::Path,
+    time::Duration,
 };
 
 use anyhow::{anyhow, Result};
@@ -25,8 +26,8 @@ use crate::{
 };
 
 use super::{
-    pubkeys::MemPublicKeyStore, DownloadPolicy, ImportNamespaceOutcome, OpenError, PublicKeyStore,
-    Query,
+    pubkeys::MemPublicKeyStore, CompactOutcome, DownloadPolicy, ImportNamespaceOutcome, OpenError,
+    PublicKeyStore, Query,
 };
 
 mod bounds;
@@ -37,14 +38,15 @@ mod ranges;
 pub(crate) mod tables;
 
 use self::{
-    bounds::{ByKeyBounds, RecordsBounds},
+    bounds::{ByHashBounds, ByKeyBounds, ByTimestampBounds, RecordsBounds},
     ranges::RangeExt,
     tables::{RecordsTable, TransactionAndTables},
 };
 use self::{
     query::QueryIterator,
     tables::{
-        LatestPerAuthorKey, LatestPerAuthorValue, ReadOnlyTables, RecordsId, RecordsValue, Tables,
+        LatestPerAuthorKey, LatestPerAuthorValue, PrunedValue, ReadOnlyTables, RecordsId,
+        RecordsValue, Tables,
     },
 };
 
@@ -374,8 +376,16 @@ impl Store {
     }
 
     /// Import a new replica namespace.
+    ///
+    /// For a [`Capability::Delegated`], the namespace is imported as read only, and the grant is
+    /// inserted into the replica.
     pub fn import_namespace(&mut self, capability: Capability) -> Result<ImportNamespaceOutcome> {
-        self.modify(|tables| {
+        let grant = match &capability {
+            Capability::Delegated(grant) => Some(grant.entry().clone()),
+            _ => None,
+        };
+        let namespace = capability.id();
+        let outcome = self.modify(|tables| {
             let outcome = {
                 let (capability, outcome) = {
                     let existing = tables.namespaces.get(capability.id().as_bytes())?;
@@ -397,7 +407,13 @@ impl Store {
                 outcome
             };
             Ok(outcome)
-        })
+        })?;
+        if let Some(grant) = grant {
+            // the grant is verified on deserialization, revocations are applied when reading
+            let mut instance = StoreInstance::new(namespace, self);
+            crate::ranger::Store::put(&mut instance, grant)?;
+        }
+        Ok(outcome)
     }
 
     /// Remove a replica.
@@ -418,9 +434,22 @@ impl Store {
             let _ = tables
                 .records_by_key
                 .retain_in(bounds.as_ref(), |_k, _v| false);
+            let bounds = ByTimestampBounds::namespace(*namespace);
+            tables
+                .records_by_timestamp
+                .retain_in(bounds.as_ref(), |_k, _v| false)?;
+            let bounds = ByHashBounds::namespace(*namespace);
+            tables
+                .records_by_hash
+                .retain_in(bounds.as_ref(), |_k, _v| false)?;
+            tables
+                .records_by_expiry
+                .retain(|(_expires, ns, _author, _key), _v| ns != namespace.as_bytes())?;
             tables.namespaces.remove(namespace.as_bytes())?;
             tables.namespace_peers.remove_all(namespace.as_bytes())?;
             tables.download_policy.remove(namespace.as_bytes())?;
+            let bounds = RecordsBounds::namespace(*namespace);
+            tables.pruned.retain_in(bounds.as_ref(), |_k, _v| false)?;
             Ok(())
         })
     }
@@ -436,6 +465,8 @@ impl Store {
     }
 
     /// Get an entry by key and author.
+    ///
+    /// Expired entries are not returned.
     pub fn get_exact(
         &mut self,
         namespace: NamespaceId,
@@ -443,13 +474,48 @@ impl Store {
         key: impl AsRef<[u8]>,
         include_empty: bool,
     ) -> Result<Option<SignedEntry>> {
-        get_exact(
+        let now = crate::sync::system_time_now();
+        let entry = get_exact(
             &self.tables()?.records,
             namespace,
             author,
             key,
             include_empty,
-        )
+        )?;
+        Ok(entry.filter(|entry| !entry.is_expired(now)))
+    }
+
+    /// Get the heads of a key: the entry of every author for the key, newest first.
+    ///
+    /// Each author has at most one entry per key, so the heads are the concurrent values of
+    /// the key that were not overwritten. Empty entries, which mark deletions, are included.
+    /// The first head is the entry selected by [`Query::single_latest_per_key`].
+    pub fn get_heads(
+        &mut self,
+        namespace: NamespaceId,
+        key: impl AsRef<[u8]>,
+    ) -> Result<Vec<SignedEntry>> {
+        let query = Query::key_exact(key).include_empty();
+        let mut heads = self
+            .get_many(namespace, query)?
+            .collect::<Result<Vec<_>>>()?;
+        // the sort is stable, so on equal timestamps the first author wins, like in
+        // `LatestPerKeySelector`
+        heads.sort_by_key(|entry| std::cmp::Reverse(entry.timestamp()));
+        Ok(heads)
+    }
+
+    /// Get the highest timestamp of all entries of a namespace.
+    pub fn get_latest_timestamp(&mut self, namespace: &NamespaceId) -> Result<Option<u64>> {
+        let tables = self.tables()?;
+        let bounds = ByTimestampBounds::namespace(*namespace);
+        let latest = tables
+            .records_by_timestamp
+            .range(bounds.as_ref())?
+            .next_back()
+            .transpose()?
+            .map(|(id, _)| id.value().1);
+        Ok(latest)
     }
 
     /// Get all content hashes of all replicas in the store.
@@ -595,6 +661,109 @@ impl Store {
             Some(value) => postcard::from_bytes(value.value())?,
         })
     }
+
+    /// Prune deletion markers of a namespace that are older than `max_age`.
+    ///
+    /// Deleting a key or a prefix inserts an empty entry (deletion marker) that removes the
+    /// entries of the same author it supersedes, and stays in the document so that these
+    /// entries are not synced back from peers that still hold them. Earlier entries of an author
+    /// for the same key are replaced when inserting, and entries of other authors are never
+    /// superseded, so the deletion markers are the only records that pile up. This removes the
+    /// deletion markers that are older than the horizon and do not expire on their own.
+    ///
+    /// The removed markers are still remembered with their signatures, but are not part of the
+    /// set of entries anymore. Entries that a removed marker supersedes are not inserted, neither
+    /// locally nor during sync. A peer that sends such an entry during sync is answered with the
+    /// marker, which it then applies without storing it either, see
+    /// [`crate::ranger::Store::put_compacted`], so that the sets of both peers are equal again
+    /// and later syncs do not exchange these entries. Markers that are superseded by newer
+    /// entries or newer pruned markers of the same author are forgotten.
+    pub fn compact(
+        &mut self,
+        namespace: &NamespaceId,
+        max_age: Duration,
+    ) -> Result<CompactOutcome> {
+        let now = crate::sync::system_time_now();
+        let horizon = now.saturating_sub(max_age.as_micros().try_into().unwrap_or(u64::MAX));
+        self.modify(|tables| {
+            // ensure the document exists
+            anyhow::ensure!(
+                tables.namespaces.get(namespace.as_bytes())?.is_some(),
+                "document not created"
+            );
+
+            let bounds = RecordsBounds::namespace(*namespace);
+            let mut tombstones = Vec::new();
+            for item in tables.records.range(bounds.as_ref())? {
+                let (id, value) = item?;
+                let (timestamp, _namespace_sig, _author_sig, len, _hash, expires) = value.value();
+                if len == 0 && expires.is_none() && timestamp < horizon {
+                    tombstones.push(into_entry(id.value(), value.value()));
+                }
+            }
+
+            let outcome = CompactOutcome {
+                horizon,
+                tombstones: tombstones.len(),
+            };
+            for entry in tombstones {
+                let (namespace, author, key) = entry.id().as_byte_tuple();
+                let id = (namespace, author, key);
+                tables.records.remove(id)?;
+                remove_from_indexes(tables, id, entry.timestamp(), Hash::EMPTY.as_bytes(), None)?;
+                insert_pruned(tables, &entry)?;
+            }
+            Ok(outcome)
+        })
+    }
+
+    /// Get the deletion marker removed by [`Self::compact`] that supersedes `entry`, if any.
+    fn get_pruned(&mut self, entry: &SignedEntry) -> Result<Option<SignedEntry>> {
+        let tables = self.tables()?;
+        let (namespace, author, key) = entry.id().as_byte_tuple();
+        for len in 0..=key.len() {
+            let id = (namespace, author, &key[..len]);
+            if let Some(pruned) = tables.pruned.get(id)? {
+                let (timestamp, _namespace_sig, _author_sig) = pruned.value();
+                if *entry.record() <= Record::empty(timestamp) {
+                    return Ok(Some(into_pruned_entry(id, pruned.value())));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Remove all records whose expiry timestamp has passed, in all namespaces.
+    ///
+    /// Expired records are already hidden from queries and set reconciliation, this frees the
+    /// space they take up in the store. Returns the number of removed records.
+    pub fn remove_expired(&mut self) -> Result<usize> {
+        let now = crate::sync::system_time_now();
+        self.modify(|tables| {
+            let expired = tables
+                .records_by_expiry
+                .range(..(now + 1, &[u8::MIN; 32], &[u8::MIN; 32], &[][..]))?
+                .map(|item| {
+                    let (id, _) = item?;
+                    let (_expires, namespace, author, key) = id.value();
+                    anyhow::Ok((*namespace, *author, key.to_vec()))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let mut removed = 0;
+            for (namespace, author, key) in expired {
+                let id = (&namespace, &author, &key[..]);
+                let Some(value) = tables.records.remove(id)? else {
+                    continue;
+                };
+                let (timestamp, _namespace_sig, _author_sig, _len, hash, expires) = value.value();
+                let hash = *hash;
+                drop(value);
+                remove_from_indexes(tables, id, timestamp, &hash, expires)?;
+                removed += 1;
+            }
+            Ok(removed)
+        })
+    }
 }
 
 impl PublicKeyStore for Store {
@@ -640,6 +809,13 @@ impl<'a> PublicKeyStore for StoreInstance<'a> {
     }
 }
 
+impl<'a> StoreInstance<'a> {
+    /// Get the highest timestamp of all entries of this namespace.
+    pub(crate) fn get_latest_timestamp(&mut self) -> Result<Option<u64>> {
+        self.store.get_latest_timestamp(&self.namespace)
+    }
+}
+
 impl<'a> super::DownloadPolicyStore for StoreInstance<'a> {
     fn get_download_policy(&mut self, namespace: &NamespaceId) -> Result<DownloadPolicy> {
         self.store.get_download_policy(namespace)
@@ -716,16 +892,19 @@ impl<'a> crate::ranger::Store<SignedEntry> for StoreInstance<'a> {
                 &e.signature().author().to_bytes(),
                 e.content_len(),
                 hash.as_bytes(),
+                e.expires(),
             );
-            tables.records.insert(key, value)?;
+            let previous = tables.records.insert(key, value)?.map(|previous| {
+                let (timestamp, _namespace_sig, _author_sig, _len, hash, expires) =
+                    previous.value();
+                (timestamp, *hash, expires)
+            });
 
-            // insert into by key index table
-            let key = (
-                &id.namespace().to_bytes(),
-                id.key(),
-                &id.author().to_bytes(),
-            );
-            tables.records_by_key.insert(key, ())?;
+            // update the index tables
+            if let Some((timestamp, hash, expires)) = previous {
+                remove_from_indexes(tables, key, timestamp, &hash, expires)?;
+            }
+            insert_into_indexes(tables, key, e.timestamp(), hash.as_bytes(), e.expires())?;
 
             // insert into latest table
             let key = (&e.id().namespace().to_bytes(), &e.id().author().to_bytes());
@@ -736,13 +915,15 @@ impl<'a> crate::ranger::Store<SignedEntry> for StoreInstance<'a> {
     }
 
     fn get_range(&mut self, range: Range<RecordIdentifier>) -> Result<Self::RangeIterator<'_>> {
+        // expired entries are excluded from set reconciliation
+        let now = crate::sync::system_time_now();
         let tables = self.store.as_mut().tables()?;
         let iter = match range.x().cmp(range.y()) {
             // identity range: iter1 = all, iter2 = none
             Ordering::Equal => {
                 // iterator for all entries in replica
                 let bounds = RecordsBounds::namespace(self.namespace);
-                let iter = RecordsRange::with_bounds(&tables.records, bounds)?;
+                let iter = RecordsRange::with_bounds(&tables.records, bounds)?.skip_expired(now);
                 chain_none(iter)
             }
             // regular range: iter1 = x <= t < y, iter2 = none
@@ -751,7 +932,7 @@ impl<'a> crate::ranger::Store<SignedEntry> for StoreInstance<'a> {
                 let start = Bound::Included(range.x().to_byte_tuple());
                 let end = Bound::Excluded(range.y().to_byte_tuple());
                 let bounds = RecordsBounds::new(start, end);
-                let iter = RecordsRange::with_bounds(&tables.records, bounds)?;
+                let iter = RecordsRange::with_bounds(&tables.records, bounds)?.skip_expired(now);
                 chain_none(iter)
             }
             // split range: iter1 = start <= t < y, iter2 = x <= t <= end
@@ -759,12 +940,12 @@ impl<'a> crate::ranger::Store<SignedEntry> for StoreInstance<'a> {
                 // iterator for entries from start to range.y
                 let end = Bound::Excluded(range.y().to_byte_tuple());
                 let bounds = RecordsBounds::from_start(&self.namespace, end);
-                let iter = RecordsRange::with_bounds(&tables.records, bounds)?;
+                let iter = RecordsRange::with_bounds(&tables.records, bounds)?.skip_expired(now);
 
                 // iterator for entries from range.x to end
                 let start = Bound::Included(range.x().to_byte_tuple());
                 let bounds = RecordsBounds::to_end(&self.namespace, start);
-                let iter2 = RecordsRange::with_bounds(&tables.records, bounds)?;
+                let iter2 = RecordsRange::with_bounds(&tables.records, bounds)?.skip_expired(now);
 
                 iter.chain(Some(iter2).into_iter().flatten())
             }
@@ -774,22 +955,30 @@ impl<'a> crate::ranger::Store<SignedEntry> for StoreInstance<'a> {
 
     fn entry_remove(&mut self, id: &RecordIdentifier) -> Result<Option<SignedEntry>> {
         self.store.as_mut().modify(|tables| {
-            let entry = {
-                let (namespace, author, key) = id.as_byte_tuple();
-                let id = (namespace, key, author);
-                tables.records_by_key.remove(id)?;
-                let id = (namespace, author, key);
-                let value = tables.records.remove(id)?;
-                value.map(|value| into_entry(id, value.value()))
-            };
+            let (namespace, author, key) = id.as_byte_tuple();
+            let id = (namespace, author, key);
+            let entry = tables
+                .records
+                .remove(id)?
+                .map(|value| into_entry(id, value.value()));
+            if let Some(entry) = &entry {
+                remove_from_indexes(
+                    tables,
+                    id,
+                    entry.timestamp(),
+                    entry.content_hash().as_bytes(),
+                    entry.expires(),
+                )?;
+            }
             Ok(entry)
         })
     }
 
     fn all(&mut self) -> Result<Self::RangeIterator<'_>> {
+        let now = crate::sync::system_time_now();
         let tables = self.store.as_mut().tables()?;
         let bounds = RecordsBounds::namespace(self.namespace);
-        let iter = RecordsRange::with_bounds(&tables.records, bounds)?;
+        let iter = RecordsRange::with_bounds(&tables.records, bounds)?.skip_expired(now);
         Ok(chain_none(iter))
     }
 
@@ -816,16 +1005,124 @@ impl<'a> crate::ranger::Store<SignedEntry> for StoreInstance<'a> {
         let bounds = RecordsBounds::author_prefix(id.namespace(), id.author(), id.key_bytes());
         self.store.as_mut().modify(|tables| {
             let cb = |_k: RecordsId, v: RecordsValue| {
-                let (timestamp, _namespace_sig, _author_sig, len, hash) = v;
-                let record = Record::new(hash.into(), len, timestamp);
+                let (timestamp, _namespace_sig, _author_sig, len, hash, expires) = v;
+                let record = Record::new(hash.into(), len, timestamp).with_expires(expires);
 
                 predicate(&record)
             };
-            let iter = tables.records.extract_from_if(bounds.as_ref(), cb)?;
-            let count = iter.count();
-            Ok(count)
+            let removed = tables
+                .records
+                .extract_from_if(bounds.as_ref(), cb)?
+                .map(|item| {
+                    let (id, value) = item?;
+                    let (namespace, author, key) = id.value();
+                    let (timestamp, _namespace_sig, _author_sig, _len, hash, expires) =
+                        value.value();
+                    anyhow::Ok((*namespace, *author, key.to_vec(), timestamp, *hash, expires))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            for (namespace, author, key, timestamp, hash, expires) in &removed {
+                let id = (namespace, author, &key[..]);
+                remove_from_indexes(tables, id, *timestamp, hash, *expires)?;
+            }
+            // pruned deletion markers that are superseded are not needed anymore
+            tables.pruned.retain_in(
+                bounds.as_ref(),
+                |_k, (timestamp, _namespace_sig, _author_sig)| {
+                    !predicate(&Record::empty(timestamp))
+                },
+            )?;
+            Ok(removed.len())
         })
     }
+
+    fn get_compacted(&mut self, entry: &SignedEntry) -> Result<Option<SignedEntry>> {
+        self.store.as_mut().get_pruned(entry)
+    }
+
+    fn entry_put_compacted(&mut self, entry: SignedEntry) -> Result<()> {
+        self.store
+            .as_mut()
+            .modify(|tables| insert_pruned(tables, &entry))
+    }
+}
+
+/// Remember a deletion marker that was pruned, and forget the pruned markers it supersedes.
+fn insert_pruned(tables: &mut Tables, entry: &SignedEntry) -> Result<()> {
+    let id = entry.id();
+    let bounds = RecordsBounds::author_prefix(id.namespace(), id.author(), id.key_bytes());
+    tables.pruned.retain_in(
+        bounds.as_ref(),
+        |_k, (timestamp, _namespace_sig, _author_sig)| Record::empty(timestamp) > *entry.record(),
+    )?;
+    let (namespace, author, key) = id.as_byte_tuple();
+    let value = (
+        entry.timestamp(),
+        &entry.signature().namespace().to_bytes(),
+        &entry.signature().author().to_bytes(),
+    );
+    tables.pruned.insert((namespace, author, key), value)?;
+    Ok(())
+}
+
+/// Convert a row of the pruned table to the deletion marker it was pruned from.
+fn into_pruned_entry(id: RecordsId, value: PrunedValue) -> SignedEntry {
+    let (timestamp, namespace_sig, author_sig) = value;
+    let value = (
+        timestamp,
+        namespace_sig,
+        author_sig,
+        0,
+        Hash::EMPTY.as_bytes(),
+        None,
+    );
+    into_entry(id, value)
+}
+
+/// Insert the rows of the by-key, by-timestamp, by-hash and by-expiry indexes for a record.
+fn insert_into_indexes(
+    tables: &mut Tables,
+    (namespace, author, key): RecordsId,
+    timestamp: u64,
+    hash: &[u8; 32],
+    expires: Option<u64>,
+) -> Result<()> {
+    tables.records_by_key.insert((namespace, key, author), ())?;
+    tables
+        .records_by_timestamp
+        .insert((namespace, timestamp, author, key), ())?;
+    tables
+        .records_by_hash
+        .insert((namespace, hash, author, key), ())?;
+    if let Some(expires) = expires {
+        tables
+            .records_by_expiry
+            .insert((expires, namespace, author, key), ())?;
+    }
+    Ok(())
+}
+
+/// Remove the rows of the by-key, by-timestamp, by-hash and by-expiry indexes for a record.
+fn remove_from_indexes(
+    tables: &mut Tables,
+    (namespace, author, key): RecordsId,
+    timestamp: u64,
+    hash: &[u8; 32],
+    expires: Option<u64>,
+) -> Result<()> {
+    tables.records_by_key.remove((namespace, key, author))?;
+    tables
+        .records_by_timestamp
+        .remove((namespace, timestamp, author, key))?;
+    tables
+        .records_by_hash
+        .remove((namespace, hash, author, key))?;
+    if let Some(expires) = expires {
+        tables
+            .records_by_expiry
+            .remove((expires, namespace, author, key))?;
+    }
+    Ok(())
 }
 
 fn chain_none<'a, I: Iterator<Item = T> + 'a, T>(
@@ -950,9 +1247,9 @@ impl<'a> Iterator for LatestIterator<'a> {
 
 fn into_entry(key: RecordsId, value: RecordsValue) -> SignedEntry {
     let (namespace, author, key) = key;
-    let (timestamp, namespace_sig, author_sig, len, hash) = value;
+    let (timestamp, namespace_sig, author_sig, len, hash, expires) = value;
     let id = RecordIdentifier::new(namespace, author, key);
-    let record = Record::new(hash.into(), len, timestamp);
+    let record = Record::new(hash.into(), len, timestamp).with_expires(expires);
     let entry = Entry::new(id, record);
     let entry_signature = EntrySignature::from_parts(namespace_sig, author_sig);
     SignedEntry::new(entry_signature, entry)
@@ -960,7 +1257,10 @@ fn into_entry(key: RecordsId, value: RecordsValue) -> SignedEntry {
 
 #[cfg(test)]
 mod tests {
-    use super::tables::LATEST_PER_AUTHOR_TABLE;
+    use super::tables::{
+        LATEST_PER_AUTHOR_TABLE, RECORDS_BY_HASH_TABLE, RECORDS_BY_TIMESTAMP_TABLE, RECORDS_TABLE,
+        RECORDS_TABLE_V1,
+    };
 
     use crate::ranger::Store as _;
 
@@ -993,6 +1293,43 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_compact_collapses_pruned() -> Result<()> {
+        let dbfile = tempfile::NamedTempFile::new()?;
+        let mut store = Store::persistent(dbfile.path())?;
+
+        let author = store.new_author(&mut rand::thread_rng())?;
+        let namespace = NamespaceSecret::new(&mut rand::thread_rng());
+        let mut replica = store.new_replica(namespace.clone())?;
+        replica.hash_and_insert("a/b", &author, b"v")?;
+        replica.delete_prefix("a/b", &author)?;
+        drop(replica);
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(
+            store.compact(&namespace.id(), Duration::ZERO)?.tombstones,
+            1
+        );
+        assert_eq!(store.tables()?.pruned.len()?, 1);
+
+        // a newer deletion marker for a prefix replaces the pruned markers below it
+        let mut replica = store.open_replica(&namespace.id())?;
+        replica.delete_prefix("a/", &author)?;
+        drop(replica);
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(
+            store.compact(&namespace.id(), Duration::ZERO)?.tombstones,
+            1
+        );
+        assert_eq!(store.tables()?.pruned.len()?, 1);
+
+        // a newer entry replaces the pruned markers it supersedes
+        let mut replica = store.open_replica(&namespace.id())?;
+        replica.hash_and_insert("a", &author, b"v")?;
+        drop(replica);
+        assert_eq!(store.tables()?.pruned.len()?, 0);
+        Ok(())
+    }
+
     #[test]
     fn test_basics() -> Result<()> {
         let dbfile = tempfile::NamedTempFile::new()?;
@@ -1070,6 +1407,120 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_migration_005_populate_by_timestamp_and_by_hash_index() -> Result<()> {
+        let dbfile = tempfile::NamedTempFile::new()?;
+        let namespace = NamespaceSecret::new(&mut rand::thread_rng());
+
+        // create a store and add some data
+        let (since, hash) = {
+            let mut store = Store::persistent(dbfile.path())?;
+            let author1 = store.new_author(&mut rand::thread_rng())?;
+            let author2 = store.new_author(&mut rand::thread_rng())?;
+            let mut replica = store.new_replica(namespace.clone())?;
+            replica.hash_and_insert(b"k1", &author1, b"v1")?;
+            let since = crate::sync::system_time_now();
+            let hash = replica.hash_and_insert(b"k2", &author2, b"v2")?;
+            replica.hash_and_insert(b"k3", &author1, b"v2")?;
+
+            // drop everything to clear file locks.
+            store.close_replica(namespace.id());
+            // flush the store to disk
+            store.flush()?;
+            drop(store);
+            (since, hash)
+        };
+
+        // create a copy of our db file with the new index tables deleted.
+        let dbfile_before_migration = copy_and_modify(dbfile.path(), |tx| {
+            tx.delete_table(RECORDS_BY_TIMESTAMP_TABLE)?;
+            tx.delete_table(RECORDS_BY_HASH_TABLE)?;
+            Ok(())
+        })?;
+
+        // open the copied db file, which will run the migration.
+        let mut store = Store::persistent(dbfile_before_migration.path())?;
+        let keys = |store: &mut Store, query: Query| -> Result<Vec<Vec<u8>>> {
+            store
+                .get_many(namespace.id(), query)?
+                .map(|entry| entry.map(|entry| entry.key().to_vec()))
+                .collect()
+        };
+        assert_eq!(
+            keys(&mut store, Query::updated_since(since).build())?,
+            vec![b"k2".to_vec(), b"k3".to_vec()]
+        );
+        let mut by_hash = keys(&mut store, Query::content_hash(hash).build())?;
+        by_hash.sort();
+        assert_eq!(by_hash, vec![b"k2".to_vec(), b"k3".to_vec()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_migration_006_records_populate_v2() -> Result<()> {
+        let dbfile = tempfile::NamedTempFile::new()?;
+        let namespace = NamespaceSecret::new(&mut rand::thread_rng());
+
+        // create a store and add some data
+        let expected = {
+            let mut store = Store::persistent(dbfile.path())?;
+            let author = store.new_author(&mut rand::thread_rng())?;
+            let mut replica = store.new_replica(namespace.clone())?;
+            replica.hash_and_insert(b"k1", &author, b"v1")?;
+            replica.hash_and_insert(b"k2", &author, b"v2")?;
+            let expected = store
+                .get_many(namespace.id(), Query::all())?
+                .collect::<Result<Vec<_>>>()?;
+
+            // drop everything to clear file locks.
+            store.close_replica(namespace.id());
+            // flush the store to disk
+            store.flush()?;
+            drop(store);
+            expected
+        };
+
+        // create a copy of our db file with the records moved back to the v1 table, and the
+        // indexes deleted.
+        let dbfile_before_migration = copy_and_modify(dbfile.path(), |tx| {
+            {
+                let records = tx.open_table(RECORDS_TABLE)?;
+                let mut records_v1 = tx.open_table(RECORDS_TABLE_V1)?;
+                for item in records.iter()? {
+                    let (id, value) = item?;
+                    let (timestamp, namespace_sig, author_sig, len, hash, _expires) = value.value();
+                    records_v1.insert(
+                        id.value(),
+                        (timestamp, namespace_sig, author_sig, len, hash),
+                    )?;
+                }
+            }
+            tx.delete_table(RECORDS_TABLE)?;
+            tx.delete_table(RECORDS_BY_TIMESTAMP_TABLE)?;
+            tx.delete_table(RECORDS_BY_HASH_TABLE)?;
+            Ok(())
+        })?;
+
+        // open the copied db file, which will run the migration.
+        let mut store = Store::persistent(dbfile_before_migration.path())?;
+        let actual = store
+            .get_many(namespace.id(), Query::all())?
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(expected, actual);
+        let since = expected[0].timestamp();
+        assert_eq!(
+            store
+                .get_many(namespace.id(), Query::updated_since(since).build())?
+                .count(),
+            2
+        );
+        let tables = store.tables()?;
+        assert_eq!(tables.records.len()?, 2);
+
+        Ok(())
+    }
+
     fn copy_and_modify(
         source: &Path,
         modify: impl Fn(&redb::WriteTransaction) -> Result<()>,
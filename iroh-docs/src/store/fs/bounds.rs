@@ -2,9 +2,17 @@ use std::ops::{Bound, RangeBounds};
 
 use bytes::Bytes;
 
-use crate::{store::KeyFilter, AuthorId, NamespaceId};
+use iroh_base::hash::Hash;
 
-use super::tables::{RecordsByKeyId, RecordsByKeyIdOwned, RecordsId, RecordsIdOwned};
+use crate::{
+    store::{KeyFilter, TimestampFilter},
+    AuthorId, NamespaceId,
+};
+
+use super::tables::{
+    RecordsByHashId, RecordsByHashIdOwned, RecordsByKeyId, RecordsByKeyIdOwned,
+    RecordsByTimestampId, RecordsByTimestampIdOwned, RecordsId, RecordsIdOwned,
+};
 
 /// Bounds on the records table.
 ///
@@ -17,11 +25,20 @@ impl RecordsBounds {
     }
 
     pub fn author_key(ns: NamespaceId, author: AuthorId, key_matcher: KeyFilter) -> Self {
+        if let KeyFilter::Range(start, end) = key_matcher {
+            let (ns, author) = (ns.to_bytes(), author.to_bytes());
+            let end = end.max(start.clone());
+            return Self(
+                Bound::Included((ns, author, start)),
+                Bound::Excluded((ns, author, end)),
+            );
+        }
         let key_is_exact = matches!(key_matcher, KeyFilter::Exact(_));
         let key = match key_matcher {
             KeyFilter::Any => Bytes::new(),
             KeyFilter::Exact(key) => key,
             KeyFilter::Prefix(prefix) => prefix,
+            KeyFilter::Range(..) => unreachable!("handled above"),
         };
         let author = author.to_bytes();
         let ns = ns.to_bytes();
@@ -126,6 +143,12 @@ impl ByKeyBounds {
                 };
                 Self(start, end)
             }
+            KeyFilter::Range(start, end) => {
+                let end = end.max(start);
+                let start = (ns.to_bytes(), start.clone(), [0u8; 32]);
+                let end = (ns.to_bytes(), end.clone(), [0u8; 32]);
+                Self(Bound::Included(start), Bound::Excluded(end))
+            }
         }
     }
 
@@ -158,6 +181,92 @@ impl RangeBounds<RecordsByKeyIdOwned> for ByKeyBounds {
     }
 }
 
+/// Bounds for the by-timestamp index table.
+///
+/// Supports bounds by timestamp.
+pub struct ByTimestampBounds(
+    Bound<RecordsByTimestampIdOwned>,
+    Bound<RecordsByTimestampIdOwned>,
+);
+
+impl ByTimestampBounds {
+    pub fn new(ns: NamespaceId, filter: &TimestampFilter) -> Self {
+        let since = filter.since.unwrap_or(0);
+        let start = Bound::Included((ns.to_bytes(), since, [0u8; 32], Bytes::new()));
+        let end = match filter.before {
+            Some(before) => {
+                Bound::Excluded((ns.to_bytes(), before.max(since), [0u8; 32], Bytes::new()))
+            }
+            None => Self::namespace_end(ns),
+        };
+        Self(start, end)
+    }
+
+    pub fn namespace(ns: NamespaceId) -> Self {
+        Self::new(ns, &TimestampFilter::default())
+    }
+
+    fn namespace_end(ns: NamespaceId) -> Bound<RecordsByTimestampIdOwned> {
+        let mut ns_end = ns.to_bytes();
+        if increment_by_one(&mut ns_end) {
+            Bound::Excluded((ns_end, 0, [0u8; 32], Bytes::new()))
+        } else {
+            Bound::Unbounded
+        }
+    }
+
+    pub fn as_ref(
+        &self,
+    ) -> (
+        Bound<RecordsByTimestampId<'_>>,
+        Bound<RecordsByTimestampId<'_>>,
+    ) {
+        fn map(id: &RecordsByTimestampIdOwned) -> RecordsByTimestampId<'_> {
+            (&id.0, id.1, &id.2, &id.3[..])
+        }
+        (map_bound(&self.0, map), map_bound(&self.1, map))
+    }
+}
+
+/// Bounds for the by-hash index table.
+///
+/// Supports bounds by content hash.
+pub struct ByHashBounds(Bound<RecordsByHashIdOwned>, Bound<RecordsByHashIdOwned>);
+
+impl ByHashBounds {
+    pub fn new(ns: NamespaceId, hash: Hash) -> Self {
+        let start = Bound::Included((ns.to_bytes(), *hash.as_bytes(), [0u8; 32], Bytes::new()));
+        let mut hash_end = *hash.as_bytes();
+        let mut ns_end = ns.to_bytes();
+        let end = if increment_by_one(&mut hash_end) {
+            Bound::Excluded((ns.to_bytes(), hash_end, [0u8; 32], Bytes::new()))
+        } else if increment_by_one(&mut ns_end) {
+            Bound::Excluded((ns_end, [0u8; 32], [0u8; 32], Bytes::new()))
+        } else {
+            Bound::Unbounded
+        };
+        Self(start, end)
+    }
+
+    pub fn namespace(ns: NamespaceId) -> Self {
+        let start = Bound::Included((ns.to_bytes(), [0u8; 32], [0u8; 32], Bytes::new()));
+        let mut ns_end = ns.to_bytes();
+        let end = if increment_by_one(&mut ns_end) {
+            Bound::Excluded((ns_end, [0u8; 32], [0u8; 32], Bytes::new()))
+        } else {
+            Bound::Unbounded
+        };
+        Self(start, end)
+    }
+
+    pub fn as_ref(&self) -> (Bound<RecordsByHashId<'_>>, Bound<RecordsByHashId<'_>>) {
+        fn map(id: &RecordsByHashIdOwned) -> RecordsByHashId<'_> {
+            (&id.0, &id.1, &id.2, &id.3[..])
+        }
+        (map_bound(&self.0, map), map_bound(&self.1, map))
+    }
+}
+
 /// Increment a byte string by one, by incrementing the last byte that is not 255 by one.
 ///
 /// Returns false if all bytes are 255.
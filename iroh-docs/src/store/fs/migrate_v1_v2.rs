@@ -70,7 +70,7 @@ pub fn run(source: impl AsRef<Path>) -> Result<redb::Database> {
         old::NAMESPACES_TABLE,
         new::tables::NAMESPACES_TABLE
     );
-    migrate_table!(rtx, wtx, old::RECORDS_TABLE, new::tables::RECORDS_TABLE);
+    migrate_table!(rtx, wtx, old::RECORDS_TABLE, new::tables::RECORDS_TABLE_V1);
     migrate_table!(
         rtx,
         wtx,
@@ -125,14 +125,14 @@ mod old {
     use crate::PeerIdBytes;
 
     use super::new::tables::{
-        LatestPerAuthorKey, LatestPerAuthorValue, Nanos, RecordsByKeyId, RecordsId, RecordsValue,
+        LatestPerAuthorKey, LatestPerAuthorValue, Nanos, RecordsByKeyId, RecordsId, RecordsValueV1,
     };
 
     pub const AUTHORS_TABLE: TableDefinition<&[u8; 32], &[u8; 32]> =
         TableDefinition::new("authors-1");
     pub const NAMESPACES_TABLE: TableDefinition<&[u8; 32], (u8, &[u8; 32])> =
         TableDefinition::new("namespaces-2");
-    pub const RECORDS_TABLE: TableDefinition<RecordsId, RecordsValue> =
+    pub const RECORDS_TABLE: TableDefinition<RecordsId, RecordsValueV1> =
         TableDefinition::new("records-1");
     pub const LATEST_PER_AUTHOR_TABLE: TableDefinition<LatestPerAuthorKey, LatestPerAuthorValue> =
         TableDefinition::new("latest-by-author-1");
@@ -7,8 +7,8 @@ use tracing::{debug, info};
 use crate::{Capability, NamespaceSecret};
 
 use super::tables::{
-    LATEST_PER_AUTHOR_TABLE, NAMESPACES_TABLE, NAMESPACES_TABLE_V1, RECORDS_BY_KEY_TABLE,
-    RECORDS_TABLE,
+    LATEST_PER_AUTHOR_TABLE, NAMESPACES_TABLE, NAMESPACES_TABLE_V1, RECORDS_BY_HASH_TABLE,
+    RECORDS_BY_KEY_TABLE, RECORDS_BY_TIMESTAMP_TABLE, RECORDS_TABLE, RECORDS_TABLE_V1,
 };
 
 /// Run all database migrations, if needed.
@@ -17,6 +17,8 @@ pub fn run_migrations(db: &Database) -> Result<()> {
     run_migration(db, migration_002_namespaces_populate_v2)?;
     run_migration(db, migration_003_namespaces_delete_v1)?;
     run_migration(db, migration_004_populate_by_key_index)?;
+    run_migration(db, migration_005_populate_by_timestamp_and_by_hash_index)?;
+    run_migration(db, migration_006_records_populate_v2)?;
     Ok(())
 }
 
@@ -58,7 +60,7 @@ fn migration_001_populate_latest_table(tx: &WriteTransaction) -> Result<MigrateO
     for next in iter {
         let next = next?;
         let (namespace, author, key) = next.0.value();
-        let (timestamp, _namespace_sig, _author_sig, _len, _hash) = next.1.value();
+        let (timestamp, _namespace_sig, _author_sig, _len, _hash, _expires) = next.1.value();
         heads
             .entry((*namespace, *author))
             .and_modify(|e| {
@@ -137,3 +139,57 @@ fn migration_004_populate_by_key_index(tx: &WriteTransaction) -> Result<MigrateO
     }
     Ok(MigrateOutcome::Execute(len))
 }
+
+/// migration 005: populate the by_timestamp and by_hash index tables (which did not exist before)
+fn migration_005_populate_by_timestamp_and_by_hash_index(
+    tx: &WriteTransaction,
+) -> Result<MigrateOutcome> {
+    let mut by_timestamp_table = tx.open_table(RECORDS_BY_TIMESTAMP_TABLE)?;
+    let mut by_hash_table = tx.open_table(RECORDS_BY_HASH_TABLE)?;
+    let records_table = tx.open_table(RECORDS_TABLE)?;
+    if !by_timestamp_table.is_empty()? || !by_hash_table.is_empty()? {
+        return Ok(MigrateOutcome::Skip);
+    }
+
+    let iter = records_table.iter()?;
+    let mut len = 0;
+    for next in iter {
+        let next = next?;
+        let (namespace, author, key) = next.0.value();
+        let (timestamp, _namespace_sig, _author_sig, _len, hash, _expires) = next.1.value();
+        by_timestamp_table.insert((namespace, timestamp, author, key), ())?;
+        by_hash_table.insert((namespace, hash, author, key), ())?;
+        len += 1;
+    }
+    Ok(MigrateOutcome::Execute(len))
+}
+
+/// Copy the records from V1 to V2, which adds the expiry to the record values.
+///
+/// The migrations that populate the index tables ran against the (empty) V2 table before, so
+/// they are run again on the copied records. The V1 table is deleted afterwards.
+fn migration_006_records_populate_v2(tx: &WriteTransaction) -> Result<MigrateOutcome> {
+    let records_v1_exists = tx
+        .list_tables()?
+        .any(|handle| handle.name() == RECORDS_TABLE_V1.name());
+    if !records_v1_exists {
+        return Ok(MigrateOutcome::Skip);
+    }
+    let mut entries = 0;
+    {
+        let records_v1 = tx.open_table(RECORDS_TABLE_V1)?;
+        let mut records_v2 = tx.open_table(RECORDS_TABLE)?;
+        for res in records_v1.iter()? {
+            let (id, value) = res?;
+            let (timestamp, namespace_sig, author_sig, len, hash) = value.value();
+            let value = (timestamp, namespace_sig, author_sig, len, hash, None);
+            records_v2.insert(id.value(), value)?;
+            entries += 1;
+        }
+    }
+    migration_001_populate_latest_table(tx)?;
+    migration_004_populate_by_key_index(tx)?;
+    migration_005_populate_by_timestamp_and_by_hash_index(tx)?;
+    tx.delete_table(RECORDS_TABLE_V1)?;
+    Ok(MigrateOutcome::Execute(entries))
+}
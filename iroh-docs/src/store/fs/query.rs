@@ -7,20 +7,25 @@ use crate::{
         util::{IndexKind, LatestPerKeySelector, SelectorRes},
         AuthorFilter, KeyFilter, Query,
     },
+    sync::{is_expired, system_time_now},
     AuthorId, NamespaceId, SignedEntry,
 };
 
 use super::{
-    bounds::{ByKeyBounds, RecordsBounds},
-    ranges::{RecordsByKeyRange, RecordsRange},
-    RecordsValue,
+    bounds::{ByHashBounds, ByKeyBounds, ByTimestampBounds, RecordsBounds},
+    ranges::{RecordsByKeyRange, RecordsIndexRange, RecordsRange},
+    tables::{RecordsByHashId, RecordsByTimestampId},
+    RecordsId, RecordsValue,
 };
 
 /// A query iterator for entry queries.
+///
+/// Entries that are expired at the time the iterator is created are skipped.
 #[derive(Debug)]
 pub struct QueryIterator {
     range: QueryRange,
     query: Query,
+    now: u64,
     offset: u64,
     count: u64,
 }
@@ -36,6 +41,12 @@ enum QueryRange {
         author_filter: AuthorFilter,
         selector: Option<LatestPerKeySelector>,
     },
+    Timestamp {
+        range: RecordsIndexRange<RecordsByTimestampId<'static>>,
+    },
+    Hash {
+        range: RecordsIndexRange<RecordsByHashId<'static>>,
+    },
 }
 
 impl QueryIterator {
@@ -74,11 +85,30 @@ impl QueryIterator {
                     selector,
                 }
             }
+            IndexKind::Timestamp => {
+                let bounds = ByTimestampBounds::new(namespace, &query.filter_timestamp);
+                let range = RecordsIndexRange::with_bounds(
+                    tables.records_by_timestamp,
+                    tables.records,
+                    bounds.as_ref(),
+                )?;
+                QueryRange::Timestamp { range }
+            }
+            IndexKind::Hash(hash) => {
+                let bounds = ByHashBounds::new(namespace, hash);
+                let range = RecordsIndexRange::with_bounds(
+                    tables.records_by_hash,
+                    tables.records,
+                    bounds.as_ref(),
+                )?;
+                QueryRange::Hash { range }
+            }
         };
 
         Ok(Self {
             range,
             query,
+            now: system_time_now(),
             offset: 0,
             count: 0,
         })
@@ -102,9 +132,22 @@ impl Iterator for QueryIterator {
                     range.next_filtered(&self.query.sort_direction, |(_ns, _author, key), value| {
                         key_filter.matches(key)
                             && (self.query.include_empty || !value_is_empty(&value))
+                            && self.query.matches_record(value.0, value.4)
+                            && !is_expired(value.5, self.now)
                     })
                 }
 
+                // the timestamp and hash ranges only cover one filter each, so all other filters
+                // are applied on each entry.
+                QueryRange::Timestamp { range } => range
+                    .next_filtered(&self.query.sort_direction, |id, value| {
+                        matches_all(&self.query, self.now, id, &value)
+                    }),
+                QueryRange::Hash { range } => range
+                    .next_filtered(&self.query.sort_direction, |id, value| {
+                        matches_all(&self.query, self.now, id, &value)
+                    }),
+
                 QueryRange::KeyAuthor {
                     range,
                     author_filter,
@@ -122,6 +165,12 @@ impl Iterator for QueryIterator {
                         Ok(next) => next,
                     };
 
+                    // skip expired entries before the selector, so that they do not shadow
+                    // older entries for the same key
+                    if matches!(&next, Some(e) if e.is_expired(self.now)) {
+                        continue;
+                    }
+
                     // push the entry into the selector. if active, only the latest entry
                     // for each key will be emitted.
                     let next = match selector {
@@ -138,6 +187,12 @@ impl Iterator for QueryIterator {
                         continue;
                     }
 
+                    // skip the entry if it does not match the timestamp and hash filters
+                    if matches!(&next, Some(e) if !self.query.matches_record(e.timestamp(), e.content_hash().as_bytes()))
+                    {
+                        continue;
+                    }
+
                     break next.map(Result::Ok);
                 },
             };
@@ -154,7 +209,20 @@ impl Iterator for QueryIterator {
     }
 }
 
+fn matches_all(
+    query: &Query,
+    now: u64,
+    (_ns, author, key): RecordsId,
+    value: &RecordsValue,
+) -> bool {
+    query.filter_author.matches(&AuthorId::from(author))
+        && query.filter_key.matches(key)
+        && (query.include_empty || !value_is_empty(value))
+        && query.matches_record(value.0, value.4)
+        && !is_expired(value.5, now)
+}
+
 fn value_is_empty(value: &RecordsValue) -> bool {
-    let (_timestamp, _namespace_sig, _author_sig, _len, hash) = value;
+    let (_timestamp, _namespace_sig, _author_sig, _len, hash, _expires) = value;
     *hash == Hash::EMPTY.as_bytes()
 }
@@ -1,13 +1,15 @@
 //! Ranges and helpers for working with [`redb`] tables
 
+use std::ops::Bound;
+
 use redb::{Key, Range, ReadOnlyTable, ReadableTable, Value};
 
-use crate::{store::SortDirection, SignedEntry};
+use crate::{store::SortDirection, sync::is_expired, SignedEntry};
 
 use super::{
     bounds::{ByKeyBounds, RecordsBounds},
     into_entry,
-    tables::{RecordsByKeyId, RecordsId, RecordsValue},
+    tables::{RecordsByHashId, RecordsByKeyId, RecordsByTimestampId, RecordsId, RecordsValue},
 };
 
 /// An extension trait for [`Range`] that provides methods for mapped retrieval.
@@ -72,7 +74,11 @@ impl<'a, K: Key + 'static, V: Value + 'static> RangeExt<K, V> for Range<'a, K, V
 /// An iterator over a range of entries from the records table.
 #[derive(derive_more::Debug)]
 #[debug("RecordsRange")]
-pub struct RecordsRange<'a>(Range<'a, RecordsId<'static>, RecordsValue<'static>>);
+pub struct RecordsRange<'a> {
+    range: Range<'a, RecordsId<'static>, RecordsValue<'static>>,
+    /// If set, entries that expired at this timestamp are skipped by the iterator.
+    expired_at: Option<u64>,
+}
 
 // pub type RecordsRange<'a> = Range<'a, RecordsId<'static>, RecordsValue<'static>>;
 
@@ -82,7 +88,20 @@ impl<'a> RecordsRange<'a> {
         bounds: RecordsBounds,
     ) -> anyhow::Result<Self> {
         let range = records.range(bounds.as_ref())?;
-        Ok(Self(range))
+        Ok(Self::new(range))
+    }
+
+    fn new(range: Range<'a, RecordsId<'static>, RecordsValue<'static>>) -> Self {
+        Self {
+            range,
+            expired_at: None,
+        }
+    }
+
+    /// Skip entries that are expired at `now` when iterating.
+    pub(super) fn skip_expired(mut self, now: u64) -> Self {
+        self.expired_at = Some(now);
+        self
     }
 
     //
@@ -94,7 +113,7 @@ impl<'a> RecordsRange<'a> {
         direction: &SortDirection,
         filter: impl for<'x> Fn(RecordsId<'x>, RecordsValue<'x>) -> bool,
     ) -> Option<anyhow::Result<SignedEntry>> {
-        self.0
+        self.range
             .next_filter_map(direction, |k, v| filter(k, v).then(|| into_entry(k, v)))
     }
 }
@@ -104,21 +123,26 @@ impl RecordsRange<'static> {
         records: &ReadOnlyTable<RecordsId<'static>, RecordsValue<'static>>,
     ) -> anyhow::Result<Self> {
         let range = records.range::<RecordsId<'static>>(..)?;
-        Ok(Self(range))
+        Ok(Self::new(range))
     }
     pub(super) fn with_bounds_static(
         records: &ReadOnlyTable<RecordsId<'static>, RecordsValue<'static>>,
         bounds: RecordsBounds,
     ) -> anyhow::Result<Self> {
         let range = records.range(bounds.as_ref())?;
-        Ok(Self(range))
+        Ok(Self::new(range))
     }
 }
 
 impl<'a> Iterator for RecordsRange<'a> {
     type Item = anyhow::Result<SignedEntry>;
     fn next(&mut self) -> Option<Self::Item> {
-        self.0.next_map(into_entry)
+        match self.expired_at {
+            None => self.range.next_map(into_entry),
+            Some(now) => self.range.next_filter_map(&SortDirection::Asc, |k, v| {
+                (!is_expired(v.5, now)).then(|| into_entry(k, v))
+            }),
+        }
     }
 }
 
@@ -165,3 +189,63 @@ impl RecordsByKeyRange {
         entry
     }
 }
+
+/// Key of an index table that refers to a row in the records table.
+pub trait RecordsIndexKey: Key + 'static {
+    /// Get the id in the records table from the index key.
+    fn records_id(key: Self::SelfType<'_>) -> RecordsId<'_>;
+}
+
+impl RecordsIndexKey for RecordsByTimestampId<'static> {
+    fn records_id((namespace, _timestamp, author, key): Self::SelfType<'_>) -> RecordsId<'_> {
+        (namespace, author, key)
+    }
+}
+
+impl RecordsIndexKey for RecordsByHashId<'static> {
+    fn records_id((namespace, _hash, author, key): Self::SelfType<'_>) -> RecordsId<'_> {
+        (namespace, author, key)
+    }
+}
+
+/// An iterator over a range of entries from an index table, which is resolved to the entries
+/// in the records table.
+#[derive(derive_more::Debug)]
+#[debug("RecordsIndexRange")]
+pub struct RecordsIndexRange<K: RecordsIndexKey> {
+    records_table: ReadOnlyTable<RecordsId<'static>, RecordsValue<'static>>,
+    index_range: Range<'static, K, ()>,
+}
+
+impl<K: RecordsIndexKey> RecordsIndexRange<K> {
+    pub fn with_bounds<'b>(
+        index_table: ReadOnlyTable<K, ()>,
+        records_table: ReadOnlyTable<RecordsId<'static>, RecordsValue<'static>>,
+        bounds: (Bound<K::SelfType<'b>>, Bound<K::SelfType<'b>>),
+    ) -> anyhow::Result<Self> {
+        let index_range = index_table.range(bounds)?;
+        Ok(Self {
+            records_table,
+            index_range,
+        })
+    }
+
+    /// Get the next item in the range.
+    ///
+    /// Omit items for which the `filter` function returns false.
+    pub fn next_filtered(
+        &mut self,
+        direction: &SortDirection,
+        filter: impl for<'x> Fn(RecordsId<'x>, RecordsValue<'x>) -> bool,
+    ) -> Option<anyhow::Result<SignedEntry>> {
+        self.index_range.next_try_filter_map(direction, |k, _v| {
+            let records_id = K::records_id(k);
+            let value = match self.records_table.get(&records_id) {
+                Ok(value) => value?,
+                Err(err) => return Some(Err(err.into())),
+            };
+            let value = value.value();
+            filter(records_id, value).then(|| Ok(into_entry(records_id, value)))
+        })
+    }
+}
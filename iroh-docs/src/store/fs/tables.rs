@@ -28,16 +28,32 @@ pub const NAMESPACES_TABLE_V1: TableDefinition<&[u8; 32], &[u8; 32]> =
 pub const NAMESPACES_TABLE: TableDefinition<&[u8; 32], (u8, &[u8; 32])> =
     TableDefinition::new("namespaces-2");
 
-/// Table: Records
+/// Table: Records v1 (replaced by Records v2 in migration 006)
 /// Key:   `([u8; 32], [u8; 32], &[u8])`
 ///      # (NamespaceId, AuthorId, Key)
 /// Value: `(u64, [u8; 32], [u8; 32], u64, [u8; 32])`
 ///      # (timestamp, signature_namespace, signature_author, len, hash)
-pub const RECORDS_TABLE: TableDefinition<RecordsId, RecordsValue> =
+pub const RECORDS_TABLE_V1: TableDefinition<RecordsId, RecordsValueV1> =
     TableDefinition::new("records-1");
+pub type RecordsValueV1<'a> = (u64, &'a [u8; 64], &'a [u8; 64], u64, &'a [u8; 32]);
+
+/// Table: Records v2
+/// Key:   `([u8; 32], [u8; 32], &[u8])`
+///      # (NamespaceId, AuthorId, Key)
+/// Value: `(u64, [u8; 32], [u8; 32], u64, [u8; 32], Option<u64>)`
+///      # (timestamp, signature_namespace, signature_author, len, hash, expires)
+pub const RECORDS_TABLE: TableDefinition<RecordsId, RecordsValue> =
+    TableDefinition::new("records-2");
 pub type RecordsId<'a> = (&'a [u8; 32], &'a [u8; 32], &'a [u8]);
 pub type RecordsIdOwned = ([u8; 32], [u8; 32], Bytes);
-pub type RecordsValue<'a> = (u64, &'a [u8; 64], &'a [u8; 64], u64, &'a [u8; 32]);
+pub type RecordsValue<'a> = (
+    u64,
+    &'a [u8; 64],
+    &'a [u8; 64],
+    u64,
+    &'a [u8; 32],
+    Option<u64>,
+);
 pub type RecordsTable = ReadOnlyTable<RecordsId<'static>, RecordsValue<'static>>;
 
 /// Table: Latest per author
@@ -56,6 +72,29 @@ pub const RECORDS_BY_KEY_TABLE: TableDefinition<RecordsByKeyId, ()> =
 pub type RecordsByKeyId<'a> = (&'a [u8; 32], &'a [u8], &'a [u8; 32]);
 pub type RecordsByKeyIdOwned = ([u8; 32], Bytes, [u8; 32]);
 
+/// Table: Records by timestamp
+/// Key:   `([u8; 32], u64, [u8; 32], Vec<u8>)` # (NamespaceId, Timestamp, AuthorId, Key)
+/// Value: `()`
+pub const RECORDS_BY_TIMESTAMP_TABLE: TableDefinition<RecordsByTimestampId, ()> =
+    TableDefinition::new("records-by-timestamp-1");
+pub type RecordsByTimestampId<'a> = (&'a [u8; 32], u64, &'a [u8; 32], &'a [u8]);
+pub type RecordsByTimestampIdOwned = ([u8; 32], u64, [u8; 32], Bytes);
+
+/// Table: Records by content hash
+/// Key:   `([u8; 32], [u8; 32], [u8; 32], Vec<u8>)` # (NamespaceId, Hash, AuthorId, Key)
+/// Value: `()`
+pub const RECORDS_BY_HASH_TABLE: TableDefinition<RecordsByHashId, ()> =
+    TableDefinition::new("records-by-hash-1");
+pub type RecordsByHashId<'a> = (&'a [u8; 32], &'a [u8; 32], &'a [u8; 32], &'a [u8]);
+pub type RecordsByHashIdOwned = ([u8; 32], [u8; 32], [u8; 32], Bytes);
+
+/// Table: Records by expiry
+/// Key:   `(u64, [u8; 32], [u8; 32], Vec<u8>)` # (Expires, NamespaceId, AuthorId, Key)
+/// Value: `()`
+pub const RECORDS_BY_EXPIRY_TABLE: TableDefinition<RecordsByExpiryId, ()> =
+    TableDefinition::new("records-by-expiry-1");
+pub type RecordsByExpiryId<'a> = (u64, &'a [u8; 32], &'a [u8; 32], &'a [u8]);
+
 /// Table: Peers per document.
 /// Key:   `[u8; 32]`        # NamespaceId
 /// Value: `(u64, [u8; 32])` # ([`Nanos`], &[`PeerIdBytes`]) representing the last time a peer was used.
@@ -73,6 +112,12 @@ pub type Nanos = u64;
 pub const DOWNLOAD_POLICY_TABLE: TableDefinition<&[u8; 32], &[u8]> =
     TableDefinition::new("download-policy-1");
 
+/// Table: Pruned deletion markers
+/// Key:   `([u8; 32], [u8; 32], &[u8])` # (NamespaceId, AuthorId, Key)
+/// Value: `(u64, [u8; 64], [u8; 64])`   # (timestamp, signature_namespace, signature_author)
+pub const PRUNED_TABLE: TableDefinition<RecordsId, PrunedValue> = TableDefinition::new("pruned-1");
+pub type PrunedValue<'a> = (u64, &'a [u8; 64], &'a [u8; 64]);
+
 self_cell::self_cell! {
     struct TransactionAndTablesInner {
         owner: WriteTransaction,
@@ -116,10 +161,14 @@ impl TransactionAndTables {
 pub struct Tables<'tx> {
     pub records: Table<'tx, RecordsId<'static>, RecordsValue<'static>>,
     pub records_by_key: Table<'tx, RecordsByKeyId<'static>, ()>,
+    pub records_by_timestamp: Table<'tx, RecordsByTimestampId<'static>, ()>,
+    pub records_by_hash: Table<'tx, RecordsByHashId<'static>, ()>,
+    pub records_by_expiry: Table<'tx, RecordsByExpiryId<'static>, ()>,
     pub namespaces: Table<'tx, &'static [u8; 32], (u8, &'static [u8; 32])>,
     pub latest_per_author: Table<'tx, LatestPerAuthorKey<'static>, LatestPerAuthorValue<'static>>,
     pub namespace_peers: MultimapTable<'tx, &'static [u8; 32], (Nanos, &'static PeerIdBytes)>,
     pub download_policy: Table<'tx, &'static [u8; 32], &'static [u8]>,
+    pub pruned: Table<'tx, RecordsId<'static>, PrunedValue<'static>>,
     pub authors: Table<'tx, &'static [u8; 32], &'static [u8; 32]>,
 }
 
@@ -127,18 +176,26 @@ impl<'tx> Tables<'tx> {
     pub fn new(tx: &'tx WriteTransaction) -> Result<Self, redb::TableError> {
         let records = tx.open_table(RECORDS_TABLE)?;
         let records_by_key = tx.open_table(RECORDS_BY_KEY_TABLE)?;
+        let records_by_timestamp = tx.open_table(RECORDS_BY_TIMESTAMP_TABLE)?;
+        let records_by_hash = tx.open_table(RECORDS_BY_HASH_TABLE)?;
+        let records_by_expiry = tx.open_table(RECORDS_BY_EXPIRY_TABLE)?;
         let namespaces = tx.open_table(NAMESPACES_TABLE)?;
         let latest_per_author = tx.open_table(LATEST_PER_AUTHOR_TABLE)?;
         let namespace_peers = tx.open_multimap_table(NAMESPACE_PEERS_TABLE)?;
         let download_policy = tx.open_table(DOWNLOAD_POLICY_TABLE)?;
+        let pruned = tx.open_table(PRUNED_TABLE)?;
         let authors = tx.open_table(AUTHORS_TABLE)?;
         Ok(Self {
             records,
             records_by_key,
+            records_by_timestamp,
+            records_by_hash,
+            records_by_expiry,
             namespaces,
             latest_per_author,
             namespace_peers,
             download_policy,
+            pruned,
             authors,
         })
     }
@@ -147,12 +204,16 @@ impl<'tx> Tables<'tx> {
 pub struct ReadOnlyTables {
     pub records: ReadOnlyTable<RecordsId<'static>, RecordsValue<'static>>,
     pub records_by_key: ReadOnlyTable<RecordsByKeyId<'static>, ()>,
+    pub records_by_timestamp: ReadOnlyTable<RecordsByTimestampId<'static>, ()>,
+    pub records_by_hash: ReadOnlyTable<RecordsByHashId<'static>, ()>,
+    pub records_by_expiry: ReadOnlyTable<RecordsByExpiryId<'static>, ()>,
     pub namespaces: ReadOnlyTable<&'static [u8; 32], (u8, &'static [u8; 32])>,
     pub latest_per_author:
         ReadOnlyTable<LatestPerAuthorKey<'static>, LatestPerAuthorValue<'static>>,
     #[debug("namespace_peers")]
     pub namespace_peers: ReadOnlyMultimapTable<&'static [u8; 32], (Nanos, &'static PeerIdBytes)>,
     pub download_policy: ReadOnlyTable<&'static [u8; 32], &'static [u8]>,
+    pub pruned: ReadOnlyTable<RecordsId<'static>, PrunedValue<'static>>,
     pub authors: ReadOnlyTable<&'static [u8; 32], &'static [u8; 32]>,
     tx: ReadTransaction,
 }
@@ -161,18 +222,26 @@ impl ReadOnlyTables {
     pub fn new(tx: ReadTransaction) -> Result<Self, redb::TableError> {
         let records = tx.open_table(RECORDS_TABLE)?;
         let records_by_key = tx.open_table(RECORDS_BY_KEY_TABLE)?;
+        let records_by_timestamp = tx.open_table(RECORDS_BY_TIMESTAMP_TABLE)?;
+        let records_by_hash = tx.open_table(RECORDS_BY_HASH_TABLE)?;
+        let records_by_expiry = tx.open_table(RECORDS_BY_EXPIRY_TABLE)?;
         let namespaces = tx.open_table(NAMESPACES_TABLE)?;
         let latest_per_author = tx.open_table(LATEST_PER_AUTHOR_TABLE)?;
         let namespace_peers = tx.open_multimap_table(NAMESPACE_PEERS_TABLE)?;
         let download_policy = tx.open_table(DOWNLOAD_POLICY_TABLE)?;
+        let pruned = tx.open_table(PRUNED_TABLE)?;
         let authors = tx.open_table(AUTHORS_TABLE)?;
         Ok(Self {
             records,
             records_by_key,
+            records_by_timestamp,
+            records_by_hash,
+            records_by_expiry,
             namespaces,
             latest_per_author,
             namespace_peers,
             download_policy,
+            pruned,
             authors,
             tx,
         })
@@ -1,10 +1,12 @@
 //! Utilities useful across different store impls.
 
+use iroh_base::hash::Hash;
+
 use crate::SignedEntry;
 
 use super::{AuthorFilter, KeyFilter, Query, QueryKind, SortBy};
 
-/// A helper for stores that have by-author and by-key indexes for records.
+/// A helper for stores that have by-author, by-key, by-timestamp and by-hash indexes for records.
 #[derive(Debug)]
 pub enum IndexKind {
     AuthorKey {
@@ -16,22 +18,28 @@ pub enum IndexKind {
         author_filter: AuthorFilter,
         latest_per_key: bool,
     },
+    Timestamp,
+    Hash(Hash),
 }
 
 impl From<&Query> for IndexKind {
     fn from(query: &Query) -> Self {
         match &query.kind {
-            QueryKind::Flat(details) => match (&query.filter_author, details.sort_by) {
-                (AuthorFilter::Any, SortBy::KeyAuthor) => IndexKind::KeyAuthor {
-                    range: query.filter_key.clone(),
-                    author_filter: AuthorFilter::Any,
-                    latest_per_key: false,
-                },
-                _ => IndexKind::AuthorKey {
-                    range: query.filter_author.clone(),
-                    key_filter: query.filter_key.clone(),
-                },
-            },
+            QueryKind::Flat(details) => {
+                match (&query.filter_author, details.sort_by, query.filter_hash) {
+                    (_, SortBy::Timestamp, _) => IndexKind::Timestamp,
+                    (_, SortBy::AuthorKey, Some(hash)) => IndexKind::Hash(hash),
+                    (AuthorFilter::Any, SortBy::KeyAuthor, _) => IndexKind::KeyAuthor {
+                        range: query.filter_key.clone(),
+                        author_filter: AuthorFilter::Any,
+                        latest_per_key: false,
+                    },
+                    _ => IndexKind::AuthorKey {
+                        range: query.filter_author.clone(),
+                        key_filter: query.filter_key.clone(),
+                    },
+                }
+            }
             QueryKind::SingleLatestPerKey(_) => IndexKind::KeyAuthor {
                 range: query.filter_key.clone(),
                 author_filter: query.filter_author.clone(),
@@ -26,6 +26,7 @@ pub use crate::heads::AuthorHeads;
 #[cfg(feature = "metrics")]
 use crate::metrics::Metrics;
 use crate::{
+    grant::{is_grant_entry, WriteGrant, WriteGrants, GRANT_KEY_PREFIX, REVOCATION_KEY_PREFIX},
     keys::{Author, AuthorId, AuthorPublicKey, NamespaceId, NamespacePublicKey, NamespaceSecret},
     ranger::{self, Fingerprint, InsertOutcome, RangeEntry, RangeKey, RangeValue, Store},
     store::{self, fs::StoreInstance, DownloadPolicyStore, PublicKeyStore},
@@ -157,6 +158,12 @@ pub enum Capability {
     Write(NamespaceSecret),
     /// Read only access to the namespace.
     Read(NamespaceId),
+    /// Read access to the namespace, and write access for a single author.
+    ///
+    /// The grant is stored in the namespace itself, so after importing, this capability is
+    /// stored and reported as [`Capability::Read`]. Writing with the author of the grant still
+    /// works as long as the grant is not revoked.
+    Delegated(WriteGrant),
 }
 
 impl Capability {
@@ -165,6 +172,7 @@ impl Capability {
         match self {
             Capability::Write(secret) => secret.id(),
             Capability::Read(id) => *id,
+            Capability::Delegated(grant) => grant.namespace(),
         }
     }
 
@@ -173,24 +181,28 @@ impl Capability {
     pub fn secret_key(&self) -> Result<&NamespaceSecret, ReadOnly> {
         match self {
             Capability::Write(secret) => Ok(secret),
-            Capability::Read(_) => Err(ReadOnly),
+            Capability::Read(_) | Capability::Delegated(_) => Err(ReadOnly),
         }
     }
 
     /// Get the kind of capability.
+    ///
+    /// This is [`CapabilityKind::Read`] for [`Capability::Delegated`].
     pub fn kind(&self) -> CapabilityKind {
         match self {
             Capability::Write(_) => CapabilityKind::Write,
-            Capability::Read(_) => CapabilityKind::Read,
+            Capability::Read(_) | Capability::Delegated(_) => CapabilityKind::Read,
         }
     }
 
     /// Get the raw representation of this namespace capability.
+    ///
+    /// A [`Capability::Delegated`] is represented like a [`Capability::Read`].
     pub fn raw(&self) -> (u8, [u8; 32]) {
         let capability_repr: u8 = self.kind().into();
         let bytes = match self {
             Capability::Write(secret) => secret.to_bytes(),
-            Capability::Read(id) => id.to_bytes(),
+            Capability::Read(_) | Capability::Delegated(_) => self.id().to_bytes(),
         };
         (capability_repr, bytes)
     }
@@ -222,7 +234,7 @@ impl Capability {
         }
 
         // the only capability upgrade is from read-only (self) to writable (other)
-        if matches!(self, Capability::Read(_)) && matches!(other, Capability::Write(_)) {
+        if !matches!(self, Capability::Write(_)) && matches!(other, Capability::Write(_)) {
             let _ = std::mem::replace(self, other);
             Ok(true)
         } else {
@@ -346,6 +358,10 @@ where
     /// The entry will by signed by the provided `author`.
     /// The `len` must be the byte length of the data identified by `hash`.
     ///
+    /// The timestamp of the entry is the current system time, but always greater than the
+    /// timestamps of all entries in the replica, so that the new entry replaces all values of the
+    /// key that were observed so far, even if their authors' clocks are ahead of ours.
+    ///
     /// Returns the number of entries removed as a consequence of this insertion,
     /// or an error either if the entry failed to validate or if a store operation failed.
     pub fn insert(
@@ -354,16 +370,34 @@ where
         author: &Author,
         hash: Hash,
         len: u64,
+    ) -> Result<usize, InsertError> {
+        self.insert_with_expiry(key, author, hash, len, None)
+    }
+
+    /// Insert a new record at the given key, which expires at `expires`.
+    ///
+    /// `expires` is a timestamp in micros since the Unix epoch. Once it has passed, the entry
+    /// is no longer returned from queries or synced to other peers, and is eventually removed
+    /// from the store. Peers that do not support expiring entries reject them.
+    ///
+    /// See [`Self::insert`] for details.
+    pub fn insert_with_expiry(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        author: &Author,
+        hash: Hash,
+        len: u64,
+        expires: Option<u64>,
     ) -> Result<usize, InsertError> {
         if len == 0 || hash == Hash::EMPTY {
             return Err(InsertError::EntryIsEmpty);
         }
         self.info.ensure_open()?;
         let id = RecordIdentifier::new(self.id(), author.id(), key);
-        let record = Record::new_current(hash, len);
+        let timestamp = self.next_timestamp()?;
+        let record = Record::new(hash, len, timestamp).with_expires(expires);
         let entry = Entry::new(id, record);
-        let secret = self.secret_key()?;
-        let signed_entry = entry.sign(secret, author);
+        let signed_entry = self.sign_entry(entry, author)?;
         self.insert_entry(signed_entry, InsertOrigin::Local)
     }
 
@@ -380,15 +414,93 @@ where
     ) -> Result<usize, InsertError> {
         self.info.ensure_open()?;
         let id = RecordIdentifier::new(self.id(), author.id(), prefix);
-        let entry = Entry::new_empty(id);
-        let signed_entry = entry.sign(self.secret_key()?, author);
+        let timestamp = self.next_timestamp()?;
+        let entry = Entry::new(id, Record::empty(timestamp));
+        let signed_entry = self.sign_entry(entry, author)?;
         self.insert_entry(signed_entry, InsertOrigin::Local)
     }
 
+    /// Allow `author` to write entries with keys starting with `prefix` to this replica.
+    ///
+    /// This inserts a [`WriteGrant`] into the replica, which is synced to other peers like any
+    /// other entry. The grant can be shared with the author in a [`Capability::Delegated`].
+    /// See [`WriteGrant::new`] for the meaning of `expires`.
+    ///
+    /// Fails if the replica is read only.
+    pub fn grant_write(
+        &mut self,
+        author: AuthorId,
+        prefix: impl AsRef<[u8]>,
+        expires: Option<u64>,
+    ) -> Result<WriteGrant, InsertError> {
+        self.info.ensure_open()?;
+        let grant = WriteGrant::new(self.secret_key()?, author, prefix, expires);
+        self.insert_entry(grant.entry().clone(), InsertOrigin::Local)?;
+        Ok(grant)
+    }
+
+    /// Revoke all [`WriteGrant`]s issued to `author`.
+    ///
+    /// The grants are kept and still cover the entries of `author` that are in this replica, so
+    /// these entries stay valid, also for peers that did not receive them yet. All other entries
+    /// of `author` are rejected, including entries written before the revocation that this
+    /// replica did not receive yet.
+    ///
+    /// Returns the number of grants revoked. Fails if the replica is read only.
+    pub fn revoke_write(&mut self, author: AuthorId) -> Result<usize, InsertError> {
+        self.info.ensure_open()?;
+        let accepted = self
+            .store
+            .store
+            .get_many(self.id(), store::Query::author(author).include_empty())
+            .map_err(InsertError::Store)?
+            .map(|entry| entry.map(|entry| entry.entry().clone()))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .map_err(InsertError::Store)?;
+        let revoked = self
+            .write_grants()
+            .map_err(InsertError::Store)?
+            .for_author(author)
+            .count();
+        for entry in WriteGrant::revocation(self.secret_key()?, author, &accepted) {
+            self.insert_entry(entry, InsertOrigin::Local)?;
+        }
+        Ok(revoked)
+    }
+
+    /// Get the [`WriteGrant`]s and revocations stored in this replica.
+    pub fn write_grants(&mut self) -> anyhow::Result<WriteGrants> {
+        let namespace = self.id();
+        let mut grants = WriteGrants::default();
+        for prefix in [GRANT_KEY_PREFIX, REVOCATION_KEY_PREFIX] {
+            let id = RecordIdentifier::new(namespace, namespace.as_bytes(), prefix);
+            for entry in self.store.prefixed_by(&id)? {
+                grants.insert_stored(&entry?);
+            }
+        }
+        Ok(grants)
+    }
+
+    /// Sign a local entry, either with the namespace secret or with a [`WriteGrant`].
+    fn sign_entry(&mut self, entry: Entry, author: &Author) -> Result<SignedEntry, InsertError> {
+        if let Ok(secret) = self.secret_key() {
+            return Ok(entry.sign(secret, author));
+        }
+        let grants = self.write_grants().map_err(InsertError::Store)?;
+        if grants.find(&entry).is_some() {
+            Ok(entry.sign_delegated(author))
+        } else if grants.for_author(author.id()).next().is_some() {
+            Err(ValidationFailure::NotAuthorized.into())
+        } else {
+            Err(InsertError::ReadOnly)
+        }
+    }
+
     /// Insert an entry into this replica which was received from a remote peer.
     ///
     /// This will verify both the namespace and author signatures of the entry, emit an `on_insert`
-    /// event, and insert the entry into the replica store.
+    /// event, and insert the entry into the replica store. Entries of delegated authors must be
+    /// covered by a [`WriteGrant`] stored in this replica.
     ///
     /// Returns the number of entries removed as a consequence of this insertion,
     /// or an error if the entry failed to validate or if a store operation failed.
@@ -420,8 +532,19 @@ where
         #[cfg(feature = "metrics")]
         let len = entry.content_len();
 
+        let grants = match origin {
+            InsertOrigin::Local => WriteGrants::default(),
+            InsertOrigin::Sync { .. } => self.write_grants().map_err(InsertError::Store)?,
+        };
         let store = &self.store;
-        validate_entry(system_time_now(), store, namespace, &entry, &origin)?;
+        validate_entry(
+            system_time_now(),
+            store,
+            &grants,
+            namespace,
+            &entry,
+            &origin,
+        )?;
 
         let outcome = self.store.put(entry.clone()).map_err(InsertError::Store)?;
         tracing::debug!(?origin, hash = %entry.content_hash(), ?outcome, "insert");
@@ -454,7 +577,7 @@ where
                     .store
                     .get_download_policy(&self.id())
                     .unwrap_or_default();
-                let should_download = download_policy.matches(entry.entry());
+                let should_download = should_download(&download_policy, &entry);
                 Event::RemoteInsert {
                     namespace,
                     entry,
@@ -487,6 +610,20 @@ where
         Ok(hash)
     }
 
+    /// Get the timestamp for a new local entry in this replica.
+    ///
+    /// Local timestamps form a hybrid logical clock: the timestamp is the current system time,
+    /// but always greater than the timestamps of all entries in the replica. This way a local
+    /// write wins over every entry it could have observed, even if the clocks of their authors
+    /// are ahead of ours.
+    fn next_timestamp(&mut self) -> Result<u64, InsertError> {
+        let latest = self
+            .store
+            .get_latest_timestamp()
+            .map_err(InsertError::Store)?;
+        Ok(hybrid_timestamp(system_time_now(), latest))
+    }
+
     /// Get the identifier for an entry in this replica.
     pub fn record_id(&self, key: impl AsRef<[u8]>, author: &Author) -> RecordIdentifier {
         RecordIdentifier::new(self.info.capability.id(), author.id(), key)
@@ -519,8 +656,12 @@ where
                 .insert(entry.author(), entry.timestamp());
         }
 
-        // let subscribers = std::rc::Rc::new(&mut self.subscribers);
-        // l
+        // grants received in this message can cover entries in the same message
+        let mut grants = self.write_grants()?;
+        for (entry, _content_status) in message.values() {
+            grants.insert(entry);
+        }
+
         let cb = self.info.content_status_cb.clone();
         let download_policy = self
             .store
@@ -535,13 +676,13 @@ where
                     from: from_peer,
                     remote_content_status: content_status,
                 };
-                validate_entry(now, store, my_namespace, entry, &origin).is_ok()
+                validate_entry(now, store, &grants, my_namespace, entry, &origin).is_ok()
             },
             // on_insert callback: is called when an entry was actually inserted in the store
             |_store, entry, content_status| {
                 // We use `send_with` to only clone the entry if we have active subscriptions.
                 self.info.subscribers.send_with(|| {
-                    let should_download = download_policy.matches(entry.entry());
+                    let should_download = should_download(&download_policy, &entry);
                     Event::RemoteInsert {
                         from: from_peer,
                         namespace: my_namespace,
@@ -591,16 +732,26 @@ where
 #[error("Replica allows read access only.")]
 pub struct ReadOnly;
 
+/// Whether the content of a remote entry should be downloaded.
+///
+/// Grant entries have no content, so they are never downloaded.
+fn should_download(policy: &store::DownloadPolicy, entry: &SignedEntry) -> bool {
+    !is_grant_entry(entry.entry()) && policy.matches(entry.entry())
+}
+
 /// Validate a [`SignedEntry`] if it's fit to be inserted.
 ///
 /// This validates that
-/// * the entry's author and namespace signatures are correct
+/// * the entry's author and namespace signatures are correct, or that the entry is signed by a
+///   delegated author and covered by one of `grants`
 /// * the entry's namespace matches the current replica
 /// * the entry's timestamp is not more than 10 minutes in the future of our system time
+/// * the entry is not expired
 /// * the entry is newer than an existing entry for the same key and author, if such exists.
 fn validate_entry<S: ranger::Store<SignedEntry> + PublicKeyStore>(
     now: u64,
     store: &S,
+    grants: &WriteGrants,
     expected_namespace: NamespaceId,
     entry: &SignedEntry,
     origin: &InsertOrigin,
@@ -611,14 +762,19 @@ fn validate_entry<S: ranger::Store<SignedEntry> + PublicKeyStore>(
     }
 
     // Verify signature for non-local entries.
-    if !matches!(origin, InsertOrigin::Local) && entry.verify(store).is_err() {
-        return Err(ValidationFailure::BadSignature);
+    if !matches!(origin, InsertOrigin::Local) {
+        entry.verify(store, grants)?;
     }
 
     // Verify that the timestamp of the entry is not too far in the future.
-    if entry.timestamp() > now + MAX_TIMESTAMP_FUTURE_SHIFT {
+    if entry.timestamp() > max_timestamp(now) {
         return Err(ValidationFailure::TooFarInTheFuture);
     }
+
+    // Verify that the entry did not expire yet.
+    if entry.record().is_expired(now) {
+        return Err(ValidationFailure::Expired);
+    }
     Ok(())
 }
 
@@ -661,6 +817,12 @@ pub enum ValidationFailure {
     /// Entry has length 0 but not the empty hash, or the empty hash but not length 0.
     #[error("Entry has length 0 but not the empty hash, or the empty hash but not length 0")]
     InvalidEmptyEntry,
+    /// Entry is signed by a delegated author, but no write grant covers it.
+    #[error("Entry is not covered by a write grant")]
+    NotAuthorized,
+    /// Entry has an expiry timestamp that lies in the past.
+    #[error("Entry is expired")]
+    Expired,
 }
 
 /// A signed entry.
@@ -725,13 +887,47 @@ impl SignedEntry {
         Self::from_entry(entry, namespace, author)
     }
 
+    /// Create a new signed entry by signing an entry as a delegated `author`.
+    ///
+    /// The entry is only valid if a [`WriteGrant`] covers it.
+    pub fn from_delegated_entry(entry: Entry, author: &Author) -> Self {
+        let signature = EntrySignature::from_delegated_entry(&entry, author);
+        SignedEntry { signature, entry }
+    }
+
     /// Verify the signatures on this entry.
-    pub fn verify<S: store::PublicKeyStore>(&self, store: &S) -> Result<(), SignatureError> {
-        self.signature.verify(
-            &self.entry,
-            &self.entry.namespace().public_key(store)?,
-            &self.entry.author().public_key(store)?,
-        )
+    ///
+    /// Entries signed by a delegated author must be covered by one of `grants`.
+    pub fn verify<S: store::PublicKeyStore>(
+        &self,
+        store: &S,
+        grants: &WriteGrants,
+    ) -> Result<(), ValidationFailure> {
+        let bad_signature = |_| ValidationFailure::BadSignature;
+        let namespace = self
+            .entry
+            .namespace()
+            .public_key(store)
+            .map_err(bad_signature)?;
+        let author = self
+            .entry
+            .author()
+            .public_key(store)
+            .map_err(bad_signature)?;
+        if self
+            .signature
+            .verify(&self.entry, &namespace, &author)
+            .is_ok()
+        {
+            return Ok(());
+        }
+        self.signature
+            .verify_delegated(&self.entry, &author)
+            .map_err(bad_signature)?;
+        match grants.find(&self.entry) {
+            Some(_) => Ok(()),
+            None => Err(ValidationFailure::NotAuthorized),
+        }
     }
 
     /// Get the signature.
@@ -794,6 +990,9 @@ impl RangeEntry for SignedEntry {
         hasher.update(self.key());
         hasher.update(&self.timestamp().to_be_bytes());
         hasher.update(self.content_hash().as_bytes());
+        if let Some(expires) = self.entry.record.expires {
+            hasher.update(&expires.to_be_bytes());
+        }
         Fingerprint(hasher.finalize().into())
     }
 }
@@ -835,6 +1034,37 @@ impl EntrySignature {
         }
     }
 
+    /// Create a new signature by signing an entry as a delegated `author`.
+    ///
+    /// In place of the namespace signature, the author signs the entry a second time, with a
+    /// prefix that marks it as delegated.
+    pub fn from_delegated_entry(entry: &Entry, author: &Author) -> Self {
+        let bytes = entry.to_vec();
+        let namespace_signature = author.sign(&delegated_bytes(&bytes));
+        let author_signature = author.sign(&bytes);
+
+        EntrySignature {
+            author_signature,
+            namespace_signature,
+        }
+    }
+
+    /// Verify that this signature was created by signing the `entry` as the delegated
+    /// `author`.
+    ///
+    /// This does not check that the author is allowed to write the entry.
+    pub fn verify_delegated(
+        &self,
+        entry: &Entry,
+        author: &AuthorPublicKey,
+    ) -> Result<(), SignatureError> {
+        let bytes = entry.to_vec();
+        author.verify(&delegated_bytes(&bytes), &self.namespace_signature)?;
+        author.verify(&bytes, &self.author_signature)?;
+
+        Ok(())
+    }
+
     /// Verify that this signature was created by signing the `entry` with the
     /// secret keys of the specified `author` and `namespace`.
     pub fn verify(
@@ -930,7 +1160,15 @@ impl Entry {
     }
 
     /// Serialize this entry into its canonical byte representation used for signing.
+    ///
+    /// Entries without an expiry are encoded as in the first version of the protocol. Entries
+    /// with an expiry are prefixed with [`EXPIRING_ENTRY_PREFIX`], so that their signatures
+    /// never verify for peers that do not know about expiry, and are rejected by them instead of
+    /// being stored as permanent entries.
     pub fn encode(&self, out: &mut Vec<u8>) {
+        if self.record.expires.is_some() {
+            out.extend_from_slice(EXPIRING_ENTRY_PREFIX);
+        }
         self.id.encode(out);
         self.record.encode(out);
     }
@@ -946,6 +1184,22 @@ impl Entry {
     pub fn sign(self, namespace: &NamespaceSecret, author: &Author) -> SignedEntry {
         SignedEntry::from_entry(self, namespace, author)
     }
+
+    /// Sign this entry as a delegated [`Author`], see [`WriteGrant`].
+    pub fn sign_delegated(self, author: &Author) -> SignedEntry {
+        SignedEntry::from_delegated_entry(self, author)
+    }
+}
+
+/// Version prefix of the canonical encoding of entries with an expiry.
+const EXPIRING_ENTRY_PREFIX: &[u8] = b"iroh-docs/entry-v2/";
+
+/// Domain separation prefix for the second author signature of delegated entries.
+const DELEGATED_SIGNATURE_PREFIX: &[u8] = b"iroh-docs/delegated-entry/";
+
+/// The bytes signed in place of the namespace signature by delegated authors.
+fn delegated_bytes(entry_bytes: &[u8]) -> Vec<u8> {
+    [DELEGATED_SIGNATURE_PREFIX, entry_bytes].concat()
 }
 
 const NAMESPACE_BYTES: std::ops::Range<usize> = 0..32;
@@ -979,7 +1233,26 @@ impl RangeKey for RecordIdentifier {
     }
 }
 
-fn system_time_now() -> u64 {
+/// Advance a hybrid logical clock at `now` (micros since the Unix epoch), which has observed
+/// timestamps up to `latest`.
+///
+/// The logical part of the clock is folded into the microseconds, so the result is a valid
+/// timestamp that is at least `now` and strictly greater than `latest`. The clock never
+/// advances past [`max_timestamp`], so the result is accepted by peers with the same wall
+/// clock time, even if `latest` is at or beyond the bound.
+pub(crate) fn hybrid_timestamp(now: u64, latest: Option<u64>) -> u64 {
+    match latest {
+        Some(latest) if latest >= now => latest.saturating_add(1).min(max_timestamp(now)),
+        _ => now,
+    }
+}
+
+/// The latest timestamp that is accepted for entries at `now`, inclusive.
+pub(crate) fn max_timestamp(now: u64) -> u64 {
+    now.saturating_add(MAX_TIMESTAMP_FUTURE_SHIFT)
+}
+
+pub(crate) fn system_time_now() -> u64 {
     SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .expect("time drift")
@@ -1079,7 +1352,15 @@ pub struct Record {
     /// Hash of the content data.
     hash: Hash,
     /// Record creation timestamp. Counted as micros since the Unix epoch.
+    ///
+    /// For entries created through a [`Replica`], this is a hybrid logical clock, see
+    /// [`Replica::insert`].
     timestamp: u64,
+    /// Optional expiry timestamp. Counted as micros since the Unix epoch.
+    ///
+    /// See [`Entry::encode`] for how it is covered by the entry signature. When sending entries
+    /// to other peers, it is sent in an [`ExpiryEnvelope`], see [`WireEntry`].
+    expires: Option<u64>,
 }
 
 impl RangeValue for Record {}
@@ -1112,9 +1393,19 @@ impl Record {
             hash,
             len,
             timestamp,
+            expires: None,
         }
     }
 
+    /// Set the time after which this record expires, in micros since the Unix epoch.
+    ///
+    /// Expired records are hidden from queries, excluded from set reconciliation and eventually
+    /// removed from the store.
+    pub fn with_expires(mut self, expires: Option<u64>) -> Self {
+        self.expires = expires;
+        self
+    }
+
     /// Create a tombstone record (empty content)
     pub fn empty(timestamp: u64) -> Self {
         Self::new(Hash::EMPTY, 0, timestamp)
@@ -1151,6 +1442,16 @@ impl Record {
         self.timestamp
     }
 
+    /// Get the expiry timestamp of this record, if any.
+    pub fn expires(&self) -> Option<u64> {
+        self.expires
+    }
+
+    /// Return `true` if this record expired at or before `now` (micros since the Unix epoch).
+    pub fn is_expired(&self, now: u64) -> bool {
+        is_expired(self.expires, now)
+    }
+
     #[cfg(test)]
     pub(crate) fn current_from_data(data: impl AsRef<[u8]>) -> Self {
         let len = data.as_ref().len() as u64;
@@ -1169,7 +1470,151 @@ impl Record {
     pub(crate) fn encode(&self, out: &mut Vec<u8>) {
         out.extend_from_slice(&self.len.to_be_bytes());
         out.extend_from_slice(self.hash.as_ref());
-        out.extend_from_slice(&self.timestamp.to_be_bytes())
+        out.extend_from_slice(&self.timestamp.to_be_bytes());
+        if let Some(expires) = self.expires {
+            out.extend_from_slice(&expires.to_be_bytes());
+        }
+    }
+}
+
+/// Return `true` if the expiry timestamp `expires` is at or before `now`.
+pub(crate) fn is_expired(expires: Option<u64>, now: u64) -> bool {
+    expires.is_some_and(|expires| expires <= now)
+}
+
+/// A [`SignedEntry`] as sent to other peers, serialized without its expiry.
+///
+/// Sync and gossip messages contain their entries as [`WireEntry`], so that they are encoded
+/// exactly as in the first version of the protocol. The expiries are sent in an
+/// [`ExpiryEnvelope`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct WireEntry(pub(crate) SignedEntry);
+
+impl Serialize for WireEntry {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let SignedEntry { signature, entry } = &self.0;
+        let Record {
+            len,
+            hash,
+            timestamp,
+            ..
+        } = &entry.record;
+        (signature, &entry.id, len, hash, timestamp).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for WireEntry {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (signature, id, len, hash, timestamp) = Deserialize::deserialize(deserializer)?;
+        let record = Record {
+            len,
+            hash,
+            timestamp,
+            expires: None,
+        };
+        let entry = Entry { id, record };
+        Ok(Self(SignedEntry { signature, entry }))
+    }
+}
+
+impl RangeEntry for WireEntry {
+    type Key = RecordIdentifier;
+    type Value = Record;
+
+    fn key(&self) -> &Self::Key {
+        RangeEntry::key(&self.0)
+    }
+
+    fn value(&self) -> &Self::Value {
+        RangeEntry::value(&self.0)
+    }
+
+    fn as_fingerprint(&self) -> crate::ranger::Fingerprint {
+        RangeEntry::as_fingerprint(&self.0)
+    }
+}
+
+/// Expiry timestamps of the entries in a message sent to other peers.
+///
+/// A [`WireEntry`] does not contain the expiry, so messages with entries are encoded
+/// exactly as in the first version of the protocol. The expiries follow the message in this
+/// envelope, which peers that only know the first version ignore. Without its expiry, the
+/// signature of an expiring entry does not verify, so these peers reject such entries instead of
+/// storing them as permanent entries.
+///
+/// The envelope also carries the deletion markers that the sender pruned from its store and
+/// answers stale entries with, see [`crate::ranger::Message::pruned`]. Peers that do not know
+/// about them keep the stale entries.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub(crate) enum ExpiryEnvelope {
+    /// The position in the message and the expiry of each entry that has an expiry.
+    V1(Vec<(u32, u64)>),
+    /// Like [`ExpiryEnvelope::V1`], with pruned deletion markers.
+    V2 {
+        /// The position in the message and the expiry of each entry that has an expiry.
+        expiries: Vec<(u32, u64)>,
+        /// Deletion markers pruned by the sender, which never expire.
+        pruned: Vec<WireEntry>,
+    },
+}
+
+impl ExpiryEnvelope {
+    /// Serialize `message`, followed by an envelope if any of its `entries` has an expiry or
+    /// there are `pruned` entries.
+    ///
+    /// `entries` must yield the entries of `message` in the same order as [`Self::decode`].
+    pub(crate) fn encode<'a, T: Serialize>(
+        message: &T,
+        entries: impl IntoIterator<Item = &'a SignedEntry>,
+        pruned: Vec<SignedEntry>,
+    ) -> postcard::Result<Vec<u8>> {
+        let mut out = postcard::to_stdvec(message)?;
+        let expiries = entries
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, entry)| Some((i as u32, entry.expires()?)))
+            .collect::<Vec<_>>();
+        if !pruned.is_empty() {
+            let pruned = pruned.into_iter().map(WireEntry).collect();
+            out.extend(postcard::to_stdvec(&ExpiryEnvelope::V2 {
+                expiries,
+                pruned,
+            })?);
+        } else if !expiries.is_empty() {
+            out.extend(postcard::to_stdvec(&ExpiryEnvelope::V1(expiries))?);
+        }
+        Ok(out)
+    }
+
+    /// Deserialize a message serialized with [`Self::encode`], and the envelope following it.
+    ///
+    /// Envelopes of unknown versions are ignored, expiring entries then fail to verify.
+    pub(crate) fn decode<'a, T: Deserialize<'a>>(
+        bytes: &'a [u8],
+    ) -> postcard::Result<(T, Option<Self>)> {
+        let (message, rest) = postcard::take_from_bytes(bytes)?;
+        let envelope = postcard::from_bytes(rest).ok();
+        Ok((message, envelope))
+    }
+
+    /// Restore the expiries of the `entries` of the message this envelope was received with.
+    ///
+    /// Returns the pruned entries contained in the envelope.
+    pub(crate) fn apply<'a>(
+        self,
+        entries: impl IntoIterator<Item = &'a mut SignedEntry>,
+    ) -> Vec<SignedEntry> {
+        let (expiries, pruned) = match self {
+            ExpiryEnvelope::V1(expiries) => (expiries, Vec::new()),
+            ExpiryEnvelope::V2 { expiries, pruned } => (expiries, pruned),
+        };
+        let mut entries = entries.into_iter().collect::<Vec<_>>();
+        for (i, expires) in expiries {
+            if let Some(entry) = entries.get_mut(i as usize) {
+                entry.entry.record.expires = Some(expires);
+            }
+        }
+        pruned.into_iter().map(|entry| entry.0).collect()
     }
 }
 
@@ -1214,7 +1659,9 @@ mod tests {
         let record = Record::current_from_data(b"this is my cool data");
         let entry = Entry::new(record_id, record);
         let signed_entry = entry.sign(&myspace, &alice);
-        signed_entry.verify(&()).expect("failed to verify");
+        signed_entry
+            .verify(&(), &WriteGrants::default())
+            .expect("failed to verify");
 
         let mut my_replica = store.new_replica(myspace.clone())?;
         for i in 0..10 {
@@ -1231,7 +1678,7 @@ mod tests {
                 .unwrap();
             let len = format!("{i}: hello from alice").as_bytes().len() as u64;
             assert_eq!(res.entry().record().content_len(), len);
-            res.verify(&())?;
+            res.verify(&(), &WriteGrants::default())?;
         }
 
         // Test multiple records for the same key
@@ -1850,6 +2297,286 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_replica_compaction_memory() -> Result<()> {
+        let alice_store = store::Store::memory();
+        let bob_store = store::Store::memory();
+
+        test_replica_compaction(alice_store, bob_store)
+    }
+
+    #[test]
+    fn test_replica_compaction_fs() -> Result<()> {
+        let alice_dbfile = tempfile::NamedTempFile::new()?;
+        let alice_store = store::fs::Store::persistent(alice_dbfile.path())?;
+        let bob_dbfile = tempfile::NamedTempFile::new()?;
+        let bob_store = store::fs::Store::persistent(bob_dbfile.path())?;
+        test_replica_compaction(alice_store, bob_store)
+    }
+
+    fn test_replica_compaction(mut alice_store: Store, mut bob_store: Store) -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let author1 = Author::new(&mut rng);
+        let author2 = Author::new(&mut rng);
+        let myspace = NamespaceSecret::new(&mut rng);
+        let namespace = myspace.id();
+
+        let mut alice = alice_store.new_replica(myspace.clone())?;
+        alice.hash_and_insert("k", &author1, "v1")?;
+        alice.hash_and_insert("k", &author2, "v2")?;
+        alice.hash_and_insert("del/x", &author1, "x")?;
+        alice.hash_and_insert("keep", &author1, "keep")?;
+        let mut bob = bob_store.new_replica(myspace.clone())?;
+        sync(&mut alice, &mut bob)?;
+        // bob receives the first deletion marker, but not the second one
+        alice.hash_and_insert("old", &author1, "old")?;
+        alice.delete_prefix("old", &author1)?;
+        sync(&mut alice, &mut bob)?;
+        alice.delete_prefix("del/", &author1)?;
+        // bob writes an entry and goes offline until after the compaction
+        bob.hash_and_insert("offline", &author2, "offline")?;
+        drop(alice);
+        drop(bob);
+        let stale = get_entry(&mut bob_store, namespace, author1.id(), b"del/x")?;
+        assert!(get_entry(&mut bob_store, namespace, author1.id(), b"old")?.is_empty());
+
+        std::thread::sleep(Duration::from_millis(1));
+        let outcome = alice_store.compact(&namespace, Duration::ZERO)?;
+        assert_eq!(outcome.tombstones, 2);
+        assert!(get_entry(&mut alice_store, namespace, author1.id(), b"old").is_err());
+        assert!(get_entry(&mut alice_store, namespace, author1.id(), b"del/").is_err());
+        // entries of other authors for the same key are independent and kept
+        assert!(get_entry(&mut alice_store, namespace, author1.id(), b"k").is_ok());
+        assert!(get_entry(&mut alice_store, namespace, author2.id(), b"k").is_ok());
+        assert!(get_entry(&mut alice_store, namespace, author1.id(), b"keep").is_ok());
+
+        // compacting again does not find anything to prune
+        let outcome2 = alice_store.compact(&namespace, Duration::ZERO)?;
+        assert_eq!(outcome2.tombstones, 0);
+
+        // bob still has the stale entry and a pruned deletion marker. syncing does not resurrect
+        // the stale entry, but makes bob drop both, while bob's entry is accepted
+        let mut alice = alice_store.new_replica(myspace.clone())?;
+        let mut bob = bob_store.new_replica(myspace.clone())?;
+        let (alice_outcome, _bob_outcome) = sync(&mut alice, &mut bob)?;
+        assert!(alice_outcome.num_sent > 0);
+        drop(alice);
+        drop(bob);
+        assert!(get_entry(&mut bob_store, namespace, author1.id(), b"del/x").is_err());
+        assert!(get_entry(&mut bob_store, namespace, author1.id(), b"old").is_err());
+        assert!(get_entry(&mut alice_store, namespace, author1.id(), b"del/x").is_err());
+        assert!(get_entry(&mut alice_store, namespace, author2.id(), b"offline").is_ok());
+
+        // after that, the sets are equal and syncing exchanges no entries
+        let mut alice = alice_store.new_replica(myspace.clone())?;
+        let mut bob = bob_store.new_replica(myspace.clone())?;
+        let (alice_outcome, bob_outcome) = sync(&mut alice, &mut bob)?;
+        assert_eq!(alice_outcome.num_sent, 0);
+        assert_eq!(bob_outcome.num_sent, 0);
+
+        // the stale entry is not inserted anymore, neither by alice nor by bob
+        assert!(matches!(
+            alice.insert_remote_entry(stale.clone(), [2u8; 32], ContentStatus::Complete),
+            Err(InsertError::NewerEntryExists)
+        ));
+        assert!(matches!(
+            bob.insert_remote_entry(stale, [1u8; 32], ContentStatus::Complete),
+            Err(InsertError::NewerEntryExists)
+        ));
+        drop(bob);
+        alice.hash_and_insert("new", &author1, "new")?;
+        alice.hash_and_insert("del/y", &author1, "y")?;
+        drop(alice);
+        assert!(get_entry(&mut alice_store, namespace, author1.id(), b"new").is_ok());
+        assert!(get_entry(&mut alice_store, namespace, author1.id(), b"del/y").is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_replica_expiry_memory() -> Result<()> {
+        let alice_store = store::Store::memory();
+        let bob_store = store::Store::memory();
+        let carol_store = store::Store::memory();
+        test_replica_expiry(alice_store, bob_store, carol_store)
+    }
+
+    #[test]
+    fn test_replica_expiry_fs() -> Result<()> {
+        let alice_dbfile = tempfile::NamedTempFile::new()?;
+        let alice_store = store::fs::Store::persistent(alice_dbfile.path())?;
+        let bob_dbfile = tempfile::NamedTempFile::new()?;
+        let bob_store = store::fs::Store::persistent(bob_dbfile.path())?;
+        let carol_dbfile = tempfile::NamedTempFile::new()?;
+        let carol_store = store::fs::Store::persistent(carol_dbfile.path())?;
+        test_replica_expiry(alice_store, bob_store, carol_store)
+    }
+
+    fn test_replica_expiry(
+        mut alice_store: Store,
+        mut bob_store: Store,
+        mut carol_store: Store,
+    ) -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let author = Author::new(&mut rng);
+        let myspace = NamespaceSecret::new(&mut rng);
+        let namespace = myspace.id();
+
+        let expires = system_time_now() + Duration::from_millis(200).as_micros() as u64;
+        let mut alice = alice_store.new_replica(myspace.clone())?;
+        alice.hash_and_insert("keep", &author, "keep")?;
+        alice.insert_with_expiry("presence", &author, Hash::new("here"), 4, Some(expires))?;
+        let mut bob = bob_store.new_replica(myspace.clone())?;
+        sync(&mut alice, &mut bob)?;
+        drop(alice);
+        drop(bob);
+
+        let entry = get_entry(&mut bob_store, namespace, author.id(), b"presence")?;
+        assert_eq!(entry.expires(), Some(expires));
+        assert_eq!(bob_store.get_many(namespace, Query::all())?.count(), 2);
+
+        // the expiry is covered by the signature
+        let mut tampered = entry.entry().clone();
+        tampered.record.expires = None;
+        let tampered = SignedEntry::new(entry.signature().clone(), tampered);
+        let mut bob = bob_store.open_replica(&namespace)?;
+        assert!(matches!(
+            bob.insert_remote_entry(tampered, [1u8; 32], ContentStatus::Complete),
+            Err(InsertError::Validation(ValidationFailure::BadSignature))
+        ));
+        drop(bob);
+
+        std::thread::sleep(Duration::from_millis(300));
+
+        // expired entries are hidden
+        assert!(get_entry(&mut bob_store, namespace, author.id(), b"presence").is_err());
+        let keys = bob_store
+            .get_many(namespace, Query::all())?
+            .map(|entry| entry.map(|entry| entry.key().to_vec()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(keys, vec![b"keep".to_vec()]);
+
+        // expired entries are neither synced nor accepted
+        let mut bob = bob_store.open_replica(&namespace)?;
+        let mut carol = carol_store.new_replica(myspace.clone())?;
+        let (bob_outcome, _) = sync(&mut bob, &mut carol)?;
+        assert_eq!(bob_outcome.num_sent, 1);
+        assert!(matches!(
+            carol.insert_remote_entry(entry, [2u8; 32], ContentStatus::Complete),
+            Err(InsertError::Validation(ValidationFailure::Expired))
+        ));
+        drop(bob);
+        drop(carol);
+        assert!(get_entry(&mut carol_store, namespace, author.id(), b"presence").is_err());
+
+        // expired entries are removed from the store
+        assert_eq!(alice_store.remove_expired()?, 1);
+        assert_eq!(alice_store.remove_expired()?, 0);
+        assert_eq!(
+            alice_store
+                .get_many(namespace, Query::all().include_empty())?
+                .count(),
+            1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_replica_heads_memory() -> Result<()> {
+        let store = store::Store::memory();
+        test_replica_heads(store)
+    }
+
+    #[test]
+    fn test_replica_heads_fs() -> Result<()> {
+        let dbfile = tempfile::NamedTempFile::new()?;
+        let store = store::fs::Store::persistent(dbfile.path())?;
+        test_replica_heads(store)
+    }
+
+    fn test_replica_heads(mut store: Store) -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let author1 = Author::new(&mut rng);
+        let author2 = Author::new(&mut rng);
+        let author3 = Author::new(&mut rng);
+        let myspace = NamespaceSecret::new(&mut rng);
+        let namespace = myspace.id();
+
+        // author1 writes with a clock that is ahead of ours
+        let ahead = system_time_now() + MAX_TIMESTAMP_FUTURE_SHIFT / 2;
+        let entry =
+            SignedEntry::from_parts(&myspace, &author1, b"k", Record::from_data("a", ahead));
+        let mut replica = store.new_replica(myspace.clone())?;
+        replica.insert_remote_entry(entry, [1u8; 32], ContentStatus::Complete)?;
+
+        // local writes still win, because their timestamps are after the latest seen timestamp
+        replica.hash_and_insert(b"k", &author2, "b")?;
+        replica.hash_and_insert(b"other", &author2, "x")?;
+        replica.delete_prefix(b"k", &author3)?;
+        drop(replica);
+
+        let heads = store.get_heads(namespace, b"k")?;
+        let authors = heads.iter().map(|e| e.author()).collect::<Vec<_>>();
+        assert_eq!(authors, vec![author3.id(), author2.id(), author1.id()]);
+        assert!(heads[0].is_empty());
+        assert!(heads[1].timestamp() > ahead);
+        assert!(heads[0].timestamp() > heads[1].timestamp());
+
+        // the first head is the latest entry for the key
+        let latest = store
+            .get_many(
+                namespace,
+                Query::single_latest_per_key()
+                    .key_exact(b"k")
+                    .include_empty()
+                    .build(),
+            )?
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(latest, vec![heads[0].clone()]);
+
+        assert!(store.get_heads(namespace, b"missing")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_hybrid_timestamp_bound() -> Result<()> {
+        let now = 1_000_000;
+        assert_eq!(hybrid_timestamp(now, None), now);
+        assert_eq!(hybrid_timestamp(now, Some(now - 1)), now);
+        assert_eq!(hybrid_timestamp(now, Some(now)), now + 1);
+        assert_eq!(
+            hybrid_timestamp(now, Some(max_timestamp(now))),
+            max_timestamp(now)
+        );
+        assert_eq!(hybrid_timestamp(now, Some(u64::MAX)), max_timestamp(now));
+
+        // a remote entry at exactly the bound is accepted, and local writes after it are
+        // still valid
+        let mut store = store::Store::memory();
+        let mut rng = rand::thread_rng();
+        let author1 = Author::new(&mut rng);
+        let author2 = Author::new(&mut rng);
+        let myspace = NamespaceSecret::new(&mut rng);
+        let mut replica = store.new_replica(myspace.clone())?;
+        let ahead = max_timestamp(system_time_now());
+        let entry =
+            SignedEntry::from_parts(&myspace, &author1, b"k", Record::from_data("a", ahead));
+        replica.insert_remote_entry(entry, [1u8; 32], ContentStatus::Complete)?;
+        replica.hash_and_insert(b"k", &author2, "b")?;
+        replica.hash_and_insert(b"other", &author2, "x")?;
+        drop(replica);
+        let entries = store
+            .get_many(myspace.id(), Query::author(author2.id()))?
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(entries.len(), 2);
+        for entry in entries {
+            assert!(entry.timestamp() >= ahead);
+            assert!(entry.timestamp() <= max_timestamp(system_time_now()));
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_replica_remove_memory() -> Result<()> {
         let alice_store = store::Store::memory();
@@ -2120,7 +2847,7 @@ mod tests {
         // test with actor
         let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
         let author = Author::new(&mut rng);
-        let handle = SyncHandle::spawn(store, None, "test".into());
+        let handle = SyncHandle::spawn(store, None, None, "test".into());
         let author = handle.import_author(author).await?;
         let namespace = NamespaceSecret::new(&mut rng);
         let id = namespace.id();
@@ -2130,7 +2857,14 @@ mod tests {
         handle.import_namespace(capability).await?;
         handle.open(namespace.id(), Default::default()).await?;
         let res = handle
-            .insert_local(id, author, b"foo".to_vec().into(), Hash::new(b"bar"), 3)
+            .insert_local(
+                id,
+                author,
+                b"foo".to_vec().into(),
+                Hash::new(b"bar"),
+                3,
+                None,
+            )
             .await;
         assert!(res.is_err());
 
@@ -2138,24 +2872,213 @@ mod tests {
         let capability = Capability::Write(namespace.clone());
         handle.import_namespace(capability).await?;
         let res = handle
-            .insert_local(id, author, b"foo".to_vec().into(), Hash::new(b"bar"), 3)
+            .insert_local(
+                id,
+                author,
+                b"foo".to_vec().into(),
+                Hash::new(b"bar"),
+                3,
+                None,
+            )
             .await;
         assert!(res.is_ok());
 
         // close and reopen - must still succeed
         handle.close(namespace.id()).await?;
         let res = handle
-            .insert_local(id, author, b"foo".to_vec().into(), Hash::new(b"bar"), 3)
+            .insert_local(
+                id,
+                author,
+                b"foo".to_vec().into(),
+                Hash::new(b"bar"),
+                3,
+                None,
+            )
             .await;
         assert!(res.is_err());
         handle.open(namespace.id(), Default::default()).await?;
         let res = handle
-            .insert_local(id, author, b"foo".to_vec().into(), Hash::new(b"bar"), 3)
+            .insert_local(
+                id,
+                author,
+                b"foo".to_vec().into(),
+                Hash::new(b"bar"),
+                3,
+                None,
+            )
             .await;
         assert!(res.is_ok());
         Ok(())
     }
 
+    #[test]
+    fn test_write_grant_memory() -> Result<()> {
+        let alice_store = store::Store::memory();
+        let bob_store = store::Store::memory();
+        test_write_grant(alice_store, bob_store)
+    }
+
+    #[test]
+    fn test_write_grant_fs() -> Result<()> {
+        let alice_dbfile = tempfile::NamedTempFile::new()?;
+        let alice_store = store::fs::Store::persistent(alice_dbfile.path())?;
+        let bob_dbfile = tempfile::NamedTempFile::new()?;
+        let bob_store = store::fs::Store::persistent(bob_dbfile.path())?;
+        test_write_grant(alice_store, bob_store)
+    }
+
+    fn test_write_grant(mut alice_store: Store, mut bob_store: Store) -> Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let alice = Author::new(&mut rng);
+        let bob = Author::new(&mut rng);
+        let carol = Author::new(&mut rng);
+        let namespace = NamespaceSecret::new(&mut rng);
+
+        // alice owns the namespace and allows bob to write below "shared/"
+        let mut alice_replica = alice_store.new_replica(namespace.clone())?;
+        alice_replica.hash_and_insert(b"alice", &alice, b"hi")?;
+        let grant = alice_replica.grant_write(bob.id(), b"shared/", None)?;
+        assert_eq!(grant.author(), bob.id());
+        assert_eq!(grant.prefix(), b"shared/");
+        assert_eq!(grant.expires(), None);
+        let expired = alice_replica.grant_write(carol.id(), b"", Some(1))?;
+        assert_eq!(expired.expires(), Some(1));
+
+        // bob only gets the grant, not the namespace secret
+        let capability = Capability::Delegated(grant);
+        assert!(matches!(capability.kind(), CapabilityKind::Read));
+        bob_store.import_namespace(capability)?;
+        let mut bob_replica = bob_store.open_replica(&namespace.id())?;
+        assert!(bob_replica.secret_key().is_err());
+        bob_replica.hash_and_insert(b"shared/bob", &bob, b"hello")?;
+        let res = bob_replica.hash_and_insert(b"private", &bob, b"hello");
+        assert!(matches!(
+            res,
+            Err(InsertError::Validation(ValidationFailure::NotAuthorized))
+        ));
+        let res = bob_replica.hash_and_insert(b"shared/carol", &carol, b"hello");
+        assert!(matches!(res, Err(InsertError::ReadOnly)));
+
+        // alice accepts bob's entry during sync
+        sync(&mut alice_replica, &mut bob_replica)?;
+        let entry = get_entry(&mut alice_store, namespace.id(), bob.id(), b"shared/bob")?;
+        assert_eq!(entry.content_hash(), Hash::new(b"hello"));
+        assert!(get_entry(&mut bob_store, namespace.id(), alice.id(), b"alice").is_ok());
+
+        // entries not covered by a grant are rejected
+        let mut alice_replica = alice_store.open_replica(&namespace.id())?;
+        let entry = Entry::new(
+            RecordIdentifier::new(namespace.id(), carol.id(), b"carol"),
+            Record::current_from_data(b"hello"),
+        );
+        let res = alice_replica.insert_remote_entry(
+            entry.sign_delegated(&carol),
+            [1u8; 32],
+            ContentStatus::Missing,
+        );
+        assert!(matches!(
+            res,
+            Err(InsertError::Validation(ValidationFailure::NotAuthorized))
+        ));
+
+        // after revoking, bob's new entries are rejected, and so are the entries he wrote before
+        // that alice did not see
+        let mut bob_replica = bob_store.open_replica(&namespace.id())?;
+        bob_replica.hash_and_insert(b"shared/unseen", &bob, b"hello")?;
+        assert_eq!(alice_replica.revoke_write(bob.id())?, 1);
+        assert_eq!(alice_replica.revoke_write(bob.id())?, 0);
+        bob_replica.hash_and_insert(b"shared/late", &bob, b"too late")?;
+        sync(&mut alice_replica, &mut bob_replica)?;
+        assert!(get_entry(&mut alice_store, namespace.id(), bob.id(), b"shared/bob").is_ok());
+        assert!(get_entry(&mut alice_store, namespace.id(), bob.id(), b"shared/unseen").is_err());
+        assert!(get_entry(&mut alice_store, namespace.id(), bob.id(), b"shared/late").is_err());
+        let mut bob_replica = bob_store.open_replica(&namespace.id())?;
+        let res = bob_replica.hash_and_insert(b"shared/bob", &bob, b"again");
+        assert!(matches!(res, Err(InsertError::ReadOnly)));
+
+        // new entries are rejected whatever their timestamp, also for existing keys
+        let mut alice_replica = alice_store.open_replica(&namespace.id())?;
+        for (key, timestamp) in [
+            (&b"shared/bob"[..], system_time_now()),
+            (b"shared/other", system_time_now()),
+            (b"shared/backdated", 1),
+        ] {
+            let entry = Entry::new(
+                RecordIdentifier::new(namespace.id(), bob.id(), key),
+                Record::new(Hash::new(b"late"), 4, timestamp),
+            );
+            let res = alice_replica.insert_remote_entry(
+                entry.sign_delegated(&bob),
+                [1u8; 32],
+                ContentStatus::Missing,
+            );
+            assert!(matches!(
+                res,
+                Err(InsertError::Validation(ValidationFailure::NotAuthorized))
+            ));
+        }
+
+        // the revoked grant is kept, so bob's entries that alice accepted are still accepted by
+        // peers that did not have them yet
+        let mut dave_store = store::Store::memory();
+        dave_store.import_namespace(Capability::Read(namespace.id()))?;
+        let mut dave_replica = dave_store.open_replica(&namespace.id())?;
+        let mut alice_replica = alice_store.open_replica(&namespace.id())?;
+        sync(&mut alice_replica, &mut dave_replica)?;
+        let entry = get_entry(&mut dave_store, namespace.id(), bob.id(), b"shared/bob")?;
+        assert_eq!(entry.content_hash(), Hash::new(b"hello"));
+        assert!(get_entry(&mut dave_store, namespace.id(), bob.id(), b"shared/unseen").is_err());
+        assert!(get_entry(&mut dave_store, namespace.id(), bob.id(), b"shared/late").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_revoke_write_many_entries() -> Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let bob = Author::new(&mut rng);
+        let namespace = NamespaceSecret::new(&mut rng);
+        let mut alice_store = store::Store::memory();
+        let mut bob_store = store::Store::memory();
+        let mut alice_replica = alice_store.new_replica(namespace.clone())?;
+        let grant = alice_replica.grant_write(bob.id(), b"", None)?;
+        bob_store.import_namespace(Capability::Delegated(grant))?;
+        let mut bob_replica = bob_store.open_replica(&namespace.id())?;
+        for i in 0..200 {
+            bob_replica.hash_and_insert(format!("{i}"), &bob, b"hello")?;
+        }
+        sync(&mut alice_replica, &mut bob_replica)?;
+        assert_eq!(alice_replica.revoke_write(bob.id())?, 1);
+
+        // the keys of the revocation and of the accepted entries have a fixed length, and the
+        // revocation commits to all accepted entries
+        let revocations = alice_store
+            .get_many(namespace.id(), Query::key_prefix(REVOCATION_KEY_PREFIX))?
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(revocations.len(), 201);
+        let (revoked, accepted): (Vec<_>, Vec<_>) = revocations
+            .iter()
+            .partition(|entry| entry.key().len() == REVOCATION_KEY_PREFIX.len() + 33);
+        assert_eq!(revoked.len(), 1);
+        let content_len = REVOCATION_KEY_PREFIX.len() + 33 + 200 * 32;
+        assert_eq!(revoked[0].content_len(), content_len as u64);
+        assert!(accepted
+            .iter()
+            .all(|entry| entry.key().len() == REVOCATION_KEY_PREFIX.len() + 65));
+
+        // all accepted entries are still synced to new peers
+        let mut carol_store = store::Store::memory();
+        carol_store.import_namespace(Capability::Read(namespace.id()))?;
+        let mut carol_replica = carol_store.open_replica(&namespace.id())?;
+        let mut alice_replica = alice_store.open_replica(&namespace.id())?;
+        sync(&mut alice_replica, &mut carol_replica)?;
+        drop(carol_replica);
+        let count = carol_store
+            .get_many(namespace.id(), Query::author(bob.id()))?
+            .count();
+        assert_eq!(count, 200);
+        Ok(())
+    }
+
     /// This tests that no events are emitted for entries received during sync which are obsolete
     /// (too old) by the time they are actually inserted in the store.
     #[test]
@@ -2250,28 +3173,6 @@ mod tests {
         replica.hash_and_insert("hi/moon", &a2, "a1")?;
         replica.hash_and_insert("hi", &a3, "a3")?;
 
-        struct QueryTester<'a> {
-            store: &'a mut Store,
-            namespace: NamespaceId,
-        }
-        impl<'a> QueryTester<'a> {
-            fn assert(&mut self, query: impl Into<Query>, expected: Vec<(&'static str, &Author)>) {
-                let query = query.into();
-                let actual = self
-                    .store
-                    .get_many(self.namespace, query.clone())
-                    .unwrap()
-                    .map(|e| e.map(|e| (String::from_utf8(e.key().to_vec()).unwrap(), e.author())))
-                    .collect::<Result<Vec<_>>>()
-                    .unwrap();
-                let expected = expected
-                    .into_iter()
-                    .map(|(key, author)| (key.to_string(), author.id()))
-                    .collect::<Vec<_>>();
-                assert_eq!(actual, expected, "query: {query:#?}")
-            }
-        }
-
         let mut qt = QueryTester {
             store: &mut store,
             namespace: namespace_id,
@@ -2402,6 +3303,147 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_replica_index_queries_mem() -> Result<()> {
+        let store = store::Store::memory();
+
+        test_replica_index_queries(store)
+    }
+
+    #[test]
+    fn test_replica_index_queries_fs() -> Result<()> {
+        let dbfile = tempfile::NamedTempFile::new()?;
+        let store = store::fs::Store::persistent(dbfile.path())?;
+
+        test_replica_index_queries(store)
+    }
+
+    fn test_replica_index_queries(mut store: Store) -> Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let namespace = NamespaceSecret::new(&mut rng);
+        let namespace_id = namespace.id();
+        let a1 = store.new_author(&mut rng)?;
+        let a2 = store.new_author(&mut rng)?;
+        assert!(a1.id() < a2.id());
+
+        let mut replica = store.new_replica(namespace.clone())?;
+        let mut insert = |key: &str, author: &Author, value: &str, timestamp: u64| {
+            let id = RecordIdentifier::new(namespace_id, author.id(), key);
+            let record = Record::from_data(value, timestamp);
+            let entry = Entry::new(id, record).sign(&namespace, author);
+            replica.insert_entry(entry, InsertOrigin::Local)
+        };
+        insert("a", &a1, "x", 10)?;
+        insert("b", &a2, "y", 20)?;
+        insert("c", &a1, "x", 30)?;
+        insert("a", &a2, "z", 40)?;
+        insert("d", &a1, "y", 50)?;
+        let hash_x = Hash::new("x");
+        let hash_y = Hash::new("y");
+
+        let mut qt = QueryTester {
+            store: &mut store,
+            namespace: namespace_id,
+        };
+
+        // timestamp index
+        qt.assert(
+            Query::updated_since(30),
+            vec![("c", &a1), ("a", &a2), ("d", &a1)],
+        );
+        qt.assert(
+            Query::updated_since(30).updated_before(50),
+            vec![("c", &a1), ("a", &a2)],
+        );
+        qt.assert(
+            Query::all().sort_by(SortBy::Timestamp, SortDirection::Desc),
+            vec![("d", &a1), ("a", &a2), ("c", &a1), ("b", &a2), ("a", &a1)],
+        );
+        qt.assert(
+            Query::updated_since(0).author(a2.id()),
+            vec![("b", &a2), ("a", &a2)],
+        );
+        qt.assert(Query::all().updated_before(20), vec![("a", &a1)]);
+
+        // key range
+        qt.assert(Query::key_range("b", "d"), vec![("c", &a1), ("b", &a2)]);
+        qt.assert(
+            Query::key_range("b", "d").sort_by(SortBy::KeyAuthor, SortDirection::Asc),
+            vec![("b", &a2), ("c", &a1)],
+        );
+        qt.assert(Query::author(a1.id()).key_range("a", "c"), vec![("a", &a1)]);
+        qt.assert(Query::key_range("d", "a"), vec![]);
+
+        // hash index
+        qt.assert(Query::content_hash(hash_x), vec![("a", &a1), ("c", &a1)]);
+        qt.assert(Query::content_hash(hash_y), vec![("d", &a1), ("b", &a2)]);
+        qt.assert(
+            Query::content_hash(hash_y).sort_by(SortBy::KeyAuthor, SortDirection::Asc),
+            vec![("b", &a2), ("d", &a1)],
+        );
+
+        // the timestamp and hash filters apply to the latest entry per key
+        qt.assert(
+            Query::single_latest_per_key().updated_since(30),
+            vec![("a", &a2), ("c", &a1), ("d", &a1)],
+        );
+        qt.assert(
+            Query::single_latest_per_key().content_hash(hash_x),
+            vec![("c", &a1)],
+        );
+        qt.assert(
+            Query::single_latest_per_key().key_range("b", "z"),
+            vec![("b", &a2), ("c", &a1), ("d", &a1)],
+        );
+
+        // the indexes are updated when entries are replaced or deleted
+        let mut replica = store.new_replica(namespace.clone())?;
+        let mut insert = |key: &str, author: &Author, value: &str, timestamp: u64| {
+            let id = RecordIdentifier::new(namespace_id, author.id(), key);
+            let record = Record::from_data(value, timestamp);
+            let entry = Entry::new(id, record).sign(&namespace, author);
+            replica.insert_entry(entry, InsertOrigin::Local)
+        };
+        insert("c", &a1, "y", 60)?;
+        replica.delete_prefix("d", &a1)?;
+        let mut qt = QueryTester {
+            store: &mut store,
+            namespace: namespace_id,
+        };
+        qt.assert(Query::content_hash(hash_x), vec![("a", &a1)]);
+        qt.assert(Query::content_hash(hash_y), vec![("c", &a1), ("b", &a2)]);
+        qt.assert(Query::updated_since(40), vec![("a", &a2), ("c", &a1)]);
+        qt.assert(
+            Query::updated_since(40).include_empty(),
+            vec![("a", &a2), ("c", &a1), ("d", &a1)],
+        );
+
+        Ok(())
+    }
+
+    struct QueryTester<'a> {
+        store: &'a mut Store,
+        namespace: NamespaceId,
+    }
+
+    impl<'a> QueryTester<'a> {
+        fn assert(&mut self, query: impl Into<Query>, expected: Vec<(&'static str, &Author)>) {
+            let query = query.into();
+            let actual = self
+                .store
+                .get_many(self.namespace, query.clone())
+                .unwrap()
+                .map(|e| e.map(|e| (String::from_utf8(e.key().to_vec()).unwrap(), e.author())))
+                .collect::<Result<Vec<_>>>()
+                .unwrap();
+            let expected = expected
+                .into_iter()
+                .map(|(key, author)| (key.to_string(), author.id()))
+                .collect::<Vec<_>>();
+            assert_eq!(actual, expected, "query: {query:#?}")
+        }
+    }
+
     #[test]
     fn test_dl_policies_mem() -> Result<()> {
         let mut store = store::Store::memory();
@@ -101,4 +101,32 @@ mod tests {
         ").unwrap();
         assert_eq_hex!(base32, expected);
     }
+
+    #[test]
+    fn test_ticket_delegated() {
+        let mut rng = rand::thread_rng();
+        let namespace = crate::NamespaceSecret::new(&mut rng);
+        let author = crate::Author::new(&mut rng);
+        let node_id = iroh_net::key::SecretKey::generate().public();
+        let grant = crate::WriteGrant::new(&namespace, author.id(), b"prefix/", Some(1234));
+        let ticket = DocTicket::new(
+            Capability::Delegated(grant.clone()),
+            vec![NodeAddr::from_parts(node_id, None, vec![])],
+        );
+        let parsed = DocTicket::from_str(&ticket.to_string()).unwrap();
+        let Capability::Delegated(parsed_grant) = parsed.capability else {
+            panic!("expected delegated capability");
+        };
+        assert_eq!(parsed_grant, grant);
+        assert_eq!(parsed_grant.namespace(), namespace.id());
+        assert_eq!(parsed_grant.author(), author.id());
+        assert_eq!(parsed_grant.prefix(), b"prefix/");
+        assert_eq!(parsed_grant.expires(), Some(1234));
+
+        // a grant that is not signed by the namespace is rejected
+        let forged =
+            crate::SignedEntry::from_entry(grant.entry().entry().clone(), &namespace, &author);
+        let bytes = postcard::to_stdvec(&forged).unwrap();
+        assert!(postcard::from_bytes::<crate::WriteGrant>(&bytes).is_err());
+    }
 }
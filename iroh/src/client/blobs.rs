@@ -60,12 +60,16 @@ use futures_util::SinkExt;
 use genawaiter::sync::{Co, Gen};
 use iroh_base::{node_addr::AddrInfoOptions, ticket::BlobTicket};
 use iroh_blobs::{
+    downloader::{IntentId, QueueSnapshot},
     export::ExportProgress as BytesExportProgress,
-    format::collection::{Collection, SimpleStore},
+    format::{
+        chunked::ChunkerConfig,
+        collection::{Collection, SimpleStore},
+    },
     get::db::DownloadProgress as BytesDownloadProgress,
-    store::{ConsistencyCheckProgress, ExportFormat, ExportMode, ValidateProgress},
+    store::{ConsistencyCheckProgress, ExportFormat, ExportMode, StoreStats, ValidateProgress},
     util::SetTagOption,
-    BlobFormat, Hash, Tag,
+    BlobFormat, Hash, HashAndFormat, Tag,
 };
 use iroh_net::NodeAddr;
 use portable_atomic::{AtomicU64, Ordering};
@@ -78,9 +82,10 @@ use tracing::warn;
 
 use crate::rpc_protocol::blobs::{
     AddPathRequest, AddStreamRequest, AddStreamUpdate, ConsistencyCheckRequest,
-    CreateCollectionRequest, CreateCollectionResponse, DeleteRequest, DownloadRequest,
-    ExportRequest, ListIncompleteRequest, ListRequest, ReadAtRequest, ReadAtResponse,
-    ValidateRequest,
+    CreateCollectionRequest, CreateCollectionResponse, DeleteRequest, DownloadQueueRequest,
+    DownloadRequest, ExportArchiveRequest, ExportRequest, ImportArchiveRequest,
+    ImportArchiveResponse, ListIncompleteRequest, ListRequest, ReadAtLazyRequest, ReadAtRequest,
+    ReadAtResponse, SetDownloadPriorityRequest, StoreStatsRequest, ValidateRequest,
 };
 use crate::rpc_protocol::node::StatusRequest;
 
@@ -141,6 +146,31 @@ impl Client {
             .await
     }
 
+    /// Read a single blob, fetching missing ranges from `nodes` as they are read.
+    ///
+    /// See [`Self::read_at_lazy`] for details.
+    pub async fn read_lazy(&self, hash: Hash, nodes: Vec<NodeAddr>) -> Result<Reader> {
+        Reader::from_rpc_read_at_lazy(&self.rpc, hash, 0, None, LazyReadOptions::new(nodes)).await
+    }
+
+    /// Read offset + len from a single blob, fetching missing ranges from other nodes.
+    ///
+    /// Unlike [`Self::read_at`], this works for blobs that are missing or incomplete in the
+    /// local store. Ranges that are not present are requested from the nodes in
+    /// [`LazyReadOptions::nodes`] right before they are read, together with
+    /// [`LazyReadOptions::prefetch`] bytes ahead. Fetched ranges are kept in the local store.
+    ///
+    /// If `len` is `None` it will read the full blob.
+    pub async fn read_at_lazy(
+        &self,
+        hash: Hash,
+        offset: u64,
+        len: Option<usize>,
+        opts: LazyReadOptions,
+    ) -> Result<Reader> {
+        Reader::from_rpc_read_at_lazy(&self.rpc, hash, offset, len, opts).await
+    }
+
     /// Import a blob from a filesystem path.
     ///
     /// `path` should be an absolute path valid for the file system on which
@@ -154,6 +184,35 @@ impl Client {
         tag: SetTagOption,
         wrap: WrapOption,
     ) -> Result<AddProgress> {
+        self.add_from_path_with_opts(
+            path,
+            AddPathOptions {
+                in_place,
+                tag,
+                wrap,
+                chunking: None,
+                meta: false,
+            },
+        )
+        .await
+    }
+
+    /// Import a blob from a filesystem path, with additional options.
+    ///
+    /// `path` should be an absolute path valid for the file system on which
+    /// the node runs.
+    pub async fn add_from_path_with_opts(
+        &self,
+        path: PathBuf,
+        opts: AddPathOptions,
+    ) -> Result<AddProgress> {
+        let AddPathOptions {
+            in_place,
+            tag,
+            wrap,
+            chunking,
+            meta,
+        } = opts;
         let stream = self
             .rpc
             .server_streaming(AddPathRequest {
@@ -161,6 +220,8 @@ impl Client {
                 in_place,
                 tag,
                 wrap,
+                chunking,
+                meta,
             })
             .await?;
         Ok(AddProgress::new(stream))
@@ -279,6 +340,7 @@ impl Client {
                 nodes: vec![node],
                 tag: SetTagOption::Auto,
                 mode: DownloadMode::Queued,
+                priority: 0,
             },
         )
         .await
@@ -293,6 +355,7 @@ impl Client {
                 nodes: vec![node],
                 tag: SetTagOption::Auto,
                 mode: DownloadMode::Queued,
+                priority: 0,
             },
         )
         .await
@@ -309,6 +372,7 @@ impl Client {
             nodes,
             tag,
             mode,
+            priority,
         } = opts;
         let stream = self
             .rpc
@@ -318,6 +382,7 @@ impl Client {
                 nodes,
                 tag,
                 mode,
+                priority,
             })
             .await?;
         Ok(DownloadProgress::new(
@@ -325,6 +390,22 @@ impl Client {
         ))
     }
 
+    /// Get a snapshot of the active, queued and parked downloads of the download queue.
+    pub async fn download_queue(&self) -> Result<QueueSnapshot> {
+        let snapshot = self.rpc.rpc(DownloadQueueRequest).await??;
+        Ok(snapshot)
+    }
+
+    /// Change the priority of a download intent in the download queue.
+    ///
+    /// The intents of queued downloads are listed in the [`download_queue`](Self::download_queue).
+    pub async fn set_download_priority(&self, intent: IntentId, priority: i32) -> Result<()> {
+        self.rpc
+            .rpc(SetDownloadPriorityRequest { intent, priority })
+            .await??;
+        Ok(())
+    }
+
     /// Export a blob from the internal blob store to a path on the node's filesystem.
     ///
     /// `destination` should be an writeable, absolute path on the local node's filesystem.
@@ -353,6 +434,43 @@ impl Client {
         ))
     }
 
+    /// Export a blob, or a hash seq and all its children, to an archive file.
+    ///
+    /// The archive contains the verifiable bao encoding of the data, so it can be
+    /// moved to another node by other means and imported there with
+    /// [`Self::import_archive`]. See [`iroh_blobs::store::archive`] for details.
+    ///
+    /// `destination` should be a writeable, absolute path on the local node's filesystem.
+    pub async fn export_archive(
+        &self,
+        hash: Hash,
+        format: BlobFormat,
+        destination: PathBuf,
+    ) -> Result<()> {
+        self.rpc
+            .rpc(ExportArchiveRequest {
+                hash,
+                format,
+                path: destination,
+            })
+            .await??;
+        Ok(())
+    }
+
+    /// Validate and import the data of an archive file created with [`Self::export_archive`].
+    ///
+    /// `path` should be an absolute path on the local node's filesystem. Returns the
+    /// root hash and format of the archive, and the tag the data was tagged with.
+    pub async fn import_archive(
+        &self,
+        path: PathBuf,
+        tag: SetTagOption,
+    ) -> Result<(HashAndFormat, Tag)> {
+        let ImportArchiveResponse { hash, format, tag } =
+            self.rpc.rpc(ImportArchiveRequest { path, tag }).await??;
+        Ok((HashAndFormat { hash, format }, tag))
+    }
+
     /// List all complete blobs.
     pub async fn list(&self) -> Result<impl Stream<Item = Result<BlobInfo>>> {
         let stream = self.rpc.server_streaming(ListRequest).await?;
@@ -398,6 +516,13 @@ impl Client {
         Ok(())
     }
 
+    /// Get counts and sizes of the entries in the blob store, and the size of
+    /// the data that is reachable from each tag.
+    pub async fn stats(&self) -> Result<StoreStats> {
+        let stats = self.rpc.rpc(StoreStatsRequest).await??;
+        Ok(stats)
+    }
+
     /// Delete a blob.
     ///
     /// **Warning**: this operation deletes the blob from the local store even
@@ -447,7 +572,7 @@ impl SimpleStore for Client {
 }
 
 /// Whether to wrap the added data in a collection.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum WrapOption {
     /// Do not wrap the file or directory.
     NoWrap,
@@ -549,8 +674,12 @@ impl AddProgress {
         let stream = stream.map(move |item| match item {
             Ok(item) => {
                 let item = item.into();
-                if let iroh_blobs::provider::AddProgress::Found { size, .. } = &item {
-                    total_size.fetch_add(*size, Ordering::Relaxed);
+                match &item {
+                    iroh_blobs::provider::AddProgress::Found { size, .. }
+                    | iroh_blobs::provider::AddProgress::Skipped { size, .. } => {
+                        total_size.fetch_add(*size, Ordering::Relaxed);
+                    }
+                    _ => {}
                 }
                 Ok(item)
             }
@@ -843,8 +972,33 @@ impl Reader {
         let stream = rpc
             .server_streaming(ReadAtRequest { hash, offset, len })
             .await?;
-        let mut stream = flatten(stream);
+        Self::from_read_at_stream(flatten(stream), offset, len).await
+    }
+
+    async fn from_rpc_read_at_lazy(
+        rpc: &RpcClient,
+        hash: Hash,
+        offset: u64,
+        len: Option<usize>,
+        opts: LazyReadOptions,
+    ) -> anyhow::Result<Self> {
+        let stream = rpc
+            .server_streaming(ReadAtLazyRequest {
+                hash,
+                offset,
+                len,
+                nodes: opts.nodes,
+                prefetch: opts.prefetch,
+            })
+            .await?;
+        Self::from_read_at_stream(flatten(stream), offset, len).await
+    }
 
+    async fn from_read_at_stream(
+        mut stream: impl Stream<Item = anyhow::Result<ReadAtResponse>> + Send + Sync + Unpin + 'static,
+        offset: u64,
+        len: Option<usize>,
+    ) -> anyhow::Result<Self> {
         let (size, is_complete) = match stream.next().await {
             Some(Ok(ReadAtResponse::Entry { size, is_complete })) => (size, is_complete),
             Some(Err(err)) => return Err(err),
@@ -905,6 +1059,30 @@ impl Stream for Reader {
     }
 }
 
+/// Options to configure adding data from a path, see [`Client::add_from_path_with_opts`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddPathOptions {
+    /// If true, iroh assumes that the data will not change and shares it in place
+    /// without copying it to the iroh data directory.
+    pub in_place: bool,
+    /// Tag to tag the data with.
+    pub tag: SetTagOption,
+    /// Whether to wrap the added data in a collection.
+    pub wrap: WrapOption,
+    /// Split a single file into content defined chunks with this config.
+    ///
+    /// Data that is shared with other chunked imports is then only stored once. The
+    /// result is a [`iroh_blobs::format::chunked::ChunkedBlob`], with a
+    /// [`BlobFormat::HashSeq`] root.
+    pub chunking: Option<ChunkerConfig>,
+    /// Keep file modes, modification times, symlinks and empty directories
+    /// when adding a directory.
+    ///
+    /// The hash of the resulting collection then depends on this metadata, so
+    /// adding the same files twice can give different hashes.
+    pub meta: bool,
+}
+
 /// Options to configure a download request.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DownloadOptions {
@@ -921,6 +1099,38 @@ pub struct DownloadOptions {
     pub tag: SetTagOption,
     /// Whether to directly start the download or add it to the download queue.
     pub mode: DownloadMode,
+    /// The priority of the download in the download queue.
+    ///
+    /// Queued downloads with a higher priority are started first. The default priority is `0`.
+    /// Only used if `mode` is set to [`DownloadMode::Queued`].
+    pub priority: i32,
+}
+
+/// Options to configure a lazy read, see [`Client::read_at_lazy`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LazyReadOptions {
+    /// Nodes to fetch missing ranges from.
+    ///
+    /// They are tried in order, and a node is only dropped once a request to it fails.
+    pub nodes: Vec<NodeAddr>,
+    /// Number of bytes to fetch ahead of the current read position.
+    ///
+    /// Whenever data at the read position is missing, this many bytes past the end of the
+    /// current read are fetched along with it, so sequential reads don't need a request each.
+    pub prefetch: u64,
+}
+
+impl LazyReadOptions {
+    /// The default number of bytes to fetch ahead.
+    pub const DEFAULT_PREFETCH: u64 = 1024 * 1024;
+
+    /// Create options to fetch from `nodes` with the default prefetch.
+    pub fn new(nodes: Vec<NodeAddr>) -> Self {
+        Self {
+            nodes,
+            prefetch: Self::DEFAULT_PREFETCH,
+        }
+    }
 }
 
 /// Set the mode for whether to directly start the download or add it to the download queue.
@@ -933,7 +1143,8 @@ pub enum DownloadMode {
     Direct,
     /// Queue the download.
     ///
-    /// The download queue will be processed in-order, while respecting the downloader concurrency limits.
+    /// The download queue will be processed in order of priority, while respecting the downloader
+    /// concurrency limits.
     Queued,
 }
 
@@ -1128,6 +1339,157 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_blob_read_at_lazy() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+
+        let provider = crate::node::Node::memory().bind_port(0).spawn().await?;
+        let node = crate::node::Node::memory().bind_port(0).spawn().await?;
+
+        let mut buf = vec![0u8; 1024 * 1024 * 3 + 17];
+        rand::thread_rng().fill_bytes(&mut buf);
+        let hash = provider
+            .blobs()
+            .add_bytes(buf.clone())
+            .await
+            .context("add bytes")?
+            .hash;
+        let nodes = vec![provider.node_addr().await?];
+
+        // nothing is available locally
+        assert!(node.blobs().read_at(hash, 0, Some(10)).await.is_err());
+
+        // a range in the middle, without prefetching
+        let opts = LazyReadOptions {
+            nodes: nodes.clone(),
+            prefetch: 0,
+        };
+        let mut reader = node
+            .blobs()
+            .read_at_lazy(hash, 1024 * 1024 + 5, Some(100_000), opts)
+            .await?;
+        assert_eq!(reader.size(), buf.len() as u64);
+        assert!(!reader.is_complete());
+        let res = reader.read_to_bytes().await?;
+        assert_eq!(&res[..], &buf[1024 * 1024 + 5..1024 * 1024 + 5 + 100_000]);
+
+        // the tail, with a length beyond the end of the blob
+        let res = node
+            .blobs()
+            .read_at_lazy(
+                hash,
+                3 * 1024 * 1024,
+                Some(1024),
+                LazyReadOptions::new(nodes.clone()),
+            )
+            .await?
+            .read_to_bytes()
+            .await?;
+        assert_eq!(&res[..], &buf[3 * 1024 * 1024..]);
+
+        // the whole blob, which makes it complete locally
+        let res = node
+            .blobs()
+            .read_lazy(hash, nodes)
+            .await?
+            .read_to_bytes()
+            .await?;
+        assert_eq!(&res[..], &buf[..]);
+
+        provider.shutdown().await?;
+        let res = node.blobs().read_to_bytes(hash).await?;
+        assert_eq!(&res[..], &buf[..]);
+        let res = node
+            .blobs()
+            .read_at_lazy(hash, 10, Some(20), LazyReadOptions::new(vec![]))
+            .await?
+            .read_to_bytes()
+            .await?;
+        assert_eq!(&res[..], &buf[10..30]);
+
+        node.shutdown().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_blob_stats() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+
+        let node = crate::node::Node::memory().spawn().await?;
+        let a = node.blobs().add_bytes(vec![1u8; 1024 * 100]).await?.hash;
+        let b = node.blobs().add_bytes(&b"hello"[..]).await?.hash;
+        let collection: Collection = [("a", a), ("b", b)].into_iter().collect();
+        let (hash, tag) = node
+            .blobs()
+            .create_collection(collection, SetTagOption::Auto, Vec::new())
+            .await?;
+
+        let stats = node.blobs().stats().await?;
+        // the two blobs, the collection metadata and the hash seq
+        assert_eq!(stats.entries.owned.count, 4);
+        assert_eq!(stats.entries.partial.count, 0);
+        assert!(stats.entries.owned.data_size > 1024 * 100);
+        assert!(stats.entries.owned.outboard_size > 0);
+        let tag_stats = stats
+            .tags
+            .iter()
+            .find(|stats| stats.tag == tag)
+            .context("tag not found")?;
+        assert_eq!(tag_stats.content, HashAndFormat::hash_seq(hash));
+        assert_eq!(tag_stats.blobs, 4);
+        assert_eq!(tag_stats.missing, 0);
+        assert!(tag_stats.size > 1024 * 100 + 5);
+
+        node.shutdown().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_blob_export_import_archive() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+
+        let provider = crate::node::Node::memory().spawn().await?;
+        let node = crate::node::Node::memory().spawn().await?;
+
+        let mut buf = vec![0u8; 1024 * 200];
+        rand::thread_rng().fill_bytes(&mut buf);
+        let a = provider.blobs().add_bytes(buf.clone()).await?.hash;
+        let b = provider.blobs().add_bytes(&b"hello"[..]).await?.hash;
+        let collection: Collection = [("a", a), ("b", b)].into_iter().collect();
+        let (hash, _) = provider
+            .blobs()
+            .create_collection(collection.clone(), SetTagOption::Auto, Vec::new())
+            .await?;
+
+        let temp_dir = tempfile::tempdir().context("tempdir")?;
+        let path = temp_dir.path().join("archive");
+        provider
+            .blobs()
+            .export_archive(hash, BlobFormat::HashSeq, path.clone())
+            .await?;
+        let (content, tag) = node
+            .blobs()
+            .import_archive(path, SetTagOption::Named("imported".into()))
+            .await?;
+        assert_eq!(content, HashAndFormat::hash_seq(hash));
+        assert_eq!(tag, Tag::from("imported"));
+        assert_eq!(node.blobs().get_collection(hash).await?, collection);
+        assert_eq!(node.blobs().read_to_bytes(a).await?, buf);
+
+        // exporting missing data fails
+        let path = temp_dir.path().join("missing");
+        let missing = Hash::new(b"missing");
+        assert!(node
+            .blobs()
+            .export_archive(missing, BlobFormat::Raw, path)
+            .await
+            .is_err());
+
+        provider.shutdown().await?;
+        node.shutdown().await?;
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_blob_get_collection() -> Result<()> {
         let _guard = iroh_test::logging::setup();
@@ -1196,6 +1558,241 @@ mod tests {
         Ok(())
     }
 
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_blob_add_export_dir_meta() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        use iroh_blobs::format::collection::EntryKind;
+
+        let _guard = iroh_test::logging::setup();
+
+        let node = crate::node::Node::memory().spawn().await?;
+        let temp_dir = tempfile::tempdir().context("tempdir")?;
+
+        let in_root = temp_dir.path().join("in");
+        std::fs::create_dir_all(in_root.join("empty"))?;
+        std::fs::write(in_root.join("run.sh"), b"#!/bin/sh")?;
+        std::fs::set_permissions(
+            in_root.join("run.sh"),
+            std::fs::Permissions::from_mode(0o755),
+        )?;
+        let mtime = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        std::fs::File::open(in_root.join("run.sh"))?.set_modified(mtime)?;
+        std::os::unix::fs::symlink("run.sh", in_root.join("link"))?;
+        // a read only directory with a symlink in it
+        std::fs::create_dir_all(in_root.join("ro"))?;
+        std::os::unix::fs::symlink("../run.sh", in_root.join("ro/link"))?;
+        std::fs::File::open(in_root.join("ro"))?.set_modified(mtime)?;
+        std::fs::set_permissions(in_root.join("ro"), std::fs::Permissions::from_mode(0o555))?;
+
+        let client = node.client();
+        // metadata is only kept when asked for
+        let outcome = client
+            .blobs()
+            .add_from_path(
+                in_root.clone(),
+                false,
+                SetTagOption::Auto,
+                WrapOption::NoWrap,
+            )
+            .await?
+            .finish()
+            .await?;
+        let collection = client.blobs().get_collection(outcome.hash).await?;
+        assert!(collection.meta("empty").is_none());
+
+        let opts = AddPathOptions {
+            in_place: false,
+            tag: SetTagOption::Auto,
+            wrap: WrapOption::NoWrap,
+            chunking: None,
+            meta: true,
+        };
+        let outcome = client
+            .blobs()
+            .add_from_path_with_opts(in_root, opts)
+            .await?
+            .finish()
+            .await?;
+        let collection = client.blobs().get_collection(outcome.hash).await?;
+        // only the file has content, the rest is metadata
+        assert_eq!(collection.len(), 1);
+        assert_eq!(
+            collection.meta("empty").map(|x| &x.kind),
+            Some(&EntryKind::Dir)
+        );
+
+        let out_root = temp_dir.path().join("out");
+        client
+            .blobs()
+            .export(
+                outcome.hash,
+                out_root.clone(),
+                ExportFormat::Collection,
+                ExportMode::Copy,
+            )
+            .await?
+            .finish()
+            .await?;
+        let file = std::fs::metadata(out_root.join("run.sh"))?;
+        assert_eq!(file.permissions().mode() & 0o777, 0o755);
+        assert_eq!(file.modified()?, mtime);
+        assert!(out_root.join("empty").is_dir());
+        assert_eq!(
+            std::fs::read_link(out_root.join("link"))?,
+            PathBuf::from("run.sh")
+        );
+        let dir = std::fs::metadata(out_root.join("ro"))?;
+        assert_eq!(dir.permissions().mode() & 0o777, 0o555);
+        assert_eq!(dir.modified()?, mtime);
+        assert_eq!(
+            std::fs::read_link(out_root.join("ro/link"))?,
+            PathBuf::from("../run.sh")
+        );
+        // make the directories removable again
+        for dir in [temp_dir.path().join("in/ro"), out_root.join("ro")] {
+            std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o755))?;
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_blob_add_from_path_chunked() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+
+        let node = crate::node::Node::memory().spawn().await?;
+        let temp_dir = tempfile::tempdir().context("tempdir")?;
+        let in_path = temp_dir.path().join("in");
+        let mut data = vec![0u8; 1024 * 1024];
+        rand::thread_rng().fill_bytes(&mut data);
+        std::fs::write(&in_path, &data)?;
+
+        let client = node.client();
+        let config = ChunkerConfig {
+            min_size: 1024,
+            avg_size: 4096,
+            max_size: 16384,
+        };
+        let opts = |chunking| AddPathOptions {
+            in_place: false,
+            tag: SetTagOption::Auto,
+            wrap: WrapOption::NoWrap,
+            chunking,
+            meta: false,
+        };
+        let outcome = client
+            .blobs()
+            .add_from_path_with_opts(in_path.clone(), opts(Some(config)))
+            .await?
+            .finish()
+            .await?;
+        assert_eq!(outcome.format, BlobFormat::HashSeq);
+
+        let out_path = temp_dir.path().join("out");
+        client
+            .blobs()
+            .export(
+                outcome.hash,
+                out_path.clone(),
+                ExportFormat::Chunked,
+                ExportMode::Copy,
+            )
+            .await?
+            .finish()
+            .await?;
+        assert_eq!(std::fs::read(out_path)?, data);
+
+        // invalid configs are rejected
+        let config = ChunkerConfig {
+            avg_size: 3000,
+            ..config
+        };
+        let res = client
+            .blobs()
+            .add_from_path_with_opts(in_path, opts(Some(config)))
+            .await?
+            .finish()
+            .await;
+        assert!(res.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_blob_add_from_path_incremental() -> Result<()> {
+        use iroh_blobs::provider::AddProgress as Event;
+
+        let _guard = iroh_test::logging::setup();
+
+        let node = crate::node::Node::memory().spawn().await?;
+        let temp_dir = tempfile::tempdir().context("tempdir")?;
+        let in_root = temp_dir.path().join("in");
+        std::fs::create_dir_all(&in_root)?;
+        for i in 0..5 {
+            std::fs::write(in_root.join(format!("file-{i}")), vec![i as u8; 10_000])?;
+        }
+
+        let client = node.client();
+        // returns the hash of the collection and the names of the hashed and skipped files
+        let add = |root: PathBuf, in_place: bool| async move {
+            let mut stream = client
+                .blobs()
+                .add_from_path(root, in_place, SetTagOption::Auto, WrapOption::NoWrap)
+                .await?;
+            let mut hashed = Vec::new();
+            let mut skipped = Vec::new();
+            while let Some(item) = stream.next().await {
+                match item? {
+                    Event::Found { name, .. } => hashed.push(name),
+                    Event::Skipped { name, .. } => skipped.push(name),
+                    Event::AllDone { hash, .. } => return Ok((hash, hashed, skipped)),
+                    Event::Abort(err) => return Err(err.into()),
+                    _ => {}
+                }
+            }
+            anyhow::bail!("add stream ended early")
+        };
+
+        let (hash0, hashed, skipped) = add(in_root.clone(), false).await?;
+        assert_eq!(hashed.len(), 5);
+        assert!(skipped.is_empty());
+
+        // nothing changed, nothing is hashed again
+        let (hash1, hashed, skipped) = add(in_root.clone(), false).await?;
+        assert_eq!(hash0, hash1);
+        assert!(hashed.is_empty());
+        assert_eq!(skipped.len(), 5);
+
+        // only the changed file is hashed again
+        std::fs::write(in_root.join("file-2"), b"changed")?;
+        let (hash2, hashed, skipped) = add(in_root.clone(), false).await?;
+        assert_ne!(hash1, hash2);
+        assert_eq!(hashed, vec![in_root.join("file-2").display().to_string()]);
+        assert_eq!(skipped.len(), 4);
+        let collection = client.blobs().get_collection(hash2).await?;
+        let (_, changed) = collection
+            .iter()
+            .find(|(name, _)| name == "file-2")
+            .context("missing file-2")?;
+        assert_eq!(
+            client.blobs().read_to_bytes(*changed).await?,
+            &b"changed"[..]
+        );
+
+        // files whose data is gone from the store are hashed again
+        client.blobs().delete_blob(*changed).await?;
+        let (_, hashed, _) = add(in_root.clone(), false).await?;
+        assert_eq!(hashed, vec![in_root.join("file-2").display().to_string()]);
+
+        // adding in place is a different import, so no file is skipped
+        let (_, hashed, skipped) = add(in_root.clone(), true).await?;
+        assert_eq!(hashed.len(), 5);
+        assert!(skipped.is_empty());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_blob_share() -> Result<()> {
         let _guard = iroh_test::logging::setup();
@@ -1246,4 +1843,23 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_blob_download_queue() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+
+        let node = crate::node::Node::memory().spawn().await?;
+        let client = node.client();
+
+        let snapshot = client.blobs().download_queue().await?;
+        assert!(snapshot.active.is_empty());
+        assert!(snapshot.queued.is_empty());
+        assert!(snapshot.parked.is_empty());
+
+        // there is no such intent
+        let res = client.blobs().set_download_priority(IntentId(0), 1).await;
+        assert!(res.is_err());
+
+        Ok(())
+    }
 }
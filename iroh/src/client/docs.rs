@@ -27,10 +27,10 @@ use serde::{Deserialize, Serialize};
 
 use crate::rpc_protocol::docs::{
     CloseRequest, CreateRequest, DelRequest, DelResponse, DocListRequest, DocSubscribeRequest,
-    DropRequest, ExportFileRequest, GetDownloadPolicyRequest, GetExactRequest, GetManyRequest,
-    GetSyncPeersRequest, ImportFileRequest, ImportRequest, LeaveRequest, OpenRequest,
-    SetDownloadPolicyRequest, SetHashRequest, SetRequest, ShareRequest, StartSyncRequest,
-    StatusRequest,
+    DropRequest, ExportFileRequest, GetDownloadPolicyRequest, GetExactRequest, GetHeadsRequest,
+    GetManyRequest, GetSyncPeersRequest, ImportFileRequest, ImportRequest, LeaveRequest,
+    OpenRequest, RevokeWriteRequest, SetDownloadPolicyRequest, SetHashRequest, SetRequest,
+    ShareDelegatedRequest, ShareRequest, StartSyncRequest, StatusRequest,
 };
 use crate::rpc_protocol::RpcService;
 
@@ -190,6 +190,21 @@ impl Doc {
         author_id: AuthorId,
         key: impl Into<Bytes>,
         value: impl Into<Bytes>,
+    ) -> Result<Hash> {
+        self.set_bytes_with_expiry(author_id, key, value, None)
+            .await
+    }
+
+    /// Set the content of a key to a byte array, with an optional expiry.
+    ///
+    /// `expires` is a timestamp in microseconds since the Unix epoch. Once it has passed, the
+    /// entry is no longer returned or synced, and is eventually removed from the document.
+    pub async fn set_bytes_with_expiry(
+        &self,
+        author_id: AuthorId,
+        key: impl Into<Bytes>,
+        value: impl Into<Bytes>,
+        expires: Option<u64>,
     ) -> Result<Hash> {
         self.ensure_open()?;
         let res = self
@@ -198,6 +213,7 @@ impl Doc {
                 author_id,
                 key: key.into(),
                 value: value.into(),
+                expires,
             })
             .await??;
         Ok(res.entry.content_hash())
@@ -306,6 +322,23 @@ impl Doc {
         Ok(res.entry.map(|entry| entry.into()))
     }
 
+    /// Get the entries of all authors for a key, newest first.
+    ///
+    /// These are the concurrent values of the key: each author has at most one entry per key,
+    /// and entries of different authors do not replace each other. The first entry is the one
+    /// returned by queries for the latest entry per key. Empty entries (deletion markers) are
+    /// included.
+    pub async fn get_heads(&self, key: impl AsRef<[u8]>) -> Result<Vec<Entry>> {
+        self.ensure_open()?;
+        let res = self
+            .rpc(GetHeadsRequest {
+                doc_id: self.id(),
+                key: key.as_ref().to_vec().into(),
+            })
+            .await??;
+        Ok(res.entries.into_iter().map(Entry::from).collect())
+    }
+
     /// Get entries.
     pub async fn get_many(
         &self,
@@ -345,6 +378,51 @@ impl Doc {
         Ok(res.0)
     }
 
+    /// Allow `author` to write entries with keys starting with `prefix` to this document, and
+    /// share the grant over a ticket.
+    ///
+    /// The ticket gives read access to the document, and write access for `author` only. The
+    /// node importing it must have the secret key of `author`. If `expires` is set, only entries
+    /// with a timestamp (in microseconds since the Unix epoch) before `expires` are covered.
+    ///
+    /// Fails if this node does not have write access to the document.
+    pub async fn share_delegated(
+        &self,
+        author: AuthorId,
+        prefix: impl Into<Bytes>,
+        expires: Option<u64>,
+        addr_options: AddrInfoOptions,
+    ) -> anyhow::Result<DocTicket> {
+        self.ensure_open()?;
+        let res = self
+            .rpc(ShareDelegatedRequest {
+                doc_id: self.id(),
+                author_id: author,
+                prefix: prefix.into(),
+                expires,
+                addr_options,
+            })
+            .await??;
+        Ok(res.0)
+    }
+
+    /// Revoke all write grants of `author` for this document.
+    ///
+    /// The entries of `author` that this node has at the time of the revocation stay valid. All
+    /// other entries of `author` are rejected, whatever their timestamp.
+    ///
+    /// Returns the number of grants revoked.
+    pub async fn revoke_write(&self, author: AuthorId) -> Result<usize> {
+        self.ensure_open()?;
+        let res = self
+            .rpc(RevokeWriteRequest {
+                doc_id: self.id(),
+                author_id: author,
+            })
+            .await??;
+        Ok(res.removed)
+    }
+
     /// Start to sync this document with a list of peers.
     pub async fn start_sync(&self, peers: Vec<NodeAddr>) -> Result<()> {
         self.ensure_open()?;
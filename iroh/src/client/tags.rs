@@ -7,16 +7,24 @@
 //!
 //! [Client::list] can be used to list all tags.
 //! [Client::list_hash_seq] can be used to list all tags with a hash_seq format.
+//! [Client::list_prefix] can be used to list all tags that start with a prefix.
 //!
-//! [Client::delete] can be used to delete a tag.
+//! [Client::delete] can be used to delete a tag, and [Client::delete_prefix]
+//! to delete all tags that start with a prefix.
+//!
+//! [Client::update] can be used to atomically change several tags, only if
+//! they have the expected values.
+//!
+//! Applications that share a node can use a prefix per application, e.g.
+//! `myapp/`, as their namespace of tags.
 use anyhow::Result;
 use futures_lite::{Stream, StreamExt};
-use iroh_blobs::{BlobFormat, Hash, Tag};
+use iroh_blobs::{util::TagUpdate, BlobFormat, Hash, Tag};
 use ref_cast::RefCast;
 use serde::{Deserialize, Serialize};
 
 use super::RpcClient;
-use crate::rpc_protocol::tags::{DeleteRequest, ListRequest};
+use crate::rpc_protocol::tags::{DeletePrefixRequest, DeleteRequest, ListRequest, UpdateRequest};
 
 /// Iroh tags client.
 #[derive(Debug, Clone, RefCast)]
@@ -38,11 +46,37 @@ impl Client {
         Ok(stream.map(|res| res.map_err(anyhow::Error::from)))
     }
 
+    /// List all tags that start with `prefix`.
+    pub async fn list_prefix(
+        &self,
+        prefix: impl Into<Tag>,
+    ) -> Result<impl Stream<Item = Result<TagInfo>>> {
+        let request = ListRequest::all().with_prefix(prefix.into());
+        let stream = self.rpc.server_streaming(request).await?;
+        Ok(stream.map(|res| res.map_err(anyhow::Error::from)))
+    }
+
     /// Delete a tag.
     pub async fn delete(&self, name: Tag) -> Result<()> {
         self.rpc.rpc(DeleteRequest { name }).await??;
         Ok(())
     }
+
+    /// Delete all tags that start with `prefix`, returning the number of deleted tags.
+    pub async fn delete_prefix(&self, prefix: impl Into<Tag>) -> Result<u64> {
+        let prefix = prefix.into();
+        let count = self.rpc.rpc(DeletePrefixRequest { prefix }).await??;
+        Ok(count)
+    }
+
+    /// Atomically apply a batch of tag changes.
+    ///
+    /// Returns `false` without changing anything if the expectation of any of
+    /// the changes does not hold, see [`TagUpdate::if_equals`].
+    pub async fn update(&self, updates: Vec<TagUpdate>) -> Result<bool> {
+        let applied = self.rpc.rpc(UpdateRequest { updates }).await??;
+        Ok(applied)
+    }
 }
 
 /// Information about a tag.
@@ -55,3 +89,66 @@ pub struct TagInfo {
     /// Hash of the data
     pub hash: Hash,
 }
+
+#[cfg(test)]
+mod tests {
+    use futures_lite::StreamExt;
+    use iroh_blobs::HashAndFormat;
+
+    use crate::node::Node;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tags_prefix_and_update() -> Result<()> {
+        let node = Node::memory().spawn().await?;
+        let a = HashAndFormat::raw(node.blobs().add_bytes(b"a".to_vec()).await?.hash);
+        let b = HashAndFormat::raw(node.blobs().add_bytes(b"b".to_vec()).await?.hash);
+        let tags = node.tags();
+        let names = |infos: Vec<TagInfo>| infos.into_iter().map(|x| x.name).collect::<Vec<_>>();
+
+        // setting two tags atomically
+        let updates = vec![
+            TagUpdate::set("app1/current".into(), a).if_equals(None),
+            TagUpdate::set("app1/previous".into(), b).if_equals(None),
+        ];
+        assert!(tags.update(updates).await?);
+        assert!(
+            tags.update(vec![TagUpdate::set("app2/current".into(), a)])
+                .await?
+        );
+        let listed: Vec<_> = tags.list_prefix("app1/").await?.try_collect().await?;
+        assert_eq!(
+            names(listed),
+            vec![Tag::from("app1/current"), Tag::from("app1/previous")]
+        );
+
+        // a swap with a stale expectation changes nothing
+        let swap = |current| {
+            vec![
+                TagUpdate::set("app1/current".into(), b).if_equals(Some(current)),
+                TagUpdate::set("app1/previous".into(), a).if_equals(Some(b)),
+            ]
+        };
+        assert!(!tags.update(swap(b)).await?);
+        let listed: Vec<_> = tags
+            .list_prefix("app1/current")
+            .await?
+            .try_collect()
+            .await?;
+        assert_eq!(listed[0].hash, a.hash);
+        assert!(tags.update(swap(a)).await?);
+        let listed: Vec<_> = tags
+            .list_prefix("app1/current")
+            .await?
+            .try_collect()
+            .await?;
+        assert_eq!(listed[0].hash, b.hash);
+
+        // deleting by prefix leaves the other namespaces alone
+        assert_eq!(tags.delete_prefix("app1/").await?, 2);
+        let listed: Vec<_> = tags.list_prefix("app").await?.try_collect().await?;
+        assert_eq!(names(listed), vec![Tag::from("app2/current")]);
+        Ok(())
+    }
+}
@@ -61,6 +61,13 @@ use crate::node::{docs::DocsEngine, protocol::ProtocolMap};
 
 mod builder;
 mod docs;
+#[cfg(all(feature = "fuse", unix))]
+mod fuse;
+#[cfg(any(test, all(feature = "fuse", unix)))]
+mod fuse_tree;
+#[cfg(feature = "gateway")]
+mod gateway;
+mod import_index;
 mod protocol;
 mod rpc;
 mod rpc_status;
@@ -69,6 +76,8 @@ pub use self::builder::{
     Builder, DiscoveryConfig, DocsStorage, GcPolicy, ProtocolBuilder, StorageConfig,
     DEFAULT_RPC_ADDR,
 };
+#[cfg(all(feature = "fuse", unix))]
+pub use self::fuse::{FuseMount, MountRoot};
 pub use self::rpc_status::RpcStatus;
 pub use protocol::ProtocolHandler;
 
@@ -111,6 +120,9 @@ struct NodeInner<D> {
     rt: LocalPoolHandle,
     downloader: Downloader,
     gossip_dispatcher: GossipDispatcher,
+    import_index: import_index::ImportIndex,
+    #[cfg(feature = "gateway")]
+    gateway_addr: Option<SocketAddr>,
 }
 
 /// In memory node.
@@ -141,6 +153,24 @@ impl FsNode {
 }
 
 impl<D: BaoStore> Node<D> {
+    /// Mounts a collection, blob or tag as a read-only directory tree.
+    ///
+    /// Reads are served from the blob store of this node. Ranges that are not
+    /// available locally are fetched on demand from `nodes`. The view is
+    /// unmounted when the returned [`FuseMount`] is dropped.
+    #[cfg(all(feature = "fuse", unix))]
+    pub async fn mount(
+        &self,
+        root: MountRoot,
+        mountpoint: impl AsRef<Path>,
+        nodes: Vec<iroh_net::NodeAddr>,
+    ) -> Result<FuseMount> {
+        let db = self.inner.db.clone();
+        let downloader = self.inner.downloader.clone();
+        let rt = self.inner.rt.clone();
+        fuse::mount(db, downloader, rt, root, mountpoint.as_ref(), nodes).await
+    }
+
     /// Returns the [`Endpoint`] of the node.
     ///
     /// This can be used to establish connections to other nodes under any
@@ -199,6 +229,12 @@ impl<D: BaoStore> Node<D> {
         self.inner.rpc_addr
     }
 
+    /// Returns `Some(addr)` if the http gateway is running, `None` otherwise.
+    #[cfg(feature = "gateway")]
+    pub fn gateway_addr(&self) -> Option<SocketAddr> {
+        self.inner.gateway_addr
+    }
+
     /// Shutdown the node.
     ///
     /// This does not gracefully terminate currently: all connections are closed and
@@ -229,6 +265,33 @@ impl<D: BaoStore> Node<D> {
     pub fn get_protocol<P: ProtocolHandler>(&self, alpn: &[u8]) -> Option<Arc<P>> {
         self.protocols.get_typed(alpn)
     }
+
+    /// Set the merge handler for a document.
+    ///
+    /// See [`iroh_docs::engine::Engine::set_merge_handler`]. Fails if docs are disabled.
+    pub async fn set_merge_handler(
+        &self,
+        doc_id: iroh_docs::NamespaceId,
+        author: iroh_docs::AuthorId,
+        handler: Arc<dyn iroh_docs::engine::MergeHandler>,
+    ) -> Result<()> {
+        let docs = self
+            .inner
+            .docs
+            .as_ref()
+            .ok_or_else(|| anyhow!("docs are disabled"))?;
+        docs.set_merge_handler(doc_id, author, handler).await
+    }
+
+    /// Remove the merge handler of a document.
+    pub async fn remove_merge_handler(&self, doc_id: iroh_docs::NamespaceId) -> Result<()> {
+        let docs = self
+            .inner
+            .docs
+            .as_ref()
+            .ok_or_else(|| anyhow!("docs are disabled"))?;
+        docs.remove_merge_handler(doc_id).await
+    }
 }
 
 impl<D> std::ops::Deref for Node<D> {
@@ -256,6 +319,7 @@ impl<D: iroh_blobs::store::Store> NodeInner<D> {
         internal_rpc: IrohServerEndpoint,
         protocols: Arc<ProtocolMap>,
         gc_policy: GcPolicy,
+        gc_storage_quota: Option<u64>,
         gc_done_callback: Option<Box<dyn Fn() + Send>>,
     ) {
         let (ipv4, ipv6) = self.endpoint.bound_sockets();
@@ -284,9 +348,9 @@ impl<D: iroh_blobs::store::Store> NodeInner<D> {
         // Spawn a task for the garbage collection.
         if let GcPolicy::Interval(gc_period) = gc_policy {
             let inner = self.clone();
-            let handle = self
-                .rt
-                .spawn_pinned(move || inner.run_gc_loop(gc_period, gc_done_callback));
+            let handle = self.rt.spawn_pinned(move || {
+                inner.run_gc_loop(gc_period, gc_storage_quota, gc_done_callback)
+            });
             // We cannot spawn tasks that run on the local pool directly into the join set,
             // so instead we create a new task that supervises the local task.
             join_set.spawn({
@@ -416,6 +480,7 @@ impl<D: iroh_blobs::store::Store> NodeInner<D> {
     async fn run_gc_loop(
         self: Arc<Self>,
         gc_period: Duration,
+        storage_quota: Option<u64>,
         done_cb: Option<Box<dyn Fn() + Send>>,
     ) {
         tracing::info!("Starting GC task with interval {:?}", gc_period);
@@ -458,6 +523,9 @@ impl<D: iroh_blobs::store::Store> NodeInner<D> {
             let mut stream = db.gc_mark(&mut live);
             while let Some(item) = stream.next().await {
                 match item {
+                    GcMarkEvent::TagExpired { tag, value } => {
+                        tracing::debug!("tag {} for {:?} expired", tag, value);
+                    }
                     GcMarkEvent::CustomDebug(text) => {
                         tracing::debug!("{}", text);
                     }
@@ -473,9 +541,19 @@ impl<D: iroh_blobs::store::Store> NodeInner<D> {
             drop(stream);
 
             tracing::debug!("Starting GC sweep phase");
-            let mut stream = db.gc_sweep(&live);
+            let mut stream = db.gc_sweep(&live, storage_quota);
             while let Some(item) = stream.next().await {
                 match item {
+                    GcSweepEvent::Evicted { hash, size } => {
+                        tracing::debug!("evicted {} ({} bytes)", hash, size);
+                    }
+                    GcSweepEvent::QuotaExceeded { used, quota } => {
+                        tracing::warn!(
+                            "live data uses {} bytes, exceeding the storage quota of {} bytes",
+                            used,
+                            quota
+                        );
+                    }
                     GcSweepEvent::CustomDebug(text) => {
                         tracing::debug!("{}", text);
                     }
@@ -10,6 +10,7 @@ use futures_lite::StreamExt;
 use iroh_base::key::SecretKey;
 use iroh_blobs::{
     downloader::Downloader,
+    provider::{AcceptPush, AuthorizeGet, ProviderOptions, TransferLimiter, TransferLimits},
     store::{Map, Store as BaoStore},
 };
 use iroh_docs::engine::DefaultAuthorStorage;
@@ -42,7 +43,10 @@ use crate::{
     util::{fs::load_secret_key, path::IrohPaths},
 };
 
-use super::{docs::DocsEngine, rpc_status::RpcStatus, IrohServerEndpoint, Node, NodeInner};
+use super::{
+    docs::DocsEngine, import_index::ImportIndex, rpc_status::RpcStatus, IrohServerEndpoint, Node,
+    NodeInner,
+};
 
 /// Default bind address for the node.
 /// 11204 is "iroh" in leetspeak <https://simple.wikipedia.org/wiki/Leet>
@@ -54,6 +58,10 @@ const ENDPOINT_WAIT: Duration = Duration::from_secs(5);
 /// Default interval between GC runs.
 const DEFAULT_GC_INTERVAL: Duration = Duration::from_secs(60 * 5);
 
+/// Default time for which content fetched by the http gateway is kept.
+#[cfg(feature = "gateway")]
+const DEFAULT_GATEWAY_FETCH_TTL: Duration = Duration::from_secs(60 * 60);
+
 const MAX_CONNECTIONS: u32 = 1024;
 const MAX_STREAMS: u64 = 10;
 
@@ -95,14 +103,25 @@ where
     keylog: bool,
     relay_mode: RelayMode,
     gc_policy: GcPolicy,
+    gc_storage_quota: Option<u64>,
     dns_resolver: Option<DnsResolver>,
     node_discovery: DiscoveryConfig,
     docs_storage: DocsStorage,
+    docs_compaction: Option<Duration>,
     #[cfg(any(test, feature = "test-utils"))]
     insecure_skip_relay_cert_verify: bool,
     /// Callback to register when a gc loop is done
     #[debug("callback")]
     gc_done_callback: Option<Box<dyn Fn() + Send>>,
+    blobs_accept_push: Option<Arc<dyn AcceptPush>>,
+    blobs_authorize_get: Option<Arc<dyn AuthorizeGet>>,
+    blobs_transfer_limits: Option<TransferLimits>,
+    #[cfg(feature = "gateway")]
+    gateway_addr: Option<SocketAddr>,
+    #[cfg(feature = "gateway")]
+    gateway_fetch_tickets: bool,
+    #[cfg(feature = "gateway")]
+    gateway_fetch_ttl: Duration,
 }
 
 /// Configuration for storage.
@@ -199,17 +218,31 @@ impl Default for Builder<iroh_blobs::store::mem::Store> {
             rpc_endpoint: mk_external_rpc(),
             rpc_addr: None,
             gc_policy: GcPolicy::Disabled,
+            gc_storage_quota: None,
             docs_storage: DocsStorage::Memory,
+            docs_compaction: None,
             node_discovery: Default::default(),
             #[cfg(any(test, feature = "test-utils"))]
             insecure_skip_relay_cert_verify: false,
             gc_done_callback: None,
+            blobs_accept_push: None,
+            blobs_authorize_get: None,
+            blobs_transfer_limits: None,
+            #[cfg(feature = "gateway")]
+            gateway_addr: None,
+            #[cfg(feature = "gateway")]
+            gateway_fetch_tickets: false,
+            #[cfg(feature = "gateway")]
+            gateway_fetch_ttl: DEFAULT_GATEWAY_FETCH_TTL,
         }
     }
 }
 
 impl<D: Map> Builder<D> {
     /// Creates a new builder for [`Node`] using the given databases.
+    ///
+    /// Any blob store can be used here, e.g. the object storage backed store in
+    /// `iroh_blobs::store::s3`, which is available with the `s3-store` feature.
     pub fn with_db_and_store(
         blobs_store: D,
         docs_storage: DocsStorage,
@@ -232,11 +265,22 @@ impl<D: Map> Builder<D> {
             rpc_endpoint: mk_external_rpc(),
             rpc_addr: None,
             gc_policy: GcPolicy::Disabled,
+            gc_storage_quota: None,
             docs_storage,
+            docs_compaction: None,
             node_discovery: Default::default(),
             #[cfg(any(test, feature = "test-utils"))]
             insecure_skip_relay_cert_verify: false,
             gc_done_callback: None,
+            blobs_accept_push: None,
+            blobs_authorize_get: None,
+            blobs_transfer_limits: None,
+            #[cfg(feature = "gateway")]
+            gateway_addr: None,
+            #[cfg(feature = "gateway")]
+            gateway_fetch_tickets: false,
+            #[cfg(feature = "gateway")]
+            gateway_fetch_ttl: DEFAULT_GATEWAY_FETCH_TTL,
         }
     }
 }
@@ -296,11 +340,22 @@ where
             relay_mode: self.relay_mode,
             dns_resolver: self.dns_resolver,
             gc_policy: self.gc_policy,
+            gc_storage_quota: self.gc_storage_quota,
             docs_storage,
+            docs_compaction: self.docs_compaction,
             node_discovery: self.node_discovery,
             #[cfg(any(test, feature = "test-utils"))]
             insecure_skip_relay_cert_verify: false,
             gc_done_callback: self.gc_done_callback,
+            blobs_accept_push: self.blobs_accept_push,
+            blobs_authorize_get: self.blobs_authorize_get,
+            blobs_transfer_limits: self.blobs_transfer_limits,
+            #[cfg(feature = "gateway")]
+            gateway_addr: self.gateway_addr,
+            #[cfg(feature = "gateway")]
+            gateway_fetch_tickets: self.gateway_fetch_tickets,
+            #[cfg(feature = "gateway")]
+            gateway_fetch_ttl: self.gateway_fetch_ttl,
         })
     }
 
@@ -344,6 +399,92 @@ where
         self
     }
 
+    /// Sets a storage quota in bytes that garbage collection enforces.
+    ///
+    /// Without a quota, garbage collection deletes all blobs that are not
+    /// protected by a tag. With a quota, such blobs are kept until the store
+    /// exceeds the quota, and then deleted least recently used first. Access
+    /// times are only tracked while the node is running, so after a restart
+    /// blobs that have not been accessed since are deleted first.
+    ///
+    /// The quota is only enforced if garbage collection is enabled, see
+    /// [`Self::gc_policy`].
+    pub fn gc_storage_quota(mut self, quota: u64) -> Self {
+        self.gc_storage_quota = Some(quota);
+        self
+    }
+
+    /// Accept blobs pushed to this node by other nodes.
+    ///
+    /// By default, all push requests are rejected. With this, each push request is passed
+    /// to `accept_push`, which decides whether the pushed data is stored and how it is tagged.
+    pub fn accept_blobs_push(mut self, accept_push: Arc<dyn AcceptPush>) -> Self {
+        self.blobs_accept_push = Some(accept_push);
+        self
+    }
+
+    /// Restrict which nodes can retrieve which blobs from this node.
+    ///
+    /// By default, any node can retrieve any blob. With this, each get request is passed
+    /// to `authorize_get` before any data is sent, and denied requests are closed with
+    /// [`iroh_blobs::protocol::Closed::AccessDenied`].
+    pub fn authorize_blobs_get(mut self, authorize_get: Arc<dyn AuthorizeGet>) -> Self {
+        self.blobs_authorize_get = Some(authorize_get);
+        self
+    }
+
+    /// Limit the bandwidth and the number of concurrent transfers used to serve blobs.
+    ///
+    /// By default, blobs are served to as many nodes as request them, as fast as possible.
+    /// Get and have requests beyond the maximum number of concurrent transfers are closed with
+    /// [`iroh_blobs::protocol::Closed::TooManyTransfers`].
+    pub fn blobs_transfer_limits(mut self, limits: TransferLimits) -> Self {
+        self.blobs_transfer_limits = Some(limits);
+        self
+    }
+
+    /// Serve blobs and collections over HTTP on the given address.
+    ///
+    /// Blobs are served at `/blob/{hash}`, and entries of collections at
+    /// `/collection/{hash}/{path}`, honouring `Range` requests. Only content that is
+    /// available locally is served, unless [`Self::http_gateway_fetch_tickets`] is enabled.
+    #[cfg(feature = "gateway")]
+    pub fn http_gateway(mut self, addr: SocketAddr) -> Self {
+        self.gateway_addr = Some(addr);
+        self
+    }
+
+    /// Allow the http gateway to fetch content with blob tickets.
+    ///
+    /// Disabled by default. When enabled, a blob ticket can be used in place of the hash, to
+    /// fetch content that is missing locally from the node in the ticket as it is read. This
+    /// lets anyone who can reach the gateway make the node download and store arbitrary
+    /// content. Fetched content is tagged with `gateway/blob/{hash}` or
+    /// `gateway/collection/{hash}`, and these tags expire after the time set with
+    /// [`Self::http_gateway_fetch_ttl`], after which gc may delete the content again.
+    #[cfg(feature = "gateway")]
+    pub fn http_gateway_fetch_tickets(mut self, enabled: bool) -> Self {
+        self.gateway_fetch_tickets = enabled;
+        self
+    }
+
+    /// Sets how long content fetched by the http gateway is kept after it was last requested.
+    ///
+    /// Defaults to one hour.
+    #[cfg(feature = "gateway")]
+    pub fn http_gateway_fetch_ttl(mut self, ttl: Duration) -> Self {
+        self.gateway_fetch_ttl = ttl;
+        self
+    }
+
+    /// Periodically compacts all documents, pruning deletion markers older than `max_age`.
+    ///
+    /// Disabled by default. See [`iroh_docs::store::fs::Store::compact`] for details.
+    pub fn docs_compaction(mut self, max_age: Duration) -> Self {
+        self.docs_compaction = Some(max_age);
+        self
+    }
+
     /// Disables documents support on this node completely.
     pub fn disable_docs(mut self) -> Self {
         self.docs_storage = DocsStorage::Disabled;
@@ -544,9 +685,23 @@ where
             endpoint.clone(),
             gossip.clone(),
             downloader.clone(),
+            lp.clone(),
+            self.docs_compaction,
         )
         .await?;
         let gossip_dispatcher = GossipDispatcher::new(gossip.clone());
+        let import_index = match self.storage {
+            StorageConfig::Persistent(ref root) => {
+                ImportIndex::persistent(IrohPaths::ImportIndex.with_root(root))?
+            }
+            StorageConfig::Mem => ImportIndex::memory()?,
+        };
+
+        #[cfg(feature = "gateway")]
+        let gateway = match self.gateway_addr {
+            Some(addr) => Some(tokio::net::TcpListener::bind(addr).await?),
+            None => None,
+        };
 
         // Initialize the internal RPC connection.
         let (internal_rpc, controller) = quic_rpc::transport::flume::connection::<RpcService>(32);
@@ -568,6 +723,12 @@ where
             downloader,
             gossip,
             gossip_dispatcher,
+            import_index,
+            #[cfg(feature = "gateway")]
+            gateway_addr: gateway
+                .as_ref()
+                .map(|listener| listener.local_addr())
+                .transpose()?,
         });
 
         let protocol_builder = ProtocolBuilder {
@@ -576,10 +737,21 @@ where
             internal_rpc,
             external_rpc: self.rpc_endpoint,
             gc_policy: self.gc_policy,
+            gc_storage_quota: self.gc_storage_quota,
             gc_done_callback: self.gc_done_callback,
+            #[cfg(feature = "gateway")]
+            gateway,
+            #[cfg(feature = "gateway")]
+            gateway_fetch_tickets: self.gateway_fetch_tickets,
+            #[cfg(feature = "gateway")]
+            gateway_fetch_ttl: self.gateway_fetch_ttl,
         };
 
-        let protocol_builder = protocol_builder.register_iroh_protocols();
+        let protocol_builder = protocol_builder.register_iroh_protocols(
+            self.blobs_accept_push,
+            self.blobs_authorize_get,
+            self.blobs_transfer_limits,
+        );
 
         Ok(protocol_builder)
     }
@@ -602,6 +774,13 @@ pub struct ProtocolBuilder<D> {
     #[debug("callback")]
     gc_done_callback: Option<Box<dyn Fn() + Send>>,
     gc_policy: GcPolicy,
+    gc_storage_quota: Option<u64>,
+    #[cfg(feature = "gateway")]
+    gateway: Option<tokio::net::TcpListener>,
+    #[cfg(feature = "gateway")]
+    gateway_fetch_tickets: bool,
+    #[cfg(feature = "gateway")]
+    gateway_fetch_ttl: Duration,
 }
 
 impl<D: iroh_blobs::store::Store> ProtocolBuilder<D> {
@@ -700,10 +879,22 @@ impl<D: iroh_blobs::store::Store> ProtocolBuilder<D> {
     }
 
     /// Registers the core iroh protocols (blobs, gossip, docs).
-    fn register_iroh_protocols(mut self) -> Self {
+    fn register_iroh_protocols(
+        mut self,
+        blobs_accept_push: Option<Arc<dyn AcceptPush>>,
+        blobs_authorize_get: Option<Arc<dyn AuthorizeGet>>,
+        blobs_transfer_limits: Option<TransferLimits>,
+    ) -> Self {
         // Register blobs.
-        let blobs_proto =
-            BlobsProtocol::new(self.blobs_db().clone(), self.local_pool_handle().clone());
+        let blobs_proto = BlobsProtocol::new(
+            self.blobs_db().clone(),
+            self.local_pool_handle().clone(),
+            ProviderOptions {
+                accept_push: blobs_accept_push,
+                authorize_get: blobs_authorize_get,
+                limiter: blobs_transfer_limits.map(TransferLimiter::new),
+            },
+        );
         self = self.accept(iroh_blobs::protocol::ALPN, Arc::new(blobs_proto));
 
         // Register gossip.
@@ -727,6 +918,13 @@ impl<D: iroh_blobs::store::Store> ProtocolBuilder<D> {
             protocols,
             gc_done_callback,
             gc_policy,
+            gc_storage_quota,
+            #[cfg(feature = "gateway")]
+            gateway,
+            #[cfg(feature = "gateway")]
+            gateway_fetch_tickets,
+            #[cfg(feature = "gateway")]
+            gateway_fetch_ttl,
         } = self;
         let protocols = Arc::new(protocols);
         let node_id = inner.endpoint.node_id();
@@ -741,17 +939,43 @@ impl<D: iroh_blobs::store::Store> ProtocolBuilder<D> {
             return Err(err);
         }
 
+        // Serve the http gateway until the node is shut down.
+        #[cfg(feature = "gateway")]
+        let gateway_task = gateway.map(|listener| {
+            let serve = super::gateway::serve(
+                inner.client.clone(),
+                listener,
+                gateway_fetch_tickets,
+                gateway_fetch_ttl,
+                inner.cancel_token.clone(),
+            );
+            tokio::task::spawn(async move {
+                if let Err(err) = serve.await {
+                    tracing::warn!("http gateway failed: {err}");
+                }
+            })
+        });
+
         // Spawn the main task and store it in the node for structured termination in shutdown.
-        let fut = inner
-            .clone()
-            .run(
-                external_rpc,
-                internal_rpc,
-                protocols.clone(),
-                gc_policy,
-                gc_done_callback,
-            )
-            .instrument(error_span!("node", me=%node_id.fmt_short()));
+        let fut = inner.clone().run(
+            external_rpc,
+            internal_rpc,
+            protocols.clone(),
+            gc_policy,
+            gc_storage_quota,
+            gc_done_callback,
+        );
+        // The gateway stops on the same cancel token, so join it once the main task is done.
+        #[cfg(feature = "gateway")]
+        let fut = async move {
+            fut.await;
+            if let Some(task) = gateway_task {
+                if let Err(err) = task.await {
+                    tracing::warn!("http gateway task failed: {err}");
+                }
+            }
+        };
+        let fut = fut.instrument(error_span!("node", me=%node_id.fmt_short()));
         let task = tokio::task::spawn(fut);
 
         let node = Node {
@@ -1,4 +1,4 @@
-use std::{ops::Deref, sync::Arc};
+use std::{ops::Deref, sync::Arc, time::Duration};
 
 use anyhow::Result;
 use futures_lite::future::Boxed as BoxedFuture;
@@ -7,6 +7,7 @@ use iroh_gossip::net::Gossip;
 
 use iroh_docs::engine::{DefaultAuthorStorage, Engine};
 use iroh_net::{endpoint::Connecting, Endpoint};
+use tokio_util::task::LocalPoolHandle;
 
 use crate::node::{DocsStorage, ProtocolHandler};
 
@@ -15,6 +16,7 @@ use crate::node::{DocsStorage, ProtocolHandler};
 pub(crate) struct DocsEngine(Engine);
 
 impl DocsEngine {
+    #[allow(clippy::too_many_arguments)]
     pub async fn spawn<S: iroh_blobs::store::Store>(
         storage: DocsStorage,
         blobs_store: S,
@@ -22,6 +24,8 @@ impl DocsEngine {
         endpoint: Endpoint,
         gossip: Gossip,
         downloader: Downloader,
+        local_pool: LocalPoolHandle,
+        compaction: Option<Duration>,
     ) -> anyhow::Result<Option<Self>> {
         let docs_store = match storage {
             DocsStorage::Disabled => return Ok(None),
@@ -34,7 +38,9 @@ impl DocsEngine {
             docs_store,
             blobs_store,
             downloader,
+            local_pool,
             default_author_storage,
+            compaction,
         )
         .await?;
         Ok(Some(DocsEngine(engine)))
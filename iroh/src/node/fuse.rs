@@ -0,0 +1,466 @@
+//! A read-only FUSE view of collections and blobs.
+//!
+//! A mounted collection shows up as a directory tree, where the names of the
+//! collection are split into path components at `/`. If the collection has
+//! file system metadata, permission bits, modification times, symlinks and
+//! empty directories are shown as well. A single blob shows up as a directory
+//! with one file that is named after the hash.
+//!
+//! Reads are served straight from the blob store. If a read touches a range
+//! that is not available locally, only the chunks it needs, plus a little
+//! read ahead, are fetched with the [`Downloader`] from the nodes given when
+//! mounting, and the read completes once they are in. Collections and their
+//! metadata are fetched completely when mounting.
+//!
+//! This is only available on unix, with the `fuse` feature.
+use std::{
+    ffi::OsStr,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context, Result};
+use bao_tree::{ChunkNum, ChunkRanges};
+use bytes::Bytes;
+use fuser::{
+    FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry,
+    ReplyOpen, Request,
+};
+use iroh_base::hash::{BlobFormat, Hash, HashAndFormat};
+use iroh_blobs::{
+    downloader::{DownloadKind, DownloadRequest, Downloader},
+    format::collection::Collection,
+    get::db::{verified_ranges, verified_size},
+    hashseq::HashSeq,
+    store::{EntryStatus, MapEntry, Store as BaoStore},
+    util::{Tag, TempTag},
+};
+use iroh_io::AsyncSliceReader;
+use iroh_net::NodeAddr;
+use tokio_util::task::LocalPoolHandle;
+use tracing::{debug, warn};
+
+use super::fuse_tree::{Inode, InodeKind, Tree};
+
+/// How long the kernel may cache attributes of entries whose size is known.
+const ATTR_TTL: Duration = Duration::from_secs(60);
+
+/// Block size reported to the kernel.
+const BLOCK_SIZE: u32 = 4096;
+
+/// How many bytes to fetch ahead of a read of missing data, so sequential
+/// reads do not need a request each.
+const READ_AHEAD: u64 = 1024 * 1024;
+
+/// What to mount.
+#[derive(Debug, Clone)]
+pub enum MountRoot {
+    /// A collection, shown as a directory tree.
+    Collection(Hash),
+    /// A single blob, shown as a directory with one file named after the hash.
+    Blob(Hash),
+    /// The content of a tag, either a collection or a single blob depending on
+    /// the format of the tag.
+    Tag(Tag),
+}
+
+/// A mounted FUSE view.
+///
+/// The view is unmounted when this is dropped.
+#[derive(derive_more::Debug)]
+pub struct FuseMount {
+    #[debug(skip)]
+    session: fuser::BackgroundSession,
+    /// Protects the mounted content from garbage collection.
+    root: TempTag,
+}
+
+impl FuseMount {
+    /// The hash and format of the mounted content.
+    pub fn root(&self) -> HashAndFormat {
+        *self.root.inner()
+    }
+
+    /// Unmount the view and wait for the session to end.
+    pub fn unmount(self) {
+        self.session.join();
+    }
+}
+
+/// Mount content of the store at the given mount point.
+pub(super) async fn mount<D: BaoStore>(
+    db: D,
+    downloader: Downloader,
+    rt: LocalPoolHandle,
+    root: MountRoot,
+    mountpoint: &Path,
+    nodes: Vec<NodeAddr>,
+) -> Result<FuseMount> {
+    let shared = Arc::new(Shared {
+        db,
+        downloader,
+        nodes,
+    });
+    let root = match root {
+        MountRoot::Collection(hash) => HashAndFormat::hash_seq(hash),
+        MountRoot::Blob(hash) => HashAndFormat::raw(hash),
+        MountRoot::Tag(tag) => {
+            // only tags starting with the name can match, which stores can look up by range
+            let mut tags = shared.db.tags_with_prefix(tag.clone()).await?;
+            loop {
+                match tags.next() {
+                    Some(item) => {
+                        let (name, value) = item?;
+                        if name == tag {
+                            break value;
+                        }
+                    }
+                    None => bail!("tag {} not found", tag),
+                }
+            }
+        }
+    };
+    let temp_tag = shared.db.temp_tag(root);
+    let tree = match root.format {
+        BlobFormat::HashSeq => {
+            let collection = shared.load_collection(root.hash).await?;
+            Tree::from_collection(&collection)
+        }
+        BlobFormat::Raw => Tree::from_blob(root.hash),
+    };
+    debug!("mounting {:?} with {} inodes", root, tree.inodes.len());
+    let fs = BlobFs {
+        shared,
+        tree: Arc::new(tree),
+        rt,
+        uid: unsafe { libc::getuid() },
+        gid: unsafe { libc::getgid() },
+        mount_time: SystemTime::now(),
+    };
+    let mountpoint = mountpoint.to_owned();
+    let session = tokio::task::spawn_blocking(move || {
+        let options = [
+            MountOption::RO,
+            MountOption::FSName("iroh".into()),
+            MountOption::DefaultPermissions,
+        ];
+        fuser::spawn_mount2(fs, mountpoint, &options)
+    })
+    .await??;
+    Ok(FuseMount {
+        session,
+        root: temp_tag,
+    })
+}
+
+/// State shared between the FUSE callbacks and the tasks serving them.
+#[derive(derive_more::Debug)]
+struct Shared<D: BaoStore> {
+    db: D,
+    downloader: Downloader,
+    nodes: Vec<NodeAddr>,
+}
+
+impl<D: BaoStore> Shared<D> {
+    /// Download a blob, or the missing parts of it.
+    async fn download(&self, kind: DownloadKind) -> Result<()> {
+        if self.nodes.is_empty() {
+            bail!(
+                "blob {} is not available locally, and no nodes to fetch it from",
+                kind.hash()
+            );
+        }
+        debug!("fetching {} on demand", kind);
+        let request = DownloadRequest::new(kind, self.nodes.clone());
+        self.downloader.queue(request).await.await?;
+        Ok(())
+    }
+
+    /// Make sure a blob is complete in the store.
+    async fn ensure_complete(&self, hash: Hash) -> Result<()> {
+        if self.db.entry_status(&hash).await? != EntryStatus::Complete {
+            self.download(HashAndFormat::raw(hash).into()).await?;
+        }
+        Ok(())
+    }
+
+    /// Load a collection, fetching its links and metadata if needed.
+    ///
+    /// The children of the collection are fetched lazily, when they are read.
+    async fn load_collection(&self, hash: Hash) -> Result<Collection> {
+        self.ensure_complete(hash).await?;
+        let entry = self.db.get(&hash).await?.context("collection not found")?;
+        let mut reader = entry.data_reader().await?;
+        let size = reader.size().await?;
+        let links = HashSeq::try_from(reader.read_at(0, size as usize).await?)?;
+        let meta = links.iter().next().context("collection has no metadata")?;
+        self.ensure_complete(meta).await?;
+        Collection::load_db(&self.db, &hash).await
+    }
+
+    /// Get the size of a blob, if it is known locally.
+    async fn size(&self, hash: Hash) -> Result<Option<u64>> {
+        let entry = self.db.get(&hash).await?;
+        Ok(entry.map(|entry| entry.size().value()))
+    }
+
+    /// Read a range of a blob, fetching the missing chunks of the range
+    /// first if it is not available locally.
+    async fn read(&self, hash: Hash, offset: u64, len: usize) -> Result<Bytes> {
+        let end = offset.saturating_add(len as u64);
+        if !self.is_available(hash, offset, end).await? {
+            let read_ahead = end.saturating_add(READ_AHEAD);
+            let chunks = ChunkNum::full_chunks(offset)..ChunkNum::chunks(read_ahead);
+            self.download(DownloadKind::raw_chunks(hash, chunks))
+                .await?;
+        }
+        let entry = self.db.get(&hash).await?.context("blob not found")?;
+        let size = entry.size().value();
+        let len = size.saturating_sub(offset).min(len as u64) as usize;
+        let data = entry.data_reader().await?.read_at(offset, len).await?;
+        Ok(data)
+    }
+
+    /// Check if a range of a blob is available locally.
+    ///
+    /// If the size of a partial blob is not verified yet, the range is not
+    /// available, since the blob may end before the end of the range.
+    async fn is_available(&self, hash: Hash, offset: u64, end: u64) -> Result<bool> {
+        let Some(entry) = self.db.get_mut(&hash).await? else {
+            return Ok(false);
+        };
+        if entry.is_complete() {
+            return Ok(true);
+        }
+        let Some(size) = verified_size::<D>(&entry).await? else {
+            return Ok(false);
+        };
+        let end = end.min(size);
+        let wanted = ChunkRanges::from(ChunkNum::full_chunks(offset)..ChunkNum::chunks(end));
+        let verified = verified_ranges::<D>(&entry, &wanted).await?;
+        Ok(verified.is_superset(&wanted))
+    }
+}
+
+/// The FUSE file system.
+struct BlobFs<D: BaoStore> {
+    shared: Arc<Shared<D>>,
+    tree: Arc<Tree>,
+    rt: LocalPoolHandle,
+    uid: u32,
+    gid: u32,
+    mount_time: SystemTime,
+}
+
+impl<D: BaoStore> BlobFs<D> {
+    /// Compute the attributes of an inode, and how long they are valid.
+    ///
+    /// Attributes of files with a size that is not known locally are not
+    /// cached, since the size can change once the blob is fetched.
+    async fn attr(&self, ino: u64, inode: &Inode) -> Result<(FileAttr, Duration)> {
+        let mtime = inode
+            .meta
+            .as_ref()
+            .and_then(|meta| meta.mtime)
+            .unwrap_or(self.mount_time);
+        let (kind, size, default_perm, ttl) = match &inode.kind {
+            InodeKind::Dir(_) => (FileType::Directory, 0, 0o555, ATTR_TTL),
+            InodeKind::Symlink(target) => (FileType::Symlink, target.len() as u64, 0o777, ATTR_TTL),
+            InodeKind::File(hash) => match self.shared.size(*hash).await? {
+                Some(size) => (FileType::RegularFile, size, 0o444, ATTR_TTL),
+                None => {
+                    let hint = inode.meta.as_ref().and_then(|meta| meta.size);
+                    (
+                        FileType::RegularFile,
+                        hint.unwrap_or(0),
+                        0o444,
+                        Duration::ZERO,
+                    )
+                }
+            },
+        };
+        // the view is read only, so never report write permissions
+        let perm = inode
+            .meta
+            .as_ref()
+            .and_then(|meta| meta.mode)
+            .unwrap_or(default_perm)
+            & 0o7555;
+        let attr = FileAttr {
+            ino,
+            size,
+            blocks: size.div_ceil(512),
+            atime: mtime,
+            mtime,
+            ctime: mtime,
+            crtime: mtime,
+            kind,
+            perm: perm as u16,
+            nlink: if kind == FileType::Directory { 2 } else { 1 },
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: BLOCK_SIZE,
+            flags: 0,
+        };
+        Ok((attr, ttl))
+    }
+
+    /// Run an async operation for an inode, replying from a task.
+    fn spawn_attr(
+        &self,
+        ino: u64,
+        reply: impl FnOnce(Result<(FileAttr, Duration)>) + Send + 'static,
+    ) {
+        let this = self.clone_handle();
+        self.rt.spawn_pinned(move || async move {
+            let res = match this.tree.get(ino) {
+                Some(inode) => this.attr(ino, inode).await,
+                None => Err(anyhow::anyhow!("inode {} not found", ino)),
+            };
+            reply(res);
+        });
+    }
+
+    fn clone_handle(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            tree: self.tree.clone(),
+            rt: self.rt.clone(),
+            uid: self.uid,
+            gid: self.gid,
+            mount_time: self.mount_time,
+        }
+    }
+}
+
+impl<D: BaoStore> Filesystem for BlobFs<D> {
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let Some(children) = self.tree.children(parent) else {
+            reply.error(libc::ENOTDIR);
+            return;
+        };
+        let Some(ino) = name.to_str().and_then(|name| children.get(name)).copied() else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        self.spawn_attr(ino, move |res| match res {
+            Ok((attr, ttl)) => reply.entry(&ttl, &attr, 0),
+            Err(cause) => {
+                warn!("lookup failed: {:#}", cause);
+                reply.error(libc::EIO);
+            }
+        });
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
+        if self.tree.get(ino).is_none() {
+            reply.error(libc::ENOENT);
+            return;
+        }
+        self.spawn_attr(ino, move |res| match res {
+            Ok((attr, ttl)) => reply.attr(&ttl, &attr),
+            Err(cause) => {
+                warn!("getattr failed: {:#}", cause);
+                reply.error(libc::EIO);
+            }
+        });
+    }
+
+    fn readlink(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyData) {
+        match self.tree.get(ino).map(|inode| &inode.kind) {
+            Some(InodeKind::Symlink(target)) => reply.data(target.as_bytes()),
+            Some(_) => reply.error(libc::EINVAL),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn open(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        let Some(InodeKind::File(hash)) = self.tree.get(ino).map(|inode| &inode.kind) else {
+            reply.error(libc::EISDIR);
+            return;
+        };
+        if flags & libc::O_ACCMODE != libc::O_RDONLY {
+            reply.error(libc::EROFS);
+            return;
+        }
+        // if the size is not known yet, bypass the page cache so reads are not
+        // cut off at a stale size
+        let open_flags = match self.shared.db.entry_status_sync(hash) {
+            Ok(EntryStatus::Complete) => 0,
+            _ => fuser::consts::FOPEN_DIRECT_IO,
+        };
+        reply.opened(0, open_flags);
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let Some(InodeKind::File(hash)) = self.tree.get(ino).map(|inode| &inode.kind) else {
+            reply.error(libc::EISDIR);
+            return;
+        };
+        let Ok(offset) = u64::try_from(offset) else {
+            reply.error(libc::EINVAL);
+            return;
+        };
+        let hash = *hash;
+        let shared = self.shared.clone();
+        self.rt.spawn_pinned(move || async move {
+            match shared.read(hash, offset, size as usize).await {
+                Ok(data) => reply.data(&data),
+                Err(cause) => {
+                    warn!("reading {} failed: {:#}", hash, cause);
+                    reply.error(libc::EIO);
+                }
+            }
+        });
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let Some(inode) = self.tree.get(ino) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        let InodeKind::Dir(children) = &inode.kind else {
+            reply.error(libc::ENOTDIR);
+            return;
+        };
+        let entries = [
+            (ino, FileType::Directory, "."),
+            (inode.parent, FileType::Directory, ".."),
+        ]
+        .into_iter()
+        .chain(children.iter().map(|(name, ino)| {
+            let kind = match self.tree.get(*ino).map(|inode| &inode.kind) {
+                Some(InodeKind::Dir(_)) => FileType::Directory,
+                Some(InodeKind::Symlink(_)) => FileType::Symlink,
+                _ => FileType::RegularFile,
+            };
+            (*ino, kind, name.as_str())
+        }));
+        for (i, (ino, kind, name)) in entries.enumerate().skip(offset as usize) {
+            // the offset is the offset of the next entry
+            if reply.add(ino, i as i64 + 1, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
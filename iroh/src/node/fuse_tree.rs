@@ -0,0 +1,218 @@
+//! The directory tree of a FUSE view, mapping paths to inodes.
+//!
+//! This does not depend on FUSE itself, so it is also built for tests without
+//! the `fuse` feature.
+use std::collections::BTreeMap;
+
+use iroh_base::hash::Hash;
+use iroh_blobs::format::collection::{Collection, EntryKind, EntryMeta};
+use tracing::warn;
+
+/// The inode of the root directory, as defined by FUSE.
+pub(super) const ROOT_INO: u64 = 1;
+
+/// An inode of the mounted tree.
+#[derive(Debug)]
+pub(super) struct Inode {
+    pub(super) parent: u64,
+    pub(super) kind: InodeKind,
+    pub(super) meta: Option<EntryMeta>,
+}
+
+#[derive(Debug)]
+pub(super) enum InodeKind {
+    Dir(BTreeMap<String, u64>),
+    File(Hash),
+    Symlink(String),
+}
+
+/// The directory tree of a mount, inode numbers are indices plus one.
+#[derive(Debug)]
+pub(super) struct Tree {
+    pub(super) inodes: Vec<Inode>,
+}
+
+impl Tree {
+    fn new() -> Self {
+        Self {
+            inodes: vec![Inode {
+                parent: ROOT_INO,
+                kind: InodeKind::Dir(BTreeMap::new()),
+                meta: None,
+            }],
+        }
+    }
+
+    pub(super) fn from_blob(hash: Hash) -> Self {
+        let mut tree = Self::new();
+        tree.insert(&hash.to_hex(), InodeKind::File(hash), None);
+        tree
+    }
+
+    pub(super) fn from_collection(collection: &Collection) -> Self {
+        let mut tree = Self::new();
+        for (name, hash) in collection.iter() {
+            let meta = collection.meta(name).cloned();
+            tree.insert(name, InodeKind::File(*hash), meta);
+        }
+        for (name, meta) in collection.iter_meta() {
+            let kind = match &meta.kind {
+                EntryKind::File => continue,
+                EntryKind::Dir => InodeKind::Dir(BTreeMap::new()),
+                EntryKind::Symlink { target } => InodeKind::Symlink(target.clone()),
+            };
+            tree.insert(name, kind, Some(meta.clone()));
+        }
+        tree
+    }
+
+    pub(super) fn get(&self, ino: u64) -> Option<&Inode> {
+        self.inodes.get(usize::try_from(ino).ok()?.checked_sub(1)?)
+    }
+
+    pub(super) fn children(&self, ino: u64) -> Option<&BTreeMap<String, u64>> {
+        match &self.get(ino)?.kind {
+            InodeKind::Dir(children) => Some(children),
+            _ => None,
+        }
+    }
+
+    /// Insert an entry, creating parent directories as needed.
+    ///
+    /// Entries with invalid names, or that conflict with existing entries, are
+    /// skipped. Metadata for an existing directory is merged.
+    fn insert(&mut self, name: &str, kind: InodeKind, meta: Option<EntryMeta>) {
+        let parts = name.split('/').collect::<Vec<_>>();
+        if parts.iter().any(|part| matches!(*part, "" | "." | "..")) {
+            warn!("skipping collection entry with invalid name {:?}", name);
+            return;
+        }
+        let (last, dirs) = parts.split_last().expect("split always yields a part");
+        let mut parent = ROOT_INO;
+        for dir in dirs {
+            parent = match self.children(parent).and_then(|c| c.get(*dir)) {
+                Some(ino) if self.children(*ino).is_some() => *ino,
+                Some(_) => {
+                    warn!("skipping collection entry {:?} below a non-directory", name);
+                    return;
+                }
+                None => self.add(parent, dir, InodeKind::Dir(BTreeMap::new()), None),
+            };
+        }
+        match self.children(parent).and_then(|c| c.get(*last)).copied() {
+            None => {
+                self.add(parent, last, kind, meta);
+            }
+            Some(ino) if matches!(kind, InodeKind::Dir(_)) && self.children(ino).is_some() => {
+                self.inodes[ino as usize - 1].meta = meta;
+            }
+            Some(_) => warn!("skipping duplicate collection entry {:?}", name),
+        }
+    }
+
+    fn add(&mut self, parent: u64, name: &str, kind: InodeKind, meta: Option<EntryMeta>) -> u64 {
+        self.inodes.push(Inode { parent, kind, meta });
+        let ino = self.inodes.len() as u64;
+        if let InodeKind::Dir(children) = &mut self.inodes[parent as usize - 1].kind {
+            children.insert(name.to_owned(), ino);
+        }
+        ino
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tree_from_collection() {
+        let mut collection = [
+            ("a/b/c.txt", Hash::new(b"c")),
+            ("a/d.txt", Hash::new(b"d")),
+            ("e.txt", Hash::new(b"e")),
+            ("../escape", Hash::new(b"x")),
+            ("e.txt/f", Hash::new(b"f")),
+        ]
+        .into_iter()
+        .collect::<Collection>();
+        collection.set_meta("empty".into(), EntryMeta::new(EntryKind::Dir));
+        collection.set_meta(
+            "link".into(),
+            EntryMeta::new(EntryKind::Symlink {
+                target: "a/d.txt".into(),
+            }),
+        );
+        let tree = Tree::from_collection(&collection);
+        let root = tree.children(ROOT_INO).unwrap();
+        assert_eq!(
+            root.keys().collect::<Vec<_>>(),
+            vec!["a", "e.txt", "empty", "link"]
+        );
+        let a = tree.children(root["a"]).unwrap();
+        assert_eq!(a.keys().collect::<Vec<_>>(), vec!["b", "d.txt"]);
+        let b = tree.children(a["b"]).unwrap();
+        assert!(matches!(
+            tree.get(b["c.txt"]).unwrap().kind,
+            InodeKind::File(hash) if hash == Hash::new(b"c")
+        ));
+        assert_eq!(tree.get(a["b"]).unwrap().parent, root["a"]);
+        assert!(tree.children(root["empty"]).unwrap().is_empty());
+        assert!(matches!(
+            &tree.get(root["link"]).unwrap().kind,
+            InodeKind::Symlink(target) if target == "a/d.txt"
+        ));
+    }
+
+    #[test]
+    fn tree_from_blob() {
+        let hash = Hash::new(b"blob");
+        let tree = Tree::from_blob(hash);
+        let root = tree.children(ROOT_INO).unwrap();
+        assert_eq!(root.len(), 1);
+        assert!(matches!(
+            tree.get(root[&hash.to_hex()]).unwrap().kind,
+            InodeKind::File(h) if h == hash
+        ));
+    }
+
+    #[test]
+    fn tree_paths_to_inodes() {
+        let mut collection = [
+            ("dir/file", Hash::new(b"file")),
+            ("dir/file", Hash::new(b"duplicate")),
+            ("dir//empty-part", Hash::new(b"x")),
+            ("dir/./dot", Hash::new(b"x")),
+        ]
+        .into_iter()
+        .collect::<Collection>();
+        let mut meta = EntryMeta::new(EntryKind::Dir);
+        meta.mode = Some(0o750);
+        collection.set_meta("dir".into(), meta);
+        let tree = Tree::from_collection(&collection);
+
+        // resolve a path component by component, starting at the root
+        let lookup = |path: &str| {
+            path.split('/')
+                .try_fold(ROOT_INO, |ino, name| tree.children(ino)?.get(name).copied())
+        };
+        let dir = lookup("dir").unwrap();
+        let file = lookup("dir/file").unwrap();
+        assert_eq!(tree.get(file).unwrap().parent, dir);
+        assert_eq!(tree.get(dir).unwrap().parent, ROOT_INO);
+        assert!(matches!(
+            tree.get(file).unwrap().kind,
+            InodeKind::File(hash) if hash == Hash::new(b"file")
+        ));
+        // the metadata of the directory is merged into the implicitly created one
+        assert_eq!(
+            tree.get(dir).unwrap().meta.as_ref().unwrap().mode,
+            Some(0o750)
+        );
+        // invalid names are skipped
+        assert_eq!(tree.children(dir).unwrap().len(), 1);
+        assert_eq!(tree.inodes.len(), 3);
+        assert!(lookup("dir/file/below").is_none());
+        assert!(tree.get(0).is_none());
+        assert!(tree.get(4).is_none());
+    }
+}
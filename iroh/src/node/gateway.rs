@@ -0,0 +1,496 @@
+//! An HTTP gateway serving blobs and collections.
+//!
+//! The gateway serves two kinds of paths:
+//!
+//! - `/blob/{hash}` serves a single blob.
+//! - `/collection/{hash}/{path}` serves the entry named `path` of a collection. If there is
+//!   no such entry, `{path}/index.html` is tried as well.
+//!
+//! If enabled with [`crate::node::Builder::http_gateway_fetch_tickets`], a [`BlobTicket`] can
+//! be used instead of a hash. Content that is not available locally is then fetched from the
+//! node in the ticket, range by range as it is read, see
+//! [`crate::client::blobs::Client::read_at_lazy`]. The content is tagged before it is fetched,
+//! so nothing is stored without a tag. These tags expire after
+//! [`crate::node::Builder::http_gateway_fetch_ttl`], counted from the last request for the
+//! content, so fetched content can be garbage collected again.
+//!
+//! Since content is addressed by its hash, the hash doubles as a strong `ETag`, and responses
+//! may be cached forever. A single byte range requested with the `Range` header is honoured,
+//! other range requests are answered with the full content.
+//!
+//! This is only available with the `gateway` feature.
+use std::{ops::Range, str::FromStr, time::Duration};
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use bytes::Bytes;
+use iroh_base::{
+    hash::{BlobFormat, Hash, HashAndFormat},
+    ticket::BlobTicket,
+};
+use iroh_blobs::{
+    format::collection::{Collection, SimpleStore},
+    util::{Tag, TagUpdate},
+};
+use iroh_net::NodeAddr;
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+use crate::client::{
+    blobs::{self, LazyReadOptions, Reader},
+    Iroh,
+};
+
+/// Responses never change for a given url, so they may be cached forever.
+const CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// State of the gateway handlers.
+#[derive(Debug, Clone)]
+struct Gateway {
+    client: Iroh,
+    /// Whether tickets may be used to fetch content that is missing locally.
+    fetch_tickets: bool,
+    /// Time after which the tags of fetched content expire.
+    fetch_ttl: Duration,
+}
+
+impl Gateway {
+    /// Parse the content referenced in a url, either by its hash or with a ticket.
+    ///
+    /// Content referenced with a ticket is tagged, so that fetched data is not left
+    /// untagged in the store. The tag expires after the fetch ttl.
+    async fn content(&self, id: &str, kind: &str, format: BlobFormat) -> Result<Content, Error> {
+        let content = id.parse::<Content>()?;
+        if content.nodes.is_empty() {
+            return Ok(content);
+        }
+        if !self.fetch_tickets {
+            return Err(Error::new(
+                StatusCode::FORBIDDEN,
+                "fetching content with tickets is disabled".to_string(),
+            ));
+        }
+        let tag = Tag::from(format!("gateway/{kind}/{}", content.hash));
+        let value = HashAndFormat {
+            hash: content.hash,
+            format,
+        };
+        self.client
+            .tags()
+            .update(vec![TagUpdate::set(tag, value).with_ttl(self.fetch_ttl)])
+            .await
+            .map_err(|err| Error::new(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+        Ok(content)
+    }
+}
+
+/// Serve the gateway on `listener` until `cancel` is cancelled.
+///
+/// Tickets are only accepted if `fetch_tickets` is true, content fetched with them is kept
+/// for `fetch_ttl`.
+pub(super) async fn serve(
+    client: Iroh,
+    listener: TcpListener,
+    fetch_tickets: bool,
+    fetch_ttl: Duration,
+    cancel: CancellationToken,
+) -> std::io::Result<()> {
+    let state = Gateway {
+        client,
+        fetch_tickets,
+        fetch_ttl,
+    };
+    let app = Router::new()
+        .route("/blob/:id", get(get_blob))
+        .route("/collection/:id/*path", get(get_collection_entry))
+        .with_state(state);
+    debug!("http gateway listening at {:?}", listener.local_addr());
+    let shutdown = async move { cancel.cancelled().await };
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown)
+        .await
+}
+
+async fn get_blob(
+    State(gateway): State<Gateway>,
+    method: Method,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let content = gateway.content(&id, "blob", BlobFormat::Raw).await?;
+    serve_blob(
+        gateway.client.blobs(),
+        &method,
+        &headers,
+        content,
+        mime_guess::mime::APPLICATION_OCTET_STREAM.as_ref(),
+    )
+    .await
+}
+
+async fn get_collection_entry(
+    State(gateway): State<Gateway>,
+    method: Method,
+    Path((id, path)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let content = gateway
+        .content(&id, "collection", BlobFormat::HashSeq)
+        .await?;
+    let store = LazyStore {
+        blobs: gateway.client.blobs().clone(),
+        opts: LazyReadOptions::new(content.nodes.clone()),
+    };
+    let collection = Collection::load(content.hash, &store)
+        .await
+        .map_err(|err| content.read_error(err))?;
+    let (name, hash) = lookup(&collection, &path)
+        .ok_or_else(|| Error::new(StatusCode::NOT_FOUND, format!("{path} not found")))?;
+    let content_type = mime_guess::from_path(name).first_or_octet_stream();
+    let content = Content {
+        hash,
+        nodes: content.nodes,
+    };
+    serve_blob(
+        gateway.client.blobs(),
+        &method,
+        &headers,
+        content,
+        content_type.as_ref(),
+    )
+    .await
+}
+
+/// Find the entry for `path` in a collection, falling back to an `index.html` below it.
+fn lookup<'a>(collection: &'a Collection, path: &str) -> Option<(&'a str, Hash)> {
+    let index = format!("{}/index.html", path.trim_end_matches('/'));
+    for name in [path, index.as_str()] {
+        if let Some((entry, hash)) = collection.iter().find(|(entry, _)| entry == name) {
+            return Some((entry.as_str(), *hash));
+        }
+    }
+    None
+}
+
+/// Respond with (a range of) a blob.
+async fn serve_blob(
+    blobs: &blobs::Client,
+    method: &Method,
+    headers: &HeaderMap,
+    content: Content,
+    content_type: &str,
+) -> Result<Response, Error> {
+    let etag = format!("\"{}\"", content.hash.to_hex());
+    let response = Response::builder()
+        .header(header::ETAG, &etag)
+        .header(header::CACHE_CONTROL, CACHE_CONTROL)
+        .header(header::ACCEPT_RANGES, "bytes");
+    if header_matches(headers, header::IF_NONE_MATCH, &etag) {
+        return Ok(response
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())?);
+    }
+    // a range is only valid for the content identified by `If-Range`
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|_| if_range_matches(headers, &etag));
+    let with_body = method != Method::HEAD;
+    let response = response.header(header::CONTENT_TYPE, content_type);
+
+    let (response, reader) = match range {
+        None if with_body => {
+            let reader = content.read_at(blobs, 0, None).await?;
+            let response = response
+                .status(StatusCode::OK)
+                .header(header::CONTENT_LENGTH, reader.size());
+            (response, Some(reader))
+        }
+        _ => {
+            // get the size first, to know what the range refers to
+            let size = content.read_at(blobs, 0, Some(0)).await?.size();
+            match range.map(|range| parse_range(range, size)) {
+                None | Some(ByteRange::Full) => {
+                    let response = response
+                        .status(StatusCode::OK)
+                        .header(header::CONTENT_LENGTH, size);
+                    let reader = if with_body {
+                        Some(content.read_at(blobs, 0, None).await?)
+                    } else {
+                        None
+                    };
+                    (response, reader)
+                }
+                Some(ByteRange::Partial(range)) => {
+                    let len = range.end - range.start;
+                    let response = response
+                        .status(StatusCode::PARTIAL_CONTENT)
+                        .header(header::CONTENT_LENGTH, len)
+                        .header(
+                            header::CONTENT_RANGE,
+                            format!("bytes {}-{}/{}", range.start, range.end - 1, size),
+                        );
+                    let reader = if with_body {
+                        let len = Some(len as usize);
+                        Some(content.read_at(blobs, range.start, len).await?)
+                    } else {
+                        None
+                    };
+                    (response, reader)
+                }
+                Some(ByteRange::Unsatisfiable) => {
+                    let response = response
+                        .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                        .header(header::CONTENT_RANGE, format!("bytes */{size}"));
+                    (response, None)
+                }
+            }
+        }
+    };
+    let body = match reader {
+        Some(reader) => Body::from_stream(reader),
+        None => Body::empty(),
+    };
+    Ok(response.body(body)?)
+}
+
+/// Check if a conditional header matches an etag.
+fn header_matches(headers: &HeaderMap, name: header::HeaderName, etag: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|value| value.trim().trim_start_matches("W/"))
+        .any(|value| value == etag || value == "*")
+}
+
+/// Check if the `If-Range` header, if any, allows to serve a range of the content with `etag`.
+///
+/// Unlike `If-None-Match`, `If-Range` holds a single validator that must be a strong match, see
+/// RFC 9110, section 13.1.5. Weak etags, `*` and dates never match.
+fn if_range_matches(headers: &HeaderMap, etag: &str) -> bool {
+    let mut values = headers.get_all(header::IF_RANGE).iter();
+    match (values.next(), values.next()) {
+        (None, _) => true,
+        (Some(value), None) => value.to_str().is_ok_and(|value| value.trim() == etag),
+        (Some(_), Some(_)) => false,
+    }
+}
+
+/// The outcome of parsing a `Range` header.
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    /// The header is to be ignored, and the full content served.
+    Full,
+    /// A single range of bytes.
+    Partial(Range<u64>),
+    /// The range does not overlap the content.
+    Unsatisfiable,
+}
+
+/// Parse a `Range` header for content of `size` bytes.
+///
+/// Only single byte ranges are supported. Anything else, including malformed headers, is
+/// ignored, as allowed by RFC 9110.
+fn parse_range(value: &str, size: u64) -> ByteRange {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return ByteRange::Full;
+    };
+    let Some((first, last)) = spec.split_once('-').filter(|_| !spec.contains(',')) else {
+        return ByteRange::Full;
+    };
+    let (first, last) = (first.trim(), last.trim());
+    let range = if first.is_empty() {
+        // a suffix range, for the last bytes of the content
+        let Ok(len) = last.parse::<u64>() else {
+            return ByteRange::Full;
+        };
+        if len == 0 {
+            return ByteRange::Unsatisfiable;
+        }
+        size.saturating_sub(len)..size
+    } else {
+        let Ok(first) = first.parse::<u64>() else {
+            return ByteRange::Full;
+        };
+        let end = if last.is_empty() {
+            size
+        } else {
+            match last.parse::<u64>() {
+                Ok(last) if last >= first => last.saturating_add(1).min(size),
+                _ => return ByteRange::Full,
+            }
+        };
+        first..end
+    };
+    if range.start >= size {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Partial(range)
+}
+
+/// Content referenced in a url, either by its hash or with a ticket.
+#[derive(Debug)]
+struct Content {
+    hash: Hash,
+    /// Nodes to fetch missing ranges from.
+    nodes: Vec<NodeAddr>,
+}
+
+impl FromStr for Content {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(hash) = Hash::from_str(s) {
+            return Ok(Self {
+                hash,
+                nodes: Vec::new(),
+            });
+        }
+        match BlobTicket::from_str(s) {
+            Ok(ticket) => Ok(Self {
+                hash: ticket.hash(),
+                nodes: vec![ticket.node_addr().clone()],
+            }),
+            Err(_) => Err(Error::new(
+                StatusCode::BAD_REQUEST,
+                format!("{s} is neither a hash nor a ticket"),
+            )),
+        }
+    }
+}
+
+impl Content {
+    /// Open a reader for a range of the content.
+    async fn read_at(
+        &self,
+        blobs: &blobs::Client,
+        offset: u64,
+        len: Option<usize>,
+    ) -> Result<Reader, Error> {
+        let opts = LazyReadOptions::new(self.nodes.clone());
+        blobs
+            .read_at_lazy(self.hash, offset, len, opts)
+            .await
+            .map_err(|err| self.read_error(err))
+    }
+
+    /// Turn an error reading the content into a response.
+    fn read_error(&self, err: anyhow::Error) -> Error {
+        debug!("failed to read {}: {err:#}", self.hash);
+        if self.nodes.is_empty() {
+            // we only looked locally
+            Error::new(StatusCode::NOT_FOUND, format!("{} not found", self.hash))
+        } else {
+            Error::new(
+                StatusCode::BAD_GATEWAY,
+                format!("failed to fetch {}: {err}", self.hash),
+            )
+        }
+    }
+}
+
+/// Loads the blobs of a collection, fetching them if needed.
+#[derive(Debug)]
+struct LazyStore {
+    blobs: blobs::Client,
+    opts: LazyReadOptions,
+}
+
+impl SimpleStore for LazyStore {
+    async fn load(&self, hash: Hash) -> anyhow::Result<Bytes> {
+        self.blobs
+            .read_at_lazy(hash, 0, None, self.opts.clone())
+            .await?
+            .read_to_bytes()
+            .await
+    }
+}
+
+/// An error response.
+#[derive(Debug)]
+struct Error {
+    status: StatusCode,
+    message: String,
+}
+
+impl Error {
+    fn new(status: StatusCode, message: String) -> Self {
+        Self { status, message }
+    }
+}
+
+impl From<axum::http::Error> for Error {
+    fn from(err: axum::http::Error) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        (self.status, self.message).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_parsing() {
+        use ByteRange::*;
+        assert_eq!(parse_range("bytes=0-99", 1000), Partial(0..100));
+        assert_eq!(parse_range("bytes=100-", 1000), Partial(100..1000));
+        assert_eq!(parse_range("bytes=-100", 1000), Partial(900..1000));
+        assert_eq!(parse_range("bytes=-2000", 1000), Partial(0..1000));
+        assert_eq!(parse_range("bytes=990-2000", 1000), Partial(990..1000));
+        assert_eq!(parse_range("bytes=1000-", 1000), Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 1000), Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-", 0), Unsatisfiable);
+        // ignored
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), Full);
+        assert_eq!(parse_range("bytes=5-1", 1000), Full);
+        assert_eq!(parse_range("items=0-1", 1000), Full);
+        assert_eq!(parse_range("bytes=a-b", 1000), Full);
+    }
+
+    #[test]
+    fn etag_matching() {
+        let etag = "\"abc\"";
+        let mut headers = HeaderMap::new();
+        assert!(!header_matches(&headers, header::IF_NONE_MATCH, etag));
+        headers.insert(header::IF_NONE_MATCH, "\"x\", W/\"abc\"".parse().unwrap());
+        assert!(header_matches(&headers, header::IF_NONE_MATCH, etag));
+        headers.insert(header::IF_NONE_MATCH, "*".parse().unwrap());
+        assert!(header_matches(&headers, header::IF_NONE_MATCH, etag));
+        headers.insert(header::IF_NONE_MATCH, "\"x\"".parse().unwrap());
+        assert!(!header_matches(&headers, header::IF_NONE_MATCH, etag));
+    }
+
+    #[test]
+    fn if_range_matching() {
+        let etag = "\"abc\"";
+        let mut headers = HeaderMap::new();
+        assert!(if_range_matches(&headers, etag));
+        headers.insert(header::IF_RANGE, "\"abc\"".parse().unwrap());
+        assert!(if_range_matches(&headers, etag));
+        // only a strong comparison with a single etag counts
+        for value in [
+            "W/\"abc\"",
+            "*",
+            "\"x\", \"abc\"",
+            "Sat, 29 Oct 1994 19:43:31 GMT",
+        ] {
+            headers.insert(header::IF_RANGE, value.parse().unwrap());
+            assert!(!if_range_matches(&headers, etag), "{value}");
+        }
+    }
+}
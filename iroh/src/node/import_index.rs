@@ -0,0 +1,203 @@
+//! An index of imported files, so unchanged files are not hashed again.
+//!
+//! Each file that is imported from a path is recorded with its size, mtime and inode
+//! as soon as it is stored, together with the import mode. When the same tree
+//! is added again in the same way, or an interrupted add is repeated, files whose metadata
+//! still matches are taken from the store instead of being read and hashed.
+
+use std::{
+    fs::Metadata,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use iroh_blobs::{store::ImportMode, Hash};
+use redb::{Database, Durability, TableDefinition};
+
+/// The value of the files table: `(size, mtime secs, mtime nanos, inode, hash)`.
+type FileValue = (u64, u64, u32, u64, &'static [u8; 32]);
+
+/// Maps `(path, import mode)` of an imported file to its [`FileValue`].
+const FILES_TABLE: TableDefinition<(&str, u8), FileValue> = TableDefinition::new("files");
+
+/// How often writes to the index are made durable.
+///
+/// Writes in between are cheap, but may be lost when the process is killed, in which
+/// case the files are just hashed again.
+const SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The metadata of a file that is used to detect changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FileStamp {
+    /// The size of the file in bytes.
+    pub size: u64,
+    mtime: Duration,
+    inode: u64,
+}
+
+impl FileStamp {
+    /// Get the stamp from the metadata of a file.
+    ///
+    /// Returns `None` if the platform does not provide a modification time.
+    pub fn new(meta: &Metadata) -> Option<Self> {
+        let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(meta);
+        #[cfg(not(unix))]
+        let inode = 0;
+        Some(Self {
+            size: meta.len(),
+            mtime,
+            inode,
+        })
+    }
+}
+
+/// A persistent map from imported files to their hashes.
+#[derive(Debug, Clone)]
+pub(crate) struct ImportIndex {
+    db: Arc<Database>,
+    last_sync: Arc<Mutex<Instant>>,
+}
+
+impl ImportIndex {
+    /// Create an index that is kept in memory.
+    pub fn memory() -> Result<Self> {
+        let db = Database::builder().create_with_backend(redb::backends::InMemoryBackend::new())?;
+        Self::new(db)
+    }
+
+    /// Create or open an index stored in the database file at `path`.
+    pub fn persistent(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let db = Database::create(path)
+            .with_context(|| format!("failed to open import index {}", path.display()))?;
+        Self::new(db)
+    }
+
+    fn new(db: Database) -> Result<Self> {
+        let tx = db.begin_write()?;
+        tx.open_table(FILES_TABLE)?;
+        tx.commit()?;
+        Ok(Self {
+            db: Arc::new(db),
+            last_sync: Arc::new(Mutex::new(Instant::now())),
+        })
+    }
+
+    /// Get the hash of the file at `path`, if it was imported with the same `stamp` and `mode`.
+    pub async fn get(
+        &self,
+        path: &Path,
+        stamp: FileStamp,
+        mode: ImportMode,
+    ) -> Result<Option<Hash>> {
+        let Some(path) = path.to_str().map(ToOwned::to_owned) else {
+            return Ok(None);
+        };
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || {
+            let tx = db.begin_read()?;
+            let table = tx.open_table(FILES_TABLE)?;
+            let key = (path.as_str(), mode_key(mode));
+            let Some(value) = table.get(key)? else {
+                return Ok(None);
+            };
+            let (size, secs, nanos, inode, hash) = value.value();
+            let found = FileStamp {
+                size,
+                mtime: Duration::new(secs, nanos),
+                inode,
+            };
+            Ok((found == stamp).then(|| Hash::from(*hash)))
+        })
+        .await?
+    }
+
+    /// Record that the file at `path` with `stamp` was imported with `mode` as `hash`.
+    ///
+    /// Paths that are not valid unicode are not recorded.
+    pub async fn insert(
+        &self,
+        path: &Path,
+        stamp: FileStamp,
+        mode: ImportMode,
+        hash: Hash,
+    ) -> Result<()> {
+        let Some(path) = path.to_str().map(ToOwned::to_owned) else {
+            return Ok(());
+        };
+        let db = self.db.clone();
+        let last_sync = self.last_sync.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut tx = db.begin_write()?;
+            let mut last_sync = last_sync.lock().unwrap();
+            if last_sync.elapsed() < SYNC_INTERVAL {
+                tx.set_durability(Durability::Eventual);
+            } else {
+                *last_sync = Instant::now();
+            }
+            {
+                let mut table = tx.open_table(FILES_TABLE)?;
+                let mtime = stamp.mtime;
+                let value = (
+                    stamp.size,
+                    mtime.as_secs(),
+                    mtime.subsec_nanos(),
+                    stamp.inode,
+                    hash.as_bytes(),
+                );
+                let key = (path.as_str(), mode_key(mode));
+                table.insert(key, value)?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await?
+    }
+}
+
+/// The import mode as stored in the key of the files table.
+fn mode_key(mode: ImportMode) -> u8 {
+    match mode {
+        ImportMode::Copy => 0,
+        ImportMode::TryReference => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn import_index_persistent() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("file");
+        std::fs::write(&file, b"hello")?;
+        let stamp = FileStamp::new(&std::fs::metadata(&file)?).context("no mtime")?;
+        let hash = Hash::new(b"hello");
+
+        let copy = ImportMode::Copy;
+        let db_path = dir.path().join("import-index.redb");
+        let index = ImportIndex::persistent(&db_path)?;
+        assert_eq!(index.get(&file, stamp, copy).await?, None);
+        index.insert(&file, stamp, copy, hash).await?;
+        assert_eq!(index.get(&file, stamp, copy).await?, Some(hash));
+        drop(index);
+
+        let index = ImportIndex::persistent(&db_path)?;
+        assert_eq!(index.get(&file, stamp, copy).await?, Some(hash));
+        // a changed file is not found
+        let changed = FileStamp { size: 6, ..stamp };
+        assert_eq!(index.get(&file, changed, copy).await?, None);
+        // neither is a file imported in a different mode
+        let reference = ImportMode::TryReference;
+        assert_eq!(index.get(&file, stamp, reference).await?, None);
+        index.insert(&file, stamp, reference, hash).await?;
+        assert_eq!(index.get(&file, stamp, reference).await?, Some(hash));
+        assert_eq!(index.get(&file, stamp, copy).await?, Some(hash));
+        Ok(())
+    }
+}
@@ -3,6 +3,7 @@ use std::{any::Any, collections::BTreeMap, fmt, sync::Arc};
 use anyhow::Result;
 use futures_lite::future::Boxed as BoxedFuture;
 use futures_util::future::join_all;
+use iroh_blobs::provider::ProviderOptions;
 use iroh_net::endpoint::Connecting;
 
 /// Handler for incoming connections.
@@ -80,11 +81,12 @@ impl ProtocolMap {
 pub(crate) struct BlobsProtocol<S> {
     rt: tokio_util::task::LocalPoolHandle,
     store: S,
+    options: ProviderOptions,
 }
 
 impl<S: iroh_blobs::store::Store> BlobsProtocol<S> {
-    pub fn new(store: S, rt: tokio_util::task::LocalPoolHandle) -> Self {
-        Self { rt, store }
+    pub fn new(store: S, rt: tokio_util::task::LocalPoolHandle, options: ProviderOptions) -> Self {
+        Self { rt, store, options }
     }
 }
 
@@ -95,6 +97,7 @@ impl<S: iroh_blobs::store::Store> ProtocolHandler for BlobsProtocol<S> {
                 conn.await?,
                 self.store.clone(),
                 MockEventSender,
+                self.options.clone(),
                 self.rt.clone(),
             )
             .await;
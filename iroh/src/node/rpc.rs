@@ -1,29 +1,38 @@
+use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::io;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use anyhow::{anyhow, ensure, Result};
+use bao_tree::{ChunkNum, ChunkRanges};
 use futures_buffered::BufferedStreamExt;
 use futures_lite::{Stream, StreamExt};
 use genawaiter::sync::{Co, Gen};
+use iroh_base::hash::Hash;
 use iroh_base::rpc::{RpcError, RpcResult};
-use iroh_blobs::downloader::{DownloadRequest, Downloader};
+use iroh_blobs::downloader::{DownloadRequest, Downloader, QueueSnapshot};
 use iroh_blobs::export::ExportProgress;
 use iroh_blobs::format::collection::Collection;
-use iroh_blobs::get::db::DownloadProgress;
+use iroh_blobs::get::db::{get_ranges, verified_ranges, verified_size, DownloadProgress};
 use iroh_blobs::get::Stats;
-use iroh_blobs::store::{ConsistencyCheckProgress, ExportFormat, ImportProgress, MapEntry};
-use iroh_blobs::util::progress::ProgressSender;
+use iroh_blobs::store::{
+    archive::{export_archive, import_archive, ArchiveHeader},
+    BaoBlobSize, ConsistencyCheckProgress, EntryStatus, ExportFormat, ImportProgress, MapEntry,
+    StoreStats,
+};
+use iroh_blobs::util::progress::{IdGenerator, ProgressSender};
 use iroh_blobs::util::SetTagOption;
 use iroh_blobs::BlobFormat;
 use iroh_blobs::{
     provider::AddProgress,
     store::{Store as BaoStore, ValidateProgress},
     util::progress::FlumeProgressSender,
-    HashAndFormat,
+    HashAndFormat, TempTag,
 };
-use iroh_io::AsyncSliceReader;
+use iroh_io::{AsyncSliceReader, TokioStreamReader, TokioStreamWriter};
+use iroh_net::endpoint::Connection;
 use iroh_net::relay::RelayUrl;
 use iroh_net::{Endpoint, NodeAddr, NodeId};
 use quic_rpc::server::{RpcChannel, RpcServerError};
@@ -36,14 +45,17 @@ use crate::client::{
     tags::TagInfo,
     NodeStatus,
 };
-use crate::node::{docs::DocsEngine, NodeInner};
+use crate::node::{docs::DocsEngine, import_index::FileStamp, NodeInner};
 use crate::rpc_protocol::{
     authors, blobs,
     blobs::{
         AddPathRequest, AddPathResponse, AddStreamRequest, AddStreamResponse, AddStreamUpdate,
         ConsistencyCheckRequest, CreateCollectionRequest, CreateCollectionResponse, DeleteRequest,
-        DownloadRequest as BlobDownloadRequest, DownloadResponse, ExportRequest, ExportResponse,
-        ListIncompleteRequest, ListRequest, ReadAtRequest, ReadAtResponse, ValidateRequest,
+        DownloadQueueRequest, DownloadRequest as BlobDownloadRequest, DownloadResponse,
+        ExportArchiveRequest, ExportRequest, ExportResponse, ImportArchiveRequest,
+        ImportArchiveResponse, ListIncompleteRequest, ListRequest, ReadAtLazyRequest,
+        ReadAtRequest, ReadAtResponse, SetDownloadPriorityRequest, StoreStatsRequest,
+        ValidateRequest,
     },
     docs::Request as DocsRequest,
     docs::{
@@ -57,7 +69,10 @@ use crate::rpc_protocol::{
         ShutdownRequest, StatsRequest, StatsResponse, StatusRequest, WatchResponse,
     },
     tags,
-    tags::{DeleteRequest as TagDeleteRequest, ListRequest as ListTagsRequest},
+    tags::{
+        DeletePrefixRequest as TagDeletePrefixRequest, DeleteRequest as TagDeleteRequest,
+        ListRequest as ListTagsRequest, UpdateRequest as TagUpdateRequest,
+    },
     Request, RpcService,
 };
 
@@ -176,13 +191,22 @@ impl<D: BaoStore> Handler<D> {
                     .await
             }
             Download(msg) => chan.server_streaming(msg, self, Self::blob_download).await,
+            DownloadQueue(msg) => chan.rpc(msg, self, Self::blob_download_queue).await,
+            SetDownloadPriority(msg) => chan.rpc(msg, self, Self::blob_set_download_priority).await,
             Export(msg) => chan.server_streaming(msg, self, Self::blob_export).await,
+            ExportArchive(msg) => chan.rpc(msg, self, Self::blob_export_archive).await,
+            ImportArchive(msg) => chan.rpc(msg, self, Self::blob_import_archive).await,
             Validate(msg) => chan.server_streaming(msg, self, Self::blob_validate).await,
+            Stats(msg) => chan.rpc(msg, self, Self::blob_stats).await,
             Fsck(msg) => {
                 chan.server_streaming(msg, self, Self::blob_consistency_check)
                     .await
             }
             ReadAt(msg) => chan.server_streaming(msg, self, Self::blob_read_at).await,
+            ReadAtLazy(msg) => {
+                chan.server_streaming(msg, self, Self::blob_read_at_lazy)
+                    .await
+            }
             AddStream(msg) => chan.bidi_streaming(msg, self, Self::blob_add_stream).await,
             AddStreamUpdate(_msg) => Err(RpcServerError::UnexpectedUpdateMessage),
         }
@@ -197,6 +221,8 @@ impl<D: BaoStore> Handler<D> {
         match msg {
             ListTags(msg) => chan.server_streaming(msg, self, Self::blob_list_tags).await,
             DeleteTag(msg) => chan.rpc(msg, self, Self::blob_delete_tag).await,
+            DeletePrefix(msg) => chan.rpc(msg, self, Self::blob_delete_tag_prefix).await,
+            UpdateTags(msg) => chan.rpc(msg, self, Self::blob_update_tags).await,
         }
     }
 
@@ -364,6 +390,12 @@ impl<D: BaoStore> Handler<D> {
                 })
                 .await
             }
+            GetHeads(msg) => {
+                chan.rpc(msg, self, |handler, req| {
+                    handler.with_docs(|docs| async move { docs.doc_get_heads(req).await })
+                })
+                .await
+            }
             StartSync(msg) => {
                 chan.rpc(msg, self, |handler, req| {
                     handler.with_docs(|docs| async move { docs.doc_start_sync(req).await })
@@ -382,6 +414,18 @@ impl<D: BaoStore> Handler<D> {
                 })
                 .await
             }
+            ShareDelegated(msg) => {
+                chan.rpc(msg, self, |handler, req| {
+                    handler.with_docs(|docs| async move { docs.doc_share_delegated(req).await })
+                })
+                .await
+            }
+            RevokeWrite(msg) => {
+                chan.rpc(msg, self, |handler, req| {
+                    handler.with_docs(|docs| async move { docs.doc_revoke_write(req).await })
+                })
+                .await
+            }
             Subscribe(msg) => {
                 chan.try_server_streaming(msg, self, |handler, req| async move {
                     handler
@@ -497,19 +541,45 @@ impl<D: BaoStore> Handler<D> {
     }
 
     async fn blob_delete_tag(self, msg: TagDeleteRequest) -> RpcResult<()> {
-        self.inner.db.set_tag(msg.name, None).await?;
+        self.inner.db.set_tag(msg.name, None, None).await?;
         Ok(())
     }
 
+    async fn blob_delete_tag_prefix(self, msg: TagDeletePrefixRequest) -> RpcResult<u64> {
+        let deleted = self.inner.db.delete_tags_with_prefix(msg.prefix).await?;
+        Ok(deleted.len() as u64)
+    }
+
+    async fn blob_update_tags(self, msg: TagUpdateRequest) -> RpcResult<bool> {
+        let applied = self.inner.db.update_tags(msg.updates).await?;
+        Ok(applied)
+    }
+
     async fn blob_delete_blob(self, msg: DeleteRequest) -> RpcResult<()> {
         self.inner.db.delete(vec![msg.hash]).await?;
         Ok(())
     }
 
+    async fn blob_download_queue(self, _msg: DownloadQueueRequest) -> RpcResult<QueueSnapshot> {
+        let snapshot = self.inner.downloader.queue_snapshot().await?;
+        Ok(snapshot)
+    }
+
+    async fn blob_set_download_priority(self, msg: SetDownloadPriorityRequest) -> RpcResult<()> {
+        self.inner
+            .downloader
+            .set_priority(msg.intent, msg.priority)
+            .await?;
+        Ok(())
+    }
+
     fn blob_list_tags(self, msg: ListTagsRequest) -> impl Stream<Item = TagInfo> + Send + 'static {
         tracing::info!("blob_list_tags");
         Gen::new(|co| async move {
-            let tags = self.inner.db.tags().await.unwrap();
+            let tags = match msg.prefix {
+                Some(prefix) => self.inner.db.tags_with_prefix(prefix).await.unwrap(),
+                None => self.inner.db.tags().await.unwrap(),
+            };
             #[allow(clippy::manual_flatten)]
             for item in tags {
                 if let Ok((name, HashAndFormat { hash, format })) = item {
@@ -737,6 +807,69 @@ impl<D: BaoStore> Handler<D> {
         rx.into_stream().map(ExportResponse)
     }
 
+    async fn blob_stats(self, _msg: StoreStatsRequest) -> RpcResult<StoreStats> {
+        let db = self.inner.db.clone();
+        // computing the stats reads hash seqs, which is not Send
+        let stats = self
+            .rt()
+            .spawn_pinned(move || async move { db.stats().await })
+            .await
+            .map_err(|_| anyhow!("stats task panicked"))??;
+        Ok(stats)
+    }
+
+    async fn blob_export_archive(self, msg: ExportArchiveRequest) -> RpcResult<()> {
+        let db = self.inner.db.clone();
+        // readers of the store are not Send, so run on the local pool
+        self.rt()
+            .spawn_pinned(move || async move {
+                let header = ArchiveHeader::new(HashAndFormat {
+                    hash: msg.hash,
+                    format: msg.format,
+                });
+                let file = tokio::fs::File::create(&msg.path).await?;
+                let writer = TokioStreamWriter(tokio::io::BufWriter::new(file));
+                export_archive(&db, &header, writer).await
+            })
+            .await
+            .map_err(|_| anyhow!("export task panicked"))??;
+        Ok(())
+    }
+
+    async fn blob_import_archive(
+        self,
+        msg: ImportArchiveRequest,
+    ) -> RpcResult<ImportArchiveResponse> {
+        let ImportArchiveRequest { path, tag } = msg;
+        let db = self.inner.db.clone();
+        // the temp tag protects the imported data until it is tagged below
+        let (header, _temp_tag) = self
+            .rt()
+            .spawn_pinned(move || async move {
+                let file = tokio::fs::File::open(&path).await?;
+                let reader = TokioStreamReader::new(tokio::io::BufReader::new(file));
+                import_archive(&db, reader).await
+            })
+            .await
+            .map_err(|_| anyhow!("import task panicked"))??;
+        let hash_and_format = header.hash_and_format();
+        let tag = match tag {
+            SetTagOption::Named(tag) => {
+                self.inner
+                    .db
+                    .set_tag(tag.clone(), Some(hash_and_format), None)
+                    .await?;
+                tag
+            }
+            SetTagOption::Auto => self.inner.db.create_tag(hash_and_format, None).await?,
+        };
+        Ok(ImportArchiveResponse {
+            hash: header.hash,
+            format: header.format,
+            tag,
+        })
+    }
+
     async fn blob_add_from_path0(
         self,
         msg: AddPathRequest,
@@ -768,6 +901,8 @@ impl<D: BaoStore> Handler<D> {
             path: root,
             in_place,
             tag,
+            chunking,
+            meta,
         } = msg;
         // Check that the path is absolute and exists.
         anyhow::ensure!(root.is_absolute(), "path must be absolute");
@@ -787,26 +922,40 @@ impl<D: BaoStore> Handler<D> {
             WrapOption::NoWrap => root.is_dir(),
         };
 
-        let temp_tag = if create_collection {
+        let temp_tag = if let Some(config) = chunking {
+            anyhow::ensure!(
+                !create_collection,
+                "chunked import only supports a single file"
+            );
+            let file = tokio::fs::File::open(&root).await?;
+            let stream = tokio_util::io::ReaderStream::new(file);
+            let (tag, _size) = self
+                .inner
+                .db
+                .import_stream_chunked(stream, config, import_progress)
+                .await?;
+            tag
+        } else if create_collection {
             // import all files below root recursively
-            let data_sources = crate::util::fs::scan_path(root, wrap)?;
+            let data_sources = crate::util::fs::scan_path(root.clone(), wrap.clone())?;
             const IO_PARALLELISM: usize = 4;
             let result: Vec<_> = futures_lite::stream::iter(data_sources)
                 .map(|source| {
                     let import_progress = import_progress.clone();
-                    let db = self.inner.db.clone();
+                    let progress = progress.clone();
+                    let this = self.clone();
                     async move {
                         let name = source.name().to_string();
-                        let (tag, size) = db
-                            .import_file(
+                        let (tag, size) = this
+                            .import_file_indexed(
                                 source.path().to_owned(),
                                 import_mode,
-                                BlobFormat::Raw,
                                 import_progress,
+                                progress,
                             )
                             .await?;
                         let hash = *tag.hash();
-                        io::Result::Ok((name, hash, size, tag))
+                        anyhow::Ok((name, hash, size, tag))
                     }
                 })
                 .buffered_ordered(IO_PARALLELISM)
@@ -814,18 +963,23 @@ impl<D: BaoStore> Handler<D> {
                 .await?;
 
             // create a collection
-            let (collection, _child_tags): (Collection, Vec<_>) = result
+            let (mut collection, _child_tags): (Collection, Vec<_>) = result
                 .into_iter()
                 .map(|(name, hash, _, tag)| ((name, hash), tag))
                 .unzip();
+            if meta {
+                // keep modes, mtimes, symlinks and empty directories
+                for (name, meta) in crate::util::fs::scan_path_meta(root, wrap)? {
+                    collection.set_meta(name, meta);
+                }
+            }
 
             collection.store(&self.inner.db).await?
         } else {
             // import a single file
             let (tag, _size) = self
-                .inner
-                .db
-                .import_file(root, import_mode, BlobFormat::Raw, import_progress)
+                .clone()
+                .import_file_indexed(root, import_mode, import_progress, progress.clone())
                 .await?;
             tag
         };
@@ -836,11 +990,11 @@ impl<D: BaoStore> Handler<D> {
             SetTagOption::Named(tag) => {
                 self.inner
                     .db
-                    .set_tag(tag.clone(), Some(*hash_and_format))
+                    .set_tag(tag.clone(), Some(*hash_and_format), None)
                     .await?;
                 tag
             }
-            SetTagOption::Auto => self.inner.db.create_tag(*hash_and_format).await?,
+            SetTagOption::Auto => self.inner.db.create_tag(*hash_and_format, None).await?,
         };
         progress
             .send(AddProgress::AllDone {
@@ -852,6 +1006,45 @@ impl<D: BaoStore> Handler<D> {
         Ok(())
     }
 
+    /// Import a file, unless the import index shows that it is unchanged since it was last
+    /// added and its data is still complete in the store.
+    ///
+    /// Imported files are recorded in the index as soon as they are stored, so an
+    /// interrupted add of a directory continues where it stopped when it is repeated.
+    async fn import_file_indexed(
+        self,
+        path: PathBuf,
+        import_mode: iroh_blobs::store::ImportMode,
+        import_progress: impl ProgressSender<Msg = ImportProgress> + IdGenerator,
+        progress: FlumeProgressSender<AddProgress>,
+    ) -> anyhow::Result<(TempTag, u64)> {
+        let index = &self.inner.import_index;
+        let stamp = FileStamp::new(&tokio::fs::metadata(&path).await?);
+        if let Some(stamp) = stamp {
+            if let Some(hash) = index.get(&path, stamp, import_mode).await? {
+                // protect the data before checking that it is still there
+                let tag = self.inner.db.temp_tag(HashAndFormat::raw(hash));
+                if self.inner.db.entry_status(&hash).await? == EntryStatus::Complete {
+                    let name = path.to_string_lossy().to_string();
+                    let size = stamp.size;
+                    progress
+                        .send(AddProgress::Skipped { name, size, hash })
+                        .await?;
+                    return Ok((tag, size));
+                }
+            }
+        }
+        let (tag, size) = self
+            .inner
+            .db
+            .import_file(path.clone(), import_mode, BlobFormat::Raw, import_progress)
+            .await?;
+        if let Some(stamp) = stamp {
+            index.insert(&path, stamp, import_mode, *tag.hash()).await?;
+        }
+        Ok((tag, size))
+    }
+
     #[allow(clippy::unused_async)]
     async fn node_stats(self, _req: StatsRequest) -> RpcResult<StatsResponse> {
         #[cfg(feature = "metrics")]
@@ -976,11 +1169,11 @@ impl<D: BaoStore> Handler<D> {
             SetTagOption::Named(tag) => {
                 self.inner
                     .db
-                    .set_tag(tag.clone(), Some(hash_and_format))
+                    .set_tag(tag.clone(), Some(hash_and_format), None)
                     .await?;
                 tag
             }
-            SetTagOption::Auto => self.inner.db.create_tag(hash_and_format).await?,
+            SetTagOption::Auto => self.inner.db.create_tag(hash_and_format, None).await?,
         };
         progress
             .send(AddProgress::AllDone { hash, tag, format })
@@ -1050,6 +1243,73 @@ impl<D: BaoStore> Handler<D> {
         rx.into_stream()
     }
 
+    fn blob_read_at_lazy(
+        self,
+        req: ReadAtLazyRequest,
+    ) -> impl Stream<Item = RpcResult<ReadAtResponse>> + Send + 'static {
+        let (tx, rx) = flume::bounded(RPC_BLOB_GET_CHANNEL_CAP);
+        let db = self.inner.db.clone();
+        let endpoint = self.inner.endpoint.clone();
+        self.inner.rt.spawn_pinned(move || async move {
+            if let Err(err) =
+                read_loop(req, db, endpoint, tx.clone(), RPC_BLOB_GET_CHUNK_SIZE).await
+            {
+                tx.send_async(RpcResult::Err(err.into())).await.ok();
+            }
+        });
+
+        async fn read_loop<D: iroh_blobs::store::Store>(
+            req: ReadAtLazyRequest,
+            db: D,
+            endpoint: Endpoint,
+            tx: flume::Sender<RpcResult<ReadAtResponse>>,
+            max_chunk_size: usize,
+        ) -> anyhow::Result<()> {
+            // protect the partial data from gc while we are reading
+            let _tag = db.temp_tag(HashAndFormat::raw(req.hash));
+            let mut fetcher = RangeFetcher::new(db, endpoint, req.hash, req.nodes).await?;
+            // the first fetch also verifies the size
+            let first = req.len.unwrap_or(max_chunk_size).min(max_chunk_size) as u64;
+            fetcher
+                .ensure(req.offset..req.offset.saturating_add(first), req.prefetch)
+                .await?;
+            let (entry, size) = fetcher.entry_and_size()?;
+            ensure!(req.offset <= size, "offset is beyond the end of the blob");
+            tx.send_async(Ok(ReadAtResponse::Entry {
+                size: BaoBlobSize::Verified(size),
+                is_complete: entry.is_complete(),
+            }))
+            .await?;
+
+            let end = match req.len {
+                Some(len) => (req.offset + len as u64).min(size),
+                None => size,
+            };
+            let mut offset = req.offset;
+            while offset < end {
+                let chunk_size = (end - offset).min(max_chunk_size as u64);
+                fetcher
+                    .ensure(offset..offset + chunk_size, req.prefetch)
+                    .await?;
+                let (entry, _) = fetcher.entry_and_size()?;
+                let chunk = entry
+                    .data_reader()
+                    .await?
+                    .read_at(offset, chunk_size as usize)
+                    .await?;
+                ensure!(
+                    chunk.len() as u64 == chunk_size,
+                    "fetched data is missing from the store"
+                );
+                tx.send_async(Ok(ReadAtResponse::Data { chunk })).await?;
+                offset += chunk_size;
+            }
+            Ok(())
+        }
+
+        rx.into_stream()
+    }
+
     fn node_connections(
         self,
         _: ConnectionsRequest,
@@ -1104,15 +1364,15 @@ impl<D: BaoStore> Handler<D> {
             SetTagOption::Named(tag) => {
                 self.inner
                     .db
-                    .set_tag(tag.clone(), Some(*hash_and_format))
+                    .set_tag(tag.clone(), Some(*hash_and_format), None)
                     .await?;
                 tag
             }
-            SetTagOption::Auto => self.inner.db.create_tag(*hash_and_format).await?,
+            SetTagOption::Auto => self.inner.db.create_tag(*hash_and_format, None).await?,
         };
 
         for tag in tags_to_delete {
-            self.inner.db.set_tag(tag, None).await?;
+            self.inner.db.set_tag(tag, None, None).await?;
         }
 
         Ok(CreateCollectionResponse { hash, tag })
@@ -1135,6 +1395,7 @@ where
         nodes,
         tag,
         mode,
+        priority,
     } = req;
     let hash_and_format = HashAndFormat { hash, format };
     let temp_tag = db.temp_tag(hash_and_format);
@@ -1145,6 +1406,7 @@ where
                 downloader,
                 hash_and_format,
                 nodes,
+                priority,
                 progress.clone(),
             )
             .await?
@@ -1158,10 +1420,10 @@ where
     progress.send(DownloadProgress::AllDone(stats)).await.ok();
     match tag {
         SetTagOption::Named(tag) => {
-            db.set_tag(tag, Some(hash_and_format)).await?;
+            db.set_tag(tag, Some(hash_and_format), None).await?;
         }
         SetTagOption::Auto => {
-            db.create_tag(hash_and_format).await?;
+            db.create_tag(hash_and_format, None).await?;
         }
     }
     drop(temp_tag);
@@ -1174,6 +1436,7 @@ async fn download_queued(
     downloader: &Downloader,
     hash_and_format: HashAndFormat,
     nodes: Vec<NodeAddr>,
+    priority: i32,
     progress: FlumeProgressSender<DownloadProgress>,
 ) -> Result<Stats> {
     let mut node_ids = Vec::with_capacity(nodes.len());
@@ -1187,7 +1450,9 @@ async fn download_queued(
     }
     let can_download = !node_ids.is_empty() && (any_added || endpoint.discovery().is_some());
     anyhow::ensure!(can_download, "no way to reach a node for download");
-    let req = DownloadRequest::new(hash_and_format, node_ids).progress_sender(progress);
+    let req = DownloadRequest::new(hash_and_format, node_ids)
+        .progress_sender(progress)
+        .priority(priority);
     let handle = downloader.queue(req).await;
     let stats = handle.await?;
     Ok(stats)
@@ -1250,6 +1515,193 @@ where
     res.map_err(Into::into)
 }
 
+/// Fetches ranges of a single blob on demand, keeping the partial entry around.
+///
+/// Stores may not persist partial entries, so the entry is kept alive here until the blob is
+/// complete. Candidate nodes are tried in order, and a connection is reused until it fails.
+pub(super) struct RangeFetcher<D: BaoStore> {
+    db: D,
+    endpoint: Endpoint,
+    hash: Hash,
+    nodes: VecDeque<NodeAddr>,
+    conn: Option<Connection>,
+    entry: Option<D::EntryMut>,
+    /// The verified size of the blob, once known.
+    size: Option<u64>,
+    /// The chunks that are present in `entry`.
+    valid: ChunkRanges,
+}
+
+impl<D: BaoStore> RangeFetcher<D> {
+    pub(super) async fn new(
+        db: D,
+        endpoint: Endpoint,
+        hash: Hash,
+        nodes: Vec<NodeAddr>,
+    ) -> Result<Self> {
+        let entry = db.get_mut(&hash).await?;
+        let (size, valid) = match &entry {
+            Some(entry) if entry.is_complete() => (Some(entry.size().value()), ChunkRanges::all()),
+            Some(entry) => (
+                verified_size::<D>(entry).await?,
+                verified_ranges::<D>(entry, &ChunkRanges::all()).await?,
+            ),
+            None => (None, ChunkRanges::empty()),
+        };
+        Ok(Self {
+            db,
+            endpoint,
+            hash,
+            nodes: nodes.into(),
+            conn: None,
+            entry,
+            size,
+            valid,
+        })
+    }
+
+    /// The entry and the verified size of the blob.
+    ///
+    /// Fails if nothing was fetched yet.
+    pub(super) fn entry_and_size(&self) -> Result<(&D::EntryMut, u64)> {
+        match (&self.entry, self.size) {
+            (Some(entry), Some(size)) => Ok((entry, size)),
+            _ => Err(anyhow!("size of the blob is not known")),
+        }
+    }
+
+    /// Make sure the bytes in `range` are present, fetching them plus `prefetch` bytes
+    /// ahead if any of them are missing.
+    pub(super) async fn ensure(
+        &mut self,
+        range: std::ops::Range<u64>,
+        prefetch: u64,
+    ) -> Result<()> {
+        let Some(ranges) = ranges_to_fetch(range, prefetch, self.size, &self.valid) else {
+            return Ok(());
+        };
+        let (entry, size) = self.fetch(ranges.clone()).await?;
+        let size = *self.size.get_or_insert(size);
+        // track what we fetched instead of asking the entry, since valid ranges computed
+        // from the outboard can include chunks next to the fetched ones
+        let all = ChunkRanges::from(..ChunkNum::chunks(size));
+        self.valid |= ranges.intersection(&all);
+        let beyond_end: ChunkRanges = ranges.difference(&all);
+        if !beyond_end.is_empty() {
+            // ranges past the end stand for the last chunk
+            let last = ChunkNum(ChunkNum::chunks(size).0.saturating_sub(1));
+            self.valid |= ChunkRanges::from(last..ChunkNum::chunks(size));
+        }
+        if self.valid.is_superset(&all) {
+            self.db.insert_complete(entry.clone()).await?;
+        }
+        self.entry = Some(entry);
+        Ok(())
+    }
+
+    /// Fetch `ranges` from the first node that can provide them.
+    ///
+    /// Returns the entry and the size of the blob claimed by the node.
+    async fn fetch(&mut self, ranges: ChunkRanges) -> Result<(D::EntryMut, u64)> {
+        let mut last_err = None;
+        loop {
+            let conn = match &self.conn {
+                Some(conn) => conn.clone(),
+                None => {
+                    let Some(node) = self.nodes.pop_front() else {
+                        let err = last_err.unwrap_or_else(|| anyhow!("no nodes to fetch from"));
+                        return Err(err.context(format!("failed to fetch {}", self.hash)));
+                    };
+                    match self
+                        .endpoint
+                        .connect(node, iroh_blobs::protocol::ALPN)
+                        .await
+                    {
+                        Ok(conn) => self.conn.insert(conn).clone(),
+                        Err(err) => {
+                            debug!("failed to connect: {err:#}");
+                            last_err = Some(err);
+                            continue;
+                        }
+                    }
+                }
+            };
+            let res = get_ranges(
+                &self.db,
+                conn,
+                &self.hash,
+                self.entry.clone(),
+                ranges.clone(),
+            )
+            .await;
+            match res {
+                Ok((entry, size, _stats)) => return Ok((entry, size)),
+                Err(err) => {
+                    debug!("failed to fetch ranges: {err:#}");
+                    self.conn = None;
+                    last_err = Some(err.into());
+                }
+            }
+        }
+    }
+}
+
+/// The chunks to fetch so that the bytes in `range` are present, or `None` if they already are.
+///
+/// Missing data is fetched with `prefetch` bytes ahead, but not past the end of the blob if its
+/// `size` is known. If it is not, the last chunk is requested as well to verify the size.
+fn ranges_to_fetch(
+    range: std::ops::Range<u64>,
+    prefetch: u64,
+    size: Option<u64>,
+    valid: &ChunkRanges,
+) -> Option<ChunkRanges> {
+    let wanted = ChunkRanges::from(ChunkNum::full_chunks(range.start)..ChunkNum::chunks(range.end));
+    if size.is_some() && valid.is_superset(&wanted) {
+        return None;
+    }
+    let end = range.end.saturating_add(prefetch);
+    let mut ranges = ChunkRanges::from(ChunkNum::full_chunks(range.start)..ChunkNum::chunks(end));
+    match size {
+        Some(size) => ranges = ranges.intersection(&ChunkRanges::from(..ChunkNum::chunks(size))),
+        // request the last chunk to verify the size
+        None => ranges = ranges.union(&ChunkRanges::from(ChunkNum(u64::MAX)..)),
+    }
+    Some(ranges.difference(valid))
+}
+
 fn docs_disabled() -> RpcError {
     anyhow!("docs are disabled").into()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(range: std::ops::Range<u64>) -> ChunkRanges {
+        ChunkRanges::from(ChunkNum(range.start)..ChunkNum(range.end))
+    }
+
+    #[test]
+    fn read_ahead_ranges() {
+        // a read of missing data fetches ahead, but not past the end of the blob
+        let ranges = ranges_to_fetch(0..100, 4096, Some(10_000), &ChunkRanges::empty());
+        assert_eq!(ranges, Some(chunks(0..5)));
+        let ranges = ranges_to_fetch(8192..9000, 4096, Some(10_000), &ChunkRanges::empty());
+        assert_eq!(ranges, Some(chunks(8..10)));
+
+        // chunks that are present are not fetched again
+        let ranges = ranges_to_fetch(0..100, 4096, Some(10_000), &chunks(2..4));
+        assert_eq!(ranges, Some(chunks(0..2).union(&chunks(4..5))));
+
+        // nothing is fetched if the read is covered
+        assert_eq!(
+            ranges_to_fetch(1024..3000, 4096, Some(10_000), &chunks(1..3)),
+            None
+        );
+
+        // with an unknown size, the last chunk is fetched to verify it
+        let ranges = ranges_to_fetch(1024..3000, 0, None, &chunks(1..3));
+        assert_eq!(ranges, Some(ChunkRanges::from(ChunkNum(u64::MAX)..)));
+    }
+}
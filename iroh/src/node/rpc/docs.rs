@@ -21,12 +21,13 @@ use crate::rpc_protocol::{
         CreateResponse as DocCreateResponse, DelRequest, DelResponse, DocListRequest,
         DocSubscribeRequest, DocSubscribeResponse, DropRequest, DropResponse,
         GetDownloadPolicyRequest, GetDownloadPolicyResponse, GetExactRequest, GetExactResponse,
-        GetManyRequest, GetManyResponse, GetSyncPeersRequest, GetSyncPeersResponse,
-        ImportRequest as DocImportRequest, ImportResponse as DocImportResponse, LeaveRequest,
-        LeaveResponse, ListResponse as DocListResponse, OpenRequest, OpenResponse,
-        SetDownloadPolicyRequest, SetDownloadPolicyResponse, SetHashRequest, SetHashResponse,
-        SetRequest, SetResponse, ShareRequest, ShareResponse, StartSyncRequest, StartSyncResponse,
-        StatusRequest, StatusResponse,
+        GetHeadsRequest, GetHeadsResponse, GetManyRequest, GetManyResponse, GetSyncPeersRequest,
+        GetSyncPeersResponse, ImportRequest as DocImportRequest,
+        ImportResponse as DocImportResponse, LeaveRequest, LeaveResponse,
+        ListResponse as DocListResponse, OpenRequest, OpenResponse, RevokeWriteRequest,
+        RevokeWriteResponse, SetDownloadPolicyRequest, SetDownloadPolicyResponse, SetHashRequest,
+        SetHashResponse, SetRequest, SetResponse, ShareDelegatedRequest, ShareRequest,
+        ShareResponse, StartSyncRequest, StartSyncResponse, StatusRequest, StatusResponse,
     },
 };
 
@@ -167,6 +168,41 @@ impl DocsEngine {
         }))
     }
 
+    pub async fn doc_share_delegated(
+        &self,
+        req: ShareDelegatedRequest,
+    ) -> RpcResult<ShareResponse> {
+        let ShareDelegatedRequest {
+            doc_id,
+            author_id,
+            prefix,
+            expires,
+            addr_options,
+        } = req;
+        let mut me = self.endpoint.node_addr().await?;
+        me.apply_options(addr_options);
+
+        let grant = self
+            .sync
+            .grant_write(doc_id, author_id, prefix, expires)
+            .await?;
+        self.start_sync(doc_id, vec![]).await?;
+
+        Ok(ShareResponse(DocTicket {
+            capability: iroh_docs::Capability::Delegated(grant),
+            nodes: vec![me],
+        }))
+    }
+
+    pub async fn doc_revoke_write(
+        &self,
+        req: RevokeWriteRequest,
+    ) -> RpcResult<RevokeWriteResponse> {
+        let RevokeWriteRequest { doc_id, author_id } = req;
+        let removed = self.sync.revoke_write(doc_id, author_id).await?;
+        Ok(RevokeWriteResponse { removed })
+    }
+
     pub async fn doc_subscribe(
         &self,
         req: DocSubscribeRequest,
@@ -208,11 +244,19 @@ impl DocsEngine {
             author_id,
             key,
             value,
+            expires,
         } = req;
         let len = value.len();
         let tag = bao_store.import_bytes(value, BlobFormat::Raw).await?;
         self.sync
-            .insert_local(doc_id, author_id, key.clone(), *tag.hash(), len as u64)
+            .insert_local(
+                doc_id,
+                author_id,
+                key.clone(),
+                *tag.hash(),
+                len as u64,
+                expires,
+            )
             .await?;
         let entry = self
             .sync
@@ -241,7 +285,7 @@ impl DocsEngine {
             size,
         } = req;
         self.sync
-            .insert_local(doc_id, author_id, key.clone(), hash, size)
+            .insert_local(doc_id, author_id, key.clone(), hash, size, None)
             .await?;
         Ok(SetHashResponse {})
     }
@@ -279,6 +323,12 @@ impl DocsEngine {
         Ok(GetExactResponse { entry })
     }
 
+    pub async fn doc_get_heads(&self, req: GetHeadsRequest) -> RpcResult<GetHeadsResponse> {
+        let GetHeadsRequest { doc_id, key } = req;
+        let entries = self.sync.get_heads(doc_id, key).await?;
+        Ok(GetHeadsResponse { entries })
+    }
+
     pub async fn doc_set_download_policy(
         &self,
         req: SetDownloadPolicyRequest,
@@ -4,11 +4,15 @@ use bytes::Bytes;
 use iroh_base::hash::Hash;
 use iroh_base::rpc::RpcResult;
 use iroh_blobs::{
+    downloader::{IntentId, QueueSnapshot},
     export::ExportProgress,
-    format::collection::Collection,
+    format::{chunked::ChunkerConfig, collection::Collection},
     get::db::DownloadProgress,
     provider::AddProgress,
-    store::{BaoBlobSize, ConsistencyCheckProgress, ExportFormat, ExportMode, ValidateProgress},
+    store::{
+        BaoBlobSize, ConsistencyCheckProgress, ExportFormat, ExportMode, StoreStats,
+        ValidateProgress,
+    },
     util::SetTagOption,
     BlobFormat, Tag,
 };
@@ -27,16 +31,22 @@ use super::RpcService;
 #[nested_enum_utils::enum_conversions(super::Request)]
 pub enum Request {
     ReadAt(ReadAtRequest),
+    ReadAtLazy(ReadAtLazyRequest),
     AddStream(AddStreamRequest),
     AddStreamUpdate(AddStreamUpdate),
     AddPath(AddPathRequest),
     Download(DownloadRequest),
+    DownloadQueue(DownloadQueueRequest),
+    SetDownloadPriority(SetDownloadPriorityRequest),
     Export(ExportRequest),
+    ExportArchive(ExportArchiveRequest),
+    ImportArchive(ImportArchiveRequest),
     List(ListRequest),
     ListIncomplete(ListIncompleteRequest),
     Delete(DeleteRequest),
     Validate(ValidateRequest),
     Fsck(ConsistencyCheckRequest),
+    Stats(StoreStatsRequest),
     CreateCollection(CreateCollectionRequest),
 }
 
@@ -50,9 +60,12 @@ pub enum Response {
     List(RpcResult<BlobInfo>),
     ListIncomplete(RpcResult<IncompleteBlobInfo>),
     Download(DownloadResponse),
+    DownloadQueue(RpcResult<QueueSnapshot>),
     Fsck(ConsistencyCheckProgress),
     Export(ExportResponse),
+    ImportArchive(RpcResult<ImportArchiveResponse>),
     Validate(ValidateProgress),
+    Stats(RpcResult<StoreStats>),
     CreateCollection(RpcResult<CreateCollectionResponse>),
 }
 
@@ -74,6 +87,16 @@ pub struct AddPathRequest {
     pub tag: SetTagOption,
     /// Whether to wrap the added data in a collection
     pub wrap: WrapOption,
+    /// Split the file into content defined chunks with this config.
+    ///
+    /// Only supported for a single file that is not wrapped. The result is a
+    /// [`iroh_blobs::format::chunked::ChunkedBlob`].
+    pub chunking: Option<ChunkerConfig>,
+    /// Keep file system metadata when adding a collection.
+    ///
+    /// File modes, modification times, symlinks and empty directories are
+    /// stored in the collection, so its hash depends on them.
+    pub meta: bool,
 }
 
 impl Msg<RpcService> for AddPathRequest {
@@ -107,6 +130,10 @@ pub struct DownloadRequest {
     pub tag: SetTagOption,
     /// Whether to directly start the download or add it to the download queue.
     pub mode: DownloadMode,
+    /// The priority of the download in the download queue.
+    ///
+    /// Only used if `mode` is set to [`DownloadMode::Queued`].
+    pub priority: i32,
 }
 
 impl Msg<RpcService> for DownloadRequest {
@@ -121,6 +148,27 @@ impl ServerStreamingMsg<RpcService> for DownloadRequest {
 #[derive(Debug, Clone, Serialize, Deserialize, derive_more::From, derive_more::Into)]
 pub struct DownloadResponse(pub DownloadProgress);
 
+/// Get a snapshot of the download queue.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DownloadQueueRequest;
+
+impl RpcMsg<RpcService> for DownloadQueueRequest {
+    type Response = RpcResult<QueueSnapshot>;
+}
+
+/// Change the priority of a queued download.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetDownloadPriorityRequest {
+    /// The download intent to change the priority of.
+    pub intent: IntentId,
+    /// The new priority.
+    pub priority: i32,
+}
+
+impl RpcMsg<RpcService> for SetDownloadPriorityRequest {
+    type Response = RpcResult<()>;
+}
+
 /// A request to the node to download and share the data specified by the hash.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExportRequest {
@@ -152,6 +200,53 @@ impl ServerStreamingMsg<RpcService> for ExportRequest {
 #[derive(Debug, Clone, Serialize, Deserialize, derive_more::From, derive_more::Into)]
 pub struct ExportResponse(pub ExportProgress);
 
+/// A request to the node to write an archive of a blob or hash seq to a file.
+///
+/// See [`iroh_blobs::store::archive`] for the archive format.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportArchiveRequest {
+    /// The hash of the blob or hash seq to export.
+    pub hash: Hash,
+    /// If the format is [`BlobFormat::HashSeq`], all children are exported as well.
+    pub format: BlobFormat,
+    /// The path of the archive file to create.
+    ///
+    /// This should be an absolute path valid for the file system on which
+    /// the node runs.
+    pub path: PathBuf,
+}
+
+impl RpcMsg<RpcService> for ExportArchiveRequest {
+    type Response = RpcResult<()>;
+}
+
+/// A request to the node to validate and store the data of an archive file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportArchiveRequest {
+    /// The path of the archive file.
+    ///
+    /// This should be an absolute path valid for the file system on which
+    /// the node runs.
+    pub path: PathBuf,
+    /// Tag to tag the imported data with.
+    pub tag: SetTagOption,
+}
+
+/// A response to an import archive request
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportArchiveResponse {
+    /// The root hash of the archive.
+    pub hash: Hash,
+    /// The format of the root.
+    pub format: BlobFormat,
+    /// The tag of the imported data.
+    pub tag: Tag,
+}
+
+impl RpcMsg<RpcService> for ImportArchiveRequest {
+    type Response = RpcResult<ImportArchiveResponse>;
+}
+
 /// A request to the node to validate the integrity of all provided data
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConsistencyCheckRequest {
@@ -242,6 +337,31 @@ pub enum ReadAtResponse {
     },
 }
 
+/// Get the bytes for a hash, fetching missing ranges from other nodes
+///
+/// Responds with the same [`ReadAtResponse`] messages as [`ReadAtRequest`].
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReadAtLazyRequest {
+    /// Hash to get bytes for
+    pub hash: Hash,
+    /// Offset to start reading at
+    pub offset: u64,
+    /// Length of the data to get
+    pub len: Option<usize>,
+    /// Nodes to fetch missing ranges from, tried in order
+    pub nodes: Vec<NodeAddr>,
+    /// Number of bytes to fetch ahead of the current read position
+    pub prefetch: u64,
+}
+
+impl Msg<RpcService> for ReadAtLazyRequest {
+    type Pattern = ServerStreaming;
+}
+
+impl ServerStreamingMsg<RpcService> for ReadAtLazyRequest {
+    type Response = RpcResult<ReadAtResponse>;
+}
+
 /// Write a blob from a byte stream
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AddStreamRequest {
@@ -271,6 +391,14 @@ impl BidiStreamingMsg<RpcService> for AddStreamRequest {
 #[derive(Debug, Serialize, Deserialize, derive_more::Into)]
 pub struct AddStreamResponse(pub AddProgress);
 
+/// Get statistics for the blob store of the node.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoreStatsRequest;
+
+impl RpcMsg<RpcService> for StoreStatsRequest {
+    type Response = RpcResult<StoreStats>;
+}
+
 /// Delete a blob
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DeleteRequest {
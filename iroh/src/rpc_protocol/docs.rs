@@ -36,12 +36,15 @@ pub enum Request {
     SetHash(SetHashRequest),
     Get(GetManyRequest),
     GetExact(GetExactRequest),
+    GetHeads(GetHeadsRequest),
     ImportFile(ImportFileRequest),
     ExportFile(ExportFileRequest),
     Del(DelRequest),
     StartSync(StartSyncRequest),
     Leave(LeaveRequest),
     Share(ShareRequest),
+    ShareDelegated(ShareDelegatedRequest),
+    RevokeWrite(RevokeWriteRequest),
     Subscribe(DocSubscribeRequest),
     GetDownloadPolicy(GetDownloadPolicyRequest),
     SetDownloadPolicy(SetDownloadPolicyRequest),
@@ -63,10 +66,12 @@ pub enum Response {
     SetHash(RpcResult<SetHashResponse>),
     Get(RpcResult<GetManyResponse>),
     GetExact(RpcResult<GetExactResponse>),
+    GetHeads(RpcResult<GetHeadsResponse>),
     ImportFile(ImportFileResponse),
     ExportFile(ExportFileResponse),
     Del(RpcResult<DelResponse>),
     Share(RpcResult<ShareResponse>),
+    RevokeWrite(RpcResult<RevokeWriteResponse>),
     StartSync(RpcResult<StartSyncResponse>),
     Leave(RpcResult<LeaveResponse>),
     Subscribe(RpcResult<DocSubscribeResponse>),
@@ -169,10 +174,49 @@ impl RpcMsg<RpcService> for ShareRequest {
     type Response = RpcResult<ShareResponse>;
 }
 
-/// The response to [`ShareRequest`]
+/// The response to [`ShareRequest`] and [`ShareDelegatedRequest`]
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ShareResponse(pub DocTicket);
 
+/// Allow an author to write to a document, and share the grant over a ticket.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ShareDelegatedRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+    /// The author that may write to the document
+    pub author_id: AuthorId,
+    /// Prefix that the keys written by the author must start with
+    pub prefix: Bytes,
+    /// Time in microseconds since the Unix epoch from which on entries are no longer covered
+    pub expires: Option<u64>,
+    /// Configuration of the addresses in the ticket.
+    pub addr_options: AddrInfoOptions,
+}
+
+impl RpcMsg<RpcService> for ShareDelegatedRequest {
+    type Response = RpcResult<ShareResponse>;
+}
+
+/// Revoke all write grants of an author for a document
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RevokeWriteRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+    /// The author whose grants are revoked
+    pub author_id: AuthorId,
+}
+
+impl RpcMsg<RpcService> for RevokeWriteRequest {
+    type Response = RpcResult<RevokeWriteResponse>;
+}
+
+/// Response to [`RevokeWriteRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RevokeWriteResponse {
+    /// The number of grants that were revoked.
+    pub removed: usize,
+}
+
 /// Get info on a document
 #[derive(Serialize, Deserialize, Debug)]
 pub struct StatusRequest {
@@ -282,6 +326,8 @@ pub struct SetRequest {
     // TODO: Allow to provide the hash directly
     // TODO: Add a way to provide content as stream
     pub value: Bytes,
+    /// Expiry of this entry, in microseconds since the Unix epoch.
+    pub expires: Option<u64>,
 }
 
 impl RpcMsg<RpcService> for SetRequest {
@@ -455,6 +501,26 @@ pub struct GetExactResponse {
     pub entry: Option<SignedEntry>,
 }
 
+/// Get the entries of all authors for a key
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetHeadsRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+    /// Key of the entries
+    pub key: Bytes,
+}
+
+impl RpcMsg<RpcService> for GetHeadsRequest {
+    type Response = RpcResult<GetHeadsResponse>;
+}
+
+/// Response to [`GetHeadsRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetHeadsResponse {
+    /// The entries of all authors for the key, newest first
+    pub entries: Vec<SignedEntry>,
+}
+
 /// Set a download policy
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SetDownloadPolicyRequest {
@@ -507,3 +573,29 @@ pub struct GetSyncPeersResponse {
     /// List of peers ids
     pub peers: Option<Vec<PeerIdBytes>>,
 }
+
+#[cfg(test)]
+mod tests {
+    use iroh_docs::{Author, NamespaceSecret, Record, RecordIdentifier};
+
+    use super::*;
+
+    #[test]
+    fn entry_response_keeps_expiry() {
+        let mut rng = rand::thread_rng();
+        let namespace = NamespaceSecret::new(&mut rng);
+        let author = Author::new(&mut rng);
+        let record = Record::new_current(Hash::new(b"here"), 4);
+        let expires = record.timestamp() + 60_000_000;
+        let record = record.with_expires(Some(expires));
+        let id = RecordIdentifier::new(namespace.id(), author.id(), b"presence");
+        let entry = Entry::new(id, record).sign(&namespace, &author);
+
+        let response = GetManyResponse { entry };
+        let bytes = postcard::to_stdvec(&response).unwrap();
+        let decoded: GetManyResponse = postcard::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.entry, response.entry);
+        assert_eq!(decoded.entry.expires(), Some(expires));
+        assert!(decoded.entry.verify(&(), &Default::default()).is_ok());
+    }
+}
@@ -1,5 +1,5 @@
 use iroh_base::rpc::RpcResult;
-use iroh_blobs::Tag;
+use iroh_blobs::{util::TagUpdate, Tag};
 use quic_rpc::message::{Msg, RpcMsg, ServerStreaming, ServerStreamingMsg};
 use serde::{Deserialize, Serialize};
 
@@ -12,7 +12,9 @@ use super::RpcService;
 #[nested_enum_utils::enum_conversions(super::Request)]
 pub enum Request {
     DeleteTag(DeleteRequest),
+    DeletePrefix(DeletePrefixRequest),
     ListTags(ListRequest),
+    UpdateTags(UpdateRequest),
 }
 
 #[allow(missing_docs)]
@@ -21,6 +23,8 @@ pub enum Request {
 pub enum Response {
     ListTags(TagInfo),
     DeleteTag(RpcResult<()>),
+    DeletePrefix(RpcResult<u64>),
+    UpdateTags(RpcResult<bool>),
 }
 
 /// List all collections
@@ -32,6 +36,8 @@ pub struct ListRequest {
     pub raw: bool,
     /// List hash seq tags
     pub hash_seq: bool,
+    /// Only list tags that start with this prefix
+    pub prefix: Option<Tag>,
 }
 
 impl ListRequest {
@@ -40,6 +46,7 @@ impl ListRequest {
         Self {
             raw: true,
             hash_seq: true,
+            prefix: None,
         }
     }
 
@@ -48,6 +55,7 @@ impl ListRequest {
         Self {
             raw: true,
             hash_seq: false,
+            prefix: None,
         }
     }
 
@@ -56,8 +64,15 @@ impl ListRequest {
         Self {
             raw: false,
             hash_seq: true,
+            prefix: None,
         }
     }
+
+    /// Only list tags that start with `prefix`
+    pub fn with_prefix(mut self, prefix: Tag) -> Self {
+        self.prefix = Some(prefix);
+        self
+    }
 }
 
 impl Msg<RpcService> for ListRequest {
@@ -78,3 +93,30 @@ pub struct DeleteRequest {
 impl RpcMsg<RpcService> for DeleteRequest {
     type Response = RpcResult<()>;
 }
+
+/// Delete all tags that start with a prefix
+///
+/// Responds with the number of deleted tags.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeletePrefixRequest {
+    /// Prefix of the tags to delete
+    pub prefix: Tag,
+}
+
+impl RpcMsg<RpcService> for DeletePrefixRequest {
+    type Response = RpcResult<u64>;
+}
+
+/// Atomically apply a batch of tag changes
+///
+/// Responds with `false` if the expectations of the changes did not hold, in
+/// which case nothing was changed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateRequest {
+    /// The changes, see [`TagUpdate`]
+    pub updates: Vec<TagUpdate>,
+}
+
+impl RpcMsg<RpcService> for UpdateRequest {
+    type Response = RpcResult<bool>;
+}
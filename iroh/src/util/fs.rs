@@ -7,6 +7,7 @@ use std::{
 
 use anyhow::{bail, Context};
 use bytes::Bytes;
+use iroh_blobs::format::collection::EntryMeta;
 use iroh_net::key::SecretKey;
 use tokio::io::AsyncWriteExt;
 use walkdir::WalkDir;
@@ -84,11 +85,7 @@ pub fn scan_dir(root: PathBuf, wrap: WrapOption) -> anyhow::Result<Vec<DataSourc
     if !root.is_dir() {
         bail!("Expected {} to be a file", root.to_string_lossy());
     }
-    let prefix = match wrap {
-        WrapOption::NoWrap => None,
-        WrapOption::Wrap { name: None } => Some(file_name(&root)?),
-        WrapOption::Wrap { name: Some(name) } => Some(name),
-    };
+    let prefix = wrap_prefix(&root, wrap)?;
     let files = WalkDir::new(&root).into_iter();
     let data_sources = files
         .map(|entry| {
@@ -109,6 +106,51 @@ pub fn scan_dir(root: PathBuf, wrap: WrapOption) -> anyhow::Result<Vec<DataSourc
     data_sources.into_iter().collect::<anyhow::Result<Vec<_>>>()
 }
 
+/// Read file system metadata for all entries below a path.
+///
+/// Entries are named like the data sources returned by [`scan_path`], but
+/// unlike [`scan_path`] this includes directories and symlinks.
+pub fn scan_path_meta(path: PathBuf, wrap: WrapOption) -> anyhow::Result<Vec<(String, EntryMeta)>> {
+    if !path.is_dir() {
+        return scan_path(path, wrap)?
+            .into_iter()
+            .map(|source| Ok((source.name, EntryMeta::from_path(&source.path)?)))
+            .collect();
+    }
+    let prefix = wrap_prefix(&path, wrap)?;
+    WalkDir::new(&path)
+        .into_iter()
+        .map(|entry| {
+            let entry = entry?;
+            let relative = entry.path().strip_prefix(&path)?;
+            let name = if relative.as_os_str().is_empty() {
+                // the root itself only has a name if it is wrapped
+                let Some(prefix) = &prefix else {
+                    return Ok(None);
+                };
+                prefix.clone()
+            } else {
+                let name = relative_canonicalized_path_to_string(relative)?;
+                match &prefix {
+                    Some(prefix) => format!("{prefix}/{name}"),
+                    None => name,
+                }
+            };
+            anyhow::Ok(Some((name, EntryMeta::from_path(entry.path())?)))
+        })
+        .filter_map(Result::transpose)
+        .collect()
+}
+
+/// The name prefix for entries below `root`, depending on the wrap option.
+fn wrap_prefix(root: &Path, wrap: WrapOption) -> anyhow::Result<Option<String>> {
+    Ok(match wrap {
+        WrapOption::NoWrap => None,
+        WrapOption::Wrap { name: None } => Some(file_name(root)?),
+        WrapOption::Wrap { name: Some(name) } => Some(name),
+    })
+}
+
 /// This function converts a canonicalized relative path to a string, returning
 /// an error if the path is not valid unicode.
 ///
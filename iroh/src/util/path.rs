@@ -27,6 +27,9 @@ pub enum IrohPaths {
     /// Path to the [`iroh_docs::AuthorId`] of the node's default author
     #[strum(serialize = "default-author")]
     DefaultAuthor,
+    /// Path to the index of files imported from the file system, used to skip unchanged files.
+    #[strum(serialize = "import-index.redb")]
+    ImportIndex,
 }
 
 impl AsRef<Path> for IrohPaths {
@@ -0,0 +1,114 @@
+#![cfg(all(feature = "fuse", unix))]
+use std::path::Path;
+
+use anyhow::Result;
+use futures_lite::StreamExt;
+use iroh::{
+    client::blobs::{AddPathOptions, WrapOption},
+    node::{MountRoot, Node},
+};
+use iroh_blobs::util::SetTagOption;
+use rand::RngCore;
+
+/// Read a file of a mount on a blocking thread, since reads are served by
+/// the same runtime.
+async fn read(path: impl AsRef<Path>) -> Result<Vec<u8>> {
+    let path = path.as_ref().to_owned();
+    Ok(tokio::task::spawn_blocking(move || std::fs::read(path)).await??)
+}
+
+/// Read a range of a file of a mount on a blocking thread.
+async fn read_at(path: impl AsRef<Path>, offset: u64, len: usize) -> Result<Vec<u8>> {
+    use std::os::unix::fs::FileExt;
+
+    let path = path.as_ref().to_owned();
+    Ok(tokio::task::spawn_blocking(move || {
+        let mut buf = vec![0u8; len];
+        std::fs::File::open(path)?.read_exact_at(&mut buf, offset)?;
+        std::io::Result::Ok(buf)
+    })
+    .await??)
+}
+
+#[tokio::test]
+#[ignore = "needs fuse support, i.e. /dev/fuse and the permission to mount"]
+async fn mount_collection_fetches_on_demand() -> Result<()> {
+    let _guard = iroh_test::logging::setup();
+    let dir = tempfile::tempdir()?;
+    let src = dir.path().join("src");
+    std::fs::create_dir_all(src.join("sub"))?;
+    std::fs::create_dir_all(src.join("empty"))?;
+    let mut large = vec![0u8; 8 * 1024 * 1024];
+    rand::thread_rng().fill_bytes(&mut large);
+    std::fs::write(src.join("sub/large"), &large)?;
+    std::fs::write(src.join("small"), b"hello")?;
+
+    let provider = Node::memory().bind_port(0).spawn().await?;
+    let opts = AddPathOptions {
+        in_place: false,
+        tag: SetTagOption::Auto,
+        wrap: WrapOption::NoWrap,
+        chunking: None,
+        meta: true,
+    };
+    let outcome = provider
+        .blobs()
+        .add_from_path_with_opts(src, opts)
+        .await?
+        .finish()
+        .await?;
+
+    // the mounting node has nothing locally, everything is fetched on demand
+    let node = Node::memory().bind_port(0).spawn().await?;
+    let mountpoint = dir.path().join("mnt");
+    std::fs::create_dir_all(&mountpoint)?;
+    let mount = node
+        .mount(
+            MountRoot::Collection(outcome.hash),
+            &mountpoint,
+            vec![provider.node_addr().await?],
+        )
+        .await?;
+
+    let mut names = std::fs::read_dir(&mountpoint)?
+        .map(|entry| Ok(entry?.file_name().into_string().unwrap()))
+        .collect::<Result<Vec<_>>>()?;
+    names.sort();
+    assert_eq!(names, vec!["empty", "small", "sub"]);
+    assert!(mountpoint.join("empty").is_dir());
+    assert_eq!(read(mountpoint.join("small")).await?, b"hello");
+    // reading a small range only fetches the chunks around it
+    let offset = 4 * 1024 * 1024;
+    assert_eq!(
+        read_at(mountpoint.join("sub/large"), offset as u64, 1024).await?,
+        &large[offset..offset + 1024]
+    );
+    let large_hash = iroh_blobs::Hash::new(&large);
+    let complete = node
+        .blobs()
+        .list()
+        .await?
+        .try_collect::<_, _, Vec<_>>()
+        .await?;
+    assert!(!complete.iter().any(|info| info.hash == large_hash));
+    assert_eq!(read(mountpoint.join("sub/large")).await?, large);
+    let complete = node
+        .blobs()
+        .list()
+        .await?
+        .try_collect::<_, _, Vec<_>>()
+        .await?;
+    assert!(complete.iter().any(|info| info.hash == large_hash));
+    // once fetched, the size is known
+    assert_eq!(
+        std::fs::metadata(mountpoint.join("sub/large"))?.len(),
+        large.len() as u64
+    );
+    // the mount is read only
+    assert!(std::fs::write(mountpoint.join("small"), b"x").is_err());
+
+    tokio::task::spawn_blocking(move || mount.unmount()).await?;
+    node.shutdown().await?;
+    provider.shutdown().await?;
+    Ok(())
+}
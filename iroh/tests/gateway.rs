@@ -0,0 +1,311 @@
+#![cfg(feature = "gateway")]
+use std::{net::SocketAddr, time::Duration};
+
+use anyhow::{Context, Result};
+use futures_lite::StreamExt;
+use iroh::{
+    base::ticket::BlobTicket,
+    client::blobs::BlobStatus,
+    node::{GcPolicy, Node},
+};
+use iroh_blobs::{
+    format::collection::Collection,
+    util::{SetTagOption, Tag},
+    BlobFormat,
+};
+use rand::RngCore;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// A minimal http response.
+#[derive(Debug)]
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Send a request over a fresh connection and read the whole response.
+async fn request(
+    addr: SocketAddr,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+) -> Result<Response> {
+    let mut stream = tokio::net::TcpStream::connect(addr).await?;
+    let mut req = format!("{method} {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n");
+    for (name, value) in headers {
+        req.push_str(&format!("{name}: {value}\r\n"));
+    }
+    req.push_str("\r\n");
+    stream.write_all(req.as_bytes()).await?;
+    let mut res = Vec::new();
+    stream.read_to_end(&mut res).await?;
+    let split = res
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .context("no end of headers")?;
+    let head = std::str::from_utf8(&res[..split])?;
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .context("no status")?
+        .parse()?;
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_owned(), value.trim().to_owned()))
+        .collect();
+    Ok(Response {
+        status,
+        headers,
+        body: res[split + 4..].to_vec(),
+    })
+}
+
+#[tokio::test]
+async fn gateway_serves_blobs_and_collections() -> Result<()> {
+    let _guard = iroh_test::logging::setup();
+    let localhost: SocketAddr = "127.0.0.1:0".parse()?;
+    let provider = Node::memory()
+        .bind_port(0)
+        .http_gateway(localhost)
+        .spawn()
+        .await?;
+    let addr = provider.gateway_addr().context("gateway not running")?;
+
+    let mut video = vec![0u8; 1024 * 200];
+    rand::thread_rng().fill_bytes(&mut video);
+    let video_hash = provider.blobs().add_bytes(video.clone()).await?.hash;
+    let index_hash = provider
+        .blobs()
+        .add_bytes(&b"<html></html>"[..])
+        .await?
+        .hash;
+    let collection: Collection = [("site/index.html", index_hash), ("video.mp4", video_hash)]
+        .into_iter()
+        .collect();
+    let (collection_hash, _) = provider
+        .blobs()
+        .create_collection(collection, SetTagOption::Auto, Vec::new())
+        .await?;
+
+    // a full blob
+    let res = request(addr, "GET", &format!("/blob/{video_hash}"), &[]).await?;
+    assert_eq!(res.status, 200);
+    assert_eq!(res.body, video);
+    assert_eq!(res.header("content-type"), Some("application/octet-stream"));
+    assert_eq!(res.header("accept-ranges"), Some("bytes"));
+    let etag = res.header("etag").context("no etag")?.to_owned();
+    assert_eq!(etag, format!("\"{}\"", video_hash.to_hex()));
+
+    // conditional requests
+    let res = request(
+        addr,
+        "GET",
+        &format!("/blob/{video_hash}"),
+        &[("If-None-Match", &etag)],
+    )
+    .await?;
+    assert_eq!(res.status, 304);
+    assert!(res.body.is_empty());
+
+    // ranges
+    let res = request(
+        addr,
+        "GET",
+        &format!("/blob/{video_hash}"),
+        &[("Range", "bytes=100000-100099")],
+    )
+    .await?;
+    assert_eq!(res.status, 206);
+    assert_eq!(
+        res.header("content-range"),
+        Some(format!("bytes 100000-100099/{}", video.len()).as_str())
+    );
+    assert_eq!(res.body, &video[100000..100100]);
+    let res = request(
+        addr,
+        "GET",
+        &format!("/blob/{video_hash}"),
+        &[("Range", "bytes=-10"), ("If-Range", "\"other\"")],
+    )
+    .await?;
+    assert_eq!(res.status, 200);
+    assert_eq!(res.body, video);
+    let res = request(
+        addr,
+        "GET",
+        &format!("/blob/{video_hash}"),
+        &[("Range", &format!("bytes={}-", video.len()))],
+    )
+    .await?;
+    assert_eq!(res.status, 416);
+    let res = request(addr, "HEAD", &format!("/blob/{video_hash}"), &[]).await?;
+    assert_eq!(res.status, 200);
+    assert_eq!(
+        res.header("content-length"),
+        Some(video.len().to_string().as_str())
+    );
+    assert!(res.body.is_empty());
+
+    // collections
+    let res = request(
+        addr,
+        "GET",
+        &format!("/collection/{collection_hash}/video.mp4"),
+        &[],
+    )
+    .await?;
+    assert_eq!(res.status, 200);
+    assert_eq!(res.header("content-type"), Some("video/mp4"));
+    assert_eq!(res.body, video);
+    let res = request(
+        addr,
+        "GET",
+        &format!("/collection/{collection_hash}/site/"),
+        &[],
+    )
+    .await?;
+    assert_eq!(res.status, 200);
+    assert_eq!(res.header("content-type"), Some("text/html"));
+    assert_eq!(res.body, b"<html></html>");
+    let res = request(
+        addr,
+        "GET",
+        &format!("/collection/{collection_hash}/missing"),
+        &[],
+    )
+    .await?;
+    assert_eq!(res.status, 404);
+
+    // unknown content and garbage
+    let node = Node::memory()
+        .bind_port(0)
+        .http_gateway(localhost)
+        .spawn()
+        .await?;
+    let addr = node.gateway_addr().context("gateway not running")?;
+    let res = request(addr, "GET", &format!("/blob/{video_hash}"), &[]).await?;
+    assert_eq!(res.status, 404);
+    let res = request(addr, "GET", "/blob/garbage", &[]).await?;
+    assert_eq!(res.status, 400);
+
+    // tickets are only accepted when enabled
+    let provider_addr = provider.node_addr().await?;
+    let ticket = BlobTicket::new(provider_addr.clone(), video_hash, BlobFormat::Raw)?;
+    let res = request(addr, "GET", &format!("/blob/{ticket}"), &[]).await?;
+    assert_eq!(res.status, 403);
+    node.shutdown().await?;
+
+    // content fetched with tickets
+    let node = Node::memory()
+        .bind_port(0)
+        .http_gateway(localhost)
+        .http_gateway_fetch_tickets(true)
+        .spawn()
+        .await?;
+    let addr = node.gateway_addr().context("gateway not running")?;
+    let res = request(
+        addr,
+        "GET",
+        &format!("/blob/{ticket}"),
+        &[("Range", "bytes=-1000")],
+    )
+    .await?;
+    assert_eq!(res.status, 206);
+    assert_eq!(res.body, &video[video.len() - 1000..]);
+    let ticket = BlobTicket::new(provider_addr, collection_hash, BlobFormat::HashSeq)?;
+    let res = request(addr, "GET", &format!("/collection/{ticket}/video.mp4"), &[]).await?;
+    assert_eq!(res.status, 200);
+    assert_eq!(res.body, video);
+    // fetched content is tagged
+    let tags = node
+        .tags()
+        .list_prefix("gateway/")
+        .await?
+        .map(|tag| tag.map(|tag| (tag.name, tag.hash, tag.format)))
+        .try_collect::<_, _, Vec<_>>()
+        .await?;
+    assert_eq!(
+        tags,
+        vec![
+            (
+                Tag::from(format!("gateway/blob/{video_hash}")),
+                video_hash,
+                BlobFormat::Raw
+            ),
+            (
+                Tag::from(format!("gateway/collection/{collection_hash}")),
+                collection_hash,
+                BlobFormat::HashSeq
+            ),
+        ]
+    );
+
+    node.shutdown().await?;
+    provider.shutdown().await?;
+    Ok(())
+}
+
+/// Content fetched with tickets is only kept until its tag expires.
+#[tokio::test]
+async fn gateway_fetched_content_expires() -> Result<()> {
+    let _guard = iroh_test::logging::setup();
+    let localhost: SocketAddr = "127.0.0.1:0".parse()?;
+    let provider = Node::memory().bind_port(0).spawn().await?;
+    let mut data = vec![0u8; 1024 * 20];
+    rand::thread_rng().fill_bytes(&mut data);
+    let hash = provider.blobs().add_bytes(data.clone()).await?.hash;
+    let ticket = BlobTicket::new(provider.node_addr().await?, hash, BlobFormat::Raw)?;
+
+    let (gc_send, gc_recv) = flume::unbounded();
+    let node = Node::memory()
+        .bind_port(0)
+        .http_gateway(localhost)
+        .http_gateway_fetch_tickets(true)
+        .http_gateway_fetch_ttl(Duration::from_secs(1))
+        .gc_policy(GcPolicy::Interval(Duration::from_millis(100)))
+        .register_gc_done_cb(Box::new(move || {
+            gc_send.send(()).ok();
+        }))
+        .spawn()
+        .await?;
+    let addr = node.gateway_addr().context("gateway not running")?;
+    let res = request(addr, "GET", &format!("/blob/{ticket}"), &[]).await?;
+    assert_eq!(res.status, 200);
+    assert_eq!(res.body, data);
+    assert_eq!(
+        node.blobs().status(hash).await?,
+        BlobStatus::Complete {
+            size: data.len() as u64
+        }
+    );
+
+    // once the tag has expired, gc deletes the content
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    while gc_recv.try_recv().is_ok() {}
+    for _ in 0..3 {
+        gc_recv.recv_async().await?;
+    }
+    assert!(node.blobs().status(hash).await.is_err());
+    let tags = node
+        .tags()
+        .list_prefix("gateway/")
+        .await?
+        .try_collect::<_, _, Vec<_>>()
+        .await?;
+    assert!(tags.is_empty());
+
+    node.shutdown().await?;
+    provider.shutdown().await?;
+    Ok(())
+}
@@ -11,7 +11,7 @@ use rand::RngCore;
 
 use iroh_blobs::{
     hashseq::HashSeq,
-    store::{EntryStatus, MapMut, Store},
+    store::{EntryStatus, Map, MapMut, ReadableStore, Store},
     util::Tag,
     BlobFormat, HashAndFormat, IROH_BLOCK_SIZE,
 };
@@ -67,6 +67,31 @@ async fn gc_test_node() -> (
     (node, bao_store, gc_recv)
 }
 
+async fn gc_test_node_with_quota(
+    quota: u64,
+) -> (
+    Node<iroh_blobs::store::mem::Store>,
+    iroh_blobs::store::mem::Store,
+    flume::Receiver<()>,
+) {
+    let bao_store = iroh_blobs::store::mem::Store::new();
+    let (gc_send, gc_recv) = flume::unbounded();
+    let node = node::Builder::with_db_and_store(
+        bao_store.clone(),
+        DocsStorage::Memory,
+        iroh::node::StorageConfig::Mem,
+    )
+    .gc_policy(iroh::node::GcPolicy::Interval(Duration::from_millis(500)))
+    .gc_storage_quota(quota)
+    .register_gc_done_cb(Box::new(move || {
+        gc_send.send(()).ok();
+    }))
+    .spawn()
+    .await
+    .unwrap();
+    (node, bao_store, gc_recv)
+}
+
 async fn step(evs: &flume::Receiver<()>) {
     // drain the event queue, we want a new GC
     while evs.try_recv().is_ok() {}
@@ -101,7 +126,7 @@ async fn gc_basics() -> Result<()> {
     // create an explicit tag for h1 (as raw) and then delete the temp tag. Entry should still be there.
     let tag = Tag::from("test");
     bao_store
-        .set_tag(tag.clone(), Some(HashAndFormat::raw(h2)))
+        .set_tag(tag.clone(), Some(HashAndFormat::raw(h2)), None)
         .await?;
     drop(tt2);
     tracing::info!("dropped tt2");
@@ -109,7 +134,7 @@ async fn gc_basics() -> Result<()> {
     assert_eq!(bao_store.entry_status(&h2).await?, EntryStatus::Complete);
 
     // delete the explicit tag, entry should be gone
-    bao_store.set_tag(tag, None).await?;
+    bao_store.set_tag(tag, None, None).await?;
     step(&evs).await;
     assert_eq!(bao_store.entry_status(&h2).await?, EntryStatus::NotFound);
 
@@ -147,7 +172,7 @@ async fn gc_hashseq_impl() -> Result<()> {
     // make a permanent tag for the link seq, then delete the temp tag. Entries should still be there.
     let tag = Tag::from("test");
     bao_store
-        .set_tag(tag.clone(), Some(HashAndFormat::hash_seq(hr)))
+        .set_tag(tag.clone(), Some(HashAndFormat::hash_seq(hr)), None)
         .await?;
     drop(ttr);
     step(&evs).await;
@@ -157,7 +182,7 @@ async fn gc_hashseq_impl() -> Result<()> {
 
     // change the permanent tag to be just for the linkseq itself as a blob. Only the linkseq should be there, not the entries.
     bao_store
-        .set_tag(tag.clone(), Some(HashAndFormat::raw(hr)))
+        .set_tag(tag.clone(), Some(HashAndFormat::raw(hr)), None)
         .await?;
     step(&evs).await;
     assert_eq!(bao_store.entry_status(&h1).await?, EntryStatus::NotFound);
@@ -165,7 +190,7 @@ async fn gc_hashseq_impl() -> Result<()> {
     assert_eq!(bao_store.entry_status(&hr).await?, EntryStatus::Complete);
 
     // delete the permanent tag, everything should be gone
-    bao_store.set_tag(tag, None).await?;
+    bao_store.set_tag(tag, None, None).await?;
     step(&evs).await;
     assert_eq!(bao_store.entry_status(&h1).await?, EntryStatus::NotFound);
     assert_eq!(bao_store.entry_status(&h2).await?, EntryStatus::NotFound);
@@ -175,10 +200,111 @@ async fn gc_hashseq_impl() -> Result<()> {
     Ok(())
 }
 
+/// Test that tags with a ttl protect their content until they expire.
+#[tokio::test]
+async fn gc_tag_ttl() -> Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+    let (node, bao_store, evs) = gc_test_node().await;
+    let tt1 = bao_store
+        .import_bytes(create_test_data(1234), BlobFormat::Raw)
+        .await?;
+    let tt2 = bao_store
+        .import_bytes(create_test_data(5678), BlobFormat::Raw)
+        .await?;
+    let h1 = *tt1.hash();
+    let h2 = *tt2.hash();
+    let tag1 = Tag::from("test");
+    bao_store
+        .set_tag(
+            tag1.clone(),
+            Some(HashAndFormat::raw(h1)),
+            Some(Duration::from_secs(3600)),
+        )
+        .await?;
+    bao_store
+        .create_tag(HashAndFormat::raw(h2), Some(Duration::ZERO))
+        .await?;
+    drop(tt1);
+    drop(tt2);
+
+    // the expired tag is removed, the other one still protects its content
+    step(&evs).await;
+    assert_eq!(bao_store.entry_status(&h1).await?, EntryStatus::Complete);
+    assert_eq!(bao_store.entry_status(&h2).await?, EntryStatus::NotFound);
+    let tags = bao_store
+        .tags()
+        .await?
+        .map(|item| item.map(|(tag, _)| tag))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    assert_eq!(tags, vec![tag1.clone()]);
+
+    // setting the tag without a ttl makes it permanent
+    bao_store
+        .set_tag(tag1.clone(), Some(HashAndFormat::raw(h1)), None)
+        .await?;
+    assert!(bao_store.remove_expired_tags().await?.is_empty());
+    bao_store
+        .set_tag(
+            tag1.clone(),
+            Some(HashAndFormat::raw(h1)),
+            Some(Duration::ZERO),
+        )
+        .await?;
+    step(&evs).await;
+    assert_eq!(bao_store.entry_status(&h1).await?, EntryStatus::NotFound);
+
+    node.shutdown().await?;
+    Ok(())
+}
+
+/// Test that untagged blobs are kept within the storage quota and evicted least recently used first.
+#[tokio::test]
+async fn gc_storage_quota() -> Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+    let (node, bao_store, evs) = gc_test_node_with_quota(10000).await;
+    let tt1 = bao_store
+        .import_bytes(create_test_data(4000), BlobFormat::Raw)
+        .await?;
+    let tt2 = bao_store
+        .import_bytes(create_test_data(4000), BlobFormat::Raw)
+        .await?;
+    let h1 = *tt1.hash();
+    let h2 = *tt2.hash();
+    drop(tt1);
+    drop(tt2);
+
+    // untagged data is kept as long as the store is within the quota
+    step(&evs).await;
+    assert_eq!(bao_store.entry_status(&h1).await?, EntryStatus::Complete);
+    assert_eq!(bao_store.entry_status(&h2).await?, EntryStatus::Complete);
+
+    // access the blobs, h2 more recently than h1
+    bao_store.get(&h1).await?;
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    bao_store.get(&h2).await?;
+    let tt3 = bao_store
+        .import_bytes(create_test_data(4000), BlobFormat::Raw)
+        .await?;
+    let h3 = *tt3.hash();
+    bao_store
+        .set_tag(Tag::from("test"), Some(HashAndFormat::raw(h3)), None)
+        .await?;
+    drop(tt3);
+
+    // the least recently used untagged blob is evicted to get below the quota
+    step(&evs).await;
+    assert_eq!(bao_store.entry_status(&h1).await?, EntryStatus::NotFound);
+    assert_eq!(bao_store.entry_status(&h2).await?, EntryStatus::Complete);
+    assert_eq!(bao_store.entry_status(&h3).await?, EntryStatus::Complete);
+
+    node.shutdown().await?;
+    Ok(())
+}
+
 #[cfg(feature = "fs-store")]
 mod file {
     use super::*;
-    use std::{io, path::PathBuf};
+    use std::{collections::BTreeSet, io, path::PathBuf};
 
     use bao_tree::{
         io::fsm::{BaoContentItem, ResponseDecoderNext},
@@ -190,8 +316,11 @@ mod file {
     use testdir::testdir;
 
     use iroh_blobs::{
-        store::{BaoBatchWriter, ConsistencyCheckProgress, Map, MapEntryMut, ReportLevel},
-        util::progress::{FlumeProgressSender, ProgressSender as _},
+        store::{
+            BaoBatchWriter, ConsistencyCheckProgress, GcMarkEvent, GcSweepEvent, ImportMode, Map,
+            MapEntryMut, ReadableStore, ReportLevel,
+        },
+        util::progress::{FlumeProgressSender, IgnoreProgressSender, ProgressSender as _},
         TempTag,
     };
     use tokio::io::AsyncReadExt;
@@ -313,7 +442,11 @@ mod file {
         drop(tt2);
         let tag = Tag::from("test");
         bao_store
-            .set_tag(tag.clone(), Some(HashAndFormat::hash_seq(*ttr.hash())))
+            .set_tag(
+                tag.clone(),
+                Some(HashAndFormat::hash_seq(*ttr.hash())),
+                None,
+            )
             .await?;
         drop(ttr);
 
@@ -333,7 +466,7 @@ mod file {
 
         tracing::info!("changing tag from hashseq to raw, this should orphan the children");
         bao_store
-            .set_tag(tag.clone(), Some(HashAndFormat::raw(hr)))
+            .set_tag(tag.clone(), Some(HashAndFormat::raw(hr)), None)
             .await?;
 
         // now only hr itself should be protected, but not its children
@@ -350,7 +483,7 @@ mod file {
         assert!(!path(&hr).exists());
         assert!(!outboard_path(&hr).exists());
 
-        bao_store.set_tag(tag, None).await?;
+        bao_store.set_tag(tag, None, None).await?;
         step(&evs).await;
         bao_store.sync().await?;
         assert!(check_consistency(&bao_store).await? <= ReportLevel::Info);
@@ -462,6 +595,119 @@ mod file {
         Ok(())
     }
 
+    /// Test that the file store reports expired tags and evicted blobs during gc.
+    #[tokio::test]
+    async fn gc_file_tag_ttl_and_quota() -> Result<()> {
+        let _ = tracing_subscriber::fmt::try_init();
+        let dir = testdir!();
+
+        let bao_store = iroh_blobs::store::fs::Store::load(dir.clone()).await?;
+        let tt1 = bao_store
+            .import_bytes(create_test_data(100000), BlobFormat::Raw)
+            .await?;
+        let tt2 = bao_store
+            .import_bytes(create_test_data(1000), BlobFormat::Raw)
+            .await?;
+        let h1 = *tt1.hash();
+        let h2 = *tt2.hash();
+        let tag = bao_store
+            .create_tag(HashAndFormat::raw(h1), Some(Duration::ZERO))
+            .await?;
+        drop(tt1);
+        drop(tt2);
+
+        let usage = bao_store
+            .blob_usage()
+            .await?
+            .collect::<std::io::Result<Vec<_>>>()?;
+        assert_eq!(usage.len(), 2);
+        assert_eq!(usage.iter().map(|u| u.size).sum::<u64>(), 101000);
+
+        bao_store.gc_start().await?;
+        let mut live = BTreeSet::new();
+        let mut expired = Vec::new();
+        let mut stream = bao_store.gc_mark(&mut live);
+        while let Some(event) = stream.next().await {
+            match event {
+                GcMarkEvent::TagExpired { tag, value } => expired.push((tag, value)),
+                GcMarkEvent::Error(cause) => return Err(cause),
+                _ => {}
+            }
+        }
+        drop(stream);
+        assert_eq!(expired, vec![(tag, HashAndFormat::raw(h1))]);
+        assert!(live.is_empty());
+
+        // only the large blob needs to go to get below the quota. Access the
+        // small one so the large one is the least recently used.
+        bao_store.get(&h2).await?;
+        let mut evicted = Vec::new();
+        let mut stream = bao_store.gc_sweep(&live, Some(50000));
+        while let Some(event) = stream.next().await {
+            match event {
+                GcSweepEvent::Evicted { hash, size } => evicted.push((hash, size)),
+                GcSweepEvent::Error(cause) => return Err(cause),
+                _ => {}
+            }
+        }
+        drop(stream);
+        assert_eq!(evicted, vec![(h1, 100000)]);
+        assert_eq!(bao_store.entry_status(&h1).await?, EntryStatus::NotFound);
+        assert_eq!(bao_store.entry_status(&h2).await?, EntryStatus::Complete);
+        Ok(())
+    }
+
+    /// Test that dead entries with external data are deleted with a quota, although they do not
+    /// count towards it.
+    #[tokio::test]
+    async fn gc_file_quota_external() -> Result<()> {
+        let _ = tracing_subscriber::fmt::try_init();
+        let dir = testdir!();
+
+        let bao_store = iroh_blobs::store::fs::Store::load(dir.join("store")).await?;
+        let path = dir.join("external.data");
+        std::fs::write(&path, create_test_data(100000))?;
+        let (tt, _) = bao_store
+            .import_file(
+                path.clone(),
+                ImportMode::TryReference,
+                BlobFormat::Raw,
+                IgnoreProgressSender::default(),
+            )
+            .await?;
+        let hash = *tt.hash();
+        drop(tt);
+        let usage = bao_store
+            .blob_usage()
+            .await?
+            .collect::<std::io::Result<Vec<_>>>()?;
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].size, 0);
+
+        // the store is well within the quota, but the dead entry is deleted anyway
+        bao_store.gc_start().await?;
+        let mut live = BTreeSet::new();
+        let mut stream = bao_store.gc_mark(&mut live);
+        while let Some(event) = stream.next().await {
+            if let GcMarkEvent::Error(cause) = event {
+                return Err(cause);
+            }
+        }
+        drop(stream);
+        assert!(live.is_empty());
+        let mut stream = bao_store.gc_sweep(&live, Some(1000000));
+        while let Some(event) = stream.next().await {
+            if let GcSweepEvent::Error(cause) = event {
+                return Err(cause);
+            }
+        }
+        drop(stream);
+        assert_eq!(bao_store.entry_status(&hash).await?, EntryStatus::NotFound);
+        // the external file is not owned by the store
+        assert!(path.exists());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn gc_file_stress() -> Result<()> {
         let _ = tracing_subscriber::fmt::try_init();
@@ -479,7 +725,7 @@ mod file {
             if i % 100 == 0 {
                 let tag = Tag::from(format!("test{}", i));
                 bao_store
-                    .set_tag(tag.clone(), Some(HashAndFormat::raw(*tt.hash())))
+                    .set_tag(tag.clone(), Some(HashAndFormat::raw(*tt.hash())), None)
                     .await?;
                 live.push(*tt.hash());
             } else {
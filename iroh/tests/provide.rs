@@ -1,30 +1,47 @@
 use std::{
     collections::BTreeMap,
     net::SocketAddr,
+    num::NonZeroU64,
     ops::Range,
+    sync::Arc,
     time::{Duration, Instant},
 };
 
 use anyhow::{Context, Result};
 use bytes::Bytes;
-use futures_lite::FutureExt;
+use futures_lite::{future::Boxed as BoxedFuture, FutureExt, StreamExt};
 use iroh::node::{Builder, DocsStorage};
 use iroh_base::node_addr::AddrInfoOptions;
-use iroh_net::{defaults::staging::default_relay_map, key::SecretKey, NodeAddr, NodeId};
+use iroh_net::{
+    defaults::staging::default_relay_map, endpoint::ReadError, key::SecretKey, NodeAddr, NodeId,
+};
 use rand::RngCore;
 
 use bao_tree::{blake3, ChunkNum, ChunkRanges};
 use iroh_blobs::{
     format::collection::Collection,
     get::{
-        fsm::ConnectedNext,
-        fsm::{self, DecodeError},
+        db::{
+            get_blob_striped, get_chunks_to_db, get_many_to_db, get_ranges, verified_ranges,
+            DownloadProgress,
+        },
+        error::GetError,
+        fsm::{self, AtBlobHeaderNextError, DecodeError},
+        fsm::{ConnectedNext, EndBlobNext},
+        request::get_available_ranges,
         Stats,
     },
-    protocol::{GetRequest, RangeSpecSeq},
-    store::{MapMut, Store},
+    protocol::{Closed, GetManyRequest, GetRequest, HaveRequest, PushRequest, RangeSpecSeq},
+    provider::{AcceptPush, AuthorizeGet, TransferLimits},
+    push::{push, PushError},
+    store::{Map, MapEntry, MapMut, Store},
+    util::{
+        progress::{FlumeProgressSender, IgnoreProgressSender},
+        SetTagOption, Tag,
+    },
     BlobFormat, Hash,
 };
+use iroh_io::AsyncSliceReaderExt;
 
 /// Create a new endpoint and dial a peer, returning the connection.
 async fn dial(secret_key: SecretKey, peer: NodeAddr) -> anyhow::Result<quinn::Connection> {
@@ -544,3 +561,417 @@ async fn test_collection_stat() {
     .expect("timeout")
     .expect("get failed");
 }
+
+/// Request multiple unrelated blobs in a single request.
+#[tokio::test]
+async fn test_get_many() {
+    let data_a = make_test_data(1024 * 64 + 1234);
+    let data_b = make_test_data(1234);
+    let (db, hashes) =
+        iroh_blobs::store::readonly_mem::Store::new([("a", &data_a), ("b", &data_b)]);
+    let hash_a = Hash::from(hashes["a"]);
+    let hash_b = Hash::from(hashes["b"]);
+    let missing = Hash::from(blake3::hash(b"missing"));
+    let node = test_node(db).spawn().await.unwrap();
+    let addrs = node.local_endpoint_addresses().await.unwrap();
+    let peer_id = node.node_id();
+    tokio::time::timeout(Duration::from_secs(10), async move {
+        let (secret_key, peer) = get_options(peer_id, addrs);
+        let connection = dial(secret_key, peer).await?;
+        // the last chunk of a, nothing of the missing blob, and all of b
+        let request = GetManyRequest::new(
+            vec![hash_a, missing, hash_b],
+            RangeSpecSeq::from_ranges([
+                ChunkRanges::from(ChunkNum(u64::MAX)..),
+                ChunkRanges::empty(),
+                ChunkRanges::all(),
+            ]),
+        );
+        let response = fsm::start_get_many(connection.clone(), request);
+        let connected = response.next().await?;
+        let ConnectedNext::StartChild(start) = connected.next().await? else {
+            panic!("expected StartChild");
+        };
+        assert_eq!(start.child_offset(), 0);
+        let (end, actual) = start.next(hash_a).concatenate_into_vec().await?;
+        assert_eq!(actual, last_chunk(&data_a));
+        let EndBlobNext::MoreChildren(start) = end.next() else {
+            panic!("expected MoreChildren");
+        };
+        assert_eq!(start.child_offset(), 2);
+        let (end, actual) = start.next(hash_b).concatenate_into_vec().await?;
+        assert_eq!(actual, data_b);
+        let EndBlobNext::Closing(closing) = end.next() else {
+            panic!("expected Closing");
+        };
+        closing.next().await?;
+
+        // get both blobs into a store
+        let store = iroh_blobs::store::mem::Store::new();
+        get_many_to_db(
+            &store,
+            || async move { Ok(connection) },
+            &[hash_a, hash_b],
+            IgnoreProgressSender::default(),
+        )
+        .await?;
+        for (hash, data) in [(hash_a, &data_a), (hash_b, &data_b)] {
+            let entry = store.get(&hash).await?.context("blob not found")?;
+            assert!(entry.is_complete());
+            let actual = entry.data_reader().await?.read_to_end().await?;
+            assert_eq!(actual, data);
+        }
+        anyhow::Ok(())
+    })
+    .await
+    .expect("timeout")
+    .expect("get many failed");
+}
+
+/// Download a blob striped across two nodes that have it and one node that does not.
+#[tokio::test]
+async fn test_get_blob_striped() {
+    let data = make_test_data(1024 * 1024 + 1234);
+    let hash = Hash::from(blake3::hash(&data));
+    let mut nodes = Vec::new();
+    for has_data in [true, false, true] {
+        let entries = has_data.then(|| ("data", &data));
+        let (db, _) = iroh_blobs::store::readonly_mem::Store::new(entries);
+        nodes.push(test_node(db).spawn().await.unwrap());
+    }
+    let mut peers = Vec::new();
+    for node in &nodes {
+        let addrs = node.local_endpoint_addresses().await.unwrap();
+        peers.push(get_options(node.node_id(), addrs).1);
+    }
+    tokio::time::timeout(Duration::from_secs(10), async move {
+        let endpoint = iroh_net::Endpoint::builder().bind(0).await?;
+        let mut connections = futures_buffered::join_all(
+            peers
+                .into_iter()
+                .map(|peer| endpoint.connect(peer, iroh::blobs::protocol::ALPN)),
+        )
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+        let first = connections.remove(0);
+        let store = iroh_blobs::store::mem::Store::new();
+        let (sender, receiver) = flume::unbounded();
+        get_blob_striped(
+            &store,
+            first,
+            futures_lite::stream::iter(connections),
+            &hash,
+            16.try_into()?,
+            FlumeProgressSender::new(sender),
+        )
+        .await?;
+        let entry = store.get(&hash).await?.context("blob not found")?;
+        assert!(entry.is_complete());
+        let actual = entry.data_reader().await?.read_to_end().await?;
+        assert_eq!(actual, data);
+        // every piece is counted once, even if it was downloaded from two nodes
+        let offsets = receiver
+            .drain()
+            .filter_map(|event| match event {
+                DownloadProgress::Progress { offset, .. } => Some(offset),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert!(offsets.windows(2).all(|w| w[0] < w[1]), "{offsets:?}");
+        assert_eq!(offsets.last(), Some(&(data.len() as u64)));
+        drop(nodes);
+        anyhow::Ok(())
+    })
+    .await
+    .expect("timeout")
+    .expect("striped get failed");
+}
+
+/// Download a blob in ranges, only requesting the chunks that are missing.
+#[tokio::test]
+async fn test_get_chunks_to_db() {
+    let data = make_test_data(1024 * 1024 + 1234);
+    let hash = Hash::from(blake3::hash(&data));
+    let (db, _) = iroh_blobs::store::readonly_mem::Store::new([("data", &data)]);
+    let node = test_node(db).spawn().await.unwrap();
+    let addrs = node.local_endpoint_addresses().await.unwrap();
+    let peer = get_options(node.node_id(), addrs).1;
+    tokio::time::timeout(Duration::from_secs(10), async move {
+        let endpoint = iroh_net::Endpoint::builder().bind(0).await?;
+        let store = iroh_blobs::store::mem::Store::new();
+        let middle = ChunkNum(16)..ChunkNum(32);
+        let connection = endpoint
+            .connect(peer.clone(), iroh::blobs::protocol::ALPN)
+            .await?;
+        get_chunks_to_db(&store, connection, &hash, middle.clone()).await?;
+        let entry = store.get_mut(&hash).await?.context("blob not found")?;
+        assert!(!entry.is_complete());
+        let verified =
+            verified_ranges::<iroh_blobs::store::mem::Store>(&entry, &ChunkRanges::all()).await?;
+        // the last chunk group is requested as well, to verify the size
+        assert_eq!(
+            verified,
+            ChunkRanges::from(middle.clone()) | ChunkRanges::from(ChunkNum(1024)..ChunkNum(1026))
+        );
+        // chunks that are present are not requested again
+        let connection = endpoint
+            .connect(peer.clone(), iroh::blobs::protocol::ALPN)
+            .await?;
+        let stats = get_chunks_to_db(&store, connection, &hash, middle).await?;
+        assert_eq!(stats.bytes_read, 0);
+        // once all chunks are in, the blob is complete
+        let connection = endpoint.connect(peer, iroh::blobs::protocol::ALPN).await?;
+        get_chunks_to_db(&store, connection, &hash, ChunkNum(0)..ChunkNum(1026)).await?;
+        let entry = store.get(&hash).await?.context("blob not found")?;
+        assert!(entry.is_complete());
+        let actual = entry.data_reader().await?.read_to_end().await?;
+        assert_eq!(actual, data);
+        drop(node);
+        anyhow::Ok(())
+    })
+    .await
+    .expect("timeout")
+    .expect("ranged get failed");
+}
+
+/// Ask nodes with complete, partial and no data for a blob which ranges they have.
+#[tokio::test]
+async fn test_have_request() {
+    let data = make_test_data(1024 * 1024 + 1234);
+    let hash = Hash::from(blake3::hash(&data));
+    let first_half = ChunkRanges::from(..ChunkNum(512));
+    let (full_db, _) = iroh_blobs::store::readonly_mem::Store::new([("data", &data)]);
+    let full = test_node(full_db).spawn().await.unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let partial_db = iroh_blobs::store::fs::Store::load(dir.path())
+        .await
+        .unwrap();
+    let partial = test_node(partial_db.clone()).spawn().await.unwrap();
+    let (empty_db, _) = iroh_blobs::store::readonly_mem::Store::new(None::<(&str, &[u8])>);
+    let empty = test_node(empty_db).spawn().await.unwrap();
+    let peers = [
+        (
+            full.node_id(),
+            full.local_endpoint_addresses().await.unwrap(),
+        ),
+        (
+            partial.node_id(),
+            partial.local_endpoint_addresses().await.unwrap(),
+        ),
+        (
+            empty.node_id(),
+            empty.local_endpoint_addresses().await.unwrap(),
+        ),
+    ]
+    .map(|(node_id, addrs)| get_options(node_id, addrs).1);
+    tokio::time::timeout(Duration::from_secs(10), async move {
+        let endpoint = iroh_net::Endpoint::builder().bind(0).await?;
+        let mut connections = Vec::new();
+        for peer in peers {
+            connections.push(endpoint.connect(peer, iroh::blobs::protocol::ALPN).await?);
+        }
+        // give the partial node the first half of the blob
+        get_ranges(
+            &partial_db,
+            connections[0].clone(),
+            &hash,
+            None,
+            first_half.clone(),
+        )
+        .await?;
+        let expected = [ChunkRanges::all(), first_half, ChunkRanges::empty()];
+        for (connection, expected) in connections.iter().zip(expected) {
+            let ranges = get_available_ranges(connection, HaveRequest::single(hash)).await?;
+            let actual = ranges.iter().next().unwrap().to_chunk_ranges();
+            assert_eq!(actual, expected);
+        }
+        drop((full, partial, empty));
+        anyhow::Ok(())
+    })
+    .await
+    .expect("timeout")
+    .expect("have request failed");
+}
+
+/// Only accept pushes from a single node.
+#[derive(Debug)]
+struct AcceptPushFrom(NodeId);
+
+impl AcceptPush for AcceptPushFrom {
+    fn accept(&self, remote: NodeId, _request: &PushRequest) -> BoxedFuture<Option<SetTagOption>> {
+        let accept = (remote == self.0).then(|| SetTagOption::Named(Tag::from("pushed")));
+        Box::pin(async move { accept })
+    }
+}
+
+/// Push a collection to a node, and check that pushes from other nodes are rejected.
+#[tokio::test]
+async fn test_push() {
+    let child1 = make_test_data(123456);
+    let child2 = make_test_data(1234);
+    let (src, hash) = create_test_db([("a", &child1), ("b", &child2)]);
+    let secret_key = SecretKey::generate();
+    let node = test_node(iroh_blobs::store::mem::Store::new())
+        .accept_blobs_push(Arc::new(AcceptPushFrom(secret_key.public())))
+        .spawn()
+        .await
+        .unwrap();
+    let addrs = node.local_endpoint_addresses().await.unwrap();
+    let (_, peer) = get_options(node.node_id(), addrs);
+    tokio::time::timeout(Duration::from_secs(10), async move {
+        let connection = dial(secret_key, peer.clone()).await?;
+        push(connection, &src, PushRequest::all(hash)).await?;
+        let collection = src.get_content(&hash).context("collection not found")?;
+        assert_eq!(node.blobs().read_to_bytes(hash).await?, collection);
+        assert_eq!(
+            node.blobs()
+                .read_to_bytes(blake3::hash(&child1).into())
+                .await?,
+            child1
+        );
+        assert_eq!(
+            node.blobs()
+                .read_to_bytes(blake3::hash(&child2).into())
+                .await?,
+            child2
+        );
+        // the pushed data is tagged with the tag chosen by the accept hook
+        let tags = node
+            .tags()
+            .list()
+            .await?
+            .try_collect::<_, _, Vec<_>>()
+            .await?;
+        assert!(tags.iter().any(|tag| tag.name == Tag::from("pushed")
+            && tag.hash == hash
+            && tag.format == BlobFormat::HashSeq));
+
+        let connection = dial(SecretKey::generate(), peer).await?;
+        let res = push(connection, &src, PushRequest::single(hash)).await;
+        assert!(matches!(res, Err(PushError::Rejected)), "{res:?}");
+        anyhow::Ok(())
+    })
+    .await
+    .expect("timeout")
+    .expect("push failed");
+}
+
+#[derive(Debug)]
+struct AuthorizeGetFrom(NodeId);
+
+impl AuthorizeGet for AuthorizeGetFrom {
+    fn authorize(&self, remote: NodeId, _request: &GetRequest) -> BoxedFuture<bool> {
+        let authorized = remote == self.0;
+        Box::pin(async move { authorized })
+    }
+}
+
+/// Check that get requests from unauthorized nodes are denied.
+#[tokio::test]
+async fn test_authorize_get() {
+    let (db, hash) = create_test_db([("a", b"hello"), ("b", b"world")]);
+    let secret_key = SecretKey::generate();
+    let node = test_node(db)
+        .authorize_blobs_get(Arc::new(AuthorizeGetFrom(secret_key.public())))
+        .spawn()
+        .await
+        .unwrap();
+    let addrs = node.local_endpoint_addresses().await.unwrap();
+    let (_, peer) = get_options(node.node_id(), addrs);
+    tokio::time::timeout(Duration::from_secs(10), async move {
+        let (collection, children, _) =
+            run_collection_get_request(secret_key, peer.clone(), GetRequest::all(hash)).await?;
+        validate_children(collection, children)?;
+
+        let connection = dial(SecretKey::generate(), peer).await?;
+        let connected = fsm::start(connection, GetRequest::all(hash)).next().await?;
+        let ConnectedNext::StartRoot(start) = connected.next().await? else {
+            anyhow::bail!("expected StartRoot");
+        };
+        let res = start.next().next().await.map_err(GetError::from);
+        assert!(matches!(res, Err(GetError::AccessDenied(_))), "{res:?}");
+        anyhow::Ok(())
+    })
+    .await
+    .expect("timeout")
+    .expect("get failed");
+}
+
+#[tokio::test]
+async fn test_transfer_limits() {
+    let _guard = iroh_test::logging::setup();
+    let (db, hash) = create_test_db([("a", b"hello"), ("b", b"world")]);
+    let node = test_node(db)
+        .blobs_transfer_limits(TransferLimits {
+            max_concurrent_transfers: Some(0),
+            ..Default::default()
+        })
+        .spawn()
+        .await
+        .unwrap();
+    let addrs = node.local_endpoint_addresses().await.unwrap();
+    let (secret_key, peer) = get_options(node.node_id(), addrs);
+    tokio::time::timeout(Duration::from_secs(10), async move {
+        let connection = dial(secret_key, peer).await?;
+        let connected = fsm::start(connection.clone(), GetRequest::all(hash))
+            .next()
+            .await?;
+        let ConnectedNext::StartRoot(start) = connected.next().await? else {
+            anyhow::bail!("expected StartRoot");
+        };
+        let res = start.next().next().await;
+        assert!(
+            matches!(
+                res,
+                Err(AtBlobHeaderNextError::Read(ReadError::Reset(code)))
+                    if code == Closed::TooManyTransfers.into()
+            ),
+            "{res:?}"
+        );
+        // have requests count as transfers as well
+        let res = get_available_ranges(&connection, HaveRequest::single(hash)).await;
+        assert!(res.is_err(), "{res:?}");
+        anyhow::Ok(())
+    })
+    .await
+    .expect("timeout")
+    .expect("get failed");
+}
+
+#[tokio::test]
+async fn test_upload_rate_limit() {
+    let _guard = iroh_test::logging::setup();
+    let data = make_test_data(1024 * 256);
+    let (db, hashes) = iroh_blobs::store::readonly_mem::Store::new([("test", &data)]);
+    let hash = Hash::from(*hashes.values().next().unwrap());
+    let node = test_node(db)
+        .blobs_transfer_limits(TransferLimits {
+            max_upload_rate_per_peer: NonZeroU64::new(1024 * 128),
+            ..Default::default()
+        })
+        .spawn()
+        .await
+        .unwrap();
+    let addrs = node.local_endpoint_addresses().await.unwrap();
+    let (secret_key, peer) = get_options(node.node_id(), addrs);
+    tokio::time::timeout(Duration::from_secs(10), async move {
+        let connection = dial(secret_key, peer).await?;
+        let t0 = Instant::now();
+        let connected = fsm::start(connection, GetRequest::single(hash))
+            .next()
+            .await?;
+        let ConnectedNext::StartRoot(start) = connected.next().await? else {
+            anyhow::bail!("expected StartRoot");
+        };
+        let (_, actual) = start.next().concatenate_into_vec().await?;
+        assert_eq!(actual, data);
+        // 256 KiB at 128 KiB/s, minus the burst allowance
+        let elapsed = t0.elapsed();
+        assert!(elapsed >= Duration::from_millis(1500), "{elapsed:?}");
+        anyhow::Ok(())
+    })
+    .await
+    .expect("timeout")
+    .expect("get failed");
+}
@@ -0,0 +1,95 @@
+#![cfg(feature = "s3-store")]
+use std::time::Duration;
+
+use anyhow::Result;
+use iroh::node::{Builder, DocsStorage, GcPolicy, Node, StorageConfig};
+use iroh_blobs::{
+    store::s3::{test_utils::fake_s3, S3Config, Store},
+    Hash,
+};
+use rand::RngCore;
+
+fn random_data(size: usize) -> Vec<u8> {
+    let mut data = vec![0u8; size];
+    rand::thread_rng().fill_bytes(&mut data);
+    data
+}
+
+fn data_key(hash: &Hash) -> String {
+    format!("{}.data", hash.to_hex())
+}
+
+/// A node can use the s3 store to add, provide, fetch and gc blobs.
+#[tokio::test]
+async fn s3_store_node() -> Result<()> {
+    let _guard = iroh_test::logging::setup();
+    let (addr, bucket) = fake_s3().await;
+    let dir = tempfile::tempdir()?;
+    let config = S3Config::new(
+        format!("http://{addr}"),
+        "bucket",
+        "us-east-1",
+        "access",
+        "secret",
+    );
+    let store = Store::load(dir.path(), config).await?;
+    let (gc_send, gc_recv) = flume::unbounded();
+    let node = Builder::with_db_and_store(store, DocsStorage::Memory, StorageConfig::Mem)
+        .bind_port(0)
+        .gc_policy(GcPolicy::Interval(Duration::from_millis(100)))
+        .register_gc_done_cb(Box::new(move || {
+            gc_send.send(()).ok();
+        }))
+        .spawn()
+        .await?;
+
+    // added data ends up in the bucket
+    let data = random_data(200 * 1024);
+    let added = node.blobs().add_bytes(data.clone()).await?;
+    assert!(bucket
+        .lock()
+        .unwrap()
+        .objects
+        .contains_key(&data_key(&added.hash)));
+    assert_eq!(node.blobs().read_to_bytes(added.hash).await?, data);
+
+    // other nodes can fetch from the node, and the node can fetch from them
+    let other = Node::memory().bind_port(0).spawn().await?;
+    other
+        .blobs()
+        .download(added.hash, node.node_addr().await?)
+        .await?
+        .finish()
+        .await?;
+    assert_eq!(other.blobs().read_to_bytes(added.hash).await?, data);
+    let other_data = random_data(100 * 1024);
+    let other_hash = other.blobs().add_bytes(other_data.clone()).await?.hash;
+    node.blobs()
+        .download(other_hash, other.node_addr().await?)
+        .await?
+        .finish()
+        .await?;
+    assert_eq!(node.blobs().read_to_bytes(other_hash).await?, other_data);
+    assert_eq!(
+        bucket.lock().unwrap().objects.get(&data_key(&other_hash)),
+        Some(&other_data)
+    );
+
+    // once untagged, gc deletes the blob from the bucket
+    node.tags().delete(added.tag).await?;
+    while gc_recv.try_recv().is_ok() {}
+    for _ in 0..3 {
+        gc_recv.recv_async().await?;
+    }
+    assert!(node.blobs().status(added.hash).await.is_err());
+    assert!(!bucket
+        .lock()
+        .unwrap()
+        .objects
+        .contains_key(&data_key(&added.hash)));
+    assert_eq!(node.blobs().read_to_bytes(other_hash).await?, other_data);
+
+    other.shutdown().await?;
+    node.shutdown().await?;
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
     future::Future,
     sync::Arc,
     time::{Duration, Instant},
@@ -24,8 +24,9 @@ use tracing_subscriber::{prelude::*, EnvFilter};
 
 use iroh_blobs::Hash;
 use iroh_docs::{
+    engine::{MergeHandler, MergeHead},
     store::{DownloadPolicy, FilterKind, Query},
-    AuthorId, ContentStatus,
+    AuthorId, ContentStatus, NamespaceId,
 };
 use iroh_net::relay::RelayMode;
 
@@ -132,6 +133,120 @@ async fn sync_simple() -> Result<()> {
     Ok(())
 }
 
+/// Test that an author with a delegated write grant can write to a doc without its secret.
+#[tokio::test]
+async fn sync_delegated_write() -> Result<()> {
+    setup_logging();
+    let mut rng = test_rng(b"sync_delegated_write");
+    let nodes = spawn_nodes(2, &mut rng).await?;
+    let clients = nodes.iter().map(|node| node.client()).collect::<Vec<_>>();
+
+    let author0 = clients[0].authors().create().await?;
+    let author1 = clients[1].authors().create().await?;
+    let doc0 = clients[0].docs().create().await?;
+    doc0.set_bytes(author0, b"k1".to_vec(), b"v1".to_vec())
+        .await?;
+    let ticket = doc0
+        .share_delegated(
+            author1,
+            b"shared/".to_vec(),
+            None,
+            AddrInfoOptions::RelayAndAddresses,
+        )
+        .await?;
+
+    info!("node1: join");
+    let doc1 = clients[1].docs().import(ticket).await?;
+    doc1.set_bytes(author1, b"shared/k2".to_vec(), b"v2".to_vec())
+        .await?;
+    assert!(doc1
+        .set_bytes(author1, b"other".to_vec(), b"v3".to_vec())
+        .await
+        .is_err());
+    assert!(doc1
+        .set_bytes(author0, b"shared/k3".to_vec(), b"v3".to_vec())
+        .await
+        .is_err());
+
+    info!("node0: wait for delegated entry");
+    tokio::time::timeout(TIMEOUT, async {
+        while get_latest(&doc0, b"shared/k2").await.ok() != Some(b"v2".to_vec()) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await?;
+
+    for node in nodes {
+        node.shutdown().await?;
+    }
+    Ok(())
+}
+
+/// Merges values by taking the union of their `|`-separated parts.
+#[derive(Debug)]
+struct UnionMerge;
+
+impl MergeHandler for UnionMerge {
+    fn merge(&self, _namespace: NamespaceId, _key: &[u8], heads: &[MergeHead]) -> Option<Bytes> {
+        let parts = heads
+            .iter()
+            .filter_map(|head| head.content.as_ref())
+            .flat_map(|content| content.split(|b| *b == b'|'))
+            .collect::<BTreeSet<_>>();
+        Some(parts.into_iter().collect::<Vec<_>>().join(&b'|').into())
+    }
+}
+
+/// Test that concurrent values of a key are merged by the merge handler of a document.
+#[tokio::test]
+async fn sync_merge_handler() -> Result<()> {
+    setup_logging();
+    let mut rng = test_rng(b"sync_merge_handler");
+    let nodes = spawn_nodes(2, &mut rng).await?;
+    let clients = nodes.iter().map(|node| node.client()).collect::<Vec<_>>();
+
+    let author0 = clients[0].authors().create().await?;
+    let author1 = clients[1].authors().create().await?;
+    let doc0 = clients[0].docs().create().await?;
+    nodes[0]
+        .set_merge_handler(doc0.id(), author0, Arc::new(UnionMerge))
+        .await?;
+    doc0.set_bytes(author0, b"k".to_vec(), b"a".to_vec())
+        .await?;
+    let ticket = doc0
+        .share(ShareMode::Write, AddrInfoOptions::RelayAndAddresses)
+        .await?;
+
+    info!("node1: join");
+    let doc1 = clients[1].docs().import(ticket).await?;
+    doc1.set_bytes(author1, b"k".to_vec(), b"b".to_vec())
+        .await?;
+
+    info!("node0: wait for merged value");
+    tokio::time::timeout(TIMEOUT, async {
+        while get_latest(&doc0, b"k").await.ok() != Some(b"a|b".to_vec()) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await?;
+    let heads = doc0.get_heads(b"k".to_vec()).await?;
+    assert_eq!(heads.len(), 2);
+    assert_eq!(heads[0].author(), author0);
+
+    info!("node1: wait for merged value");
+    tokio::time::timeout(TIMEOUT, async {
+        while get_latest(&doc1, b"k").await.ok() != Some(b"a|b".to_vec()) {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await?;
+
+    for node in nodes {
+        node.shutdown().await?;
+    }
+    Ok(())
+}
+
 /// Test subscribing to replica events (without sync)
 #[tokio::test]
 async fn sync_subscribe_no_sync() -> Result<()> {